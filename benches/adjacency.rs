@@ -0,0 +1,115 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Compares the adjacency list storage of factor graphs with the petgraph CSR it replaced.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use gs_rs::factor_graph::adjacency::{AdjacencyList, NodeIndex};
+use gs_rs::factor_graph::factor::Factor;
+use gs_rs::factor_graph::variable::VariableId;
+use gs_rs::factor_graph::FactorGraph;
+use gs_rs::parser::g2o::G2oParser;
+use gs_rs::parser::Parser;
+use petgraph::csr::Csr;
+use petgraph::visit::EdgeRef;
+use petgraph::Directed;
+
+type FactorCsr = Csr<VariableId, Factor, Directed, usize>;
+type FactorAdjacency = AdjacencyList<VariableId, Factor>;
+
+// returns the variable IDs and all edges of the benchmark input in the order of the factor graph
+fn load(file_name: &str) -> (Vec<VariableId>, Vec<(usize, usize, Factor)>) {
+    let factor_graph: FactorGraph =
+        G2oParser::parse_file(&["data_files/benchmark_input/", file_name, ".g2o"].concat()).unwrap();
+    let ids = factor_graph
+        .node_indices
+        .iter()
+        .map(|i| factor_graph.get_var(*i).get_id())
+        .collect();
+    let edges = factor_graph
+        .node_indices
+        .iter()
+        .flat_map(|i| factor_graph.adjacency.edges(*i))
+        .map(|edge| (edge.source().index(), edge.target().index(), edge.weight().clone()))
+        .collect();
+    (ids, edges)
+}
+
+fn build_csr(ids: &[VariableId], edges: &[(usize, usize, Factor)]) -> FactorCsr {
+    let mut csr = Csr::new();
+    ids.iter().for_each(|id| {
+        csr.add_node(*id);
+    });
+    edges.iter().for_each(|(source, target, factor)| {
+        csr.add_edge(*source, *target, factor.clone());
+    });
+    csr
+}
+
+fn build_adjacency(ids: &[VariableId], edges: &[(usize, usize, Factor)]) -> FactorAdjacency {
+    let mut adjacency = AdjacencyList::new();
+    ids.iter().for_each(|id| {
+        adjacency.add_node(*id);
+    });
+    edges.iter().for_each(|(source, target, factor)| {
+        adjacency.add_edge(NodeIndex::new(*source), NodeIndex::new(*target), factor.clone());
+    });
+    adjacency
+}
+
+// visits all edges like the assembly of the linear system, summing up values of the factors and their variables
+fn traverse_csr(csr: &FactorCsr, node_count: usize) -> f64 {
+    (0..node_count)
+        .flat_map(|i| csr.edges(i))
+        .map(|edge| edge.weight().constraint[0] + csr[edge.source()].0 as f64 + csr[edge.target()].0 as f64)
+        .sum()
+}
+
+fn traverse_adjacency(adjacency: &FactorAdjacency) -> f64 {
+    (0..adjacency.node_count())
+        .flat_map(|i| adjacency.edges(NodeIndex::new(i)))
+        .map(|edge| edge.weight().constraint[0] + adjacency[edge.source()].0 as f64 + adjacency[edge.target()].0 as f64)
+        .sum()
+}
+
+fn bench_traversal(c: &mut Criterion) {
+    for file_name in &["MIT_2D", "Sphere_3D"] {
+        let (ids, edges) = load(file_name);
+        let csr = build_csr(&ids, &edges);
+        let adjacency = build_adjacency(&ids, &edges);
+        c.bench_function(&[file_name, "_traversal_csr"].concat(), |b| {
+            b.iter(|| traverse_csr(black_box(&csr), ids.len()))
+        });
+        c.bench_function(&[file_name, "_traversal_adjacency"].concat(), |b| {
+            b.iter(|| traverse_adjacency(black_box(&adjacency)))
+        });
+    }
+}
+
+fn bench_insertion(c: &mut Criterion) {
+    for file_name in &["MIT_2D", "Sphere_3D"] {
+        let (ids, edges) = load(file_name);
+        c.bench_function(&[file_name, "_insertion_csr"].concat(), |b| {
+            b.iter(|| build_csr(black_box(&ids), black_box(&edges)))
+        });
+        c.bench_function(&[file_name, "_insertion_adjacency"].concat(), |b| {
+            b.iter(|| build_adjacency(black_box(&ids), black_box(&edges)))
+        });
+    }
+}
+
+criterion_group! {
+    name = benches;
+    config = Criterion::default().sample_size(10);
+    targets = bench_traversal, bench_insertion
+}
+criterion_main!(benches);
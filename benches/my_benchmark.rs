@@ -11,6 +11,8 @@
 //
 
 use criterion::{criterion_group, criterion_main, Criterion};
+use gs_rs::examples_gen::triangle_with_loop_closure;
+use gs_rs::factor_graph::FactorGraph;
 use gs_rs::optimizer::optimize;
 use gs_rs::parser::g2o::G2oParser;
 use gs_rs::parser::Parser;
@@ -40,9 +42,18 @@ fn bench_sphere_3d_10(c: &mut Criterion) {
     });
 }
 
+fn bench_triangle_10(c: &mut Criterion) {
+    c.bench_function("Triangle_2D_10_iterations", |b| {
+        b.iter(|| {
+            let factor_graph: FactorGraph = triangle_with_loop_closure().into();
+            optimize(&factor_graph, 10);
+        })
+    });
+}
+
 criterion_group! {
     name = benches;
     config = Criterion::default().sample_size(10);
-    targets = bench_mit_2d_1, bench_mit_2d_50, bench_sphere_3d_1, bench_sphere_3d_10
+    targets = bench_mit_2d_1, bench_mit_2d_50, bench_sphere_3d_1, bench_sphere_3d_10, bench_triangle_10
 }
 criterion_main!(benches);
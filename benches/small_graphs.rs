@@ -0,0 +1,46 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Compares the fast path for small factor graphs with the general optimization.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use gs_rs::examples_gen::{single_landmark_with_two_observations, triangle_with_loop_closure};
+use gs_rs::factor_graph::FactorGraph;
+use gs_rs::optimizer::optimize;
+use gs_rs::optimizer::small::optimize_small;
+use gs_rs::parser::model::FactorGraphModel;
+
+fn bench_paths(c: &mut Criterion, name: &str, get_model: fn() -> FactorGraphModel) {
+    c.bench_function(&[name, "_general_10_iterations"].concat(), |b| {
+        b.iter(|| {
+            let factor_graph: FactorGraph = get_model().into();
+            optimize(&factor_graph, 10);
+        })
+    });
+    c.bench_function(&[name, "_small_10_iterations"].concat(), |b| {
+        b.iter(|| {
+            let factor_graph: FactorGraph = get_model().into();
+            optimize_small::<8>(&factor_graph, 10).unwrap();
+        })
+    });
+}
+
+fn bench_triangle(c: &mut Criterion) {
+    bench_paths(c, "Triangle_2D", triangle_with_loop_closure);
+}
+
+fn bench_single_landmark(c: &mut Criterion) {
+    bench_paths(c, "Single_Landmark_2D", single_landmark_with_two_observations);
+}
+
+criterion_group!(benches, bench_triangle, bench_single_landmark);
+criterion_main!(benches);
@@ -0,0 +1,27 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+use gs_rs::examples_gen::single_landmark_with_two_observations;
+use gs_rs::factor_graph::FactorGraph;
+use gs_rs::optimizer::optimize;
+use gs_rs::visualizer::visualize;
+
+fn main() {
+    // build a minimal factor graph in code instead of parsing a file
+    let factor_graph: FactorGraph = single_landmark_with_two_observations().into();
+
+    // optimize the factor graph with 10 iterations
+    optimize(&factor_graph, 10);
+
+    // visualize the factor graph (does not work multiple times in a single execution of the program)
+    visualize(&factor_graph);
+}
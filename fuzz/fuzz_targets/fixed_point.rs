@@ -0,0 +1,24 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Reads arbitrary input as a fixed-point trajectory. Invalid input must be reported as an error instead of panicking.
+
+#![no_main]
+
+use gs_rs::parser::fixed_point::reader::TrajectoryReader;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(reader) = TrajectoryReader::new(data) {
+        reader.for_each(drop);
+    }
+});
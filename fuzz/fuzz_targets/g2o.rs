@@ -0,0 +1,29 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Parses arbitrary input as a G2O file and optimizes the parsed factor graph. Invalid input must be reported as an
+//! error by the parser instead of panicking in the parser or the optimizer.
+
+#![no_main]
+
+use gs_rs::optimizer::optimize;
+use gs_rs::parser::g2o::G2oParser;
+use gs_rs::parser::Parser;
+use libfuzzer_sys::fuzz_target;
+
+fuzz_target!(|data: &[u8]| {
+    if let Ok(s) = std::str::from_utf8(data) {
+        if let Ok(factor_graph) = G2oParser::parse_string(s) {
+            optimize(&factor_graph, 1);
+        }
+    }
+});
@@ -0,0 +1,645 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Ready-made calibration problems, which are small factor graphs of custom and range factors.
+//!
+//! The hand-eye calibration estimates the fixed transform X between two rigidly connected sensors, e.g. a robot
+//! hand and a camera, from pairs of their relative motions A and B, which satisfy A X = X B.
+//!
+//! ```
+//! use gs_rs::calibration::calibrate_hand_eye;
+//! use nalgebra::{Isometry3, Vector3};
+//!
+//! let extrinsic = Isometry3::new(Vector3::new(0.1, 0.0, 0.2), Vector3::new(0.0, 0.3, 0.0));
+//! let motions = [
+//!     Isometry3::new(Vector3::new(1.0, 0.0, 0.0), Vector3::new(0.5, 0.0, 0.0)),
+//!     Isometry3::new(Vector3::new(0.0, 1.0, 0.0), Vector3::new(0.0, 0.0, 0.7)),
+//! ];
+//! let pairs: Vec<_> = motions.iter().map(|a| (*a, extrinsic.inverse() * a * extrinsic)).collect();
+//! let calibration = calibrate_hand_eye(&pairs, 10).unwrap();
+//! assert!((calibration.extrinsic.to_homogeneous() - extrinsic.to_homogeneous()).norm() < 1e-6);
+//! ```
+//!
+//! The beacon self-calibration estimates the positions of static range beacons, e.g. of a UWB setup, from the
+//! ranges which a vehicle measures to them along a trajectory that is known from odometry, see
+//! [BeaconCalibrationBuilder](struct.BeaconCalibrationBuilder.html).
+
+use crate::factor_graph::factor::CustomResidual;
+use crate::factor_graph::variable::VariableId;
+use crate::factor_graph::FactorGraph;
+use crate::optimizer::autodiff::Dual;
+use crate::optimizer::linear_system::iso3d_gradients::get_isometry;
+use crate::optimizer::optimize;
+use crate::parser::model::converter::convert_model;
+use crate::parser::model::{Edge, FactorGraphModel, Vertex};
+use nalgebra::{DMatrix, DVector, Isometry3, Matrix3, Rotation2, Rotation3, Translation3, UnitQuaternion, Vector2};
+use std::collections::{BTreeMap, BTreeSet};
+use std::f64::consts::PI;
+
+/// The name of the [hand-eye residual](fn.hand_eye_residual.html).
+pub const HAND_EYE_RESIDUAL: &str = "HandEye";
+
+// a pose of dual numbers, consisting of the translation and the rotation quaternion [x, y, z, w]
+type DualPose = ([Dual; 3], [Dual; 4]);
+
+/// The result of a [hand-eye calibration](fn.calibrate_hand_eye.html).
+#[derive(Debug, Clone, PartialEq)]
+pub struct HandEyeCalibration {
+    /// The transform X from the second sensor's frame into the first one's, which satisfies A X = X B for each
+    /// pair of motions A and B.
+    pub extrinsic: Isometry3<f64>,
+    /// The root mean square of the distances between the translations of A X and X B.
+    pub translation_rms: f64,
+    /// The root mean square of the angles of the rotations between A X and X B.
+    pub rotation_rms: f64,
+}
+
+/// Returns the residual of a unary custom factor on a Vehicle3D variable X, whose constraint consists of the
+/// concatenated poses of the motions A and B of each hand-eye pair as [x, y, z, q_x, q_y, q_z, q_w].
+///
+/// The error consists of six entries per pair: the difference between the translations of A X and X B and the vector
+/// part of the rotation quaternion from X B to A X, like the error of Odometry3D factors. All pairs share a single
+/// factor because a factor graph contains at most one unary factor per variable.
+pub fn hand_eye_residual() -> CustomResidual {
+    CustomResidual::new(HAND_EYE_RESIDUAL, |contents: &[Vec<Dual>], constraint: &[f64]| {
+        let x = get_dual_pose(&contents[0]);
+        let mut error = Vec::with_capacity(constraint.len() / 14 * 6);
+        for pair in constraint.chunks_exact(14) {
+            let constants: Vec<Dual> = pair.iter().map(|v| Dual::constant(*v)).collect();
+            let (a, b) = (get_dual_pose(&constants[0..7]), get_dual_pose(&constants[7..14]));
+            let (ax, xb) = (compose(&a, &x), compose(&x, &b));
+            let rotation = multiply(&conjugate(&xb.1), &ax.1);
+            let sign = if rotation[3].value < 0.0 { -1.0 } else { 1.0 };
+            error.extend_from_slice(&[
+                ax.0[0] - xb.0[0],
+                ax.0[1] - xb.0[1],
+                ax.0[2] - xb.0[2],
+                rotation[0] * sign,
+                rotation[1] * sign,
+                rotation[2] * sign,
+            ]);
+        }
+        error
+    })
+}
+
+/// Estimates the transform X between two rigidly connected sensors from pairs of their relative motions A and B,
+/// which satisfy A X = X B, with the given number of iterations.
+///
+/// The rotation is initialized by aligning the rotation vectors of the motions, and the translation by solving the
+/// linear least squares problem of the translations afterwards. Both are refined jointly by optimizing a factor
+/// graph with a single variable and a [hand-eye factor](fn.hand_eye_residual.html) over all pairs, whose information
+/// matrix is the identity.
+///
+/// Returns an error if there are fewer than two pairs or if the rotation axes of the motions are all parallel, in
+/// which case X is not determined.
+pub fn calibrate_hand_eye(
+    pairs: &[(Isometry3<f64>, Isometry3<f64>)],
+    iterations: usize,
+) -> Result<HandEyeCalibration, String> {
+    if pairs.len() < 2 {
+        return Err(format!(
+            "A hand-eye calibration needs at least two pairs of motions, but got {}",
+            pairs.len()
+        ));
+    }
+    let initial = initialize_hand_eye(pairs)?;
+
+    let model = FactorGraphModel {
+        vertices: vec![Vertex {
+            id: VariableId(0),
+            vertex_type: String::from("Vehicle3D"),
+            content: get_content(&initial),
+        }],
+        edges: vec![Edge {
+            edge_type: String::from(HAND_EYE_RESIDUAL),
+            vertices: vec![VariableId(0)],
+            restriction: pairs
+                .iter()
+                .flat_map(|(a, b)| get_content(a).into_iter().chain(get_content(b)))
+                .collect(),
+            information_matrix: DMatrix::<f64>::identity(6 * pairs.len(), 6 * pairs.len())
+                .as_slice()
+                .to_vec(),
+        }],
+        ..Default::default()
+    };
+    let mut residuals = BTreeMap::new();
+    residuals.insert(String::from(HAND_EYE_RESIDUAL), hand_eye_residual());
+    let factor_graph = convert_model(&model, &residuals)?;
+    optimize(&factor_graph, iterations);
+
+    let extrinsic = get_isometry(&factor_graph.get_var_by_id(VariableId(0)).unwrap().get_content());
+    let (mut translation_sum, mut rotation_sum) = (0.0, 0.0);
+    for (a, b) in pairs {
+        let difference = (extrinsic * b).inverse() * (a * extrinsic);
+        translation_sum += ((a * extrinsic).translation.vector - (extrinsic * b).translation.vector).norm_squared();
+        rotation_sum += difference.rotation.angle().powi(2);
+    }
+    Ok(HandEyeCalibration {
+        extrinsic,
+        translation_rms: (translation_sum / pairs.len() as f64).sqrt(),
+        rotation_rms: (rotation_sum / pairs.len() as f64).sqrt(),
+    })
+}
+
+/// Standard deviations of the measurements and priors of a
+/// [beacon self-calibration](struct.BeaconCalibrationBuilder.html).
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BeaconNoise {
+    /// The standard deviation of the ranges, 0.1 by default, e.g. in meters for UWB ranging.
+    pub range_deviation: f64,
+    /// The standard deviation of each entry of the odometry errors, 0.05 by default.
+    pub odometry_deviation: f64,
+    /// The standard deviation of the weak prior of each unknown beacon at its initial position, 10 by default.
+    pub beacon_prior_deviation: f64,
+}
+
+impl Default for BeaconNoise {
+    fn default() -> Self {
+        BeaconNoise {
+            range_deviation: 0.1,
+            odometry_deviation: 0.05,
+            beacon_prior_deviation: 10.0,
+        }
+    }
+}
+
+/// Builder of the factor graphs of beacon self-calibrations, in which a vehicle measures ranges to static beacons at
+/// unknown positions along a trajectory that is known from odometry.
+///
+/// The vehicle starts at the fixed origin, which defines the frame of the beacons, and each odometry measurement adds
+/// a pose, to which the ranges measured afterwards belong. Beacons at known positions, e.g. surveyed anchors, are
+/// fixed. Each unknown beacon starts at the multilateration of its ranges from the poses composed from the odometry
+/// and gets a weak prior at this position, so that the factor graph can be optimized even if the ranges do not
+/// determine the beacon, e.g. for a single range. Directions in which the ranges do not determine a beacon, e.g. its
+/// height above a planar trajectory, start at the mean of the poses.
+///
+/// ```
+/// use gs_rs::calibration::{BeaconCalibrationBuilder, BeaconNoise};
+/// use gs_rs::optimizer::optimize;
+///
+/// let beacon: [f64; 2] = [2.0, 3.0];
+/// let mut builder = BeaconCalibrationBuilder::new_2d(BeaconNoise::default());
+/// for step in 0..8 {
+///     if step > 0 {
+///         builder.add_odometry(vec![1.0, 0.0, std::f64::consts::FRAC_PI_2]).unwrap();
+///     }
+///     // the vehicle drives around the square with the corners (0, 0) and (1, 1)
+///     let (x, y) = [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)][step % 4];
+///     builder.add_range(0, ((beacon[0] - x).powi(2) + (beacon[1] - y).powi(2)).sqrt()).unwrap();
+/// }
+/// let (factor_graph, beacons) = builder.build().unwrap();
+/// optimize(&factor_graph, 10);
+/// let estimate = factor_graph.get_var_by_id(beacons[&0]).unwrap().get_content();
+/// assert!((estimate[0] - 2.0).abs() < 1e-6 && (estimate[1] - 3.0).abs() < 1e-6);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct BeaconCalibrationBuilder {
+    noise: BeaconNoise,
+    // the dimension of the beacons' positions
+    dim: usize,
+    poses: Vec<Vec<f64>>,
+    odometry: Vec<Vec<f64>>,
+    // the index of the pose, the beacon and the range
+    ranges: Vec<(usize, usize, f64)>,
+    known_beacons: BTreeMap<usize, Vec<f64>>,
+}
+
+impl BeaconCalibrationBuilder {
+    /// Returns a builder of a calibration with Vehicle2D poses and Landmark2D beacons.
+    pub fn new_2d(noise: BeaconNoise) -> Self {
+        Self::new(noise, 2, vec![0.0; 3])
+    }
+
+    /// Returns a builder of a calibration with Vehicle3D poses and Landmark3D beacons.
+    pub fn new_3d(noise: BeaconNoise) -> Self {
+        Self::new(noise, 3, vec![0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0])
+    }
+
+    fn new(noise: BeaconNoise, dim: usize, origin: Vec<f64>) -> Self {
+        BeaconCalibrationBuilder {
+            noise,
+            dim,
+            poses: vec![origin],
+            odometry: vec![],
+            ranges: vec![],
+            known_beacons: BTreeMap::new(),
+        }
+    }
+
+    /// Adds a pose which is measured relative to the latest pose by the given odometry, in the format of the
+    /// constraints of Odometry2D or Odometry3D factors, and returns its ID.
+    ///
+    /// Returns an error if the odometry has the wrong number of values.
+    pub fn add_odometry(&mut self, odometry: Vec<f64>) -> Result<VariableId, String> {
+        let latest = self.poses.last().unwrap();
+        if odometry.len() != latest.len() {
+            return Err(format!(
+                "The odometry has {} values, but poses have {}",
+                odometry.len(),
+                latest.len()
+            ));
+        }
+        let pose = if self.dim == 2 {
+            let position =
+                Vector2::new(latest[0], latest[1]) + Rotation2::new(latest[2]) * Vector2::new(odometry[0], odometry[1]);
+            let rotation = (latest[2] + odometry[2] + PI).rem_euclid(2.0 * PI) - PI;
+            vec![position.x, position.y, rotation]
+        } else {
+            get_content(&(get_isometry(latest) * get_isometry(&odometry)))
+        };
+        self.poses.push(pose);
+        self.odometry.push(odometry);
+        Ok(VariableId(self.poses.len() - 1))
+    }
+
+    /// Adds the range to the beacon with the given ID, which is measured from the latest pose.
+    ///
+    /// Returns an error if the range is negative or if the range to the beacon was already measured from the latest
+    /// pose.
+    pub fn add_range(&mut self, beacon: usize, range: f64) -> Result<(), String> {
+        let pose = self.poses.len() - 1;
+        if range.is_nan() || range < 0.0 {
+            return Err(format!(
+                "The range to beacon {} must be non-negative, but is {}",
+                beacon, range
+            ));
+        }
+        if self.ranges.iter().any(|(p, b, _)| *p == pose && *b == beacon) {
+            return Err(format!(
+                "The range to beacon {} was already measured from pose {}",
+                beacon, pose
+            ));
+        }
+        self.ranges.push((pose, beacon, range));
+        Ok(())
+    }
+
+    /// Adds a beacon at the given known position, which is fixed.
+    ///
+    /// Returns an error if the position has the wrong number of values.
+    pub fn add_known_beacon(&mut self, beacon: usize, position: Vec<f64>) -> Result<(), String> {
+        if position.len() != self.dim {
+            return Err(format!(
+                "The position of beacon {} has {} values, but beacons have {}",
+                beacon,
+                position.len(),
+                self.dim
+            ));
+        }
+        self.known_beacons.insert(beacon, position);
+        Ok(())
+    }
+
+    /// Returns the factor graph and the map from the beacon IDs to the IDs of their landmarks, which follow the
+    /// poses in ascending order of beacon ID.
+    pub fn build(&self) -> Result<(FactorGraph, BTreeMap<usize, VariableId>), String> {
+        let (vehicle_type, landmark_type, odometry_type, range_type) = match self.dim {
+            2 => ("Vehicle2D", "Landmark2D", "Odometry2D", "Range2D"),
+            _ => ("Vehicle3D", "Landmark3D", "Odometry3D", "Range3D"),
+        };
+        let beacon_ids: BTreeSet<usize> = self
+            .ranges
+            .iter()
+            .map(|(_, beacon, _)| *beacon)
+            .chain(self.known_beacons.keys().copied())
+            .collect();
+        let beacons: BTreeMap<usize, VariableId> = beacon_ids
+            .iter()
+            .enumerate()
+            .map(|(i, beacon)| (*beacon, VariableId(self.poses.len() + i)))
+            .collect();
+
+        let mut model = FactorGraphModel {
+            fixed_vertices: std::iter::once(VariableId(0)).collect(),
+            ..Default::default()
+        };
+        for (i, pose) in self.poses.iter().enumerate() {
+            model.vertices.push(Vertex {
+                id: VariableId(i),
+                vertex_type: String::from(vehicle_type),
+                content: pose.clone(),
+            });
+        }
+        let odometry_dim = 3 * (self.dim - 1);
+        for (i, odometry) in self.odometry.iter().enumerate() {
+            model.edges.push(Edge {
+                edge_type: String::from(odometry_type),
+                vertices: vec![VariableId(i), VariableId(i + 1)],
+                restriction: odometry.clone(),
+                information_matrix: get_information(odometry_dim, self.noise.odometry_deviation),
+            });
+        }
+        for (beacon, id) in &beacons {
+            let content = match self.known_beacons.get(beacon) {
+                Some(position) => {
+                    model.fixed_vertices.insert(*id);
+                    position.clone()
+                }
+                None => {
+                    let position = self.multilaterate(*beacon);
+                    model.edges.push(Edge {
+                        edge_type: String::from("DensePrior"),
+                        vertices: vec![*id],
+                        restriction: position.clone(),
+                        information_matrix: get_information(self.dim, self.noise.beacon_prior_deviation),
+                    });
+                    position
+                }
+            };
+            model.vertices.push(Vertex {
+                id: *id,
+                vertex_type: String::from(landmark_type),
+                content,
+            });
+        }
+        for (pose, beacon, range) in &self.ranges {
+            model.edges.push(Edge {
+                edge_type: String::from(range_type),
+                vertices: vec![VariableId(*pose), beacons[beacon]],
+                restriction: vec![*range],
+                information_matrix: get_information(1, self.noise.range_deviation),
+            });
+        }
+        Ok((convert_model(&model, &BTreeMap::new())?, beacons))
+    }
+
+    // returns the least squares solution of the differences between the squared ranges to the beacon, relative to
+    // the mean of its poses, or the position at its only range in the direction of the x axis
+    fn multilaterate(&self, beacon: usize) -> Vec<f64> {
+        let ranges: Vec<(DVector<f64>, f64)> = self
+            .ranges
+            .iter()
+            .filter(|(_, b, _)| *b == beacon)
+            .map(|(pose, _, range)| (DVector::from_column_slice(&self.poses[*pose][..self.dim]), *range))
+            .collect();
+        let mean = ranges.iter().map(|(p, _)| p).sum::<DVector<f64>>() / ranges.len() as f64;
+        if ranges.len() == 1 {
+            let mut position = mean;
+            position[0] += ranges[0].1;
+            return position.as_slice().to_vec();
+        }
+        let (first, first_range) = (&ranges[0].0 - &mean, ranges[0].1);
+        let mut lhs = DMatrix::zeros(ranges.len() - 1, self.dim);
+        let mut rhs = DVector::zeros(ranges.len() - 1);
+        for (k, (position, range)) in ranges.iter().skip(1).enumerate() {
+            let relative = position - &mean;
+            lhs.row_mut(k).copy_from(&((&relative - &first) * 2.0).transpose());
+            rhs[k] = relative.norm_squared() - first.norm_squared() - range * range + first_range * first_range;
+        }
+        let solution = lhs.svd(true, true).solve(&rhs, 1e-9).unwrap();
+        (mean + solution).as_slice().to_vec()
+    }
+}
+
+// returns the row-major information matrix of independent errors with the given standard deviation
+fn get_information(dim: usize, deviation: f64) -> Vec<f64> {
+    (DMatrix::<f64>::identity(dim, dim) / (deviation * deviation))
+        .as_slice()
+        .to_vec()
+}
+
+// returns the closed-form initial estimate, whose rotation maps the rotation vectors of the B motions onto the ones
+// of the A motions and whose translation solves (R_A - I) t = R t_B - t_A in the least squares sense
+fn initialize_hand_eye(pairs: &[(Isometry3<f64>, Isometry3<f64>)]) -> Result<Isometry3<f64>, String> {
+    let correlation: Matrix3<f64> = pairs
+        .iter()
+        .map(|(a, b)| b.rotation.scaled_axis() * a.rotation.scaled_axis().transpose())
+        .sum();
+    let svd = correlation.svd(true, true);
+    let (u, v_t) = (svd.u.unwrap(), svd.v_t.unwrap());
+    if svd.singular_values[1] < 1e-9 * svd.singular_values[0].max(1e-12) {
+        return Err(String::from(
+            "The rotation axes of the motions are parallel, so the hand-eye transform is not determined",
+        ));
+    }
+    let mut correction = Matrix3::identity();
+    correction[(2, 2)] = (v_t.transpose() * u.transpose()).determinant().signum();
+    let rotation = Rotation3::from_matrix_unchecked(v_t.transpose() * correction * u.transpose());
+
+    let mut lhs = DMatrix::zeros(3 * pairs.len(), 3);
+    let mut rhs = DVector::zeros(3 * pairs.len());
+    for (i, (a, b)) in pairs.iter().enumerate() {
+        lhs.slice_mut((3 * i, 0), (3, 3))
+            .copy_from(&(a.rotation.to_rotation_matrix().matrix() - Matrix3::identity()));
+        rhs.rows_mut(3 * i, 3)
+            .copy_from(&(rotation * b.translation.vector - a.translation.vector));
+    }
+    let translation = lhs.svd(true, true).solve(&rhs, 1e-12)?;
+    Ok(Isometry3::from_parts(
+        Translation3::new(translation[0], translation[1], translation[2]),
+        UnitQuaternion::from_rotation_matrix(&rotation),
+    ))
+}
+
+// returns the pose as [x, y, z, qx, qy, qz, qw]
+fn get_content(pose: &Isometry3<f64>) -> Vec<f64> {
+    let mut content = pose.translation.vector.data.as_slice().to_vec();
+    content.extend_from_slice(pose.rotation.quaternion().coords.data.as_slice());
+    content
+}
+
+fn get_dual_pose(content: &[Dual]) -> DualPose {
+    (
+        [content[0], content[1], content[2]],
+        [content[3], content[4], content[5], content[6]],
+    )
+}
+
+fn compose(a: &DualPose, b: &DualPose) -> DualPose {
+    let rotated = rotate(&a.1, &b.0);
+    (
+        [a.0[0] + rotated[0], a.0[1] + rotated[1], a.0[2] + rotated[2]],
+        multiply(&a.1, &b.1),
+    )
+}
+
+fn multiply(a: &[Dual; 4], b: &[Dual; 4]) -> [Dual; 4] {
+    [
+        a[3] * b[0] + a[0] * b[3] + a[1] * b[2] - a[2] * b[1],
+        a[3] * b[1] - a[0] * b[2] + a[1] * b[3] + a[2] * b[0],
+        a[3] * b[2] + a[0] * b[1] - a[1] * b[0] + a[2] * b[3],
+        a[3] * b[3] - a[0] * b[0] - a[1] * b[1] - a[2] * b[2],
+    ]
+}
+
+fn conjugate(q: &[Dual; 4]) -> [Dual; 4] {
+    [-q[0], -q[1], -q[2], q[3]]
+}
+
+// rotates the vector with the unit quaternion
+fn rotate(q: &[Dual; 4], v: &[Dual; 3]) -> [Dual; 3] {
+    let rotated = multiply(&multiply(q, &[v[0], v[1], v[2], Dual::constant(0.0)]), &conjugate(q));
+    [rotated[0], rotated[1], rotated[2]]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::Vector3;
+
+    fn get_extrinsic() -> Isometry3<f64> {
+        Isometry3::new(Vector3::new(0.3, -0.1, 0.5), Vector3::new(0.2, -0.4, 1.0))
+    }
+
+    fn get_motions() -> Vec<Isometry3<f64>> {
+        vec![
+            Isometry3::new(Vector3::new(1.0, 0.2, 0.0), Vector3::new(0.5, 0.1, 0.0)),
+            Isometry3::new(Vector3::new(0.0, 1.0, -0.3), Vector3::new(0.0, 0.2, 0.7)),
+            Isometry3::new(Vector3::new(-0.5, 0.4, 1.0), Vector3::new(0.3, -0.6, 0.2)),
+            Isometry3::new(Vector3::new(0.2, -0.8, 0.5), Vector3::new(-0.4, 0.0, -0.5)),
+        ]
+    }
+
+    fn assert_pose_eq(pose: &Isometry3<f64>, expected: &Isometry3<f64>, epsilon: f64) {
+        let difference = pose.inverse() * expected;
+        assert!(
+            difference.translation.vector.norm() < epsilon && difference.rotation.angle() < epsilon,
+            "{} != {}",
+            pose,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_exact_motions() {
+        let extrinsic = get_extrinsic();
+        let pairs: Vec<_> = get_motions()
+            .iter()
+            .map(|a| (*a, extrinsic.inverse() * a * extrinsic))
+            .collect();
+        assert_pose_eq(&initialize_hand_eye(&pairs).unwrap(), &extrinsic, 1e-9);
+        let calibration = calibrate_hand_eye(&pairs, 5).unwrap();
+        assert_pose_eq(&calibration.extrinsic, &extrinsic, 1e-9);
+        assert!(calibration.translation_rms < 1e-9 && calibration.rotation_rms < 1e-9);
+    }
+
+    #[test]
+    fn test_noisy_motions() {
+        let extrinsic = get_extrinsic();
+        let noise = [
+            Isometry3::new(Vector3::new(0.01, 0.0, -0.01), Vector3::new(0.0, 0.005, 0.0)),
+            Isometry3::new(Vector3::new(0.0, -0.01, 0.0), Vector3::new(-0.005, 0.0, 0.005)),
+        ];
+        let pairs: Vec<_> = get_motions()
+            .iter()
+            .enumerate()
+            .map(|(i, a)| (*a, extrinsic.inverse() * a * extrinsic * noise[i % 2]))
+            .collect();
+        let calibration = calibrate_hand_eye(&pairs, 10).unwrap();
+        assert_pose_eq(&calibration.extrinsic, &extrinsic, 0.05);
+        assert!(calibration.translation_rms > 0.0 && calibration.translation_rms < 0.05);
+        let initial = initialize_hand_eye(&pairs).unwrap();
+        let get_residual_sum = |x: &Isometry3<f64>| -> f64 {
+            pairs
+                .iter()
+                .map(|(a, b)| ((a * x).translation.vector - (x * b).translation.vector).norm_squared())
+                .sum()
+        };
+        assert!(get_residual_sum(&calibration.extrinsic) <= get_residual_sum(&initial));
+    }
+
+    // returns the poses of a 2D vehicle which drives forward by 1 and turns by π/2 in each step, and the odometry
+    fn get_square_trajectory(steps: usize) -> (Vec<[f64; 2]>, Vec<f64>) {
+        let positions = (0..=steps)
+            .map(|k| [[0.0, 0.0], [1.0, 0.0], [1.0, 1.0], [0.0, 1.0]][k % 4])
+            .collect();
+        (positions, vec![1.0, 0.0, std::f64::consts::FRAC_PI_2])
+    }
+
+    fn get_range(position: &[f64], beacon: &[f64]) -> f64 {
+        position
+            .iter()
+            .zip(beacon)
+            .map(|(p, b)| (b - p).powi(2))
+            .sum::<f64>()
+            .sqrt()
+    }
+
+    #[test]
+    fn test_beacon_calibration() {
+        let beacons = [[2.0, 3.0], [-1.5, 0.5], [0.5, -2.0]];
+        let (positions, odometry) = get_square_trajectory(8);
+        let mut builder = BeaconCalibrationBuilder::new_2d(BeaconNoise::default());
+        for (k, position) in positions.iter().enumerate() {
+            if k > 0 {
+                // the odometry overestimates the distances
+                let mut odometry = odometry.clone();
+                odometry[0] *= 1.02;
+                builder.add_odometry(odometry).unwrap();
+            }
+            for (beacon, beacon_position) in beacons.iter().enumerate() {
+                builder.add_range(beacon, get_range(position, beacon_position)).unwrap();
+            }
+        }
+        builder.add_known_beacon(7, vec![5.0, 5.0]).unwrap();
+        let (factor_graph, ids) = builder.build().unwrap();
+        let expected: BTreeMap<usize, VariableId> = [(0, 9), (1, 10), (2, 11), (7, 12)]
+            .iter()
+            .map(|(beacon, id)| (*beacon, VariableId(*id)))
+            .collect();
+        assert_eq!(ids, expected);
+        assert_eq!(
+            factor_graph.get_var_by_id(VariableId(12)).unwrap().get_content(),
+            vec![5.0, 5.0]
+        );
+
+        let get_error = |beacon: usize| -> f64 {
+            let estimate = factor_graph.get_var_by_id(ids[&beacon]).unwrap().get_content();
+            get_range(&estimate, &beacons[beacon])
+        };
+        let initial_error: f64 = (0..3).map(get_error).sum();
+        optimize(&factor_graph, 10);
+        let error: f64 = (0..3).map(get_error).sum();
+        assert!(error < 0.5 * initial_error, "{} >= 0.5 * {}", error, initial_error);
+    }
+
+    #[test]
+    fn test_beacon_calibration_3d() {
+        let beacon = [0.5, 2.0, 0.0];
+        let (positions, _) = get_square_trajectory(4);
+        let quarter_turn = std::f64::consts::FRAC_1_SQRT_2;
+        let mut builder = BeaconCalibrationBuilder::new_3d(BeaconNoise::default());
+        assert!(builder.add_odometry(vec![1.0, 0.0, 0.0]).is_err());
+        for (k, position) in positions.iter().enumerate() {
+            if k > 0 {
+                let id = builder
+                    .add_odometry(vec![1.0, 0.0, 0.0, 0.0, 0.0, quarter_turn, quarter_turn])
+                    .unwrap();
+                assert_eq!(id, VariableId(k));
+            }
+            builder
+                .add_range(0, get_range(&[position[0], position[1], 0.0], &beacon))
+                .unwrap();
+        }
+        assert!(builder.add_range(0, 1.0).is_err());
+        assert!(builder.add_range(1, -1.0).is_err());
+        assert!(builder.add_known_beacon(1, vec![0.0, 0.0]).is_err());
+        let (factor_graph, ids) = builder.build().unwrap();
+        optimize(&factor_graph, 10);
+        let estimate = factor_graph.get_var_by_id(ids[&0]).unwrap().get_content();
+        assert!(get_range(&estimate, &beacon) < 1e-6, "{:?}", estimate);
+    }
+
+    #[test]
+    fn test_undetermined_calibration() {
+        let extrinsic = get_extrinsic();
+        let motion = get_motions()[0];
+        assert!(calibrate_hand_eye(&[(motion, extrinsic.inverse() * motion * extrinsic)], 5).is_err());
+        let parallel: Vec<_> = [motion, motion * motion]
+            .iter()
+            .map(|a| (*a, extrinsic.inverse() * a * extrinsic))
+            .collect();
+        assert!(calibrate_hand_eye(&parallel, 5).is_err());
+    }
+}
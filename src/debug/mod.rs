@@ -0,0 +1,324 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Utilities for validating the optimizer's internals.
+
+#![allow(non_snake_case)]
+
+use crate::factor_graph::factor::{FactorId, FactorType};
+use crate::factor_graph::variable::{FixedType, Variable};
+use crate::factor_graph::FactorGraph;
+use crate::optimizer::linear_system::{calculate_H_b, calculate_error, calculate_jacobian};
+use nalgebra::{DMatrix, DVector};
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+/// The step size of the central differences.
+const STEP: f64 = 1e-6;
+
+/// The deviation between the analytic and the numerical Jacobians of all factors of one type.
+#[derive(Debug, Clone, PartialEq)]
+pub struct JacobianDeviation {
+    /// The checked factor type.
+    pub factor_type: FactorType,
+    /// The number of checked factors of this type.
+    pub factor_count: usize,
+    /// The maximum absolute difference between an entry of an analytic Jacobian and its numerical approximation.
+    pub max_deviation: f64,
+    /// The factor at which the maximum deviation occurred.
+    pub worst_factor: FactorId,
+}
+
+/// Compares the analytic Jacobian of each factor against a Jacobian calculated with central differences at the
+/// current variable estimates and returns the maximum deviation per factor type, in the order of first occurrence.
+///
+/// The variables are perturbed through their local parameterizations, so the check also covers the consistency of
+/// the Jacobians with the way corrections are applied. All variables are restored afterwards.
+pub fn check_jacobians(factor_graph: &FactorGraph) -> Vec<JacobianDeviation> {
+    let mut deviations: Vec<JacobianDeviation> = vec![];
+    for edge in factor_graph
+        .node_indices
+        .iter()
+        .flat_map(|i| factor_graph.adjacency.edges(*i))
+    {
+        let factor = edge.weight();
+        let analytic = calculate_jacobian(factor_graph, factor.id).unwrap();
+        let numerical = calculate_numerical_jacobian(factor_graph, factor.id);
+        let deviation = (analytic - numerical).abs().max();
+
+        match deviations.iter_mut().find(|d| d.factor_type == factor.factor_type) {
+            Some(d) => {
+                d.factor_count += 1;
+                if deviation > d.max_deviation {
+                    d.max_deviation = deviation;
+                    d.worst_factor = factor.id;
+                }
+            }
+            None => deviations.push(JacobianDeviation {
+                factor_type: factor.factor_type.clone(),
+                factor_count: 1,
+                max_deviation: deviation,
+                worst_factor: factor.id,
+            }),
+        }
+    }
+    deviations
+}
+
+// calculates the Jacobian of the factor with the given ID with central differences along the corrections of its
+// variables, which are restored afterwards
+pub(crate) fn calculate_numerical_jacobian(factor_graph: &FactorGraph, id: FactorId) -> DMatrix<f64> {
+    let variables: Vec<&Variable> = factor_graph
+        .get_factor_var_indices(id)
+        .unwrap()
+        .iter()
+        .map(|i| factor_graph.get_var(*i))
+        .collect();
+    let mut columns: Vec<DVector<f64>> = vec![];
+    for var in variables {
+        let parameterization = var.get_parameterization();
+        let content = var.get_content();
+        for k in 0..parameterization.tangent_dim() {
+            let mut correction = vec![0.0; parameterization.tangent_dim()];
+            correction[k] = STEP;
+            var.set_content(parameterization.plus(&content, &correction));
+            let err_plus = calculate_error(factor_graph, id).unwrap();
+            correction[k] = -STEP;
+            var.set_content(parameterization.plus(&content, &correction));
+            let err_minus = calculate_error(factor_graph, id).unwrap();
+            columns.push((err_plus - err_minus) / (2.0 * STEP));
+        }
+        var.set_content(content);
+    }
+    DMatrix::from_columns(&columns)
+}
+
+/// Writes the linear system H * x = -b of the next Gauss-Newton iteration at the current estimates into the given
+/// directory, which is created if necessary, e.g. to compare it with the systems of g2o or Ceres. To dump the system
+/// of the k-th iteration, the factor graph can be optimized with k - 1 iterations first.
+///
+/// The directory contains the following files:
+/// * "H.mtx": the lower triangle of H in the Matrix Market coordinate format for real symmetric matrices
+/// * "b.mtx": b in the Matrix Market array format for real general matrices
+/// * "variables.txt": one line "<variable ID> <first row> <last row>" per non-fixed variable, which maps the
+///   variable to its rows in H and b, counting from 1 like Matrix Market
+///
+/// Returns an error if a file could not be written.
+pub fn dump_system(factor_graph: &FactorGraph, path: &str) -> Result<(), String> {
+    let directory = Path::new(path);
+    fs::create_dir_all(directory).map_err(|_| format!("Directory could not be created: {}", path))?;
+    let (H, b) = calculate_H_b(factor_graph);
+
+    let mut entries: BTreeMap<(usize, usize), f64> = BTreeMap::new();
+    for ((row, col), block) in H.blocks() {
+        for ((i, j), value) in (0..block.ncols())
+            .flat_map(|j| (0..block.nrows()).map(move |i| (i, j)))
+            .zip(block.iter())
+        {
+            if row + i >= col + j && *value != 0.0 {
+                *entries.entry((row + i, col + j)).or_insert(0.0) += value;
+            }
+        }
+    }
+    let mut H_lines = vec![
+        String::from("%%MatrixMarket matrix coordinate real symmetric"),
+        format!("{} {} {}", H.dim(), H.dim(), entries.len()),
+    ];
+    H_lines.extend(
+        entries
+            .iter()
+            .map(|((row, col), value)| format!("{} {} {:?}", row + 1, col + 1, value)),
+    );
+    write_lines(&directory.join("H.mtx"), &H_lines)?;
+
+    let mut b_lines = vec![
+        String::from("%%MatrixMarket matrix array real general"),
+        format!("{} 1", b.len()),
+    ];
+    b_lines.extend(b.iter().map(|value| format!("{:?}", value)));
+    write_lines(&directory.join("b.mtx"), &b_lines)?;
+
+    let variable_lines: Vec<String> = factor_graph
+        .node_indices
+        .iter()
+        .map(|i| factor_graph.get_var(*i))
+        .filter_map(|var| match var.get_fixed_type() {
+            FixedType::NonFixed(range) => Some(format!("{} {} {}", var.get_id(), range.start + 1, range.end)),
+            FixedType::Fixed => None,
+        })
+        .collect();
+    write_lines(&directory.join("variables.txt"), &variable_lines)
+}
+
+fn write_lines(file_path: &Path, lines: &[String]) -> Result<(), String> {
+    let mut content = lines.join("\n");
+    content.push('\n');
+    fs::write(file_path, content).map_err(|_| format!("File could not be written to: {}", file_path.display()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::examples_gen::single_landmark_with_two_observations;
+    use crate::parser::g2o::G2oParser;
+    use crate::parser::json::JsonParser;
+    use crate::parser::Parser;
+    use log::{info, LevelFilter};
+
+    fn init() {
+        let _ = env_logger::builder()
+            .is_test(true)
+            .filter_level(LevelFilter::Debug)
+            .try_init();
+    }
+
+    fn assert_jacobians_match(factor_graph: &FactorGraph, expected_type_count: usize) {
+        let contents: Vec<Vec<f64>> = factor_graph
+            .node_indices
+            .iter()
+            .map(|i| factor_graph.get_var(*i).get_content())
+            .collect();
+        let deviations = check_jacobians(factor_graph);
+        info!("{:?}", deviations);
+        assert_eq!(deviations.len(), expected_type_count);
+        deviations
+            .iter()
+            .for_each(|d| assert!(d.max_deviation < 1e-6, "{:?}", d));
+        let restored_contents: Vec<Vec<f64>> = factor_graph
+            .node_indices
+            .iter()
+            .map(|i| factor_graph.get_var(*i).get_content())
+            .collect();
+        assert_eq!(contents, restored_contents);
+    }
+
+    #[test]
+    fn test_2d_jacobians() {
+        init();
+        assert_jacobians_match(
+            &JsonParser::parse_file("data_files/full_demos/all_2d_types.json").unwrap(),
+            3,
+        );
+        assert_jacobians_match(
+            &G2oParser::parse_file("data_files/optimizer_tests/full2d_0.g2o").unwrap(),
+            3,
+        );
+    }
+
+    #[test]
+    fn test_3d_jacobians() {
+        init();
+        assert_jacobians_match(
+            &JsonParser::parse_file("data_files/full_demos/all_3d_types.json").unwrap(),
+            3,
+        );
+        assert_jacobians_match(
+            &G2oParser::parse_file("data_files/optimizer_tests/obs3d_mainly_0.g2o").unwrap(),
+            2,
+        );
+        assert_jacobians_match(
+            &G2oParser::parse_file("data_files/optimizer_tests/odo3d_only_0.g2o").unwrap(),
+            1,
+        );
+    }
+
+    #[test]
+    fn test_custom_jacobians() {
+        use crate::factor_graph::factor::CustomResidual;
+        use crate::factor_graph::variable::VariableId;
+        use crate::optimizer::autodiff::Dual;
+
+        init();
+        let g2o_string = ["VERTEX_SE3:QUAT 0 1 2 3 0.1 -0.3 0.2 0.927", "VERTEX_TRACKXYZ 1 2 4 1"].join("\n");
+        let mut factor_graph: FactorGraph = G2oParser::parse_string_to_model(&g2o_string).unwrap().into();
+        // the landmark's position in the vehicle's frame, i.e. the residual of Observation3D factors
+        let residual = CustomResidual::new("CustomObservation3D", |contents: &[Vec<Dual>], constraint: &[f64]| {
+            let (pose, position) = (&contents[0], &contents[1]);
+            let delta: Vec<Dual> = (0..3).map(|k| position[k] - pose[k]).collect();
+            // inverse rotation v + 2w(u x v) + 2u x (u x v) with u = -[q_x, q_y, q_z]
+            let (u, w) = ([-pose[3], -pose[4], -pose[5]], pose[6]);
+            let cross = |a: &[Dual], b: &[Dual]| {
+                vec![
+                    a[1] * b[2] - a[2] * b[1],
+                    a[2] * b[0] - a[0] * b[2],
+                    a[0] * b[1] - a[1] * b[0],
+                ]
+            };
+            let u_delta = cross(&u, &delta);
+            let u_u_delta = cross(&u, &u_delta);
+            (0..3)
+                .map(|k| delta[k] + 2.0 * w * u_delta[k] + 2.0 * u_u_delta[k] - constraint[k])
+                .collect()
+        });
+        factor_graph
+            .add_factor(
+                VariableId(0),
+                VariableId(1),
+                FactorType::Custom(residual),
+                vec![0.5, 1.0, -1.0],
+                vec![1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0].into(),
+            )
+            .unwrap();
+        assert_jacobians_match(&factor_graph, 1);
+    }
+
+    #[test]
+    fn test_switchable_jacobians() {
+        init();
+        let g2o_string = [
+            "VERTEX_SE2 0 1 2 0.3",
+            "VERTEX_SE2 1 2 4 -1.2",
+            "VERTEX_SE3:QUAT 2 1 2 3 0.1 -0.3 0.2 0.927",
+            "VERTEX_SE3:QUAT 3 2 4 1 -0.2 0.4 0.1 0.888",
+            "VERTEX_SWITCH 4 0.5",
+            "VERTEX_SWITCH 5 0.5",
+            "EDGE_SE2_SWITCHABLE 0 1 4 1 1.5 0.5 1 0 0 1 0 1",
+            "EDGE_SE3_SWITCHABLE 2 3 5 1 1 -2 0 0.6 0 0.8 1 0 0 0 0 0 1 0 0 0 0 1 0 0 0 1 0 0 1 0 1",
+            "EDGE_SWITCH_PRIOR 4 1 1",
+        ]
+        .join("\n");
+        let factor_graph: FactorGraph = G2oParser::parse_string_to_model(&g2o_string).unwrap().into();
+        assert_jacobians_match(&factor_graph, 3);
+    }
+
+    #[test]
+    fn test_dump_system() {
+        let factor_graph: FactorGraph = single_landmark_with_two_observations().into();
+        let directory = std::env::temp_dir().join(format!("gs-rs-system-{}", std::process::id()));
+        dump_system(&factor_graph, directory.to_str().unwrap()).unwrap();
+        let read = |name: &str| fs::read_to_string(directory.join(name)).unwrap();
+        let (H_file, b_file, variables_file) = (read("H.mtx"), read("b.mtx"), read("variables.txt"));
+        fs::remove_dir_all(&directory).unwrap();
+
+        let (H, b) = calculate_H_b(&factor_graph);
+        let mut H_lines = H_file.lines();
+        assert_eq!(H_lines.next(), Some("%%MatrixMarket matrix coordinate real symmetric"));
+        let size: Vec<usize> = H_lines.next().unwrap().split(' ').map(|s| s.parse().unwrap()).collect();
+        let mut parsed_H = DMatrix::zeros(size[0], size[1]);
+        for line in H_lines {
+            let tokens: Vec<&str> = line.split(' ').collect();
+            let (row, col): (usize, usize) = (tokens[0].parse().unwrap(), tokens[1].parse().unwrap());
+            assert!(row >= col);
+            parsed_H[(row - 1, col - 1)] = tokens[2].parse().unwrap();
+            parsed_H[(col - 1, row - 1)] = tokens[2].parse().unwrap();
+        }
+        assert_eq!(size[2], H_file.lines().count() - 2);
+        assert_eq!(parsed_H, H.to_dense());
+
+        let parsed_b: Vec<f64> = b_file.lines().skip(2).map(|s| s.parse().unwrap()).collect();
+        assert_eq!(b_file.lines().nth(1), Some("5 1"));
+        assert_eq!(parsed_b, b.data.as_vec().clone());
+        // vehicle 0 is fixed
+        assert_eq!(variables_file, "1 1 3\n2 4 5\n");
+    }
+}
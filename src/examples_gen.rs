@@ -0,0 +1,163 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Small canonical factor graphs, e.g. as known-good starting points for experiments.
+//!
+//! The graphs are returned as [models](../parser/model/struct.FactorGraphModel.html), which can be converted into
+//! factor graphs, serialized or extended. Their measurements are consistent, so the total χ² is zero after the
+//! optimization, while their initial estimates are slightly off.
+//!
+//! ```
+//! use gs_rs::examples_gen::triangle_with_loop_closure;
+//! use gs_rs::factor_graph::FactorGraph;
+//! use gs_rs::optimizer::{optimize, total_chi2};
+//!
+//! let factor_graph: FactorGraph = triangle_with_loop_closure().into();
+//! optimize(&factor_graph, 10);
+//! assert!(total_chi2(&factor_graph) < 1e-12);
+//! ```
+
+use crate::factor_graph::variable::VariableId;
+use crate::parser::model::{Edge, FactorGraphModel, Vertex};
+use alloc::collections::BTreeSet;
+use alloc::string::String;
+use alloc::vec;
+use alloc::vec::Vec;
+
+/// Returns three 2D vehicle poses along an equilateral triangle with sides of length 1, connected by two odometry
+/// factors and a loop closure from the last pose back to the first one, which is fixed.
+///
+/// The optimized poses are (0, 0, 0), (1, 0, 2π/3) and (1/2, √3/2, -2π/3).
+///
+/// ```
+/// use gs_rs::examples_gen::triangle_with_loop_closure;
+/// use gs_rs::factor_graph::FactorGraph;
+/// use gs_rs::factor_graph::variable::VariableId;
+/// use gs_rs::optimizer::optimize;
+///
+/// let factor_graph: FactorGraph = triangle_with_loop_closure().into();
+/// optimize(&factor_graph, 10);
+/// let pose = factor_graph.get_var_by_id(VariableId(1)).unwrap().get_content();
+/// assert!((pose[0] - 1.0).abs() < 1e-6 && pose[1].abs() < 1e-6);
+/// ```
+pub fn triangle_with_loop_closure() -> FactorGraphModel {
+    let turn = 2.0 * core::f64::consts::FRAC_PI_3;
+    FactorGraphModel {
+        vertices: vec![
+            get_vertex(0, "Vehicle2D", vec![0.0, 0.0, 0.0]),
+            get_vertex(1, "Vehicle2D", vec![1.1, 0.1, 2.0]),
+            get_vertex(2, "Vehicle2D", vec![0.4, 1.0, -2.0]),
+        ],
+        edges: vec![
+            get_edge("Odometry2D", vec![0, 1], vec![1.0, 0.0, turn]),
+            get_edge("Odometry2D", vec![1, 2], vec![1.0, 0.0, turn]),
+            get_edge("Odometry2D", vec![2, 0], vec![1.0, 0.0, turn]),
+        ],
+        fixed_vertices: get_fixed_vertices(0),
+        ..Default::default()
+    }
+}
+
+/// Returns two 2D vehicle poses connected by an odometry factor, which both observe a single 2D landmark, and whose
+/// first pose is fixed.
+///
+/// The optimized estimates are (0, 0, 0) and (2, 0, 0) for the vehicle and (1, 1) for the landmark.
+///
+/// ```
+/// use gs_rs::examples_gen::single_landmark_with_two_observations;
+/// use gs_rs::factor_graph::FactorGraph;
+/// use gs_rs::factor_graph::variable::VariableId;
+/// use gs_rs::optimizer::optimize;
+///
+/// let factor_graph: FactorGraph = single_landmark_with_two_observations().into();
+/// optimize(&factor_graph, 10);
+/// let landmark = factor_graph.get_var_by_id(VariableId(2)).unwrap().get_content();
+/// assert!((landmark[0] - 1.0).abs() < 1e-6 && (landmark[1] - 1.0).abs() < 1e-6);
+/// ```
+pub fn single_landmark_with_two_observations() -> FactorGraphModel {
+    FactorGraphModel {
+        vertices: vec![
+            get_vertex(0, "Vehicle2D", vec![0.0, 0.0, 0.0]),
+            get_vertex(1, "Vehicle2D", vec![2.2, -0.1, 0.1]),
+            get_vertex(2, "Landmark2D", vec![0.8, 1.3]),
+        ],
+        edges: vec![
+            get_edge("Odometry2D", vec![0, 1], vec![2.0, 0.0, 0.0]),
+            get_edge("Observation2D", vec![0, 2], vec![1.0, 1.0]),
+            get_edge("Observation2D", vec![1, 2], vec![-1.0, 1.0]),
+        ],
+        fixed_vertices: get_fixed_vertices(0),
+        ..Default::default()
+    }
+}
+
+fn get_vertex(id: usize, vertex_type: &str, content: Vec<f64>) -> Vertex {
+    Vertex {
+        id: VariableId(id),
+        vertex_type: String::from(vertex_type),
+        content,
+    }
+}
+
+// returns an edge with the identity as information matrix
+fn get_edge(edge_type: &str, vertices: Vec<usize>, restriction: Vec<f64>) -> Edge {
+    let dim = restriction.len();
+    Edge {
+        edge_type: String::from(edge_type),
+        vertices: vertices.into_iter().map(VariableId).collect(),
+        restriction,
+        information_matrix: (0..dim * dim)
+            .map(|i| if i % (dim + 1) == 0 { 1.0 } else { 0.0 })
+            .collect(),
+    }
+}
+
+fn get_fixed_vertices(id: usize) -> BTreeSet<VariableId> {
+    let mut fixed_vertices = BTreeSet::new();
+    fixed_vertices.insert(VariableId(id));
+    fixed_vertices
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::factor_graph::FactorGraph;
+    use crate::optimizer::{optimize, total_chi2};
+    use crate::parser::json::JsonParser;
+    use crate::parser::Parser;
+
+    fn assert_consistent(model: FactorGraphModel) {
+        let factor_graph: FactorGraph = model.into();
+        assert!(total_chi2(&factor_graph) > 1e-3);
+        optimize(&factor_graph, 10);
+        assert!(total_chi2(&factor_graph) < 1e-12);
+    }
+
+    #[test]
+    fn test_graphs_are_consistent() {
+        assert_consistent(triangle_with_loop_closure());
+        assert_consistent(single_landmark_with_two_observations());
+    }
+
+    #[test]
+    fn test_graphs_can_be_serialized() {
+        let model = single_landmark_with_two_observations();
+        let factor_graph: FactorGraph =
+            JsonParser::parse_string_to_model(&JsonParser::compose_model_to_string(model).unwrap())
+                .unwrap()
+                .into();
+        assert_eq!(
+            FactorGraphModel::from(&factor_graph),
+            single_landmark_with_two_observations()
+        );
+    }
+}
@@ -0,0 +1,274 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Adjacency list storage of factor graphs, which supports structural changes without a rebuild.
+
+use std::ops::Index;
+use std::slice;
+
+/// The index of a node, which is its position in the order of insertion.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct NodeIndex<Ix = usize>(Ix);
+
+impl NodeIndex<usize> {
+    /// Returns the index of the node at the given position.
+    pub fn new(index: usize) -> Self {
+        NodeIndex(index)
+    }
+
+    /// Returns the position of the node.
+    pub fn index(self) -> usize {
+        self.0
+    }
+}
+
+/// A directed graph storing the outgoing edges of each node in a separate list.
+///
+/// Unlike a CSR (compressed sparse row) representation, adding or removing an edge only changes the list of its
+/// source node. Adding an edge takes amortized O(1). Several edges may connect the same nodes, e.g. multiple unary
+/// factors of a variable, so edges are looked up by a predicate on their weight, which is linear in the degree of the
+/// source node. The outgoing edges of a node are contiguous in memory and iterated in the order in which they were
+/// added.
+#[derive(Debug, Clone)]
+pub struct AdjacencyList<N, E> {
+    nodes: Vec<N>,
+    edges: Vec<Vec<(NodeIndex<usize>, E)>>,
+}
+
+impl<N, E> Default for AdjacencyList<N, E> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<N, E> AdjacencyList<N, E> {
+    /// Returns a graph without any nodes or edges.
+    pub fn new() -> Self {
+        AdjacencyList {
+            nodes: vec![],
+            edges: vec![],
+        }
+    }
+
+    /// Returns the number of nodes.
+    pub fn node_count(&self) -> usize {
+        self.nodes.len()
+    }
+
+    /// Returns the number of edges.
+    pub fn edge_count(&self) -> usize {
+        self.edges.iter().map(Vec::len).sum()
+    }
+
+    /// Adds a node with the given weight and returns its index.
+    pub fn add_node(&mut self, weight: N) -> NodeIndex<usize> {
+        self.nodes.push(weight);
+        self.edges.push(vec![]);
+        NodeIndex(self.nodes.len() - 1)
+    }
+
+    /// Adds an edge from the source to the target node, even if other edges between them already exist.
+    ///
+    /// Panics if either node is not part of the graph.
+    pub fn add_edge(&mut self, source: NodeIndex<usize>, target: NodeIndex<usize>, weight: E) {
+        assert!(target.0 < self.nodes.len(), "Unknown target node: {}", target.0);
+        self.edges[source.0].push((target, weight));
+    }
+
+    /// Returns the weight of the given node, if it is part of the graph.
+    pub fn node_weight_mut(&mut self, index: NodeIndex<usize>) -> Option<&mut N> {
+        self.nodes.get_mut(index.0)
+    }
+
+    /// Returns whether an edge from the source to the target node exists.
+    pub fn contains_edge(&self, source: NodeIndex<usize>, target: NodeIndex<usize>) -> bool {
+        self.edges[source.0].iter().any(|(t, _)| *t == target)
+    }
+
+    /// Returns the weight of the first outgoing edge of the source node whose weight satisfies the predicate.
+    pub fn edge_weight_mut<P: FnMut(&E) -> bool>(
+        &mut self,
+        source: NodeIndex<usize>,
+        mut predicate: P,
+    ) -> Option<&mut E> {
+        self.edges[source.0]
+            .iter_mut()
+            .find(|(_, weight)| predicate(weight))
+            .map(|(_, weight)| weight)
+    }
+
+    /// Removes the first outgoing edge of the source node whose weight satisfies the predicate and returns its
+    /// weight, if it exists.
+    ///
+    /// The order of the source node's remaining edges is preserved.
+    pub fn remove_edge<P: FnMut(&E) -> bool>(&mut self, source: NodeIndex<usize>, mut predicate: P) -> Option<E> {
+        let pos = self.edges[source.0].iter().position(|(_, weight)| predicate(weight))?;
+        Some(self.edges[source.0].remove(pos).1)
+    }
+
+    /// Removes the nodes for which the predicate returns false together with their incoming and outgoing edges, and
+    /// returns the new index of each previous node, or None if it was removed.
+    ///
+    /// The remaining nodes keep their order, so their indices are consecutive again. This takes O(V + E).
+    pub fn retain_nodes<F: FnMut(&N) -> bool>(&mut self, mut keep: F) -> Vec<Option<NodeIndex<usize>>> {
+        let mut count = 0;
+        let new_indices: Vec<Option<NodeIndex<usize>>> = self
+            .nodes
+            .iter()
+            .map(|node| {
+                if keep(node) {
+                    count += 1;
+                    Some(NodeIndex(count - 1))
+                } else {
+                    None
+                }
+            })
+            .collect();
+        let mut is_kept = new_indices.iter().map(Option::is_some);
+        self.nodes.retain(|_| is_kept.next().unwrap());
+        let mut is_kept = new_indices.iter().map(Option::is_some);
+        self.edges.retain(|_| is_kept.next().unwrap());
+        for edges in &mut self.edges {
+            edges.retain(|(target, _)| new_indices[target.0].is_some());
+            edges.iter_mut().for_each(|(target, _)| *target = new_indices[target.0].unwrap());
+        }
+        new_indices
+    }
+
+    /// Returns an iterator over the outgoing edges of the given node.
+    pub fn edges(&self, source: NodeIndex<usize>) -> Edges<'_, E> {
+        Edges {
+            source,
+            iter: self.edges[source.0].iter(),
+        }
+    }
+}
+
+impl<N, E> Index<NodeIndex<usize>> for AdjacencyList<N, E> {
+    type Output = N;
+
+    fn index(&self, index: NodeIndex<usize>) -> &N {
+        &self.nodes[index.0]
+    }
+}
+
+/// A reference to an edge and the nodes it connects.
+#[derive(Debug)]
+pub struct EdgeReference<'a, E> {
+    source: NodeIndex<usize>,
+    target: NodeIndex<usize>,
+    weight: &'a E,
+}
+
+impl<'a, E> EdgeReference<'a, E> {
+    /// Returns the index of the edge's source node.
+    pub fn source(&self) -> NodeIndex<usize> {
+        self.source
+    }
+
+    /// Returns the index of the edge's target node.
+    pub fn target(&self) -> NodeIndex<usize> {
+        self.target
+    }
+
+    /// Returns the edge's weight.
+    pub fn weight(&self) -> &'a E {
+        self.weight
+    }
+}
+
+/// Iterator over the outgoing edges of a node, see [edges](struct.AdjacencyList.html#method.edges).
+#[derive(Debug)]
+pub struct Edges<'a, E> {
+    source: NodeIndex<usize>,
+    iter: slice::Iter<'a, (NodeIndex<usize>, E)>,
+}
+
+impl<'a, E> Iterator for Edges<'a, E> {
+    type Item = EdgeReference<'a, E>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.iter.next().map(|(target, weight)| EdgeReference {
+            source: self.source,
+            target: *target,
+            weight,
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.iter.size_hint()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_add_edge() {
+        let mut graph = AdjacencyList::new();
+        let a = graph.add_node("a");
+        let b = graph.add_node("b");
+        graph.add_edge(a, b, 1);
+        graph.add_edge(a, a, 2);
+        graph.add_edge(a, b, 3);
+        graph.add_edge(b, a, 4);
+        assert_eq!(graph[b], "b");
+        *graph.node_weight_mut(b).unwrap() = "c";
+        assert_eq!(graph[b], "c");
+        assert!(graph.node_weight_mut(NodeIndex::new(2)).is_none());
+        assert_eq!(graph.edge_count(), 4);
+        let edges: Vec<(NodeIndex, NodeIndex, i32)> = graph
+            .edges(a)
+            .map(|edge| (edge.source(), edge.target(), *edge.weight()))
+            .collect();
+        assert_eq!(edges, vec![(a, b, 1), (a, a, 2), (a, b, 3)]);
+    }
+
+    #[test]
+    fn test_remove_and_edit_edges() {
+        let mut graph = AdjacencyList::new();
+        let nodes: Vec<NodeIndex> = (0..4).map(|i| graph.add_node(i)).collect();
+        (1..4).for_each(|i| graph.add_edge(nodes[0], nodes[i], i));
+        graph.add_edge(nodes[0], nodes[2], 20);
+        assert_eq!(graph.remove_edge(nodes[0], |weight| *weight == 2), Some(2));
+        assert_eq!(graph.remove_edge(nodes[0], |weight| *weight == 2), None);
+        assert!(graph.contains_edge(nodes[0], nodes[2]));
+        *graph.edge_weight_mut(nodes[0], |weight| *weight == 3).unwrap() = 30;
+        assert!(graph.edge_weight_mut(nodes[1], |_| true).is_none());
+        let weights: Vec<usize> = graph.edges(nodes[0]).map(|edge| *edge.weight()).collect();
+        assert_eq!(weights, vec![1, 30, 20]);
+        assert_eq!(graph.remove_edge(nodes[0], |weight| *weight == 20), Some(20));
+        assert!(!graph.contains_edge(nodes[0], nodes[2]));
+    }
+
+    #[test]
+    fn test_retain_nodes() {
+        let mut graph = AdjacencyList::new();
+        let nodes: Vec<NodeIndex> = (0..4).map(|i| graph.add_node(i)).collect();
+        graph.add_edge(nodes[0], nodes[1], "01");
+        graph.add_edge(nodes[1], nodes[3], "13");
+        graph.add_edge(nodes[3], nodes[2], "32");
+        graph.add_edge(nodes[3], nodes[0], "30");
+        let new_indices = graph.retain_nodes(|node| *node != 1);
+        assert_eq!(new_indices, vec![Some(nodes[0]), None, Some(nodes[1]), Some(nodes[2])]);
+        assert_eq!(graph.node_count(), 3);
+        assert_eq!(graph[nodes[2]], 3);
+        assert_eq!(graph.edge_count(), 2);
+        assert_eq!(graph.edges(nodes[0]).count(), 0);
+        let edges: Vec<(NodeIndex, NodeIndex, &str)> = graph
+            .edges(nodes[2])
+            .map(|edge| (edge.source(), edge.target(), *edge.weight()))
+            .collect();
+        assert_eq!(edges, vec![(nodes[2], nodes[1], "32"), (nodes[2], nodes[0], "30")]);
+    }
+}
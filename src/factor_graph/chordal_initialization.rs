@@ -0,0 +1,427 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Initial estimates of 3D pose graphs, whose rotations are calculated first by a chordal relaxation.
+//!
+//! The relaxation drops the constraint that the rotations are orthonormal, so that each relative rotation R_ij of an
+//! odometry factor yields the linear equation R_i * R_ij = R_j between the entries of the rotation matrices. The
+//! least-squares solution is projected onto the closest rotations afterwards. Given these rotations, the relative
+//! translations of the odometry factors are linear in the positions, which are calculated by a second least-squares
+//! problem. Unlike the [spanning tree](../initialization/index.html), the result does not depend on the order in
+//! which the measurements are composed, so that the errors of long loops in sphere- or torus-like datasets are
+//! distributed over all rotations.
+
+#![allow(non_snake_case)]
+
+use crate::factor_graph::adjacency::NodeIndex;
+use crate::factor_graph::factor::FactorType;
+use crate::factor_graph::variable::{FixedType, Variable};
+use crate::factor_graph::FactorGraph;
+use crate::optimizer::block_sparse::BlockSparseMatrix;
+use crate::optimizer::linear_system::iso3d_gradients::get_isometry;
+use crate::optimizer::solver::sparse_cholesky::SparseCholeskySolver;
+use crate::optimizer::solver::LinearSolver;
+use nalgebra::{DVector, Isometry3, Matrix3, Point3, Rotation3, Translation3, UnitQuaternion, Vector3};
+use std::collections::HashSet;
+
+// a linear equation x_j - A * x_i = c between the 3-vectors of two variables
+struct RelativeEquation {
+    i: usize,
+    j: usize,
+    a: Matrix3<f64>,
+    c: Vector3<f64>,
+}
+
+impl FactorGraph {
+    /// Replaces the estimates of the non-fixed Vehicle3D variables by the ones calculated from the Odometry3D factors
+    /// by a chordal relaxation of the rotations, followed by the positions given these rotations, see the
+    /// [module documentation](index.html).
+    ///
+    /// Fixed variables and Position3D factors anchor the rotations and positions. Components of the odometry which
+    /// have neither keep the estimate of their first vehicle variable. Equations are weighted equally, i.e. the
+    /// information matrices are not taken into account. Afterwards, each non-fixed Landmark3D variable is placed at
+    /// the mean of its Observation3D factors. Other variables keep their estimates.
+    ///
+    /// Since the relaxation is not exact for noisy measurements, the result is only meant as the starting point of
+    /// an optimization.
+    pub fn initialize_with_chordal_relaxation(&self) -> Result<(), String> {
+        let var_count = self.node_indices.len();
+        let mut odometry = vec![];
+        let mut priors = vec![];
+        let mut observations = vec![];
+        for edge in self.node_indices.iter().flat_map(|i| self.adjacency.edges(*i)) {
+            let factor = self.materialize_factor(edge.weight());
+            match factor.factor_type {
+                FactorType::Odometry3D => odometry.push((
+                    edge.source().index(),
+                    edge.target().index(),
+                    get_isometry(&factor.constraint),
+                )),
+                FactorType::Position3D => priors.push((edge.source().index(), get_isometry(&factor.constraint))),
+                FactorType::Observation3D => {
+                    let z = &factor.constraint;
+                    observations.push((
+                        edge.source().index(),
+                        edge.target().index(),
+                        Point3::new(z[0], z[1], z[2]),
+                    ));
+                }
+                _ => (),
+            }
+        }
+
+        // the variables which keep their estimates, i.e. all but the non-fixed vehicles of anchored components
+        let is_vehicle = |i: usize| matches!(self.get_var(NodeIndex::new(i)), Variable::Vehicle3D(_));
+        let mut is_known: Vec<bool> = (0..var_count)
+            .map(|i| !is_vehicle(i) || self.get_var(NodeIndex::new(i)).get_fixed_type() == &FixedType::Fixed)
+            .collect();
+        let components = get_components(var_count, &odometry);
+        let mut anchored_components: HashSet<usize> = (0..var_count)
+            .filter(|i| is_vehicle(*i) && is_known[*i])
+            .chain(priors.iter().map(|(i, _)| *i))
+            .map(|i| components[i])
+            .collect();
+        for i in (0..var_count).filter(|i| is_vehicle(*i)) {
+            if anchored_components.insert(components[i]) {
+                is_known[i] = true;
+            }
+        }
+        let estimates: Vec<Isometry3<f64>> = (0..var_count)
+            .map(|i| match is_vehicle(i) {
+                true => get_isometry(&self.get_var(NodeIndex::new(i)).get_content()),
+                false => Isometry3::identity(),
+            })
+            .collect();
+
+        // each row of the rotations is determined independently, since R_i * R_ij = R_j relates the same rows
+        let mut rotations: Vec<Matrix3<f64>> = estimates
+            .iter()
+            .map(|e| *e.rotation.to_rotation_matrix().matrix())
+            .collect();
+        for row in 0..3 {
+            let known: Vec<Vector3<f64>> = rotations.iter().map(|r| r.row(row).transpose()).collect();
+            let relative: Vec<RelativeEquation> = odometry
+                .iter()
+                .map(|(i, j, z)| RelativeEquation {
+                    i: *i,
+                    j: *j,
+                    a: z.rotation.to_rotation_matrix().matrix().transpose(),
+                    c: Vector3::zeros(),
+                })
+                .collect();
+            let absolute: Vec<(usize, Vector3<f64>)> = priors
+                .iter()
+                .map(|(i, z)| (*i, z.rotation.to_rotation_matrix().matrix().row(row).transpose()))
+                .collect();
+            let solution = solve_least_squares(&is_known, &known, &relative, &absolute)?;
+            for (rotation, solved) in rotations.iter_mut().zip(solution) {
+                rotation.set_row(row, &solved.transpose());
+            }
+        }
+        let rotations: Vec<Rotation3<f64>> = rotations
+            .iter()
+            .zip(&is_known)
+            .zip(&estimates)
+            .map(|((rotation, is_known), estimate)| match is_known {
+                true => estimate.rotation.to_rotation_matrix(),
+                false => project_onto_rotations(rotation),
+            })
+            .collect();
+
+        let known: Vec<Vector3<f64>> = estimates.iter().map(|e| e.translation.vector).collect();
+        let relative: Vec<RelativeEquation> = odometry
+            .iter()
+            .map(|(i, j, z)| RelativeEquation {
+                i: *i,
+                j: *j,
+                a: Matrix3::identity(),
+                c: rotations[*i] * z.translation.vector,
+            })
+            .collect();
+        let absolute: Vec<(usize, Vector3<f64>)> = priors.iter().map(|(i, z)| (*i, z.translation.vector)).collect();
+        let translations = solve_least_squares(&is_known, &known, &relative, &absolute)?;
+
+        for i in (0..var_count).filter(|i| !is_known[*i]) {
+            let rotation = UnitQuaternion::from_rotation_matrix(&rotations[i]);
+            let pose = Isometry3::from_parts(Translation3::from(translations[i]), rotation);
+            let mut content = pose.translation.vector.data.as_slice().to_vec();
+            content.extend_from_slice(pose.rotation.coords.data.as_slice());
+            self.get_var(NodeIndex::new(i)).set_content(content);
+        }
+        let mut landmark_sums = vec![(Vector3::zeros(), 0); var_count];
+        for (i, j, z) in &observations {
+            let pose = get_isometry(&self.get_var(NodeIndex::new(*i)).get_content());
+            landmark_sums[*j].0 += (pose * z).coords;
+            landmark_sums[*j].1 += 1;
+        }
+        for (j, (sum, count)) in landmark_sums.into_iter().enumerate() {
+            let var = self.get_var(NodeIndex::new(j));
+            if count > 0 && var.get_fixed_type() != &FixedType::Fixed {
+                var.set_content((sum / count as f64).data.as_slice().to_vec());
+            }
+        }
+        Ok(())
+    }
+}
+
+// returns the index of the component of each variable, in which the odometry factors connect the variables
+fn get_components(var_count: usize, odometry: &[(usize, usize, Isometry3<f64>)]) -> Vec<usize> {
+    let mut parents: Vec<usize> = (0..var_count).collect();
+    fn find(parents: &mut [usize], i: usize) -> usize {
+        let mut root = i;
+        while parents[root] != root {
+            root = parents[root];
+        }
+        parents[i] = root;
+        root
+    }
+    for (i, j, _) in odometry {
+        let (root_i, root_j) = (find(&mut parents, *i), find(&mut parents, *j));
+        parents[root_i] = root_j;
+    }
+    (0..var_count).map(|i| find(&mut parents, i)).collect()
+}
+
+// solves the least-squares problem of the relative equations x_j - A * x_i = c and the absolute equations x_i = c,
+// in which the known variables keep the given vectors
+fn solve_least_squares(
+    is_known: &[bool],
+    known: &[Vector3<f64>],
+    relative: &[RelativeEquation],
+    absolute: &[(usize, Vector3<f64>)],
+) -> Result<Vec<Vector3<f64>>, String> {
+    let mut blocks = vec![None; is_known.len()];
+    let mut dim = 0;
+    for (i, is_known) in is_known.iter().enumerate() {
+        if !is_known {
+            blocks[i] = Some(dim..dim + 3);
+            dim += 3;
+        }
+    }
+    // H and b of the equations, whose residuals are linear in the unknown vectors with the known vectors inserted
+    let mut H = BlockSparseMatrix::new(dim);
+    let mut b = DVector::zeros(dim);
+    for equation in relative {
+        let (i, j) = (equation.i, equation.j);
+        let mut residual = -equation.c;
+        if blocks[i].is_none() {
+            residual -= equation.a * known[i];
+        }
+        if blocks[j].is_none() {
+            residual += known[j];
+        }
+        // the Jacobians are -A for x_i and the identity for x_j
+        if let Some(rows_i) = &blocks[i] {
+            H.add_block(rows_i.clone(), rows_i.clone(), &(equation.a.transpose() * equation.a));
+            let updated = b.rows(rows_i.start, 3) - equation.a.transpose() * residual;
+            b.rows_mut(rows_i.start, 3).copy_from(&updated);
+            if let Some(rows_j) = &blocks[j] {
+                H.add_block(rows_i.clone(), rows_j.clone(), &(-equation.a.transpose()));
+                H.add_block(rows_j.clone(), rows_i.clone(), &(-equation.a));
+            }
+        }
+        if let Some(rows_j) = &blocks[j] {
+            H.add_block(rows_j.clone(), rows_j.clone(), &Matrix3::identity());
+            let updated = b.rows(rows_j.start, 3) + residual;
+            b.rows_mut(rows_j.start, 3).copy_from(&updated);
+        }
+    }
+    for (i, c) in absolute {
+        if let Some(rows) = &blocks[*i] {
+            H.add_block(rows.clone(), rows.clone(), &Matrix3::identity());
+            let updated = b.rows(rows.start, 3) - c;
+            b.rows_mut(rows.start, 3).copy_from(&updated);
+        }
+    }
+    let solution = match dim {
+        0 => vec![],
+        _ => SparseCholeskySolver.solve_block_sparse(&H, &(b * -1.0))?,
+    };
+    Ok(blocks
+        .iter()
+        .zip(known)
+        .map(|(block, known)| match block {
+            Some(rows) => Vector3::from_column_slice(&solution[rows.clone()]),
+            None => *known,
+        })
+        .collect())
+}
+
+// returns the rotation closest to the matrix in the Frobenius norm
+fn project_onto_rotations(matrix: &Matrix3<f64>) -> Rotation3<f64> {
+    let svd = matrix.svd(true, true);
+    let (u, v_t) = (svd.u.unwrap(), svd.v_t.unwrap());
+    let mut correction = Matrix3::identity();
+    if (u * v_t).determinant() < 0.0 {
+        correction[(2, 2)] = -1.0;
+    }
+    Rotation3::from_matrix_unchecked(u * correction * v_t)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::factor_graph::variable::VariableId;
+    use crate::optimizer::{optimize, total_chi2};
+    use crate::parser::g2o::G2oParser;
+    use crate::parser::Parser;
+
+    const INFORMATION_3D: &str = "1 0 0 1 0 1";
+    const INFORMATION_6D: &str = "1 0 0 0 0 0 1 0 0 0 0 1 0 0 0 1 0 0 1 0 1";
+    const IDENTITY: &str = "0 0 0 0 0 0 1";
+
+    fn get_content(factor_graph: &FactorGraph, id: usize) -> Vec<f64> {
+        factor_graph.get_var_by_id(VariableId(id)).unwrap().get_content()
+    }
+
+    fn assert_position_eq(content: &[f64], expected: &Vector3<f64>) {
+        let position = Vector3::from_column_slice(&content[..3]);
+        assert!((position - expected).norm() < 1e-9, "{:?} != {:?}", position, expected);
+    }
+
+    fn format_isometry(iso: &Isometry3<f64>) -> String {
+        let (t, q) = (iso.translation.vector, iso.rotation.coords);
+        format!("{} {} {} {} {} {} {}", t.x, t.y, t.z, q[0], q[1], q[2], q[3])
+    }
+
+    // returns poses along a helix whose orientations turn around all axes, and a landmark in its center
+    fn get_ground_truth() -> (Vec<Isometry3<f64>>, Point3<f64>) {
+        let poses = (0..12)
+            .map(|k| {
+                let angle = 0.5 * k as f64;
+                Isometry3::from_parts(
+                    Translation3::new(5.0 * angle.cos(), 5.0 * angle.sin(), 0.3 * k as f64),
+                    UnitQuaternion::from_scaled_axis(Vector3::new(0.4 * angle.sin(), 0.3 * angle.cos(), angle)),
+                )
+            })
+            .collect();
+        (poses, Point3::new(0.0, 0.0, 1.0))
+    }
+
+    // returns the lines of a loop through the poses, each perturbed by the given function of the pose's index
+    fn get_lines(
+        poses: &[Isometry3<f64>],
+        landmark: &Point3<f64>,
+        noise: impl Fn(usize) -> Isometry3<f64>,
+    ) -> Vec<String> {
+        let mut lines: Vec<String> = (0..poses.len())
+            .map(|k| format!("VERTEX_SE3:QUAT {} {}", k, IDENTITY))
+            .collect();
+        lines.push(format!("VERTEX_TRACKXYZ {} 0 0 0", poses.len()));
+        for k in 0..poses.len() {
+            let next = (k + 1) % poses.len();
+            let measurement = poses[k].inverse() * poses[next] * noise(k);
+            lines.push(format!(
+                "EDGE_SE3:QUAT {} {} {} {}",
+                k,
+                next,
+                format_isometry(&measurement),
+                INFORMATION_6D
+            ));
+        }
+        for k in [2, 7] {
+            let local = poses[k].inverse_transform_point(landmark);
+            lines.push(format!(
+                "EDGE_SE3_TRACKXYZ {} {} 0 {} {} {} {}",
+                k,
+                poses.len(),
+                local.x,
+                local.y,
+                local.z,
+                INFORMATION_3D
+            ));
+        }
+        lines
+    }
+
+    #[test]
+    fn test_consistent_loop_is_recovered() {
+        let (poses, landmark) = get_ground_truth();
+        let mut lines = get_lines(&poses, &landmark, |_| Isometry3::identity());
+        lines[0] = format!("VERTEX_SE3:QUAT 0 {}", format_isometry(&poses[0]));
+        lines.push(String::from("FIX 0"));
+        let factor_graph: FactorGraph = G2oParser::parse_string_to_model(&lines.join("\n")).unwrap().into();
+        factor_graph.initialize_with_chordal_relaxation().unwrap();
+        assert!(total_chi2(&factor_graph) < 1e-12, "{}", total_chi2(&factor_graph));
+        for (k, pose) in poses.iter().enumerate() {
+            let estimate = get_isometry(&get_content(&factor_graph, k));
+            assert_position_eq(estimate.translation.vector.as_slice(), &pose.translation.vector);
+            assert!(estimate.rotation.angle_to(&pose.rotation) < 1e-9);
+        }
+        assert_position_eq(&get_content(&factor_graph, poses.len()), &landmark.coords);
+    }
+
+    #[test]
+    fn test_noisy_loop_converges() {
+        let (poses, landmark) = get_ground_truth();
+        let noise = |k: usize| {
+            let k = k as f64;
+            Isometry3::from_parts(
+                Translation3::new(0.05 * k.sin(), 0.05 * k.cos(), -0.03),
+                UnitQuaternion::from_scaled_axis(Vector3::new(0.02 * k.cos(), -0.03, 0.04 * k.sin())),
+            )
+        };
+        let mut lines = get_lines(&poses, &landmark, noise);
+        lines.push(String::from("FIX 0"));
+        let lines = lines.join("\n");
+
+        // the optimum is close to the ground truth
+        let expected: FactorGraph = G2oParser::parse_string_to_model(&lines).unwrap().into();
+        for (k, pose) in poses.iter().enumerate() {
+            let mut content = pose.translation.vector.data.as_slice().to_vec();
+            content.extend_from_slice(pose.rotation.coords.data.as_slice());
+            expected.get_var_by_id(VariableId(k)).unwrap().set_content(content);
+        }
+        let landmark_var = expected.get_var_by_id(VariableId(poses.len())).unwrap();
+        landmark_var.set_content(landmark.coords.data.as_slice().to_vec());
+        optimize(&expected, 20);
+
+        let factor_graph: FactorGraph = G2oParser::parse_string_to_model(&lines).unwrap().into();
+        factor_graph.initialize_with_chordal_relaxation().unwrap();
+        optimize(&factor_graph, 5);
+        let (chi2, expected_chi2) = (total_chi2(&factor_graph), total_chi2(&expected));
+        assert!(
+            (chi2 - expected_chi2).abs() < 1e-6 * expected_chi2,
+            "{} != {}",
+            chi2,
+            expected_chi2
+        );
+    }
+
+    #[test]
+    fn test_anchors() {
+        let pose = Isometry3::from_parts(
+            Translation3::new(1.0, 2.0, 3.0),
+            UnitQuaternion::from_scaled_axis(Vector3::new(0.0, 0.0, 1.0)),
+        );
+        let odometry = format!("0 0 1 0 0 0 1 {}", INFORMATION_6D);
+        let factor_graph: FactorGraph = G2oParser::parse_string_to_model(
+            &[
+                format!("VERTEX_SE3:QUAT 0 {}", IDENTITY),
+                format!("VERTEX_SE3:QUAT 1 {}", IDENTITY),
+                String::from("VERTEX_SE3:QUAT 2 0 0 5 0 0 0 1"),
+                format!("VERTEX_SE3:QUAT 3 {}", IDENTITY),
+                format!("EDGE_SE3_PRIOR 1 0 {} {}", format_isometry(&pose), INFORMATION_6D),
+                format!("EDGE_SE3:QUAT 0 1 {}", odometry),
+                format!("EDGE_SE3:QUAT 2 3 {}", odometry),
+            ]
+            .join("\n"),
+        )
+        .unwrap()
+        .into();
+        factor_graph.initialize_with_chordal_relaxation().unwrap();
+        assert!(total_chi2(&factor_graph) < 1e-12, "{}", total_chi2(&factor_graph));
+        assert_position_eq(&get_content(&factor_graph, 0), &Vector3::new(1.0, 2.0, 2.0));
+        // the second component keeps the estimate of its first pose
+        assert_position_eq(&get_content(&factor_graph, 3), &Vector3::new(0.0, 0.0, 6.0));
+    }
+}
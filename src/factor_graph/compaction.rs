@@ -0,0 +1,174 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Renumbering of the variables to contiguous IDs, e.g. before exporting to tools which require dense IDs.
+
+use crate::factor_graph::variable::{Variable, VariableId};
+use crate::factor_graph::FactorGraph;
+use std::collections::BTreeMap;
+
+impl FactorGraph {
+    /// Renumbers the variables to the IDs 0..N in the order of their current IDs and returns the map from the old
+    /// to the new IDs.
+    ///
+    /// The renumbering is deterministic and keeps the relative order of the IDs, so compacting a factor graph whose
+    /// IDs are already contiguous from 0 does not change it. All references to the variables are updated, i.e. the
+    /// factors' additional variables, the [equality constraints](#method.add_equality_constraint) and the
+    /// [fixed components](#method.fix_components). Factor IDs and internal indices are not changed.
+    ///
+    /// ```
+    /// use gs_rs::factor_graph::FactorGraph;
+    /// use gs_rs::factor_graph::variable::VariableId;
+    /// use gs_rs::parser::g2o::G2oParser;
+    /// use gs_rs::parser::Parser;
+    ///
+    /// let g2o = "VERTEX_SE2 10 0 0 0\nVERTEX_SE2 42 1 0 0\nEDGE_SE2 10 42 1 0 0 1 0 0 1 0 1";
+    /// let mut factor_graph: FactorGraph = G2oParser::parse_string_to_model(g2o).unwrap().into();
+    /// let id_map = factor_graph.compact_ids();
+    /// assert_eq!(id_map[&VariableId(42)], VariableId(1));
+    /// assert_eq!(factor_graph.factors_between(VariableId(0), VariableId(1)).len(), 1);
+    /// ```
+    pub fn compact_ids(&mut self) -> BTreeMap<VariableId, VariableId> {
+        let id_map: BTreeMap<VariableId, VariableId> = self
+            .custom_to_csr_id_map
+            .keys()
+            .enumerate()
+            .map(|(new_id, old_id)| (*old_id, VariableId(new_id)))
+            .collect();
+
+        for index in self.node_indices.clone() {
+            let var = self.adjacency.node_weight_mut(index).unwrap();
+            let new_id = id_map[&var.get_id()];
+            set_id(var, new_id);
+        }
+        for id in self.factor_id_map.keys().copied().collect::<Vec<_>>() {
+            let factor = self.get_factor_mut(id).unwrap();
+            factor.additional_variables.iter_mut().for_each(|id| *id = id_map[id]);
+        }
+        self.custom_to_csr_id_map = self
+            .custom_to_csr_id_map
+            .iter()
+            .map(|(id, index)| (id_map[id], *index))
+            .collect();
+        self.equalities = self
+            .equalities
+            .iter()
+            .map(|(tied, representative)| (id_map[tied], id_map[representative]))
+            .collect();
+        self.fixed_components = std::mem::take(&mut self.fixed_components)
+            .into_iter()
+            .map(|(id, mask)| (id_map[&id], mask))
+            .collect();
+        self.timestamps = std::mem::take(&mut self.timestamps)
+            .into_iter()
+            .map(|(id, time)| (id_map[&id], time))
+            .collect();
+        id_map
+    }
+}
+
+fn set_id(var: &mut Variable, id: VariableId) {
+    match var {
+        Variable::Vehicle2D(v) => v.id = id,
+        Variable::Landmark2D(v) => v.id = id,
+        Variable::Vehicle3D(v) => v.id = id,
+        Variable::Landmark3D(v) => v.id = id,
+        Variable::Switch(v) => v.id = id,
+        Variable::Velocity3D(v) => v.id = id,
+        Variable::ImuBias(v) => v.id = id,
+        Variable::VehicleSim3(v) => v.id = id,
+        Variable::Plane3D(v) => v.id = id,
+        Variable::LineLandmark2D(v) => v.id = id,
+        Variable::OdometryCalibration2D(v) => v.id = id,
+        Variable::Extrinsic2D(v) => v.id = id,
+        Variable::Extrinsic3D(v) => v.id = id,
+        Variable::Anchor2D(v) => v.id = id,
+        Variable::Anchor3D(v) => v.id = id,
+        Variable::UserDefined(v) => v.id = id,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimizer::{optimize, total_chi2};
+    use crate::parser::g2o::G2oParser;
+    use crate::parser::model::FactorGraphModel;
+    use crate::parser::Parser;
+
+    fn get_factor_graph() -> FactorGraph {
+        let g2o = [
+            "VERTEX_SE2 7 0.0 0.0 0.0",
+            "FIX 7",
+            "VERTEX_SE2 3 1.1 0.1 0.1",
+            "VERTEX_SE2 20 2.0 0.2 0.0",
+            "VERTEX_SWITCH 100 1.0",
+            "VERTEX_SE2 15 2.1 0.0 0.0",
+            "EDGE_SE2 7 3 1.0 0.0 0.0 1.0 0.0 0.0 1.0 0.0 1.0",
+            "EDGE_SE2 3 20 1.0 0.0 0.0 1.0 0.0 0.0 1.0 0.0 1.0",
+            "EDGE_SE2_SWITCHABLE 7 20 100 2.0 0.0 0.0 1.0 0.0 0.0 1.0 0.0 1.0",
+            "EDGE_SWITCH_PRIOR 100 1.0 1.0",
+            "EDGE_PRIOR_SE2 15 2.1 0.0 0.0 1.0 0.0 0.0 1.0 0.0 1.0",
+        ]
+        .join("\n");
+        G2oParser::parse_string_to_model(&g2o).unwrap().into()
+    }
+
+    #[test]
+    fn test_compact_ids() {
+        let mut factor_graph = get_factor_graph();
+        factor_graph
+            .add_equality_constraint(VariableId(20), VariableId(15))
+            .unwrap();
+        factor_graph
+            .fix_components(VariableId(3), vec![false, false, true])
+            .unwrap();
+        let id_map = factor_graph.compact_ids();
+        let expected: BTreeMap<_, _> = [(3, 0), (7, 1), (15, 2), (20, 3), (100, 4)]
+            .iter()
+            .map(|(old, new)| (VariableId(*old), VariableId(*new)))
+            .collect();
+        assert_eq!(id_map, expected);
+
+        let model = FactorGraphModel::from(&factor_graph);
+        let mut ids: Vec<usize> = model.vertices.iter().map(|v| v.id.0).collect();
+        ids.sort_unstable();
+        assert_eq!(ids, vec![0, 1, 2, 3, 4]);
+        assert!(model.fixed_vertices.contains(&VariableId(1)));
+        let switchable = model
+            .edges
+            .iter()
+            .find(|e| e.edge_type == "SwitchableOdometry2D")
+            .unwrap();
+        assert_eq!(switchable.vertices, vec![VariableId(1), VariableId(3), VariableId(4)]);
+        assert_eq!(factor_graph.factors_between(VariableId(0), VariableId(3)).len(), 1);
+        assert_eq!(
+            factor_graph.fixed_components(VariableId(0)),
+            Some(&[false, false, true][..])
+        );
+        assert!(factor_graph.is_tied(factor_graph.custom_to_csr_id_map[&VariableId(3)]));
+    }
+
+    #[test]
+    fn test_compaction_keeps_optimization_result() {
+        let expected = get_factor_graph();
+        optimize(&expected, 10);
+        let mut factor_graph = get_factor_graph();
+        factor_graph.compact_ids();
+        optimize(&factor_graph, 10);
+        assert_eq!(total_chi2(&factor_graph), total_chi2(&expected));
+        assert_eq!(
+            factor_graph.compact_ids(),
+            (0..5).map(|i| (VariableId(i), VariableId(i))).collect()
+        );
+    }
+}
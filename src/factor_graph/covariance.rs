@@ -0,0 +1,94 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Recovery of the marginal covariances of the estimates, e.g. to pass their uncertainty on to downstream consumers.
+
+#![allow(non_snake_case)]
+
+use crate::factor_graph::variable::{FixedType, VariableId};
+use crate::factor_graph::FactorGraph;
+use crate::optimizer::linear_system::calculate_H_b;
+use crate::optimizer::solver::sparse_cholesky::solve_with_cholesky;
+use nalgebra::{CsCholesky, DMatrix, DVector};
+use std::collections::BTreeMap;
+
+impl FactorGraph {
+    /// Returns the marginal covariance of each non-fixed variable at the current estimates, i.e. its diagonal block
+    /// of H^-1.
+    ///
+    /// The covariances refer to the corrections of the variables' local parameterizations, see
+    /// [parameterization](variable/parameterization/index.html), e.g. (x, y, rotation) for 2D vehicle poses. Fixed
+    /// variables have no covariance, and the rows and columns of fixed components are zero, see
+    /// [fix_components](struct.FactorGraph.html#method.fix_components).
+    ///
+    /// Returns an error if H is not positive-definite, i.e. if some variables are not determined by the factors.
+    pub fn marginal_covariances(&self) -> Result<BTreeMap<VariableId, DMatrix<f64>>, String> {
+        let (H, _) = calculate_H_b(self);
+        let cholesky = CsCholesky::new(&H.to_cs_matrix());
+        let mut covariances = BTreeMap::new();
+        for var in self.node_indices.iter().map(|i| self.get_var(*i)) {
+            let range = match var.get_fixed_type() {
+                FixedType::NonFixed(range) => range.clone(),
+                FixedType::Fixed => continue,
+            };
+            let mut covariance = DMatrix::zeros(range.len(), range.len());
+            for (column, i) in range.clone().enumerate() {
+                let mut unit = DVector::zeros(self.matrix_dim);
+                unit[i] = 1.0;
+                let solution = solve_with_cholesky(&cholesky, &unit)?;
+                covariance.column_mut(column).copy_from_slice(&solution[range.clone()]);
+            }
+            let mask = self.fixed_components(var.get_id()).unwrap_or(&[]);
+            for (k, _) in mask.iter().enumerate().filter(|(_, is_fixed)| **is_fixed) {
+                covariance.row_mut(k).fill(0.0);
+                covariance.column_mut(k).fill(0.0);
+            }
+            covariances.insert(var.get_id(), covariance);
+        }
+        Ok(covariances)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::g2o::G2oParser;
+    use crate::parser::Parser;
+
+    #[test]
+    fn test_chain_covariances() {
+        // the covariances of a chain with unit information matrices grow linearly with the distance to the fixed pose
+        let g2o_string = [
+            "VERTEX_SE2 0 0 0 0",
+            "FIX 0",
+            "VERTEX_SE2 1 1 0 0",
+            "VERTEX_XY 2 1 1",
+            "EDGE_SE2 0 1 1 0 0 1 0 0 1 0 1",
+            "EDGE_SE2_XY 1 2 0 1 1 0 1",
+        ]
+        .join("\n");
+        let factor_graph: FactorGraph = G2oParser::parse_string_to_model(&g2o_string).unwrap().into();
+        let covariances = factor_graph.marginal_covariances().unwrap();
+        assert_eq!(covariances.len(), 2);
+        assert!((&covariances[&VariableId(1)] - DMatrix::identity(3, 3)).norm() < 1e-9);
+
+        // the landmark is observed at (0, 1) from pose 1, so the uncertainty of the rotation moves it along x
+        let expected = DMatrix::from_row_slice(2, 2, &[3.0, 0.0, 0.0, 2.0]);
+        assert!((&covariances[&VariableId(2)] - expected).norm() < 1e-9);
+    }
+
+    #[test]
+    fn test_undetermined_variables() {
+        let factor_graph: FactorGraph = G2oParser::parse_string_to_model("VERTEX_SE2 0 0 0 0").unwrap().into();
+        assert!(factor_graph.marginal_covariances().is_err());
+    }
+}
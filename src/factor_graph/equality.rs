@@ -0,0 +1,196 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Hard equality constraints between variables, e.g. for calibration rigs or gauge ties, which are enforced exactly
+//! instead of by stiff factors which deteriorate the conditioning of H.
+
+use crate::factor_graph::adjacency::NodeIndex;
+use crate::factor_graph::variable::VariableId;
+use crate::factor_graph::FactorGraph;
+use core::mem::discriminant;
+use std::collections::BTreeMap;
+
+impl FactorGraph {
+    /// Constrains the variable with the ID `tied` to be exactly equal to the variable with the ID `representative`.
+    ///
+    /// The constraint is enforced by aliasing: the tied variable takes the representative's estimate and shares its
+    /// range in H, so that the factors of both variables act on a single block and both estimates stay equal during
+    /// optimizations. The tied variable is fixed if and only if the representative is, which also holds after
+    /// [freeze](#method.freeze) and [unfreeze](#method.unfreeze). If the representative is tied itself, the variable
+    /// is tied to the representative's representative instead. Equality constraints are not part of
+    /// [models](../parser/model/struct.FactorGraphModel.html).
+    ///
+    /// Returns an error without changing the factor graph if one of the IDs is unknown, if both IDs are equal or
+    /// belong to variables of different types, or if the tied variable is already part of an equality constraint.
+    ///
+    /// ```
+    /// use gs_rs::examples_gen::triangle_with_loop_closure;
+    /// use gs_rs::factor_graph::FactorGraph;
+    /// use gs_rs::factor_graph::variable::VariableId;
+    ///
+    /// let mut factor_graph: FactorGraph = triangle_with_loop_closure().into();
+    /// factor_graph.add_equality_constraint(VariableId(1), VariableId(2)).unwrap();
+    /// assert_eq!(factor_graph.matrix_dim, 3);
+    /// assert!(factor_graph.add_equality_constraint(VariableId(0), VariableId(2)).is_err());
+    /// ```
+    pub fn add_equality_constraint(&mut self, representative: VariableId, tied: VariableId) -> Result<(), String> {
+        let representative = self.equalities.get(&representative).copied().unwrap_or(representative);
+        let (representative_index, tied_index) = (self.get_csr_index(representative)?, self.get_csr_index(tied)?);
+        if representative == tied {
+            return Err(format!("The variable {} cannot be tied to itself", tied));
+        }
+        if discriminant(self.get_var(representative_index)) != discriminant(self.get_var(tied_index)) {
+            return Err(format!(
+                "The variables {} and {} have different types and cannot be tied",
+                representative, tied
+            ));
+        }
+        if self.is_tied(tied_index) {
+            return Err(format!(
+                "The variable {} is already part of an equality constraint",
+                tied
+            ));
+        }
+        self.get_var(tied_index)
+            .set_content(self.get_var(representative_index).get_content());
+        self.equalities.insert(tied, representative);
+        self.set_fixed(&[], false)
+    }
+
+    /// Returns the equality constraints as map from each tied variable's ID to its representative's ID, see
+    /// [add_equality_constraint](#method.add_equality_constraint).
+    pub fn equality_constraints(&self) -> &BTreeMap<VariableId, VariableId> {
+        &self.equalities
+    }
+
+    // returns whether the variable at the given index is tied to another variable or another variable is tied to it
+    pub(crate) fn is_tied(&self, csr_index: NodeIndex<usize>) -> bool {
+        let id = self.get_var(csr_index).get_id();
+        self.equalities.contains_key(&id) || self.equalities.values().any(|representative| *representative == id)
+    }
+
+    // returns the internal index of the variable whose range in H the variable at the given index uses
+    pub(crate) fn get_representative(&self, csr_index: NodeIndex<usize>) -> NodeIndex<usize> {
+        match self.equalities.get(&self.get_var(csr_index).get_id()) {
+            Some(representative) => self.custom_to_csr_id_map[representative],
+            None => csr_index,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::factor_graph::variable::{FixedType, VariableId};
+    use crate::factor_graph::FactorGraph;
+    use crate::optimizer::ordering::fill_reducing_permutation;
+    use crate::optimizer::{optimize, total_chi2};
+    use crate::parser::g2o::G2oParser;
+    use crate::parser::model::{Edge, FactorGraphModel, Vertex};
+    use crate::parser::Parser;
+    use approx::assert_relative_eq;
+    use std::collections::BTreeSet;
+
+    // returns a fixed pose and two poses which are measured at (1, 0, 0) and (1.2, 0, 0) from it
+    fn get_model() -> FactorGraphModel {
+        let get_vertex = |id: usize| Vertex {
+            id: VariableId(id),
+            vertex_type: String::from("Vehicle2D"),
+            content: vec![0.1 * id as f64, 0.0, 0.0],
+        };
+        let get_edge = |target: usize, x: f64| Edge {
+            edge_type: String::from("Odometry2D"),
+            vertices: vec![VariableId(0), VariableId(target)],
+            restriction: vec![x, 0.0, 0.0],
+            information_matrix: vec![1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0],
+        };
+        let mut fixed_vertices = BTreeSet::new();
+        fixed_vertices.insert(VariableId(0));
+        FactorGraphModel {
+            vertices: vec![get_vertex(0), get_vertex(1), get_vertex(2)],
+            edges: vec![get_edge(1, 1.0), get_edge(2, 1.2)],
+            fixed_vertices,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_tied_variables_are_equal() {
+        let mut factor_graph: FactorGraph = get_model().into();
+        factor_graph
+            .add_equality_constraint(VariableId(1), VariableId(2))
+            .unwrap();
+        assert_eq!(factor_graph.matrix_dim, 3);
+        assert_eq!(
+            factor_graph.get_var_by_id(VariableId(2)).unwrap().get_fixed_type(),
+            &FixedType::NonFixed(0..3)
+        );
+        optimize(&factor_graph, 5);
+        let (pose_1, pose_2) = (
+            factor_graph.get_var_by_id(VariableId(1)).unwrap().get_content(),
+            factor_graph.get_var_by_id(VariableId(2)).unwrap().get_content(),
+        );
+        assert_eq!(pose_1, pose_2);
+        assert_relative_eq!(pose_1[0], 1.1, epsilon = 1e-9);
+
+        factor_graph.freeze(&[VariableId(1)]).unwrap();
+        assert_eq!(factor_graph.matrix_dim, 0);
+        assert_eq!(
+            factor_graph.get_var_by_id(VariableId(2)).unwrap().get_fixed_type(),
+            &FixedType::Fixed
+        );
+    }
+
+    #[test]
+    fn test_invalid_equality_constraints() {
+        let mut factor_graph: FactorGraph = get_model().into();
+        assert!(factor_graph
+            .add_equality_constraint(VariableId(1), VariableId(1))
+            .is_err());
+        assert!(factor_graph
+            .add_equality_constraint(VariableId(1), VariableId(5))
+            .is_err());
+        factor_graph
+            .add_equality_constraint(VariableId(1), VariableId(2))
+            .unwrap();
+        assert!(factor_graph
+            .add_equality_constraint(VariableId(0), VariableId(2))
+            .is_err());
+        assert!(factor_graph
+            .add_equality_constraint(VariableId(0), VariableId(1))
+            .is_err());
+        assert_eq!(factor_graph.equality_constraints().len(), 1);
+
+        let mut factor_graph = G2oParser::parse_file("data_files/full_demos/all_2d_types.g2o").unwrap();
+        assert!(factor_graph
+            .add_equality_constraint(VariableId(1), VariableId(2))
+            .is_err());
+    }
+
+    #[test]
+    fn test_tied_poses_keep_a_valid_ordering() {
+        let mut factor_graph = G2oParser::parse_file("data_files/optimizer_tests/full2d_0.g2o").unwrap();
+        factor_graph
+            .add_equality_constraint(VariableId(10), VariableId(40))
+            .unwrap();
+        let mut permutation = fill_reducing_permutation(&factor_graph).unwrap_or_default();
+        permutation.sort_unstable();
+        assert!(permutation.is_empty() || permutation == (0..factor_graph.matrix_dim).collect::<Vec<_>>());
+
+        let initial_chi2 = total_chi2(&factor_graph);
+        optimize(&factor_graph, 5);
+        assert!(total_chi2(&factor_graph) < initial_chi2);
+        assert_eq!(
+            factor_graph.get_var_by_id(VariableId(10)).unwrap().get_content(),
+            factor_graph.get_var_by_id(VariableId(40)).unwrap().get_content()
+        );
+    }
+}
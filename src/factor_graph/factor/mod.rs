@@ -12,7 +12,35 @@
 
 //! The internal representation of a factor graph's measurement.
 
+#[cfg(feature = "std")]
+use crate::factor_graph::variable::Variable;
+use crate::factor_graph::variable::VariableId;
+#[cfg(feature = "std")]
+use crate::factor_graph::FactorGraph;
+#[cfg(feature = "std")]
+use crate::optimizer::autodiff::Dual;
+#[cfg(feature = "std")]
+use crate::optimizer::linear_system::iso3d_gradients::get_isometry;
+use alloc::boxed::Box;
+use alloc::vec::Vec;
+use core::fmt;
 use nalgebra::DMatrix;
+#[cfg(feature = "std")]
+use nalgebra::{Point3, Rotation2, Vector2};
+#[cfg(feature = "std")]
+use std::f64::consts::PI;
+#[cfg(feature = "std")]
+use std::rc::Rc;
+
+/// Type-safe ID of a factor, unique within its factor graph.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct FactorId(pub usize);
+
+impl fmt::Display for FactorId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
 
 /// Enum representing a supported factor type.
 #[derive(Debug, Clone, PartialEq)]
@@ -23,44 +51,681 @@ pub enum FactorType {
     Odometry2D,
     /// Relative measurement to an observed stationary variable in 2D.
     Observation2D,
+    /// Bearing and range from a vehicle pose to an observed stationary variable in 2D.
+    BearingRange2D,
+    /// Bearing from a vehicle pose to an observed stationary variable in 2D, e.g. of a monocular camera.
+    Bearing2D,
     /// Vehicle pose measurement in 3D.
     Position3D,
+    /// Vehicle position measurement in 3D without an orientation, e.g. from GPS.
+    PositionOnly3D,
+    /// Vehicle heading measurement in 2D without a position, e.g. from a compass.
+    Heading2D,
+    /// Vehicle yaw measurement in 3D without a position, roll and pitch, e.g. from a magnetometer.
+    Heading3D,
+    /// Vehicle altitude measurement in 3D, i.e. of the z coordinate of its position, e.g. from a barometer.
+    Altitude3D,
     /// Relative measurement between two poses in 3D.
     Odometry3D,
     /// Relative measurement to an observed stationary variable in 3D.
     Observation3D,
+    /// Pixel observation of a stationary variable in 3D by a pinhole camera with fixed intrinsics at the vehicle
+    /// pose, e.g. for bundle adjustment. The camera looks along the z axis of the vehicle frame.
+    Projection3D,
+    /// Pixel observation of a stationary variable in 3D by a rectified stereo camera with fixed intrinsics, whose left
+    /// camera is at the vehicle pose and whose right camera is offset by the baseline along the x axis.
+    StereoProjection3D,
+    /// Distance between a vehicle pose and an observed stationary variable in 2D, e.g. a UWB beacon.
+    Range2D,
+    /// Distance between a vehicle pose and an observed stationary variable in 3D, e.g. a UWB beacon.
+    Range3D,
+    /// Relative measurement between two poses in 2D whose error is scaled by a switch variable.
+    SwitchableOdometry2D,
+    /// Relative measurement between two poses in 3D whose error is scaled by a switch variable.
+    SwitchableOdometry3D,
+    /// Prior on the value of a switch variable.
+    SwitchPrior,
+    /// Preintegrated IMU measurements between two poses in 3D, which also depends on the velocities and IMU biases
+    /// at both poses, see [imu](../imu/index.html). Its additional variables are the velocity at the source, the
+    /// velocity at the target, the bias at the source and the bias at the target.
+    ImuPreintegration3D,
+    /// Smoothness prior on three consecutive poses in 2D, which penalizes the change of their relative motion, e.g.
+    /// when odometry is missing or very noisy. Its source is the previous pose, its target the next pose and its
+    /// additional variable the middle pose.
+    ConstantVelocity2D,
+    /// Smoothness prior on three consecutive poses in 3D, see ConstantVelocity2D.
+    ConstantVelocity3D,
+    /// Relative measurement between two poses in 3D with a scale, e.g. odometry or a loop closure of a monocular
+    /// camera, so that the optimization corrects scale drift.
+    OdometrySim3,
+    /// Points measured in the frame of a pose in 3D, e.g. by a LiDAR, which lie on a plane landmark. Its source is
+    /// the pose and its target the plane, its information matrix has one row per point.
+    PointToPlane3D,
+    /// Line measured in the frame of a pose in 2D in Hessian normal form, e.g. a wall extracted from a laser scan.
+    /// Its source is the pose and its target the line landmark.
+    LineObservation2D,
+    /// Relative measurement between two poses in 2D by an odometry with systematic errors, which are estimated by
+    /// its additional variable, a calibration shared by all factors of the same odometry.
+    CalibratedOdometry2D,
+    /// Relative measurement to an observed stationary variable in 2D by a sensor whose pose in the vehicle's frame is
+    /// estimated by its additional variable, an extrinsic calibration shared by all factors of the same sensor.
+    ExtrinsicObservation2D,
+    /// Relative measurement to an observed stationary variable in 3D by a sensor with an extrinsic calibration, see
+    /// ExtrinsicObservation2D.
+    ExtrinsicObservation3D,
+    /// Relative measurement between two poses in 2D, which are estimated in the frames of their mapping sessions.
+    /// Its additional variables are the anchors of the source's and the target's session, so that the factor aligns
+    /// the sessions if they differ, e.g. for a loop closure between two sessions.
+    AnchoredOdometry2D,
+    /// Relative measurement between two poses in 3D in the frames of their sessions, see AnchoredOdometry2D.
+    AnchoredOdometry3D,
+    /// Relative measurement to an observed stationary variable in 2D, which are estimated in the frames of their
+    /// sessions. Its additional variables are the anchors of the vehicle's and the observed variable's session.
+    AnchoredObservation2D,
+    /// Relative measurement to an observed stationary variable in 3D in the frames of their sessions, see
+    /// AnchoredObservation2D.
+    AnchoredObservation3D,
+    /// Displacement between two landmarks in 2D in the world frame, e.g. from a map prior or a structure prior.
+    LandmarkOffset2D,
+    /// Displacement between two landmarks in 3D in the world frame, see LandmarkOffset2D.
+    LandmarkOffset3D,
+    /// Measurement of a pose in 2D like Position2D, which is taken between the times of two poses, e.g. by a GPS
+    /// which is not synchronized with the odometry. It constrains the pose linearly interpolated between its source,
+    /// the pose before the measurement, and its additional variable, the pose after it.
+    InterpolatedPose2D,
+    /// Measurement of a pose in 3D like Position3D between the times of two poses, see InterpolatedPose2D. The
+    /// rotation is interpolated by SLERP.
+    InterpolatedPose3D,
+    /// Vehicle position measurement in 2D by an antenna at a fixed lever arm in the vehicle's frame, e.g. a GPS
+    /// receiver which is not mounted at the vehicle's origin. The lever arm is rotated by the vehicle's orientation.
+    LeverArmPosition2D,
+    /// Vehicle position measurement in 3D by an antenna at a fixed lever arm in the vehicle's frame, see
+    /// LeverArmPosition2D.
+    LeverArmPosition3D,
+    /// Measurement with multiple hypotheses of the wrapped type, of which the dominant one is used at each
+    /// linearization.
+    MaxMixture(MaxMixture),
+    /// Gaussian prior on the poses or positions of several variables, e.g. as left by marginalizing out their
+    /// neighbours. Its variables are the source, the target unless it is the source, and the additional variables.
+    DensePrior,
+    /// User-defined measurement whose Jacobians are calculated with automatic differentiation.
+    #[cfg(feature = "std")]
+    Custom(CustomResidual),
+    /// User-defined measurement with its own error and Jacobians, see [CustomFactor](trait.CustomFactor.html).
+    #[cfg(feature = "std")]
+    UserDefined(Rc<dyn CustomFactor>),
+}
+
+#[cfg(feature = "std")]
+impl FactorType {
+    // returns whether a factor of this type can connect the given variables, in the order of
+    // FactorGraph::get_factor_var_indices
+    pub(crate) fn accepts_variables(&self, vars: &[&Variable]) -> bool {
+        use crate::factor_graph::variable::Variable::*;
+        match self {
+            FactorType::Position2D => matches!(vars, [Vehicle2D(_)]),
+            FactorType::Odometry2D => matches!(vars, [Vehicle2D(_), Vehicle2D(_)]),
+            FactorType::Observation2D => matches!(vars, [Vehicle2D(_), Landmark2D(_)]),
+            FactorType::Position3D => matches!(vars, [Vehicle3D(_)]),
+            FactorType::Odometry3D => matches!(vars, [Vehicle3D(_), Vehicle3D(_)]),
+            FactorType::Observation3D => matches!(vars, [Vehicle3D(_), Landmark3D(_)]),
+            FactorType::SwitchableOdometry2D => matches!(vars, [Vehicle2D(_), Vehicle2D(_), Switch(_)]),
+            FactorType::SwitchableOdometry3D => matches!(vars, [Vehicle3D(_), Vehicle3D(_), Switch(_)]),
+            FactorType::SwitchPrior => matches!(vars, [Switch(_)]),
+            FactorType::BearingRange2D => matches!(vars, [Vehicle2D(_), Landmark2D(_)]),
+            FactorType::Range2D => matches!(vars, [Vehicle2D(_), Landmark2D(_)]),
+            FactorType::Range3D => matches!(vars, [Vehicle3D(_), Landmark3D(_)]),
+            FactorType::Bearing2D => matches!(vars, [Vehicle2D(_), Landmark2D(_)]),
+            FactorType::PositionOnly3D => matches!(vars, [Vehicle3D(_)]),
+            FactorType::ImuPreintegration3D => matches!(
+                vars,
+                [
+                    Vehicle3D(_),
+                    Vehicle3D(_),
+                    Velocity3D(_),
+                    Velocity3D(_),
+                    ImuBias(_),
+                    ImuBias(_)
+                ]
+            ),
+            FactorType::ConstantVelocity2D => matches!(vars, [Vehicle2D(_), Vehicle2D(_), Vehicle2D(_)]),
+            FactorType::ConstantVelocity3D => matches!(vars, [Vehicle3D(_), Vehicle3D(_), Vehicle3D(_)]),
+            FactorType::Projection3D => matches!(vars, [Vehicle3D(_), Landmark3D(_)]),
+            FactorType::StereoProjection3D => matches!(vars, [Vehicle3D(_), Landmark3D(_)]),
+            FactorType::OdometrySim3 => matches!(vars, [VehicleSim3(_), VehicleSim3(_)]),
+            FactorType::PointToPlane3D => matches!(vars, [Vehicle3D(_), Plane3D(_)]),
+            FactorType::LineObservation2D => matches!(vars, [Vehicle2D(_), LineLandmark2D(_)]),
+            FactorType::CalibratedOdometry2D => matches!(vars, [Vehicle2D(_), Vehicle2D(_), OdometryCalibration2D(_)]),
+            FactorType::ExtrinsicObservation2D => matches!(vars, [Vehicle2D(_), Landmark2D(_), Extrinsic2D(_)]),
+            FactorType::ExtrinsicObservation3D => matches!(vars, [Vehicle3D(_), Landmark3D(_), Extrinsic3D(_)]),
+            FactorType::Heading2D => matches!(vars, [Vehicle2D(_)]),
+            FactorType::Heading3D => matches!(vars, [Vehicle3D(_)]),
+            FactorType::Altitude3D => matches!(vars, [Vehicle3D(_)]),
+            FactorType::AnchoredOdometry2D => matches!(vars, [Vehicle2D(_), Vehicle2D(_), Anchor2D(_), Anchor2D(_)]),
+            FactorType::AnchoredOdometry3D => matches!(vars, [Vehicle3D(_), Vehicle3D(_), Anchor3D(_), Anchor3D(_)]),
+            FactorType::AnchoredObservation2D => {
+                matches!(vars, [Vehicle2D(_), Landmark2D(_), Anchor2D(_), Anchor2D(_)])
+            }
+            FactorType::AnchoredObservation3D => {
+                matches!(vars, [Vehicle3D(_), Landmark3D(_), Anchor3D(_), Anchor3D(_)])
+            }
+            FactorType::LandmarkOffset2D => matches!(vars, [Landmark2D(_), Landmark2D(_)]),
+            FactorType::LandmarkOffset3D => matches!(vars, [Landmark3D(_), Landmark3D(_)]),
+            FactorType::InterpolatedPose2D => matches!(vars, [Vehicle2D(_), Vehicle2D(_)]),
+            FactorType::InterpolatedPose3D => matches!(vars, [Vehicle3D(_), Vehicle3D(_)]),
+            FactorType::LeverArmPosition2D => matches!(vars, [Vehicle2D(_)]),
+            FactorType::LeverArmPosition3D => matches!(vars, [Vehicle3D(_)]),
+            FactorType::MaxMixture(mixture) => mixture.factor_type.accepts_variables(vars),
+            FactorType::DensePrior | FactorType::Custom(_) | FactorType::UserDefined(_) => true,
+        }
+    }
+}
+
+/// Hypotheses of a max-mixture factor as described by Olson and Agarwal, "Inference on networks of mixtures for
+/// robust robot mapping".
+///
+/// The factor's own constraint and information matrix form the first component, the alternatives the remaining
+/// ones. Each time the factor is linearized, the component with the highest weighted Gaussian likelihood, i.e. the
+/// lowest 0.5 * χ² - ln(weight) - 0.5 * ln(det(information matrix)), is used as a factor of the wrapped type.
+/// All information matrices are expected to have the same shape.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MaxMixture {
+    /// The type of all components.
+    pub factor_type: Box<FactorType>,
+    /// The weight of the first component.
+    pub weight: f64,
+    /// The remaining components.
+    pub alternatives: Vec<MixtureComponent>,
+}
+
+/// Single hypothesis of a max-mixture factor.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MixtureComponent {
+    /// The component's weight, e.g. its prior probability.
+    pub weight: f64,
+    /// The component's constraint, in the format of the wrapped factor type.
+    pub constraint: Vec<f64>,
+    /// The component's information matrix.
+    pub information_matrix: InformationMatrix,
+}
+
+/// Signature of a custom factor's residual function.
+///
+/// The function receives the contents of the factor's variables (only the source's for unary factors, source and
+/// target otherwise) and the factor's constraint. It has to return the error vector, which is expected to have as
+/// many entries as the information matrix has rows and to be zero if the measurement is matched perfectly.
+#[cfg(feature = "std")]
+pub type ResidualFn = dyn Fn(&[Vec<Dual>], &[f64]) -> Vec<Dual>;
+
+/// Named residual function of a custom factor. Two custom factor types are equal if their names are equal.
+#[cfg(feature = "std")]
+#[derive(Clone)]
+pub struct CustomResidual {
+    name: String,
+    residual: Rc<ResidualFn>,
+}
+
+#[cfg(feature = "std")]
+impl CustomResidual {
+    /// Returns a custom residual with the given name and residual function, see [ResidualFn](type.ResidualFn.html).
+    ///
+    /// The function only has to be written once in terms of dual numbers, its Jacobians are derived automatically.
+    pub fn new<F>(name: &str, residual: F) -> Self
+    where
+        F: Fn(&[Vec<Dual>], &[f64]) -> Vec<Dual> + 'static,
+    {
+        CustomResidual {
+            name: String::from(name),
+            residual: Rc::new(residual),
+        }
+    }
+
+    /// Returns the name of the custom residual.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Evaluates the residual function.
+    pub fn evaluate(&self, contents: &[Vec<Dual>], constraint: &[f64]) -> Vec<Dual> {
+        (self.residual)(contents, constraint)
+    }
+}
+
+#[cfg(feature = "std")]
+impl fmt::Debug for CustomResidual {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CustomResidual({:?})", self.name)
+    }
+}
+
+#[cfg(feature = "std")]
+impl PartialEq for CustomResidual {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+/// Measurement model of a user-defined factor, which is added with
+/// [add_custom_factor](../struct.FactorGraph.html#method.add_custom_factor).
+///
+/// Unlike a [CustomResidual](struct.CustomResidual.html), the factor calculates its Jacobians itself, e.g.
+/// analytically, and holds its measurement, so that its constraint is empty. Two user-defined factor types are equal
+/// if their names are equal.
+#[cfg(feature = "std")]
+pub trait CustomFactor {
+    /// Returns the name of the factor's type, e.g. the edge type of the factor in a composed model.
+    fn name(&self) -> &str;
+
+    /// Returns the number of entries of the factor's error, i.e. the dimension of its information matrix.
+    fn residual_dim(&self) -> usize;
+
+    /// Returns the IDs of the variables the factor depends on, starting with its source. A single ID makes the factor
+    /// unary.
+    fn variable_ids(&self) -> Vec<VariableId>;
+
+    /// Returns the factor's error, which is expected to be zero if the measurement is matched perfectly, and its
+    /// Jacobian with respect to each of the given variables, which are in the order of their IDs.
+    ///
+    /// Each Jacobian has a row per error entry and a column per tangent direction of the variable's
+    /// [parameterization](../variable/parameterization/trait.LocalParameterization.html), e.g. the translation in the
+    /// local frame followed by the rotation for 3D poses.
+    fn error_and_jacobians(&self, vars: &[&Variable]) -> (Vec<f64>, Vec<DMatrix<f64>>);
+}
+
+#[cfg(feature = "std")]
+impl fmt::Debug for dyn CustomFactor {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "CustomFactor({:?})", self.name())
+    }
+}
+
+#[cfg(feature = "std")]
+impl PartialEq for dyn CustomFactor {
+    fn eq(&self, other: &Self) -> bool {
+        self.name() == other.name()
+    }
 }
 
 /// Structure representing a measurement.
 #[derive(Debug, Clone)]
 pub struct Factor {
+    /// The factor's ID.
+    pub id: FactorId,
     /// The factor's type.
     pub factor_type: FactorType,
     /// The factor's constraint.
     ///
-    /// Content for Position2D and Odometry2D: vec![position_x, position_y, rotation]
+    /// Content for Position2D, Odometry2D, SwitchableOdometry2D, CalibratedOdometry2D and AnchoredOdometry2D: vec![position_x, position_y, rotation]
+    ///
+    /// Content for Observation2D, ExtrinsicObservation2D and AnchoredObservation2D: vec![position_x, position_y]
     ///
-    /// Content for Observation2D: vec![position_x, position_y]
+    /// Content for BearingRange2D: vec![bearing, range]
     ///
-    /// Content for Position3D and Odometry3D: vec![position_x, position_y, position_z, rotation_quaternion_x, rotation_quaternion_y, rotation_quaternion_z, rotation_quaternion_w]
+    /// Content for Bearing2D: vec![bearing]
     ///
-    /// Content for Observation3D: vec![position_x, position_y, position_z]
+    /// Content for Heading2D and Heading3D: vec![heading]
+    ///
+    /// Content for Altitude3D: vec![position_z]
+    ///
+    /// Content for Position3D, Odometry3D, SwitchableOdometry3D and AnchoredOdometry3D: vec![position_x, position_y, position_z, rotation_quaternion_x, rotation_quaternion_y, rotation_quaternion_z, rotation_quaternion_w]
+    ///
+    /// Content for PositionOnly3D, Observation3D, ExtrinsicObservation3D and AnchoredObservation3D: vec![position_x, position_y, position_z]
+    ///
+    /// Content for Projection3D: vec![pixel_x, pixel_y, focal_length_x, focal_length_y, principal_point_x, principal_point_y]
+    ///
+    /// Content for StereoProjection3D: vec![left_pixel_x, right_pixel_x, pixel_y, focal_length_x, focal_length_y, principal_point_x, principal_point_y, baseline]
+    ///
+    /// Content for Range2D and Range3D: vec![range]
+    ///
+    /// Content for SwitchPrior: vec![prior_value]
+    ///
+    /// Content for ImuPreintegration3D: vec![delta_position_x, delta_position_y, delta_position_z, delta_velocity_x, delta_velocity_y, delta_velocity_z, delta_quaternion_x, delta_quaternion_y, delta_quaternion_z, delta_quaternion_w, delta_time, acc_bias_x, acc_bias_y, acc_bias_z, gyro_bias_x, gyro_bias_y, gyro_bias_z, gravity_x, gravity_y, gravity_z]
+    /// followed by the column-major 3x3 Jacobians of the delta position with respect to the accelerometer and the
+    /// gyroscope bias, of the delta velocity with respect to both biases and of the delta rotation with respect to the
+    /// gyroscope bias, see [ImuPreintegrator](../imu/struct.ImuPreintegrator.html)
+    ///
+    /// Content for ConstantVelocity2D and ConstantVelocity3D: vec![]
+    ///
+    /// Content for OdometrySim3: vec![position_x, position_y, position_z, rotation_quaternion_x, rotation_quaternion_y, rotation_quaternion_z, rotation_quaternion_w, scale]
+    ///
+    /// Content for PointToPlane3D: vec![point_x, point_y, point_z] of each point
+    ///
+    /// Content for LineObservation2D: vec![angle, distance]
+    ///
+    /// Content for LandmarkOffset2D: vec![delta_position_x, delta_position_y]
+    ///
+    /// Content for LandmarkOffset3D: vec![delta_position_x, delta_position_y, delta_position_z]
+    ///
+    /// Content for InterpolatedPose2D: vec![position_x, position_y, rotation, fraction] with the fraction of the time
+    /// between both poses which passed at the measurement
+    ///
+    /// Content for InterpolatedPose3D: vec![position_x, position_y, position_z, rotation_quaternion_x, rotation_quaternion_y, rotation_quaternion_z, rotation_quaternion_w, fraction]
+    ///
+    /// Content for LeverArmPosition2D: vec![position_x, position_y, lever_arm_x, lever_arm_y] with the antenna's
+    /// position in the world frame and the lever arm in the vehicle's frame
+    ///
+    /// Content for LeverArmPosition3D: vec![position_x, position_y, position_z, lever_arm_x, lever_arm_y, lever_arm_z]
+    ///
+    /// Content for MaxMixture: the first component's constraint, in the format of the wrapped factor type
+    ///
+    /// Content for DensePrior: the concatenated contents of all variables, in the format of their types
+    ///
+    /// Content for Custom: arbitrary, as expected by the residual function
+    ///
+    /// Content for UserDefined: vec![]
     pub constraint: Vec<f64>,
     /// The factor's wrapped information matrix, equalling the inverse of the factor's mean matrix.
     pub information_matrix: InformationMatrix,
+    /// The IDs of the factor's variables besides its source and target, e.g. the switch variable of switchable
+    /// factors.
+    pub additional_variables: Vec<VariableId>,
+}
+
+#[cfg(feature = "std")]
+impl Factor {
+    /// Returns the measurement predicted by the current estimates of the factor's variables.
+    ///
+    /// The prediction has the same format as the constraint, so that the factor's residual is the difference
+    /// between both. Rotations in 2D are normalized to [-PI, PI), quaternions to a non-negative w component.
+    /// Projection factors predict the pixel or, for stereo cameras, the pixels followed by their intrinsics.
+    /// Switchable factors predict the measurement of their poses, regardless of the switch, max-mixture factors the
+    /// one of their dominant component. Calibrated odometry factors predict the uncalibrated measurement. Dense
+    /// priors predict the contents of their variables.
+    /// Since the measurement model of custom and user-defined factors is unknown, the constraint of IMU factors also
+    /// contains the bias correction of their measurement, constant-velocity factors have no measurement and the
+    /// measured points of point-to-plane factors are not determined by the plane, their residual is returned instead.
+    ///
+    /// Panics if the factor is not part of the given factor graph.
+    pub fn predict(&self, factor_graph: &FactorGraph) -> Vec<f64> {
+        let (source, target) = factor_graph
+            .factor_id_map
+            .get(&self.id)
+            .expect("The factor is not part of the factor graph.");
+        let content_i = factor_graph.get_var(*source).get_content();
+        let content_j = factor_graph.get_var(*target).get_content();
+        match &self.factor_type {
+            FactorType::Position2D | FactorType::Position3D | FactorType::SwitchPrior => content_i,
+            FactorType::PositionOnly3D => content_i[..3].to_vec(),
+            FactorType::Altitude3D => vec![content_i[2]],
+            FactorType::Heading2D | FactorType::Heading3D => {
+                use crate::optimizer::linear_system::heading_handler;
+                vec![normalize_rotation(heading_handler::get_heading(factor_graph.get_var(*source)))]
+            }
+            FactorType::Odometry2D | FactorType::SwitchableOdometry2D => {
+                let mut prediction = predict_local_position_2d(&content_i, &content_j);
+                prediction.push(normalize_rotation(content_j[2] - content_i[2]));
+                prediction
+            }
+            FactorType::Observation2D => predict_local_position_2d(&content_i, &content_j),
+            FactorType::CalibratedOdometry2D => {
+                // the odometry scales the relative motion and biases its rotation per travelled distance
+                let calibration = factor_graph.get_var(factor_graph.get_factor_var_indices(self.id).unwrap()[2]);
+                let calibration = calibration.get_content();
+                let position = predict_local_position_2d(&content_i, &content_j);
+                let rotation = normalize_rotation(content_j[2] - content_i[2]);
+                let distance = position[0].hypot(position[1]);
+                vec![
+                    calibration[0] * position[0],
+                    calibration[0] * position[1],
+                    normalize_rotation(calibration[1] * rotation + calibration[2] * distance),
+                ]
+            }
+            FactorType::LineObservation2D => {
+                let (sin, cos) = content_j[0].sin_cos();
+                vec![
+                    normalize_rotation(content_j[0] - content_i[2]),
+                    content_j[1] - cos * content_i[0] - sin * content_i[1],
+                ]
+            }
+            FactorType::BearingRange2D | FactorType::Bearing2D => {
+                let local_position = predict_local_position_2d(&content_i, &content_j);
+                let mut prediction = vec![normalize_rotation(local_position[1].atan2(local_position[0]))];
+                if self.factor_type == FactorType::BearingRange2D {
+                    prediction.push(local_position[0].hypot(local_position[1]));
+                }
+                prediction
+            }
+            FactorType::Odometry3D | FactorType::SwitchableOdometry3D | FactorType::OdometrySim3 => {
+                let local_iso = get_isometry(&content_i).inverse() * get_isometry(&content_j);
+                let mut rotation = local_iso.rotation.quaternion().coords;
+                if rotation[3] < 0.0 {
+                    rotation = -rotation;
+                }
+                let mut prediction = local_iso.translation.vector.data.as_slice().to_vec();
+                prediction.extend_from_slice(rotation.data.as_slice());
+                if self.factor_type == FactorType::OdometrySim3 {
+                    // the relative translation is measured in units of the source's scale
+                    prediction[..3].iter_mut().for_each(|t| *t /= content_i[7]);
+                    prediction.push(content_j[7] / content_i[7]);
+                }
+                prediction
+            }
+            FactorType::ExtrinsicObservation2D | FactorType::ExtrinsicObservation3D => {
+                // the observed variable is measured in the frame of the sensor at the vehicle's pose
+                let extrinsic = factor_graph.get_var(factor_graph.get_factor_var_indices(self.id).unwrap()[2]);
+                let extrinsic = extrinsic.get_content();
+                if self.factor_type == FactorType::ExtrinsicObservation2D {
+                    let (sin, cos) = content_i[2].sin_cos();
+                    let sensor_pose = [
+                        content_i[0] + cos * extrinsic[0] - sin * extrinsic[1],
+                        content_i[1] + sin * extrinsic[0] + cos * extrinsic[1],
+                        content_i[2] + extrinsic[2],
+                    ];
+                    predict_local_position_2d(&sensor_pose, &content_j)
+                } else {
+                    let sensor_pose = get_isometry(&content_i) * get_isometry(&extrinsic);
+                    let position = Point3::new(content_j[0], content_j[1], content_j[2]);
+                    sensor_pose.inverse_transform_point(&position).coords.data.as_slice().to_vec()
+                }
+            }
+            FactorType::AnchoredOdometry2D | FactorType::AnchoredObservation2D => {
+                // both variables are transformed from the frames of their sessions into the world frame
+                let indices = factor_graph.get_factor_var_indices(self.id).unwrap();
+                let anchor_i = factor_graph.get_var(indices[2]).get_content();
+                let anchor_j = factor_graph.get_var(indices[3]).get_content();
+                let (sin, cos) = anchor_i[2].sin_cos();
+                let pose_i = [
+                    anchor_i[0] + cos * content_i[0] - sin * content_i[1],
+                    anchor_i[1] + sin * content_i[0] + cos * content_i[1],
+                    anchor_i[2] + content_i[2],
+                ];
+                let (sin, cos) = anchor_j[2].sin_cos();
+                let position_j = [
+                    anchor_j[0] + cos * content_j[0] - sin * content_j[1],
+                    anchor_j[1] + sin * content_j[0] + cos * content_j[1],
+                ];
+                let mut prediction = predict_local_position_2d(&pose_i, &position_j);
+                if self.factor_type == FactorType::AnchoredOdometry2D {
+                    prediction.push(normalize_rotation(anchor_j[2] + content_j[2] - pose_i[2]));
+                }
+                prediction
+            }
+            FactorType::AnchoredOdometry3D | FactorType::AnchoredObservation3D => {
+                let indices = factor_graph.get_factor_var_indices(self.id).unwrap();
+                let anchor_i = get_isometry(&factor_graph.get_var(indices[2]).get_content());
+                let anchor_j = get_isometry(&factor_graph.get_var(indices[3]).get_content());
+                let pose_i = anchor_i * get_isometry(&content_i);
+                if self.factor_type == FactorType::AnchoredObservation3D {
+                    let position = anchor_j * Point3::new(content_j[0], content_j[1], content_j[2]);
+                    pose_i.inverse_transform_point(&position).coords.data.as_slice().to_vec()
+                } else {
+                    let local_iso = pose_i.inverse() * anchor_j * get_isometry(&content_j);
+                    let mut rotation = local_iso.rotation.quaternion().coords;
+                    if rotation[3] < 0.0 {
+                        rotation = -rotation;
+                    }
+                    let mut prediction = local_iso.translation.vector.data.as_slice().to_vec();
+                    prediction.extend_from_slice(rotation.data.as_slice());
+                    prediction
+                }
+            }
+            FactorType::Observation3D => {
+                let local_position = get_isometry(&content_i).inverse_transform_point(&Point3::new(
+                    content_j[0],
+                    content_j[1],
+                    content_j[2],
+                ));
+                local_position.coords.data.as_slice().to_vec()
+            }
+            FactorType::Projection3D | FactorType::StereoProjection3D => {
+                use crate::optimizer::linear_system::{projection_handler, stereo_projection_handler};
+                let vars = [factor_graph.get_var(*source), factor_graph.get_var(*target)];
+                let local = projection_handler::get_local_position(&vars);
+                let (mut prediction, pixel_len) = match self.factor_type {
+                    FactorType::Projection3D => (projection_handler::project(&self.constraint, &local).to_vec(), 2),
+                    _ => (stereo_projection_handler::project(&self.constraint, &local).to_vec(), 3),
+                };
+                prediction.extend_from_slice(&self.constraint[pixel_len..]);
+                prediction
+            }
+            FactorType::Range2D | FactorType::Range3D => {
+                let dim = if self.factor_type == FactorType::Range2D { 2 } else { 3 };
+                let squared_range: f64 = (0..dim).map(|k| (content_j[k] - content_i[k]).powi(2)).sum();
+                vec![squared_range.sqrt()]
+            }
+            FactorType::InterpolatedPose2D | FactorType::InterpolatedPose3D => {
+                use crate::optimizer::linear_system::interpolation_handler;
+                let after = factor_graph.get_var(factor_graph.get_factor_var_indices(self.id).unwrap()[1]);
+                let fraction = self.constraint[self.constraint.len() - 1];
+                let mut prediction = interpolation_handler::interpolate(&content_i, &after.get_content(), fraction);
+                prediction.push(fraction);
+                prediction
+            }
+            FactorType::LeverArmPosition2D | FactorType::LeverArmPosition3D => {
+                use crate::optimizer::linear_system::lever_arm_handler;
+                let lever_arm = &self.constraint[self.constraint.len() / 2..];
+                let mut prediction = lever_arm_handler::get_antenna_position(&content_i, lever_arm);
+                prediction.extend_from_slice(lever_arm);
+                prediction
+            }
+            FactorType::LandmarkOffset2D | FactorType::LandmarkOffset3D => {
+                content_j.iter().zip(&content_i).map(|(j, i)| j - i).collect()
+            }
+            FactorType::MaxMixture(_) => {
+                crate::optimizer::linear_system::get_dominant_component(factor_graph, self).predict(factor_graph)
+            }
+            FactorType::DensePrior => factor_graph
+                .get_factor_var_indices(self.id)
+                .unwrap()
+                .iter()
+                .flat_map(|i| factor_graph.get_var(*i).get_content())
+                .collect(),
+            FactorType::Custom(_)
+            | FactorType::UserDefined(_)
+            | FactorType::ImuPreintegration3D
+            | FactorType::ConstantVelocity2D
+            | FactorType::ConstantVelocity3D
+            | FactorType::PointToPlane3D => {
+                crate::optimizer::linear_system::calculate_error(factor_graph, self.id)
+                    .unwrap()
+                    .data
+                    .into()
+            }
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+fn predict_local_position_2d(pose_i: &[f64], position_j: &[f64]) -> Vec<f64> {
+    let delta = Vector2::new(position_j[0] - pose_i[0], position_j[1] - pose_i[1]);
+    (Rotation2::new(-pose_i[2]) * delta).data.as_slice().to_vec()
+}
+
+#[cfg(feature = "std")]
+fn normalize_rotation(rotation: f64) -> f64 {
+    let rotation = rotation % (2.0 * PI);
+    if rotation >= PI {
+        rotation - 2.0 * PI
+    } else if rotation < -PI {
+        rotation + 2.0 * PI
+    } else {
+        rotation
+    }
 }
 
 /// Structure wrapping the information matrix of a factor.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct InformationMatrix {
     pub content: DMatrix<f64>,
 }
 
 impl From<Vec<f64>> for InformationMatrix {
     fn from(content: Vec<f64>) -> Self {
-        let dim = (content.len() as f64).sqrt() as usize;
+        let mut dim = 0;
+        while (dim + 1) * (dim + 1) <= content.len() {
+            dim += 1;
+        }
         InformationMatrix {
             content: DMatrix::from_vec(dim, dim, content),
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::factor_graph::factor::FactorType::*;
+    use crate::parser::g2o::G2oParser;
+    use crate::parser::Parser;
+    use approx::assert_relative_eq;
+
+    fn get_prediction(factor_graph: &FactorGraph, factor_type: FactorType) -> Vec<f64> {
+        factor_graph
+            .factor_id_map
+            .keys()
+            .map(|id| factor_graph.get_factor(*id).unwrap())
+            .find(|factor| factor.factor_type == factor_type)
+            .unwrap()
+            .predict(factor_graph)
+    }
+
+    #[test]
+    fn test_predict_2d() {
+        let information = "1 0 0 1 0 1";
+        let g2o_string = [
+            "VERTEX_SE2 0 1 1 1.5",
+            "VERTEX_SE2 1 1 3 -3",
+            "VERTEX_XY 2 0 1",
+            &format!("EDGE_PRIOR_SE2 0 0 0 0 {}", information),
+            &format!("EDGE_SE2 0 1 0 0 0 {}", information),
+            "EDGE_SE2_XY 0 2 0 0 1 0 1",
+        ]
+        .join("\n");
+        let factor_graph: FactorGraph = G2oParser::parse_string_to_model(&g2o_string).unwrap().into();
+
+        let position = get_prediction(&factor_graph, Position2D);
+        assert_eq!(position, vec![1.0, 1.0, 1.5]);
+        let odometry = get_prediction(&factor_graph, Odometry2D);
+        assert_relative_eq!(odometry[0], 2.0 * 1.5f64.sin(), epsilon = 1e-12);
+        assert_relative_eq!(odometry[1], 2.0 * 1.5f64.cos(), epsilon = 1e-12);
+        assert_relative_eq!(odometry[2], 2.0 * PI - 4.5, epsilon = 1e-12);
+        let observation = get_prediction(&factor_graph, Observation2D);
+        assert_relative_eq!(observation[0], -1.5f64.cos(), epsilon = 1e-12);
+        assert_relative_eq!(observation[1], 1.5f64.sin(), epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_predict_3d() {
+        let information = "1 0 0 0 0 0 1 0 0 0 0 1 0 0 0 1 0 0 1 0 1";
+        let g2o_string = [
+            "VERTEX_SE3:QUAT 0 1 2 3 0 0 0.7071067811865476 0.7071067811865476",
+            "VERTEX_SE3:QUAT 1 1 3 3 0 0 -0.7071067811865476 -0.7071067811865476",
+            "VERTEX_TRACKXYZ 2 0 2 3",
+            &format!("EDGE_SE3:QUAT 0 1 0 0 0 0 0 0 1 {}", information),
+            "EDGE_SE3_TRACKXYZ 0 2 0 0 0 0 1 0 0 1 0 1",
+        ]
+        .join("\n");
+        let factor_graph: FactorGraph = G2oParser::parse_string_to_model(&g2o_string).unwrap().into();
+
+        let odometry = get_prediction(&factor_graph, Odometry3D);
+        let expected = [1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0];
+        odometry
+            .iter()
+            .zip(expected.iter())
+            .for_each(|(predicted, expected)| assert_relative_eq!(predicted, expected, epsilon = 1e-12));
+        let observation = get_prediction(&factor_graph, Observation3D);
+        assert_relative_eq!(observation[0], 0.0, epsilon = 1e-12);
+        assert_relative_eq!(observation[1], 1.0, epsilon = 1e-12);
+        assert_relative_eq!(observation[2], 0.0, epsilon = 1e-12);
+    }
+}
@@ -0,0 +1,177 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Innovation gating for factors which are added to an existing factor graph.
+//!
+//! The innovation of a factor is the squared Mahalanobis distance of its error under the innovation covariance,
+//! i.e. the covariance of the current estimates projected into the measurement space plus the measurement's own
+//! covariance. A factor whose innovation is far above the chi-squared quantile of its dimension is most likely
+//! an outlier.
+
+#![allow(non_snake_case)]
+
+use crate::factor_graph::factor::{FactorId, FactorType, InformationMatrix};
+use crate::factor_graph::variable::VariableId;
+use crate::factor_graph::FactorGraph;
+use crate::optimizer::linear_system::{calculate_H_b, calculate_chi2, calculate_factor_H_b};
+use crate::optimizer::solver::sparse_cholesky::SparseCholeskySolver;
+use crate::optimizer::solver::LinearSolver;
+
+/// What happens to a factor whose innovation exceeds the gate's threshold.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GatingAction {
+    /// The factor is not added to the factor graph.
+    Reject,
+    /// The factor is added to the factor graph, but reported as an outlier.
+    Flag,
+}
+
+/// Configuration of the innovation gate.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct InnovationGate {
+    /// The innovation above which a factor is considered an outlier, e.g. 7.81 (95% quantile of the chi-squared
+    /// distribution with 3 degrees of freedom) for Position2D and Odometry2D factors.
+    pub threshold: f64,
+    /// What happens to factors above the threshold.
+    pub action: GatingAction,
+}
+
+/// Outcome of a gated insertion.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct GatingResult {
+    /// The ID of the added factor, or None if the factor was rejected.
+    pub factor_id: Option<FactorId>,
+    /// The factor's innovation at the time of insertion.
+    pub innovation: f64,
+    /// Whether the innovation exceeded the threshold.
+    pub is_outlier: bool,
+}
+
+impl FactorGraph {
+    /// Adds a factor like [add_factor](struct.FactorGraph.html#method.add_factor), but first checks its innovation
+    /// against the current estimates and their covariance and handles it according to the given gate.
+    ///
+    /// The factor graph is left unchanged if an error is returned.
+    pub fn add_factor_gated(
+        &mut self,
+        source: VariableId,
+        target: VariableId,
+        factor_type: FactorType,
+        constraint: Vec<f64>,
+        information_matrix: InformationMatrix,
+        gate: &InnovationGate,
+    ) -> Result<GatingResult, String> {
+        let id = self.add_factor(source, target, factor_type, constraint, information_matrix)?;
+        let innovation = match self.calculate_innovation(id) {
+            Ok(innovation) => innovation,
+            Err(e) => {
+                self.remove_factor(id)?;
+                return Err(e);
+            }
+        };
+        let is_outlier = innovation > gate.threshold;
+        let factor_id = if is_outlier && gate.action == GatingAction::Reject {
+            self.remove_factor(id)?;
+            None
+        } else {
+            Some(id)
+        };
+        Ok(GatingResult {
+            factor_id,
+            innovation,
+            is_outlier,
+        })
+    }
+
+    /// Returns the innovation of the factor with the given ID with respect to all other factors.
+    ///
+    /// For the factor's error e, information matrix W, Jacobian J, and the remaining factors' part H0 of H, the
+    /// innovation e^T * (J * H0^-1 * J^T + W^-1)^-1 * e is calculated as e^T * W * e - b^T * (H0 + J^T*W*J)^-1 * b
+    /// with b = J^T*W*e. This only requires the factor's own contributions to H and b and does not fail for
+    /// variables which are only determined by the factor.
+    ///
+    /// Returns an error if the factor is not part of the factor graph or if H is not positive-definite.
+    pub fn calculate_innovation(&self, id: FactorId) -> Result<f64, String> {
+        let chi2 = calculate_chi2(self, id).ok_or_else(|| format!("Factor {} is not part of the factor graph", id))?;
+        let (_, factor_b) = calculate_factor_H_b(self, id).unwrap();
+        let (H, _) = calculate_H_b(self);
+        let x = SparseCholeskySolver.solve_block_sparse(&H, &factor_b)?;
+        Ok(chi2 - factor_b.iter().zip(x.iter()).map(|(b, x)| b * x).sum::<f64>())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::g2o::G2oParser;
+    use crate::parser::Parser;
+    use approx::assert_relative_eq;
+
+    fn get_chain_graph() -> FactorGraph {
+        let information = "1 0 0 1 0 1";
+        let g2o_string = [
+            "VERTEX_SE2 0 0 0 0",
+            "FIX 0",
+            "VERTEX_SE2 1 1 0 0",
+            "VERTEX_SE2 2 2 0 0",
+            &format!("EDGE_SE2 0 1 1 0 0 {}", information),
+            &format!("EDGE_SE2 1 2 1 0 0 {}", information),
+        ]
+        .join("\n");
+        G2oParser::parse_string_to_model(&g2o_string).unwrap().into()
+    }
+
+    fn add_prior(graph: &mut FactorGraph, x: f64, action: GatingAction) -> GatingResult {
+        let gate = InnovationGate {
+            threshold: 7.81,
+            action,
+        };
+        let information = vec![1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0];
+        graph
+            .add_factor_gated(
+                VariableId(1),
+                VariableId(1),
+                FactorType::Position2D,
+                vec![x, 0.0, 0.0],
+                information.into(),
+                &gate,
+            )
+            .unwrap()
+    }
+
+    #[test]
+    fn test_innovation_includes_estimate_covariance() {
+        // the estimate of vehicle 1 has unit covariance, so the innovation covariance is twice the identity
+        let mut graph = get_chain_graph();
+        let result = add_prior(&mut graph, 2.0, GatingAction::Reject);
+        assert_relative_eq!(result.innovation, 0.5, epsilon = 1e-10);
+        assert!(!result.is_outlier);
+        assert!(graph.get_factor(result.factor_id.unwrap()).is_some());
+    }
+
+    #[test]
+    fn test_outliers_are_rejected_or_flagged() {
+        let mut graph = get_chain_graph();
+        let rejected = add_prior(&mut graph, 6.0, GatingAction::Reject);
+        assert_relative_eq!(rejected.innovation, 12.5, epsilon = 1e-10);
+        assert!(rejected.is_outlier);
+        assert_eq!(rejected.factor_id, None);
+        assert!(graph.factors_between(VariableId(1), VariableId(1)).is_empty());
+
+        let flagged = add_prior(&mut graph, 6.0, GatingAction::Flag);
+        assert!(flagged.is_outlier);
+        assert_eq!(
+            graph.factors_between(VariableId(1), VariableId(1)),
+            vec![flagged.factor_id.unwrap()]
+        );
+    }
+}
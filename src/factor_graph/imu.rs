@@ -0,0 +1,495 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Preintegrated IMU measurements between consecutive vehicle poses in 3D as described by Forster et al.,
+//! "On-Manifold Preintegration for Real-Time Visual-Inertial Odometry", e.g. for visual-inertial odometry.
+//!
+//! The accelerometer and gyroscope measurements between two poses are integrated once into a motion relative to the
+//! first pose, which does not depend on the estimates. When the estimate of the biases changes during the
+//! optimization, the integrated motion is corrected to first order instead of being integrated again. The resulting
+//! factor connects the poses, velocities and IMU biases at both ends of the integration, see
+//! [add_imu_factor](../struct.FactorGraph.html#method.add_imu_factor).
+
+use crate::factor_graph::factor::{FactorId, FactorType, InformationMatrix};
+use crate::factor_graph::variable::{Variable, VariableId};
+use crate::factor_graph::FactorGraph;
+use nalgebra::{DMatrix, Matrix3, SMatrix, UnitQuaternion, Vector3};
+
+// offsets of the parts of the constraint of an ImuPreintegration3D factor, see Factor::constraint
+pub(crate) const DELTA_POSITION: usize = 0;
+pub(crate) const DELTA_VELOCITY: usize = 3;
+pub(crate) const DELTA_ROTATION: usize = 6;
+pub(crate) const DELTA_TIME: usize = 10;
+pub(crate) const BIAS: usize = 11;
+pub(crate) const GRAVITY: usize = 17;
+pub(crate) const BIAS_JACOBIANS: usize = 20;
+pub(crate) const CONSTRAINT_LEN: usize = 65;
+
+/// Noise characteristics of an IMU and the gravity in the world frame.
+///
+/// The defaults are the values of the ADIS16448 IMU of the EuRoC MAV dataset.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImuParameters {
+    /// The white noise density of the accelerometer in m/(s²·√Hz).
+    pub accelerometer_noise_density: f64,
+    /// The white noise density of the gyroscope in rad/(s·√Hz).
+    pub gyroscope_noise_density: f64,
+    /// The random walk of the accelerometer bias in m/(s³·√Hz).
+    pub accelerometer_random_walk: f64,
+    /// The random walk of the gyroscope bias in rad/(s²·√Hz).
+    pub gyroscope_random_walk: f64,
+    /// The gravity vector in the world frame.
+    pub gravity: [f64; 3],
+}
+
+impl Default for ImuParameters {
+    fn default() -> Self {
+        ImuParameters {
+            accelerometer_noise_density: 2.0e-3,
+            gyroscope_noise_density: 1.6968e-4,
+            accelerometer_random_walk: 3.0e-3,
+            gyroscope_random_walk: 1.9393e-5,
+            gravity: [0.0, 0.0, -9.81],
+        }
+    }
+}
+
+/// IDs of the pose, velocity and IMU bias variables of a vehicle at one point in time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ImuStateIds {
+    /// The ID of the Vehicle3D variable.
+    pub pose: VariableId,
+    /// The ID of the Velocity3D variable.
+    pub velocity: VariableId,
+    /// The ID of the ImuBias variable.
+    pub bias: VariableId,
+}
+
+/// Accumulator of the IMU measurements between two vehicle poses.
+#[derive(Debug, Clone)]
+pub struct ImuPreintegrator {
+    parameters: ImuParameters,
+    bias: [f64; 6],
+    delta_position: Vector3<f64>,
+    delta_velocity: Vector3<f64>,
+    delta_rotation: UnitQuaternion<f64>,
+    delta_time: f64,
+    // the Jacobians of the deltas with respect to the accelerometer and gyroscope biases
+    d_position_d_acc: Matrix3<f64>,
+    d_position_d_gyro: Matrix3<f64>,
+    d_velocity_d_acc: Matrix3<f64>,
+    d_velocity_d_gyro: Matrix3<f64>,
+    d_rotation_d_gyro: Matrix3<f64>,
+    // the covariance of the rotation, velocity and position deltas, in this order
+    covariance: SMatrix<f64, 9, 9>,
+}
+
+impl ImuPreintegrator {
+    /// Returns a preintegrator without measurements, which removes the given biases
+    /// [acc_x, acc_y, acc_z, gyro_x, gyro_y, gyro_z] from the measurements, e.g. the current estimate of the bias
+    /// variable at the first pose.
+    pub fn new(bias: [f64; 6], parameters: ImuParameters) -> Self {
+        ImuPreintegrator {
+            parameters,
+            bias,
+            delta_position: Vector3::zeros(),
+            delta_velocity: Vector3::zeros(),
+            delta_rotation: UnitQuaternion::identity(),
+            delta_time: 0.0,
+            d_position_d_acc: Matrix3::zeros(),
+            d_position_d_gyro: Matrix3::zeros(),
+            d_velocity_d_acc: Matrix3::zeros(),
+            d_velocity_d_gyro: Matrix3::zeros(),
+            d_rotation_d_gyro: Matrix3::zeros(),
+            covariance: SMatrix::zeros(),
+        }
+    }
+
+    /// Integrates a measurement of the accelerometer, i.e. the specific force in the vehicle frame, and of the
+    /// gyroscope, which are assumed to be constant for the given time step.
+    pub fn integrate(&mut self, acceleration: [f64; 3], angular_velocity: [f64; 3], dt: f64) {
+        let acceleration = Vector3::from(acceleration) - Vector3::new(self.bias[0], self.bias[1], self.bias[2]);
+        let rotation_vector =
+            (Vector3::from(angular_velocity) - Vector3::new(self.bias[3], self.bias[4], self.bias[5])) * dt;
+        let rotation = self.delta_rotation.to_rotation_matrix().into_inner();
+        let step_rotation = UnitQuaternion::from_scaled_axis(rotation_vector);
+        let right_jacobian = get_right_jacobian(&rotation_vector);
+        let rotated_skew = rotation * acceleration.cross_matrix();
+
+        let mut a = SMatrix::<f64, 9, 9>::identity();
+        a.fixed_slice_mut::<3, 3>(0, 0)
+            .copy_from(&step_rotation.to_rotation_matrix().into_inner().transpose());
+        a.fixed_slice_mut::<3, 3>(3, 0).copy_from(&(-rotated_skew * dt));
+        a.fixed_slice_mut::<3, 3>(6, 0)
+            .copy_from(&(-rotated_skew * 0.5 * dt * dt));
+        a.fixed_slice_mut::<3, 3>(6, 3).copy_from(&(Matrix3::identity() * dt));
+        let mut b_gyro = SMatrix::<f64, 9, 3>::zeros();
+        b_gyro.fixed_slice_mut::<3, 3>(0, 0).copy_from(&(right_jacobian * dt));
+        let mut b_acc = SMatrix::<f64, 9, 3>::zeros();
+        b_acc.fixed_slice_mut::<3, 3>(3, 0).copy_from(&(rotation * dt));
+        b_acc
+            .fixed_slice_mut::<3, 3>(6, 0)
+            .copy_from(&(rotation * 0.5 * dt * dt));
+        // the continuous noise densities are converted to the covariances of the discrete measurements
+        let gyro_variance = self.parameters.gyroscope_noise_density.powi(2) / dt;
+        let acc_variance = self.parameters.accelerometer_noise_density.powi(2) / dt;
+        self.covariance = a * self.covariance * a.transpose()
+            + b_gyro * b_gyro.transpose() * gyro_variance
+            + b_acc * b_acc.transpose() * acc_variance;
+
+        self.d_position_d_acc += self.d_velocity_d_acc * dt - rotation * 0.5 * dt * dt;
+        self.d_position_d_gyro += self.d_velocity_d_gyro * dt - rotated_skew * self.d_rotation_d_gyro * 0.5 * dt * dt;
+        self.d_velocity_d_acc -= rotation * dt;
+        self.d_velocity_d_gyro -= rotated_skew * self.d_rotation_d_gyro * dt;
+        self.d_rotation_d_gyro =
+            step_rotation.to_rotation_matrix().into_inner().transpose() * self.d_rotation_d_gyro - right_jacobian * dt;
+
+        self.delta_position += self.delta_velocity * dt + rotation * acceleration * 0.5 * dt * dt;
+        self.delta_velocity += rotation * acceleration * dt;
+        self.delta_rotation *= step_rotation;
+        self.delta_time += dt;
+    }
+
+    /// Returns the total duration of the integrated measurements.
+    pub fn delta_time(&self) -> f64 {
+        self.delta_time
+    }
+
+    /// Returns the constraint of an ImuPreintegration3D factor for the integrated measurements, see
+    /// [Factor::constraint](../factor/struct.Factor.html#structfield.constraint).
+    pub fn constraint(&self) -> Vec<f64> {
+        let mut constraint = Vec::with_capacity(CONSTRAINT_LEN);
+        constraint.extend_from_slice(self.delta_position.as_slice());
+        constraint.extend_from_slice(self.delta_velocity.as_slice());
+        constraint.extend_from_slice(self.delta_rotation.coords.as_slice());
+        constraint.push(self.delta_time);
+        constraint.extend_from_slice(&self.bias);
+        constraint.extend_from_slice(&self.parameters.gravity);
+        for jacobian in &[
+            self.d_position_d_acc,
+            self.d_position_d_gyro,
+            self.d_velocity_d_acc,
+            self.d_velocity_d_gyro,
+            self.d_rotation_d_gyro,
+        ] {
+            constraint.extend_from_slice(jacobian.as_slice());
+        }
+        constraint
+    }
+
+    /// Returns the information matrix of an ImuPreintegration3D factor for the integrated measurements, i.e. the
+    /// inverse of the covariance of its error [position, velocity, rotation, acc_bias, gyro_bias].
+    ///
+    /// Returns an error if the covariance is singular, e.g. since no measurements were integrated or the noise
+    /// densities are zero.
+    pub fn information_matrix(&self) -> Result<InformationMatrix, String> {
+        // the error of the rotation is the vector part of a quaternion, i.e. half of the rotation vector
+        let mut reordering = SMatrix::<f64, 9, 9>::zeros();
+        reordering.fixed_slice_mut::<3, 3>(0, 6).fill_with_identity();
+        reordering.fixed_slice_mut::<3, 3>(3, 3).fill_with_identity();
+        reordering
+            .fixed_slice_mut::<3, 3>(6, 0)
+            .copy_from(&(Matrix3::identity() * 0.5));
+        let mut covariance = DMatrix::zeros(15, 15);
+        covariance
+            .slice_mut((0, 0), (9, 9))
+            .copy_from(&(reordering * self.covariance * reordering.transpose()));
+        for k in 9..12 {
+            covariance[(k, k)] = self.parameters.accelerometer_random_walk.powi(2) * self.delta_time;
+            covariance[(k + 3, k + 3)] = self.parameters.gyroscope_random_walk.powi(2) * self.delta_time;
+        }
+        let information = covariance
+            .cholesky()
+            .ok_or_else(|| String::from("The covariance of the preintegrated IMU measurements is singular"))?
+            .inverse();
+        Ok(InformationMatrix {
+            content: (&information + information.transpose()) * 0.5,
+        })
+    }
+}
+
+// returns the right Jacobian of SO(3) at the given rotation vector
+fn get_right_jacobian(rotation_vector: &Vector3<f64>) -> Matrix3<f64> {
+    let angle = rotation_vector.norm();
+    let skew = rotation_vector.cross_matrix();
+    if angle < 1e-6 {
+        return Matrix3::identity() - skew * 0.5;
+    }
+    Matrix3::identity() - skew * ((1.0 - angle.cos()) / angle.powi(2))
+        + skew * skew * ((angle - angle.sin()) / angle.powi(3))
+}
+
+impl FactorGraph {
+    /// Adds an ImuPreintegration3D factor for the measurements integrated between the states `from` and `to` and
+    /// returns its ID.
+    ///
+    /// The factor's source and target are the poses, its additional variables the velocities and biases at both
+    /// states. Returns an error if one of the IDs is unknown or belongs to a variable of the wrong type, or if the
+    /// information matrix cannot be calculated, see
+    /// [information_matrix](imu/struct.ImuPreintegrator.html#method.information_matrix).
+    pub fn add_imu_factor(
+        &mut self,
+        from: ImuStateIds,
+        to: ImuStateIds,
+        preintegrator: &ImuPreintegrator,
+    ) -> Result<FactorId, String> {
+        let expected_types = [
+            (from.pose, "Vehicle3D"),
+            (to.pose, "Vehicle3D"),
+            (from.velocity, "Velocity3D"),
+            (to.velocity, "Velocity3D"),
+            (from.bias, "ImuBias"),
+            (to.bias, "ImuBias"),
+        ];
+        for (id, expected_type) in &expected_types {
+            let var_type = match self.get_var(self.get_csr_index(*id)?) {
+                Variable::Vehicle3D(_) => "Vehicle3D",
+                Variable::Velocity3D(_) => "Velocity3D",
+                Variable::ImuBias(_) => "ImuBias",
+                _ => "",
+            };
+            if var_type != *expected_type {
+                return Err(format!(
+                    "The variable {} of the IMU factor is not of the type {}",
+                    id, expected_type
+                ));
+            }
+        }
+        self.add_factor_with_additional_variables(
+            from.pose,
+            to.pose,
+            vec![from.velocity, to.velocity, from.bias, to.bias],
+            FactorType::ImuPreintegration3D,
+            preintegrator.constraint(),
+            preintegrator.information_matrix()?,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimizer::linear_system::calculate_error;
+    use crate::optimizer::{optimize, total_chi2};
+    use crate::parser::model::{FactorGraphModel, Vertex};
+    use approx::assert_relative_eq;
+    use std::collections::BTreeSet;
+
+    const BIAS: [f64; 6] = [0.02, -0.01, 0.03, 0.001, -0.002, 0.003];
+
+    fn get_states(id: usize) -> ImuStateIds {
+        ImuStateIds {
+            pose: VariableId(3 * id),
+            velocity: VariableId(3 * id + 1),
+            bias: VariableId(3 * id + 2),
+        }
+    }
+
+    // returns a factor graph with the given poses, velocities and biases at the states 0, 1, ...
+    fn get_factor_graph(states: &[(Vec<f64>, Vec<f64>, Vec<f64>)], fixed: &[VariableId]) -> FactorGraph {
+        let mut vertices = vec![];
+        for (i, (pose, velocity, bias)) in states.iter().enumerate() {
+            let ids = get_states(i);
+            for (id, vertex_type, content) in [
+                (ids.pose, "Vehicle3D", pose),
+                (ids.velocity, "Velocity3D", velocity),
+                (ids.bias, "ImuBias", bias),
+            ] {
+                vertices.push(Vertex {
+                    id,
+                    vertex_type: String::from(vertex_type),
+                    content: content.clone(),
+                });
+            }
+        }
+        FactorGraphModel {
+            vertices,
+            edges: vec![],
+            fixed_vertices: fixed.iter().copied().collect::<BTreeSet<_>>(),
+            ..Default::default()
+        }
+        .into()
+    }
+
+    fn get_pose(position: Vector3<f64>, rotation: UnitQuaternion<f64>) -> Vec<f64> {
+        let mut pose = position.as_slice().to_vec();
+        pose.extend_from_slice(rotation.coords.as_slice());
+        pose
+    }
+
+    // integrates one second of measurements with the bias BIAS, whose specific force results from the constant
+    // acceleration in the world frame at the given orientation and whose angular velocity is constant
+    fn integrate_constant_motion(
+        acceleration: Vector3<f64>,
+        angular_velocity: Vector3<f64>,
+        rotation: UnitQuaternion<f64>,
+        parameters: ImuParameters,
+    ) -> ImuPreintegrator {
+        let mut preintegrator = ImuPreintegrator::new(BIAS, parameters);
+        let specific_force = rotation.inverse() * (acceleration - Vector3::from(parameters.gravity));
+        for _ in 0..100 {
+            preintegrator.integrate(
+                [
+                    specific_force[0] + BIAS[0],
+                    specific_force[1] + BIAS[1],
+                    specific_force[2] + BIAS[2],
+                ],
+                [
+                    angular_velocity[0] + BIAS[3],
+                    angular_velocity[1] + BIAS[4],
+                    angular_velocity[2] + BIAS[5],
+                ],
+                0.01,
+            );
+        }
+        preintegrator
+    }
+
+    #[test]
+    fn test_constant_motion_has_zero_error() {
+        let rotation = UnitQuaternion::from_euler_angles(0.1, -0.2, 0.3);
+        let (position, velocity) = (Vector3::new(1.0, 2.0, 3.0), Vector3::new(0.5, -0.3, 0.2));
+        let acceleration = Vector3::new(0.2, 0.1, -0.3);
+        // the rotation is constant, so the specific force in the vehicle frame is constant as well
+        let translation = integrate_constant_motion(acceleration, Vector3::zeros(), rotation, Default::default());
+        assert_relative_eq!(translation.delta_time(), 1.0, epsilon = 1e-12);
+        // without gravity and acceleration, the constant angular velocity is integrated exactly
+        let angular_velocity = Vector3::new(0.1, 0.2, -0.3);
+        let parameters = ImuParameters {
+            gravity: [0.0; 3],
+            ..Default::default()
+        };
+        let rotating = integrate_constant_motion(Vector3::zeros(), angular_velocity, rotation, parameters);
+
+        let bias = BIAS.to_vec();
+        let mut factor_graph = get_factor_graph(
+            &[
+                (get_pose(position, rotation), velocity.as_slice().to_vec(), bias.clone()),
+                (
+                    get_pose(position + velocity + acceleration * 0.5, rotation),
+                    (velocity + acceleration).as_slice().to_vec(),
+                    bias.clone(),
+                ),
+                (
+                    get_pose(
+                        position + velocity * 2.0 + acceleration * 1.5,
+                        rotation * UnitQuaternion::from_scaled_axis(angular_velocity),
+                    ),
+                    (velocity + acceleration).as_slice().to_vec(),
+                    bias,
+                ),
+            ],
+            &[],
+        );
+        let translation_id = factor_graph
+            .add_imu_factor(get_states(0), get_states(1), &translation)
+            .unwrap();
+        let rotating_id = factor_graph
+            .add_imu_factor(get_states(1), get_states(2), &rotating)
+            .unwrap();
+        for id in [translation_id, rotating_id] {
+            let err = calculate_error(&factor_graph, id).unwrap();
+            assert_eq!(err.len(), 15);
+            err.iter().for_each(|e| assert_relative_eq!(*e, 0.0, epsilon = 1e-9));
+        }
+    }
+
+    #[test]
+    fn test_information_matrix() {
+        let preintegrator = ImuPreintegrator::new([0.0; 6], Default::default());
+        assert!(preintegrator.information_matrix().is_err());
+        let preintegrator = integrate_constant_motion(
+            Vector3::new(0.2, 0.1, -0.3),
+            Vector3::new(0.1, 0.2, -0.3),
+            UnitQuaternion::identity(),
+            Default::default(),
+        );
+        let information = preintegrator.information_matrix().unwrap().content;
+        assert_eq!(information.shape(), (15, 15));
+        assert_eq!(information, information.transpose());
+        assert!(information.clone().cholesky().is_some());
+        // the bias errors are independent of the others
+        assert_relative_eq!(information[(9, 9)], 1.0 / 3.0e-3_f64.powi(2), max_relative = 1e-9);
+        assert_eq!(information[(0, 12)], 0.0);
+
+        let mut factor_graph = get_factor_graph(&[(vec![0.0; 7], vec![0.0; 3], vec![0.0; 6])], &[]);
+        let result = factor_graph.add_imu_factor(
+            get_states(0),
+            ImuStateIds {
+                pose: VariableId(1),
+                ..get_states(0)
+            },
+            &preintegrator,
+        );
+        assert_eq!(
+            result,
+            Err(String::from(
+                "The variable 1 of the IMU factor is not of the type Vehicle3D"
+            ))
+        );
+    }
+
+    #[test]
+    fn test_optimization_recovers_velocity_and_bias() {
+        let preintegrator = integrate_constant_motion(
+            Vector3::new(0.4, -0.2, 0.1),
+            Vector3::new(0.3, -0.1, 0.2),
+            UnitQuaternion::identity(),
+            Default::default(),
+        );
+        // the second state is the one predicted by the preintegrated measurements
+        let constraint = preintegrator.constraint();
+        let rotation = UnitQuaternion::from_euler_angles(0.3, 0.2, -0.1);
+        let (position, velocity) = (Vector3::new(1.0, 2.0, 3.0), Vector3::new(0.5, -0.3, 0.2));
+        let gravity = Vector3::from(ImuParameters::default().gravity);
+        let delta_rotation = UnitQuaternion::from_quaternion(nalgebra::Quaternion::new(
+            constraint[DELTA_ROTATION + 3],
+            constraint[DELTA_ROTATION],
+            constraint[DELTA_ROTATION + 1],
+            constraint[DELTA_ROTATION + 2],
+        ));
+        let expected_velocity =
+            velocity + gravity + rotation * Vector3::from_column_slice(&constraint[DELTA_VELOCITY..]);
+        let pose = get_pose(
+            position + velocity + gravity * 0.5 + rotation * Vector3::from_column_slice(&constraint[DELTA_POSITION..]),
+            rotation * delta_rotation,
+        );
+        let perturbed_velocity = expected_velocity + Vector3::new(0.3, -0.2, 0.1);
+        let perturbed_bias: Vec<f64> = BIAS.iter().map(|b| b + 0.01).collect();
+        let mut factor_graph = get_factor_graph(
+            &[
+                (
+                    get_pose(position, rotation),
+                    velocity.as_slice().to_vec(),
+                    perturbed_bias.clone(),
+                ),
+                (pose, perturbed_velocity.as_slice().to_vec(), perturbed_bias),
+            ],
+            &[get_states(0).pose, get_states(0).velocity, get_states(1).pose],
+        );
+        factor_graph
+            .add_imu_factor(get_states(0), get_states(1), &preintegrator)
+            .unwrap();
+        assert!(total_chi2(&factor_graph) > 1.0);
+        optimize(&factor_graph, 20);
+        assert!(total_chi2(&factor_graph) < 1e-12);
+        let velocity = factor_graph
+            .get_var_by_id(get_states(1).velocity)
+            .unwrap()
+            .get_content();
+        (0..3).for_each(|k| assert_relative_eq!(velocity[k], expected_velocity[k], epsilon = 1e-6));
+        for id in [get_states(0).bias, get_states(1).bias] {
+            let bias = factor_graph.get_var_by_id(id).unwrap().get_content();
+            (0..6).for_each(|k| assert_relative_eq!(bias[k], BIAS[k], epsilon = 1e-6));
+        }
+    }
+}
@@ -0,0 +1,419 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Initial estimates composed from the measurements along a spanning tree of the factor graph, or triangulated from
+//! the observations of landmarks.
+
+use crate::factor_graph::adjacency::NodeIndex;
+use crate::factor_graph::factor::FactorType;
+use crate::factor_graph::variable::{FixedType, Variable};
+use crate::factor_graph::FactorGraph;
+use crate::optimizer::linear_system::iso3d_gradients::get_isometry;
+use nalgebra::{DMatrix, DVector, Isometry3, Matrix2, Point3, Rotation2, Vector2};
+use std::collections::VecDeque;
+use std::f64::consts::PI;
+
+// a factor which determines one of its variables from the other one, seen from the determining variable
+struct TreeEdge {
+    other: usize,
+    factor_type: FactorType,
+    constraint: Vec<f64>,
+    // whether the determining variable is the factor's source
+    is_forward: bool,
+}
+
+impl FactorGraph {
+    /// Replaces the estimates of the non-fixed variables by composing the measurements along a breadth-first
+    /// spanning tree, e.g. for files in which all estimates are zero.
+    ///
+    /// The tree starts at the fixed variables and at the vehicle variables with a Position2D or Position3D factor,
+    /// whose estimates are set to the measured pose. It consists of odometry factors in both directions and of
+    /// observation factors from the vehicle to the landmark. Components without such a start continue from their
+    /// first vehicle variable, which keeps its estimate. Variables which cannot be reached this way, e.g. switch
+    /// variables, keep their estimates as well.
+    ///
+    /// Since the tree may contain wrong loop closures, the result is only meant as the starting point of an
+    /// optimization.
+    pub fn initialize_from_spanning_tree(&self) {
+        let var_count = self.node_indices.len();
+        let mut tree_edges: Vec<Vec<TreeEdge>> = (0..var_count).map(|_| vec![]).collect();
+        let mut priors: Vec<Option<Vec<f64>>> = vec![None; var_count];
+        for edge in self.node_indices.iter().flat_map(|i| self.adjacency.edges(*i)) {
+            let factor = self.materialize_factor(edge.weight());
+            let (source, target) = (edge.source().index(), edge.target().index());
+            match factor.factor_type {
+                FactorType::Position2D | FactorType::Position3D => priors[source] = Some(factor.constraint.clone()),
+                FactorType::Odometry2D | FactorType::Odometry3D => {
+                    tree_edges[target].push(TreeEdge {
+                        other: source,
+                        factor_type: factor.factor_type.clone(),
+                        constraint: factor.constraint.clone(),
+                        is_forward: false,
+                    });
+                    tree_edges[source].push(TreeEdge {
+                        other: target,
+                        factor_type: factor.factor_type.clone(),
+                        constraint: factor.constraint.clone(),
+                        is_forward: true,
+                    });
+                }
+                FactorType::Observation2D | FactorType::Observation3D => tree_edges[source].push(TreeEdge {
+                    other: target,
+                    factor_type: factor.factor_type.clone(),
+                    constraint: factor.constraint.clone(),
+                    is_forward: true,
+                }),
+                _ => (),
+            }
+        }
+
+        let mut is_initialized = vec![false; var_count];
+        let mut queue = VecDeque::new();
+        for i in 0..var_count {
+            let var = self.get_var(NodeIndex::new(i));
+            if var.get_fixed_type() == &FixedType::Fixed {
+                is_initialized[i] = true;
+                queue.push_back(i);
+            } else if let Some(prior) = &priors[i] {
+                var.set_content(prior.clone());
+                is_initialized[i] = true;
+                queue.push_back(i);
+            }
+        }
+        loop {
+            while let Some(i) = queue.pop_front() {
+                let content = self.get_var(NodeIndex::new(i)).get_content();
+                for edge in &tree_edges[i] {
+                    if !is_initialized[edge.other] {
+                        self.get_var(NodeIndex::new(edge.other))
+                            .set_content(compose(&content, edge));
+                        is_initialized[edge.other] = true;
+                        queue.push_back(edge.other);
+                    }
+                }
+            }
+            let next_root = (0..var_count).find(|i| {
+                !is_initialized[*i]
+                    && matches!(
+                        self.get_var(NodeIndex::new(*i)),
+                        Variable::Vehicle2D(_) | Variable::Vehicle3D(_)
+                    )
+            });
+            match next_root {
+                Some(i) => {
+                    is_initialized[i] = true;
+                    queue.push_back(i);
+                }
+                None => return,
+            }
+        }
+    }
+
+    /// Replaces the estimates of the non-fixed landmarks by triangulating their Observation2D and Observation3D
+    /// factors from the current estimates of the observing vehicles, e.g. for files in which all landmarks are at
+    /// the origin.
+    ///
+    /// Each observation places the landmark at a position with the factor's information matrix, rotated into the
+    /// world frame. The landmark is set to the information-weighted mean of these positions, which minimizes the
+    /// observations' χ² for fixed vehicles. If the summed information matrix is singular, e.g. for a single
+    /// observation without information along the viewing ray, the mean of the positions is used instead. Landmarks
+    /// without observations and landmarks which are part of an
+    /// [equality constraint](#method.add_equality_constraint) keep their estimates.
+    ///
+    /// 2D landmarks which are only observed by Bearing2D factors are set to the point with the least
+    /// information-weighted squared distance to the viewing rays. Since a single ray does not determine a position,
+    /// landmarks with fewer than two bearings keep their estimates, as do landmarks whose rays are parallel.
+    pub fn initialize_landmarks(&self) {
+        let var_count = self.node_indices.len();
+        let mut observations: Vec<Vec<(DVector<f64>, DMatrix<f64>)>> = vec![vec![]; var_count];
+        // the vehicle position and the information-weighted projection orthogonal to the viewing ray
+        let mut bearings: Vec<Vec<(Vector2<f64>, Matrix2<f64>)>> = vec![vec![]; var_count];
+        for edge in self.node_indices.iter().flat_map(|i| self.adjacency.edges(*i)) {
+            let landmark = edge.target();
+            if self.get_var(landmark).get_fixed_type() == &FixedType::Fixed || self.is_tied(landmark) {
+                continue;
+            }
+            let factor = self.materialize_factor(edge.weight());
+            let (pose, z) = (self.get_var(edge.source()).get_content(), &factor.constraint);
+            let (position, rotation) = match factor.factor_type {
+                FactorType::Observation2D => {
+                    let rotation = Rotation2::new(pose[2]);
+                    let position = Vector2::new(pose[0], pose[1]) + rotation * Vector2::new(z[0], z[1]);
+                    (
+                        DVector::from_column_slice(position.as_slice()),
+                        DMatrix::from_column_slice(2, 2, rotation.matrix().as_slice()),
+                    )
+                }
+                FactorType::Observation3D => {
+                    let iso = get_isometry(&pose);
+                    let position = iso * Point3::new(z[0], z[1], z[2]);
+                    let rotation = iso.rotation.to_rotation_matrix();
+                    (
+                        DVector::from_column_slice(position.coords.as_slice()),
+                        DMatrix::from_column_slice(3, 3, rotation.matrix().as_slice()),
+                    )
+                }
+                FactorType::Bearing2D => {
+                    let direction = Vector2::new((pose[2] + z[0]).cos(), (pose[2] + z[0]).sin());
+                    let projection = Matrix2::identity() - direction * direction.transpose();
+                    let information = factor.information_matrix.content[(0, 0)];
+                    bearings[landmark.index()].push((Vector2::new(pose[0], pose[1]), projection * information));
+                    continue;
+                }
+                _ => continue,
+            };
+            let information = &rotation * &factor.information_matrix.content * rotation.transpose();
+            observations[landmark.index()].push((position, information));
+        }
+
+        for (i, observations) in observations.iter().enumerate().filter(|(_, o)| !o.is_empty()) {
+            let dim = observations[0].0.len();
+            let (information, weighted_sum) = observations.iter().fold(
+                (DMatrix::zeros(dim, dim), DVector::zeros(dim)),
+                |(information, weighted_sum), (position, observation_information)| {
+                    (
+                        information + observation_information,
+                        weighted_sum + observation_information * position,
+                    )
+                },
+            );
+            let position = match information.cholesky() {
+                Some(cholesky) => cholesky.solve(&weighted_sum),
+                None => {
+                    observations.iter().map(|(position, _)| position).sum::<DVector<f64>>() / observations.len() as f64
+                }
+            };
+            self.get_var(NodeIndex::new(i)).set_content(position.as_slice().to_vec());
+        }
+
+        for (i, bearings) in bearings.iter().enumerate() {
+            if bearings.len() < 2 || !observations[i].is_empty() {
+                continue;
+            }
+            let (information, weighted_sum) = bearings.iter().fold(
+                (Matrix2::zeros(), Vector2::zeros()),
+                |(information, weighted_sum), (position, projection)| {
+                    (information + projection, weighted_sum + projection * position)
+                },
+            );
+            if let Some(cholesky) = information.cholesky() {
+                self.get_var(NodeIndex::new(i)).set_content(cholesky.solve(&weighted_sum).as_slice().to_vec());
+            }
+        }
+    }
+}
+
+// returns the estimate of the edge's other variable, given the one of the determining variable
+fn compose(content: &[f64], edge: &TreeEdge) -> Vec<f64> {
+    let z = &edge.constraint;
+    match (&edge.factor_type, edge.is_forward) {
+        (FactorType::Odometry2D, true) => {
+            let position = Vector2::new(content[0], content[1]) + Rotation2::new(content[2]) * Vector2::new(z[0], z[1]);
+            vec![position.x, position.y, normalize_rotation(content[2] + z[2])]
+        }
+        (FactorType::Odometry2D, false) => {
+            let rotation = content[2] - z[2];
+            let position = Vector2::new(content[0], content[1]) - Rotation2::new(rotation) * Vector2::new(z[0], z[1]);
+            vec![position.x, position.y, normalize_rotation(rotation)]
+        }
+        (FactorType::Observation2D, _) => {
+            let position = Vector2::new(content[0], content[1]) + Rotation2::new(content[2]) * Vector2::new(z[0], z[1]);
+            vec![position.x, position.y]
+        }
+        (FactorType::Odometry3D, true) => get_pose(&(get_isometry(content) * get_isometry(z))),
+        (FactorType::Odometry3D, false) => get_pose(&(get_isometry(content) * get_isometry(z).inverse())),
+        (FactorType::Observation3D, _) => {
+            let position = get_isometry(content) * Point3::new(z[0], z[1], z[2]);
+            position.coords.data.as_slice().to_vec()
+        }
+        _ => unreachable!("Only odometry and observation factors are part of the spanning tree"),
+    }
+}
+
+// returns the pose as [x, y, z, qx, qy, qz, qw]
+fn get_pose(iso: &Isometry3<f64>) -> Vec<f64> {
+    let mut pose = iso.translation.vector.data.as_slice().to_vec();
+    pose.extend_from_slice(iso.rotation.quaternion().coords.data.as_slice());
+    pose
+}
+
+fn normalize_rotation(rotation: f64) -> f64 {
+    (rotation + PI).rem_euclid(2.0 * PI) - PI
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::factor_graph::variable::VariableId;
+    use crate::optimizer::{optimize, total_chi2};
+    use crate::parser::g2o::G2oParser;
+    use crate::parser::Parser;
+
+    fn get_content(factor_graph: &FactorGraph, id: usize) -> Vec<f64> {
+        factor_graph.get_var_by_id(VariableId(id)).unwrap().get_content()
+    }
+
+    fn assert_content_eq(content: &[f64], expected: &[f64]) {
+        assert_eq!(content.len(), expected.len());
+        assert!(
+            content.iter().zip(expected).all(|(a, b)| (a - b).abs() < 1e-9),
+            "{:?} != {:?}",
+            content,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_initialize_2d() {
+        let information = "1 0 0 1 0 1";
+        let factor_graph: FactorGraph = G2oParser::parse_string_to_model(
+            &[
+                "VERTEX_SE2 0 0 0 0",
+                "VERTEX_SE2 1 0 0 0",
+                "VERTEX_SE2 2 0 0 0",
+                "VERTEX_SE2 3 0 0 0",
+                "VERTEX_XY 4 0 0",
+                "FIX 0",
+                &format!("EDGE_SE2 0 1 1 0 1.5707963267948966 {}", information),
+                &format!("EDGE_SE2 1 2 1 0 1.5707963267948966 {}", information),
+                // backwards
+                &format!("EDGE_SE2 3 2 1 0 0 {}", information),
+                "EDGE_SE2_XY 2 4 2 0 1 0 1",
+            ]
+            .join("\n"),
+        )
+        .unwrap()
+        .into();
+        factor_graph.initialize_from_spanning_tree();
+        assert_content_eq(&get_content(&factor_graph, 0), &[0.0, 0.0, 0.0]);
+        assert_content_eq(&get_content(&factor_graph, 1), &[1.0, 0.0, 0.5 * PI]);
+        assert_content_eq(&get_content(&factor_graph, 2), &[1.0, 1.0, -PI]);
+        assert_content_eq(&get_content(&factor_graph, 3), &[2.0, 1.0, -PI]);
+        assert_content_eq(&get_content(&factor_graph, 4), &[-1.0, 1.0]);
+        assert!(total_chi2(&factor_graph) < 1e-12);
+    }
+
+    #[test]
+    fn test_initialize_3d_from_prior() {
+        let information = (0..6)
+            .flat_map(|i| (i..6).map(move |j| if i == j { "1" } else { "0" }))
+            .collect::<Vec<_>>()
+            .join(" ");
+        let quarter_turn = "0 0 0.7071067811865476 0.7071067811865476";
+        let factor_graph: FactorGraph = G2oParser::parse_string_to_model(
+            &[
+                "VERTEX_SE3:QUAT 0 0 0 0 0 0 0 1",
+                "VERTEX_SE3:QUAT 1 0 0 0 0 0 0 1",
+                "VERTEX_TRACKXYZ 2 0 0 0",
+                &format!("EDGE_SE3_PRIOR 0 0 1 2 3 {} {}", quarter_turn, information),
+                &format!("EDGE_SE3:QUAT 0 1 1 0 0 0 0 0 1 {}", information),
+                "EDGE_SE3_TRACKXYZ 1 2 0 1 0 0 1 0 0 1 0 1",
+            ]
+            .join("\n"),
+        )
+        .unwrap()
+        .into();
+        factor_graph.initialize_from_spanning_tree();
+        let pose = get_content(&factor_graph, 1);
+        assert_content_eq(&pose[..3], &[1.0, 3.0, 3.0]);
+        assert_content_eq(&get_content(&factor_graph, 2), &[1.0, 4.0, 3.0]);
+        assert!(total_chi2(&factor_graph) < 1e-12, "{}", total_chi2(&factor_graph));
+    }
+
+    #[test]
+    fn test_initialize_landmarks() {
+        // the first observation of 3 only determines its y coordinate, the second one only its x coordinate
+        let factor_graph: FactorGraph = G2oParser::parse_string_to_model(
+            &[
+                "VERTEX_SE2 0 0 0 0",
+                "VERTEX_SE2 1 2 0 1.5707963267948966",
+                "VERTEX_XY 3 0 0",
+                "VERTEX_XY 4 0 0",
+                "VERTEX_XY 5 0 0",
+                "FIX 0 1 5",
+                "EDGE_SE2_XY 0 3 5 2 0.000001 0 1",
+                "EDGE_SE2_XY 1 3 9 0 0.000001 0 1",
+                "EDGE_SE2_XY 1 4 1 1 1 0 0",
+                "EDGE_SE2_XY 0 5 1 1 1 0 1",
+            ]
+            .join("\n"),
+        )
+        .unwrap()
+        .into();
+        factor_graph.initialize_landmarks();
+        let landmark = get_content(&factor_graph, 3);
+        assert!((landmark[0] - 2.0).abs() < 1e-4 && (landmark[1] - 2.0).abs() < 1e-4);
+        // singular information
+        assert_content_eq(&get_content(&factor_graph, 4), &[1.0, 1.0]);
+        // fixed
+        assert_content_eq(&get_content(&factor_graph, 5), &[0.0, 0.0]);
+    }
+
+    #[test]
+    fn test_initialize_landmarks_from_bearings() {
+        let factor_graph: FactorGraph = G2oParser::parse_string_to_model(
+            &[
+                "VERTEX_SE2 0 0 0 0",
+                "VERTEX_SE2 1 2 0 1.5707963267948966",
+                "VERTEX_SE2 2 0 2 0",
+                "VERTEX_XY 3 0 0",
+                "VERTEX_XY 4 0 0",
+                "VERTEX_XY 5 0 0",
+                "FIX 0 1 2",
+                "EDGE_BEARING_SE2_XY 0 3 0.7853981633974483 100",
+                "EDGE_BEARING_SE2_XY 1 3 0 100",
+                // single bearing
+                "EDGE_BEARING_SE2_XY 0 4 0.5 100",
+                // parallel rays
+                "EDGE_BEARING_SE2_XY 0 5 0 100",
+                "EDGE_BEARING_SE2_XY 2 5 0 100",
+            ]
+            .join("\n"),
+        )
+        .unwrap()
+        .into();
+        factor_graph.initialize_landmarks();
+        assert_content_eq(&get_content(&factor_graph, 3), &[2.0, 2.0]);
+        assert_content_eq(&get_content(&factor_graph, 4), &[0.0, 0.0]);
+        assert_content_eq(&get_content(&factor_graph, 5), &[0.0, 0.0]);
+        assert!(total_chi2(&factor_graph) > 1.0);
+    }
+
+    #[test]
+    fn test_initialization_helps_optimization() {
+        let factor_graph = G2oParser::parse_file("data_files/optimizer_tests/full2d_0.g2o").unwrap();
+        let expected_chi2 = {
+            optimize(&factor_graph, 10);
+            total_chi2(&factor_graph)
+        };
+        factor_graph
+            .node_indices
+            .iter()
+            .map(|i| factor_graph.get_var(*i))
+            .filter(|var| var.get_fixed_type() != &FixedType::Fixed)
+            .for_each(|var| var.set_content(vec![0.0; var.get_content().len()]));
+        factor_graph.initialize_from_spanning_tree();
+        optimize(&factor_graph, 10);
+        assert!(total_chi2(&factor_graph) < expected_chi2 * 1.01);
+
+        let factor_graph = G2oParser::parse_file("data_files/optimizer_tests/full2d_0.g2o").unwrap();
+        factor_graph
+            .node_indices
+            .iter()
+            .map(|i| factor_graph.get_var(*i))
+            .filter(|var| matches!(var, Variable::Landmark2D(_)))
+            .for_each(|var| var.set_content(vec![0.0, 0.0]));
+        factor_graph.initialize_landmarks();
+        optimize(&factor_graph, 10);
+        assert!(total_chi2(&factor_graph) < expected_chi2 * 1.01);
+    }
+}
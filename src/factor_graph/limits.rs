@@ -0,0 +1,191 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Hard limits on the size of factor graphs, which make parsing and optimization fail with an error instead of
+//! allocating unbounded memory, e.g. in services which process graphs of untrusted clients.
+
+use crate::factor_graph::variable::FixedType;
+use crate::factor_graph::FactorGraph;
+use crate::parser::model::FactorGraphModel;
+use std::collections::BTreeMap;
+use std::mem::size_of;
+
+/// Limits on the resources needed by a factor graph, each of which is unlimited if it is None.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ResourceLimits {
+    /// The maximum number of variables.
+    pub max_variables: Option<usize>,
+    /// The maximum number of factors.
+    pub max_factors: Option<usize>,
+    /// The maximum dimension of the state, i.e. of the linear system.
+    pub max_state_dim: Option<usize>,
+    /// The maximum number of bytes of the memory estimate, see
+    /// [ResourceUsage](struct.ResourceUsage.html#structfield.memory_estimate).
+    pub max_memory: Option<usize>,
+}
+
+impl ResourceLimits {
+    /// Returns an error which names the first exceeded limit, if any.
+    pub fn check(&self, usage: &ResourceUsage) -> Result<(), String> {
+        let limits = [
+            ("variables", self.max_variables, usage.variables),
+            ("factors", self.max_factors, usage.factors),
+            ("the state dimension", self.max_state_dim, usage.state_dim),
+            ("the memory estimate", self.max_memory, usage.memory_estimate),
+        ];
+        for (name, limit, value) in limits.iter() {
+            match limit {
+                Some(limit) if value > limit => {
+                    return Err(format!("The limit of {} for {} is exceeded by {}", limit, name, value))
+                }
+                _ => (),
+            }
+        }
+        Ok(())
+    }
+}
+
+/// The resources needed by a factor graph or a model, which are compared with
+/// [ResourceLimits](struct.ResourceLimits.html).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResourceUsage {
+    /// The number of variables.
+    pub variables: usize,
+    /// The number of factors.
+    pub factors: usize,
+    /// The dimension of the state, i.e. the sum of the tangent space dimensions of the non-fixed variables.
+    pub state_dim: usize,
+    /// The number of bytes of the estimates, measurements and information matrices, and of the linear system of an
+    /// iteration, whose blocks are counted separately for each factor, so that shared blocks are overestimated.
+    pub memory_estimate: usize,
+}
+
+impl From<&FactorGraph> for ResourceUsage {
+    fn from(factor_graph: &FactorGraph) -> Self {
+        let get_dim = |fixed_type: &FixedType| match fixed_type {
+            FixedType::NonFixed(range) => range.len(),
+            FixedType::Fixed => 0,
+        };
+        let mut value_count: usize = factor_graph
+            .node_indices
+            .iter()
+            .map(|i| factor_graph.get_var(*i).get_content().len())
+            .sum();
+        for id in factor_graph.factor_id_map.keys() {
+            let factor = factor_graph.materialize_factor(factor_graph.get_factor(*id).unwrap());
+            let dim: usize = factor_graph
+                .get_factor_var_indices(*id)
+                .unwrap()
+                .iter()
+                .map(|i| get_dim(factor_graph.get_var(*i).get_fixed_type()))
+                .sum();
+            value_count += factor.constraint.len() + factor.information_matrix.content.len() + dim * dim;
+        }
+        ResourceUsage {
+            variables: factor_graph.node_indices.len(),
+            factors: factor_graph.factor_id_map.len(),
+            state_dim: factor_graph.matrix_dim,
+            memory_estimate: (value_count + factor_graph.matrix_dim) * size_of::<f64>(),
+        }
+    }
+}
+
+impl From<&FactorGraphModel> for ResourceUsage {
+    fn from(model: &FactorGraphModel) -> Self {
+        let dims: BTreeMap<_, _> = model
+            .vertices
+            .iter()
+            .map(|vertex| {
+                // only the rotations of 3D vehicles and the normals of planes have fewer tangent space dimensions
+                // than values
+                let dim = match vertex.vertex_type.as_str() {
+                    _ if model.fixed_vertices.contains(&vertex.id) => 0,
+                    "Vehicle3D" | "Extrinsic3D" | "Anchor3D" => 6,
+                    "VehicleSim3" => 7,
+                    "Plane3D" => 3,
+                    _ => vertex.content.len(),
+                };
+                (vertex.id, dim)
+            })
+            .collect();
+        let state_dim = dims.values().sum();
+        let mut value_count: usize = model.vertices.iter().map(|vertex| vertex.content.len()).sum();
+        for edge in &model.edges {
+            let dim: usize = edge.vertices.iter().map(|id| dims.get(id).unwrap_or(&0)).sum();
+            value_count += edge.restriction.len() + edge.information_matrix.len() + dim * dim;
+        }
+        ResourceUsage {
+            variables: model.vertices.len(),
+            factors: model.edges.len(),
+            state_dim,
+            memory_estimate: (value_count + state_dim) * size_of::<f64>(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimizer::optimize_with_limits;
+    use crate::parser::g2o::G2oParser;
+    use crate::parser::Parser;
+
+    const FILE_NAME: &str = "data_files/full_demos/all_3d_types.g2o";
+
+    #[test]
+    fn test_usage_of_model_and_factor_graph() {
+        let model = G2oParser::parse_file_to_model(FILE_NAME).unwrap();
+        let factor_graph = G2oParser::parse_file(FILE_NAME).unwrap();
+        let usage = ResourceUsage::from(&model);
+        assert_eq!(usage, ResourceUsage::from(&factor_graph));
+        assert_eq!(usage.variables, model.vertices.len());
+        assert_eq!(usage.factors, model.edges.len());
+        assert_eq!(usage.state_dim, factor_graph.matrix_dim);
+    }
+
+    #[test]
+    fn test_limits() {
+        let factor_graph = G2oParser::parse_file(FILE_NAME).unwrap();
+        let usage = ResourceUsage::from(&factor_graph);
+        let limits = ResourceLimits {
+            max_variables: Some(usage.variables),
+            max_factors: Some(usage.factors),
+            max_state_dim: Some(usage.state_dim),
+            max_memory: Some(usage.memory_estimate),
+        };
+        assert_eq!(limits.check(&usage), Ok(()));
+        assert!(G2oParser::parse_file_with_limits(FILE_NAME, &limits).is_ok());
+        assert!(optimize_with_limits(&factor_graph, 1, &limits).is_ok());
+
+        let limits = ResourceLimits {
+            max_state_dim: Some(usage.state_dim - 1),
+            ..Default::default()
+        };
+        assert_eq!(
+            limits.check(&usage),
+            Err(format!(
+                "The limit of {} for the state dimension is exceeded by {}",
+                usage.state_dim - 1,
+                usage.state_dim
+            ))
+        );
+        assert!(G2oParser::parse_file_with_limits(FILE_NAME, &limits).is_err());
+        assert!(optimize_with_limits(&factor_graph, 1, &limits).is_err());
+        let limits = ResourceLimits {
+            max_memory: Some(100),
+            ..Default::default()
+        };
+        assert!(G2oParser::parse_file_with_limits(FILE_NAME, &limits)
+            .unwrap_err()
+            .contains("file size"));
+    }
+}
@@ -0,0 +1,331 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Marginalization of variables into a dense prior on their Markov blanket, e.g. for the sparsification of pose
+//! graphs in long-term mapping.
+
+#![allow(non_snake_case)]
+
+use crate::factor_graph::adjacency::NodeIndex;
+use crate::factor_graph::factor::{FactorId, FactorType, InformationMatrix};
+use crate::factor_graph::variable::{FixedType, VariableId};
+use crate::factor_graph::FactorGraph;
+use crate::optimizer::linear_system::{calculate_factor_H_b, calculate_jacobian};
+use nalgebra::{DMatrix, DVector};
+use std::collections::{BTreeMap, BTreeSet};
+
+impl FactorGraph {
+    /// Removes the variables with the given IDs together with their factors, and replaces the factors' influence on
+    /// the remaining variables by a single DensePrior factor, whose ID is returned.
+    ///
+    /// The prior's variables are the Markov blanket of the removed variables, i.e. the non-fixed variables which
+    /// share a factor with one of them. The factors are linearized at the current estimates, so that the prior's
+    /// contributions to H and b are the Schur complements of the removed variables in the factors' joint H and b.
+    /// Marginalizing optimized variables therefore keeps the optimum of the remaining ones. Since the prior is not
+    /// linearized again, it is only accurate as long as the estimates of its variables do not change much.
+    ///
+    /// Returns None if the Markov blanket is empty, in which case the variables and factors are removed anyway.
+    /// Returns an error without changing the factor graph if one of the IDs is unknown, if the removed variables are
+    /// not fully constrained by their factors or if one of them or of the variables in their Markov blanket is part
+    /// of an [equality constraint](#method.add_equality_constraint). The prior is added with
+    /// [add_dense_prior](#method.add_dense_prior), and an error is returned after removing the variables if it
+    /// cannot be added.
+    pub fn marginalize(&mut self, ids: &[VariableId]) -> Result<Option<FactorId>, String> {
+        let marginalized = ids
+            .iter()
+            .map(|id| self.get_csr_index(*id))
+            .collect::<Result<BTreeSet<NodeIndex<usize>>, String>>()?;
+        let factors: Vec<FactorId> = self
+            .factor_id_map
+            .keys()
+            .copied()
+            .filter(|id| {
+                self.get_factor_var_indices(*id)
+                    .unwrap()
+                    .iter()
+                    .any(|i| marginalized.contains(i))
+            })
+            .collect();
+        let blanket: Vec<NodeIndex<usize>> = factors
+            .iter()
+            .flat_map(|id| self.get_factor_var_indices(*id).unwrap())
+            .filter(|i| !marginalized.contains(i) && self.get_var(*i).get_fixed_type() != &FixedType::Fixed)
+            .collect::<BTreeSet<NodeIndex<usize>>>()
+            .into_iter()
+            .collect();
+        if let Some(index) = marginalized.iter().chain(blanket.iter()).find(|i| self.is_tied(**i)) {
+            return Err(format!(
+                "The variable {} is part of an equality constraint and cannot be marginalized",
+                self.get_var(*index).get_id()
+            ));
+        }
+        if blanket.is_empty() {
+            self.remove_marginalized(&factors, &marginalized);
+            return Ok(None);
+        }
+
+        // the prior's mean minimizes the quadratic approximation, which is rank-deficient if nothing in the removed
+        // factors anchors the blanket, e.g. for odometry factors only
+        let (H, b) = self.calculate_marginal_H_b(&factors, &marginalized, &blanket)?;
+        let svd = H.clone().svd(true, true);
+        let eps = 1e-12 * svd.singular_values.max();
+        let mean_correction = -svd.solve(&b, eps)?;
+        let mut constraint = vec![];
+        let mut offset = 0;
+        for i in &blanket {
+            let parameterization = self.get_var(*i).get_parameterization();
+            let dim = parameterization.tangent_dim();
+            constraint.extend(parameterization.plus(
+                &self.get_var(*i).get_content(),
+                &mean_correction.as_slice()[offset..offset + dim],
+            ));
+            offset += dim;
+        }
+
+        let blanket_ids: Vec<VariableId> = blanket.iter().map(|i| self.get_var(*i).get_id()).collect();
+        self.remove_marginalized(&factors, &marginalized);
+        let id = self.add_dense_prior(
+            &blanket_ids,
+            constraint,
+            InformationMatrix {
+                content: DMatrix::identity(H.nrows(), H.ncols()),
+            },
+        )?;
+        // the information matrix refers to the prior's errors instead of the variables' corrections
+        let jacobian_inverse = calculate_jacobian(self, id)
+            .unwrap()
+            .try_inverse()
+            .expect("The Jacobian of a dense prior is invertible.");
+        let information = jacobian_inverse.transpose() * &H * jacobian_inverse;
+        self.set_information_matrix(
+            id,
+            InformationMatrix {
+                content: (&information + information.transpose()) * 0.5,
+            },
+        )?;
+        Ok(Some(id))
+    }
+
+    /// Adds a DensePrior factor on the variables with the given IDs and returns its ID.
+    ///
+    /// The constraint consists of the concatenated contents of the variables in the given order, the rows and
+    /// columns of the information matrix belong to their errors in the same order. The factor's source and target
+    /// are the first variable, the others are its additional variables.
+    pub fn add_dense_prior(
+        &mut self,
+        variables: &[VariableId],
+        constraint: Vec<f64>,
+        information_matrix: InformationMatrix,
+    ) -> Result<FactorId, String> {
+        let indices = variables
+            .iter()
+            .map(|id| self.get_csr_index(*id))
+            .collect::<Result<Vec<NodeIndex<usize>>, String>>()?;
+        let content_len: usize = indices.iter().map(|i| self.get_var(*i).get_content().len()).sum();
+        let dim: usize = indices
+            .iter()
+            .map(|i| self.get_var(*i).get_parameterization().tangent_dim())
+            .sum();
+        if constraint.len() != content_len || information_matrix.content.shape() != (dim, dim) {
+            return Err(format!(
+                "A dense prior on the variables {:?} needs a constraint with {} values and a {}x{} information matrix",
+                variables, content_len, dim, dim
+            ));
+        }
+        let (first, others) = variables
+            .split_first()
+            .ok_or("A dense prior needs at least one variable")?;
+        self.add_factor_with_additional_variables(
+            *first,
+            *first,
+            others.to_vec(),
+            FactorType::DensePrior,
+            constraint,
+            information_matrix,
+        )
+    }
+
+    // returns H and b of the factors restricted to the blanket after eliminating the marginalized variables
+    pub(crate) fn calculate_marginal_H_b(
+        &self,
+        factors: &[FactorId],
+        marginalized: &BTreeSet<NodeIndex<usize>>,
+        blanket: &[NodeIndex<usize>],
+    ) -> Result<(DMatrix<f64>, DVector<f64>), String> {
+        // maps the first row of each variable's range in H to its first row in the local system
+        let mut local_rows = BTreeMap::new();
+        let mut dim = 0;
+        let mut marginalized_dim = 0;
+        for (i, index) in marginalized.iter().chain(blanket.iter()).enumerate() {
+            if i == marginalized.len() {
+                marginalized_dim = dim;
+            }
+            if let FixedType::NonFixed(range) = self.get_var(*index).get_fixed_type() {
+                local_rows.insert(range.start, (dim, range.len()));
+                dim += range.len();
+            }
+        }
+
+        let mut H = DMatrix::zeros(dim, dim);
+        let mut b = DVector::zeros(dim);
+        for id in factors {
+            let (factor_H, factor_b) = calculate_factor_H_b(self, *id).unwrap();
+            for ((row, col), block) in factor_H.blocks() {
+                let (local_row, local_col) = (local_rows[row].0, local_rows[col].0);
+                let mut local_block = H.slice_mut((local_row, local_col), block.shape());
+                local_block += block;
+            }
+            for (row, (local_row, len)) in &local_rows {
+                let mut local_segment = b.rows_mut(*local_row, *len);
+                local_segment += factor_b.rows(*row, *len);
+            }
+        }
+
+        let blanket_dim = dim - marginalized_dim;
+        let H_MM_inverse = H
+            .slice((0, 0), (marginalized_dim, marginalized_dim))
+            .into_owned()
+            .try_inverse()
+            .ok_or("The marginalized variables are not fully constrained by their factors")?;
+        let H_BM = H.slice((marginalized_dim, 0), (blanket_dim, marginalized_dim));
+        let H_BB = H.slice((marginalized_dim, marginalized_dim), (blanket_dim, blanket_dim));
+        let gain = H_BM * H_MM_inverse;
+        Ok((
+            H_BB - &gain * H_BM.transpose(),
+            b.rows(marginalized_dim, blanket_dim) - gain * b.rows(0, marginalized_dim),
+        ))
+    }
+
+    // removes the factors and variables and assigns consecutive indices and ranges in H to the remaining variables
+    pub(crate) fn remove_marginalized(&mut self, factors: &[FactorId], marginalized: &BTreeSet<NodeIndex<usize>>) {
+        factors.iter().for_each(|id| {
+            self.remove_factor(*id).unwrap();
+        });
+        let ids: BTreeSet<VariableId> = marginalized.iter().map(|i| self.get_var(*i).get_id()).collect();
+        let new_indices = self.adjacency.retain_nodes(|var| !ids.contains(&var.get_id()));
+        self.node_indices = (0..self.adjacency.node_count()).map(NodeIndex::new).collect();
+        self.custom_to_csr_id_map = self
+            .custom_to_csr_id_map
+            .iter()
+            .filter_map(|(id, i)| new_indices[i.index()].map(|new_index| (*id, new_index)))
+            .collect();
+        self.timestamps.retain(|id, _| !ids.contains(id));
+        self.factor_id_map.values_mut().for_each(|(source, target)| {
+            *source = new_indices[source.index()].unwrap();
+            *target = new_indices[target.index()].unwrap();
+        });
+        self.set_fixed(&[], false).unwrap();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::examples_gen::single_landmark_with_two_observations;
+    use crate::optimizer::linear_system::calculate_H_b;
+    use crate::optimizer::{optimize, total_chi2};
+    use crate::parser::g2o::G2oParser;
+    use crate::parser::json::JsonParser;
+    use crate::parser::model::FactorGraphModel;
+    use crate::parser::Parser;
+
+    // returns the Schur complement of the variable with the given ID in H and b of the whole factor graph
+    fn get_expected_H_b(factor_graph: &FactorGraph, id: VariableId) -> (DMatrix<f64>, DVector<f64>) {
+        let (H, b) = calculate_H_b(factor_graph);
+        let (H, b) = (H.to_dense(), b);
+        let range = match factor_graph.get_var_by_id(id).unwrap().get_fixed_type() {
+            FixedType::NonFixed(range) => range.clone(),
+            FixedType::Fixed => unreachable!(),
+        };
+        let kept: Vec<usize> = (0..H.nrows()).filter(|i| !range.contains(i)).collect();
+        let H_KM = H.select_rows(&kept).columns(range.start, range.len()).into_owned();
+        let H_MM_inverse = H
+            .slice((range.start, range.start), (range.len(), range.len()))
+            .try_inverse()
+            .unwrap();
+        let gain = &H_KM * H_MM_inverse;
+        (
+            H.select_rows(&kept).select_columns(&kept) - &gain * H_KM.transpose(),
+            b.select_rows(&kept) - gain * b.rows(range.start, range.len()),
+        )
+    }
+
+    fn assert_schur_complement(file_name: &str, id: VariableId) {
+        let mut factor_graph = G2oParser::parse_file(file_name).unwrap();
+        let (expected_H, expected_b) = get_expected_H_b(&factor_graph, id);
+        let var_count = factor_graph.node_indices.len();
+        let prior = factor_graph.marginalize(&[id]).unwrap().unwrap();
+        assert_eq!(factor_graph.node_indices.len(), var_count - 1);
+        assert!(factor_graph.get_var_by_id(id).is_none());
+        assert_eq!(factor_graph.get_factor_var_indices(prior).unwrap().len(), 2);
+
+        let (H, b) = calculate_H_b(&factor_graph);
+        assert!((H.to_dense() - &expected_H).norm() < 1e-6 * expected_H.norm());
+        assert!((b - &expected_b).norm() < 1e-6 * expected_b.norm());
+    }
+
+    #[test]
+    fn test_prior_is_schur_complement() {
+        assert_schur_complement("data_files/optimizer_tests/full2d_0.g2o", VariableId(5));
+        assert_schur_complement("data_files/optimizer_tests/odo3d_only_0.g2o", VariableId(10));
+    }
+
+    #[test]
+    fn test_optimum_is_kept() {
+        let mut factor_graph = G2oParser::parse_file("data_files/optimizer_tests/full2d_0.g2o").unwrap();
+        optimize(&factor_graph, 30);
+        factor_graph.marginalize(&[VariableId(5), VariableId(6)]).unwrap();
+        let estimates: Vec<Vec<f64>> = factor_graph
+            .node_indices
+            .iter()
+            .map(|i| factor_graph.get_var(*i).get_content())
+            .collect();
+        optimize(&factor_graph, 5);
+        for (i, estimate) in factor_graph.node_indices.iter().zip(estimates) {
+            let content = factor_graph.get_var(*i).get_content();
+            assert!(content.iter().zip(estimate).all(|(a, b)| (a - b).abs() < 1e-6));
+        }
+    }
+
+    #[test]
+    fn test_marginalize_landmark() {
+        let mut factor_graph: FactorGraph = single_landmark_with_two_observations().into();
+        assert!(factor_graph.marginalize(&[VariableId(7)]).is_err());
+        assert_eq!(factor_graph.node_indices.len(), 3);
+
+        optimize(&factor_graph, 10);
+        let prior = factor_graph.marginalize(&[VariableId(2)]).unwrap().unwrap();
+        let factor = factor_graph.get_factor(prior).unwrap();
+        assert_eq!(factor.factor_type, FactorType::DensePrior);
+        assert!(factor.additional_variables.is_empty());
+        assert_eq!(
+            factor.predict(&factor_graph),
+            factor_graph.get_var_by_id(VariableId(1)).unwrap().get_content()
+        );
+        assert!(total_chi2(&factor_graph) < 1e-12);
+
+        let model = FactorGraphModel::from(&factor_graph);
+        assert!(model.edges.iter().any(|e| e.edge_type == "DensePrior"));
+        let parsed: FactorGraph =
+            JsonParser::parse_string_to_model(&JsonParser::compose_model_to_string(model).unwrap())
+                .unwrap()
+                .into();
+        let (parsed_model, model) = (FactorGraphModel::from(&parsed), FactorGraphModel::from(&factor_graph));
+        assert_eq!(parsed_model.edges.len(), model.edges.len());
+        assert!(parsed_model
+            .edges
+            .iter()
+            .zip(&model.edges)
+            .all(|(a, b)| a.vertices == b.vertices));
+        assert!(total_chi2(&parsed) < 1e-12);
+    }
+}
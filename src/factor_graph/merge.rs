@@ -0,0 +1,411 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Merging of two sessions, i.e. factor graphs in different coordinate frames, which observe common landmarks.
+//!
+//! The transform from the second session's frame into the first one's is estimated from the positions of
+//! corresponding landmarks with the method of Umeyama, which is made robust against wrong correspondences with
+//! RANSAC (random sample consensus), see [ransac](../ransac/index.html).
+
+use crate::factor_graph::imu::GRAVITY;
+use crate::factor_graph::ransac::{ransac_points, RansacConfig};
+use crate::factor_graph::units::UnitWarning;
+use crate::factor_graph::variable::{Variable, VariableId};
+use crate::factor_graph::FactorGraph;
+use crate::optimizer::linear_system::iso3d_gradients::get_isometry;
+use crate::parser::model::{Edge, FactorGraphModel, Vertex};
+use nalgebra::{DMatrix, DVector, Isometry3, Matrix3, Point3, Rotation3, Translation3, UnitQuaternion, Vector3};
+use std::collections::BTreeMap;
+
+/// Configuration of [merge_session](../struct.FactorGraph.html#method.merge_session).
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionAlignmentConfig {
+    /// The maximum distance between an aligned landmark of the second session and the corresponding landmark of
+    /// the first session, up to which the correspondence is an inlier.
+    pub inlier_threshold: f64,
+    /// The number of random minimal sets of correspondences from which a transform is estimated.
+    pub ransac_iterations: usize,
+    /// The seed of the random sampling, which makes the alignment reproducible.
+    pub seed: u64,
+    /// The diagonal entries of the information matrices of the priors which link the second session to the first.
+    pub prior_information: f64,
+}
+
+impl Default for SessionAlignmentConfig {
+    fn default() -> Self {
+        SessionAlignmentConfig {
+            inlier_threshold: 0.5,
+            ransac_iterations: 100,
+            seed: 0,
+            prior_information: 1.0,
+        }
+    }
+}
+
+/// The result of aligning the second session with the first one.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionAlignment {
+    /// The transform from the second session's frame into the first one's. For 2D sessions, it is a rotation
+    /// around the z axis and a translation within the xy plane.
+    pub transform: Isometry3<f64>,
+    /// The correspondences which agree with the transform, as pairs of the first and the second session's IDs.
+    pub inliers: Vec<(VariableId, VariableId)>,
+    /// The offset which was added to the IDs of the second session's variables which are not merged with a
+    /// landmark of the first session.
+    pub id_offset: usize,
+    /// The warning if the sessions are likely in different units, see
+    /// [check_unit_consistency](../struct.FactorGraph.html#method.check_unit_consistency) with the default
+    /// configuration. The sessions are merged regardless.
+    pub unit_warning: Option<UnitWarning>,
+}
+
+impl FactorGraph {
+    /// Returns the union of this session and the other one, whose estimates are transformed into this session's
+    /// frame, and the alignment of the sessions.
+    ///
+    /// The correspondences are pairs of landmark IDs of this and the other session, e.g. matched by their
+    /// descriptors. They have to be either 2D or 3D landmarks, and at least two or three, respectively, have to
+    /// agree with the estimated transform. The landmarks of inlying correspondences are merged, i.e. the other
+    /// session's factors are connected to this session's landmark instead. All other variables of the other
+    /// session get new IDs by adding an offset.
+    ///
+    /// Instead of the other session's fixed vehicles, which would contradict this session's anchoring, a
+    /// Position2D or Position3D factor at their transformed pose links the sessions. Position factors of the
+    /// other session are transformed as well. Max-mixture factors only keep their dominant component.
+    ///
+    /// The merged factor graph is meant to be optimized jointly afterwards. It keeps this session's declared unit, or
+    /// the other session's if this session declares none.
+    pub fn merge_session(
+        &self,
+        other: &FactorGraph,
+        correspondences: &[(VariableId, VariableId)],
+        config: &SessionAlignmentConfig,
+    ) -> Result<(FactorGraph, SessionAlignment), String> {
+        let mut own_positions = vec![];
+        let mut other_positions = vec![];
+        let mut is_3d = None;
+        for (own_id, other_id) in correspondences {
+            let own_var = self
+                .get_var_by_id(*own_id)
+                .ok_or_else(|| format!("Unknown variable ID: {}", own_id))?;
+            let other_var = other
+                .get_var_by_id(*other_id)
+                .ok_or_else(|| format!("Unknown variable ID in the other session: {}", other_id))?;
+            let pair_is_3d = match (own_var, other_var) {
+                (Variable::Landmark2D(_), Variable::Landmark2D(_)) => false,
+                (Variable::Landmark3D(_), Variable::Landmark3D(_)) => true,
+                _ => {
+                    return Err(format!(
+                        "Landmarks {} and {} must both be either 2D or 3D landmarks",
+                        own_id, other_id
+                    ))
+                }
+            };
+            if *is_3d.get_or_insert(pair_is_3d) != pair_is_3d {
+                return Err(String::from("The correspondences must not mix 2D and 3D landmarks"));
+            }
+            own_positions.push(DVector::from_vec(own_var.get_content()));
+            other_positions.push(DVector::from_vec(other_var.get_content()));
+        }
+        let ransac_config = RansacConfig {
+            inlier_threshold: config.inlier_threshold,
+            iterations: config.ransac_iterations,
+            seed: config.seed,
+            ..Default::default()
+        };
+        let (transform, inlier_indices) = ransac_points(&other_positions, &own_positions, false, &ransac_config)?;
+        let (rotation, translation) = (transform.rotation, transform.translation);
+        let transform = match is_3d {
+            Some(true) => {
+                let rotation = Rotation3::from_matrix_unchecked(Matrix3::from_iterator(rotation.iter().copied()));
+                Isometry3::from_parts(
+                    Translation3::new(translation[0], translation[1], translation[2]),
+                    UnitQuaternion::from_rotation_matrix(&rotation),
+                )
+            }
+            _ => Isometry3::new(
+                Vector3::new(translation[0], translation[1], 0.0),
+                Vector3::new(0.0, 0.0, rotation[(1, 0)].atan2(rotation[(0, 0)])),
+            ),
+        };
+        let inliers: Vec<(VariableId, VariableId)> = inlier_indices.iter().map(|i| correspondences[*i]).collect();
+
+        let id_offset = self.custom_to_csr_id_map.keys().last().map_or(0, |id| id.0 + 1);
+        let merged_ids: BTreeMap<VariableId, VariableId> = inliers.iter().map(|(own, other)| (*other, *own)).collect();
+        let get_id = |id: &VariableId| merged_ids.get(id).copied().unwrap_or(VariableId(id.0 + id_offset));
+        let mut model = FactorGraphModel::from(self);
+        model.unit = self.unit().or_else(|| other.unit());
+        let other_model = FactorGraphModel::from(other);
+        for (id, time) in &other_model.timestamps {
+            model.timestamps.entry(get_id(id)).or_insert(*time);
+        }
+        for vertex in other_model.vertices {
+            if merged_ids.contains_key(&vertex.id) {
+                continue;
+            }
+            let content = transform_content(&transform, &vertex.vertex_type, &vertex.content);
+            if other_model.fixed_vertices.contains(&vertex.id) {
+                match vertex.vertex_type.as_str() {
+                    "Vehicle2D" | "Vehicle3D" => model.edges.push(get_prior(
+                        &vertex.vertex_type,
+                        get_id(&vertex.id),
+                        content.clone(),
+                        config.prior_information,
+                    )),
+                    _ => {
+                        model.fixed_vertices.insert(get_id(&vertex.id));
+                    }
+                }
+            }
+            model.vertices.push(Vertex {
+                id: get_id(&vertex.id),
+                vertex_type: vertex.vertex_type,
+                content,
+            });
+        }
+        for edge in other_model.edges {
+            let restriction = match edge.edge_type.as_str() {
+                "Position2D" => transform_content(&transform, "Vehicle2D", &edge.restriction),
+                "Position3D" => transform_content(&transform, "Vehicle3D", &edge.restriction),
+                "PositionOnly3D" => transform_content(&transform, "Landmark3D", &edge.restriction),
+                "InterpolatedPose2D" | "InterpolatedPose3D" => {
+                    // the pose is in the world frame, the fraction of the time between both poses does not change
+                    let (vertex_type, dim) = match edge.edge_type.as_str() {
+                        "InterpolatedPose2D" => ("Vehicle2D", 3),
+                        _ => ("Vehicle3D", 7),
+                    };
+                    let mut restriction = transform_content(&transform, vertex_type, &edge.restriction[..dim]);
+                    restriction.push(edge.restriction[dim]);
+                    restriction
+                }
+                "LeverArmPosition2D" | "LeverArmPosition3D" => {
+                    // the antenna's position is in the world frame, the lever arm in the vehicle's frame
+                    let (vertex_type, dim) = match edge.edge_type.as_str() {
+                        "LeverArmPosition2D" => ("Landmark2D", 2),
+                        _ => ("Landmark3D", 3),
+                    };
+                    let mut restriction = transform_content(&transform, vertex_type, &edge.restriction[..dim]);
+                    restriction.extend_from_slice(&edge.restriction[dim..]);
+                    restriction
+                }
+                "Altitude3D" => vec![edge.restriction[0] + transform.translation.z],
+                "LandmarkOffset2D" => {
+                    let offset = [edge.restriction[0], edge.restriction[1], 0.0];
+                    transform_content(&transform, "Velocity3D", &offset)[..2].to_vec()
+                }
+                "LandmarkOffset3D" => transform_content(&transform, "Velocity3D", &edge.restriction),
+                "Heading2D" | "Heading3D" => {
+                    let heading = edge.restriction[0] + transform.rotation.euler_angles().2;
+                    vec![heading.sin().atan2(heading.cos())]
+                }
+                "ImuPreintegration3D" => {
+                    // the preintegrated motion is relative to the first pose, only the gravity is in the world frame
+                    let mut restriction = edge.restriction;
+                    let gravity = transform_content(&transform, "Velocity3D", &restriction[GRAVITY..GRAVITY + 3]);
+                    restriction[GRAVITY..GRAVITY + 3].copy_from_slice(&gravity);
+                    restriction
+                }
+                _ => edge.restriction,
+            };
+            model.edges.push(Edge {
+                edge_type: edge.edge_type,
+                vertices: edge.vertices.iter().map(get_id).collect(),
+                restriction,
+                information_matrix: edge.information_matrix,
+            });
+        }
+        Ok((
+            model.into(),
+            SessionAlignment {
+                transform,
+                inliers,
+                id_offset,
+                unit_warning: self.check_unit_consistency(other, &Default::default()),
+            },
+        ))
+    }
+}
+
+// returns the content of a variable of the given type, transformed into the first session's frame
+pub(crate) fn transform_content(transform: &Isometry3<f64>, vertex_type: &str, content: &[f64]) -> Vec<f64> {
+    match vertex_type {
+        "Vehicle2D" => {
+            let position = transform * Point3::new(content[0], content[1], 0.0);
+            let rotation = content[2] + transform.rotation.euler_angles().2;
+            vec![position.x, position.y, rotation.sin().atan2(rotation.cos())]
+        }
+        "Landmark2D" => {
+            let position = transform * Point3::new(content[0], content[1], 0.0);
+            vec![position.x, position.y]
+        }
+        "Vehicle3D" | "VehicleSim3" => {
+            let pose = transform * get_isometry(content);
+            let mut transformed = pose.translation.vector.data.as_slice().to_vec();
+            transformed.extend_from_slice(pose.rotation.quaternion().coords.data.as_slice());
+            // the scale of a Sim(3) pose does not change with the frame
+            transformed.extend_from_slice(&content[7..]);
+            transformed
+        }
+        "Landmark3D" => (transform * Point3::new(content[0], content[1], content[2]))
+            .coords
+            .data
+            .as_slice()
+            .to_vec(),
+        "Velocity3D" => (transform.rotation * Vector3::new(content[0], content[1], content[2]))
+            .data
+            .as_slice()
+            .to_vec(),
+        "LineLandmark2D" => {
+            // like planes, with the normal (cos(angle), sin(angle)) rotated around the z axis
+            let angle = content[0] + transform.rotation.euler_angles().2;
+            let (sin, cos) = angle.sin_cos();
+            let translation = transform.translation.vector;
+            vec![sin.atan2(cos), content[1] + cos * translation.x + sin * translation.y]
+        }
+        "Plane3D" => {
+            // the rotated normal n' and the distance d' = d + n' * t describe the same points in the new frame
+            let normal = transform.rotation * Vector3::new(content[0], content[1], content[2]);
+            let mut transformed = normal.data.as_slice().to_vec();
+            transformed.push(content[3] + normal.dot(&transform.translation.vector));
+            transformed
+        }
+        _ => content.to_vec(),
+    }
+}
+
+// returns a Position2D or Position3D factor for the vehicle, whose information matrix is a multiple of the identity
+fn get_prior(vertex_type: &str, id: VariableId, pose: Vec<f64>, information: f64) -> Edge {
+    let (edge_type, dim) = match vertex_type {
+        "Vehicle2D" => ("Position2D", 3),
+        _ => ("Position3D", 6),
+    };
+    Edge {
+        edge_type: String::from(edge_type),
+        vertices: vec![id],
+        restriction: pose,
+        information_matrix: DMatrix::from_diagonal_element(dim, dim, information)
+            .as_slice()
+            .to_vec(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimizer::{optimize, total_chi2};
+    use crate::parser::g2o::G2oParser;
+    use crate::parser::Parser;
+    use approx::assert_relative_eq;
+    use nalgebra::{Isometry2, Point2, Vector2};
+    use std::f64::consts::FRAC_PI_2;
+
+    const INFORMATION_2D: &str = "1 0 0 1 0 1";
+
+    // the landmarks in the first session's frame, the last one is only part of the first session
+    const LANDMARKS: [(f64, f64); 4] = [(1.0, 1.0), (2.0, 1.0), (0.0, 2.0), (3.0, 3.0)];
+
+    fn get_observation(vehicle: usize, landmark: usize, pose: (f64, f64), position: (f64, f64)) -> String {
+        format!(
+            "EDGE_SE2_XY {} {} {} {} 1 0 1",
+            vehicle,
+            landmark,
+            position.0 - pose.0,
+            position.1 - pose.1
+        )
+    }
+
+    fn get_first_session() -> FactorGraph {
+        let mut lines = vec![
+            String::from("VERTEX_SE2 0 0 0 0"),
+            String::from("VERTEX_SE2 1 1 0 0"),
+            String::from("FIX 0"),
+            format!("EDGE_SE2 0 1 1 0 0 {}", INFORMATION_2D),
+        ];
+        for (i, landmark) in LANDMARKS.iter().enumerate() {
+            lines.push(format!("VERTEX_XY {} {} {}", i + 2, landmark.0, landmark.1));
+            lines.push(get_observation(i % 2, i + 2, (i as f64 % 2.0, 0.0), *landmark));
+        }
+        G2oParser::parse_string_to_model(&lines.join("\n")).unwrap().into()
+    }
+
+    // returns the second session, whose frame results from the first one's by the given transform, with a
+    // vehicle at (1, 0) in the first session's frame and the landmarks besides the last one, which is replaced
+    fn get_second_session(frame: &Isometry2<f64>) -> FactorGraph {
+        let pose = (1.0, 0.0);
+        let local_pose = frame.inverse() * Point2::new(pose.0, pose.1);
+        let mut landmarks = LANDMARKS.to_vec();
+        landmarks[3] = (-3.0, -3.0);
+        let mut lines = vec![
+            format!(
+                "VERTEX_SE2 0 {} {} {}",
+                local_pose.x,
+                local_pose.y,
+                -frame.rotation.angle()
+            ),
+            String::from("FIX 0"),
+        ];
+        for (i, landmark) in landmarks.iter().enumerate() {
+            let local = frame.inverse() * Point2::new(landmark.0, landmark.1);
+            lines.push(format!("VERTEX_XY {} {} {}", i + 10, local.x, local.y));
+            lines.push(get_observation(0, i + 10, pose, *landmark));
+        }
+        G2oParser::parse_string_to_model(&lines.join("\n")).unwrap().into()
+    }
+
+    #[test]
+    fn test_merge_2d_sessions() {
+        let frame = Isometry2::new(Vector2::new(1.0, 2.0), FRAC_PI_2);
+        let first = get_first_session();
+        let second = get_second_session(&frame);
+        let correspondences: Vec<(VariableId, VariableId)> =
+            (0..4).map(|i| (VariableId(i + 2), VariableId(i + 10))).collect();
+        let config = SessionAlignmentConfig {
+            inlier_threshold: 0.1,
+            ..Default::default()
+        };
+        let (merged, alignment) = first.merge_session(&second, &correspondences, &config).unwrap();
+        assert_eq!(alignment.inliers, correspondences[..3].to_vec());
+        assert_eq!(alignment.id_offset, 6);
+        assert_eq!(alignment.unit_warning, None);
+        assert!((alignment.transform.translation.vector - Vector3::new(1.0, 2.0, 0.0)).norm() < 1e-9);
+        assert_relative_eq!(alignment.transform.rotation.angle(), FRAC_PI_2, epsilon = 1e-9);
+
+        // the merged landmarks, the second session's vehicle and its replaced landmark
+        assert_eq!(merged.node_indices.len(), 8);
+        assert!(merged.get_var_by_id(VariableId(12)).is_none());
+        let pose = merged.get_var_by_id(VariableId(6)).unwrap().get_content();
+        assert_relative_eq!(pose[0], 1.0, epsilon = 1e-9);
+        assert_relative_eq!(pose[1], 0.0, epsilon = 1e-9);
+        assert_relative_eq!(pose[2], 0.0, epsilon = 1e-9);
+        assert_eq!(merged.factors_between(VariableId(6), VariableId(2)).len(), 1);
+        assert_eq!(merged.factors_between(VariableId(6), VariableId(6)).len(), 1);
+        let landmark = merged.get_var_by_id(VariableId(19)).unwrap().get_content();
+        assert_relative_eq!(landmark[0], -3.0, epsilon = 1e-9);
+        assert_relative_eq!(landmark[1], -3.0, epsilon = 1e-9);
+        optimize(&merged, 5);
+        assert!(total_chi2(&merged) < 1e-12);
+    }
+
+    #[test]
+    fn test_invalid_correspondences() {
+        let first = get_first_session();
+        let second = get_second_session(&Isometry2::identity());
+        let config = SessionAlignmentConfig::default();
+        let too_few = [(VariableId(2), VariableId(10))];
+        assert!(first.merge_session(&second, &too_few, &config).is_err());
+        let no_landmarks = [(VariableId(0), VariableId(0)), (VariableId(2), VariableId(10))];
+        assert!(first.merge_session(&second, &no_landmarks, &config).is_err());
+        let unknown = [(VariableId(2), VariableId(10)), (VariableId(3), VariableId(42))];
+        assert!(first.merge_session(&second, &unknown, &config).is_err());
+    }
+
+}
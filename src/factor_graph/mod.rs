@@ -11,37 +11,722 @@
 //
 
 //! The internal representation of a factor graph.
+//!
+//! The variables and factors are available without the feature "std", the factor graph itself is not.
 
-use petgraph::csr::{Csr, NodeIndex};
-use petgraph::Directed;
-use std::collections::HashMap;
-use std::ops::Index;
+#[cfg(feature = "std")]
+use adjacency::{AdjacencyList, NodeIndex};
+#[cfg(feature = "std")]
+use payload_store::PayloadStore;
+#[cfg(feature = "std")]
+use std::collections::{BTreeMap, BTreeSet};
+#[cfg(feature = "std")]
+use std::rc::Rc;
 
+#[cfg(feature = "std")]
+pub mod adjacency;
+#[cfg(feature = "std")]
+pub mod chordal_initialization;
+#[cfg(feature = "std")]
+pub mod compaction;
+#[cfg(feature = "std")]
+pub mod covariance;
 pub mod factor;
+#[cfg(feature = "std")]
+pub mod equality;
+#[cfg(feature = "std")]
+pub mod gating;
+#[cfg(feature = "std")]
+pub mod imu;
+#[cfg(feature = "std")]
+pub mod initialization;
+#[cfg(feature = "std")]
+pub mod limits;
+#[cfg(feature = "std")]
+pub mod marginalization;
+#[cfg(feature = "std")]
+pub mod merge;
+#[cfg(feature = "std")]
+pub mod payload_store;
+#[cfg(feature = "std")]
+pub mod ransac;
+#[cfg(feature = "std")]
+pub mod sampling;
+#[cfg(feature = "std")]
+pub mod smoothing;
+#[cfg(feature = "std")]
+pub mod sparsification;
+#[cfg(feature = "std")]
+pub mod timestamps;
+#[cfg(feature = "std")]
+pub mod topology;
+pub mod units;
 pub mod variable;
 
-use factor::Factor;
-use variable::Variable;
+#[cfg(feature = "std")]
+use factor::{CustomFactor, Factor, FactorId, FactorType, InformationMatrix};
+#[cfg(feature = "std")]
+use variable::{CustomVariable, FixedType, UserDefinedVariable, Variable, VariableId};
 
-/// A CSR (compressed sparse row) representation of a factor graph.
-pub type FactorGraphCsr<'a> = Csr<Variable, Factor, Directed, usize>;
+/// The adjacency list representation of a factor graph.
+#[cfg(feature = "std")]
+pub type FactorGraphAdjacency = AdjacencyList<Variable, Factor>;
 
 /// Structure representing the factor graph internally.
+#[cfg(feature = "std")]
 #[derive(Debug)]
 pub struct FactorGraph {
-    /// The factor graph's CSR (compressed sparse row) representation.
-    pub csr: Csr<Variable, Factor, Directed, usize>,
+    /// The factor graph's adjacency list representation.
+    pub adjacency: FactorGraphAdjacency,
     /// The indices at which the factor graph's nodes can be found in get_var(/*node_index*/).
     pub node_indices: Vec<NodeIndex<usize>>,
-    /// Map from custom IDs as stated in the parsed file to internal CSR indices.
-    pub custom_to_csr_id_map: HashMap<usize, NodeIndex<usize>>,
+    /// Map from custom IDs as stated in the parsed file to internal indices.
+    ///
+    /// Ordered by ID, so that iterating it gives the same order in every run.
+    pub custom_to_csr_id_map: BTreeMap<VariableId, NodeIndex<usize>>,
     /// The number of nodes which are dynamic, i.e. the number of fixed nodes subtracted of the total number of nodes.
     pub matrix_dim: usize,
+    /// Map from factor IDs to the internal indices of the factor's source and target variables.
+    ///
+    /// Ordered by ID, so that reductions over all factors, e.g. of their χ², give bit-identical results in every run.
+    pub factor_id_map: BTreeMap<FactorId, (NodeIndex<usize>, NodeIndex<usize>)>,
+    next_factor_id: usize,
+    payload_store: Option<PayloadStore>,
+    equalities: BTreeMap<VariableId, VariableId>,
+    fixed_components: BTreeMap<VariableId, Vec<bool>>,
+    unit: Option<units::LengthUnit>,
+    timestamps: BTreeMap<VariableId, f64>,
 }
 
+#[cfg(feature = "std")]
+impl Default for FactorGraph {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(feature = "std")]
 impl FactorGraph {
-    /// Returns the variable at the corresponding internal CSR index.
-    pub fn get_var(&self, csr_index: usize) -> &Variable {
-        self.csr.index(csr_index)
+    /// Returns a new factor graph without any variables or factors.
+    pub fn new() -> Self {
+        FactorGraph {
+            adjacency: AdjacencyList::new(),
+            node_indices: vec![],
+            custom_to_csr_id_map: BTreeMap::new(),
+            matrix_dim: 0,
+            factor_id_map: BTreeMap::new(),
+            next_factor_id: 0,
+            payload_store: None,
+            equalities: BTreeMap::new(),
+            fixed_components: BTreeMap::new(),
+            unit: None,
+            timestamps: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the variable at the corresponding internal index.
+    pub fn get_var(&self, csr_index: NodeIndex<usize>) -> &Variable {
+        &self.adjacency[csr_index]
+    }
+
+    /// Returns the variable with the given ID, if it is part of the factor graph.
+    pub fn get_var_by_id(&self, id: VariableId) -> Option<&Variable> {
+        self.custom_to_csr_id_map.get(&id).map(|i| self.get_var(*i))
+    }
+
+    /// Adds a factor between the variables with the given IDs and returns the factor's ID, which stays valid until
+    /// the factor is removed.
+    ///
+    /// Unary factors (e.g. Position2D) are expected to have the same variable as source and target. Several factors
+    /// may connect the same variables, e.g. a heading and an altitude factor of the same pose. An error is returned
+    /// if a variable is unknown or if the factor type cannot connect variables of their types.
+    pub fn add_factor(
+        &mut self,
+        source: VariableId,
+        target: VariableId,
+        factor_type: FactorType,
+        constraint: Vec<f64>,
+        information_matrix: InformationMatrix,
+    ) -> Result<FactorId, String> {
+        self.add_factor_with_additional_variables(source, target, vec![], factor_type, constraint, information_matrix)
+    }
+
+    /// Adds a factor which also depends on variables besides its source and target, e.g. the switch variable of a
+    /// switchable factor, see [add_factor](#method.add_factor).
+    pub fn add_factor_with_additional_variables(
+        &mut self,
+        source: VariableId,
+        target: VariableId,
+        additional_variables: Vec<VariableId>,
+        factor_type: FactorType,
+        constraint: Vec<f64>,
+        information_matrix: InformationMatrix,
+    ) -> Result<FactorId, String> {
+        let source_index = self.get_csr_index(source)?;
+        let target_index = self.get_csr_index(target)?;
+        let mut var_ids = vec![source];
+        if target != source {
+            var_ids.push(target);
+        }
+        var_ids.extend(&additional_variables);
+        let vars = var_ids
+            .iter()
+            .map(|id| self.get_csr_index(*id).map(|i| self.get_var(i)))
+            .collect::<Result<Vec<&Variable>, String>>()?;
+        if !factor_type.accepts_variables(&vars) {
+            // max-mixture factors are reported by the type of their components
+            let component_type = match &factor_type {
+                FactorType::MaxMixture(mixture) => &*mixture.factor_type,
+                other_type => other_type,
+            };
+            let var_ids: Vec<String> = var_ids.iter().map(|id| id.to_string()).collect();
+            return Err(format!(
+                "A factor of type {:?} cannot connect the variables {}",
+                component_type,
+                var_ids.join(", ")
+            ));
+        }
+        let id = FactorId(self.next_factor_id);
+        let factor = Factor {
+            id,
+            factor_type,
+            constraint,
+            information_matrix,
+            additional_variables,
+        };
+        self.adjacency.add_edge(source_index, target_index, factor);
+        self.next_factor_id += 1;
+        self.factor_id_map.insert(id, (source_index, target_index));
+        Ok(id)
+    }
+
+    /// Adds a user-defined factor between the variables with the IDs it returns, see
+    /// [CustomFactor](factor/trait.CustomFactor.html). The first two variables are the factor's source and target,
+    /// the remaining ones its additional variables.
+    ///
+    /// Returns an error if the factor has no variables or if the dimension of the information matrix does not equal
+    /// the factor's residual dimension.
+    pub fn add_custom_factor<F: CustomFactor + 'static>(
+        &mut self,
+        factor: F,
+        information_matrix: InformationMatrix,
+    ) -> Result<FactorId, String> {
+        let ids = factor.variable_ids();
+        if ids.is_empty() {
+            return Err(format!("The custom factor {} has no variables", factor.name()));
+        }
+        if information_matrix.content.nrows() != factor.residual_dim() {
+            return Err(format!(
+                "The custom factor {} has a residual of dimension {}, but an information matrix of dimension {}",
+                factor.name(),
+                factor.residual_dim(),
+                information_matrix.content.nrows()
+            ));
+        }
+        let target_index = ids.len().min(2) - 1;
+        self.add_factor_with_additional_variables(
+            ids[0],
+            ids[target_index],
+            ids[target_index + 1..].to_vec(),
+            FactorType::UserDefined(Rc::new(factor)),
+            vec![],
+            information_matrix,
+        )
+    }
+
+    /// Adds a variable of a user-defined type with the given ID, which is appended to H unless it is fixed, see
+    /// [CustomVariable](variable/trait.CustomVariable.html).
+    ///
+    /// Returns an error if a variable with the same ID already exists.
+    pub fn add_custom_variable<V: CustomVariable + 'static>(
+        &mut self,
+        id: VariableId,
+        variable: V,
+        fixed: bool,
+    ) -> Result<(), String> {
+        if self.custom_to_csr_id_map.contains_key(&id) {
+            return Err(format!("A variable with ID {} already exists", id));
+        }
+        let fixed_type = if fixed {
+            FixedType::Fixed
+        } else {
+            let tangent_dim = variable.dim();
+            self.matrix_dim += tangent_dim;
+            FixedType::NonFixed(self.matrix_dim - tangent_dim..self.matrix_dim)
+        };
+        let variable = UserDefinedVariable::new(id, Box::new(variable), fixed_type);
+        let index = self.adjacency.add_node(Variable::UserDefined(variable));
+        self.node_indices.push(index);
+        self.custom_to_csr_id_map.insert(id, index);
+        Ok(())
+    }
+
+    /// Returns the factor with the given ID, if it is part of the factor graph.
+    pub fn get_factor(&self, id: FactorId) -> Option<&Factor> {
+        let (source_index, _) = self.factor_id_map.get(&id)?;
+        self.adjacency
+            .edges(*source_index)
+            .map(|edge| edge.weight())
+            .find(|f| f.id == id)
+    }
+
+    // returns the factor with the given ID for changing it in place, if it is part of the factor graph
+    pub(crate) fn get_factor_mut(&mut self, id: FactorId) -> Option<&mut Factor> {
+        let (source_index, _) = self.factor_id_map.get(&id)?;
+        self.adjacency.edge_weight_mut(*source_index, |f| f.id == id)
+    }
+
+    /// Returns the internal indices of all variables the factor with the given ID depends on, i.e. the source, the
+    /// target unless the factor is unary and the additional variables, or None if the factor is not part of the factor
+    /// graph.
+    pub fn get_factor_var_indices(&self, id: FactorId) -> Option<Vec<NodeIndex<usize>>> {
+        let (source_index, target_index) = self.factor_id_map.get(&id)?;
+        let mut indices = vec![*source_index];
+        if target_index != source_index {
+            indices.push(*target_index);
+        }
+        let factor = self.get_factor(id)?;
+        indices.extend(factor.additional_variables.iter().map(|v| self.custom_to_csr_id_map[v]));
+        Some(indices)
+    }
+
+    /// Returns the IDs of all factors connecting the two variables, regardless of their direction.
+    ///
+    /// Passing the same ID twice returns the variable's unary factors.
+    pub fn factors_between(&self, a: VariableId, b: VariableId) -> Vec<FactorId> {
+        let (a_index, b_index) = match (self.custom_to_csr_id_map.get(&a), self.custom_to_csr_id_map.get(&b)) {
+            (Some(a_index), Some(b_index)) => (*a_index, *b_index),
+            _ => return vec![],
+        };
+        let mut ids: Vec<FactorId> = self
+            .adjacency
+            .edges(a_index)
+            .filter(|edge| edge.target() == b_index)
+            .map(|edge| edge.weight().id)
+            .collect();
+        if a_index != b_index {
+            ids.extend(
+                self.adjacency
+                    .edges(b_index)
+                    .filter(|edge| edge.target() == a_index)
+                    .map(|edge| edge.weight().id),
+            );
+        }
+        ids
+    }
+
+    /// Removes the factor with the given ID from the factor graph and returns it.
+    pub fn remove_factor(&mut self, id: FactorId) -> Result<Factor, String> {
+        let (source_index, _) = self
+            .factor_id_map
+            .remove(&id)
+            .ok_or_else(|| format!("Unknown factor ID: {}", id))?;
+        let factor = self.adjacency.remove_edge(source_index, |f| f.id == id).unwrap();
+        let factor = self.materialize_factor(&factor).into_owned();
+        if let Some(store) = &mut self.payload_store {
+            store.remove(id);
+        }
+        Ok(factor)
+    }
+
+    /// Replaces the information matrix of the factor with the given ID, e.g. to reweight a measurement.
+    pub fn set_information_matrix(
+        &mut self,
+        id: FactorId,
+        information_matrix: InformationMatrix,
+    ) -> Result<(), String> {
+        self.restore_factor_payload(id);
+        match self.get_factor(id) {
+            None => return Err(format!("Unknown factor ID: {}", id)),
+            Some(factor) if factor.information_matrix.content.shape() != information_matrix.content.shape() => {
+                return Err(format!(
+                    "Information matrix of factor {} must have the shape {:?}",
+                    id,
+                    factor.information_matrix.content.shape()
+                ))
+            }
+            _ => (),
+        }
+        self.get_factor_mut(id).unwrap().information_matrix = information_matrix;
+        Ok(())
+    }
+
+    /// Replaces the constraint of the factor with the given ID, e.g. to correct a measurement.
+    pub fn set_constraint(&mut self, id: FactorId, constraint: Vec<f64>) -> Result<(), String> {
+        self.restore_factor_payload(id);
+        match self.get_factor(id) {
+            None => return Err(format!("Unknown factor ID: {}", id)),
+            Some(factor) if factor.constraint.len() != constraint.len() => {
+                return Err(format!(
+                    "Constraint of factor {} must have {} values",
+                    id,
+                    factor.constraint.len()
+                ))
+            }
+            _ => (),
+        }
+        self.get_factor_mut(id).unwrap().constraint = constraint;
+        Ok(())
+    }
+
+    /// Fixes the variables with the given IDs at their current estimates, e.g. to only optimize a window of recent
+    /// poses, and recomputes the ranges of the remaining variables in H.
+    ///
+    /// Tied variables follow their representatives, see [add_equality_constraint](#method.add_equality_constraint).
+    ///
+    /// Returns an error without changing any variable if one of the IDs is unknown.
+    pub fn freeze(&mut self, ids: &[VariableId]) -> Result<(), String> {
+        self.set_fixed(ids, true)
+    }
+
+    /// Makes the variables with the given IDs optimizable again, see [freeze](#method.freeze).
+    pub fn unfreeze(&mut self, ids: &[VariableId]) -> Result<(), String> {
+        self.set_fixed(ids, false)
+    }
+
+    /// Fixes the components of the variable with the given ID whose entries in the mask are true, e.g. the rotation
+    /// of a GPS-anchored 2D pose, while the other components are optimized. An empty mask unfixes all components.
+    ///
+    /// The mask refers to the components of the variable's corrections, see
+    /// [parameterization](variable/parameterization/index.html), e.g. (x, y, rotation) for 2D vehicle poses. The
+    /// variable keeps its range in H, but the rows and columns of the fixed components are replaced by the ones of
+    /// the identity and their entries of b by zero, so that the Gauss-Newton step does not change them. Marginal
+    /// covariances of fixed components are zero. Like equality constraints, fixed components are not part of models.
+    ///
+    /// Returns an error without changing the variable if the ID is unknown or if the mask neither is empty nor has
+    /// an entry for each component.
+    pub fn fix_components(&mut self, id: VariableId, mask: Vec<bool>) -> Result<(), String> {
+        let tangent_dim = self.get_var(self.get_csr_index(id)?).get_parameterization().tangent_dim();
+        if mask.is_empty() {
+            self.fixed_components.remove(&id);
+        } else if mask.len() == tangent_dim {
+            self.fixed_components.insert(id, mask);
+        } else {
+            return Err(format!(
+                "The mask of variable {} has {} entries, but the variable has {} components",
+                id,
+                mask.len(),
+                tangent_dim
+            ));
+        }
+        Ok(())
+    }
+
+    /// Returns the mask of the fixed components of the variable with the given ID, if some of them are fixed, see
+    /// [fix_components](#method.fix_components).
+    pub fn fixed_components(&self, id: VariableId) -> Option<&[bool]> {
+        self.fixed_components.get(&id).map(Vec::as_slice)
+    }
+
+    // returns the rows in H of the fixed components of the non-fixed variables
+    pub(crate) fn get_fixed_rows(&self) -> Vec<usize> {
+        let mut rows: Vec<usize> = self
+            .fixed_components
+            .iter()
+            .filter_map(|(id, mask)| match self.get_var_by_id(*id)?.get_fixed_type() {
+                FixedType::NonFixed(range) => Some(range.clone().zip(mask).filter(|(_, is_fixed)| **is_fixed)),
+                FixedType::Fixed => None,
+            })
+            .flatten()
+            .map(|(row, _)| row)
+            .collect();
+        rows.sort_unstable();
+        rows.dedup();
+        rows
+    }
+
+    // assigns consecutive ranges in H to all non-fixed variables in the order of insertion, except for tied
+    // variables, which share the range of their representative
+    fn set_fixed(&mut self, ids: &[VariableId], is_fixed: bool) -> Result<(), String> {
+        let changed = ids
+            .iter()
+            .map(|id| self.get_csr_index(*id))
+            .collect::<Result<BTreeSet<NodeIndex<usize>>, String>>()?;
+        self.matrix_dim = 0;
+        for index in self.node_indices.clone() {
+            let var = self.get_var(index);
+            if self.equalities.contains_key(&var.get_id()) {
+                continue;
+            }
+            let fixed_type = if changed.contains(&index) && is_fixed
+                || !changed.contains(&index) && var.get_fixed_type() == &FixedType::Fixed
+            {
+                FixedType::Fixed
+            } else {
+                let tangent_dim = var.get_parameterization().tangent_dim();
+                self.matrix_dim += tangent_dim;
+                FixedType::NonFixed(self.matrix_dim - tangent_dim..self.matrix_dim)
+            };
+            self.adjacency.node_weight_mut(index).unwrap().set_fixed_type(fixed_type);
+        }
+        for (tied, representative) in self.equalities.clone() {
+            let fixed_type = match self.get_var_by_id(representative).unwrap().get_fixed_type() {
+                FixedType::NonFixed(range) => FixedType::NonFixed(range.clone()),
+                FixedType::Fixed => FixedType::Fixed,
+            };
+            let index = self.custom_to_csr_id_map[&tied];
+            self.adjacency.node_weight_mut(index).unwrap().set_fixed_type(fixed_type);
+        }
+        Ok(())
+    }
+
+    fn get_csr_index(&self, id: VariableId) -> Result<NodeIndex<usize>, String> {
+        self.custom_to_csr_id_map
+            .get(&id)
+            .copied()
+            .ok_or_else(|| format!("Unknown variable ID: {}", id))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimizer::optimize;
+    use crate::parser::g2o::G2oParser;
+    use crate::parser::json::JsonParser;
+    use crate::parser::Parser;
+
+    fn get_ranges(graph: &FactorGraph) -> Vec<Option<std::ops::Range<usize>>> {
+        graph
+            .node_indices
+            .iter()
+            .map(|i| match graph.get_var(*i).get_fixed_type() {
+                FixedType::NonFixed(range) => Some(range.clone()),
+                FixedType::Fixed => None,
+            })
+            .collect()
+    }
+
+    fn get_2d_graph() -> FactorGraph {
+        JsonParser::parse_file("data_files/full_demos/all_2d_types.json").unwrap()
+    }
+
+    // rotation in 2D stored as the unit vector [cos, sin], which is corrected by an angle
+    #[derive(Clone)]
+    struct Direction([f64; 2]);
+
+    impl CustomVariable for Direction {
+        fn name(&self) -> &str {
+            "Direction2D"
+        }
+
+        fn dim(&self) -> usize {
+            1
+        }
+
+        fn get(&self) -> Vec<f64> {
+            self.0.to_vec()
+        }
+
+        fn set(&mut self, content: Vec<f64>) {
+            self.0 = [content[0], content[1]];
+        }
+
+        fn retract(&self, content: &[f64], correction: &[f64]) -> Vec<f64> {
+            let (sin, cos) = correction[0].sin_cos();
+            vec![cos * content[0] - sin * content[1], sin * content[0] + cos * content[1]]
+        }
+
+        fn clone_box(&self) -> Box<dyn CustomVariable> {
+            Box::new(self.clone())
+        }
+    }
+
+    // returns a fixed direction at angle 0 and a direction at the given angle, which are measured 0.5 apart
+    fn get_direction_graph(angle: f64) -> FactorGraph {
+        use crate::factor_graph::factor::CustomResidual;
+        use crate::optimizer::autodiff::Dual;
+
+        let mut graph = FactorGraph::new();
+        graph.add_custom_variable(VariableId(0), Direction([1.0, 0.0]), true).unwrap();
+        graph
+            .add_custom_variable(VariableId(1), Direction([angle.cos(), angle.sin()]), false)
+            .unwrap();
+        // the sine of the difference between the relative and the measured angle
+        let residual = CustomResidual::new("DirectionDifference2D", |contents: &[Vec<Dual>], constraint: &[f64]| {
+            let (a, b) = (&contents[0], &contents[1]);
+            let (cos, sin) = (a[0] * b[0] + a[1] * b[1], a[0] * b[1] - a[1] * b[0]);
+            vec![sin * constraint[0].cos() - cos * constraint[0].sin()]
+        });
+        graph
+            .add_factor(
+                VariableId(0),
+                VariableId(1),
+                FactorType::Custom(residual),
+                vec![0.5],
+                vec![1.0].into(),
+            )
+            .unwrap();
+        graph
+    }
+
+    #[test]
+    fn test_add_custom_variable() {
+        let mut graph = get_direction_graph(1.0);
+        assert_eq!(
+            graph.add_custom_variable(VariableId(1), Direction([1.0, 0.0]), false),
+            Err(String::from("A variable with ID 1 already exists"))
+        );
+        assert_eq!(get_ranges(&graph), vec![None, Some(0..1)]);
+        assert_eq!(
+            crate::parser::model::FactorGraphModel::from(&graph).vertices[1].vertex_type,
+            "Direction2D"
+        );
+        optimize(&graph, 10);
+        let direction = graph.get_var_by_id(VariableId(1)).unwrap().get_content();
+        assert!((direction[1].atan2(direction[0]) - 0.5).abs() < 1e-6);
+        assert!((direction[0].hypot(direction[1]) - 1.0).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_custom_variables_are_copied() {
+        let graph = get_direction_graph(1.0);
+        let copy = graph.sample(1.0, 0).unwrap();
+        optimize(&copy, 10);
+        let content = |graph: &FactorGraph| graph.get_var_by_id(VariableId(1)).unwrap().get_content();
+        assert_eq!(content(&graph), vec![1.0f64.cos(), 1.0f64.sin()]);
+        assert!((content(&copy)[1].atan2(content(&copy)[0]) - 0.5).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_add_and_lookup_factor() {
+        let mut graph = get_2d_graph();
+        let id = graph
+            .add_factor(
+                VariableId(1),
+                VariableId(2),
+                FactorType::Observation2D,
+                vec![1.0, 0.5],
+                vec![1.0, 0.0, 0.0, 1.0].into(),
+            )
+            .unwrap();
+        assert_eq!(id, FactorId(3));
+        assert_eq!(graph.factors_between(VariableId(2), VariableId(1)), vec![id]);
+        assert_eq!(graph.factors_between(VariableId(1), VariableId(1)), vec![FactorId(2)]);
+        assert_eq!(graph.get_factor(id).unwrap().constraint, vec![1.0, 0.5]);
+
+        let parallel_id = graph
+            .add_factor(
+                VariableId(1),
+                VariableId(2),
+                FactorType::Observation2D,
+                vec![1.5, 0.5],
+                vec![1.0, 0.0, 0.0, 1.0].into(),
+            )
+            .unwrap();
+        assert_eq!(
+            graph.factors_between(VariableId(1), VariableId(2)),
+            vec![id, parallel_id]
+        );
+        graph
+            .set_information_matrix(parallel_id, vec![2.0, 0.0, 0.0, 2.0].into())
+            .unwrap();
+        assert_eq!(graph.get_factor(id).unwrap().information_matrix.content[(0, 0)], 1.0);
+        graph.remove_factor(id).unwrap();
+        assert_eq!(graph.factors_between(VariableId(1), VariableId(2)), vec![parallel_id]);
+        assert_eq!(graph.get_factor(parallel_id).unwrap().constraint, vec![1.5, 0.5]);
+    }
+
+    #[test]
+    fn test_remove_and_reweight_factor() {
+        let mut graph = get_2d_graph();
+        let odometry_id = graph.factors_between(VariableId(0), VariableId(1))[0];
+        let observation_id = graph.factors_between(VariableId(0), VariableId(2))[0];
+
+        graph
+            .set_information_matrix(observation_id, vec![5.0, 0.0, 0.0, 5.0].into())
+            .unwrap();
+        assert_eq!(
+            graph.get_factor(observation_id).unwrap().information_matrix.content[(1, 1)],
+            5.0
+        );
+        assert!(graph.set_information_matrix(observation_id, vec![1.0].into()).is_err());
+        graph.set_constraint(observation_id, vec![2.0, 1.0]).unwrap();
+        assert_eq!(graph.get_factor(observation_id).unwrap().constraint, vec![2.0, 1.0]);
+        assert!(graph.set_constraint(observation_id, vec![1.0]).is_err());
+
+        let removed = graph.remove_factor(odometry_id).unwrap();
+        assert_eq!(removed.id, odometry_id);
+        assert!(graph.get_factor(odometry_id).is_none());
+        assert!(graph.factors_between(VariableId(0), VariableId(1)).is_empty());
+        assert_eq!(graph.get_factor(observation_id).unwrap().id, observation_id);
+        assert!(graph.remove_factor(odometry_id).is_err());
+    }
+
+    #[test]
+    fn test_freeze_all_but_recent_poses() {
+        let mut graph = G2oParser::parse_file("data_files/optimizer_tests/full2d_0.g2o").unwrap();
+        let (original_ranges, original_dim) = (get_ranges(&graph), graph.matrix_dim);
+        let vehicles: Vec<VariableId> = graph
+            .node_indices
+            .iter()
+            .map(|i| graph.get_var(*i))
+            .filter(|var| matches!(var, Variable::Vehicle2D(_)))
+            .map(|var| var.get_id())
+            .collect();
+        let window = &vehicles[vehicles.len() - 5..];
+        let frozen: Vec<VariableId> = graph
+            .custom_to_csr_id_map
+            .keys()
+            .filter(|id| !window.contains(id))
+            .copied()
+            .collect();
+
+        assert!(graph.freeze(&[VariableId(0), VariableId(1000)]).is_err());
+        assert_eq!(get_ranges(&graph), original_ranges);
+        graph.freeze(&frozen).unwrap();
+        assert_eq!(graph.matrix_dim, 15);
+        let ranges: Vec<std::ops::Range<usize>> = get_ranges(&graph).into_iter().flatten().collect();
+        assert_eq!(ranges, (0..5).map(|i| 3 * i..3 * i + 3).collect::<Vec<_>>());
+
+        let frozen_contents: Vec<Vec<f64>> = frozen
+            .iter()
+            .map(|id| graph.get_var_by_id(*id).unwrap().get_content())
+            .collect();
+        let window_content = graph.get_var_by_id(window[0]).unwrap().get_content();
+        optimize(&graph, 3);
+        frozen
+            .iter()
+            .zip(&frozen_contents)
+            .for_each(|(id, content)| assert_eq!(&graph.get_var_by_id(*id).unwrap().get_content(), content));
+        assert_ne!(graph.get_var_by_id(window[0]).unwrap().get_content(), window_content);
+
+        // the originally fixed variable stays fixed
+        graph.unfreeze(&frozen[1..]).unwrap();
+        assert_eq!(get_ranges(&graph), original_ranges);
+        assert_eq!(graph.matrix_dim, original_dim);
+    }
+
+    #[test]
+    fn test_fix_rotation_of_gps_anchored_pose() {
+        let information = "1 0 0 1 0 1";
+        let g2o_string = [
+            "VERTEX_SE2 0 0 0 0",
+            "VERTEX_SE2 1 0.9 0.3 0.2",
+            "FIX 0",
+            &format!("EDGE_SE2 0 1 1 0 0 {}", information),
+            &format!("EDGE_PRIOR_SE2 1 1.4 0.2 0 {}", information),
+        ]
+        .join("\n");
+        let mut graph: FactorGraph = G2oParser::parse_string_to_model(&g2o_string).unwrap().into();
+        assert!(graph.fix_components(VariableId(1), vec![true]).is_err());
+        assert!(graph.fix_components(VariableId(5), vec![false, false, true]).is_err());
+        graph.fix_components(VariableId(1), vec![false, false, true]).unwrap();
+        assert_eq!(graph.fixed_components(VariableId(1)), Some(&[false, false, true][..]));
+        assert_eq!(graph.matrix_dim, 3);
+
+        optimize(&graph, 5);
+        let pose = graph.get_var_by_id(VariableId(1)).unwrap().get_content();
+        assert_eq!(pose[2], 0.2);
+        // the translation is the mean of the odometry's and the prior's
+        assert!((pose[0] - 1.2).abs() < 1e-9 && (pose[1] - 0.1).abs() < 1e-9, "{:?}", pose);
+        let covariance = &graph.marginal_covariances().unwrap()[&VariableId(1)];
+        assert_eq!(covariance.row(2).iter().chain(covariance.column(2).iter()).sum::<f64>(), 0.0);
+        assert!(covariance[(0, 0)] > 0.0);
+
+        graph.fix_components(VariableId(1), vec![]).unwrap();
+        assert_eq!(graph.fixed_components(VariableId(1)), None);
+        optimize(&graph, 5);
+        assert_ne!(graph.get_var_by_id(VariableId(1)).unwrap().get_content()[2], 0.2);
     }
 }
@@ -0,0 +1,247 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Disk-backed storage of factor payloads, i.e. constraints and information matrices, for factor graphs which do
+//! not fit into memory otherwise.
+//!
+//! The payloads are written to a file as little-endian f64 values, which is memory-mapped afterwards, so the
+//! operating system only keeps the recently used parts in memory.
+
+use crate::factor_graph::factor::{Factor, FactorId, InformationMatrix};
+use crate::factor_graph::FactorGraph;
+use memmap2::Mmap;
+use nalgebra::DMatrix;
+use std::borrow::Cow;
+use std::collections::HashMap;
+use std::convert::TryInto;
+use std::fs::OpenOptions;
+use std::io::{BufWriter, Write};
+use std::mem;
+use std::path::{Path, PathBuf};
+
+const F64_SIZE: usize = mem::size_of::<f64>();
+
+/// Memory-mapped file containing the payloads of offloaded factors.
+#[derive(Debug)]
+pub(crate) struct PayloadStore {
+    path: PathBuf,
+    // None if the file is empty, since empty files cannot be mapped
+    mmap: Option<Mmap>,
+    locations: HashMap<FactorId, PayloadLocation>,
+}
+
+// the position of a factor's payload in the file, in f64 values
+#[derive(Debug, Clone, Copy)]
+struct PayloadLocation {
+    offset: usize,
+    constraint_len: usize,
+    information_dim: usize,
+}
+
+impl PayloadStore {
+    // returns whether the factor's payload is stored in the file
+    fn contains(&self, id: FactorId) -> bool {
+        self.locations.contains_key(&id)
+    }
+
+    // forgets the factor's payload, e.g. after the factor was removed
+    pub(crate) fn remove(&mut self, id: FactorId) {
+        self.locations.remove(&id);
+    }
+
+    // returns the factor's payload, which has to be stored in the file
+    fn read(&self, id: FactorId) -> (Vec<f64>, InformationMatrix) {
+        let location = self.locations[&id];
+        let information_len = location.information_dim * location.information_dim;
+        let values: Vec<f64> = self.mmap.as_ref().unwrap()[location.offset * F64_SIZE..]
+            .chunks_exact(F64_SIZE)
+            .take(location.constraint_len + information_len)
+            .map(|bytes| f64::from_le_bytes(bytes.try_into().unwrap()))
+            .collect();
+        let information_matrix = InformationMatrix {
+            content: DMatrix::from_column_slice(
+                location.information_dim,
+                location.information_dim,
+                &values[location.constraint_len..],
+            ),
+        };
+        (values[..location.constraint_len].to_vec(), information_matrix)
+    }
+}
+
+impl FactorGraph {
+    /// Moves the constraints and information matrices of all factors into a memory-mapped file at the given path,
+    /// which is created or truncated.
+    ///
+    /// Afterwards, the factors only keep empty payloads in memory, which are materialized from the file whenever a
+    /// factor is linearized, converted or removed, see [materialize_factor](#method.materialize_factor). Factors
+    /// added later stay in memory until the payloads are offloaded again, which requires a different path. The
+    /// alternative components of max-mixture factors always stay in memory.
+    ///
+    /// The file has to stay unchanged as long as the factor graph exists.
+    pub fn offload_factor_payloads(&mut self, path: &Path) -> Result<(), String> {
+        if let Some(store) = &self.payload_store {
+            if store.path == path {
+                return Err(format!("The factor payloads are already stored at {}", path.display()));
+            }
+        }
+        // the file is also opened for reading, which mapping it requires
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create(true)
+            .truncate(true)
+            .open(path)
+            .map_err(|e| format!("Could not create {}: {}", path.display(), e))?;
+        let mut writer = BufWriter::new(&file);
+        let mut locations = HashMap::new();
+        let mut offset = 0;
+        for edge in self.node_indices.iter().flat_map(|i| self.adjacency.edges(*i)) {
+            let factor = self.materialize_factor(edge.weight());
+            let information = &factor.information_matrix.content;
+            for value in factor.constraint.iter().chain(information.iter()) {
+                writer
+                    .write_all(&value.to_le_bytes())
+                    .map_err(|e| format!("Could not write {}: {}", path.display(), e))?;
+            }
+            locations.insert(
+                factor.id,
+                PayloadLocation {
+                    offset,
+                    constraint_len: factor.constraint.len(),
+                    information_dim: information.nrows(),
+                },
+            );
+            offset += factor.constraint.len() + information.len();
+        }
+        writer
+            .flush()
+            .map_err(|e| format!("Could not write {}: {}", path.display(), e))?;
+        drop(writer);
+        let mmap = if offset == 0 {
+            None
+        } else {
+            // safe as long as the file is not modified, which is documented above
+            let mmap = unsafe { Mmap::map(&file) };
+            Some(mmap.map_err(|e| format!("Could not map {}: {}", path.display(), e))?)
+        };
+
+        for id in self.factor_id_map.keys().copied().collect::<Vec<_>>() {
+            let factor = self.get_factor_mut(id).unwrap();
+            factor.constraint = vec![];
+            factor.information_matrix = InformationMatrix {
+                content: DMatrix::zeros(0, 0),
+            };
+        }
+        self.payload_store = Some(PayloadStore {
+            path: path.to_path_buf(),
+            mmap,
+            locations,
+        });
+        Ok(())
+    }
+
+    /// Returns the factor with its constraint and information matrix, which are read from the file if they were
+    /// offloaded, see [offload_factor_payloads](#method.offload_factor_payloads).
+    pub fn materialize_factor<'a>(&self, factor: &'a Factor) -> Cow<'a, Factor> {
+        match &self.payload_store {
+            Some(store) if store.contains(factor.id) => {
+                let (constraint, information_matrix) = store.read(factor.id);
+                let mut factor = factor.clone();
+                factor.constraint = constraint;
+                factor.information_matrix = information_matrix;
+                Cow::Owned(factor)
+            }
+            _ => Cow::Borrowed(factor),
+        }
+    }
+
+    // moves the factor's payload back into memory, e.g. before it is modified
+    pub(crate) fn restore_factor_payload(&mut self, id: FactorId) {
+        let (constraint, information_matrix) = match &mut self.payload_store {
+            Some(store) if store.contains(id) => {
+                let payload = store.read(id);
+                store.remove(id);
+                payload
+            }
+            _ => return,
+        };
+        let factor = self.get_factor_mut(id).unwrap();
+        factor.constraint = constraint;
+        factor.information_matrix = information_matrix;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::factor_graph::factor::FactorType;
+    use crate::factor_graph::variable::VariableId;
+    use crate::optimizer::optimize;
+    use crate::parser::g2o::G2oParser;
+    use crate::parser::model::FactorGraphModel;
+    use crate::parser::Parser;
+    use std::env;
+    use std::fs;
+
+    fn get_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("gs-rs-{}-{}.bin", name, std::process::id()))
+    }
+
+    #[test]
+    fn test_offloaded_factor_graph_optimizes_like_in_memory_one() {
+        let file_name = "data_files/optimizer_tests/full2d_0.g2o";
+        let in_memory = G2oParser::parse_file(file_name).unwrap();
+        let mut offloaded = G2oParser::parse_file(file_name).unwrap();
+        let path = get_path("optimize");
+        offloaded.offload_factor_payloads(&path).unwrap();
+        assert!(offloaded.get_factor(FactorId(0)).unwrap().constraint.is_empty());
+        assert_eq!(FactorGraphModel::from(&offloaded), FactorGraphModel::from(&in_memory));
+
+        optimize(&in_memory, 3);
+        optimize(&offloaded, 3);
+        assert_eq!(FactorGraphModel::from(&offloaded), FactorGraphModel::from(&in_memory));
+        assert!(offloaded.offload_factor_payloads(&path).is_err());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_edit_offloaded_factors() {
+        let mut factor_graph: FactorGraph = G2oParser::parse_string_to_model(
+            &[
+                "VERTEX_SE2 0 0 0 0",
+                "VERTEX_SE2 1 1 0 0",
+                "EDGE_SE2 0 1 1 0 0 1 0 0 1 0 1",
+                "EDGE_PRIOR_SE2 0 0 0 0 1 0 0 1 0 1",
+            ]
+            .join("\n"),
+        )
+        .unwrap()
+        .into();
+        let path = get_path("edit");
+        factor_graph.offload_factor_payloads(&path).unwrap();
+
+        let information_matrix = InformationMatrix::from(vec![2.0, 0.0, 0.0, 0.0, 2.0, 0.0, 0.0, 0.0, 2.0]);
+        factor_graph
+            .set_information_matrix(FactorId(0), information_matrix.clone())
+            .unwrap();
+        let factor = factor_graph.get_factor(FactorId(0)).unwrap();
+        assert_eq!(factor.constraint, vec![1.0, 0.0, 0.0]);
+        assert_eq!(factor.information_matrix, information_matrix);
+
+        let removed = factor_graph.remove_factor(FactorId(1)).unwrap();
+        assert_eq!(removed.factor_type, FactorType::Position2D);
+        assert_eq!(removed.constraint, vec![0.0, 0.0, 0.0]);
+        assert!(factor_graph.get_var_by_id(VariableId(1)).is_some());
+        fs::remove_file(path).unwrap();
+    }
+}
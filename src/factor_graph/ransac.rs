@@ -0,0 +1,413 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Robust estimation of the transform between two frames from corresponding points or poses, e.g. for the alignment
+//! of maps or the verification of loop closures.
+//!
+//! Wrong correspondences are rejected with RANSAC (random sample consensus): A transform is estimated from each of
+//! several random minimal sets of correspondences, and the one which agrees with the most correspondences is
+//! estimated again from all of them.
+
+use crate::factor_graph::sampling::SplitMix64;
+use nalgebra::{
+    DMatrix, DVector, Isometry2, Isometry3, Matrix3, Rotation3, Translation2, Translation3, UnitComplex, UnitQuaternion,
+};
+
+// a rotation matrix and a translation vector in 2D or 3D
+type Pose = (DMatrix<f64>, DVector<f64>);
+
+/// Configuration of the random sampling and of the inlier test.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RansacConfig {
+    /// The maximum distance between a transformed source position and the corresponding target position, up to
+    /// which the correspondence is an inlier.
+    pub inlier_threshold: f64,
+    /// The maximum angle between a transformed source orientation and the corresponding target orientation, up to
+    /// which a pose correspondence is an inlier.
+    pub rotation_threshold: f64,
+    /// The number of random minimal sets of correspondences from which a transform is estimated.
+    pub iterations: usize,
+    /// The seed of the random sampling, which makes the estimation reproducible.
+    pub seed: u64,
+}
+
+impl Default for RansacConfig {
+    fn default() -> Self {
+        RansacConfig {
+            inlier_threshold: 0.5,
+            rotation_threshold: 0.1,
+            iterations: 100,
+            seed: 0,
+        }
+    }
+}
+
+/// Similarity transform x ↦ scale * rotation * x + translation in 2D or 3D, whose scale is 1 if it is rigid.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SimilarityTransform {
+    /// The rotation matrix.
+    pub rotation: DMatrix<f64>,
+    /// The translation, which is applied after the rotation and scaling.
+    pub translation: DVector<f64>,
+    /// The scale.
+    pub scale: f64,
+}
+
+impl SimilarityTransform {
+    /// Returns the transformed point.
+    pub fn transform_point(&self, point: &DVector<f64>) -> DVector<f64> {
+        &self.rotation * point * self.scale + &self.translation
+    }
+}
+
+/// Returns the transform which maps the source points onto the corresponding target points with the least squared
+/// error, see Umeyama (1991), "Least-squares estimation of transformation parameters between two point patterns".
+/// Without scale, the transform is rigid.
+///
+/// Expects at least one point and as many source as target points of the same dimension.
+pub fn estimate_transform(source: &[DVector<f64>], target: &[DVector<f64>], with_scale: bool) -> SimilarityTransform {
+    umeyama(
+        &source.iter().collect::<Vec<_>>(),
+        &target.iter().collect::<Vec<_>>(),
+        with_scale,
+    )
+}
+
+/// Returns the transform between corresponding points in 2D or 3D which is estimated with RANSAC, and the indices of
+/// the correspondences which agree with it.
+///
+/// Each transform is estimated by [estimate_transform](fn.estimate_transform.html) from as many correspondences as
+/// the points have dimensions, i.e. it is in SE(2) or SE(3), or in Sim(2) or Sim(3) with scale.
+///
+/// Returns an error if the numbers of source and target points differ, or if there are fewer correspondences or
+/// inliers than the points have dimensions.
+pub fn ransac_points(
+    source: &[DVector<f64>],
+    target: &[DVector<f64>],
+    with_scale: bool,
+    config: &RansacConfig,
+) -> Result<(SimilarityTransform, Vec<usize>), String> {
+    check_counts(source.len(), target.len())?;
+    let sample_size = source.first().map_or(2, |p| p.len());
+    if source.len() < sample_size {
+        return Err(format!("At least {} correspondences are required", sample_size));
+    }
+    ransac(
+        source.len(),
+        sample_size,
+        config,
+        |indices| {
+            umeyama(
+                &indices.iter().map(|i| &source[*i]).collect::<Vec<_>>(),
+                &indices.iter().map(|i| &target[*i]).collect::<Vec<_>>(),
+                with_scale,
+            )
+        },
+        |transform, i| (transform.transform_point(&source[i]) - &target[i]).norm() <= config.inlier_threshold,
+    )
+    .map_err(|inlier_count| get_inlier_error(inlier_count, sample_size))
+}
+
+/// Returns the transform between corresponding poses in 2D which is estimated with RANSAC, and the indices of the
+/// correspondences which agree with it, i.e. whose transformed source pose is within both thresholds of the target
+/// pose.
+///
+/// Each transform is estimated from a single correspondence and finally from all inliers, as the rigid transform
+/// which minimizes the squared differences of the rotation matrices and then of the positions.
+///
+/// Returns an error if the numbers of source and target poses differ or if there are no correspondences.
+pub fn ransac_poses_2d(
+    source: &[Isometry2<f64>],
+    target: &[Isometry2<f64>],
+    config: &RansacConfig,
+) -> Result<(Isometry2<f64>, Vec<usize>), String> {
+    let to_pose = |pose: &Isometry2<f64>| {
+        (
+            DMatrix::from_column_slice(2, 2, pose.rotation.to_rotation_matrix().matrix().as_slice()),
+            DVector::from_column_slice(pose.translation.vector.as_slice()),
+        )
+    };
+    let (transform, inliers) = ransac_poses(
+        &source.iter().map(to_pose).collect::<Vec<_>>(),
+        &target.iter().map(to_pose).collect::<Vec<_>>(),
+        config,
+    )?;
+    let (rotation, translation) = transform;
+    Ok((
+        Isometry2::from_parts(
+            Translation2::new(translation[0], translation[1]),
+            UnitComplex::new(rotation[(1, 0)].atan2(rotation[(0, 0)])),
+        ),
+        inliers,
+    ))
+}
+
+/// Returns the transform between corresponding poses in 3D which is estimated with RANSAC, see
+/// [ransac_poses_2d](fn.ransac_poses_2d.html).
+pub fn ransac_poses_3d(
+    source: &[Isometry3<f64>],
+    target: &[Isometry3<f64>],
+    config: &RansacConfig,
+) -> Result<(Isometry3<f64>, Vec<usize>), String> {
+    let to_pose = |pose: &Isometry3<f64>| {
+        (
+            DMatrix::from_column_slice(3, 3, pose.rotation.to_rotation_matrix().matrix().as_slice()),
+            DVector::from_column_slice(pose.translation.vector.as_slice()),
+        )
+    };
+    let (transform, inliers) = ransac_poses(
+        &source.iter().map(to_pose).collect::<Vec<_>>(),
+        &target.iter().map(to_pose).collect::<Vec<_>>(),
+        config,
+    )?;
+    let (rotation, translation) = transform;
+    let rotation = Rotation3::from_matrix_unchecked(Matrix3::from_column_slice(rotation.as_slice()));
+    Ok((
+        Isometry3::from_parts(
+            Translation3::new(translation[0], translation[1], translation[2]),
+            UnitQuaternion::from_rotation_matrix(&rotation),
+        ),
+        inliers,
+    ))
+}
+
+fn ransac_poses(source: &[Pose], target: &[Pose], config: &RansacConfig) -> Result<(Pose, Vec<usize>), String> {
+    check_counts(source.len(), target.len())?;
+    if source.is_empty() {
+        return Err(String::from("At least 1 correspondence is required"));
+    }
+    ransac(
+        source.len(),
+        1,
+        config,
+        |indices| {
+            let (rotation, _) = project_to_rotation(
+                indices
+                    .iter()
+                    .fold(DMatrix::zeros(source[0].0.nrows(), source[0].0.ncols()), |sum, i| {
+                        sum + &target[*i].0 * source[*i].0.transpose()
+                    }),
+            );
+            let translation = indices.iter().fold(DVector::zeros(source[0].1.len()), |sum, i| {
+                sum + &target[*i].1 - &rotation * &source[*i].1
+            }) / indices.len() as f64;
+            (rotation, translation)
+        },
+        |(rotation, translation), i| {
+            let (source_rotation, source_position) = &source[i];
+            let (target_rotation, target_position) = &target[i];
+            let position_error = (rotation * source_position + translation - target_position).norm();
+            // the trace of a rotation by the angle α is 2cos(α) in 2D and 1 + 2cos(α) in 3D
+            let dim = rotation.nrows() as f64;
+            let cos = (((rotation * source_rotation).transpose() * target_rotation).trace() - dim + 2.0) / 2.0;
+            position_error <= config.inlier_threshold && cos.min(1.0).acos() <= config.rotation_threshold
+        },
+    )
+    .map_err(|inlier_count| get_inlier_error(inlier_count, 1))
+}
+
+// returns the transform estimated from the largest set of inliers and the indices of its inliers, or the size of the
+// largest set if it is smaller than the sample size
+fn ransac<T, E, I>(
+    count: usize,
+    sample_size: usize,
+    config: &RansacConfig,
+    estimate: E,
+    is_inlier: I,
+) -> Result<(T, Vec<usize>), usize>
+where
+    E: Fn(&[usize]) -> T,
+    I: Fn(&T, usize) -> bool,
+{
+    let get_inliers = |transform: &T| -> Vec<usize> { (0..count).filter(|i| is_inlier(transform, *i)).collect() };
+    let mut rng = SplitMix64(config.seed);
+    let mut best_inliers: Vec<usize> = vec![];
+    for _i in 0..config.iterations {
+        let mut sample: Vec<usize> = vec![];
+        while sample.len() < sample_size {
+            let index = rng.below(count);
+            if !sample.contains(&index) {
+                sample.push(index);
+            }
+        }
+        let inliers = get_inliers(&estimate(&sample));
+        if inliers.len() > best_inliers.len() {
+            best_inliers = inliers;
+        }
+    }
+    if best_inliers.len() < sample_size {
+        return Err(best_inliers.len());
+    }
+    let transform = estimate(&best_inliers);
+    let inliers = get_inliers(&transform);
+    Ok((transform, inliers))
+}
+
+fn umeyama(source: &[&DVector<f64>], target: &[&DVector<f64>], with_scale: bool) -> SimilarityTransform {
+    let dim = source[0].len();
+    let count = source.len() as f64;
+    let source_mean = source.iter().fold(DVector::zeros(dim), |sum, p| sum + *p) / count;
+    let target_mean = target.iter().fold(DVector::zeros(dim), |sum, p| sum + *p) / count;
+    let covariance = source.iter().zip(target).fold(DMatrix::zeros(dim, dim), |sum, (s, t)| {
+        sum + (*t - &target_mean) * (*s - &source_mean).transpose()
+    }) / count;
+    let (rotation, singular_value_sum) = project_to_rotation(covariance);
+    let scale = if with_scale {
+        let source_variance = source.iter().map(|s| (*s - &source_mean).norm_squared()).sum::<f64>() / count;
+        singular_value_sum / source_variance
+    } else {
+        1.0
+    };
+    let translation = &target_mean - &rotation * &source_mean * scale;
+    SimilarityTransform {
+        rotation,
+        translation,
+        scale,
+    }
+}
+
+// returns the rotation matrix which is closest to the given matrix in the Frobenius norm and the sum of the matrix's
+// singular values, whose sign is flipped for the smallest one if a reflection is prevented
+fn project_to_rotation(matrix: DMatrix<f64>) -> (DMatrix<f64>, f64) {
+    let dim = matrix.nrows();
+    let svd = matrix.svd(true, true);
+    let (u, v_t) = (svd.u.unwrap(), svd.v_t.unwrap());
+    let mut signs = DVector::from_element(dim, 1.0);
+    if u.determinant() * v_t.determinant() < 0.0 {
+        signs[dim - 1] = -1.0;
+    }
+    (
+        &u * DMatrix::from_diagonal(&signs) * &v_t,
+        svd.singular_values.dot(&signs),
+    )
+}
+
+fn check_counts(source_count: usize, target_count: usize) -> Result<(), String> {
+    if source_count != target_count {
+        return Err(format!(
+            "There are {} source and {} target values, but they have to correspond",
+            source_count, target_count
+        ));
+    }
+    Ok(())
+}
+
+fn get_inlier_error(inlier_count: usize, required_count: usize) -> String {
+    format!(
+        "Only {} correspondences agree with the best transform, but {} are required",
+        inlier_count, required_count
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+    use nalgebra::{Point3, Vector2, Vector3};
+
+    fn get_points_3d() -> Vec<DVector<f64>> {
+        vec![
+            DVector::from_vec(vec![0.0, 0.0, 0.0]),
+            DVector::from_vec(vec![1.0, 0.0, 0.0]),
+            DVector::from_vec(vec![0.0, 2.0, 0.0]),
+            DVector::from_vec(vec![0.0, 0.0, 3.0]),
+            DVector::from_vec(vec![1.0, 1.0, 1.0]),
+            DVector::from_vec(vec![-2.0, 1.0, 0.5]),
+        ]
+    }
+
+    #[test]
+    fn test_umeyama_3d() {
+        let transform = Isometry3::new(Vector3::new(1.0, -2.0, 3.0), Vector3::new(0.3, -0.2, 0.5));
+        let source: Vec<DVector<f64>> = [(0.0, 0.0, 0.0), (1.0, 0.0, 0.0), (0.0, 2.0, 0.0), (0.0, 0.0, 3.0)]
+            .iter()
+            .map(|p| DVector::from_vec(vec![p.0, p.1, p.2]))
+            .collect();
+        let target: Vec<DVector<f64>> = source
+            .iter()
+            .map(|p| {
+                let point = transform * Point3::new(p[0], p[1], p[2]);
+                DVector::from_vec(vec![point.x, point.y, point.z])
+            })
+            .collect();
+        let SimilarityTransform {
+            rotation, translation, ..
+        } = estimate_transform(&source, &target, false);
+        let expected_rotation = transform.rotation.to_rotation_matrix();
+        assert!((rotation - DMatrix::from_iterator(3, 3, expected_rotation.matrix().iter().copied())).norm() < 1e-9);
+        assert!((translation - DVector::from_vec(vec![1.0, -2.0, 3.0])).norm() < 1e-9);
+    }
+
+    #[test]
+    fn test_estimate_similarity_transform() {
+        let rotation = Rotation3::new(Vector3::new(0.1, -0.4, 0.7));
+        let expected = SimilarityTransform {
+            rotation: DMatrix::from_column_slice(3, 3, rotation.matrix().as_slice()),
+            translation: DVector::from_vec(vec![1.0, -2.0, 0.5]),
+            scale: 2.5,
+        };
+        let source = get_points_3d();
+        let target: Vec<DVector<f64>> = source.iter().map(|p| expected.transform_point(p)).collect();
+        let transform = estimate_transform(&source, &target, true);
+        assert!((&transform.rotation - &expected.rotation).norm() < 1e-9);
+        assert!((&transform.translation - &expected.translation).norm() < 1e-9);
+        assert_relative_eq!(transform.scale, 2.5, epsilon = 1e-9);
+        assert_relative_eq!(estimate_transform(&source, &source, true).scale, 1.0, epsilon = 1e-9);
+    }
+
+    #[test]
+    fn test_ransac_points_rejects_outliers() {
+        let rotation = Rotation3::new(Vector3::new(0.0, 0.3, -0.2));
+        let expected = SimilarityTransform {
+            rotation: DMatrix::from_column_slice(3, 3, rotation.matrix().as_slice()),
+            translation: DVector::from_vec(vec![-1.0, 0.0, 4.0]),
+            scale: 1.0,
+        };
+        let source = get_points_3d();
+        let mut target: Vec<DVector<f64>> = source.iter().map(|p| expected.transform_point(p)).collect();
+        target[2][0] += 3.0;
+        let (transform, inliers) = ransac_points(&source, &target, false, &RansacConfig::default()).unwrap();
+        assert_eq!(inliers, vec![0, 1, 3, 4, 5]);
+        assert!((&transform.rotation - &expected.rotation).norm() < 1e-9);
+        assert!((&transform.translation - &expected.translation).norm() < 1e-9);
+
+        assert!(ransac_points(&source[..2], &target[..2], false, &RansacConfig::default()).is_err());
+        assert!(ransac_points(&source, &target[..5], false, &RansacConfig::default()).is_err());
+    }
+
+    #[test]
+    fn test_ransac_poses() {
+        let expected = Isometry2::new(Vector2::new(3.0, -1.0), 0.8);
+        let source: Vec<Isometry2<f64>> = (0..5)
+            .map(|i| Isometry2::new(Vector2::new(i as f64, (i * i) as f64), 0.3 * i as f64))
+            .collect();
+        let mut target: Vec<Isometry2<f64>> = source.iter().map(|pose| expected * pose).collect();
+        // an outlier by its position and one by its orientation
+        target[1] = Isometry2::new(Vector2::new(5.0, 5.0), 0.0) * target[1];
+        target[3] *= UnitComplex::new(0.5);
+        let (transform, inliers) = ransac_poses_2d(&source, &target, &RansacConfig::default()).unwrap();
+        assert_eq!(inliers, vec![0, 2, 4]);
+        assert!((transform.translation.vector - expected.translation.vector).norm() < 1e-9);
+        assert_relative_eq!(transform.rotation.angle(), 0.8, epsilon = 1e-9);
+        assert!(ransac_poses_2d(&[], &[], &RansacConfig::default()).is_err());
+
+        let expected = Isometry3::new(Vector3::new(1.0, 2.0, 3.0), Vector3::new(0.2, -0.1, 1.5));
+        let source: Vec<Isometry3<f64>> = (0..4)
+            .map(|i| Isometry3::new(Vector3::new(i as f64, 0.0, 1.0), Vector3::new(0.0, 0.1 * i as f64, 0.0)))
+            .collect();
+        let target: Vec<Isometry3<f64>> = source.iter().map(|pose| expected * pose).collect();
+        let (transform, inliers) = ransac_poses_3d(&source, &target, &RansacConfig::default()).unwrap();
+        assert_eq!(inliers, vec![0, 1, 2, 3]);
+        assert!((transform.translation.vector - expected.translation.vector).norm() < 1e-9);
+        assert!(transform.rotation.angle_to(&expected.rotation) < 1e-9);
+    }
+}
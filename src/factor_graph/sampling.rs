@@ -0,0 +1,254 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Random subsampling of factor graphs, e.g. for quick previews of parameter choices.
+
+use crate::factor_graph::adjacency::NodeIndex;
+use crate::factor_graph::variable::{
+    AnchorVariable2D, AnchorVariable3D, ExtrinsicVariable2D, ExtrinsicVariable3D, FixedType, ImuBiasVariable, LandmarkVariable2D, LandmarkVariable3D,
+    LineLandmarkVariable2D, OdometryCalibrationVariable2D, PlaneVariable3D, SwitchVariable, UserDefinedVariable,
+    Variable, VehicleVariable2D, VehicleVariable3D, VehicleVariableSim3, VelocityVariable3D,
+};
+use crate::factor_graph::FactorGraph;
+use std::collections::BTreeSet;
+
+impl FactorGraph {
+    /// Returns a random connected subgraph with the given fraction of the variables, rounded up, which is
+    /// reproducible for the same seed.
+    ///
+    /// The variables are chosen by growing a random spanning tree from a random variable. If its connected
+    /// component is exhausted, the tree is continued from another random variable, so the subgraph is only
+    /// disconnected if the factor graph is. All factors between chosen variables are kept, with new IDs. The
+    /// variables are copied with their current estimates and stay fixed if they were. If none of them is fixed,
+    /// the first chosen variable is fixed, so that the subgraph can be optimized on its own.
+    ///
+    /// Returns an error if the fraction is not within (0, 1].
+    pub fn sample(&self, fraction: f64, seed: u64) -> Result<FactorGraph, String> {
+        if !(fraction > 0.0 && fraction <= 1.0) {
+            return Err(format!(
+                "The sampled fraction must be within (0, 1], but is {}",
+                fraction
+            ));
+        }
+        let var_count = self.node_indices.len();
+        let sample_count = (fraction * var_count as f64).ceil() as usize;
+        let mut rng = SplitMix64(seed);
+
+        let mut neighbors: Vec<BTreeSet<usize>> = vec![BTreeSet::new(); var_count];
+        self.factor_id_map.keys().for_each(|id| {
+            let indices = self.get_factor_var_indices(*id).unwrap();
+            for a in &indices {
+                neighbors[a.index()].extend(indices.iter().filter(|b| *b != a).map(|b| b.index()));
+            }
+        });
+
+        // randomized Prim's algorithm, i.e. each chosen variable is a neighbor of a previously chosen one
+        let mut is_chosen = vec![false; var_count];
+        let mut is_candidate = vec![false; var_count];
+        let mut chosen = vec![];
+        while chosen.len() < sample_count {
+            let start = (0..var_count)
+                .map(|offset| (rng.below(var_count) + offset) % var_count)
+                .find(|i| !is_chosen[*i])
+                .unwrap();
+            let mut candidates = vec![start];
+            is_candidate[start] = true;
+            while !candidates.is_empty() && chosen.len() < sample_count {
+                let var = candidates.swap_remove(rng.below(candidates.len()));
+                is_chosen[var] = true;
+                chosen.push(var);
+                for neighbor in &neighbors[var] {
+                    if !is_candidate[*neighbor] {
+                        is_candidate[*neighbor] = true;
+                        candidates.push(*neighbor);
+                    }
+                }
+            }
+        }
+
+        let fix_first = chosen
+            .iter()
+            .all(|i| self.get_var(NodeIndex::new(*i)).get_fixed_type() == &FixedType::Fixed);
+        self.get_subgraph(&is_chosen, |i| fix_first && i == chosen[0])
+    }
+
+    // returns the subgraph of the chosen variables and of the factors between them, in which the variables for which
+    // fix returns true are fixed as well
+    pub(crate) fn get_subgraph<F: Fn(usize) -> bool>(&self, is_chosen: &[bool], fix: F) -> Result<FactorGraph, String> {
+        let mut subgraph = FactorGraph::new();
+        for i in (0..self.node_indices.len()).filter(|i| is_chosen[*i]) {
+            let var = self.get_var(NodeIndex::new(i));
+            let tangent_dim = var.get_parameterization().tangent_dim();
+            let fixed_type = if var.get_fixed_type() == &FixedType::Fixed || fix(i) {
+                FixedType::Fixed
+            } else {
+                subgraph.matrix_dim += tangent_dim;
+                FixedType::NonFixed(subgraph.matrix_dim - tangent_dim..subgraph.matrix_dim)
+            };
+            let index = subgraph.adjacency.add_node(copy_variable(var, fixed_type));
+            subgraph.node_indices.push(index);
+            subgraph.custom_to_csr_id_map.insert(var.get_id(), index);
+        }
+        for edge in self.node_indices.iter().flat_map(|i| self.adjacency.edges(*i)) {
+            let factor = self.materialize_factor(edge.weight());
+            let indices = self.get_factor_var_indices(factor.id).unwrap();
+            if indices.iter().all(|i| is_chosen[i.index()]) {
+                subgraph.add_factor_with_additional_variables(
+                    self.get_var(edge.source()).get_id(),
+                    self.get_var(edge.target()).get_id(),
+                    factor.additional_variables.clone(),
+                    factor.factor_type.clone(),
+                    factor.constraint.clone(),
+                    factor.information_matrix.clone(),
+                )?;
+            }
+        }
+        Ok(subgraph)
+    }
+}
+
+// returns a variable with the same ID and content which does not share its content with the given one
+fn copy_variable(var: &Variable, fixed_type: FixedType) -> Variable {
+    let id = var.get_id();
+    let c = var.get_content();
+    match var {
+        Variable::Vehicle2D(_) => Variable::Vehicle2D(VehicleVariable2D::new(id, c[0], c[1], c[2], fixed_type)),
+        Variable::Landmark2D(_) => Variable::Landmark2D(LandmarkVariable2D::new(id, c[0], c[1], fixed_type)),
+        Variable::Vehicle3D(_) => Variable::Vehicle3D(VehicleVariable3D::new(
+            id, c[0], c[1], c[2], c[3], c[4], c[5], c[6], fixed_type,
+        )),
+        Variable::Landmark3D(_) => Variable::Landmark3D(LandmarkVariable3D::new(id, c[0], c[1], c[2], fixed_type)),
+        Variable::Switch(_) => Variable::Switch(SwitchVariable::new(id, c[0], fixed_type)),
+        Variable::Velocity3D(_) => Variable::Velocity3D(VelocityVariable3D::new(id, c[0], c[1], c[2], fixed_type)),
+        Variable::ImuBias(_) => Variable::ImuBias(ImuBiasVariable::new(
+            id,
+            [c[0], c[1], c[2], c[3], c[4], c[5]],
+            fixed_type,
+        )),
+        Variable::VehicleSim3(_) => Variable::VehicleSim3(VehicleVariableSim3::new(
+            id,
+            [c[0], c[1], c[2], c[3], c[4], c[5], c[6], c[7]],
+            fixed_type,
+        )),
+        Variable::Plane3D(_) => Variable::Plane3D(PlaneVariable3D::new(id, [c[0], c[1], c[2], c[3]], fixed_type)),
+        Variable::LineLandmark2D(_) => {
+            Variable::LineLandmark2D(LineLandmarkVariable2D::new(id, c[0], c[1], fixed_type))
+        }
+        Variable::OdometryCalibration2D(_) => {
+            Variable::OdometryCalibration2D(OdometryCalibrationVariable2D::new(id, [c[0], c[1], c[2]], fixed_type))
+        }
+        Variable::Extrinsic2D(_) => Variable::Extrinsic2D(ExtrinsicVariable2D::new(id, [c[0], c[1], c[2]], fixed_type)),
+        Variable::Extrinsic3D(_) => Variable::Extrinsic3D(ExtrinsicVariable3D::new(
+            id,
+            [c[0], c[1], c[2], c[3], c[4], c[5], c[6]],
+            fixed_type,
+        )),
+        Variable::Anchor2D(_) => Variable::Anchor2D(AnchorVariable2D::new(id, [c[0], c[1], c[2]], fixed_type)),
+        Variable::Anchor3D(_) => Variable::Anchor3D(AnchorVariable3D::new(
+            id,
+            [c[0], c[1], c[2], c[3], c[4], c[5], c[6]],
+            fixed_type,
+        )),
+        Variable::UserDefined(v) => {
+            Variable::UserDefined(UserDefinedVariable::new(id, v.state.borrow().clone_box(), fixed_type))
+        }
+    }
+}
+
+// small pseudo-random number generator, see https://prng.di.unimi.it/splitmix64.c
+pub(crate) struct SplitMix64(pub(crate) u64);
+
+impl SplitMix64 {
+    pub(crate) fn next(&mut self) -> u64 {
+        self.0 = self.0.wrapping_add(0x9e37_79b9_7f4a_7c15);
+        let mut z = self.0;
+        z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        z ^ (z >> 31)
+    }
+
+    // returns a number below the given bound, which has to be positive
+    pub(crate) fn below(&mut self, bound: usize) -> usize {
+        (self.next() % bound as u64) as usize
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::factor_graph::variable::VariableId;
+    use crate::optimizer::optimize;
+    use crate::parser::g2o::G2oParser;
+    use crate::parser::Parser;
+
+    fn get_ids(factor_graph: &FactorGraph) -> Vec<VariableId> {
+        factor_graph
+            .node_indices
+            .iter()
+            .map(|i| factor_graph.get_var(*i).get_id())
+            .collect()
+    }
+
+    // returns the number of variables reachable from the first one
+    fn count_reachable(factor_graph: &FactorGraph) -> usize {
+        let mut reached = BTreeSet::new();
+        let mut stack = vec![factor_graph.node_indices[0]];
+        while let Some(i) = stack.pop() {
+            if reached.insert(i) {
+                factor_graph.factor_id_map.keys().for_each(|id| {
+                    let indices = factor_graph.get_factor_var_indices(*id).unwrap();
+                    if indices.contains(&i) {
+                        stack.extend(indices);
+                    }
+                });
+            }
+        }
+        reached.len()
+    }
+
+    #[test]
+    fn test_sample() {
+        let factor_graph = G2oParser::parse_file("data_files/optimizer_tests/full2d_0.g2o").unwrap();
+        let sample = factor_graph.sample(0.25, 42).unwrap();
+        let expected_count = (0.25 * factor_graph.node_indices.len() as f64).ceil() as usize;
+        assert_eq!(sample.node_indices.len(), expected_count);
+        assert_eq!(count_reachable(&sample), expected_count);
+        assert!(sample.factor_id_map.len() >= expected_count - 1);
+        assert_eq!(get_ids(&sample), get_ids(&factor_graph.sample(0.25, 42).unwrap()));
+        assert_ne!(get_ids(&sample), get_ids(&factor_graph.sample(0.25, 43).unwrap()));
+
+        // the sample does not share its variables with the factor graph
+        let contents: Vec<Vec<f64>> = factor_graph
+            .node_indices
+            .iter()
+            .map(|i| factor_graph.get_var(*i).get_content())
+            .collect();
+        optimize(&sample, 1);
+        let unchanged_contents: Vec<Vec<f64>> = factor_graph
+            .node_indices
+            .iter()
+            .map(|i| factor_graph.get_var(*i).get_content())
+            .collect();
+        assert_eq!(contents, unchanged_contents);
+    }
+
+    #[test]
+    fn test_sample_invalid_fraction() {
+        let factor_graph = G2oParser::parse_file("data_files/optimizer_tests/full2d_0.g2o").unwrap();
+        assert!(factor_graph.sample(0.0, 0).is_err());
+        assert!(factor_graph.sample(1.5, 0).is_err());
+        assert_eq!(
+            factor_graph.sample(1.0, 0).unwrap().factor_id_map.len(),
+            factor_graph.factor_id_map.len()
+        );
+    }
+}
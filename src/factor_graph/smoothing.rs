@@ -0,0 +1,146 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Smoothness priors on trajectories, e.g. when odometry is missing or very noisy.
+
+use crate::factor_graph::factor::{FactorId, FactorType, InformationMatrix};
+use crate::factor_graph::variable::{Variable, VariableId};
+use crate::factor_graph::FactorGraph;
+
+impl FactorGraph {
+    /// Adds a constant-velocity factor for each three consecutive poses of the trajectory with the given IDs and
+    /// returns the factors' IDs, see
+    /// [ConstantVelocity2D](../factor/enum.FactorType.html#variant.ConstantVelocity2D).
+    ///
+    /// The poses are expected to be equally spaced in time. All factors have the given information matrix, which
+    /// weights the change of the relative motion between consecutive poses.
+    ///
+    /// Returns an error without changing the factor graph if one of the IDs is unknown, if the poses are not all 2D or
+    /// all 3D vehicle poses, or if the information matrix does not match their dimension.
+    ///
+    /// ```
+    /// use gs_rs::examples_gen::triangle_with_loop_closure;
+    /// use gs_rs::factor_graph::FactorGraph;
+    /// use gs_rs::factor_graph::variable::VariableId;
+    ///
+    /// let mut factor_graph: FactorGraph = triangle_with_loop_closure().into();
+    /// let poses = [VariableId(0), VariableId(1), VariableId(2)];
+    /// let information = vec![1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0];
+    /// let ids = factor_graph.add_constant_velocity_factors(&poses, information.into()).unwrap();
+    /// assert_eq!(ids.len(), 1);
+    /// ```
+    pub fn add_constant_velocity_factors(
+        &mut self,
+        poses: &[VariableId],
+        information_matrix: InformationMatrix,
+    ) -> Result<Vec<FactorId>, String> {
+        let mut is_3d = None;
+        for id in poses {
+            let var_is_3d = match self.get_var(self.get_csr_index(*id)?) {
+                Variable::Vehicle2D(_) => false,
+                Variable::Vehicle3D(_) => true,
+                _ => return Err(format!("The variable {} is not a vehicle pose", id)),
+            };
+            if is_3d.replace(var_is_3d).is_some_and(|is_3d| is_3d != var_is_3d) {
+                return Err(String::from("The poses of the trajectory are not all 2D or all 3D"));
+            }
+        }
+        let (factor_type, dim) = match is_3d {
+            Some(true) => (FactorType::ConstantVelocity3D, 6),
+            _ => (FactorType::ConstantVelocity2D, 3),
+        };
+        if information_matrix.content.shape() != (dim, dim) {
+            return Err(format!(
+                "The information matrix of constant-velocity factors has to be {}x{}",
+                dim, dim
+            ));
+        }
+        poses
+            .windows(3)
+            .map(|triplet| {
+                self.add_factor_with_additional_variables(
+                    triplet[0],
+                    triplet[2],
+                    vec![triplet[1]],
+                    factor_type.clone(),
+                    vec![],
+                    information_matrix.clone(),
+                )
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimizer::{optimize, total_chi2};
+    use crate::parser::g2o::G2oParser;
+    use crate::parser::Parser;
+    use approx::assert_relative_eq;
+
+    const INFORMATION_2D: [f64; 9] = [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0];
+
+    #[test]
+    fn test_trajectory_is_smoothed() {
+        // the first two poses are fixed and determine the velocity, the others are only connected by the priors
+        let mut factor_graph: FactorGraph = G2oParser::parse_string_to_model(
+            "VERTEX_SE2 0 0 0 0\nVERTEX_SE2 1 1 0 0.1\nVERTEX_SE2 2 2.3 0.4 0.3\nVERTEX_SE2 3 2.8 0.9 0.2\nFIX 0 1",
+        )
+        .unwrap()
+        .into();
+        let poses: Vec<VariableId> = (0..4).map(VariableId).collect();
+        let ids = factor_graph
+            .add_constant_velocity_factors(&poses, INFORMATION_2D.to_vec().into())
+            .unwrap();
+        assert_eq!(ids.len(), 2);
+        assert_eq!(factor_graph.factors_between(VariableId(0), VariableId(2)), vec![ids[0]]);
+        optimize(&factor_graph, 20);
+        assert!(total_chi2(&factor_graph) < 1e-12);
+        // the vehicle keeps turning by 0.1 while moving forward by about 1
+        for (id, expected) in [(2, [1.995004, 0.099833, 0.2]), (3, [2.975071, 0.298503, 0.3])] {
+            let pose = factor_graph.get_var_by_id(VariableId(id)).unwrap().get_content();
+            (0..3).for_each(|k| assert_relative_eq!(pose[k], expected[k], epsilon = 1e-6));
+        }
+    }
+
+    #[test]
+    fn test_invalid_trajectories() {
+        let mut factor_graph: FactorGraph = G2oParser::parse_string_to_model(
+            &[
+                "VERTEX_SE2 0 0 0 0",
+                "VERTEX_SE2 1 1 0 0",
+                "VERTEX_XY 2 2 0",
+                "VERTEX_SE2 3 2 0 0",
+                "EDGE_SE2 0 3 2 0 0 1 0 0 1 0 1",
+            ]
+            .join("\n"),
+        )
+        .unwrap()
+        .into();
+        let ids = |ids: &[usize]| ids.iter().copied().map(VariableId).collect::<Vec<_>>();
+        let information: InformationMatrix = INFORMATION_2D.to_vec().into();
+        assert_eq!(
+            factor_graph.add_constant_velocity_factors(&ids(&[0, 1, 2]), information.clone()),
+            Err(String::from("The variable 2 is not a vehicle pose"))
+        );
+        assert!(factor_graph
+            .add_constant_velocity_factors(&ids(&[1, 3, 0]), vec![1.0].into())
+            .is_err());
+        assert_eq!(factor_graph.factor_id_map.len(), 1);
+        // the trajectory may skip the pose between two poses with an odometry factor between them
+        factor_graph
+            .add_constant_velocity_factors(&ids(&[0, 1, 3]), information)
+            .unwrap();
+        assert_eq!(factor_graph.factors_between(VariableId(0), VariableId(3)).len(), 2);
+    }
+}
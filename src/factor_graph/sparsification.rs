@@ -0,0 +1,306 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Sparsification of pose graphs by removing vehicle poses whose information is approximated by virtual odometry
+//! factors, e.g. keyframe decimation in long-running sessions, whose factor graphs would grow without bound
+//! otherwise.
+//!
+//! Removing a pose turns the dense Schur complement of its factors, see
+//! [marginalize](../struct.FactorGraph.html#method.marginalize), into a chain of virtual odometry factors between
+//! its neighbors in ascending order of ID. Each virtual factor measures the relative pose at the mean of the
+//! Schur complement, with the marginal information of this relative pose. The chain is exact for a pose with
+//! odometry factors to its predecessor and successor only, and an approximation otherwise, e.g. for poses with loop
+//! closures or factors to fixed variables, whose error is measured by the Kullback-Leibler divergence from the Schur
+//! complement.
+//!
+//! ```
+//! use gs_rs::factor_graph::variable::VariableId;
+//! use gs_rs::parser::g2o::G2oParser;
+//! use gs_rs::parser::Parser;
+//!
+//! let mut factor_graph = G2oParser::parse_file("data_files/optimizer_tests/odo2d_only_0.g2o").unwrap();
+//! let keyframes: Vec<VariableId> = (1..20).step_by(2).map(VariableId).collect();
+//! let sparsification = factor_graph.sparsify(&keyframes, 1e-6).unwrap();
+//! assert!(!sparsification.removed.is_empty());
+//! assert!(sparsification.kl_divergence <= 1e-6 * sparsification.removed.len() as f64);
+//! ```
+
+#![allow(non_snake_case)]
+
+use crate::factor_graph::adjacency::NodeIndex;
+use crate::factor_graph::factor::{FactorId, FactorType, InformationMatrix};
+use crate::factor_graph::variable::{FixedType, Variable, VariableId};
+use crate::factor_graph::FactorGraph;
+use crate::optimizer::linear_system::calculate_jacobian;
+use nalgebra::{DMatrix, DVector, SymmetricEigen};
+use std::collections::BTreeSet;
+
+/// Summary of a [sparsification](../struct.FactorGraph.html#method.sparsify).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sparsification {
+    /// The IDs of the removed poses, in the order of removal.
+    pub removed: Vec<VariableId>,
+    /// The IDs of the added virtual factors.
+    pub virtual_factors: Vec<FactorId>,
+    /// The sum of the Kullback-Leibler divergences of the virtual factors from the Schur complements of the factors
+    /// which they replaced.
+    pub kl_divergence: f64,
+}
+
+impl FactorGraph {
+    /// Removes the given vehicle poses in the given order, each together with its factors, replacing the factors by
+    /// virtual odometry factors between the pose's neighbors, see the
+    /// [module documentation](sparsification/index.html), unless the Kullback-Leibler divergence of the virtual
+    /// factors from the Schur complement of the removed factors exceeds the given bound.
+    ///
+    /// The factors are linearized at the current estimates, so the factor graph should be optimized beforehand.
+    /// Poses are kept if they are fixed, part of an equality constraint or not fully constrained by their factors, if
+    /// they share factors with variables of other types, which are not part of a pose graph, or if a factor already
+    /// exists between two neighbors which the chain would connect. Poses which share factors only with fixed
+    /// variables are removed without virtual factors.
+    ///
+    /// Returns an error without changing the factor graph if one of the IDs is unknown or if the bound is negative.
+    pub fn sparsify(&mut self, candidates: &[VariableId], max_kl_divergence: f64) -> Result<Sparsification, String> {
+        for id in candidates {
+            self.get_csr_index(*id)?;
+        }
+        if max_kl_divergence.is_nan() || max_kl_divergence < 0.0 {
+            return Err(format!(
+                "The bound of the KL divergence must be non-negative, but is {}",
+                max_kl_divergence
+            ));
+        }
+        let mut sparsification = Sparsification {
+            removed: vec![],
+            virtual_factors: vec![],
+            kl_divergence: 0.0,
+        };
+        for id in candidates {
+            if let Some((virtual_factors, kl_divergence)) = self.remove_pose(*id, max_kl_divergence)? {
+                sparsification.removed.push(*id);
+                sparsification.virtual_factors.extend(virtual_factors);
+                sparsification.kl_divergence += kl_divergence;
+            }
+        }
+        Ok(sparsification)
+    }
+
+    // removes the pose and returns its virtual factors and their KL divergence, or None if the pose is kept
+    fn remove_pose(&mut self, id: VariableId, max_kl_divergence: f64) -> Result<Option<(Vec<FactorId>, f64)>, String> {
+        let index = self.get_csr_index(id)?;
+        let var = self.get_var(index);
+        let factor_type = match var {
+            Variable::Vehicle2D(_) => FactorType::Odometry2D,
+            Variable::Vehicle3D(_) => FactorType::Odometry3D,
+            _ => return Ok(None),
+        };
+        if var.get_fixed_type() == &FixedType::Fixed || self.is_tied(index) {
+            return Ok(None);
+        }
+        let marginalized: BTreeSet<NodeIndex<usize>> = std::iter::once(index).collect();
+        let factors: Vec<FactorId> = self
+            .factor_id_map
+            .keys()
+            .copied()
+            .filter(|id| self.get_factor_var_indices(*id).unwrap().contains(&index))
+            .collect();
+        let mut blanket: Vec<NodeIndex<usize>> = factors
+            .iter()
+            .flat_map(|id| self.get_factor_var_indices(*id).unwrap())
+            .filter(|i| *i != index && self.get_var(*i).get_fixed_type() != &FixedType::Fixed)
+            .collect::<BTreeSet<NodeIndex<usize>>>()
+            .into_iter()
+            .collect();
+        blanket.sort_by_key(|i| self.get_var(*i).get_id());
+        let ids: Vec<VariableId> = blanket.iter().map(|i| self.get_var(*i).get_id()).collect();
+        if blanket
+            .iter()
+            .any(|i| self.is_tied(*i) || core::mem::discriminant(self.get_var(*i)) != core::mem::discriminant(var))
+            || ids
+                .windows(2)
+                .any(|pair| !self.factors_between(pair[0], pair[1]).is_empty())
+        {
+            return Ok(None);
+        }
+        if blanket.is_empty() {
+            self.remove_marginalized(&factors, &marginalized);
+            return Ok(Some((vec![], 0.0)));
+        }
+        let (H, b) = match self.calculate_marginal_H_b(&factors, &marginalized, &blanket) {
+            Ok(H_b) => H_b,
+            Err(_) => return Ok(None),
+        };
+
+        // the pseudo-inverse of H, whose null space consists of the directions without information, e.g. the gauge
+        let eigen = SymmetricEigen::new(H);
+        let eps = 1e-9 * eigen.eigenvalues.amax();
+        let range: Vec<usize> = (0..eigen.eigenvalues.len())
+            .filter(|k| eigen.eigenvalues[*k] > eps)
+            .collect();
+        let basis = eigen.eigenvectors.select_columns(&range);
+        let variances = DMatrix::from_diagonal(
+            &range
+                .iter()
+                .map(|k| 1.0 / eigen.eigenvalues[*k])
+                .collect::<Vec<_>>()
+                .into(),
+        );
+        let covariance = &basis * variances * basis.transpose();
+
+        // the virtual factors measure the relative poses at the mean, but are linearized at the current estimates
+        let contents: Vec<Vec<f64>> = blanket.iter().map(|i| self.get_var(*i).get_content()).collect();
+        let correction = -(&covariance * b);
+        let dim = self.get_var(blanket[0]).get_parameterization().tangent_dim();
+        for (k, i) in blanket.iter().enumerate() {
+            let parameterization = self.get_var(*i).get_parameterization();
+            let mean = parameterization.plus(&contents[k], &correction.as_slice()[k * dim..(k + 1) * dim]);
+            self.get_var(*i).set_content(mean);
+        }
+        let mut virtual_factors = vec![];
+        for k in 1..blanket.len() {
+            let id = self.add_factor(
+                ids[k - 1],
+                ids[k],
+                factor_type.clone(),
+                contents[k].clone(),
+                InformationMatrix {
+                    content: DMatrix::identity(dim, dim),
+                },
+            )?;
+            let prediction = self.get_factor(id).unwrap().predict(self);
+            self.set_constraint(id, prediction)?;
+            virtual_factors.push(id);
+        }
+        blanket
+            .iter()
+            .zip(contents)
+            .for_each(|(i, content)| self.get_var(*i).set_content(content));
+
+        // each virtual factor gets the marginal information of its relative pose
+        let mut approximation = DMatrix::zeros(covariance.nrows(), covariance.ncols());
+        for (k, id) in virtual_factors.iter().enumerate() {
+            let mut jacobian = DMatrix::zeros(dim, covariance.ncols());
+            jacobian
+                .columns_mut(k * dim, 2 * dim)
+                .copy_from(&calculate_jacobian(self, *id).unwrap());
+            let information = match (&jacobian * &covariance * jacobian.transpose()).cholesky() {
+                Some(cholesky) => cholesky.inverse(),
+                None => {
+                    self.remove_virtual_factors(&virtual_factors);
+                    return Ok(None);
+                }
+            };
+            let information = (&information + information.transpose()) * 0.5;
+            approximation += jacobian.transpose() * &information * &jacobian;
+            self.set_information_matrix(*id, InformationMatrix { content: information })?;
+        }
+
+        let kl_divergence = calculate_kl_divergence(&eigen.eigenvalues.select_rows(&range), &basis, &approximation);
+        if kl_divergence > max_kl_divergence {
+            self.remove_virtual_factors(&virtual_factors);
+            return Ok(None);
+        }
+        self.remove_marginalized(&factors, &marginalized);
+        Ok(Some((virtual_factors, kl_divergence)))
+    }
+
+    fn remove_virtual_factors(&mut self, ids: &[FactorId]) {
+        ids.iter().for_each(|id| {
+            self.remove_factor(*id).unwrap();
+        });
+    }
+}
+
+// returns the KL divergence of the approximation from the Gaussian with the given non-zero eigenvalues and
+// eigenvectors of its information matrix, restricted to the directions with information
+fn calculate_kl_divergence(eigenvalues: &DVector<f64>, basis: &DMatrix<f64>, approximation: &DMatrix<f64>) -> f64 {
+    let restricted = basis.transpose() * approximation * basis;
+    let log_determinant = match restricted.clone().cholesky() {
+        Some(cholesky) => 2.0 * cholesky.l().diagonal().iter().map(|v| v.ln()).sum::<f64>(),
+        None => return f64::INFINITY,
+    };
+    let trace: f64 = (0..eigenvalues.len())
+        .map(|k| restricted[(k, k)] / eigenvalues[k])
+        .sum();
+    let log_eigenvalues: f64 = eigenvalues.iter().map(|v| v.ln()).sum();
+    0.5 * (trace - eigenvalues.len() as f64 + log_eigenvalues - log_determinant)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimizer::linear_system::calculate_H_b;
+    use crate::parser::g2o::G2oParser;
+    use crate::parser::Parser;
+
+    const FILE_NAME: &str = "data_files/optimizer_tests/odo2d_only_0.g2o";
+
+    fn get_odd_ids(count: usize) -> Vec<VariableId> {
+        (1..count).step_by(2).map(VariableId).collect()
+    }
+
+    #[test]
+    fn test_chains_are_exact() {
+        let mut factor_graph = G2oParser::parse_file(FILE_NAME).unwrap();
+        let sparsification = factor_graph.sparsify(&get_odd_ids(40), 1e-9).unwrap();
+        // the pose 1 shares a factor with the fixed pose 0, whose information no odometry factor can replace, and
+        // the poses 9 and 29 have loop closures
+        let expected: Vec<VariableId> = get_odd_ids(40)
+            .into_iter()
+            .filter(|id| ![1, 9, 29].contains(&id.0))
+            .collect();
+        assert_eq!(sparsification.removed, expected);
+        assert_eq!(sparsification.virtual_factors.len(), expected.len());
+        assert!(sparsification.kl_divergence.abs() < 1e-9);
+
+        let mut marginalized = G2oParser::parse_file(FILE_NAME).unwrap();
+        for id in &expected {
+            marginalized.marginalize(&[*id]).unwrap();
+        }
+        let (H, b) = calculate_H_b(&factor_graph);
+        let (expected_H, expected_b) = calculate_H_b(&marginalized);
+        let (H, expected_H) = (H.to_dense(), expected_H.to_dense());
+        assert!((&H - &expected_H).norm() < 1e-6 * expected_H.norm());
+        assert!((b - &expected_b).norm() < 1e-6 * expected_b.norm());
+    }
+
+    #[test]
+    fn test_loop_closures_are_approximated() {
+        let mut factor_graph = G2oParser::parse_file(FILE_NAME).unwrap();
+        let factor_count = factor_graph.factor_id_map.len();
+        let sparsification = factor_graph.sparsify(&[VariableId(9)], f64::INFINITY).unwrap();
+        assert_eq!(sparsification.removed, vec![VariableId(9)]);
+        assert!(sparsification.kl_divergence > 1e-9);
+        assert_eq!(factor_graph.factor_id_map.len(), factor_count - 1);
+        for (a, b) in [(4, 8), (8, 10)] {
+            let factors = factor_graph.factors_between(VariableId(a), VariableId(b));
+            assert_eq!(factors.len(), 1);
+            assert_eq!(
+                factor_graph.get_factor(factors[0]).unwrap().factor_type,
+                FactorType::Odometry2D
+            );
+        }
+    }
+
+    #[test]
+    fn test_poses_are_kept() {
+        let mut factor_graph = G2oParser::parse_file(FILE_NAME).unwrap();
+        let factor_count = factor_graph.factor_id_map.len();
+        assert!(factor_graph.sparsify(&[VariableId(1), VariableId(5000)], 1.0).is_err());
+        assert!(factor_graph.sparsify(&[VariableId(1)], -1.0).is_err());
+        // the pose 0 is fixed
+        let sparsification = factor_graph.sparsify(&[VariableId(0), VariableId(9)], 1e-9).unwrap();
+        assert!(sparsification.removed.is_empty());
+        assert_eq!(factor_graph.factor_id_map.len(), factor_count);
+        assert_eq!(factor_graph.node_indices.len(), 808);
+    }
+}
@@ -0,0 +1,217 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Timestamps of variables, e.g. of the poses of an odometry, and measurements of asynchronous sensors which are
+//! taken between the times of two poses, e.g. GPS fixes at 1 Hz between odometry poses at 50 Hz.
+//!
+//! Such a measurement constrains the pose interpolated between the poses before and after it, see
+//! [add_interpolated_pose_factor](../struct.FactorGraph.html#method.add_interpolated_pose_factor). Timestamps are
+//! part of [models](../../parser/model/struct.FactorGraphModel.html) and do not change the optimization.
+
+use crate::factor_graph::factor::{FactorId, FactorType, InformationMatrix};
+use crate::factor_graph::variable::{Variable, VariableId};
+use crate::factor_graph::FactorGraph;
+use std::collections::BTreeMap;
+
+impl FactorGraph {
+    /// Returns the timestamp of the variable with the given ID, if it has one.
+    pub fn timestamp(&self, id: VariableId) -> Option<f64> {
+        self.timestamps.get(&id).copied()
+    }
+
+    /// Returns the timestamps of all variables which have one, ordered by ID.
+    pub fn timestamps(&self) -> &BTreeMap<VariableId, f64> {
+        &self.timestamps
+    }
+
+    /// Sets the timestamp of the variable with the given ID, or removes it if it is None.
+    ///
+    /// Returns an error if the ID is unknown or the timestamp is not finite.
+    pub fn set_timestamp(&mut self, id: VariableId, timestamp: Option<f64>) -> Result<(), String> {
+        self.get_csr_index(id)?;
+        match timestamp {
+            Some(time) if !time.is_finite() => {
+                return Err(format!("The timestamp of variable {} is not finite: {}", id, time))
+            }
+            Some(time) => self.timestamps.insert(id, time),
+            None => self.timestamps.remove(&id),
+        };
+        Ok(())
+    }
+
+    /// Returns the IDs of the timestamped 2D or 3D vehicle poses directly before and after the given time and the
+    /// fraction of the time between them which passed at the given time, or None if the time is not between two
+    /// timestamped poses.
+    ///
+    /// Poses with the same timestamp are skipped, so the fraction is always well-defined.
+    pub fn find_enclosing_poses(&self, time: f64, is_3d: bool) -> Option<(VariableId, VariableId, f64)> {
+        let mut poses: Vec<(f64, VariableId)> = self
+            .timestamps
+            .iter()
+            .filter(|(id, _)| match self.get_var_by_id(**id) {
+                Some(Variable::Vehicle2D(_)) => !is_3d,
+                Some(Variable::Vehicle3D(_)) => is_3d,
+                _ => false,
+            })
+            .map(|(id, time)| (*time, *id))
+            .collect();
+        poses.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        poses
+            .windows(2)
+            .find(|pair| pair[0].0 <= time && time <= pair[1].0 && pair[0].0 < pair[1].0)
+            .map(|pair| (pair[0].1, pair[1].1, (time - pair[0].0) / (pair[1].0 - pair[0].0)))
+    }
+
+    /// Adds an InterpolatedPose2D or InterpolatedPose3D factor for a pose measured at the given time and returns its
+    /// ID, depending on whether the measurement is a 2D or a 3D pose, i.e. has the content of a Position2D or
+    /// Position3D factor.
+    ///
+    /// The factor constrains the pose interpolated between the timestamped poses directly before and after the time,
+    /// see [find_enclosing_poses](#method.find_enclosing_poses). Measurements without a meaningful rotation, e.g. GPS
+    /// fixes, can have zero information for it. Returns an error if the measurement has the wrong length, if the time
+    /// is not between two timestamped poses or if a factor between them cannot be added.
+    pub fn add_interpolated_pose_factor(
+        &mut self,
+        time: f64,
+        measurement: Vec<f64>,
+        information_matrix: InformationMatrix,
+    ) -> Result<FactorId, String> {
+        let (factor_type, is_3d) = match measurement.len() {
+            3 => (FactorType::InterpolatedPose2D, false),
+            7 => (FactorType::InterpolatedPose3D, true),
+            len => {
+                return Err(format!(
+                    "The measured pose has {} entries, but must be a 2D or a 3D pose",
+                    len
+                ))
+            }
+        };
+        let (before, after, fraction) = self
+            .find_enclosing_poses(time, is_3d)
+            .ok_or_else(|| format!("The time {} is not between two timestamped poses", time))?;
+        let mut constraint = measurement;
+        constraint.push(fraction);
+        self.add_factor_with_additional_variables(
+            before,
+            before,
+            vec![after],
+            factor_type,
+            constraint,
+            information_matrix,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimizer::{optimize, total_chi2};
+    use crate::parser::g2o::G2oParser;
+    use crate::parser::Parser;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn test_timestamps() {
+        let mut factor_graph: FactorGraph = G2oParser::parse_string_to_model(
+            "VERTEX_SE2 3 0 0 0\nVERTEX_SE2 5 1 0 0\nVERTEX_SE2 7 2 0 0\nVERTEX_XY 8 1 1",
+        )
+        .unwrap()
+        .into();
+        factor_graph.set_timestamp(VariableId(3), Some(0.0)).unwrap();
+        factor_graph.set_timestamp(VariableId(7), Some(2.0)).unwrap();
+        factor_graph.set_timestamp(VariableId(5), Some(1.0)).unwrap();
+        factor_graph.set_timestamp(VariableId(8), Some(1.2)).unwrap();
+        assert_eq!(factor_graph.timestamp(VariableId(5)), Some(1.0));
+        assert!(factor_graph.set_timestamp(VariableId(4), Some(0.0)).is_err());
+        assert!(factor_graph.set_timestamp(VariableId(5), Some(f64::NAN)).is_err());
+        assert_eq!(
+            factor_graph.find_enclosing_poses(1.5, false),
+            Some((VariableId(5), VariableId(7), 0.5))
+        );
+        assert_eq!(
+            factor_graph.find_enclosing_poses(1.0, false),
+            Some((VariableId(3), VariableId(5), 1.0))
+        );
+        assert_eq!(factor_graph.find_enclosing_poses(2.5, false), None);
+        assert_eq!(factor_graph.find_enclosing_poses(1.5, true), None);
+
+        factor_graph.set_timestamp(VariableId(3), None).unwrap();
+        assert_eq!(factor_graph.find_enclosing_poses(0.5, false), None);
+        factor_graph.compact_ids();
+        assert_eq!(factor_graph.timestamp(VariableId(1)), Some(1.0));
+        assert_eq!(factor_graph.timestamp(VariableId(2)), Some(2.0));
+        assert_eq!(factor_graph.timestamp(VariableId(3)), Some(1.2));
+    }
+
+    #[test]
+    fn test_asynchronous_gps_fixes() {
+        // odometry at 10 Hz along an arc, whose position in the world frame is only known from GPS fixes at other times
+        let poses: Vec<[f64; 3]> = (0..11)
+            .map(|k| {
+                let angle = 0.2 * k as f64;
+                [2.0 * angle.sin(), 2.0 - 2.0 * angle.cos(), angle]
+            })
+            .collect();
+        let mut lines: Vec<String> = poses
+            .iter()
+            .enumerate()
+            .map(|(k, pose)| format!("VERTEX_SE2 {} {} {} {}", k, pose[0] + 0.3, pose[1] - 0.2, pose[2] + 0.1))
+            .collect();
+        for k in 1..poses.len() {
+            let (previous, pose) = (poses[k - 1], poses[k]);
+            let (sin, cos) = previous[2].sin_cos();
+            let (dx, dy) = (pose[0] - previous[0], pose[1] - previous[1]);
+            lines.push(format!(
+                "EDGE_SE2 {} {} {} {} {} 1 0 0 1 0 1",
+                k - 1,
+                k,
+                cos * dx + sin * dy,
+                cos * dy - sin * dx,
+                pose[2] - previous[2]
+            ));
+        }
+        let mut factor_graph: FactorGraph = G2oParser::parse_string_to_model(&lines.join("\n")).unwrap().into();
+        for k in 0..poses.len() {
+            factor_graph.set_timestamp(VariableId(k), Some(0.1 * k as f64)).unwrap();
+        }
+        let mut fixes = vec![];
+        for time in &[0.05, 0.25, 0.55, 0.85] {
+            let (before, after, fraction) = factor_graph.find_enclosing_poses(*time, false).unwrap();
+            let (from, to) = (poses[before.0], poses[after.0]);
+            let fix = (0..3).map(|k| from[k] + fraction * (to[k] - from[k])).collect();
+            let information = vec![10.0, 0.0, 0.0, 0.0, 10.0, 0.0, 0.0, 0.0, 0.0];
+            fixes.push(
+                factor_graph
+                    .add_interpolated_pose_factor(*time, fix, information.into())
+                    .unwrap(),
+            );
+        }
+        assert!(factor_graph
+            .add_interpolated_pose_factor(1.5, vec![0.0; 3], vec![0.0; 9].into())
+            .is_err());
+        assert!(total_chi2(&factor_graph) > 1e-2);
+        optimize(&factor_graph, 10);
+        assert!(total_chi2(&factor_graph) < 1e-12);
+        for (k, pose) in poses.iter().enumerate() {
+            let estimate = factor_graph.get_var_by_id(VariableId(k)).unwrap().get_content();
+            for (actual, expected) in estimate.iter().zip(pose) {
+                assert_relative_eq!(actual, expected, epsilon = 1e-6);
+            }
+        }
+        for id in fixes {
+            let factor = factor_graph.get_factor(id).unwrap();
+            for (actual, expected) in factor.predict(&factor_graph).iter().zip(&factor.constraint) {
+                assert_relative_eq!(actual, expected, epsilon = 1e-6);
+            }
+        }
+    }
+}
@@ -0,0 +1,354 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Topology metrics of pose graphs, which indicate how well the loop closures constrain the optimization.
+//!
+//! The trajectory consists of all vehicle variables in the order of insertion. A loop closure is a factor between
+//! two vehicle variables which are not adjacent in the trajectory, or two observations of the same landmark from
+//! vehicle variables which are the next ones observing it within the trajectory. The length of a loop is the number
+//! of trajectory steps between its two vehicle variables, and the loop covers both of them and all in between. A
+//! segment of the trajectory which is not covered by any loop relies solely on odometry, so its drift is not
+//! corrected.
+//!
+//! Spanning trees and cycle bases are defined on the graph whose vertices are the variables and whose edges connect
+//! the source and target of each factor between two different variables. Additional variables of factors, e.g.
+//! switch variables, are not part of it.
+
+use crate::factor_graph::adjacency::NodeIndex;
+use crate::factor_graph::factor::FactorId;
+use crate::factor_graph::variable::{Variable, VariableId};
+use crate::factor_graph::FactorGraph;
+use std::collections::{BTreeMap, VecDeque};
+
+/// Loop closure metrics of a factor graph, see [topology](index.html).
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoopClosureMetrics {
+    /// The dimension of the cycle space, i.e. the number of independent cycles E - V + C of the graph whose V
+    /// vertices are the variables and whose E edges connect the source of each non-unary factor to its other
+    /// variables, with C connected components.
+    pub cycle_space_dimension: usize,
+    /// The number of loop closures.
+    pub loop_count: usize,
+    /// The average length of the loops, or None without loop closures.
+    pub average_loop_length: Option<f64>,
+    /// The number of loops covering each vehicle variable, in the order of the trajectory.
+    pub loop_coverage: Vec<(VariableId, usize)>,
+}
+
+impl LoopClosureMetrics {
+    /// Returns the first and last vehicle variable of each maximal segment of the trajectory which is not covered by
+    /// any loop.
+    pub fn uncovered_segments(&self) -> Vec<(VariableId, VariableId)> {
+        let mut segments: Vec<(VariableId, VariableId)> = vec![];
+        let mut is_previous_uncovered = false;
+        for (id, coverage) in &self.loop_coverage {
+            if *coverage > 0 {
+                is_previous_uncovered = false;
+            } else if is_previous_uncovered {
+                segments.last_mut().unwrap().1 = *id;
+            } else {
+                segments.push((*id, *id));
+                is_previous_uncovered = true;
+            }
+        }
+        segments
+    }
+}
+
+impl FactorGraph {
+    /// Returns the loop closure metrics of the factor graph, see [topology](topology/index.html).
+    pub fn loop_closure_metrics(&self) -> LoopClosureMetrics {
+        let var_count = self.node_indices.len();
+        let mut trajectory_indices: Vec<Option<usize>> = vec![None; var_count];
+        let mut trajectory: Vec<VariableId> = vec![];
+        for i in &self.node_indices {
+            let var = self.get_var(*i);
+            if let Variable::Vehicle2D(_) | Variable::Vehicle3D(_) = var {
+                trajectory_indices[i.index()] = Some(trajectory.len());
+                trajectory.push(var.get_id());
+            }
+        }
+
+        let mut components = UnionFind::new(var_count);
+        let mut edge_count = 0;
+        let mut loops: Vec<(usize, usize)> = vec![];
+        let mut observations: BTreeMap<usize, Vec<usize>> = BTreeMap::new();
+        for id in self.factor_id_map.keys() {
+            let indices = self.get_factor_var_indices(*id).unwrap();
+            for other in &indices[1..] {
+                components.union(indices[0].index(), other.index());
+                edge_count += 1;
+            }
+            let (source, target) = (self.factor_id_map[id].0.index(), self.factor_id_map[id].1.index());
+            match (trajectory_indices[source], trajectory_indices[target]) {
+                (Some(a), Some(b)) if a.max(b) - a.min(b) > 1 => loops.push((a.min(b), a.max(b))),
+                (Some(a), None) if self.is_landmark(target) => observations.entry(target).or_default().push(a),
+                (None, Some(b)) if self.is_landmark(source) => observations.entry(source).or_default().push(b),
+                _ => (),
+            }
+        }
+        for mut observers in observations.into_values() {
+            observers.sort_unstable();
+            observers.dedup();
+            loops.extend(observers.windows(2).map(|pair| (pair[0], pair[1])));
+        }
+
+        let mut loop_coverage: Vec<(VariableId, usize)> = trajectory.iter().map(|id| (*id, 0)).collect();
+        for (first, last) in &loops {
+            loop_coverage[*first..=*last]
+                .iter_mut()
+                .for_each(|(_, coverage)| *coverage += 1);
+        }
+        let total_length: usize = loops.iter().map(|(first, last)| last - first).sum();
+        LoopClosureMetrics {
+            cycle_space_dimension: edge_count + components.count() - var_count,
+            loop_count: loops.len(),
+            average_loop_length: if loops.is_empty() {
+                None
+            } else {
+                Some(total_length as f64 / loops.len() as f64)
+            },
+            loop_coverage,
+        }
+    }
+
+    /// Returns the IDs of the factors in a minimum spanning forest, i.e. in a minimum spanning tree of each connected
+    /// component, with respect to the given weight of each factor, see [topology](topology/index.html).
+    ///
+    /// The forest is found with Kruskal's algorithm, and factors with equal weights are preferred by their IDs. The
+    /// IDs are returned in ascending order.
+    pub fn minimum_spanning_tree<F>(&self, weight: F) -> Vec<FactorId>
+    where
+        F: Fn(FactorId) -> f64,
+    {
+        let mut edges: Vec<(f64, FactorId)> = self
+            .factor_id_map
+            .iter()
+            .filter(|(_, (source, target))| source != target)
+            .map(|(id, _)| (weight(*id), *id))
+            .collect();
+        edges.sort_by(|a, b| a.0.total_cmp(&b.0).then(a.1.cmp(&b.1)));
+        let mut components = UnionFind::new(self.node_indices.len());
+        let mut tree = vec![];
+        for (_, id) in edges {
+            let (source, target) = (self.factor_id_map[&id].0.index(), self.factor_id_map[&id].1.index());
+            if components.find(source) != components.find(target) {
+                components.union(source, target);
+                tree.push(id);
+            }
+        }
+        tree.sort_unstable();
+        tree
+    }
+
+    /// Returns the IDs of the factors in a spanning forest which follows the trajectory where possible, e.g. to
+    /// initialize the estimates by composing the odometry or to find the loop closures which close cycles.
+    ///
+    /// It is the [minimum spanning tree](#method.minimum_spanning_tree) whose weight of a factor between two vehicle
+    /// variables is the number of trajectory steps between them, so that odometry factors between consecutive poses
+    /// are preferred over loop closures. All other factors, e.g. observations, are only used to reach variables which
+    /// cannot be reached otherwise.
+    pub fn odometry_spanning_tree(&self) -> Vec<FactorId> {
+        let mut trajectory_indices: Vec<Option<usize>> = vec![None; self.node_indices.len()];
+        let mut trajectory_len = 0;
+        for i in &self.node_indices {
+            if let Variable::Vehicle2D(_) | Variable::Vehicle3D(_) = self.get_var(*i) {
+                trajectory_indices[i.index()] = Some(trajectory_len);
+                trajectory_len += 1;
+            }
+        }
+        self.minimum_spanning_tree(|id| {
+            let (source, target) = (self.factor_id_map[&id].0.index(), self.factor_id_map[&id].1.index());
+            match (trajectory_indices[source], trajectory_indices[target]) {
+                (Some(a), Some(b)) => (a.max(b) - a.min(b)) as f64,
+                _ => trajectory_len as f64,
+            }
+        })
+    }
+
+    /// Returns an independent cycle basis, i.e. the fundamental cycles of the
+    /// [odometry spanning tree](#method.odometry_spanning_tree), see [topology](topology/index.html).
+    ///
+    /// There is a cycle for each factor which is not part of the tree, ordered by the factors' IDs. Each cycle starts
+    /// with that factor, from its source to its target, and continues with the factors of the tree's path back to
+    /// the source. Their number is the dimension of the cycle space, E - V + C with E edges, V vertices and C
+    /// connected components.
+    pub fn cycle_basis(&self) -> Vec<Vec<FactorId>> {
+        let tree = self.odometry_spanning_tree();
+        let var_count = self.node_indices.len();
+        let mut neighbors: Vec<Vec<(usize, FactorId)>> = vec![vec![]; var_count];
+        for id in &tree {
+            let (source, target) = (self.factor_id_map[id].0.index(), self.factor_id_map[id].1.index());
+            neighbors[source].push((target, *id));
+            neighbors[target].push((source, *id));
+        }
+
+        // the parent and the factor to it and the depth of each variable in the rooted forest
+        let mut parents: Vec<Option<(usize, FactorId)>> = vec![None; var_count];
+        let mut depths: Vec<Option<usize>> = vec![None; var_count];
+        for root in 0..var_count {
+            if depths[root].is_some() {
+                continue;
+            }
+            depths[root] = Some(0);
+            let mut queue = VecDeque::from(vec![root]);
+            while let Some(i) = queue.pop_front() {
+                for (other, id) in &neighbors[i] {
+                    if depths[*other].is_none() {
+                        depths[*other] = Some(depths[i].unwrap() + 1);
+                        parents[*other] = Some((i, *id));
+                        queue.push_back(*other);
+                    }
+                }
+            }
+        }
+
+        self.factor_id_map
+            .iter()
+            .filter(|(id, (source, target))| source != target && tree.binary_search(id).is_err())
+            .map(|(id, (source, target))| {
+                let (mut a, mut b) = (target.index(), source.index());
+                let (mut from_target, mut from_source) = (vec![], vec![]);
+                while a != b {
+                    if depths[a] >= depths[b] {
+                        let (parent, parent_id) = parents[a].unwrap();
+                        from_target.push(parent_id);
+                        a = parent;
+                    } else {
+                        let (parent, parent_id) = parents[b].unwrap();
+                        from_source.push(parent_id);
+                        b = parent;
+                    }
+                }
+                let mut cycle = vec![*id];
+                cycle.extend(from_target);
+                cycle.extend(from_source.into_iter().rev());
+                cycle
+            })
+            .collect()
+    }
+
+    fn is_landmark(&self, index: usize) -> bool {
+        matches!(
+            self.get_var(NodeIndex::new(index)),
+            Variable::Landmark2D(_) | Variable::Landmark3D(_)
+        )
+    }
+}
+
+// disjoint sets of variables with path halving
+pub(crate) struct UnionFind {
+    parents: Vec<usize>,
+}
+
+impl UnionFind {
+    pub(crate) fn new(count: usize) -> Self {
+        UnionFind {
+            parents: (0..count).collect(),
+        }
+    }
+
+    pub(crate) fn find(&mut self, mut i: usize) -> usize {
+        while self.parents[i] != i {
+            self.parents[i] = self.parents[self.parents[i]];
+            i = self.parents[i];
+        }
+        i
+    }
+
+    pub(crate) fn union(&mut self, a: usize, b: usize) {
+        let (a, b) = (self.find(a), self.find(b));
+        self.parents[a] = b;
+    }
+
+    pub(crate) fn count(&self) -> usize {
+        self.parents
+            .iter()
+            .enumerate()
+            .filter(|(i, parent)| *i == **parent)
+            .count()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::examples_gen::triangle_with_loop_closure;
+    use crate::parser::g2o::G2oParser;
+    use crate::parser::Parser;
+
+    #[test]
+    fn test_triangle_is_covered() {
+        let factor_graph: FactorGraph = triangle_with_loop_closure().into();
+        let metrics = factor_graph.loop_closure_metrics();
+        assert_eq!(metrics.cycle_space_dimension, 1);
+        assert_eq!(metrics.loop_count, 1);
+        assert_eq!(metrics.average_loop_length, Some(2.0));
+        assert!(metrics.loop_coverage.iter().all(|(_, coverage)| *coverage == 1));
+        assert!(metrics.uncovered_segments().is_empty());
+    }
+
+    #[test]
+    fn test_loop_closures_and_landmarks() {
+        let mut lines: Vec<String> = (0..7).map(|i| format!("VERTEX_SE2 {} {} 0 0", i, i)).collect();
+        lines.push(String::from("FIX 0"));
+        lines.extend((0..6).map(|i| format!("EDGE_SE2 {} {} 1 0 0 1 0 0 1 0 1", i, i + 1)));
+        lines.push(String::from("EDGE_SE2 4 2 -2 0 0 1 0 0 1 0 1"));
+        lines.push(String::from("VERTEX_XY 10 0.5 1"));
+        lines.push(String::from("EDGE_SE2_XY 0 10 0.5 1 1 0 1"));
+        lines.push(String::from("EDGE_SE2_XY 1 10 -0.5 1 1 0 1"));
+        let factor_graph: FactorGraph = G2oParser::parse_string_to_model(&lines.join("\n")).unwrap().into();
+
+        let metrics = factor_graph.loop_closure_metrics();
+        assert_eq!(metrics.cycle_space_dimension, 2);
+        assert_eq!(metrics.loop_count, 2);
+        assert_eq!(metrics.average_loop_length, Some(1.5));
+        let coverage: Vec<usize> = metrics.loop_coverage.iter().map(|(_, coverage)| *coverage).collect();
+        assert_eq!(coverage, vec![1, 1, 1, 1, 1, 0, 0]);
+        assert_eq!(metrics.uncovered_segments(), vec![(VariableId(5), VariableId(6))]);
+    }
+
+    #[test]
+    fn test_spanning_trees_of_triangle() {
+        let factor_graph: FactorGraph = triangle_with_loop_closure().into();
+        assert_eq!(factor_graph.odometry_spanning_tree(), vec![FactorId(0), FactorId(1)]);
+        assert_eq!(
+            factor_graph.minimum_spanning_tree(|id| if id == FactorId(0) { 2.0 } else { 1.0 }),
+            vec![FactorId(1), FactorId(2)]
+        );
+        assert_eq!(
+            factor_graph.cycle_basis(),
+            vec![vec![FactorId(2), FactorId(0), FactorId(1)]]
+        );
+    }
+
+    #[test]
+    fn test_cycle_basis_spans_cycle_space() {
+        let factor_graph = G2oParser::parse_file("data_files/optimizer_tests/full2d_0.g2o").unwrap();
+        let tree = factor_graph.odometry_spanning_tree();
+        assert_eq!(tree.len(), factor_graph.node_indices.len() - 1);
+        let cycles = factor_graph.cycle_basis();
+        assert_eq!(cycles.len(), factor_graph.loop_closure_metrics().cycle_space_dimension);
+
+        // each cycle is closed, i.e. it passes each of its variables an even number of times
+        for cycle in &cycles {
+            let mut degrees: BTreeMap<NodeIndex, usize> = BTreeMap::new();
+            for id in cycle {
+                let (source, target) = factor_graph.factor_id_map[id];
+                *degrees.entry(source).or_default() += 1;
+                *degrees.entry(target).or_default() += 1;
+            }
+            assert!(degrees.values().all(|degree| *degree == 2));
+            assert!(cycle[1..].iter().all(|id| tree.contains(id)));
+        }
+    }
+}
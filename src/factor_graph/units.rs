@@ -0,0 +1,355 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Length units of factor graphs and sanity checks of the declared unit, e.g. before merging datasets, whose
+//! different units would otherwise silently corrupt the merged factor graph.
+//!
+//! The declared unit is part of [models](../../parser/model/struct.FactorGraphModel.html) and does not change the
+//! optimization. The checks compare the typical length of the odometry steps with the lengths which are plausible
+//! for a vehicle, see [UnitCheckConfig](struct.UnitCheckConfig.html).
+
+#[cfg(feature = "std")]
+use crate::factor_graph::factor::FactorType;
+#[cfg(feature = "std")]
+use crate::factor_graph::FactorGraph;
+#[cfg(feature = "std")]
+use core::fmt;
+use serde::{Deserialize, Serialize};
+
+/// The unit of the positions and translations of a factor graph.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LengthUnit {
+    /// Meters, the unit assumed if none is declared.
+    Meters,
+    /// Millimeters, e.g. of datasets recorded with motion capture systems.
+    Millimeters,
+}
+
+impl LengthUnit {
+    /// Returns the length of one unit in meters.
+    pub fn meters_per_unit(self) -> f64 {
+        match self {
+            LengthUnit::Meters => 1.0,
+            LengthUnit::Millimeters => 0.001,
+        }
+    }
+
+    /// Returns the unit's name as used in serialized files, i.e. "meters" or "millimeters".
+    pub fn name(self) -> &'static str {
+        match self {
+            LengthUnit::Meters => "meters",
+            LengthUnit::Millimeters => "millimeters",
+        }
+    }
+
+    /// Returns the unit with the given name, see [name](#method.name).
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "meters" => Some(LengthUnit::Meters),
+            "millimeters" => Some(LengthUnit::Millimeters),
+            _ => None,
+        }
+    }
+}
+
+/// Configuration of the unit checks, see [check_unit](../struct.FactorGraph.html#method.check_unit).
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnitCheckConfig {
+    /// The shortest plausible median length of the odometry steps in meters.
+    pub min_step_length: f64,
+    /// The longest plausible median length of the odometry steps in meters.
+    pub max_step_length: f64,
+    /// The largest plausible ratio between the median odometry step lengths of two factor graphs.
+    pub max_step_ratio: f64,
+}
+
+#[cfg(feature = "std")]
+impl Default for UnitCheckConfig {
+    fn default() -> Self {
+        UnitCheckConfig {
+            min_step_length: 0.005,
+            max_step_length: 50.0,
+            max_step_ratio: 100.0,
+        }
+    }
+}
+
+/// A likely mismatch between the declared and the actual unit of factor graphs.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, PartialEq)]
+pub enum UnitWarning {
+    /// The median odometry step length in the declared unit is implausible, but would be plausible in the other
+    /// unit.
+    ImplausibleStepLength {
+        /// The declared unit.
+        declared: LengthUnit,
+        /// The median odometry step length in the declared unit.
+        median_step_length: f64,
+        /// The unit in which the step length would be plausible.
+        likely_unit: LengthUnit,
+    },
+    /// Two factor graphs declare different units, so their positions are not comparable without a conversion.
+    DifferentUnits {
+        /// The unit of the first factor graph.
+        own: LengthUnit,
+        /// The unit of the second factor graph.
+        other: LengthUnit,
+    },
+    /// The median odometry step lengths of two factor graphs in meters differ by more than the plausible ratio,
+    /// e.g. since one of them declares no or the wrong unit.
+    DifferentStepLengths {
+        /// The median odometry step length of the first factor graph in meters.
+        own: f64,
+        /// The median odometry step length of the second factor graph in meters.
+        other: f64,
+    },
+}
+
+#[cfg(feature = "std")]
+impl fmt::Display for UnitWarning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UnitWarning::ImplausibleStepLength {
+                declared,
+                median_step_length,
+                likely_unit,
+            } => write!(
+                f,
+                "The median odometry step of {} {} is implausible, the factor graph is likely in {}",
+                median_step_length,
+                declared.name(),
+                likely_unit.name()
+            ),
+            UnitWarning::DifferentUnits { own, other } => write!(
+                f,
+                "The factor graphs are in different units: {} and {}",
+                own.name(),
+                other.name()
+            ),
+            UnitWarning::DifferentStepLengths { own, other } => write!(
+                f,
+                "The median odometry steps of {} meters and {} meters suggest that the factor graphs are in different \
+                 units",
+                own, other
+            ),
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl FactorGraph {
+    /// Returns the declared unit of the factor graph, if there is one.
+    pub fn unit(&self) -> Option<LengthUnit> {
+        self.unit
+    }
+
+    /// Declares the unit of the factor graph without changing any estimates or measurements.
+    pub fn set_unit(&mut self, unit: Option<LengthUnit>) {
+        self.unit = unit;
+    }
+
+    /// Returns the median length of the translations measured by the odometry factors, including switchable ones, in
+    /// the factor graph's unit, or None if there are no odometry factors.
+    pub fn median_odometry_step(&self) -> Option<f64> {
+        let mut lengths: Vec<f64> = self
+            .factor_id_map
+            .keys()
+            .filter_map(|id| self.get_factor(*id))
+            .filter_map(|factor| {
+                let factor = self.materialize_factor(factor);
+                let dim = match factor.factor_type {
+                    FactorType::Odometry2D
+                    | FactorType::SwitchableOdometry2D
+                    | FactorType::CalibratedOdometry2D
+                    | FactorType::AnchoredOdometry2D => 2,
+                    FactorType::Odometry3D | FactorType::SwitchableOdometry3D | FactorType::AnchoredOdometry3D => 3,
+                    _ => return None,
+                };
+                Some(factor.constraint[..dim].iter().map(|v| v * v).sum::<f64>().sqrt())
+            })
+            .collect();
+        if lengths.is_empty() {
+            return None;
+        }
+        lengths.sort_by(|a, b| a.partial_cmp(b).unwrap());
+        Some(lengths[lengths.len() / 2])
+    }
+
+    /// Returns a warning if the median odometry step length is implausible in the declared unit, but plausible in
+    /// the other unit, e.g. for a factor graph in millimeters which declares meters.
+    ///
+    /// Factor graphs without a declared unit or without odometry factors are not checked.
+    ///
+    /// ```
+    /// use gs_rs::examples_gen::triangle_with_loop_closure;
+    /// use gs_rs::factor_graph::FactorGraph;
+    /// use gs_rs::factor_graph::units::{LengthUnit, UnitWarning};
+    ///
+    /// let mut factor_graph: FactorGraph = triangle_with_loop_closure().into();
+    /// factor_graph.set_unit(Some(LengthUnit::Meters));
+    /// assert_eq!(factor_graph.check_unit(&Default::default()), None);
+    /// factor_graph.set_unit(Some(LengthUnit::Millimeters));
+    /// assert_eq!(
+    ///     factor_graph.check_unit(&Default::default()),
+    ///     Some(UnitWarning::ImplausibleStepLength {
+    ///         declared: LengthUnit::Millimeters,
+    ///         median_step_length: 1.0,
+    ///         likely_unit: LengthUnit::Meters,
+    ///     })
+    /// );
+    /// ```
+    pub fn check_unit(&self, config: &UnitCheckConfig) -> Option<UnitWarning> {
+        let declared = self.unit?;
+        let median_step_length = self.median_odometry_step()?;
+        let is_plausible = |unit: LengthUnit| {
+            let length = median_step_length * unit.meters_per_unit();
+            length >= config.min_step_length && length <= config.max_step_length
+        };
+        let likely_unit = match declared {
+            LengthUnit::Meters => LengthUnit::Millimeters,
+            LengthUnit::Millimeters => LengthUnit::Meters,
+        };
+        if is_plausible(declared) || !is_plausible(likely_unit) {
+            return None;
+        }
+        Some(UnitWarning::ImplausibleStepLength {
+            declared,
+            median_step_length,
+            likely_unit,
+        })
+    }
+
+    /// Returns a warning if this and the other factor graph are likely in different units, i.e. if they declare
+    /// different units or if their median odometry step lengths in meters differ by more than the plausible ratio.
+    /// Factor graphs without a declared unit are assumed to be in meters.
+    ///
+    /// Sessions which are merged, see [merge_session](#method.merge_session), are expected to be in the same unit.
+    pub fn check_unit_consistency(&self, other: &FactorGraph, config: &UnitCheckConfig) -> Option<UnitWarning> {
+        if let (Some(own), Some(other)) = (self.unit, other.unit) {
+            if own != other {
+                return Some(UnitWarning::DifferentUnits { own, other });
+            }
+        }
+        let get_step_in_meters = |factor_graph: &FactorGraph| {
+            let meters_per_unit = factor_graph.unit.map_or(1.0, LengthUnit::meters_per_unit);
+            factor_graph.median_odometry_step().map(|step| step * meters_per_unit)
+        };
+        let (own, other) = (get_step_in_meters(self)?, get_step_in_meters(other)?);
+        if own.max(other) > config.max_step_ratio * own.min(other) {
+            Some(UnitWarning::DifferentStepLengths { own, other })
+        } else {
+            None
+        }
+    }
+}
+
+#[cfg(all(test, feature = "std"))]
+mod tests {
+    use super::*;
+    use crate::examples_gen::{single_landmark_with_two_observations, triangle_with_loop_closure};
+    use crate::factor_graph::variable::VariableId;
+    use crate::parser::json::JsonParser;
+    use crate::parser::model::FactorGraphModel;
+    use crate::parser::Parser;
+
+    // returns the triangle with all positions and translations in millimeters, with the given declared unit
+    fn get_triangle_in_millimeters(unit: Option<LengthUnit>) -> FactorGraph {
+        let mut model = triangle_with_loop_closure();
+        model
+            .vertices
+            .iter_mut()
+            .for_each(|v| v.content[..2].iter_mut().for_each(|x| *x *= 1000.0));
+        model
+            .edges
+            .iter_mut()
+            .for_each(|e| e.restriction[..2].iter_mut().for_each(|x| *x *= 1000.0));
+        model.unit = unit;
+        model.into()
+    }
+
+    #[test]
+    fn test_check_unit() {
+        let config = UnitCheckConfig::default();
+        let factor_graph = get_triangle_in_millimeters(Some(LengthUnit::Meters));
+        assert_eq!(factor_graph.median_odometry_step(), Some(1000.0));
+        let mut offloaded = get_triangle_in_millimeters(Some(LengthUnit::Meters));
+        let path = std::env::temp_dir().join(format!("gs-rs-units-{}.bin", std::process::id()));
+        offloaded.offload_factor_payloads(&path).unwrap();
+        assert_eq!(offloaded.median_odometry_step(), Some(1000.0));
+        std::fs::remove_file(path).unwrap();
+        let warning = factor_graph.check_unit(&config).unwrap();
+        assert_eq!(
+            warning,
+            UnitWarning::ImplausibleStepLength {
+                declared: LengthUnit::Meters,
+                median_step_length: 1000.0,
+                likely_unit: LengthUnit::Millimeters,
+            }
+        );
+        assert_eq!(
+            warning.to_string(),
+            "The median odometry step of 1000 meters is implausible, the factor graph is likely in millimeters"
+        );
+        assert_eq!(
+            get_triangle_in_millimeters(Some(LengthUnit::Millimeters)).check_unit(&config),
+            None
+        );
+        assert_eq!(get_triangle_in_millimeters(None).check_unit(&config), None);
+        // without odometry, the unit cannot be checked
+        let mut factor_graph: FactorGraph = single_landmark_with_two_observations().into();
+        let odometry = factor_graph.factors_between(VariableId(0), VariableId(1))[0];
+        factor_graph.remove_factor(odometry).unwrap();
+        factor_graph.set_unit(Some(LengthUnit::Millimeters));
+        assert_eq!(factor_graph.median_odometry_step(), None);
+        assert_eq!(factor_graph.check_unit(&config), None);
+    }
+
+    #[test]
+    fn test_check_unit_consistency() {
+        let config = UnitCheckConfig::default();
+        let meters: FactorGraph = triangle_with_loop_closure().into();
+        assert_eq!(meters.check_unit_consistency(&meters, &config), None);
+        assert_eq!(
+            meters.check_unit_consistency(&get_triangle_in_millimeters(None), &config),
+            Some(UnitWarning::DifferentStepLengths {
+                own: 1.0,
+                other: 1000.0,
+            })
+        );
+        let millimeters = get_triangle_in_millimeters(Some(LengthUnit::Millimeters));
+        assert_eq!(meters.check_unit_consistency(&millimeters, &config), None);
+        let mut declared_meters: FactorGraph = triangle_with_loop_closure().into();
+        declared_meters.set_unit(Some(LengthUnit::Meters));
+        assert_eq!(
+            declared_meters.check_unit_consistency(&millimeters, &config),
+            Some(UnitWarning::DifferentUnits {
+                own: LengthUnit::Meters,
+                other: LengthUnit::Millimeters,
+            })
+        );
+    }
+
+    #[test]
+    fn test_unit_is_serialized() {
+        let model = FactorGraphModel::from(&get_triangle_in_millimeters(Some(LengthUnit::Millimeters)));
+        let json = JsonParser::compose_model_to_string(model).unwrap();
+        assert!(json.contains("\"unit\": \"millimeters\""));
+        let factor_graph: FactorGraph = JsonParser::parse_string_to_model(&json).unwrap().into();
+        assert_eq!(factor_graph.unit(), Some(LengthUnit::Millimeters));
+        let json = JsonParser::compose_model_to_string(triangle_with_loop_closure()).unwrap();
+        assert!(!json.contains("unit"));
+        assert_eq!(LengthUnit::from_name("meters"), Some(LengthUnit::Meters));
+        assert_eq!(LengthUnit::from_name("inches"), None);
+    }
+}
@@ -12,9 +12,40 @@
 
 //! The internal representation of a factor graph's optimizable variable.
 
-use std::cell::RefCell;
-use std::ops::Range;
-use std::rc::Rc;
+use alloc::boxed::Box;
+use alloc::rc::Rc;
+use alloc::vec::Vec;
+use core::cell::RefCell;
+use core::fmt;
+use core::ops::Range;
+#[cfg(feature = "std")]
+use parameterization::{
+    EuclideanParameterization, LineParameterization, LocalParameterization, PlaneParameterization, Se2Parameterization,
+    Se3Parameterization, Sim3Parameterization, SwitchParameterization,
+};
+use serde::{Deserialize, Serialize};
+
+#[cfg(feature = "std")]
+pub mod parameterization;
+
+/// Type-safe ID of a variable, as stated in the parsed file or chosen by the user.
+///
+/// Not to be confused with the internal index at which the variable is stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct VariableId(pub usize);
+
+impl fmt::Display for VariableId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl From<usize> for VariableId {
+    fn from(id: usize) -> Self {
+        VariableId(id)
+    }
+}
 
 #[derive(Debug, Eq, PartialEq)]
 pub enum FixedType {
@@ -25,7 +56,7 @@ pub enum FixedType {
 /// Representation of an optimizable vehicle variable.
 #[derive(Debug)]
 pub struct VehicleVariable2D {
-    pub id: usize,
+    pub id: VariableId,
     pub pose: Rc<RefCell<[f64; 3]>>,
     pub fixed_type: FixedType,
 }
@@ -33,15 +64,24 @@ pub struct VehicleVariable2D {
 /// Representation of an optimizable landmark variable.
 #[derive(Debug)]
 pub struct LandmarkVariable2D {
-    pub id: usize,
+    pub id: VariableId,
     pub position: Rc<RefCell<[f64; 2]>>,
     pub fixed_type: FixedType,
 }
 
+/// Representation of an optimizable line landmark variable in 2D, given in Hessian normal form by the angle of its
+/// normal and its distance to the origin.
+#[derive(Debug)]
+pub struct LineLandmarkVariable2D {
+    pub id: VariableId,
+    pub line: Rc<RefCell<[f64; 2]>>,
+    pub fixed_type: FixedType,
+}
+
 /// Representation of an optimizable vehicle variable.
 #[derive(Debug)]
 pub struct VehicleVariable3D {
-    pub id: usize,
+    pub id: VariableId,
     pub pose: Rc<RefCell<[f64; 7]>>,
     pub fixed_type: FixedType,
 }
@@ -49,11 +89,103 @@ pub struct VehicleVariable3D {
 /// Representation of an optimizable landmark variable.
 #[derive(Debug)]
 pub struct LandmarkVariable3D {
-    pub id: usize,
+    pub id: VariableId,
     pub position: Rc<RefCell<[f64; 3]>>,
     pub fixed_type: FixedType,
 }
 
+/// Representation of an optimizable vehicle variable with a scale, whose pose is an element of Sim(3).
+#[derive(Debug)]
+pub struct VehicleVariableSim3 {
+    pub id: VariableId,
+    pub pose: Rc<RefCell<[f64; 8]>>,
+    pub fixed_type: FixedType,
+}
+
+/// Representation of an optimizable infinite plane landmark variable, given by its unit normal and its distance to
+/// the origin.
+#[derive(Debug)]
+pub struct PlaneVariable3D {
+    pub id: VariableId,
+    pub plane: Rc<RefCell<[f64; 4]>>,
+    pub fixed_type: FixedType,
+}
+
+/// Representation of an optimizable switch variable, which scales the error of a switchable factor.
+///
+/// A value of 1 keeps the factor fully active, 0 switches it off.
+#[derive(Debug)]
+pub struct SwitchVariable {
+    pub id: VariableId,
+    pub value: Rc<RefCell<[f64; 1]>>,
+    pub fixed_type: FixedType,
+}
+
+/// Representation of an optimizable velocity variable in 3D, e.g. of an IMU-equipped vehicle.
+#[derive(Debug)]
+pub struct VelocityVariable3D {
+    pub id: VariableId,
+    pub velocity: Rc<RefCell<[f64; 3]>>,
+    pub fixed_type: FixedType,
+}
+
+/// Representation of an optimizable IMU bias variable, i.e. the biases of the accelerometer and the gyroscope.
+#[derive(Debug)]
+pub struct ImuBiasVariable {
+    pub id: VariableId,
+    pub bias: Rc<RefCell<[f64; 6]>>,
+    pub fixed_type: FixedType,
+}
+
+/// Representation of an optimizable calibration of the odometry of a vehicle in 2D, which is shared by all of its
+/// calibrated odometry factors.
+///
+/// The calibration consists of the scale of the measured translations, the scale of the measured rotations and the
+/// bias of the measured rotations per travelled distance, e.g. due to wrong wheel radii or a wrong track width of a
+/// differential drive. A calibration of [1, 1, 0] leaves the odometry unchanged.
+#[derive(Debug)]
+pub struct OdometryCalibrationVariable2D {
+    pub id: VariableId,
+    pub calibration: Rc<RefCell<[f64; 3]>>,
+    pub fixed_type: FixedType,
+}
+
+/// Representation of an optimizable extrinsic calibration of a sensor in 2D, i.e. its pose [x, y, rotation] in the
+/// frame of the vehicle, which is shared by all factors of the sensor.
+#[derive(Debug)]
+pub struct ExtrinsicVariable2D {
+    pub id: VariableId,
+    pub pose: Rc<RefCell<[f64; 3]>>,
+    pub fixed_type: FixedType,
+}
+
+/// Representation of an optimizable extrinsic calibration of a sensor in 3D, i.e. its pose [x, y, z, q_x, q_y, q_z,
+/// q_w] in the frame of the vehicle, which is shared by all factors of the sensor.
+#[derive(Debug)]
+pub struct ExtrinsicVariable3D {
+    pub id: VariableId,
+    pub pose: Rc<RefCell<[f64; 7]>>,
+    pub fixed_type: FixedType,
+}
+
+/// Representation of an optimizable anchor of a mapping session in 2D, i.e. the pose [x, y, rotation] of the
+/// session's frame in the world frame, in which the variables of the session are expressed.
+#[derive(Debug)]
+pub struct AnchorVariable2D {
+    pub id: VariableId,
+    pub pose: Rc<RefCell<[f64; 3]>>,
+    pub fixed_type: FixedType,
+}
+
+/// Representation of an optimizable anchor of a mapping session in 3D, i.e. the pose [x, y, z, q_x, q_y, q_z, q_w]
+/// of the session's frame in the world frame, in which the variables of the session are expressed.
+#[derive(Debug)]
+pub struct AnchorVariable3D {
+    pub id: VariableId,
+    pub pose: Rc<RefCell<[f64; 7]>>,
+    pub fixed_type: FixedType,
+}
+
 /// Enum representing a supported variable type.
 #[derive(Debug)]
 pub enum Variable {
@@ -65,10 +197,106 @@ pub enum Variable {
     Vehicle3D(VehicleVariable3D),
     /// Landmark position in 3D.
     Landmark3D(LandmarkVariable3D),
+    /// Switch of a switchable factor.
+    Switch(SwitchVariable),
+    /// Vehicle velocity in 3D, in the world frame.
+    Velocity3D(VelocityVariable3D),
+    /// Accelerometer and gyroscope biases of an IMU.
+    ImuBias(ImuBiasVariable),
+    /// Vehicle pose (position and rotation) in 3D with a scale, e.g. of a monocular camera whose trajectory is only
+    /// known up to a drifting scale.
+    VehicleSim3(VehicleVariableSim3),
+    /// Infinite plane landmark in 3D, i.e. the points x with n * x = d for its unit normal n and distance d.
+    Plane3D(PlaneVariable3D),
+    /// Infinite line landmark in 2D, i.e. the points x with (cos(angle), sin(angle)) * x = distance, e.g. a wall of
+    /// a corridor.
+    LineLandmark2D(LineLandmarkVariable2D),
+    /// Systematic errors of the odometry of a vehicle in 2D, i.e. its translation scale, rotation scale and rotation
+    /// bias per distance.
+    OdometryCalibration2D(OdometryCalibrationVariable2D),
+    /// Pose of a sensor in the frame of the vehicle in 2D.
+    Extrinsic2D(ExtrinsicVariable2D),
+    /// Pose of a sensor in the frame of the vehicle in 3D.
+    Extrinsic3D(ExtrinsicVariable3D),
+    /// Pose of the frame of a mapping session in the world frame in 2D.
+    Anchor2D(AnchorVariable2D),
+    /// Pose of the frame of a mapping session in the world frame in 3D.
+    Anchor3D(AnchorVariable3D),
+    /// Variable of a user-defined type, e.g. a pose in 2D with its velocity or the angles of articulated joints.
+    UserDefined(UserDefinedVariable),
+}
+
+/// State and manifold of a user-defined variable, which is added with
+/// [add_custom_variable](../struct.FactorGraph.html#method.add_custom_variable).
+///
+/// The content returned by get() is the variable's content in the factor graph, e.g. as passed to the factors, while
+/// corrections have dim() entries, so that the content may have more entries than the tangent space has dimensions.
+pub trait CustomVariable {
+    /// Returns the name of the variable's type, e.g. the vertex type of the variable in a composed model.
+    fn name(&self) -> &str;
+
+    /// Returns the dimension of the tangent space, i.e. the number of rows of the variable in H.
+    fn dim(&self) -> usize;
+
+    /// Returns the variable's content.
+    fn get(&self) -> Vec<f64>;
+
+    /// Sets the variable's content, which has the format returned by get().
+    fn set(&mut self, content: Vec<f64>);
+
+    /// Returns the given content after applying the correction, which has dim() entries, i.e. the boxplus operation
+    /// of the manifold. A zero correction is expected to leave the content unchanged.
+    fn retract(&self, content: &[f64], correction: &[f64]) -> Vec<f64>;
+
+    /// Returns a copy of the variable which does not share its state with it, e.g. for the subgraphs of connected
+    /// components.
+    fn clone_box(&self) -> Box<dyn CustomVariable>;
+}
+
+/// Representation of an optimizable variable of a user-defined type.
+pub struct UserDefinedVariable {
+    pub id: VariableId,
+    pub state: Rc<RefCell<Box<dyn CustomVariable>>>,
+    pub fixed_type: FixedType,
+}
+
+impl fmt::Debug for UserDefinedVariable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let state = self.state.borrow();
+        f.debug_struct("UserDefinedVariable")
+            .field("id", &self.id)
+            .field("name", &state.name())
+            .field("content", &state.get())
+            .field("fixed_type", &self.fixed_type)
+            .finish()
+    }
+}
+
+impl UserDefinedVariable {
+    /// Returns a new variable from a user-defined state, a given ID and whether the variable is fixed.
+    pub fn new(id: VariableId, state: Box<dyn CustomVariable>, fixed_type: FixedType) -> Self {
+        UserDefinedVariable {
+            id,
+            state: Rc::new(RefCell::new(state)),
+            fixed_type,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl LocalParameterization for UserDefinedVariable {
+    fn tangent_dim(&self) -> usize {
+        self.state.borrow().dim()
+    }
+
+    fn plus(&self, content: &[f64], correction: &[f64]) -> Vec<f64> {
+        self.state.borrow().retract(content, correction)
+    }
 }
+
 impl VehicleVariable2D {
     /// Returns a new variable from a 2D pose, a given ID and whether the variable is fixed.
-    pub fn new(id: usize, x: f64, y: f64, phi: f64, fixed_type: FixedType) -> Self {
+    pub fn new(id: VariableId, x: f64, y: f64, phi: f64, fixed_type: FixedType) -> Self {
         VehicleVariable2D {
             id,
             pose: Rc::new(RefCell::new([x, y, phi])),
@@ -79,7 +307,7 @@ impl VehicleVariable2D {
 
 impl LandmarkVariable2D {
     /// Returns a new variable from a 2D position, a given ID and whether the variable is fixed.
-    pub fn new(id: usize, x: f64, y: f64, fixed_type: FixedType) -> Self {
+    pub fn new(id: VariableId, x: f64, y: f64, fixed_type: FixedType) -> Self {
         LandmarkVariable2D {
             id,
             position: Rc::new(RefCell::new([x, y])),
@@ -88,10 +316,22 @@ impl LandmarkVariable2D {
     }
 }
 
+impl LineLandmarkVariable2D {
+    /// Returns a new variable from a line in Hessian normal form, a given ID and whether the variable is fixed.
+    pub fn new(id: VariableId, angle: f64, distance: f64, fixed_type: FixedType) -> Self {
+        LineLandmarkVariable2D {
+            id,
+            line: Rc::new(RefCell::new([angle, distance])),
+            fixed_type,
+        }
+    }
+}
+
 impl VehicleVariable3D {
     /// Returns a new variable from a 3D pose, a given ID and whether the variable is fixed.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
-        id: usize,
+        id: VariableId,
         x: f64,
         y: f64,
         z: f64,
@@ -111,7 +351,7 @@ impl VehicleVariable3D {
 
 impl LandmarkVariable3D {
     /// Returns a new variable from a 3D position, a given ID and whether the variable is fixed.
-    pub fn new(id: usize, x: f64, y: f64, z: f64, fixed_type: FixedType) -> Self {
+    pub fn new(id: VariableId, x: f64, y: f64, z: f64, fixed_type: FixedType) -> Self {
         LandmarkVariable3D {
             id,
             position: Rc::new(RefCell::new([x, y, z])),
@@ -120,6 +360,123 @@ impl LandmarkVariable3D {
     }
 }
 
+impl VehicleVariableSim3 {
+    /// Returns a new variable from a 3D pose with a scale [x, y, z, rot_x, rot_y, rot_z, rot_w, scale], a given ID and
+    /// whether the variable is fixed.
+    pub fn new(id: VariableId, pose: [f64; 8], fixed_type: FixedType) -> Self {
+        VehicleVariableSim3 {
+            id,
+            pose: Rc::new(RefCell::new(pose)),
+            fixed_type,
+        }
+    }
+}
+
+impl PlaneVariable3D {
+    /// Returns a new variable from a plane [normal_x, normal_y, normal_z, distance], a given ID and whether the
+    /// variable is fixed. The normal is normalized.
+    pub fn new(id: VariableId, plane: [f64; 4], fixed_type: FixedType) -> Self {
+        let norm = (plane[0] * plane[0] + plane[1] * plane[1] + plane[2] * plane[2]).sqrt();
+        PlaneVariable3D {
+            id,
+            plane: Rc::new(RefCell::new([plane[0] / norm, plane[1] / norm, plane[2] / norm, plane[3]])),
+            fixed_type,
+        }
+    }
+}
+
+impl SwitchVariable {
+    /// Returns a new variable from a switch value, a given ID and whether the variable is fixed.
+    pub fn new(id: VariableId, value: f64, fixed_type: FixedType) -> Self {
+        SwitchVariable {
+            id,
+            value: Rc::new(RefCell::new([value])),
+            fixed_type,
+        }
+    }
+}
+
+impl VelocityVariable3D {
+    /// Returns a new variable from a 3D velocity, a given ID and whether the variable is fixed.
+    pub fn new(id: VariableId, x: f64, y: f64, z: f64, fixed_type: FixedType) -> Self {
+        VelocityVariable3D {
+            id,
+            velocity: Rc::new(RefCell::new([x, y, z])),
+            fixed_type,
+        }
+    }
+}
+
+impl ImuBiasVariable {
+    /// Returns a new variable from the biases [acc_x, acc_y, acc_z, gyro_x, gyro_y, gyro_z], a given ID and whether
+    /// the variable is fixed.
+    pub fn new(id: VariableId, bias: [f64; 6], fixed_type: FixedType) -> Self {
+        ImuBiasVariable {
+            id,
+            bias: Rc::new(RefCell::new(bias)),
+            fixed_type,
+        }
+    }
+}
+
+impl OdometryCalibrationVariable2D {
+    /// Returns a new variable from the calibration [translation_scale, rotation_scale, rotation_bias], a given ID and
+    /// whether the variable is fixed.
+    pub fn new(id: VariableId, calibration: [f64; 3], fixed_type: FixedType) -> Self {
+        OdometryCalibrationVariable2D {
+            id,
+            calibration: Rc::new(RefCell::new(calibration)),
+            fixed_type,
+        }
+    }
+}
+
+impl ExtrinsicVariable2D {
+    /// Returns a new variable from the sensor's pose [x, y, rotation], a given ID and whether the variable is fixed.
+    pub fn new(id: VariableId, pose: [f64; 3], fixed_type: FixedType) -> Self {
+        ExtrinsicVariable2D {
+            id,
+            pose: Rc::new(RefCell::new(pose)),
+            fixed_type,
+        }
+    }
+}
+
+impl ExtrinsicVariable3D {
+    /// Returns a new variable from the sensor's pose [x, y, z, q_x, q_y, q_z, q_w], a given ID and whether the
+    /// variable is fixed.
+    pub fn new(id: VariableId, pose: [f64; 7], fixed_type: FixedType) -> Self {
+        ExtrinsicVariable3D {
+            id,
+            pose: Rc::new(RefCell::new(pose)),
+            fixed_type,
+        }
+    }
+}
+
+impl AnchorVariable2D {
+    /// Returns a new variable from the session's pose [x, y, rotation], a given ID and whether the variable is fixed.
+    pub fn new(id: VariableId, pose: [f64; 3], fixed_type: FixedType) -> Self {
+        AnchorVariable2D {
+            id,
+            pose: Rc::new(RefCell::new(pose)),
+            fixed_type,
+        }
+    }
+}
+
+impl AnchorVariable3D {
+    /// Returns a new variable from the session's pose [x, y, z, q_x, q_y, q_z, q_w], a given ID and whether the
+    /// variable is fixed.
+    pub fn new(id: VariableId, pose: [f64; 7], fixed_type: FixedType) -> Self {
+        AnchorVariable3D {
+            id,
+            pose: Rc::new(RefCell::new(pose)),
+            fixed_type,
+        }
+    }
+}
+
 impl Variable {
     pub fn get_fixed_type(&self) -> &FixedType {
         match self {
@@ -127,8 +484,85 @@ impl Variable {
             Variable::Landmark2D(v) => &v.fixed_type,
             Variable::Vehicle3D(v) => &v.fixed_type,
             Variable::Landmark3D(v) => &v.fixed_type,
+            Variable::Switch(v) => &v.fixed_type,
+            Variable::Velocity3D(v) => &v.fixed_type,
+            Variable::ImuBias(v) => &v.fixed_type,
+            Variable::VehicleSim3(v) => &v.fixed_type,
+            Variable::Plane3D(v) => &v.fixed_type,
+            Variable::LineLandmark2D(v) => &v.fixed_type,
+            Variable::OdometryCalibration2D(v) => &v.fixed_type,
+            Variable::Extrinsic2D(v) => &v.fixed_type,
+            Variable::Extrinsic3D(v) => &v.fixed_type,
+            Variable::Anchor2D(v) => &v.fixed_type,
+            Variable::Anchor3D(v) => &v.fixed_type,
+            Variable::UserDefined(v) => &v.fixed_type,
         }
     }
+    /// Replaces the fixed type, i.e. whether the variable is optimized and its range in H.
+    pub fn set_fixed_type(&mut self, fixed_type: FixedType) {
+        match self {
+            Variable::Vehicle2D(v) => v.fixed_type = fixed_type,
+            Variable::Landmark2D(v) => v.fixed_type = fixed_type,
+            Variable::Vehicle3D(v) => v.fixed_type = fixed_type,
+            Variable::Landmark3D(v) => v.fixed_type = fixed_type,
+            Variable::Switch(v) => v.fixed_type = fixed_type,
+            Variable::Velocity3D(v) => v.fixed_type = fixed_type,
+            Variable::ImuBias(v) => v.fixed_type = fixed_type,
+            Variable::VehicleSim3(v) => v.fixed_type = fixed_type,
+            Variable::Plane3D(v) => v.fixed_type = fixed_type,
+            Variable::LineLandmark2D(v) => v.fixed_type = fixed_type,
+            Variable::OdometryCalibration2D(v) => v.fixed_type = fixed_type,
+            Variable::Extrinsic2D(v) => v.fixed_type = fixed_type,
+            Variable::Extrinsic3D(v) => v.fixed_type = fixed_type,
+            Variable::Anchor2D(v) => v.fixed_type = fixed_type,
+            Variable::Anchor3D(v) => v.fixed_type = fixed_type,
+            Variable::UserDefined(v) => v.fixed_type = fixed_type,
+        }
+    }
+    pub fn get_content(&self) -> Vec<f64> {
+        match self {
+            Variable::Vehicle2D(v) => v.pose.borrow().to_vec(),
+            Variable::Landmark2D(v) => v.position.borrow().to_vec(),
+            Variable::Vehicle3D(v) => v.pose.borrow().to_vec(),
+            Variable::Landmark3D(v) => v.position.borrow().to_vec(),
+            Variable::Switch(v) => v.value.borrow().to_vec(),
+            Variable::Velocity3D(v) => v.velocity.borrow().to_vec(),
+            Variable::ImuBias(v) => v.bias.borrow().to_vec(),
+            Variable::VehicleSim3(v) => v.pose.borrow().to_vec(),
+            Variable::Plane3D(v) => v.plane.borrow().to_vec(),
+            Variable::LineLandmark2D(v) => v.line.borrow().to_vec(),
+            Variable::OdometryCalibration2D(v) => v.calibration.borrow().to_vec(),
+            Variable::Extrinsic2D(v) => v.pose.borrow().to_vec(),
+            Variable::Extrinsic3D(v) => v.pose.borrow().to_vec(),
+            Variable::Anchor2D(v) => v.pose.borrow().to_vec(),
+            Variable::Anchor3D(v) => v.pose.borrow().to_vec(),
+            Variable::UserDefined(v) => v.state.borrow().get(),
+        }
+    }
+
+    /// Returns the parameterization which applies corrections to the variable's content.
+    #[cfg(feature = "std")]
+    pub fn get_parameterization(&self) -> &dyn LocalParameterization {
+        match self {
+            Variable::Vehicle2D(_) => &Se2Parameterization,
+            Variable::Landmark2D(_) => &EuclideanParameterization::<2>,
+            Variable::Vehicle3D(_) => &Se3Parameterization,
+            Variable::Landmark3D(_) => &EuclideanParameterization::<3>,
+            Variable::Switch(_) => &SwitchParameterization,
+            Variable::Velocity3D(_) => &EuclideanParameterization::<3>,
+            Variable::ImuBias(_) => &EuclideanParameterization::<6>,
+            Variable::VehicleSim3(_) => &Sim3Parameterization,
+            Variable::Plane3D(_) => &PlaneParameterization,
+            Variable::LineLandmark2D(_) => &LineParameterization,
+            Variable::OdometryCalibration2D(_) => &EuclideanParameterization::<3>,
+            Variable::Extrinsic2D(_) => &Se2Parameterization,
+            Variable::Extrinsic3D(_) => &Se3Parameterization,
+            Variable::Anchor2D(_) => &Se2Parameterization,
+            Variable::Anchor3D(_) => &Se3Parameterization,
+            Variable::UserDefined(v) => v,
+        }
+    }
+
     pub fn set_content(&self, update: Vec<f64>) {
         let u = update;
         match self {
@@ -136,14 +570,38 @@ impl Variable {
             Variable::Landmark2D(v) => *v.position.borrow_mut() = [u[0], u[1]],
             Variable::Vehicle3D(v) => *v.pose.borrow_mut() = [u[0], u[1], u[2], u[3], u[4], u[5], u[6]],
             Variable::Landmark3D(v) => *v.position.borrow_mut() = [u[0], u[1], u[2]],
+            Variable::Switch(v) => *v.value.borrow_mut() = [u[0]],
+            Variable::Velocity3D(v) => *v.velocity.borrow_mut() = [u[0], u[1], u[2]],
+            Variable::ImuBias(v) => *v.bias.borrow_mut() = [u[0], u[1], u[2], u[3], u[4], u[5]],
+            Variable::VehicleSim3(v) => *v.pose.borrow_mut() = [u[0], u[1], u[2], u[3], u[4], u[5], u[6], u[7]],
+            Variable::Plane3D(v) => *v.plane.borrow_mut() = [u[0], u[1], u[2], u[3]],
+            Variable::LineLandmark2D(v) => *v.line.borrow_mut() = [u[0], u[1]],
+            Variable::OdometryCalibration2D(v) => *v.calibration.borrow_mut() = [u[0], u[1], u[2]],
+            Variable::Extrinsic2D(v) => *v.pose.borrow_mut() = [u[0], u[1], u[2]],
+            Variable::Extrinsic3D(v) => *v.pose.borrow_mut() = [u[0], u[1], u[2], u[3], u[4], u[5], u[6]],
+            Variable::Anchor2D(v) => *v.pose.borrow_mut() = [u[0], u[1], u[2]],
+            Variable::Anchor3D(v) => *v.pose.borrow_mut() = [u[0], u[1], u[2], u[3], u[4], u[5], u[6]],
+            Variable::UserDefined(v) => v.state.borrow_mut().set(u),
         }
     }
-    pub fn get_id(&self) -> usize {
+    pub fn get_id(&self) -> VariableId {
         match self {
             Variable::Vehicle2D(v) => v.id,
             Variable::Landmark2D(v) => v.id,
             Variable::Vehicle3D(v) => v.id,
             Variable::Landmark3D(v) => v.id,
+            Variable::Switch(v) => v.id,
+            Variable::Velocity3D(v) => v.id,
+            Variable::ImuBias(v) => v.id,
+            Variable::VehicleSim3(v) => v.id,
+            Variable::Plane3D(v) => v.id,
+            Variable::LineLandmark2D(v) => v.id,
+            Variable::OdometryCalibration2D(v) => v.id,
+            Variable::Extrinsic2D(v) => v.id,
+            Variable::Extrinsic3D(v) => v.id,
+            Variable::Anchor2D(v) => v.id,
+            Variable::Anchor3D(v) => v.id,
+            Variable::UserDefined(v) => v.id,
         }
     }
 }
@@ -0,0 +1,274 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Local parameterizations, which apply corrections from the tangent space to a variable's content.
+//!
+//! The optimizer calculates corrections in the tangent space of each variable, which has as many dimensions as
+//! the variable has rows in H. The parameterization maps such a correction back onto the variable's manifold,
+//! e.g. onto unit quaternions for rotations in 3D.
+
+#![allow(non_snake_case)]
+
+use nalgebra::{Isometry3, Matrix3, Quaternion, Translation3, UnitQuaternion, Vector3};
+use std::f64::consts::PI;
+
+/// Trait which all local parameterizations should implement.
+pub trait LocalParameterization {
+    /// Returns the dimension of the tangent space.
+    fn tangent_dim(&self) -> usize;
+
+    /// Returns the content after applying the correction, which is expected to have tangent_dim() entries.
+    fn plus(&self, content: &[f64], correction: &[f64]) -> Vec<f64>;
+}
+
+/// Parameterization of a vector space, where corrections are simply added.
+pub struct EuclideanParameterization<const N: usize>;
+
+impl<const N: usize> LocalParameterization for EuclideanParameterization<N> {
+    fn tangent_dim(&self) -> usize {
+        N
+    }
+
+    fn plus(&self, content: &[f64], correction: &[f64]) -> Vec<f64> {
+        content
+            .iter()
+            .zip(correction.iter())
+            .map(|(old, cor)| old + cor)
+            .collect()
+    }
+}
+
+/// Parameterization of 2D poses [x, y, rotation], where corrections are added and the rotation is normalized to
+/// (-PI, PI].
+pub struct Se2Parameterization;
+
+impl LocalParameterization for Se2Parameterization {
+    fn tangent_dim(&self) -> usize {
+        3
+    }
+
+    fn plus(&self, content: &[f64], correction: &[f64]) -> Vec<f64> {
+        let mut updated_content = EuclideanParameterization::<3>.plus(content, correction);
+        updated_content[2] = normalize_angle(updated_content[2]);
+        updated_content
+    }
+}
+
+/// Parameterization of 2D lines [angle, distance] in Hessian normal form, i.e. of the points x with
+/// (cos(angle), sin(angle)) * x = distance, where corrections are added and the angle is normalized to (-PI, PI].
+pub struct LineParameterization;
+
+impl LocalParameterization for LineParameterization {
+    fn tangent_dim(&self) -> usize {
+        2
+    }
+
+    fn plus(&self, content: &[f64], correction: &[f64]) -> Vec<f64> {
+        vec![normalize_angle(content[0] + correction[0]), content[1] + correction[1]]
+    }
+}
+
+/// Parameterization of 3D poses [x, y, z, q_x, q_y, q_z, q_w] on the manifold SE(3).
+///
+/// A correction [t_x, t_y, t_z, v_x, v_y, v_z] is applied from the right via the exponential map, where
+/// 2 * [v_x, v_y, v_z] is the rotation vector. For small corrections, v is the vector part of the correction's
+/// rotation quaternion, matching the error vectors and Jacobians of the 3D factors. Unlike adding v to the
+/// quaternion and normalizing afterwards, this is exact for large rotations.
+pub struct Se3Parameterization;
+
+impl LocalParameterization for Se3Parameterization {
+    fn tangent_dim(&self) -> usize {
+        6
+    }
+
+    fn plus(&self, content: &[f64], correction: &[f64]) -> Vec<f64> {
+        let old_iso = Isometry3::from_parts(
+            Translation3::new(content[0], content[1], content[2]),
+            UnitQuaternion::from_quaternion(Quaternion::new(content[6], content[3], content[4], content[5])),
+        );
+        let new_iso = old_iso * exp_se3(correction);
+        let mut updated_content = new_iso.translation.vector.data.as_slice().to_vec();
+        updated_content.extend_from_slice(new_iso.rotation.quaternion().coords.data.as_slice());
+        updated_content
+    }
+}
+
+/// Parameterization of 3D poses with a scale [x, y, z, q_x, q_y, q_z, q_w, scale] on the manifold Sim(3).
+///
+/// A correction [t_x, t_y, t_z, v_x, v_y, v_z, sigma] applies its first six entries to the pose like
+/// [Se3Parameterization](struct.Se3Parameterization.html) and multiplies the scale by exp(sigma), which keeps it
+/// positive.
+pub struct Sim3Parameterization;
+
+impl LocalParameterization for Sim3Parameterization {
+    fn tangent_dim(&self) -> usize {
+        7
+    }
+
+    fn plus(&self, content: &[f64], correction: &[f64]) -> Vec<f64> {
+        let mut updated_content = Se3Parameterization.plus(&content[..7], &correction[..6]);
+        updated_content.push(content[7] * correction[6].exp());
+        updated_content
+    }
+}
+
+/// Parameterization of infinite planes [n_x, n_y, n_z, d], i.e. of the points x with n * x = d for the unit normal
+/// n, on the manifold S(2) x R.
+///
+/// A correction [a, b, delta] rotates the normal along the great circle in the direction a * e_a + b * e_b, where
+/// e_a and e_b are the orthonormal tangent basis of [get_plane_tangent_basis](fn.get_plane_tangent_basis.html), and
+/// adds delta to the distance. The normal stays a unit vector, so the plane keeps three degrees of freedom.
+pub struct PlaneParameterization;
+
+impl LocalParameterization for PlaneParameterization {
+    fn tangent_dim(&self) -> usize {
+        3
+    }
+
+    fn plus(&self, content: &[f64], correction: &[f64]) -> Vec<f64> {
+        let normal = Vector3::new(content[0], content[1], content[2]).normalize();
+        let [e_a, e_b] = get_plane_tangent_basis(&normal);
+        let direction = correction[0] * e_a + correction[1] * e_b;
+        let angle = direction.norm();
+        let updated_normal = if angle == 0.0 {
+            normal
+        } else {
+            (angle.cos() * normal + angle.sin() / angle * direction).normalize()
+        };
+        let mut updated_content = updated_normal.data.as_slice().to_vec();
+        updated_content.push(content[3] + correction[2]);
+        updated_content
+    }
+}
+
+/// Returns an orthonormal basis of the plane which is orthogonal to the given unit normal.
+///
+/// The basis only depends on the normal, so that the corrections of
+/// [PlaneParameterization](struct.PlaneParameterization.html) and the Jacobians of the plane factors agree.
+pub fn get_plane_tangent_basis(normal: &Vector3<f64>) -> [Vector3<f64>; 2] {
+    // the axis which is least aligned with the normal keeps the cross product well-conditioned
+    let axis = Vector3::ith(normal.iamin(), 1.0);
+    let e_a = normal.cross(&axis).normalize();
+    [e_a, normal.cross(&e_a)]
+}
+
+/// Parameterization of switch variables, where corrections are added and the value is clamped to [0, 1].
+pub struct SwitchParameterization;
+
+impl LocalParameterization for SwitchParameterization {
+    fn tangent_dim(&self) -> usize {
+        1
+    }
+
+    fn plus(&self, content: &[f64], correction: &[f64]) -> Vec<f64> {
+        vec![(content[0] + correction[0]).clamp(0.0, 1.0)]
+    }
+}
+
+// normalizes an angle to (-PI, PI]
+fn normalize_angle(angle: f64) -> f64 {
+    let angle = angle % (2.0 * PI);
+    if angle > PI {
+        angle - 2.0 * PI
+    } else if angle < -PI {
+        angle + 2.0 * PI
+    } else {
+        angle
+    }
+}
+
+// maps the tangent vector [translation, rotation vector / 2] to SE(3)
+fn exp_se3(tangent: &[f64]) -> Isometry3<f64> {
+    let rho = Vector3::new(tangent[0], tangent[1], tangent[2]);
+    let omega = 2.0 * Vector3::new(tangent[3], tangent[4], tangent[5]);
+    let theta = omega.norm();
+    let omega_hat = omega.cross_matrix();
+    // left Jacobian of SO(3), using its Taylor expansion for small angles
+    let V = if theta < 1e-6 {
+        Matrix3::identity() + 0.5 * omega_hat + omega_hat * omega_hat / 6.0
+    } else {
+        Matrix3::identity()
+            + (1.0 - theta.cos()) / (theta * theta) * omega_hat
+            + (theta - theta.sin()) / (theta * theta * theta) * omega_hat * omega_hat
+    };
+    Isometry3::from_parts(Translation3::from(V * rho), UnitQuaternion::from_scaled_axis(omega))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn test_se2_rotation_is_normalized() {
+        let updated = Se2Parameterization.plus(&[1.0, 2.0, 3.0], &[1.0, -1.0, 1.0]);
+        assert_eq!(updated[..2], [2.0, 1.0]);
+        assert_relative_eq!(updated[2], 4.0 - 2.0 * PI, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_line_angle_is_normalized() {
+        let updated = LineParameterization.plus(&[3.0, 2.0], &[1.0, -0.5]);
+        assert_relative_eq!(updated[0], 4.0 - 2.0 * PI, epsilon = 1e-12);
+        assert_eq!(updated[1], 1.5);
+    }
+
+    #[test]
+    fn test_se3_large_rotation_stays_on_manifold() {
+        // rotation by PI around z, i.e. v = [0, 0, PI / 2], followed by a translation along the rotated x axis
+        let content = [1.0, 2.0, 3.0, 0.0, 0.0, 0.0, 1.0];
+        let updated = Se3Parameterization.plus(&content, &[0.0, 0.0, 0.0, 0.0, 0.0, PI / 2.0]);
+        let quaternion_norm = updated[3..].iter().map(|q| q * q).sum::<f64>().sqrt();
+        assert_relative_eq!(quaternion_norm, 1.0, epsilon = 1e-12);
+        assert_relative_eq!(updated[5].abs(), 1.0, epsilon = 1e-12);
+        assert_relative_eq!(updated[0], 1.0, epsilon = 1e-12);
+
+        let translated = Se3Parameterization.plus(&updated, &[1.0, 0.0, 0.0, 0.0, 0.0, 0.0]);
+        assert_relative_eq!(translated[0], 0.0, epsilon = 1e-12);
+        assert_relative_eq!(translated[1], 2.0, epsilon = 1e-12);
+        assert_relative_eq!(translated[2], 3.0, epsilon = 1e-12);
+    }
+
+    #[test]
+    fn test_se3_small_correction_matches_quaternion_update() {
+        let content = [0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0];
+        let updated = Se3Parameterization.plus(&content, &[0.0, 0.0, 0.0, 1e-4, -2e-4, 3e-4]);
+        assert_relative_eq!(updated[3], 1e-4, epsilon = 1e-10);
+        assert_relative_eq!(updated[4], -2e-4, epsilon = 1e-10);
+        assert_relative_eq!(updated[5], 3e-4, epsilon = 1e-10);
+    }
+
+    #[test]
+    fn test_sim3_scale_stays_positive() {
+        let content = [1.0, 2.0, 3.0, 0.0, 0.0, 0.0, 1.0, 0.5];
+        let updated = Sim3Parameterization.plus(&content, &[1.0, 0.0, 0.0, 0.0, 0.0, 0.0, -10.0]);
+        assert_eq!(updated[..7], Se3Parameterization.plus(&content[..7], &[1.0, 0.0, 0.0, 0.0, 0.0, 0.0])[..]);
+        assert!(updated[7] > 0.0);
+        assert_relative_eq!(updated[7], 0.5 * (-10.0_f64).exp(), epsilon = 1e-15);
+    }
+
+    #[test]
+    fn test_plane_normal_stays_unit() {
+        let content = [0.0, 0.0, 2.0, 1.5];
+        let updated = PlaneParameterization.plus(&content, &[PI / 2.0, 0.0, -0.5]);
+        let normal = Vector3::new(updated[0], updated[1], updated[2]);
+        assert_relative_eq!(normal.norm(), 1.0, epsilon = 1e-12);
+        // a quarter turn makes the normal orthogonal to the previous one
+        assert_relative_eq!(normal[2], 0.0, epsilon = 1e-12);
+        assert_relative_eq!(updated[3], 1.0, epsilon = 1e-12);
+
+        let [e_a, e_b] = get_plane_tangent_basis(&normal);
+        assert_relative_eq!(e_a.dot(&normal), 0.0, epsilon = 1e-12);
+        assert_relative_eq!(e_b.dot(&normal), 0.0, epsilon = 1e-12);
+        assert_relative_eq!((e_a.cross(&e_b) - normal).norm(), 0.0, epsilon = 1e-12);
+    }
+}
@@ -10,15 +10,36 @@
 // This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
 //
 
+//! Without the default feature "std", only the core graph types are available, i.e. the variables, factors and
+//! information matrices in [factor_graph](factor_graph/index.html) and the serializable model in
+//! [parser::model](parser/model/index.html). They only require an allocator, so that embedded front-ends can
+//! construct and serialize graphs on-device and offload the optimization to a host.
+
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub mod calibration;
+#[cfg(feature = "std")]
+pub mod debug;
+pub mod examples_gen;
 pub mod factor_graph;
+#[cfg(feature = "std")]
+pub mod markers;
+#[cfg(feature = "std")]
 pub mod optimizer;
 pub mod parser;
+#[cfg(feature = "std")]
 pub mod visualizer;
 
+#[cfg(feature = "std")]
 use parser::g2o::G2oParser;
+#[cfg(feature = "std")]
 use parser::Parser;
 
 /// Example for the usage of the library
+#[cfg(feature = "std")]
 pub fn optimize(in_file: &str, out_file: &str, iterations: usize) {
     let factor_graph = G2oParser::parse_file(in_file).unwrap();
 
@@ -0,0 +1,285 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Construction of factor graphs from fiducial marker detections, e.g. of AprilTags, for marker-based mapping.
+//!
+//! Each tag becomes a 3D landmark, which is observed by Observation3D factors from the camera poses which detected
+//! it. The orientations of the tags are not used, since landmarks are points.
+//!
+//! ```
+//! use gs_rs::factor_graph::FactorGraph;
+//! use gs_rs::factor_graph::variable::VariableId;
+//! use gs_rs::markers::{add_marker_detections, MarkerConfig, MarkerDetection};
+//! use gs_rs::optimizer::optimize;
+//! use gs_rs::parser::model::{FactorGraphModel, Vertex};
+//! use nalgebra::Isometry3;
+//! use std::collections::BTreeSet;
+//!
+//! // two fixed camera poses, e.g. from visual odometry
+//! let mut model = FactorGraphModel {
+//!     vertices: vec![],
+//!     edges: vec![],
+//!     fixed_vertices: BTreeSet::new(),
+//!     ..Default::default()
+//! };
+//! for (id, x) in [(0, 0.0), (1, 1.0)] {
+//!     model.vertices.push(Vertex {
+//!         id: VariableId(id),
+//!         vertex_type: String::from("Vehicle3D"),
+//!         content: vec![x, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0],
+//!     });
+//!     model.fixed_vertices.insert(VariableId(id));
+//! }
+//! let detections = [
+//!     MarkerDetection::new(VariableId(0), 7, Isometry3::translation(0.5, 0.0, 2.0)),
+//!     MarkerDetection::new(VariableId(1), 7, Isometry3::translation(-0.5, 0.0, 2.0)),
+//! ];
+//! let landmarks = add_marker_detections(&mut model, &detections, &MarkerConfig::default()).unwrap();
+//! assert_eq!(landmarks[&7], VariableId(2));
+//!
+//! let factor_graph: FactorGraph = model.into();
+//! optimize(&factor_graph, 5);
+//! let tag = factor_graph.get_var_by_id(landmarks[&7]).unwrap().get_content();
+//! assert!((tag[0] - 0.5).abs() < 1e-9 && (tag[2] - 2.0).abs() < 1e-9);
+//! ```
+
+use crate::factor_graph::variable::VariableId;
+use crate::optimizer::linear_system::iso3d_gradients::get_isometry;
+use crate::parser::model::{Edge, FactorGraphModel, Vertex};
+use nalgebra::{Isometry3, Matrix3, Point3, Vector3};
+use std::collections::{BTreeMap, BTreeSet};
+
+/// A detection of a tag from a camera pose.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MarkerDetection {
+    /// The ID of the Vehicle3D vertex of the camera pose.
+    pub camera: VariableId,
+    /// The ID of the detected tag, e.g. within its tag family.
+    pub tag: usize,
+    /// The pose of the tag in the camera's frame.
+    pub relative_pose: Isometry3<f64>,
+    /// The covariance of the tag's position in the camera's frame, or None for the default covariance, see
+    /// [MarkerConfig](struct.MarkerConfig.html).
+    pub covariance: Option<Matrix3<f64>>,
+}
+
+impl MarkerDetection {
+    /// Returns a detection with the default covariance.
+    pub fn new(camera: VariableId, tag: usize, relative_pose: Isometry3<f64>) -> Self {
+        MarkerDetection {
+            camera,
+            tag,
+            relative_pose,
+            covariance: None,
+        }
+    }
+}
+
+/// Parameters of the default covariance of detections, see [add_marker_detections](fn.add_marker_detections.html).
+///
+/// A tag at distance d is located along the viewing ray by its apparent size, i.e. by the angle s / d which it
+/// spans, and across the viewing ray by its apparent direction. With an angular standard deviation σ of the detected
+/// corners, the standard deviation is σ d across the viewing ray and σ d² / s along it, so that distant and small
+/// tags mostly constrain their direction.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct MarkerConfig {
+    /// The side length s of the tags, e.g. 0.16 for tags with 16 cm sides if positions are in meters.
+    pub tag_size: f64,
+    /// The angular standard deviation σ of the detections in radians, i.e. the pixel noise divided by the focal
+    /// length in pixels.
+    pub angular_noise: f64,
+}
+
+impl Default for MarkerConfig {
+    fn default() -> Self {
+        MarkerConfig {
+            tag_size: 0.16,
+            angular_noise: 0.002,
+        }
+    }
+}
+
+/// Adds a Landmark3D vertex for each detected tag and an Observation3D edge for each detection to the model, and
+/// returns the map from the tag IDs to the IDs of their landmarks.
+///
+/// The landmarks get consecutive IDs after the largest vertex ID of the model, in ascending order of tag ID, and
+/// start at the mean of the positions at which the detections place their tags. Detections without a covariance get
+/// the default covariance of the given configuration. Since each call adds new landmarks, all detections of a tag
+/// have to be added at once.
+///
+/// Returns an error without changing the model if a camera is not a Vehicle3D vertex of the model, if a tag is
+/// detected twice from the same camera, if a tag is at the position of its camera or if a covariance is not positive
+/// definite.
+pub fn add_marker_detections(
+    model: &mut FactorGraphModel,
+    detections: &[MarkerDetection],
+    config: &MarkerConfig,
+) -> Result<BTreeMap<usize, VariableId>, String> {
+    let cameras: BTreeMap<VariableId, &Vertex> = model
+        .vertices
+        .iter()
+        .filter(|vertex| vertex.vertex_type == "Vehicle3D")
+        .map(|vertex| (vertex.id, vertex))
+        .collect();
+    let mut positions: BTreeMap<usize, Vec<Point3<f64>>> = BTreeMap::new();
+    let mut observations = BTreeSet::new();
+    let mut information_matrices = vec![];
+    for detection in detections {
+        let camera = cameras.get(&detection.camera).ok_or(format!(
+            "The camera {} of the detection of tag {} is not a Vehicle3D vertex",
+            detection.camera, detection.tag
+        ))?;
+        if !observations.insert((detection.camera, detection.tag)) {
+            return Err(format!(
+                "The tag {} is detected twice from the camera {}",
+                detection.tag, detection.camera
+            ));
+        }
+        let relative_position = detection.relative_pose.translation.vector;
+        if relative_position.norm() == 0.0 {
+            return Err(format!(
+                "The tag {} is detected at the position of the camera {}",
+                detection.tag, detection.camera
+            ));
+        }
+        let covariance = detection
+            .covariance
+            .unwrap_or_else(|| get_default_covariance(&relative_position, config));
+        let information_matrix = covariance.cholesky().map(|cholesky| cholesky.inverse()).ok_or(format!(
+            "The covariance of the detection of tag {} from the camera {} is not positive definite",
+            detection.tag, detection.camera
+        ))?;
+        information_matrices.push(information_matrix);
+        positions
+            .entry(detection.tag)
+            .or_default()
+            .push(get_isometry(&camera.content) * Point3::from(relative_position));
+    }
+
+    let first_id = model.vertices.iter().map(|vertex| vertex.id.0 + 1).max().unwrap_or(0);
+    let landmarks: BTreeMap<usize, VariableId> = positions
+        .keys()
+        .enumerate()
+        .map(|(i, tag)| (*tag, VariableId(first_id + i)))
+        .collect();
+    for (tag, tag_positions) in &positions {
+        let mean = tag_positions.iter().map(|p| p.coords).sum::<Vector3<f64>>() / tag_positions.len() as f64;
+        model.vertices.push(Vertex {
+            id: landmarks[tag],
+            vertex_type: String::from("Landmark3D"),
+            content: mean.as_slice().to_vec(),
+        });
+    }
+    for (detection, information_matrix) in detections.iter().zip(information_matrices) {
+        model.edges.push(Edge {
+            edge_type: String::from("Observation3D"),
+            vertices: vec![detection.camera, landmarks[&detection.tag]],
+            restriction: detection.relative_pose.translation.vector.as_slice().to_vec(),
+            information_matrix: information_matrix.as_slice().to_vec(),
+        });
+    }
+    Ok(landmarks)
+}
+
+// returns the covariance with the standard deviation σ d across and σ d² / s along the viewing ray
+fn get_default_covariance(relative_position: &Vector3<f64>, config: &MarkerConfig) -> Matrix3<f64> {
+    let distance = relative_position.norm();
+    let ray = relative_position / distance;
+    let across_variance = (config.angular_noise * distance).powi(2);
+    let along_variance = (config.angular_noise * distance.powi(2) / config.tag_size).powi(2);
+    Matrix3::identity() * across_variance + ray * ray.transpose() * (along_variance - across_variance)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    // returns a model with a fixed camera at the origin and a second camera at (1, 0, 0) which is rotated by π/2
+    // around the z axis
+    fn get_cameras() -> FactorGraphModel {
+        let mut model = FactorGraphModel {
+            vertices: vec![],
+            edges: vec![],
+            fixed_vertices: BTreeSet::new(),
+            ..Default::default()
+        };
+        let half_sqrt = std::f64::consts::FRAC_1_SQRT_2;
+        for (id, content) in [
+            (0, vec![0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0]),
+            (1, vec![1.0, 0.0, 0.0, 0.0, 0.0, half_sqrt, half_sqrt]),
+        ] {
+            model.vertices.push(Vertex {
+                id: VariableId(id),
+                vertex_type: String::from("Vehicle3D"),
+                content,
+            });
+        }
+        model.fixed_vertices.insert(VariableId(0));
+        model
+    }
+
+    #[test]
+    fn test_add_marker_detections() {
+        let mut model = get_cameras();
+        let detections = [
+            MarkerDetection::new(VariableId(1), 3, Isometry3::translation(1.0, 0.0, 0.0)),
+            MarkerDetection::new(VariableId(0), 5, Isometry3::translation(0.0, 0.0, 2.0)),
+            MarkerDetection::new(VariableId(0), 3, Isometry3::translation(1.2, 1.0, 0.0)),
+        ];
+        let landmarks = add_marker_detections(&mut model, &detections, &MarkerConfig::default()).unwrap();
+        assert_eq!(
+            landmarks.into_iter().collect::<Vec<_>>(),
+            vec![(3, VariableId(2)), (5, VariableId(3))]
+        );
+        assert_eq!(model.vertices[2].vertex_type, "Landmark3D");
+        // the mean of (1, 1, 0) and (1.2, 1, 0)
+        assert_relative_eq!(
+            model.vertices[2].content.as_slice(),
+            [1.1, 1.0, 0.0].as_ref(),
+            epsilon = 1e-12
+        );
+        assert_eq!(model.vertices[3].content, vec![0.0, 0.0, 2.0]);
+        assert_eq!(model.edges.len(), 3);
+        assert_eq!(model.edges[1].vertices, vec![VariableId(0), VariableId(3)]);
+
+        // the tag at distance 2 is constrained more accurately across than along the viewing ray
+        let information = &model.edges[1].information_matrix;
+        assert_relative_eq!(information[0], 1.0 / (0.002f64 * 2.0).powi(2), max_relative = 1e-9);
+        assert_relative_eq!(
+            information[8],
+            1.0 / (0.002f64 * 4.0 / 0.16).powi(2),
+            max_relative = 1e-9
+        );
+        assert_eq!(information[2], 0.0);
+    }
+
+    #[test]
+    fn test_invalid_detections() {
+        let mut model = get_cameras();
+        let config = MarkerConfig::default();
+        let pose = Isometry3::translation(0.0, 0.0, 1.0);
+        let invalid = [
+            vec![MarkerDetection::new(VariableId(2), 0, pose)],
+            vec![MarkerDetection::new(VariableId(0), 0, pose); 2],
+            vec![MarkerDetection::new(VariableId(0), 0, Isometry3::identity())],
+            vec![MarkerDetection {
+                covariance: Some(Matrix3::zeros()),
+                ..MarkerDetection::new(VariableId(0), 0, pose)
+            }],
+        ];
+        for detections in &invalid {
+            assert!(add_marker_detections(&mut model, detections, &config).is_err());
+            assert_eq!(model, get_cameras());
+        }
+    }
+}
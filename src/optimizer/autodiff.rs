@@ -0,0 +1,293 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Forward-mode automatic differentiation with dual numbers.
+//!
+//! A dual number carries a value and its derivative with respect to a single input. Evaluating a function on
+//! dual numbers whose input is seeded with the derivative 1 yields the function's value and its derivative at
+//! once, without any approximation. A Jacobian is obtained column by column by seeding each input in turn.
+//!
+//! Custom factors (see [FactorType::Custom](../../factor_graph/factor/enum.FactorType.html#variant.Custom)) are
+//! differentiated this way, so their residuals only have to be written once in terms of [Dual](struct.Dual.html).
+
+use nalgebra::DMatrix;
+use std::ops::{Add, AddAssign, Div, Mul, MulAssign, Neg, Sub, SubAssign};
+
+/// Dual number consisting of a value and its derivative.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct Dual {
+    /// The value.
+    pub value: f64,
+    /// The derivative of the value with respect to the seeded input.
+    pub derivative: f64,
+}
+
+impl Dual {
+    /// Returns a dual number with the given value and derivative.
+    pub fn new(value: f64, derivative: f64) -> Self {
+        Dual { value, derivative }
+    }
+
+    /// Returns a dual number which does not depend on the seeded input.
+    pub fn constant(value: f64) -> Self {
+        Dual::new(value, 0.0)
+    }
+
+    /// Returns a dual number which is the seeded input itself.
+    pub fn variable(value: f64) -> Self {
+        Dual::new(value, 1.0)
+    }
+
+    // applies a function with the given value and derivative at self.value via the chain rule
+    fn chain(self, value: f64, derivative: f64) -> Self {
+        Dual::new(value, derivative * self.derivative)
+    }
+
+    pub fn sin(self) -> Self {
+        self.chain(self.value.sin(), self.value.cos())
+    }
+
+    pub fn cos(self) -> Self {
+        self.chain(self.value.cos(), -self.value.sin())
+    }
+
+    pub fn tan(self) -> Self {
+        let tan = self.value.tan();
+        self.chain(tan, 1.0 + tan * tan)
+    }
+
+    pub fn asin(self) -> Self {
+        self.chain(self.value.asin(), 1.0 / (1.0 - self.value * self.value).sqrt())
+    }
+
+    pub fn acos(self) -> Self {
+        self.chain(self.value.acos(), -1.0 / (1.0 - self.value * self.value).sqrt())
+    }
+
+    pub fn atan(self) -> Self {
+        self.chain(self.value.atan(), 1.0 / (1.0 + self.value * self.value))
+    }
+
+    /// Returns the four-quadrant arctangent of self (y) and x.
+    pub fn atan2(self, x: Dual) -> Self {
+        let squared_norm = self.value * self.value + x.value * x.value;
+        Dual::new(
+            self.value.atan2(x.value),
+            (x.value * self.derivative - self.value * x.derivative) / squared_norm,
+        )
+    }
+
+    pub fn exp(self) -> Self {
+        let exp = self.value.exp();
+        self.chain(exp, exp)
+    }
+
+    pub fn ln(self) -> Self {
+        self.chain(self.value.ln(), 1.0 / self.value)
+    }
+
+    pub fn sqrt(self) -> Self {
+        let sqrt = self.value.sqrt();
+        self.chain(sqrt, 0.5 / sqrt)
+    }
+
+    pub fn powi(self, n: i32) -> Self {
+        self.chain(self.value.powi(n), f64::from(n) * self.value.powi(n - 1))
+    }
+
+    pub fn powf(self, n: f64) -> Self {
+        self.chain(self.value.powf(n), n * self.value.powf(n - 1.0))
+    }
+
+    /// Returns the absolute value, whose derivative at 0 is taken to be 0.
+    pub fn abs(self) -> Self {
+        self.chain(
+            self.value.abs(),
+            self.value.signum() * (self.value != 0.0) as i32 as f64,
+        )
+    }
+}
+
+impl From<f64> for Dual {
+    fn from(value: f64) -> Self {
+        Dual::constant(value)
+    }
+}
+
+impl Neg for Dual {
+    type Output = Dual;
+
+    fn neg(self) -> Dual {
+        Dual::new(-self.value, -self.derivative)
+    }
+}
+
+impl Add for Dual {
+    type Output = Dual;
+
+    fn add(self, rhs: Dual) -> Dual {
+        Dual::new(self.value + rhs.value, self.derivative + rhs.derivative)
+    }
+}
+
+impl Sub for Dual {
+    type Output = Dual;
+
+    fn sub(self, rhs: Dual) -> Dual {
+        Dual::new(self.value - rhs.value, self.derivative - rhs.derivative)
+    }
+}
+
+impl Mul for Dual {
+    type Output = Dual;
+
+    fn mul(self, rhs: Dual) -> Dual {
+        Dual::new(
+            self.value * rhs.value,
+            self.derivative * rhs.value + self.value * rhs.derivative,
+        )
+    }
+}
+
+impl Div for Dual {
+    type Output = Dual;
+
+    fn div(self, rhs: Dual) -> Dual {
+        Dual::new(
+            self.value / rhs.value,
+            (self.derivative * rhs.value - self.value * rhs.derivative) / (rhs.value * rhs.value),
+        )
+    }
+}
+
+// operations with f64 on either side, treating it as a constant
+macro_rules! impl_f64_ops {
+    ($($trait:ident, $method:ident);*) => {
+        $(
+            impl $trait<f64> for Dual {
+                type Output = Dual;
+
+                fn $method(self, rhs: f64) -> Dual {
+                    self.$method(Dual::constant(rhs))
+                }
+            }
+
+            impl $trait<Dual> for f64 {
+                type Output = Dual;
+
+                fn $method(self, rhs: Dual) -> Dual {
+                    Dual::constant(self).$method(rhs)
+                }
+            }
+        )*
+    };
+}
+
+impl_f64_ops!(Add, add; Sub, sub; Mul, mul; Div, div);
+
+impl AddAssign for Dual {
+    fn add_assign(&mut self, rhs: Dual) {
+        *self = *self + rhs;
+    }
+}
+
+impl SubAssign for Dual {
+    fn sub_assign(&mut self, rhs: Dual) {
+        *self = *self - rhs;
+    }
+}
+
+impl MulAssign for Dual {
+    fn mul_assign(&mut self, rhs: Dual) {
+        *self = *self * rhs;
+    }
+}
+
+/// Returns the value of the function at x and its Jacobian, with one column per entry of x.
+pub fn jacobian<F>(function: F, x: &[f64]) -> (Vec<f64>, DMatrix<f64>)
+where
+    F: Fn(&[Dual]) -> Vec<Dual>,
+{
+    let value: Vec<f64> = function(&x.iter().map(|v| Dual::constant(*v)).collect::<Vec<_>>())
+        .iter()
+        .map(|d| d.value)
+        .collect();
+    let mut jacobian = DMatrix::zeros(value.len(), x.len());
+    for k in 0..x.len() {
+        let seeded: Vec<Dual> = x
+            .iter()
+            .enumerate()
+            .map(|(i, v)| if i == k { Dual::variable(*v) } else { Dual::constant(*v) })
+            .collect();
+        for (row, d) in function(&seeded).iter().enumerate() {
+            jacobian[(row, k)] = d.derivative;
+        }
+    }
+    (value, jacobian)
+}
+
+// quaternion of dual numbers, stored as [x, y, z, w] like the contents of 3D poses
+pub(crate) type DualQuaternion = [Dual; 4];
+
+// returns the Hamilton product of the quaternions
+pub(crate) fn quaternion_product(a: &DualQuaternion, b: &DualQuaternion) -> DualQuaternion {
+    [
+        a[3] * b[0] + a[0] * b[3] + a[1] * b[2] - a[2] * b[1],
+        a[3] * b[1] - a[0] * b[2] + a[1] * b[3] + a[2] * b[0],
+        a[3] * b[2] + a[0] * b[1] - a[1] * b[0] + a[2] * b[3],
+        a[3] * b[3] - a[0] * b[0] - a[1] * b[1] - a[2] * b[2],
+    ]
+}
+
+pub(crate) fn quaternion_conjugate(q: &DualQuaternion) -> DualQuaternion {
+    [-q[0], -q[1], -q[2], q[3]]
+}
+
+// rotates the vector by the inverse of the unit quaternion
+pub(crate) fn rotate_inverse(q: &DualQuaternion, v: &[Dual]) -> Vec<Dual> {
+    let pure = [v[0], v[1], v[2], Dual::constant(0.0)];
+    quaternion_product(&quaternion_product(&quaternion_conjugate(q), &pure), q)[..3].to_vec()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn test_elementary_derivatives() {
+        let x = Dual::variable(0.7);
+        assert_relative_eq!((x * x.sin()).derivative, 0.7_f64.sin() + 0.7 * 0.7_f64.cos());
+        assert_relative_eq!((1.0 / x).derivative, -1.0 / (0.7 * 0.7));
+        assert_relative_eq!(x.exp().ln().derivative, 1.0);
+        assert_relative_eq!(x.sqrt().derivative, 0.5 / 0.7_f64.sqrt());
+        assert_relative_eq!(x.powi(3).derivative, 3.0 * 0.49);
+        assert_relative_eq!(
+            Dual::constant(2.0).atan2(x).derivative,
+            -2.0 / (4.0 + 0.49),
+            epsilon = 1e-12
+        );
+    }
+
+    #[test]
+    fn test_jacobian_of_polar_to_cartesian() {
+        let (value, jacobian) = jacobian(|x| vec![x[0] * x[1].cos(), x[0] * x[1].sin()], &[2.0, 0.5]);
+        assert_relative_eq!(value[0], 2.0 * 0.5_f64.cos());
+        assert_relative_eq!(value[1], 2.0 * 0.5_f64.sin());
+        let expected = DMatrix::from_row_slice(
+            2,
+            2,
+            &[0.5_f64.cos(), -2.0 * 0.5_f64.sin(), 0.5_f64.sin(), 2.0 * 0.5_f64.cos()],
+        );
+        assert!((jacobian - expected).abs().max() < 1e-12);
+    }
+}
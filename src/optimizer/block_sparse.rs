@@ -0,0 +1,268 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Block-sparse storage for the matrix H of the linear system.
+//!
+//! Each factor only connects the variables it involves, so H consists of dense 2x2, 3x3 or 6x6 blocks for pairs
+//! of connected variables and is empty everywhere else. Only these blocks are stored.
+//!
+//! The matrix is generic over its scalar, so that it can be converted to single precision with
+//! [cast](struct.BlockSparseMatrix.html#method.cast), e.g. to solve the linear system with a single precision
+//! solver.
+
+#![allow(non_snake_case)]
+
+use nalgebra::storage::Storage;
+use nalgebra::{CsMatrix, DMatrix, DVector, Dim, Matrix, RealField};
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+/// Square matrix consisting of dense blocks, one for each pair of variables (variable_i, variable_j).
+///
+/// A block is keyed by the first row of variable_i's range and the first column of variable_j's range in H.
+#[derive(Debug, Clone, PartialEq)]
+pub struct BlockSparseMatrix<T: RealField + Copy = f64> {
+    dim: usize,
+    blocks: BTreeMap<(usize, usize), DMatrix<T>>,
+}
+
+impl<T: RealField + Copy> BlockSparseMatrix<T> {
+    /// Returns an empty dim x dim matrix.
+    pub fn new(dim: usize) -> Self {
+        BlockSparseMatrix {
+            dim,
+            blocks: BTreeMap::new(),
+        }
+    }
+
+    /// Returns the number of rows and columns.
+    pub fn dim(&self) -> usize {
+        self.dim
+    }
+
+    /// Returns the number of stored blocks.
+    pub fn block_count(&self) -> usize {
+        self.blocks.len()
+    }
+
+    /// Returns the block whose first entry is at (row, col), if stored.
+    pub fn get_block(&self, row: usize, col: usize) -> Option<&DMatrix<T>> {
+        self.blocks.get(&(row, col))
+    }
+
+    /// Returns an iterator over all stored blocks and the position of their first entry.
+    pub fn blocks(&self) -> impl Iterator<Item = (&(usize, usize), &DMatrix<T>)> {
+        self.blocks.iter()
+    }
+
+    /// Adds the given matrix to the block at the given rows and columns, creating the block if necessary.
+    ///
+    /// Panics if the matrix does not fit the ranges or if the ranges overlap an existing block of a different size.
+    pub fn add_block<R: Dim, C: Dim, S: Storage<T, R, C>>(
+        &mut self,
+        rows: Range<usize>,
+        cols: Range<usize>,
+        added_matrix: &Matrix<T, R, C, S>,
+    ) {
+        assert_eq!(
+            (rows.len(), cols.len()),
+            added_matrix.shape(),
+            "Block does not fit its ranges."
+        );
+        let block = self
+            .blocks
+            .entry((rows.start, cols.start))
+            .or_insert_with(|| DMatrix::zeros(rows.len(), cols.len()));
+        assert_eq!(
+            block.shape(),
+            added_matrix.shape(),
+            "Block does not match the existing block."
+        );
+        block
+            .iter_mut()
+            .zip(added_matrix.iter())
+            .for_each(|(entry, added)| *entry += *added);
+    }
+
+    /// Replaces the given rows and columns by the ones of the identity, which decouples them from all other rows,
+    /// e.g. so that the solution is zero in the fixed components of partially fixed variables.
+    ///
+    /// Panics if one of the rows is not part of a stored diagonal block.
+    pub fn fix_rows(&mut self, rows: &[usize]) {
+        for row in rows {
+            for ((start_row, start_col), block) in self.blocks.iter_mut() {
+                if (*start_row..start_row + block.nrows()).contains(row) {
+                    block.row_mut(row - start_row).fill(T::zero());
+                }
+                if (*start_col..start_col + block.ncols()).contains(row) {
+                    block.column_mut(row - start_col).fill(T::zero());
+                }
+            }
+            let ((start, _), block) = self
+                .blocks
+                .iter_mut()
+                .find(|((start_row, start_col), block)| {
+                    start_row == start_col && (*start_row..start_row + block.nrows()).contains(row)
+                })
+                .expect("Row is not part of a diagonal block.");
+            block[(row - start, row - start)] = T::one();
+        }
+    }
+
+    /// Returns the entries of the diagonal.
+    pub fn diagonal(&self) -> DVector<T> {
+        let mut diagonal = DVector::zeros(self.dim);
+        self.blocks
+            .iter()
+            .filter(|((row, col), _)| row == col)
+            .for_each(|((start, _), block)| diagonal.rows_mut(*start, block.nrows()).copy_from(&block.diagonal()));
+        diagonal
+    }
+
+    /// Returns H*x, computed block by block.
+    pub fn mul_vector(&self, x: &DVector<T>) -> DVector<T> {
+        let mut product = DVector::zeros(self.dim);
+        self.blocks.iter().for_each(|((row, col), block)| {
+            let mut rows = product.rows_mut(*row, block.nrows());
+            rows += block * x.rows(*col, block.ncols());
+        });
+        product
+    }
+
+    /// Returns P*H*P^T for the permutation P given as the original row index for each new row.
+    ///
+    /// The permutation is expected to move each block as a whole, e.g. as returned by
+    /// [fill_reducing_permutation](../ordering/fn.fill_reducing_permutation.html).
+    pub fn permute(&self, permutation: &[usize]) -> BlockSparseMatrix<T> {
+        let mut new_index = vec![0; permutation.len()];
+        permutation
+            .iter()
+            .enumerate()
+            .for_each(|(new, original)| new_index[*original] = new);
+        BlockSparseMatrix {
+            dim: self.dim,
+            blocks: self
+                .blocks
+                .iter()
+                .map(|((row, col), block)| ((new_index[*row], new_index[*col]), block.clone()))
+                .collect(),
+        }
+    }
+
+    /// Returns H as a dense matrix.
+    pub fn to_dense(&self) -> DMatrix<T> {
+        let mut dense = DMatrix::zeros(self.dim, self.dim);
+        self.blocks.iter().for_each(|((row, col), block)| {
+            dense
+                .index_mut((*row..row + block.nrows(), *col..col + block.ncols()))
+                .copy_from(block)
+        });
+        dense
+    }
+
+    /// Returns H as a column-compressed sparse matrix without explicit zeros.
+    pub fn to_cs_matrix(&self) -> CsMatrix<T> {
+        let mut irows = vec![];
+        let mut icols = vec![];
+        let mut vals = vec![];
+        self.blocks.iter().for_each(|((row, col), block)| {
+            for (j, column) in block.column_iter().enumerate() {
+                for (i, value) in column.iter().enumerate().filter(|(_, value)| !value.is_zero()) {
+                    irows.push(row + i);
+                    icols.push(col + j);
+                    vals.push(*value);
+                }
+            }
+        });
+        CsMatrix::from_triplet(self.dim, self.dim, &irows, &icols, &vals)
+    }
+
+    /// Returns the matrix with its entries converted to another scalar, e.g. from f64 to f32.
+    pub fn cast<U: RealField + Copy>(&self) -> BlockSparseMatrix<U> {
+        BlockSparseMatrix {
+            dim: self.dim,
+            blocks: self
+                .blocks
+                .iter()
+                .map(|(position, block)| {
+                    (
+                        *position,
+                        block.map(|value| U::from_subset(&value.to_subset_unchecked())),
+                    )
+                })
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::Matrix2;
+
+    fn get_test_matrix() -> BlockSparseMatrix {
+        let mut H = BlockSparseMatrix::new(4);
+        H.add_block(0..2, 0..2, &Matrix2::new(4.0, 1.0, 1.0, 3.0));
+        H.add_block(0..2, 2..4, &Matrix2::new(-1.0, 0.0, 0.0, -1.0));
+        H.add_block(2..4, 0..2, &Matrix2::new(-1.0, 0.0, 0.0, -1.0));
+        H.add_block(2..4, 2..4, &Matrix2::new(2.0, 0.0, 0.0, 2.0));
+        H.add_block(2..4, 2..4, &Matrix2::new(1.0, 0.0, 0.0, 1.0));
+        H
+    }
+
+    #[test]
+    fn test_blocks_are_accumulated() {
+        let H = get_test_matrix();
+        assert_eq!(H.block_count(), 4);
+        assert_eq!(H.get_block(2, 2).unwrap(), &DMatrix::from_diagonal_element(2, 2, 3.0));
+        assert_eq!(H.diagonal().as_slice(), &[4.0, 3.0, 3.0, 3.0]);
+        assert_eq!(DMatrix::from(H.to_cs_matrix()), H.to_dense());
+    }
+
+    #[test]
+    fn test_mul_vector_and_permute() {
+        let H = get_test_matrix();
+        let x = DVector::from_vec(vec![1.0, 2.0, 3.0, 4.0]);
+        assert_eq!(H.mul_vector(&x), H.to_dense() * &x);
+
+        let permutation = vec![2, 3, 0, 1];
+        let dense = H.to_dense();
+        assert_eq!(
+            H.permute(&permutation).to_dense(),
+            dense.select_rows(&permutation).select_columns(&permutation)
+        );
+    }
+
+    #[test]
+    fn test_fix_rows() {
+        let mut H = get_test_matrix();
+        H.fix_rows(&[1, 2]);
+        let mut expected = get_test_matrix().to_dense();
+        for i in [1, 2] {
+            expected.row_mut(i).fill(0.0);
+            expected.column_mut(i).fill(0.0);
+            expected[(i, i)] = 1.0;
+        }
+        assert_eq!(H.to_dense(), expected);
+    }
+
+    #[test]
+    fn test_cast() {
+        let H = get_test_matrix();
+        let single: BlockSparseMatrix<f32> = H.cast();
+        assert_eq!(single.block_count(), H.block_count());
+        assert_eq!(single.to_dense(), H.to_dense().map(|value| value as f32));
+        let x = DVector::from_vec(vec![1.0f32, 2.0, 3.0, 4.0]);
+        assert_eq!(single.mul_vector(&x), single.to_dense() * &x);
+        assert_eq!(single.cast::<f64>(), H);
+    }
+}
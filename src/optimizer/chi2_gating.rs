@@ -0,0 +1,65 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! χ² gating of factors between optimization iterations.
+//!
+//! Each time the linear system is assembled, i.e. after each iteration, the χ² of every factor is calculated at the
+//! current estimates. Factors above the threshold are down-weighted or deactivated for the next iteration, and
+//! regain their full weight as soon as their χ² falls below the threshold again. Unlike
+//! [Dynamic Covariance Scaling](../dcs/index.html), the weight does not depend on how far a factor exceeds the
+//! threshold.
+
+use crate::factor_graph::factor::FactorType;
+use crate::optimizer::FactorReweighting;
+
+/// Configuration of the χ² gate.
+///
+/// Deactivating factors may leave variables unconstrained, e.g. a landmark observed by a single factor, in which
+/// case the linear system cannot be solved. A small positive weight avoids this.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Chi2Gate {
+    /// The χ² above which a factor is considered inconsistent, e.g. 7.81 (95% quantile of the chi-squared
+    /// distribution with 3 degrees of freedom) for Position2D and Odometry2D factors.
+    pub threshold: f64,
+    /// The factor by which the information matrices of inconsistent factors are scaled, with 0 deactivating them.
+    pub weight: f64,
+}
+
+impl FactorReweighting for Chi2Gate {
+    fn is_reweighted(&self, _factor_type: &FactorType) -> bool {
+        true
+    }
+
+    fn get_weight(&self, _factor_type: &FactorType, chi2: f64) -> f64 {
+        if chi2 > self.threshold {
+            self.weight
+        } else {
+            1.0
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn test_get_weight() {
+        let gate = Chi2Gate {
+            threshold: 7.81,
+            weight: 0.1,
+        };
+        assert_relative_eq!(gate.get_weight(&FactorType::Odometry2D, 7.0), 1.0);
+        assert_relative_eq!(gate.get_weight(&FactorType::Position2D, 8.0), 0.1);
+    }
+}
@@ -0,0 +1,393 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Independent optimization of the connected components of a factor graph, e.g. of sessions which are not linked by
+//! any factor yet.
+//!
+//! Optimizing such a factor graph as a whole couples unrelated components in a single linear system, which is
+//! singular if one of them is not anchored, and iterates all of them until the slowest one converges. Instead, each
+//! component is optimized on its own until it converges, optionally in parallel.
+//!
+//! The components are those of the factor graph without its fixed variables, since fixed variables do not couple the
+//! variables of their factors. Each component is optimized as the subgraph of its variables, of the fixed variables
+//! with which they share factors and of the factors between all of these. If a component neither shares a factor
+//! with a fixed variable nor has a unary factor, e.g. a prior, its first variable which is not tied to another one is
+//! fixed, so that it can be optimized on its own. Equality constraints and fixed components are kept.
+
+use crate::factor_graph::adjacency::NodeIndex;
+use crate::factor_graph::factor::FactorType;
+use crate::factor_graph::topology::UnionFind;
+use crate::factor_graph::variable::{FixedType, Variable, VariableId};
+use crate::factor_graph::FactorGraph;
+use crate::optimizer::config::OptimizerConfig;
+use crate::optimizer::termination::{OptimizationReport, Termination, TerminationCriteria};
+use crate::optimizer::{optimize_with_config, total_chi2};
+use crate::parser::model::converter::convert_model;
+use crate::parser::model::FactorGraphModel;
+use std::collections::BTreeMap;
+use std::thread;
+
+/// Summary of the optimization of one connected component, see [optimize_components](fn.optimize_components.html).
+#[derive(Debug, Clone, PartialEq)]
+pub struct ComponentReport {
+    /// The IDs of the component's variables, in ascending order. Fixed variables belong to no component.
+    pub variables: Vec<VariableId>,
+    /// The summary of the component's optimization, which has no components itself.
+    pub report: OptimizationReport,
+}
+
+// a connected component with the equality constraints and fixed components between its variables, which are not
+// part of models
+struct Component {
+    variables: Vec<VariableId>,
+    equalities: Vec<(VariableId, VariableId)>,
+    masks: Vec<(VariableId, Vec<bool>)>,
+}
+
+impl Component {
+    // adds the equality constraints and fixed components to the component's subgraph
+    fn constrain(&self, subgraph: &mut FactorGraph) -> Result<(), String> {
+        for (tied, representative) in &self.equalities {
+            subgraph.add_equality_constraint(*representative, *tied)?;
+        }
+        for (id, mask) in &self.masks {
+            subgraph.fix_components(*id, mask.clone())?;
+        }
+        Ok(())
+    }
+
+    // optimizes the component's constrained subgraph and returns the summary and the estimates of its variables
+    fn optimize(&self, subgraph: &FactorGraph, config: &OptimizerConfig) -> (OptimizationReport, Vec<Vec<f64>>) {
+        let report = optimize_with_config(subgraph, config);
+        let estimates = self
+            .variables
+            .iter()
+            .map(|id| subgraph.get_var_by_id(*id).unwrap().get_content())
+            .collect();
+        (report, estimates)
+    }
+
+    // writes the estimates to the factor graph and returns the component's summary
+    fn finish(self, graph: &FactorGraph, report: OptimizationReport, estimates: Vec<Vec<f64>>) -> ComponentReport {
+        self.variables
+            .iter()
+            .zip(estimates)
+            .for_each(|(id, estimate)| graph.get_var_by_id(*id).unwrap().set_content(estimate));
+        ComponentReport {
+            variables: self.variables,
+            report,
+        }
+    }
+}
+
+/// Optimizes each connected component of the factor graph on its own with the given configuration, see the
+/// [module documentation](index.html), and writes the optimized estimates to the factor graph.
+///
+/// The returned report contains the summaries of the components, ordered by their smallest variable ID. Its number of
+/// iterations is the largest one of a component and its total χ² is the one of the factor graph. It is converged if
+/// all components converged, and has the termination of the first component which did not converge otherwise.
+///
+/// Returns an error if the subgraph of a component cannot be built, which leaves the factor graph unchanged.
+///
+/// ```
+/// use gs_rs::examples_gen::triangle_with_loop_closure;
+/// use gs_rs::factor_graph::FactorGraph;
+/// use gs_rs::factor_graph::variable::VariableId;
+/// use gs_rs::optimizer::components::optimize_components;
+/// use gs_rs::optimizer::config::OptimizerConfig;
+/// use gs_rs::optimizer::termination::Termination;
+///
+/// // a second session, which is not linked to the first one
+/// let mut model = triangle_with_loop_closure();
+/// let mut session = triangle_with_loop_closure();
+/// session.vertices.iter_mut().for_each(|vertex| vertex.id.0 += 3);
+/// session.edges.iter_mut().flat_map(|edge| &mut edge.vertices).for_each(|id| id.0 += 3);
+/// model.vertices.append(&mut session.vertices);
+/// model.edges.append(&mut session.edges);
+///
+/// let factor_graph: FactorGraph = model.into();
+/// let report = optimize_components(&factor_graph, &OptimizerConfig::default()).unwrap();
+/// assert_eq!(report.components.len(), 2);
+/// assert_eq!(report.components[1].variables, vec![VariableId(3), VariableId(4), VariableId(5)]);
+/// assert_eq!(report.termination, Termination::Converged);
+/// ```
+pub fn optimize_components(graph: &FactorGraph, config: &OptimizerConfig) -> Result<OptimizationReport, String> {
+    let mut components = vec![];
+    for vars in get_components(graph) {
+        let (component, mut subgraph) = get_component(graph, &vars)?;
+        component.constrain(&mut subgraph)?;
+        components.push((component, subgraph));
+    }
+    let reports = components
+        .into_iter()
+        .map(|(component, subgraph)| {
+            let (report, estimates) = component.optimize(&subgraph, config);
+            component.finish(graph, report, estimates)
+        })
+        .collect();
+    Ok(summarize(graph, reports))
+}
+
+/// Optimizes each connected component of the factor graph like
+/// [optimize_components](fn.optimize_components.html), but with one thread per component, the given maximum number
+/// of iterations and termination criteria, and otherwise the default configuration.
+///
+/// Since neither solvers nor robust kernels nor the residual functions of custom factors have to be thread-safe, the
+/// components are passed to the threads as [models](../../parser/model/struct.FactorGraphModel.html) and solved
+/// with the default solver and without a robust kernel.
+///
+/// Returns an error if the factor graph contains a custom or user-defined factor, a user-defined variable or if the
+/// subgraph of a component cannot be built, which leaves the factor graph unchanged.
+pub fn optimize_components_in_parallel(
+    graph: &FactorGraph,
+    max_iterations: usize,
+    termination: TerminationCriteria,
+) -> Result<OptimizationReport, String> {
+    if let Some(id) = graph
+        .factor_id_map
+        .keys()
+        .find(|id| {
+            matches!(
+                graph.get_factor(**id).unwrap().factor_type,
+                FactorType::Custom(_) | FactorType::UserDefined(_)
+            )
+        })
+    {
+        return Err(format!(
+            "The factor {} is a custom factor, which cannot be optimized in parallel",
+            id
+        ));
+    }
+    if let Some(var) = graph
+        .node_indices
+        .iter()
+        .map(|i| graph.get_var(*i))
+        .find(|var| matches!(var, Variable::UserDefined(_)))
+    {
+        return Err(format!(
+            "The variable {} is a user-defined variable, which cannot be optimized in parallel",
+            var.get_id()
+        ));
+    }
+    let mut components = vec![];
+    for vars in get_components(graph) {
+        let (component, subgraph) = get_component(graph, &vars)?;
+        components.push((component, FactorGraphModel::from(&subgraph)));
+    }
+    let results = thread::scope(|scope| {
+        let handles: Vec<_> = components
+            .iter()
+            .map(|(component, model)| {
+                scope.spawn(move || {
+                    let mut subgraph = convert_model(model, &BTreeMap::new())?;
+                    component.constrain(&mut subgraph)?;
+                    let config = OptimizerConfig {
+                        max_iterations,
+                        termination,
+                        ..Default::default()
+                    };
+                    Ok(component.optimize(&subgraph, &config))
+                })
+            })
+            .collect();
+        handles
+            .into_iter()
+            .map(|handle| handle.join().unwrap())
+            .collect::<Result<Vec<_>, String>>()
+    })?;
+    let reports = components
+        .into_iter()
+        .zip(results)
+        .map(|((component, _), (report, estimates))| component.finish(graph, report, estimates))
+        .collect();
+    Ok(summarize(graph, reports))
+}
+
+// returns the indices of the variables of each connected component of the factor graph without its fixed variables,
+// in which tied variables are connected to their representatives, ordered by ID
+fn get_components(graph: &FactorGraph) -> Vec<Vec<NodeIndex<usize>>> {
+    let is_non_fixed = |i: NodeIndex<usize>| graph.get_var(i).get_fixed_type() != &FixedType::Fixed;
+    let mut components = UnionFind::new(graph.node_indices.len());
+    for id in graph.factor_id_map.keys() {
+        let indices: Vec<NodeIndex<usize>> = graph
+            .get_factor_var_indices(*id)
+            .unwrap()
+            .into_iter()
+            .filter(|i| is_non_fixed(*i))
+            .collect();
+        indices.windows(2).for_each(|pair| components.union(pair[0].index(), pair[1].index()));
+    }
+    for (tied, representative) in graph.equality_constraints() {
+        components.union(
+            graph.custom_to_csr_id_map[tied].index(),
+            graph.custom_to_csr_id_map[representative].index(),
+        );
+    }
+    let mut component_vars: BTreeMap<usize, Vec<NodeIndex<usize>>> = BTreeMap::new();
+    for i in graph.node_indices.iter().copied().filter(|i| is_non_fixed(*i)) {
+        component_vars.entry(components.find(i.index())).or_default().push(i);
+    }
+    let mut component_vars: Vec<Vec<NodeIndex<usize>>> = component_vars.into_values().collect();
+    component_vars
+        .iter_mut()
+        .for_each(|vars| vars.sort_by_key(|i| graph.get_var(*i).get_id()));
+    component_vars.sort_by_key(|vars| graph.get_var(vars[0]).get_id());
+    component_vars
+}
+
+// returns the component of the given variables and its subgraph without equality constraints and fixed components
+fn get_component(graph: &FactorGraph, vars: &[NodeIndex<usize>]) -> Result<(Component, FactorGraph), String> {
+    let mut is_component = vec![false; graph.node_indices.len()];
+    vars.iter().for_each(|i| is_component[i.index()] = true);
+    let mut is_chosen = is_component.clone();
+    let mut is_anchored = false;
+    for id in graph.factor_id_map.keys() {
+        let indices = graph.get_factor_var_indices(*id).unwrap();
+        if indices.iter().any(|i| is_component[i.index()]) {
+            indices.iter().for_each(|i| is_chosen[i.index()] = true);
+            is_anchored |= indices.iter().all(|i| *i == indices[0]) || indices.iter().any(|i| !is_component[i.index()]);
+        }
+    }
+    let variables: Vec<VariableId> = vars.iter().map(|i| graph.get_var(*i).get_id()).collect();
+    let equalities = graph.equality_constraints();
+    let anchor = match is_anchored {
+        true => None,
+        false => vars
+            .iter()
+            .copied()
+            .find(|i| !equalities.contains_key(&graph.get_var(*i).get_id())),
+    };
+    let subgraph = graph.get_subgraph(&is_chosen, |i| Some(NodeIndex::new(i)) == anchor)?;
+    let component = Component {
+        equalities: equalities
+            .iter()
+            .filter(|(tied, _)| variables.contains(tied))
+            .map(|(tied, representative)| (*tied, *representative))
+            .collect(),
+        masks: variables
+            .iter()
+            .filter_map(|id| Some((*id, graph.fixed_components(*id)?.to_vec())))
+            .collect(),
+        variables,
+    };
+    Ok((component, subgraph))
+}
+
+// returns the report of the factor graph whose components were optimized
+fn summarize(graph: &FactorGraph, components: Vec<ComponentReport>) -> OptimizationReport {
+    OptimizationReport {
+        iterations: components.iter().map(|c| c.report.iterations).max().unwrap_or(0),
+        chi2: total_chi2(graph),
+        termination: components
+            .iter()
+            .map(|c| c.report.termination)
+            .find(|termination| *termination != Termination::Converged)
+            .unwrap_or(Termination::Converged),
+        components,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::examples_gen::triangle_with_loop_closure;
+    use crate::factor_graph::factor::CustomResidual;
+    use crate::optimizer::autodiff::Dual;
+    use crate::optimizer::optimize;
+
+    // returns two triangles, the second of which has IDs starting at 3 and is not anchored
+    fn get_two_sessions() -> FactorGraph {
+        let mut model = triangle_with_loop_closure();
+        let mut session = triangle_with_loop_closure();
+        session.vertices.iter_mut().for_each(|vertex| vertex.id.0 += 3);
+        session
+            .edges
+            .iter_mut()
+            .flat_map(|edge| &mut edge.vertices)
+            .for_each(|id| id.0 += 3);
+        model.vertices.append(&mut session.vertices);
+        model.edges.append(&mut session.edges);
+        model.into()
+    }
+
+    fn get_estimates(factor_graph: &FactorGraph) -> Vec<Vec<f64>> {
+        factor_graph
+            .node_indices
+            .iter()
+            .map(|i| factor_graph.get_var(*i).get_content())
+            .collect()
+    }
+
+    #[test]
+    fn test_components_are_optimized_independently() {
+        let factor_graph = get_two_sessions();
+        let anchor = factor_graph.get_var_by_id(VariableId(3)).unwrap().get_content();
+        let report = optimize_components(&factor_graph, &OptimizerConfig::default()).unwrap();
+        assert_eq!(report.termination, Termination::Converged);
+        assert!(report.chi2 < 1e-12);
+        let variables: Vec<Vec<usize>> = report
+            .components
+            .iter()
+            .map(|component| component.variables.iter().map(|id| id.0).collect())
+            .collect();
+        assert_eq!(variables, vec![vec![1, 2], vec![3, 4, 5]]);
+        assert!(report
+            .components
+            .iter()
+            .all(|c| c.report.termination == Termination::Converged));
+        assert_eq!(
+            report.iterations,
+            report.components.iter().map(|c| c.report.iterations).max().unwrap()
+        );
+        assert_eq!(factor_graph.get_var_by_id(VariableId(3)).unwrap().get_content(), anchor);
+
+        let triangle: FactorGraph = triangle_with_loop_closure().into();
+        optimize(&triangle, report.components[0].report.iterations);
+        assert_eq!(get_estimates(&factor_graph)[..3], get_estimates(&triangle)[..]);
+        assert_eq!(get_estimates(&factor_graph)[3..], get_estimates(&triangle)[..]);
+    }
+
+    #[test]
+    fn test_parallel_optimization() {
+        let mut sequential = get_two_sessions();
+        let mut parallel = get_two_sessions();
+        for factor_graph in [&mut sequential, &mut parallel] {
+            factor_graph
+                .fix_components(VariableId(4), vec![false, false, true])
+                .unwrap();
+        }
+        let expected = optimize_components(&sequential, &OptimizerConfig::default()).unwrap();
+        let report = optimize_components_in_parallel(&parallel, 100, TerminationCriteria::default()).unwrap();
+        assert_eq!(report, expected);
+        assert_eq!(get_estimates(&parallel), get_estimates(&sequential));
+        assert_eq!(parallel.get_var_by_id(VariableId(4)).unwrap().get_content()[2], 2.0);
+    }
+
+    #[test]
+    fn test_custom_factors_are_not_optimized_in_parallel() {
+        let mut factor_graph = get_two_sessions();
+        let residual = CustomResidual::new("Zero", |contents: &[Vec<Dual>], _: &[f64]| contents[0].clone());
+        factor_graph
+            .add_factor(
+                VariableId(4),
+                VariableId(4),
+                FactorType::Custom(residual),
+                vec![],
+                vec![1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0].into(),
+            )
+            .unwrap();
+        let estimates = get_estimates(&factor_graph);
+        assert!(optimize_components_in_parallel(&factor_graph, 10, TerminationCriteria::default()).is_err());
+        assert_eq!(get_estimates(&factor_graph), estimates);
+        assert!(optimize_components(&factor_graph, &OptimizerConfig::default()).is_ok());
+    }
+}
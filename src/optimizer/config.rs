@@ -0,0 +1,140 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Configuration of optimizations, which bundles the knobs of the specialized optimize functions, see
+//! [optimize_with_config](../fn.optimize_with_config.html).
+
+use crate::optimizer::metrics::OptimizerMetrics;
+use crate::optimizer::ordering::VariableOrdering;
+use crate::optimizer::relinearization::RelinearizationCache;
+use crate::optimizer::solver::sparse_cholesky::SparseCholeskySolver;
+use crate::optimizer::solver::LinearSolver;
+use crate::optimizer::termination::TerminationCriteria;
+use crate::optimizer::warm_start::OptimizerState;
+use crate::optimizer::FactorReweighting;
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+/// How much an optimization reports on the standard error stream.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Verbosity {
+    /// Nothing is reported.
+    Silent,
+    /// The number of iterations, the final total χ² and the reason for stopping are reported.
+    Summary,
+    /// The total χ² after each iteration is reported as well.
+    Iterations,
+}
+
+/// Configuration of [optimize_with_config](../fn.optimize_with_config.html).
+///
+/// The defaults solve each linear system with the
+/// [SparseCholeskySolver](../solver/sparse_cholesky/struct.SparseCholeskySolver.html) in the natural ordering
+/// without a robust kernel, shorten each step by a line search and iterate silently until convergence, but at most
+/// 100 times.
+///
+/// ```
+/// use gs_rs::optimizer::config::OptimizerConfig;
+/// use gs_rs::optimizer::dcs::DynamicCovarianceScaling;
+///
+/// let dcs = DynamicCovarianceScaling::default();
+/// let config = OptimizerConfig {
+///     max_iterations: 10,
+///     robust_kernel: Some(&dcs),
+///     ..Default::default()
+/// };
+/// assert_eq!(config.max_iterations, 10);
+/// ```
+#[derive(Clone, Copy)]
+pub struct OptimizerConfig<'a> {
+    /// The maximum number of iterations.
+    pub max_iterations: usize,
+    /// The solver of each linear system.
+    pub solver: &'a dyn LinearSolver,
+    /// The order in which the variables are passed to the solver. It is computed once, since the structure of the
+    /// linear system does not change between iterations.
+    pub ordering: VariableOrdering,
+    /// The reweighting of the factors according to their χ² at the estimates of the previous iteration, e.g.
+    /// [Dynamic Covariance Scaling](../dcs/struct.DynamicCovarianceScaling.html) or a
+    /// [Chi2Gate](../chi2_gating/struct.Chi2Gate.html). The first iteration is reweighted according to the initial
+    /// estimates.
+    pub robust_kernel: Option<&'a dyn FactorReweighting>,
+    /// Whether the robust kernel is replaced by a Huber kernel whose widths are estimated from the residuals before
+    /// each iteration, see [HuberKernel::estimate](../huber/struct.HuberKernel.html#method.estimate).
+    pub auto_tuned_huber: bool,
+    /// Whether each step is shortened by a line search so that the total χ² does not increase. Otherwise, each
+    /// Gauss-Newton step is applied as it is.
+    ///
+    /// Steps with a robust kernel are always applied as they are, since the reweighted factors do not minimize the
+    /// total χ².
+    pub line_search: bool,
+    /// The state of previous optimizations, which is reused and updated for the next one, see
+    /// [OptimizerState](../warm_start/struct.OptimizerState.html). Its solver and fill-reducing ordering replace
+    /// the solver and the ordering, and its linear systems are not reweighted.
+    pub state: Option<&'a RefCell<OptimizerState>>,
+    /// The cache of linearized factors, so that only the factors whose variables moved by at least its threshold
+    /// are linearized again, see [RelinearizationCache](../relinearization/struct.RelinearizationCache.html). Its
+    /// linear systems are not reweighted, and it is not used together with a state.
+    pub relinearization: Option<&'a RefCell<RelinearizationCache>>,
+    /// The criteria for stopping before the maximum number of iterations.
+    pub termination: TerminationCriteria<'a>,
+    /// How much the optimization reports.
+    pub verbosity: Verbosity,
+    /// The receiver of the optimization's report, e.g. the
+    /// [OptimizerCounters](../metrics/struct.OptimizerCounters.html) of a long-running service. Each connected
+    /// component of [optimize_components](../components/fn.optimize_components.html) reports separately.
+    pub metrics: Option<&'a dyn OptimizerMetrics>,
+}
+
+impl Default for OptimizerConfig<'_> {
+    fn default() -> Self {
+        OptimizerConfig {
+            max_iterations: 100,
+            solver: &SparseCholeskySolver,
+            ordering: VariableOrdering::Natural,
+            robust_kernel: None,
+            auto_tuned_huber: false,
+            line_search: true,
+            state: None,
+            relinearization: None,
+            termination: TerminationCriteria::default(),
+            verbosity: Verbosity::Silent,
+            metrics: None,
+        }
+    }
+}
+
+impl OptimizerConfig<'_> {
+    /// Returns the settings which determine the optimization's result by name, e.g. for the summary of a composed
+    /// file, see [compose_file_with_summary](../../parser/trait.Parser.html#method.compose_file_with_summary).
+    ///
+    /// The settings are "maxIterations", "solver", "robustKernel" and, if they are set, "convergenceThreshold" and
+    /// "timeBudget". Robust kernels have no names, so only whether one is used is recorded.
+    pub fn settings(&self) -> BTreeMap<String, String> {
+        let mut settings = BTreeMap::new();
+        settings.insert(String::from("maxIterations"), self.max_iterations.to_string());
+        settings.insert(String::from("solver"), self.solver.name());
+        let robust_kernel = if self.robust_kernel.is_some() || self.auto_tuned_huber {
+            "enabled"
+        } else {
+            "none"
+        };
+        settings.insert(String::from("robustKernel"), String::from(robust_kernel));
+        if let Some(threshold) = self.termination.convergence_threshold {
+            settings.insert(String::from("convergenceThreshold"), threshold.to_string());
+        }
+        if let Some(budget) = self.termination.time_budget {
+            settings.insert(String::from("timeBudget"), format!("{:?}", budget));
+        }
+        settings
+    }
+}
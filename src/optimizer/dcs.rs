@@ -0,0 +1,89 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Dynamic Covariance Scaling as described by Agarwal et al., "Robust Map Optimization using Dynamic Covariance
+//! Scaling".
+//!
+//! Each time the linear system is assembled, the information matrix of a factor with the squared error χ² is
+//! scaled by s² with s = min(1, 2Φ / (Φ + χ²)). Factors whose error is small compared to Φ keep their full weight,
+//! while the influence of outliers such as wrong loop closures decreases with their error. Unlike switchable
+//! constraints (see [FactorType](../../factor_graph/factor/enum.FactorType.html#variant.SwitchableOdometry2D)), no
+//! additional variables are required.
+
+use crate::factor_graph::factor::FactorType;
+use crate::optimizer::FactorReweighting;
+
+/// Configuration of Dynamic Covariance Scaling, i.e. the parameter Φ per factor type.
+///
+/// Factors of types without a configured Φ are not scaled. Since loop closures usually share their type with
+/// odometry (e.g. Odometry2D), Φ is configured for the whole type, which leaves consistent odometry factors
+/// unchanged as long as their χ² stays below Φ.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DynamicCovarianceScaling {
+    phis: Vec<(FactorType, f64)>,
+}
+
+impl DynamicCovarianceScaling {
+    /// Returns a configuration which does not scale any factor.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the configuration with the given Φ for factors of the given type, replacing a previous value.
+    ///
+    /// Agarwal et al. report Φ = 1 to work well for a wide range of datasets.
+    pub fn with_phi(mut self, factor_type: FactorType, phi: f64) -> Self {
+        self.phis.retain(|(t, _)| *t != factor_type);
+        self.phis.push((factor_type, phi));
+        self
+    }
+
+    /// Returns the Φ configured for the given factor type, if any.
+    pub fn get_phi(&self, factor_type: &FactorType) -> Option<f64> {
+        self.phis.iter().find(|(t, _)| t == factor_type).map(|(_, phi)| *phi)
+    }
+}
+
+impl FactorReweighting for DynamicCovarianceScaling {
+    fn is_reweighted(&self, factor_type: &FactorType) -> bool {
+        self.get_phi(factor_type).is_some()
+    }
+
+    /// Returns the factor s² by which the information matrix of a factor with the given type and squared error is
+    /// scaled.
+    fn get_weight(&self, factor_type: &FactorType, chi2: f64) -> f64 {
+        match self.get_phi(factor_type) {
+            Some(phi) => {
+                let scale = (2.0 * phi / (phi + chi2)).min(1.0);
+                scale * scale
+            }
+            None => 1.0,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn test_get_weight() {
+        let dcs = DynamicCovarianceScaling::new()
+            .with_phi(FactorType::Odometry2D, 2.0)
+            .with_phi(FactorType::Odometry2D, 1.0);
+        assert_eq!(dcs.get_phi(&FactorType::Odometry2D), Some(1.0));
+        assert_relative_eq!(dcs.get_weight(&FactorType::Odometry2D, 0.5), 1.0);
+        assert_relative_eq!(dcs.get_weight(&FactorType::Odometry2D, 3.0), 0.25);
+        assert_relative_eq!(dcs.get_weight(&FactorType::Position2D, 3.0), 1.0);
+    }
+}
@@ -0,0 +1,316 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Numerical diagnostics of optimizations, which stop at the first non-finite value instead of letting it spread
+//! through the whole estimate.
+
+#![allow(non_snake_case)]
+
+use crate::factor_graph::factor::FactorId;
+use crate::factor_graph::variable::{FixedType, VariableId};
+use crate::factor_graph::FactorGraph;
+use crate::optimizer::block_sparse::BlockSparseMatrix;
+use crate::optimizer::linear_system::{calculate_H_b, calculate_error, calculate_factor_H_b};
+use crate::optimizer::regularization::{find_unconstrained_blocks, UnconstrainedBlock};
+use crate::optimizer::solver::sparse_cholesky::solve_with_cholesky;
+use crate::optimizer::{total_chi2, update_vars, update_vars_with_line_search};
+use nalgebra::{CsCholesky, DVector};
+
+/// The number of power iterations for each of the extreme eigenvalues of H.
+const POWER_ITERATIONS: usize = 50;
+
+/// The diagnostics of a completed iteration.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct IterationDiagnostics {
+    /// The total χ² after the iteration.
+    pub chi2: f64,
+    /// The estimated condition number of H, see [estimate_condition_number](fn.estimate_condition_number.html).
+    pub condition_number: f64,
+}
+
+/// A numerical problem which stopped the optimization.
+#[derive(Debug, Clone, PartialEq)]
+pub enum NumericalIssue {
+    /// The errors of the factors are not finite at the estimates before the first iteration.
+    NonFiniteInitialError {
+        /// The factors with non-finite errors.
+        factors: Vec<FactorId>,
+    },
+    /// H or b is not finite, e.g. because of a non-finite information matrix.
+    NonFiniteSystem {
+        /// The iteration in which the linear system was calculated, starting at 0.
+        iteration: usize,
+        /// The factors with non-finite contributions to H or b.
+        factors: Vec<FactorId>,
+    },
+    /// H is not positive-definite, e.g. because some variables are not determined by the factors.
+    SingularSystem {
+        /// The iteration in which the linear system could not be solved, starting at 0.
+        iteration: usize,
+        /// The variables which are not determined by the factors, see
+        /// [find_unconstrained_blocks](../regularization/fn.find_unconstrained_blocks.html). Empty if H is only
+        /// numerically singular.
+        unconstrained: Vec<UnconstrainedBlock>,
+    },
+    /// The step of the linear system is not finite although H and b are, e.g. because H is nearly singular.
+    NonFiniteStep {
+        /// The iteration in which the step was calculated, starting at 0.
+        iteration: usize,
+        /// The variables with non-finite corrections.
+        variables: Vec<VariableId>,
+    },
+    /// The errors of the factors would not be finite after applying the step, which is therefore not applied.
+    NonFiniteError {
+        /// The iteration in which the step was calculated, starting at 0.
+        iteration: usize,
+        /// The factors with non-finite errors.
+        factors: Vec<FactorId>,
+    },
+}
+
+/// The result of an optimization with diagnostics.
+#[derive(Debug, Clone, PartialEq)]
+pub struct DiagnosticsReport {
+    /// The diagnostics of each completed iteration.
+    pub iterations: Vec<IterationDiagnostics>,
+    /// The problem which stopped the optimization, if any. The estimates are the ones before the iteration in which
+    /// it was detected.
+    pub issue: Option<NumericalIssue>,
+}
+
+/// Optimizes a factor graph like [optimize](../fn.optimize.html), but checks the errors, H and the step of each
+/// iteration and stops at the first numerical issue instead of applying non-finite values to the estimates.
+///
+/// Estimating the condition number of H requires a few additional solves per iteration, so this is meant for
+/// debugging.
+pub fn optimize_with_diagnostics(graph: &FactorGraph, iterations: usize) -> DiagnosticsReport {
+    let mut report = DiagnosticsReport {
+        iterations: vec![],
+        issue: None,
+    };
+    let factors = find_non_finite_errors(graph);
+    if !factors.is_empty() {
+        report.issue = Some(NumericalIssue::NonFiniteInitialError { factors });
+        return report;
+    }
+    let mut chi2 = total_chi2(graph);
+    for iteration in 0..iterations {
+        let (H, b) = calculate_H_b(graph);
+        if !is_finite_system(&H, &b) {
+            let factors = find_non_finite_contributions(graph);
+            report.issue = Some(NumericalIssue::NonFiniteSystem { iteration, factors });
+            return report;
+        }
+        let condition_number = estimate_condition_number(&H);
+        let cholesky = CsCholesky::new(&H.to_cs_matrix());
+        let step = match solve_with_cholesky(&cholesky, &(b * -1.0)) {
+            Ok(step) => step,
+            Err(_) => {
+                report.issue = Some(NumericalIssue::SingularSystem {
+                    iteration,
+                    unconstrained: find_unconstrained_blocks(graph),
+                });
+                return report;
+            }
+        };
+        let variables = find_non_finite_corrections(graph, &step);
+        if !variables.is_empty() {
+            report.issue = Some(NumericalIssue::NonFiniteStep { iteration, variables });
+            return report;
+        }
+
+        let initial_contents = get_contents(graph);
+        update_vars(graph, &step);
+        let factors = find_non_finite_errors(graph);
+        set_contents(graph, initial_contents);
+        if !factors.is_empty() {
+            report.issue = Some(NumericalIssue::NonFiniteError { iteration, factors });
+            return report;
+        }
+        chi2 = update_vars_with_line_search(graph, &step, chi2);
+        report.iterations.push(IterationDiagnostics { chi2, condition_number });
+    }
+    report
+}
+
+/// Returns an estimate of the condition number of H, i.e. the ratio of its largest and smallest eigenvalue, which
+/// is infinite if H is not positive-definite.
+///
+/// The eigenvalues are estimated with power iterations on H and on its inverse, so the estimate is a lower bound
+/// which is usually close to the condition number.
+pub fn estimate_condition_number(H: &BlockSparseMatrix) -> f64 {
+    if H.dim() == 0 {
+        return 1.0;
+    }
+    let cholesky = CsCholesky::new(&H.to_cs_matrix());
+    let start = DVector::from_element(H.dim(), 1.0 / (H.dim() as f64).sqrt());
+    let (mut x, mut y) = (start.clone(), start);
+    let (mut largest, mut inverse_smallest) = (0.0, 0.0);
+    for _i in 0..POWER_ITERATIONS {
+        let product = H.mul_vector(&x);
+        largest = product.norm();
+        x = product / largest;
+        let solution = match solve_with_cholesky(&cholesky, &y) {
+            Ok(solution) => DVector::from_vec(solution),
+            Err(_) => return f64::INFINITY,
+        };
+        inverse_smallest = solution.norm();
+        y = solution / inverse_smallest;
+    }
+    if largest.is_finite() && inverse_smallest.is_finite() {
+        largest * inverse_smallest
+    } else {
+        f64::INFINITY
+    }
+}
+
+fn find_non_finite_errors(graph: &FactorGraph) -> Vec<FactorId> {
+    graph
+        .factor_id_map
+        .keys()
+        .copied()
+        .filter(|id| calculate_error(graph, *id).unwrap().iter().any(|e| !e.is_finite()))
+        .collect()
+}
+
+fn is_finite_system(H: &BlockSparseMatrix, b: &DVector<f64>) -> bool {
+    H.blocks().all(|(_, block)| block.iter().all(|v| v.is_finite())) && b.iter().all(|v| v.is_finite())
+}
+
+fn find_non_finite_contributions(graph: &FactorGraph) -> Vec<FactorId> {
+    graph
+        .factor_id_map
+        .keys()
+        .copied()
+        .filter(|id| {
+            let (H, b) = calculate_factor_H_b(graph, *id).unwrap();
+            !is_finite_system(&H, &b)
+        })
+        .collect()
+}
+
+fn find_non_finite_corrections(graph: &FactorGraph, step: &[f64]) -> Vec<VariableId> {
+    graph
+        .node_indices
+        .iter()
+        .map(|i| graph.get_var(*i))
+        .filter(|var| match var.get_fixed_type() {
+            FixedType::NonFixed(range) => step[range.clone()].iter().any(|v| !v.is_finite()),
+            FixedType::Fixed => false,
+        })
+        .map(|var| var.get_id())
+        .collect()
+}
+
+fn get_contents(graph: &FactorGraph) -> Vec<Vec<f64>> {
+    graph
+        .node_indices
+        .iter()
+        .map(|i| graph.get_var(*i).get_content())
+        .collect()
+}
+
+fn set_contents(graph: &FactorGraph, contents: Vec<Vec<f64>>) {
+    graph
+        .node_indices
+        .iter()
+        .zip(contents)
+        .for_each(|(i, content)| graph.get_var(*i).set_content(content));
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::examples_gen::triangle_with_loop_closure;
+    use crate::factor_graph::factor::InformationMatrix;
+    use crate::parser::g2o::G2oParser;
+    use crate::parser::Parser;
+    use approx::assert_relative_eq;
+    use nalgebra::{DMatrix, SymmetricEigen};
+
+    #[test]
+    fn test_condition_number_estimate() {
+        let factor_graph: FactorGraph = triangle_with_loop_closure().into();
+        let (H, _) = calculate_H_b(&factor_graph);
+        let eigenvalues = SymmetricEigen::new(H.to_dense()).eigenvalues;
+        let expected = eigenvalues.max() / eigenvalues.min();
+        assert_relative_eq!(estimate_condition_number(&H), expected, max_relative = 1e-3);
+
+        let mut singular = BlockSparseMatrix::new(2);
+        singular.add_block(0..2, 0..2, &DMatrix::from_element(2, 2, 1.0));
+        assert_eq!(estimate_condition_number(&singular), f64::INFINITY);
+    }
+
+    #[test]
+    fn test_well_conditioned_optimization() {
+        let factor_graph = G2oParser::parse_file("data_files/optimizer_tests/full2d_0.g2o").unwrap();
+        let report = optimize_with_diagnostics(&factor_graph, 3);
+        assert_eq!(report.issue, None);
+        assert_eq!(report.iterations.len(), 3);
+        assert!(report
+            .iterations
+            .iter()
+            .all(|i| i.condition_number.is_finite() && i.condition_number >= 1.0));
+        assert_eq!(report.iterations[2].chi2, total_chi2(&factor_graph));
+    }
+
+    #[test]
+    fn test_non_finite_values_are_reported() {
+        let factor_graph: FactorGraph = triangle_with_loop_closure().into();
+        factor_graph
+            .get_var_by_id(VariableId(1))
+            .unwrap()
+            .set_content(vec![f64::NAN, 0.0, 0.0]);
+        let report = optimize_with_diagnostics(&factor_graph, 3);
+        assert!(report.iterations.is_empty());
+        assert_eq!(
+            report.issue,
+            Some(NumericalIssue::NonFiniteInitialError {
+                factors: vec![FactorId(0), FactorId(1)],
+            })
+        );
+
+        let mut factor_graph: FactorGraph = triangle_with_loop_closure().into();
+        let contents = get_contents(&factor_graph);
+        let information = InformationMatrix {
+            content: DMatrix::from_element(3, 3, f64::NAN),
+        };
+        factor_graph.set_information_matrix(FactorId(1), information).unwrap();
+        let report = optimize_with_diagnostics(&factor_graph, 3);
+        assert_eq!(
+            report.issue,
+            Some(NumericalIssue::NonFiniteSystem {
+                iteration: 0,
+                factors: vec![FactorId(1)],
+            })
+        );
+        assert_eq!(get_contents(&factor_graph), contents);
+    }
+
+    #[test]
+    fn test_singular_system_is_reported() {
+        let factor_graph: FactorGraph = G2oParser::parse_string_to_model("VERTEX_SE2 0 0 0 0\nVERTEX_XY 1 1 1")
+            .unwrap()
+            .into();
+        let report = optimize_with_diagnostics(&factor_graph, 3);
+        match report.issue {
+            Some(NumericalIssue::SingularSystem {
+                iteration,
+                unconstrained,
+            }) => {
+                assert_eq!(iteration, 0);
+                assert_eq!(unconstrained.len(), 2);
+            }
+            issue => panic!("Unexpected issue: {:?}", issue),
+        }
+    }
+}
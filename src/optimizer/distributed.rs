@@ -0,0 +1,230 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Distributed optimization of multi-robot factor graphs with a block Gauss-Seidel scheme, i.e. block coordinate
+//! descent.
+//!
+//! The factor graph is split into one partition per robot. Each partition consists of the robot's variables, of
+//! copies of the separators, i.e. the other robots' variables with which the robot's variables share factors, and of
+//! the factors between these variables. The robots optimize their partitions in turn with fixed separators and only
+//! exchange the estimates of separators, see [PartitionSolver](struct.PartitionSolver.html).
+//!
+//! ```
+//! use gs_rs::optimizer::distributed::split;
+//! use gs_rs::parser::g2o::G2oParser;
+//! use gs_rs::parser::Parser;
+//! use std::collections::BTreeMap;
+//!
+//! let factor_graph = G2oParser::parse_file("data_files/optimizer_tests/full2d_0.g2o").unwrap();
+//! let mut partitions = split(&factor_graph, |id| id.0 % 2).unwrap();
+//! let mut separators = BTreeMap::new();
+//! for _round in 0..3 {
+//!     for partition in &mut partitions {
+//!         // e.g. received from the other robots
+//!         partition.update_separators(&separators);
+//!         partition.solve(2);
+//!         // e.g. sent to the other robots
+//!         separators.extend(partition.shared_estimates());
+//!     }
+//! }
+//! ```
+
+use crate::factor_graph::adjacency::NodeIndex;
+use crate::factor_graph::variable::{FixedType, VariableId};
+use crate::factor_graph::FactorGraph;
+use crate::optimizer::optimize;
+use std::collections::{BTreeMap, BTreeSet};
+
+/// The part of a multi-robot factor graph which is optimized by one robot.
+pub struct PartitionSolver {
+    robot: usize,
+    subgraph: FactorGraph,
+    variables: Vec<VariableId>,
+    separators: Vec<VariableId>,
+    shared: Vec<VariableId>,
+}
+
+impl PartitionSolver {
+    /// Returns the robot to which the partition belongs.
+    pub fn robot(&self) -> usize {
+        self.robot
+    }
+
+    /// Returns the robot's variables.
+    pub fn variables(&self) -> &[VariableId] {
+        &self.variables
+    }
+
+    /// Returns the other robots' variables with which the robot's variables share factors.
+    pub fn separators(&self) -> &[VariableId] {
+        &self.separators
+    }
+
+    /// Returns the robot's variables which are separators of other robots.
+    pub fn shared(&self) -> &[VariableId] {
+        &self.shared
+    }
+
+    /// Returns the factor graph of the partition, in which the separators are fixed.
+    pub fn factor_graph(&self) -> &FactorGraph {
+        &self.subgraph
+    }
+
+    /// Replaces the estimates of the separators by the given ones. Estimates of other variables are ignored.
+    pub fn update_separators(&self, estimates: &BTreeMap<VariableId, Vec<f64>>) {
+        for id in &self.separators {
+            if let Some(estimate) = estimates.get(id) {
+                self.subgraph.get_var_by_id(*id).unwrap().set_content(estimate.clone());
+            }
+        }
+    }
+
+    /// Optimizes the robot's variables with the given number of iterations, keeping the separators fixed.
+    pub fn solve(&self, iterations: usize) {
+        optimize(&self.subgraph, iterations);
+    }
+
+    /// Returns the estimates of the robot's variables which are separators of other robots.
+    pub fn shared_estimates(&self) -> BTreeMap<VariableId, Vec<f64>> {
+        self.get_estimates(&self.shared)
+    }
+
+    /// Returns the estimates of the robot's variables.
+    pub fn estimates(&self) -> BTreeMap<VariableId, Vec<f64>> {
+        self.get_estimates(&self.variables)
+    }
+
+    fn get_estimates(&self, ids: &[VariableId]) -> BTreeMap<VariableId, Vec<f64>> {
+        ids.iter()
+            .map(|id| (*id, self.subgraph.get_var_by_id(*id).unwrap().get_content()))
+            .collect()
+    }
+}
+
+/// Splits the factor graph into the partitions of the robots to which the given function assigns the variables,
+/// ordered by robot.
+///
+/// The partitions start with the current estimates. Variables which are fixed in the factor graph stay fixed. If a
+/// partition neither contains a fixed variable nor has separators, its first variable is fixed, so that it can be
+/// optimized on its own.
+pub fn split<F: Fn(VariableId) -> usize>(graph: &FactorGraph, robot_of: F) -> Result<Vec<PartitionSolver>, String> {
+    let var_count = graph.node_indices.len();
+    let robots: Vec<usize> = (0..var_count)
+        .map(|i| robot_of(graph.get_var(NodeIndex::new(i)).get_id()))
+        .collect();
+    let mut separators: Vec<BTreeSet<usize>> = vec![BTreeSet::new(); var_count];
+    graph.factor_id_map.keys().for_each(|id| {
+        let indices = graph.get_factor_var_indices(*id).unwrap();
+        for a in indices.iter().map(|a| a.index()) {
+            separators[a].extend(indices.iter().map(|b| b.index()).filter(|b| robots[*b] != robots[a]));
+        }
+    });
+
+    let mut partitions = vec![];
+    for robot in robots.iter().collect::<BTreeSet<_>>() {
+        let own: Vec<usize> = (0..var_count).filter(|i| robots[*i] == *robot).collect();
+        let foreign: BTreeSet<usize> = own.iter().flat_map(|i| separators[*i].iter().copied()).collect();
+        let fix_first = foreign.is_empty()
+            && own
+                .iter()
+                .all(|i| graph.get_var(NodeIndex::new(*i)).get_fixed_type() != &FixedType::Fixed);
+        let mut is_chosen = vec![false; var_count];
+        own.iter().chain(&foreign).for_each(|i| is_chosen[*i] = true);
+        let subgraph = graph.get_subgraph(&is_chosen, |i| foreign.contains(&i) || (fix_first && i == own[0]))?;
+        let get_ids = |indices: &mut dyn Iterator<Item = usize>| {
+            indices.map(|i| graph.get_var(NodeIndex::new(i)).get_id()).collect()
+        };
+        partitions.push(PartitionSolver {
+            robot: *robot,
+            subgraph,
+            variables: get_ids(&mut own.iter().copied()),
+            separators: get_ids(&mut foreign.iter().copied()),
+            shared: get_ids(&mut own.iter().copied().filter(|i| !separators[*i].is_empty())),
+        });
+    }
+    Ok(partitions)
+}
+
+/// Optimizes a multi-robot factor graph with the given number of rounds, in each of which the robots to which the
+/// given function assigns the variables optimize their partitions in turn with the given number of iterations.
+///
+/// Since each robot starts from the shared estimates of the robots before it in the same round, this is a block
+/// Gauss-Seidel scheme. At the end, the robots' estimates are written to the factor graph.
+pub fn optimize_distributed<F: Fn(VariableId) -> usize>(
+    graph: &FactorGraph,
+    robot_of: F,
+    rounds: usize,
+    iterations: usize,
+) -> Result<(), String> {
+    let partitions = split(graph, robot_of)?;
+    let mut shared = BTreeMap::new();
+    for _round in 0..rounds {
+        for partition in &partitions {
+            partition.update_separators(&shared);
+            partition.solve(iterations);
+            shared.extend(partition.shared_estimates());
+        }
+    }
+    for (id, estimate) in partitions.iter().flat_map(|partition| partition.estimates()) {
+        let var = graph.get_var_by_id(id).unwrap();
+        if var.get_fixed_type() != &FixedType::Fixed {
+            var.set_content(estimate);
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimizer::total_chi2;
+    use crate::parser::g2o::G2oParser;
+    use crate::parser::Parser;
+
+    const FILE_NAME: &str = "data_files/optimizer_tests/full2d_0.g2o";
+
+    #[test]
+    fn test_split() {
+        let factor_graph = G2oParser::parse_file(FILE_NAME).unwrap();
+        let partitions = split(&factor_graph, |id| id.0 % 2).unwrap();
+        assert_eq!(partitions.iter().map(|p| p.robot()).collect::<Vec<_>>(), vec![0, 1]);
+        for (partition, other) in partitions.iter().zip(partitions.iter().rev()) {
+            assert!(partition.variables().iter().all(|id| id.0 % 2 == partition.robot()));
+            assert!(partition.separators().iter().all(|id| other.shared().contains(id)));
+            assert!(other.shared().iter().all(|id| partition.separators().contains(id)));
+            assert!(partition.separators().iter().all(|id| partition
+                .factor_graph()
+                .get_var_by_id(*id)
+                .unwrap()
+                .get_fixed_type()
+                == &FixedType::Fixed));
+        }
+        let variable_count: usize = partitions.iter().map(|p| p.variables().len()).sum();
+        assert_eq!(variable_count, factor_graph.node_indices.len());
+    }
+
+    #[test]
+    fn test_optimize_distributed() {
+        let expected = G2oParser::parse_file(FILE_NAME).unwrap();
+        optimize(&expected, 10);
+        let factor_graph = G2oParser::parse_file(FILE_NAME).unwrap();
+        let robot_of = |id: VariableId| {
+            if id.0 < factor_graph.node_indices.len() / 2 {
+                0
+            } else {
+                1
+            }
+        };
+        optimize_distributed(&factor_graph, robot_of, 20, 3).unwrap();
+        assert!(total_chi2(&factor_graph) < 1.01 * total_chi2(&expected));
+    }
+}
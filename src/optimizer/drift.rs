@@ -0,0 +1,255 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Odometry drift, i.e. the deviation of the dead-reckoned trajectory, which only composes the odometry
+//! measurements, from the optimized one, e.g. to characterize an odometry source.
+//!
+//! The trajectory consists of the vehicle poses in the order of their IDs, up to the first pose without an
+//! Odometry2D or Odometry3D factor from or to its predecessor. Distances are measured along the optimized trajectory.
+//!
+//! ```
+//! use gs_rs::examples_gen::triangle_with_loop_closure;
+//! use gs_rs::factor_graph::FactorGraph;
+//! use gs_rs::optimizer::drift::estimate_drift;
+//! use gs_rs::optimizer::optimize;
+//!
+//! let factor_graph: FactorGraph = triangle_with_loop_closure().into();
+//! optimize(&factor_graph, 10);
+//! let report = estimate_drift(&factor_graph, 1.0).unwrap();
+//! assert!((report.total.distance - 2.0).abs() < 1e-6);
+//! assert!(report.total.translation_drift() < 1e-6);
+//! ```
+
+use crate::factor_graph::factor::FactorType;
+use crate::factor_graph::variable::{Variable, VariableId};
+use crate::factor_graph::FactorGraph;
+use crate::optimizer::linear_system::iso3d_gradients::get_isometry;
+use nalgebra::{Isometry3, Vector3};
+
+/// The drift accumulated along a part of the trajectory, see [estimate_drift](fn.estimate_drift.html).
+#[derive(Debug, Clone, PartialEq)]
+pub struct SegmentDrift {
+    /// The ID of the pose at which the dead reckoning starts from the optimized pose.
+    pub start: VariableId,
+    /// The ID of the pose at which the drift is measured.
+    pub end: VariableId,
+    /// The distance travelled from the start to the end.
+    pub distance: f64,
+    /// The distance between the dead-reckoned and the optimized position at the end.
+    pub translation_error: f64,
+    /// The angle of the rotation between the dead-reckoned and the optimized orientation at the end.
+    pub rotation_error: f64,
+}
+
+impl SegmentDrift {
+    /// Returns the translation error per distance travelled, i.e. the relative drift.
+    pub fn translation_drift(&self) -> f64 {
+        self.translation_error / self.distance
+    }
+
+    /// Returns the rotation error per distance travelled in radians per unit of distance.
+    pub fn rotation_drift(&self) -> f64 {
+        self.rotation_error / self.distance
+    }
+}
+
+/// The drift of the dead-reckoned trajectory, see [estimate_drift](fn.estimate_drift.html).
+#[derive(Debug, Clone, PartialEq)]
+pub struct DriftReport {
+    /// The drift accumulated from the first to the last pose of the trajectory.
+    pub total: SegmentDrift,
+    /// The drift of the segments which start at each pose and end at the first pose at least the segment length
+    /// further along the trajectory. Poses closer than the segment length to the end do not start a segment.
+    pub segments: Vec<SegmentDrift>,
+}
+
+impl DriftReport {
+    /// Returns the mean of the segments' translation drifts, or None if the trajectory is shorter than the segment
+    /// length.
+    pub fn mean_segment_translation_drift(&self) -> Option<f64> {
+        mean(self.segments.iter().map(SegmentDrift::translation_drift))
+    }
+
+    /// Returns the mean of the segments' rotation drifts, or None if the trajectory is shorter than the segment
+    /// length.
+    pub fn mean_segment_rotation_drift(&self) -> Option<f64> {
+        mean(self.segments.iter().map(SegmentDrift::rotation_drift))
+    }
+}
+
+/// Compares the dead-reckoned trajectory with the current estimates, e.g. after an optimization, and returns the drift
+/// of the whole trajectory and of its segments of the given length, which for a segment starting at a pose
+/// composes the odometry measurements from the estimate of that pose on.
+///
+/// Returns an error if the segment length is not positive or if the first vehicle pose has no odometry factor to the
+/// next one.
+pub fn estimate_drift(graph: &FactorGraph, segment_length: f64) -> Result<DriftReport, String> {
+    if segment_length.is_nan() || segment_length <= 0.0 {
+        return Err(format!("The segment length {} is not positive", segment_length));
+    }
+    let (ids, poses, odometry) = get_trajectory(graph);
+    if odometry.is_empty() {
+        return Err(String::from("The trajectory has no odometry factors"));
+    }
+    let mut distances = vec![0.0];
+    for pair in poses.windows(2) {
+        let step = (pair[1].translation.vector - pair[0].translation.vector).norm();
+        distances.push(distances.last().unwrap() + step);
+    }
+    let get_drift = |start: usize, end: usize| {
+        let dead_reckoned = odometry[start..end].iter().fold(poses[start], |pose, step| pose * step);
+        SegmentDrift {
+            start: ids[start],
+            end: ids[end],
+            distance: distances[end] - distances[start],
+            translation_error: (dead_reckoned.translation.vector - poses[end].translation.vector).norm(),
+            rotation_error: poses[end].rotation.angle_to(&dead_reckoned.rotation),
+        }
+    };
+    let segments = (0..poses.len())
+        .filter_map(|start| {
+            (start + 1..poses.len())
+                .find(|end| distances[*end] - distances[start] >= segment_length)
+                .map(|end| get_drift(start, end))
+        })
+        .collect();
+    Ok(DriftReport {
+        total: get_drift(0, poses.len() - 1),
+        segments,
+    })
+}
+
+// returns the IDs and estimates of the trajectory's poses and the odometry measurements between consecutive poses
+fn get_trajectory(graph: &FactorGraph) -> (Vec<VariableId>, Vec<Isometry3<f64>>, Vec<Isometry3<f64>>) {
+    let mut vehicles: Vec<&Variable> = graph
+        .node_indices
+        .iter()
+        .map(|i| graph.get_var(*i))
+        .filter(|var| matches!(var, Variable::Vehicle2D(_) | Variable::Vehicle3D(_)))
+        .collect();
+    vehicles.sort_by_key(|var| var.get_id());
+    let (mut ids, mut poses, mut odometry) = (vec![], vec![], vec![]);
+    for (i, var) in vehicles.iter().enumerate() {
+        if i > 0 {
+            match get_odometry(graph, vehicles[i - 1].get_id(), var.get_id()) {
+                Some(step) => odometry.push(step),
+                None => break,
+            }
+        }
+        ids.push(var.get_id());
+        poses.push(get_pose(var));
+    }
+    (ids, poses, odometry)
+}
+
+// returns the odometry measurement from the first to the second pose, inverting a factor in the other direction
+fn get_odometry(graph: &FactorGraph, from: VariableId, to: VariableId) -> Option<Isometry3<f64>> {
+    let source_index = graph.custom_to_csr_id_map[&from];
+    graph.factors_between(from, to).into_iter().find_map(|id| {
+        let factor = graph.materialize_factor(graph.get_factor(id).unwrap());
+        let measurement = match factor.factor_type {
+            FactorType::Odometry2D => get_isometry_2d(&factor.constraint),
+            FactorType::Odometry3D => get_isometry(&factor.constraint),
+            _ => return None,
+        };
+        if graph.get_factor_var_indices(id).unwrap()[0] == source_index {
+            Some(measurement)
+        } else {
+            Some(measurement.inverse())
+        }
+    })
+}
+
+fn get_pose(var: &Variable) -> Isometry3<f64> {
+    match var {
+        Variable::Vehicle2D(_) => get_isometry_2d(&var.get_content()),
+        _ => get_isometry(&var.get_content()),
+    }
+}
+
+fn get_isometry_2d(pose: &[f64]) -> Isometry3<f64> {
+    Isometry3::new(Vector3::new(pose[0], pose[1], 0.0), Vector3::new(0.0, 0.0, pose[2]))
+}
+
+fn mean(values: impl ExactSizeIterator<Item = f64>) -> Option<f64> {
+    match values.len() {
+        0 => None,
+        count => Some(values.sum::<f64>() / count as f64),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::g2o::G2oParser;
+    use crate::parser::Parser;
+    use approx::assert_relative_eq;
+
+    // returns poses along the x axis which are one unit apart, whose odometry overestimates the steps by 10% and
+    // turns by the given angle, where the odometry between the last two poses is missing
+    fn get_factor_graph(pose_count: usize, turn: f64) -> FactorGraph {
+        let mut lines = vec![];
+        for i in 0..pose_count + 1 {
+            lines.push(format!("VERTEX_SE2 {} {} 0 0", i, i));
+        }
+        for i in 0..pose_count - 1 {
+            lines.push(format!("EDGE_SE2 {} {} 1.1 0 {} 1 0 0 1 0 1", i, i + 1, turn));
+        }
+        G2oParser::parse_string_to_model(&lines.join("\n")).unwrap().into()
+    }
+
+    #[test]
+    fn test_translation_drift() {
+        let report = estimate_drift(&get_factor_graph(11, 0.0), 5.0).unwrap();
+        assert_eq!((report.total.start, report.total.end), (VariableId(0), VariableId(10)));
+        assert_relative_eq!(report.total.distance, 10.0);
+        assert_relative_eq!(report.total.translation_error, 1.0, epsilon = 1e-9);
+        assert_relative_eq!(report.total.translation_drift(), 0.1, epsilon = 1e-9);
+        assert_eq!(report.total.rotation_error, 0.0);
+        assert_eq!(report.segments.len(), 6);
+        assert_eq!(
+            (report.segments[5].start, report.segments[5].end),
+            (VariableId(5), VariableId(10))
+        );
+        assert_relative_eq!(report.mean_segment_translation_drift().unwrap(), 0.1, epsilon = 1e-9);
+    }
+
+    #[test]
+    fn test_rotation_drift() {
+        let report = estimate_drift(&get_factor_graph(3, 0.01), 1.0).unwrap();
+        assert_relative_eq!(report.total.rotation_error, 0.02, epsilon = 1e-9);
+        assert_relative_eq!(report.mean_segment_rotation_drift().unwrap(), 0.01, epsilon = 1e-9);
+        assert!(estimate_drift(&get_factor_graph(3, 0.0), 3.0)
+            .unwrap()
+            .segments
+            .is_empty());
+    }
+
+    #[test]
+    fn test_offloaded_factor_payloads() {
+        let mut factor_graph = get_factor_graph(11, 0.01);
+        let path = std::env::temp_dir().join(format!("gs-rs-drift-{}.bin", std::process::id()));
+        factor_graph.offload_factor_payloads(&path).unwrap();
+        let report = estimate_drift(&factor_graph, 5.0).unwrap();
+        let expected = estimate_drift(&get_factor_graph(11, 0.01), 5.0).unwrap();
+        assert_eq!(report.total.translation_error, expected.total.translation_error);
+        assert_eq!(report.total.rotation_error, expected.total.rotation_error);
+        assert_eq!(report.segments.len(), expected.segments.len());
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_invalid_input() {
+        assert!(estimate_drift(&get_factor_graph(3, 0.0), 0.0).is_err());
+        assert!(estimate_drift(&get_factor_graph(1, 0.0), 1.0).is_err());
+    }
+}
@@ -0,0 +1,120 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Evaluation of the factors at the current estimates, e.g. to find inconsistent measurements in real datasets.
+
+use crate::factor_graph::factor::{FactorId, FactorType};
+use crate::factor_graph::variable::VariableId;
+
+/// The error of a single factor at the current estimates, see [evaluate](../fn.evaluate.html).
+#[derive(Debug, Clone, PartialEq)]
+pub struct FactorEvaluation {
+    /// The factor's ID.
+    pub id: FactorId,
+    /// The factor's type.
+    pub factor_type: FactorType,
+    /// The IDs of the factor's variables in the order of its residual.
+    pub variables: Vec<VariableId>,
+    /// The factor's error vector.
+    pub error: Vec<f64>,
+    /// The factor's squared error weighted by its information matrix.
+    pub chi2: f64,
+}
+
+/// The errors of all factors of a factor graph at the current estimates, see [evaluate](../fn.evaluate.html).
+#[derive(Debug, Clone, PartialEq)]
+pub struct Evaluation {
+    /// The evaluations of all factors in the order of their IDs.
+    pub factors: Vec<FactorEvaluation>,
+    /// The sum of the factors' χ², i.e. the total χ².
+    pub total_chi2: f64,
+}
+
+impl Evaluation {
+    /// Returns the k factors with the largest χ² in descending order, or all factors if there are fewer. Factors
+    /// with the same χ² are ordered by their IDs.
+    pub fn worst(&self, k: usize) -> Vec<&FactorEvaluation> {
+        let mut factors: Vec<&FactorEvaluation> = self.factors.iter().collect();
+        factors.sort_by(|a, b| b.chi2.total_cmp(&a.chi2).then(a.id.cmp(&b.id)));
+        factors.truncate(k);
+        factors
+    }
+
+    /// Returns the evaluation of the factor with the given ID.
+    pub fn get(&self, id: FactorId) -> Option<&FactorEvaluation> {
+        self.factors
+            .binary_search_by(|factor| factor.id.cmp(&id))
+            .ok()
+            .map(|i| &self.factors[i])
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::factor_graph::factor::FactorId;
+    use crate::factor_graph::variable::VariableId;
+    use crate::factor_graph::FactorGraph;
+    use crate::optimizer::{evaluate, optimize, total_chi2};
+    use crate::parser::g2o::G2oParser;
+    use crate::parser::Parser;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn test_evaluate() {
+        let factor_graph = G2oParser::parse_file("data_files/optimizer_tests/full2d_0.g2o").unwrap();
+        let contents: Vec<Vec<f64>> = factor_graph
+            .node_indices
+            .iter()
+            .map(|i| factor_graph.get_var(*i).get_content())
+            .collect();
+        let evaluation = evaluate(&factor_graph);
+        assert_eq!(evaluation.factors.len(), factor_graph.factor_id_map.len());
+        assert_eq!(evaluation.total_chi2, total_chi2(&factor_graph));
+        assert_relative_eq!(
+            evaluation.factors.iter().map(|f| f.chi2).sum::<f64>(),
+            evaluation.total_chi2,
+            max_relative = 1e-12
+        );
+        let restored_contents: Vec<Vec<f64>> = factor_graph
+            .node_indices
+            .iter()
+            .map(|i| factor_graph.get_var(*i).get_content())
+            .collect();
+        assert_eq!(contents, restored_contents);
+    }
+
+    #[test]
+    fn test_worst_factors() {
+        // the loop closure contradicts the odometry, which is stronger
+        let g2o_string = [
+            "VERTEX_SE2 0 0 0 0",
+            "FIX 0",
+            "VERTEX_SE2 1 1 0 0",
+            "VERTEX_SE2 2 2 0 0",
+            "EDGE_SE2 0 1 1 0 0 100 0 0 100 0 100",
+            "EDGE_SE2 1 2 1 0 0 100 0 0 100 0 100",
+            "EDGE_SE2 0 2 5 0 0 1 0 0 1 0 1",
+        ]
+        .join("\n");
+        let factor_graph: FactorGraph = G2oParser::parse_string_to_model(&g2o_string).unwrap().into();
+        optimize(&factor_graph, 5);
+        let evaluation = evaluate(&factor_graph);
+        let worst = evaluation.worst(2);
+        assert_eq!(worst.len(), 2);
+        assert_eq!(worst[0].id, FactorId(2));
+        assert_eq!(worst[0].variables, vec![VariableId(0), VariableId(2)]);
+        assert!(worst[0].chi2 > worst[1].chi2);
+        assert_eq!(evaluation.worst(10).len(), 3);
+        assert_eq!(evaluation.get(FactorId(2)), Some(worst[0]));
+        assert_eq!(evaluation.get(FactorId(3)), None);
+    }
+}
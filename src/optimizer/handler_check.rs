@@ -0,0 +1,355 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Correctness checks of the handlers which calculate the errors, Jacobians and contributions to H and b of factors,
+//! e.g. in the unit tests of new factor types.
+//!
+//! The estimates of the checked factors' variables are expected to be the ground truth of their measurements. At
+//! these estimates, each factor must have a zero error, its Jacobian must match central finite differences along
+//! the corrections of its variables, and its contribution to H must be symmetric and equal J^T * Ω * J. A factor
+//! graph for a factor type and a configuration of its variables can be built with
+//! [build_factor_graph](fn.build_factor_graph.html).
+//!
+//! ```
+//! use gs_rs::factor_graph::FactorGraph;
+//! use gs_rs::optimizer::handler_check::check_all_factors;
+//! use gs_rs::parser::g2o::G2oParser;
+//! use gs_rs::parser::Parser;
+//!
+//! let factor_graph: FactorGraph =
+//!     G2oParser::parse_string_to_model("VERTEX_SE2 0 0 0 0\nVERTEX_SE2 1 1 1 1.5\nEDGE_SE2 0 1 1 1 1.5 1 0 0 1 0 1")
+//!         .unwrap()
+//!         .into();
+//! assert!(check_all_factors(&factor_graph, 1e-6).is_ok());
+//! ```
+
+#![allow(non_snake_case)]
+
+use crate::debug::calculate_numerical_jacobian;
+use crate::factor_graph::factor::FactorId;
+use crate::factor_graph::variable::{FixedType, Variable, VariableId};
+use crate::factor_graph::FactorGraph;
+use crate::optimizer::linear_system::{calculate_error, calculate_factor_H_b, calculate_jacobian};
+use crate::parser::model::{Edge, FactorGraphModel, Vertex};
+use nalgebra::DMatrix;
+
+/// Returns a factor graph with a variable of the given type and content per vertex, whose ID is its position among
+/// the vertices, the given edges between them and the variables at the given positions fixed.
+///
+/// The types and contents are those of the [Vertex](../../parser/model/struct.Vertex.html) of a model.
+pub fn build_factor_graph(vertices: &[(&str, Vec<f64>)], edges: Vec<Edge>, fixed: &[usize]) -> FactorGraph {
+    FactorGraphModel {
+        vertices: vertices
+            .iter()
+            .enumerate()
+            .map(|(id, (vertex_type, content))| Vertex {
+                id: VariableId(id),
+                vertex_type: String::from(*vertex_type),
+                content: content.clone(),
+            })
+            .collect(),
+        edges,
+        fixed_vertices: fixed.iter().copied().map(VariableId).collect(),
+        ..Default::default()
+    }
+    .into()
+}
+
+/// Returns an edge of the given type between the vertices at the given positions, see
+/// [build_factor_graph](fn.build_factor_graph.html).
+pub fn build_edge(edge_type: &str, vertices: &[usize], restriction: Vec<f64>, information_matrix: Vec<f64>) -> Edge {
+    Edge {
+        edge_type: String::from(edge_type),
+        vertices: vertices.iter().copied().map(VariableId).collect(),
+        restriction,
+        information_matrix,
+    }
+}
+
+/// Checks all factors of the factor graph, see [check_factor](fn.check_factor.html).
+pub fn check_all_factors(factor_graph: &FactorGraph, tolerance: f64) -> Result<(), String> {
+    let mut ids: Vec<FactorId> = factor_graph.factor_id_map.keys().copied().collect();
+    ids.sort();
+    ids.into_iter()
+        .try_for_each(|id| check_factor(factor_graph, id, tolerance))
+}
+
+/// Checks the handler of the factor with the given ID at the current estimates and returns a description of the
+/// first failed check.
+///
+/// Values are compared with the given tolerance, which is relative for values whose magnitude exceeds 1. The
+/// estimates are restored after the finite differences.
+pub fn check_factor(factor_graph: &FactorGraph, id: FactorId, tolerance: f64) -> Result<(), String> {
+    let error = calculate_error(factor_graph, id).ok_or(format!("Factor {} is not part of the factor graph", id))?;
+    if let Some(k) = (0..error.len()).find(|k| !is_close(error[*k], 0.0, tolerance)) {
+        return Err(format!(
+            "Factor {} has the error {} in row {} at the ground truth",
+            id, error[k], k
+        ));
+    }
+
+    let jacobian = calculate_jacobian(factor_graph, id).unwrap();
+    let numeric_jacobian = calculate_numerical_jacobian(factor_graph, id);
+    if jacobian.shape() != numeric_jacobian.shape() {
+        return Err(format!(
+            "Factor {} has a Jacobian of shape {:?}, but its variables have a shape of {:?}",
+            id,
+            jacobian.shape(),
+            numeric_jacobian.shape()
+        ));
+    }
+    if let Some((row, col)) = find_mismatch(&jacobian, &numeric_jacobian, tolerance) {
+        return Err(format!(
+            "Factor {} has the derivative {} in row {} and column {}, but finite differences yield {}",
+            id,
+            jacobian[(row, col)],
+            row,
+            col,
+            numeric_jacobian[(row, col)]
+        ));
+    }
+
+    let (H, _) = calculate_factor_H_b(factor_graph, id).unwrap();
+    let H = H.to_dense();
+    if let Some((row, col)) = find_mismatch(&H, &H.transpose(), tolerance) {
+        return Err(format!(
+            "Factor {} contributes {} to H in row {} and column {}, but {} in the transposed position",
+            id,
+            H[(row, col)],
+            row,
+            col,
+            H[(col, row)]
+        ));
+    }
+    let expected_H = calc_expected_H(factor_graph, id, &jacobian);
+    if let Some((row, col)) = find_mismatch(&H, &expected_H, tolerance) {
+        return Err(format!(
+            "Factor {} contributes {} to H in row {} and column {}, but J^T * Ω * J yields {}",
+            id,
+            H[(row, col)],
+            row,
+            col,
+            expected_H[(row, col)]
+        ));
+    }
+    Ok(())
+}
+
+// assembles J^T * Ω * J into the rows and columns of the factor's non-fixed variables in H
+fn calc_expected_H(factor_graph: &FactorGraph, id: FactorId, jacobian: &DMatrix<f64>) -> DMatrix<f64> {
+    let information = &factor_graph.get_factor(id).unwrap().information_matrix.content;
+    let local_H = jacobian.transpose() * information * jacobian;
+    let mut ranges = vec![];
+    let mut offset = 0;
+    for var in get_vars(factor_graph, id) {
+        let dim = var.get_parameterization().tangent_dim();
+        if let FixedType::NonFixed(range) = var.get_fixed_type() {
+            ranges.push((offset, range.start, dim));
+        }
+        offset += dim;
+    }
+    let mut H = DMatrix::zeros(factor_graph.matrix_dim, factor_graph.matrix_dim);
+    for (local_row, row, rows) in &ranges {
+        for (local_col, col, cols) in &ranges {
+            H.slice_mut((*row, *col), (*rows, *cols))
+                .copy_from(&local_H.slice((*local_row, *local_col), (*rows, *cols)));
+        }
+    }
+    H
+}
+
+fn get_vars(factor_graph: &FactorGraph, id: FactorId) -> Vec<&Variable> {
+    factor_graph
+        .get_factor_var_indices(id)
+        .unwrap()
+        .iter()
+        .map(|i| factor_graph.get_var(*i))
+        .collect()
+}
+
+// returns the position of the first entry in which the matrices differ
+fn find_mismatch(a: &DMatrix<f64>, b: &DMatrix<f64>, tolerance: f64) -> Option<(usize, usize)> {
+    (0..a.ncols())
+        .flat_map(|col| (0..a.nrows()).map(move |row| (row, col)))
+        .find(|(row, col)| !is_close(a[(*row, *col)], b[(*row, *col)], tolerance))
+}
+
+fn is_close(a: f64, b: f64, tolerance: f64) -> bool {
+    (a - b).abs() <= tolerance * a.abs().max(b.abs()).max(1.0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::factor_graph::variable::VariableId;
+    use crate::parser::g2o::G2oParser;
+    use crate::parser::Parser;
+    use nalgebra::{Isometry3, Point3, Translation3, UnitQuaternion, Vector3};
+
+    const INFORMATION_3D: &str = "1 0 0 1 0 1";
+    const INFORMATION_6D: &str = "1 0 0 0 0 0 1 0 0 0 0 1 0 0 0 1 0 0 1 0 1";
+
+    fn parse(lines: &[String]) -> FactorGraph {
+        G2oParser::parse_string_to_model(&lines.join("\n")).unwrap().into()
+    }
+
+    fn get_content(iso: &Isometry3<f64>) -> Vec<f64> {
+        let (t, q) = (iso.translation.vector, iso.rotation.coords);
+        vec![t.x, t.y, t.z, q[0], q[1], q[2], q[3]]
+    }
+
+    fn identity(dim: usize) -> Vec<f64> {
+        DMatrix::<f64>::identity(dim, dim).as_slice().to_vec()
+    }
+
+    fn format_isometry(iso: &Isometry3<f64>) -> String {
+        let (t, q) = (iso.translation.vector, iso.rotation.coords);
+        format!("{} {} {} {} {} {} {}", t.x, t.y, t.z, q[0], q[1], q[2], q[3])
+    }
+
+    #[test]
+    fn test_builtin_handlers_2d() {
+        let (sin, cos) = 1.5_f64.sin_cos();
+        let factor_graph = parse(&[
+            String::from("VERTEX_SE2 0 0 0 0"),
+            String::from("VERTEX_SE2 1 1 1 1.5"),
+            String::from("VERTEX_XY 2 1 2"),
+            String::from("FIX 0"),
+            String::from("EDGE_SE2 0 1 1 1 1.5 1 0 0 1 0 1"),
+            format!("EDGE_SE2_XY 1 2 {} {} 1 0 1", sin, cos),
+            String::from("EDGE_PRIOR_SE2 1 1 1 1.5 1 0 0 1 0 1"),
+        ]);
+        assert_eq!(check_all_factors(&factor_graph, 1e-6), Ok(()));
+    }
+
+    #[test]
+    fn test_builtin_handlers_3d() {
+        let pose_0 = Isometry3::from_parts(
+            Translation3::new(0.3, -0.2, 0.1),
+            UnitQuaternion::from_scaled_axis(Vector3::new(0.2, -0.4, 0.6)),
+        );
+        let pose_1 = Isometry3::from_parts(
+            Translation3::new(1.0, 2.0, 3.0),
+            UnitQuaternion::from_scaled_axis(Vector3::new(-0.5, 0.1, 1.2)),
+        );
+        let landmark = Point3::new(1.0, 0.0, 2.0);
+        let local_landmark = pose_1.inverse_transform_point(&landmark);
+        let factor_graph = parse(&[
+            format!("VERTEX_SE3:QUAT 0 {}", format_isometry(&pose_0)),
+            format!("VERTEX_SE3:QUAT 1 {}", format_isometry(&pose_1)),
+            format!("VERTEX_TRACKXYZ 2 {} {} {}", landmark.x, landmark.y, landmark.z),
+            format!(
+                "EDGE_SE3:QUAT 0 1 {} {}",
+                format_isometry(&(pose_0.inverse() * pose_1)),
+                INFORMATION_6D
+            ),
+            format!(
+                "EDGE_SE3_TRACKXYZ 1 2 0 {} {} {} {}",
+                local_landmark.x, local_landmark.y, local_landmark.z, INFORMATION_3D
+            ),
+            format!("EDGE_SE3_PRIOR 1 0 {} {}", format_isometry(&pose_1), INFORMATION_6D),
+        ]);
+        assert_eq!(check_all_factors(&factor_graph, 1e-6), Ok(()));
+    }
+
+    #[test]
+    fn test_builtin_handlers_switchable() {
+        let pose_0 = Isometry3::from_parts(
+            Translation3::new(0.3, -0.2, 0.1),
+            UnitQuaternion::from_scaled_axis(Vector3::new(0.2, -0.4, 0.6)),
+        );
+        let pose_1 = Isometry3::from_parts(
+            Translation3::new(1.0, 2.0, 3.0),
+            UnitQuaternion::from_scaled_axis(Vector3::new(-0.5, 0.1, 1.2)),
+        );
+        let factor_graph = build_factor_graph(
+            &[
+                ("Vehicle2D", vec![0.0, 0.0, 0.0]),
+                ("Vehicle2D", vec![1.0, 1.0, 1.5]),
+                ("Switch", vec![0.9]),
+                ("Vehicle3D", get_content(&pose_0)),
+                ("Vehicle3D", get_content(&pose_1)),
+                ("Switch", vec![0.8]),
+            ],
+            vec![
+                build_edge("SwitchableOdometry2D", &[0, 1, 2], vec![1.0, 1.0, 1.5], identity(3)),
+                build_edge(
+                    "SwitchableOdometry3D",
+                    &[3, 4, 5],
+                    get_content(&(pose_0.inverse() * pose_1)),
+                    identity(6),
+                ),
+                build_edge("SwitchPrior", &[2], vec![0.9], identity(1)),
+            ],
+            &[0],
+        );
+        assert_eq!(check_all_factors(&factor_graph, 1e-6), Ok(()));
+    }
+
+    #[test]
+    fn test_builtin_handlers_max_mixture_and_custom() {
+        use crate::factor_graph::factor::{CustomResidual, FactorType, MaxMixture, MixtureComponent};
+        use crate::optimizer::autodiff::Dual;
+
+        let pose = vec![0.3, -0.5, 0.2];
+        let mut factor_graph =
+            build_factor_graph(&[("Vehicle2D", pose.clone()), ("Vehicle2D", pose.clone())], vec![], &[]);
+        let mixture = MaxMixture {
+            factor_type: Box::new(FactorType::Position2D),
+            weight: 0.5,
+            alternatives: vec![MixtureComponent {
+                weight: 0.5,
+                constraint: vec![1.0, 0.0, 0.0],
+                information_matrix: identity(3).into(),
+            }],
+        };
+        factor_graph
+            .add_factor(
+                VariableId(0),
+                VariableId(0),
+                FactorType::MaxMixture(mixture),
+                pose.clone(),
+                identity(3).into(),
+            )
+            .unwrap();
+        let residual = CustomResidual::new("CustomPosition2D", |contents: &[Vec<Dual>], constraint: &[f64]| {
+            (0..3).map(|k| contents[0][k] - constraint[k]).collect()
+        });
+        factor_graph
+            .add_factor(
+                VariableId(1),
+                VariableId(1),
+                FactorType::Custom(residual),
+                pose,
+                identity(3).into(),
+            )
+            .unwrap();
+        assert_eq!(check_all_factors(&factor_graph, 1e-6), Ok(()));
+    }
+
+    #[test]
+    fn test_failed_checks() {
+        let factor_graph = parse(&[
+            String::from("VERTEX_SE2 0 0 0 0"),
+            String::from("VERTEX_SE2 1 1 1 1.5"),
+            String::from("EDGE_SE2 0 1 1 1 1.4 1 0 0 1 0 1"),
+        ]);
+        let id = factor_graph.factors_between(VariableId(0), VariableId(1))[0];
+        let error = check_factor(&factor_graph, id, 1e-6).unwrap_err();
+        assert!(error.contains("in row 2 at the ground truth"), "{}", error);
+        assert!(check_factor(&factor_graph, FactorId(7), 1e-6).is_err());
+        assert_eq!(
+            factor_graph.get_var_by_id(VariableId(1)).unwrap().get_content(),
+            vec![1.0, 1.0, 1.5]
+        );
+    }
+}
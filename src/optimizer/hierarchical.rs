@@ -0,0 +1,621 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Hierarchical optimization of large pose graphs, which are partitioned into submaps, similar to HOG-Man
+//! (hierarchical optimization on manifolds).
+//!
+//! Each submap is a connected set of variables, which is anchored at one of its vehicle variables. The optimization
+//! consists of three steps:
+//! 1. Each submap is optimized on its own with a fixed anchor, which determines its variables relative to the
+//!    anchor.
+//! 2. A condensed factor graph of the anchors is optimized. Its factors are the odometry factors between submaps and
+//!    the Position2D and Position3D factors, whose measurements are composed with the relative estimates of the
+//!    first step. They keep the information matrices of the original factors, which neglects that the errors are
+//!    now seen from the anchors. Measurements between the same anchors are fused into one.
+//! 3. Each submap is moved rigidly along with its anchor, and the whole factor graph is refined with a few
+//!    iterations, which also take the other factors between submaps into account, e.g. observations of landmarks in
+//!    other submaps.
+//!
+//! Only the refinement solves the linear system of the whole factor graph, so that its iterations can be traded for
+//! the much smaller linear systems of the submaps and of the condensed factor graph.
+
+use crate::factor_graph::adjacency::NodeIndex;
+use crate::factor_graph::factor::FactorType;
+use crate::factor_graph::merge::transform_content;
+use crate::factor_graph::topology::UnionFind;
+use crate::factor_graph::variable::{FixedType, Variable, VariableId};
+use crate::factor_graph::FactorGraph;
+use crate::optimizer::linear_system::iso3d_gradients::get_isometry;
+use crate::optimizer::optimize;
+use crate::parser::model::{Edge, FactorGraphModel, Vertex};
+use nalgebra::{DMatrix, DVector, Isometry3, Vector3};
+use std::collections::{BTreeMap, BTreeSet, VecDeque};
+
+/// Configuration of [optimize_hierarchical](fn.optimize_hierarchical.html).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HierarchicalConfig {
+    /// The number of vehicle variables up to which a submap is grown from its first vehicle variable, see
+    /// [partition_into_submaps](fn.partition_into_submaps.html).
+    pub submap_size: usize,
+    /// The number of iterations of each submap's optimization.
+    pub local_iterations: usize,
+    /// The number of iterations of the condensed factor graph's optimization.
+    pub condensed_iterations: usize,
+    /// The number of iterations of the whole factor graph's optimization at the end.
+    pub refinement_iterations: usize,
+}
+
+impl Default for HierarchicalConfig {
+    fn default() -> Self {
+        HierarchicalConfig {
+            submap_size: 100,
+            local_iterations: 5,
+            condensed_iterations: 10,
+            refinement_iterations: 2,
+        }
+    }
+}
+
+/// A connected set of variables, which is optimized on its own.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Submap {
+    /// The vehicle variable relative to which the submap is optimized, unless the submap contains no vehicle
+    /// variable.
+    pub anchor: Option<VariableId>,
+    /// The variables of the submap in the order in which they were added.
+    pub variables: Vec<VariableId>,
+}
+
+// a pose and its information matrix
+type Measurement = (Isometry3<f64>, DMatrix<f64>);
+
+// the internal indices of a submap's anchor and variables
+struct SubmapIndices {
+    anchor: Option<usize>,
+    variables: Vec<usize>,
+}
+
+/// Partitions the factor graph into submaps, which are grown breadth-first along the factors between vehicle
+/// variables from the first vehicle variable which is not part of a submap yet, until they contain the given number
+/// of vehicle variables or no further vehicle variable can be reached.
+///
+/// The anchor of a submap is its first fixed vehicle variable, or its first vehicle variable if none is fixed. The
+/// other variables, e.g. landmarks, join the submap of their neighbor with the lowest internal index among those
+/// which belong to submaps, possibly via other such variables. Connected components without vehicle variables form
+/// submaps without anchors.
+///
+/// Returns an error if the submap size is zero.
+pub fn partition_into_submaps(graph: &FactorGraph, submap_size: usize) -> Result<Vec<Submap>, String> {
+    Ok(get_submaps(graph, submap_size)?
+        .iter()
+        .map(|submap| Submap {
+            anchor: submap.anchor.map(|i| graph.get_var(NodeIndex::new(i)).get_id()),
+            variables: submap
+                .variables
+                .iter()
+                .map(|i| graph.get_var(NodeIndex::new(*i)).get_id())
+                .collect(),
+        })
+        .collect())
+}
+
+/// Optimizes a factor graph hierarchically with the given configuration, see the [module](index.html)
+/// documentation, and returns its submaps.
+///
+/// Submaps which contain a fixed variable keep their anchor fixed in the condensed factor graph. In each connected
+/// component of the condensed factor graph which has neither such an anchor nor a Position2D or Position3D factor,
+/// the first anchor is fixed.
+///
+/// Returns an error if the submap size is zero.
+pub fn optimize_hierarchical(graph: &FactorGraph, config: &HierarchicalConfig) -> Result<Vec<Submap>, String> {
+    let submaps = get_submaps(graph, config.submap_size)?;
+    let var_count = graph.node_indices.len();
+    let mut submap_of = vec![0; var_count];
+    for (s, submap) in submaps.iter().enumerate() {
+        submap.variables.iter().for_each(|i| submap_of[*i] = s);
+    }
+    let is_fixed = |i: usize| graph.get_var(NodeIndex::new(i)).get_fixed_type() == &FixedType::Fixed;
+    let is_fixed_submap: Vec<bool> = submaps
+        .iter()
+        .map(|s| s.variables.iter().any(|i| is_fixed(*i)))
+        .collect();
+
+    // variables without factors within their submap are kept fixed in its optimization
+    let mut is_constrained = vec![false; var_count];
+    for id in graph.factor_id_map.keys() {
+        let indices: Vec<usize> = graph
+            .get_factor_var_indices(*id)
+            .unwrap()
+            .iter()
+            .map(|i| i.index())
+            .collect();
+        if indices.iter().all(|i| submap_of[*i] == submap_of[indices[0]]) {
+            indices.iter().for_each(|i| is_constrained[*i] = true);
+        }
+    }
+    let mut is_member = vec![false; var_count];
+    for (s, submap) in submaps.iter().enumerate() {
+        if submap.anchor.is_none() && !is_fixed_submap[s] {
+            continue;
+        }
+        submap.variables.iter().for_each(|i| is_member[*i] = true);
+        let subgraph = graph.get_subgraph(&is_member, |i| Some(i) == submap.anchor || !is_constrained[i])?;
+        submap.variables.iter().for_each(|i| is_member[*i] = false);
+        if subgraph.matrix_dim == 0 {
+            continue;
+        }
+        optimize(&subgraph, config.local_iterations);
+        for var in submap.variables.iter().map(|i| graph.get_var(NodeIndex::new(*i))) {
+            if var.get_fixed_type() != &FixedType::Fixed {
+                var.set_content(subgraph.get_var_by_id(var.get_id()).unwrap().get_content());
+            }
+        }
+    }
+
+    let poses: Vec<Option<Isometry3<f64>>> = (0..var_count)
+        .map(|i| get_pose(graph.get_var(NodeIndex::new(i))))
+        .collect();
+    let condensed = get_condensed_graph(graph, &submaps, &submap_of, &is_fixed_submap, &poses);
+    if condensed.matrix_dim > 0 {
+        optimize(&condensed, config.condensed_iterations);
+    }
+    for (s, submap) in submaps.iter().enumerate() {
+        let anchor = match submap.anchor {
+            Some(anchor) if !is_fixed_submap[s] => anchor,
+            _ => continue,
+        };
+        let condensed_anchor = condensed
+            .get_var_by_id(graph.get_var(NodeIndex::new(anchor)).get_id())
+            .unwrap();
+        let transform = get_pose(condensed_anchor).unwrap() * poses[anchor].unwrap().inverse();
+        for var in submap.variables.iter().map(|i| graph.get_var(NodeIndex::new(*i))) {
+            if var.get_fixed_type() != &FixedType::Fixed {
+                var.set_content(transform_content(&transform, &get_vertex_type(var), &var.get_content()));
+            }
+        }
+    }
+    optimize(graph, config.refinement_iterations);
+
+    Ok(submaps
+        .iter()
+        .map(|submap| Submap {
+            anchor: submap.anchor.map(|i| graph.get_var(NodeIndex::new(i)).get_id()),
+            variables: submap
+                .variables
+                .iter()
+                .map(|i| graph.get_var(NodeIndex::new(*i)).get_id())
+                .collect(),
+        })
+        .collect())
+}
+
+fn get_submaps(graph: &FactorGraph, submap_size: usize) -> Result<Vec<SubmapIndices>, String> {
+    if submap_size == 0 {
+        return Err(String::from("The submap size must be positive"));
+    }
+    let var_count = graph.node_indices.len();
+    let mut neighbors: Vec<BTreeSet<usize>> = vec![BTreeSet::new(); var_count];
+    graph.factor_id_map.keys().for_each(|id| {
+        let indices = graph.get_factor_var_indices(*id).unwrap();
+        for a in &indices {
+            neighbors[a.index()].extend(indices.iter().filter(|b| *b != a).map(|b| b.index()));
+        }
+    });
+
+    let mut submap_of: Vec<Option<usize>> = vec![None; var_count];
+    let mut submaps: Vec<Vec<usize>> = vec![];
+    for start in 0..var_count {
+        if submap_of[start].is_some() || !is_vehicle(graph.get_var(NodeIndex::new(start))) {
+            continue;
+        }
+        let mut submap = vec![];
+        let mut is_queued = BTreeSet::new();
+        let mut queue = VecDeque::new();
+        is_queued.insert(start);
+        queue.push_back(start);
+        while let Some(i) = queue.pop_front() {
+            if submap.len() == submap_size {
+                break;
+            }
+            submap_of[i] = Some(submaps.len());
+            submap.push(i);
+            for neighbor in neighbors[i]
+                .iter()
+                .filter(|j| is_vehicle(graph.get_var(NodeIndex::new(**j))))
+            {
+                if submap_of[*neighbor].is_none() && is_queued.insert(*neighbor) {
+                    queue.push_back(*neighbor);
+                }
+            }
+        }
+        submaps.push(submap);
+    }
+    let mut is_changed = true;
+    while is_changed {
+        is_changed = false;
+        for i in 0..var_count {
+            if submap_of[i].is_some() {
+                continue;
+            }
+            if let Some(s) = neighbors[i].iter().find_map(|neighbor| submap_of[*neighbor]) {
+                submap_of[i] = Some(s);
+                submaps[s].push(i);
+                is_changed = true;
+            }
+        }
+    }
+    for start in 0..var_count {
+        if submap_of[start].is_some() {
+            continue;
+        }
+        let mut submap = vec![];
+        let mut stack = vec![start];
+        submap_of[start] = Some(submaps.len());
+        while let Some(i) = stack.pop() {
+            submap.push(i);
+            for neighbor in &neighbors[i] {
+                if submap_of[*neighbor].is_none() {
+                    submap_of[*neighbor] = Some(submaps.len());
+                    stack.push(*neighbor);
+                }
+            }
+        }
+        submaps.push(submap);
+    }
+
+    Ok(submaps
+        .into_iter()
+        .map(|variables| {
+            let vehicles = || {
+                variables
+                    .iter()
+                    .copied()
+                    .filter(|i| is_vehicle(graph.get_var(NodeIndex::new(*i))))
+            };
+            let anchor = vehicles()
+                .find(|i| graph.get_var(NodeIndex::new(*i)).get_fixed_type() == &FixedType::Fixed)
+                .or_else(|| vehicles().next());
+            SubmapIndices { anchor, variables }
+        })
+        .collect())
+}
+
+// returns the factor graph of the anchors, see the module documentation
+fn get_condensed_graph(
+    graph: &FactorGraph,
+    submaps: &[SubmapIndices],
+    submap_of: &[usize],
+    is_fixed_submap: &[bool],
+    poses: &[Option<Isometry3<f64>>],
+) -> FactorGraph {
+    let mut model = FactorGraphModel::default();
+    let anchor_ids: Vec<Option<VariableId>> = submaps
+        .iter()
+        .map(|submap| submap.anchor.map(|i| graph.get_var(NodeIndex::new(i)).get_id()))
+        .collect();
+    for (s, submap) in submaps.iter().enumerate() {
+        if let Some(anchor) = submap.anchor {
+            let var = graph.get_var(NodeIndex::new(anchor));
+            model.vertices.push(Vertex {
+                id: var.get_id(),
+                vertex_type: get_vertex_type(var),
+                content: var.get_content(),
+            });
+            if is_fixed_submap[s] {
+                model.fixed_vertices.insert(var.get_id());
+            }
+        }
+    }
+    // the pose of the variable relative to its submap's anchor if both are vehicles of the given dimension
+    let get_relative_pose = |i: usize, is_3d: bool| {
+        let anchor = submaps[submap_of[i]].anchor?;
+        if [anchor, i]
+            .iter()
+            .all(|j| matches!(graph.get_var(NodeIndex::new(*j)), Variable::Vehicle3D(_)) == is_3d)
+        {
+            Some(poses[anchor]?.inverse() * poses[i]?)
+        } else {
+            None
+        }
+    };
+
+    let mut components = UnionFind::new(submaps.len());
+    let mut is_constrained = is_fixed_submap.to_vec();
+    let mut measurements: BTreeMap<(&str, Vec<VariableId>), Vec<Measurement>> = BTreeMap::new();
+    for (id, (source, target)) in &graph.factor_id_map {
+        let (source, target) = (source.index(), target.index());
+        let factor = graph.materialize_factor(graph.get_factor(*id).unwrap());
+        let is_3d = match factor.factor_type {
+            FactorType::Odometry2D | FactorType::Position2D => false,
+            FactorType::Odometry3D | FactorType::Position3D => true,
+            _ => continue,
+        };
+        let (s, t) = (submap_of[source], submap_of[target]);
+        let measurement = get_measurement(&factor.constraint, is_3d);
+        let (restriction, vertices) = match factor.factor_type {
+            FactorType::Odometry2D | FactorType::Odometry3D if s != t => {
+                match (get_relative_pose(source, is_3d), get_relative_pose(target, is_3d)) {
+                    (Some(source_pose), Some(target_pose)) => {
+                        components.union(s, t);
+                        (
+                            source_pose * measurement * target_pose.inverse(),
+                            vec![anchor_ids[s].unwrap(), anchor_ids[t].unwrap()],
+                        )
+                    }
+                    _ => continue,
+                }
+            }
+            FactorType::Position2D | FactorType::Position3D if !is_fixed_submap[s] => {
+                match get_relative_pose(source, is_3d) {
+                    Some(pose) => {
+                        is_constrained[s] = true;
+                        (measurement * pose.inverse(), vec![anchor_ids[s].unwrap()])
+                    }
+                    None => continue,
+                }
+            }
+            _ => continue,
+        };
+        let edge_type = match factor.factor_type {
+            FactorType::Odometry2D => "Odometry2D",
+            FactorType::Odometry3D => "Odometry3D",
+            FactorType::Position2D => "Position2D",
+            _ => "Position3D",
+        };
+        measurements
+            .entry((edge_type, vertices))
+            .or_default()
+            .push((restriction, factor.information_matrix.content.clone()));
+    }
+    for ((edge_type, vertices), measurements) in measurements {
+        let is_3d = edge_type.ends_with("3D");
+        let (restriction, information_matrix) = fuse_measurements(&measurements, is_3d);
+        model.edges.push(Edge {
+            edge_type: String::from(edge_type),
+            vertices,
+            restriction: get_constraint(&restriction, is_3d),
+            information_matrix: information_matrix.as_slice().to_owned(),
+        });
+    }
+
+    // components without fixed anchors or priors are anchored at their first anchor
+    for s in 0..submaps.len() {
+        let root = components.find(s);
+        if is_constrained[s] {
+            is_constrained[root] = true;
+        }
+    }
+    for (s, anchor_id) in anchor_ids.iter().enumerate() {
+        let root = components.find(s);
+        if let (Some(anchor_id), false) = (anchor_id, is_constrained[root]) {
+            model.fixed_vertices.insert(*anchor_id);
+            is_constrained[root] = true;
+        }
+    }
+    model.into()
+}
+
+// returns a single measurement of the same poses as the given ones in case an anchor has more than one, whose
+// information matrix is their sum and whose deviation from the first one is the information-weighted mean of theirs
+fn fuse_measurements(measurements: &[Measurement], is_3d: bool) -> Measurement {
+    let (first, _) = measurements[0];
+    let dim = if is_3d { 6 } else { 3 };
+    let mut information_sum = DMatrix::zeros(dim, dim);
+    let mut weighted_sum = DVector::zeros(dim);
+    for (measurement, information_matrix) in measurements {
+        let deviation = first.inverse() * measurement;
+        let translation = deviation.translation.vector;
+        let rotation = deviation.rotation.scaled_axis();
+        let tangent = if is_3d {
+            DVector::from_column_slice(&[
+                translation.x,
+                translation.y,
+                translation.z,
+                rotation.x,
+                rotation.y,
+                rotation.z,
+            ])
+        } else {
+            DVector::from_column_slice(&[translation.x, translation.y, rotation.z])
+        };
+        information_sum += information_matrix;
+        weighted_sum += information_matrix * tangent;
+    }
+    let tangent = match information_sum.clone().cholesky() {
+        Some(cholesky) => cholesky.solve(&weighted_sum),
+        None => return measurements[0].clone(),
+    };
+    let deviation = if is_3d {
+        Isometry3::new(
+            Vector3::new(tangent[0], tangent[1], tangent[2]),
+            Vector3::new(tangent[3], tangent[4], tangent[5]),
+        )
+    } else {
+        Isometry3::new(
+            Vector3::new(tangent[0], tangent[1], 0.0),
+            Vector3::new(0.0, 0.0, tangent[2]),
+        )
+    };
+    (first * deviation, information_sum)
+}
+
+fn is_vehicle(var: &Variable) -> bool {
+    matches!(var, Variable::Vehicle2D(_) | Variable::Vehicle3D(_))
+}
+
+fn get_vertex_type(var: &Variable) -> String {
+    String::from(match var {
+        Variable::Vehicle2D(_) => "Vehicle2D",
+        Variable::Landmark2D(_) => "Landmark2D",
+        Variable::Vehicle3D(_) => "Vehicle3D",
+        Variable::Landmark3D(_) => "Landmark3D",
+        Variable::Switch(_) => "Switch",
+        Variable::Velocity3D(_) => "Velocity3D",
+        Variable::ImuBias(_) => "ImuBias",
+        Variable::VehicleSim3(_) => "VehicleSim3",
+        Variable::Plane3D(_) => "Plane3D",
+        Variable::LineLandmark2D(_) => "LineLandmark2D",
+        Variable::OdometryCalibration2D(_) => "OdometryCalibration2D",
+        Variable::Extrinsic2D(_) => "Extrinsic2D",
+        Variable::Extrinsic3D(_) => "Extrinsic3D",
+        Variable::Anchor2D(_) => "Anchor2D",
+        Variable::Anchor3D(_) => "Anchor3D",
+        Variable::UserDefined(v) => return String::from(v.state.borrow().name()),
+    })
+}
+
+// returns the pose of a vehicle variable, where 2D poses are rotated around the z axis
+fn get_pose(var: &Variable) -> Option<Isometry3<f64>> {
+    match var {
+        Variable::Vehicle2D(_) => Some(get_measurement(&var.get_content(), false)),
+        Variable::Vehicle3D(_) => Some(get_measurement(&var.get_content(), true)),
+        _ => None,
+    }
+}
+
+// returns the pose of an odometry or position measurement
+fn get_measurement(constraint: &[f64], is_3d: bool) -> Isometry3<f64> {
+    if is_3d {
+        get_isometry(constraint)
+    } else {
+        Isometry3::new(
+            Vector3::new(constraint[0], constraint[1], 0.0),
+            Vector3::new(0.0, 0.0, constraint[2]),
+        )
+    }
+}
+
+// returns the constraint of an odometry or position factor with the given measurement
+fn get_constraint(pose: &Isometry3<f64>, is_3d: bool) -> Vec<f64> {
+    let translation = pose.translation.vector;
+    if is_3d {
+        let mut constraint = translation.data.as_slice().to_vec();
+        constraint.extend_from_slice(pose.rotation.quaternion().coords.data.as_slice());
+        constraint
+    } else {
+        vec![translation.x, translation.y, pose.rotation.euler_angles().2]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimizer::total_chi2;
+    use crate::parser::g2o::G2oParser;
+    use crate::parser::Parser;
+    use std::f64::consts::PI;
+
+    const POSE_COUNT: usize = 30;
+
+    // returns poses along a circle, connected by odometry factors and a loop closure, and a landmark at the center
+    // which is observed by the first and the middle pose, with consistent measurements and drifting estimates
+    fn get_circle() -> FactorGraph {
+        let turn = 2.0 * PI / POSE_COUNT as f64;
+        let step = 2.0 * (turn / 2.0).sin();
+        let mut lines = vec![String::from("FIX 0")];
+        for i in 0..POSE_COUNT {
+            let drift = 1.0 + 0.01 * i as f64;
+            let angle = i as f64 * turn * drift;
+            let heading = angle + PI / 2.0 + turn / 2.0;
+            lines.push(format!("VERTEX_SE2 {} {} {} {}", i, angle.cos(), angle.sin(), heading));
+            lines.push(format!(
+                "EDGE_SE2 {} {} {} 0 {} 1 0 0 1 0 1",
+                i,
+                (i + 1) % POSE_COUNT,
+                step,
+                turn
+            ));
+        }
+        lines[1] = format!("VERTEX_SE2 0 1 0 {}", PI / 2.0 + turn / 2.0);
+        lines.push(format!("VERTEX_XY {} 0.1 0.1", POSE_COUNT));
+        for i in &[0, POSE_COUNT / 2] {
+            let angle = *i as f64 * turn;
+            let heading = angle + PI / 2.0 + turn / 2.0;
+            let (x, y) = (-angle.cos(), -angle.sin());
+            let (sin, cos) = heading.sin_cos();
+            lines.push(format!(
+                "EDGE_SE2_XY {} {} {} {} 1 0 1",
+                i,
+                POSE_COUNT,
+                cos * x + sin * y,
+                cos * y - sin * x
+            ));
+        }
+        G2oParser::parse_string_to_model(&lines.join("\n")).unwrap().into()
+    }
+
+    #[test]
+    fn test_partition_into_submaps() {
+        let factor_graph = get_circle();
+        let submaps = partition_into_submaps(&factor_graph, 8).unwrap();
+        let sizes: Vec<usize> = submaps.iter().map(|submap| submap.variables.len()).collect();
+        assert_eq!(sizes, vec![9, 8, 8, 6]);
+        let anchors: Vec<Option<VariableId>> = submaps.iter().map(|submap| submap.anchor).collect();
+        assert_eq!(
+            anchors,
+            [0, 5, 13, 21]
+                .iter()
+                .map(|id| Some(VariableId(*id)))
+                .collect::<Vec<_>>()
+        );
+        let mut ids: Vec<VariableId> = submaps.iter().flat_map(|submap| submap.variables.clone()).collect();
+        ids.sort();
+        assert_eq!(ids, (0..=POSE_COUNT).map(VariableId).collect::<Vec<_>>());
+        assert!(partition_into_submaps(&factor_graph, 0).is_err());
+    }
+
+    #[test]
+    fn test_optimize_hierarchical() {
+        let factor_graph = get_circle();
+        assert!(total_chi2(&factor_graph) > 1.0);
+        let config = HierarchicalConfig {
+            submap_size: 8,
+            refinement_iterations: 0,
+            ..Default::default()
+        };
+        let submaps = optimize_hierarchical(&factor_graph, &config).unwrap();
+        assert_eq!(submaps.len(), 4);
+        assert!(total_chi2(&factor_graph) < 1e-9);
+        let landmark = factor_graph
+            .get_var_by_id(VariableId(POSE_COUNT))
+            .unwrap()
+            .get_content();
+        assert!(landmark[0].abs() < 1e-6 && landmark[1].abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_optimize_hierarchical_3d() {
+        let factor_graph = G2oParser::parse_file("data_files/optimizer_tests/obs3d_mainly_0.g2o").unwrap();
+        assert!(total_chi2(&factor_graph) > 1.0);
+        let config = HierarchicalConfig {
+            submap_size: 10,
+            refinement_iterations: 0,
+            ..Default::default()
+        };
+        optimize_hierarchical(&factor_graph, &config).unwrap();
+        assert!(total_chi2(&factor_graph) < 1e-9);
+    }
+
+    #[test]
+    fn test_fewer_iterations_of_the_whole_graph() {
+        let file_name = "data_files/optimizer_tests/odo2d_only_0.g2o";
+        let expected = G2oParser::parse_file(file_name).unwrap();
+        optimize(&expected, 2);
+        let factor_graph = G2oParser::parse_file(file_name).unwrap();
+        let config = HierarchicalConfig {
+            submap_size: 10,
+            ..Default::default()
+        };
+        optimize_hierarchical(&factor_graph, &config).unwrap();
+        assert!(total_chi2(&factor_graph) < 0.01 * total_chi2(&expected));
+    }
+}
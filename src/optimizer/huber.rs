@@ -0,0 +1,159 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Huber kernel, applied by iteratively reweighting the factors.
+//!
+//! The residual of a factor is r = √χ². The Huber kernel keeps the squared error of factors with r ≤ δ and only
+//! grows linearly in r above, which corresponds to scaling the information matrix by min(1, δ / r) each time the
+//! linear system is assembled. Instead of configuring δ by hand, it can be estimated from the residuals at the
+//! current estimates, see [estimate](struct.HuberKernel.html#method.estimate).
+
+use crate::factor_graph::factor::FactorType;
+use crate::factor_graph::FactorGraph;
+use crate::optimizer::linear_system::{calculate_chi2, get_dominant_component};
+use crate::optimizer::FactorReweighting;
+
+/// Factor converting the median absolute deviation into the standard deviation of normally distributed values.
+const MAD_TO_STD_DEV: f64 = 1.4826;
+
+/// The δ in units of the residuals' standard deviation, which keeps 95% of the efficiency of least squares for
+/// normally distributed residuals.
+const HUBER_CONSTANT: f64 = 1.345;
+
+/// Configuration of the Huber kernel, i.e. the width δ per factor type.
+///
+/// Factors of types without a configured δ are not reweighted.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct HuberKernel {
+    deltas: Vec<(FactorType, f64)>,
+}
+
+impl HuberKernel {
+    /// Returns a configuration which does not reweight any factor.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns a configuration with δ estimated from the residuals of each factor type at the current estimates.
+    ///
+    /// The scale of the residuals is estimated robustly by their median absolute deviation from zero, and δ is
+    /// set to 1.345 times the corresponding standard deviation. Factor types whose residuals are mostly zero, e.g.
+    /// because they are fitted exactly, are not reweighted. Max-mixture factors count for the type of their
+    /// dominant component.
+    pub fn estimate(factor_graph: &FactorGraph) -> Self {
+        let mut residuals: Vec<(FactorType, Vec<f64>)> = vec![];
+        for id in factor_graph.factor_id_map.keys() {
+            let factor = get_dominant_component(factor_graph, factor_graph.get_factor(*id).unwrap());
+            let residual = calculate_chi2(factor_graph, *id).unwrap().sqrt();
+            match residuals.iter_mut().find(|(t, _)| *t == factor.factor_type) {
+                Some((_, values)) => values.push(residual),
+                None => residuals.push((factor.factor_type.clone(), vec![residual])),
+            }
+        }
+        residuals
+            .into_iter()
+            .fold(Self::new(), |kernel, (factor_type, mut values)| {
+                let scale = MAD_TO_STD_DEV * median(&mut values);
+                if scale > 0.0 {
+                    kernel.with_delta(factor_type, HUBER_CONSTANT * scale)
+                } else {
+                    kernel
+                }
+            })
+    }
+
+    /// Returns the configuration with the given δ for factors of the given type, replacing a previous value.
+    pub fn with_delta(mut self, factor_type: FactorType, delta: f64) -> Self {
+        self.deltas.retain(|(t, _)| *t != factor_type);
+        self.deltas.push((factor_type, delta));
+        self
+    }
+
+    /// Returns the δ configured for the given factor type, if any.
+    pub fn get_delta(&self, factor_type: &FactorType) -> Option<f64> {
+        self.deltas
+            .iter()
+            .find(|(t, _)| t == factor_type)
+            .map(|(_, delta)| *delta)
+    }
+}
+
+impl FactorReweighting for HuberKernel {
+    fn is_reweighted(&self, factor_type: &FactorType) -> bool {
+        self.get_delta(factor_type).is_some()
+    }
+
+    /// Returns the factor min(1, δ / √χ²) by which the information matrix of a factor with the given type and
+    /// squared error is scaled.
+    fn get_weight(&self, factor_type: &FactorType, chi2: f64) -> f64 {
+        match self.get_delta(factor_type) {
+            Some(delta) if chi2 > delta * delta => delta / chi2.sqrt(),
+            _ => 1.0,
+        }
+    }
+}
+
+// returns the median of the given values, which must not be empty
+fn median(values: &mut [f64]) -> f64 {
+    values.sort_by(|a, b| a.partial_cmp(b).unwrap());
+    let mid = values.len() / 2;
+    if values.len().is_multiple_of(2) {
+        0.5 * (values[mid - 1] + values[mid])
+    } else {
+        values[mid]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::g2o::G2oParser;
+    use crate::parser::Parser;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn test_get_weight() {
+        let kernel = HuberKernel::new()
+            .with_delta(FactorType::Odometry2D, 1.0)
+            .with_delta(FactorType::Odometry2D, 2.0);
+        assert_eq!(kernel.get_delta(&FactorType::Odometry2D), Some(2.0));
+        assert_relative_eq!(kernel.get_weight(&FactorType::Odometry2D, 3.0), 1.0);
+        assert_relative_eq!(kernel.get_weight(&FactorType::Odometry2D, 16.0), 0.5);
+        assert_relative_eq!(kernel.get_weight(&FactorType::Position2D, 16.0), 1.0);
+    }
+
+    #[test]
+    fn test_estimate() {
+        // the residuals of the odometry factors are 1, 2 and 4, the prior is fitted exactly
+        let factor_graph: FactorGraph = G2oParser::parse_string_to_model(
+            &[
+                "VERTEX_SE2 0 0 0 0",
+                "VERTEX_SE2 1 2 0 0",
+                "VERTEX_SE2 2 5 0 0",
+                "VERTEX_SE2 3 6 0 0",
+                "EDGE_PRIOR_SE2 0 0 0 0 1 0 0 1 0 1",
+                "EDGE_SE2 0 1 1 0 0 1 0 0 1 0 1",
+                "EDGE_SE2 1 2 1 0 0 1 0 0 1 0 1",
+                "EDGE_SE2 2 3 5 0 0 1 0 0 1 0 1",
+            ]
+            .join("\n"),
+        )
+        .unwrap()
+        .into();
+        let kernel = HuberKernel::estimate(&factor_graph);
+        assert_relative_eq!(
+            kernel.get_delta(&FactorType::Odometry2D).unwrap(),
+            HUBER_CONSTANT * MAD_TO_STD_DEV * 2.0
+        );
+        assert_eq!(kernel.get_delta(&FactorType::Position2D), None);
+    }
+}
@@ -0,0 +1,103 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Altitude priors on vehicle poses in 3D, e.g. from a barometer or an altimeter.
+//!
+//! The constraint is the measured z coordinate of the vehicle in the world frame, its information matrix the inverse
+//! of the altitude's 1x1 variance. Neither the vehicle's horizontal position nor its orientation influence the error.
+
+#![allow(non_snake_case)]
+
+use crate::factor_graph::factor::Factor;
+use crate::factor_graph::variable::Variable;
+use crate::optimizer::block_sparse::BlockSparseMatrix;
+use crate::optimizer::linear_system::add_to_H_b;
+use crate::optimizer::linear_system::iso3d_gradients::get_isometry;
+use nalgebra::{DMatrix, DVector};
+
+pub fn update_H_b(H: &mut BlockSparseMatrix, b: &mut DVector<f64>, factor: &Factor, vars: &[&Variable]) {
+    let jacobi = calc_jacobian(vars);
+    let err = calc_error(factor, vars);
+    add_to_H_b(H, b, &factor.information_matrix.content, &jacobi, &err, vars);
+}
+
+pub fn calc_error(factor: &Factor, vars: &[&Variable]) -> Vec<f64> {
+    vec![vars[0].get_content()[2] - factor.constraint[0]]
+}
+
+/// Calculates the Jacobian with respect to the vehicle.
+///
+/// Since the correction of 3D poses is applied in the vehicle's frame, the translational part is the last row of the
+/// vehicle's rotation matrix, while rotational corrections do not move the vehicle's position.
+pub fn calc_jacobian(vars: &[&Variable]) -> DMatrix<f64> {
+    let rotation = get_isometry(&vars[0].get_content()).rotation.to_rotation_matrix();
+    let mut jacobian = DMatrix::zeros(1, 6);
+    jacobian.columns_mut(0, 3).copy_from(&rotation.matrix().row(2));
+    jacobian
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::factor_graph::factor::FactorId;
+    use crate::factor_graph::variable::VariableId;
+    use crate::optimizer::handler_check::{build_factor_graph, check_factor};
+    use crate::optimizer::{optimize, total_chi2};
+    use crate::parser::model::Edge;
+    use approx::assert_relative_eq;
+
+    fn get_altitude_edge(vertex: usize, altitude: f64) -> Edge {
+        Edge {
+            edge_type: String::from("Altitude3D"),
+            vertices: vec![VariableId(vertex)],
+            restriction: vec![altitude],
+            information_matrix: vec![1.0],
+        }
+    }
+
+    #[test]
+    fn test_handler() {
+        let factor_graph = build_factor_graph(
+            &[("Vehicle3D", vec![0.3, -0.2, 0.1, 0.1, -0.2, 0.3, 0.927362])],
+            vec![get_altitude_edge(0, 0.1)],
+            &[],
+        );
+        assert_eq!(check_factor(&factor_graph, FactorId(0), 1e-6), Ok(()));
+    }
+
+    #[test]
+    fn test_barometer_completes_planar_odometry() {
+        // the odometry does not measure vertical motion, so only the barometer determines the second pose's altitude
+        let mut information = vec![0.0; 36];
+        [0, 1, 3, 4, 5].iter().for_each(|k| information[k * 7] = 1.0);
+        let odometry = Edge {
+            edge_type: String::from("Odometry3D"),
+            vertices: vec![VariableId(0), VariableId(1)],
+            restriction: vec![1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0],
+            information_matrix: information,
+        };
+        let factor_graph = build_factor_graph(
+            &[
+                ("Vehicle3D", vec![0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0]),
+                ("Vehicle3D", vec![1.2, 0.1, 0.0, 0.0, 0.0, 0.1, 0.994987]),
+            ],
+            vec![odometry, get_altitude_edge(1, 0.4)],
+            &[0],
+        );
+        assert!(total_chi2(&factor_graph) > 1e-2);
+        optimize(&factor_graph, 20);
+        assert!(total_chi2(&factor_graph) < 1e-12);
+        let pose = factor_graph.get_var_by_id(VariableId(1)).unwrap().get_content();
+        for (actual, expected) in pose[..3].iter().zip(&[1.0, 0.0, 0.4]) {
+            assert_relative_eq!(actual, expected, epsilon = 1e-6);
+        }
+    }
+}
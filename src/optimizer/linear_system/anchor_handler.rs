@@ -0,0 +1,315 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Odometry and observation factors between variables which are estimated in the frames of their mapping sessions,
+//! whose poses in the world frame are estimated by anchor variables.
+//!
+//! Both variables are transformed into the world frame by the anchors of their sessions, so that the errors are the
+//! ones of the corresponding odometry and observation factors in the world frame. The Jacobians are calculated with
+//! automatic differentiation.
+
+#![allow(non_snake_case)]
+
+use crate::factor_graph::factor::{CustomResidual, Factor, FactorType};
+use crate::factor_graph::variable::Variable;
+use crate::optimizer::autodiff::{quaternion_conjugate, quaternion_product, rotate_inverse, Dual, DualQuaternion};
+use crate::optimizer::block_sparse::BlockSparseMatrix;
+use crate::optimizer::linear_system::custom_handler;
+use nalgebra::{DMatrix, DVector, Quaternion, UnitQuaternion};
+use std::f64::consts::PI;
+
+pub fn update_H_b(H: &mut BlockSparseMatrix, b: &mut DVector<f64>, factor: &Factor, vars: &[&Variable]) {
+    custom_handler::update_H_b(H, b, factor, &get_residual(factor), vars);
+}
+
+pub fn calc_error(factor: &Factor, vars: &[&Variable]) -> Vec<f64> {
+    custom_handler::calc_error(factor, &get_residual(factor), vars)
+}
+
+pub fn calc_jacobian(factor: &Factor, vars: &[&Variable]) -> DMatrix<f64> {
+    custom_handler::calc_jacobian(factor, &get_residual(factor), vars)
+}
+
+fn get_residual(factor: &Factor) -> CustomResidual {
+    match factor.factor_type {
+        FactorType::AnchoredOdometry2D => CustomResidual::new("AnchoredOdometry2D", calc_odometry_residual_2d),
+        FactorType::AnchoredOdometry3D => CustomResidual::new("AnchoredOdometry3D", calc_odometry_residual_3d),
+        FactorType::AnchoredObservation2D => CustomResidual::new("AnchoredObservation2D", calc_observation_residual_2d),
+        _ => CustomResidual::new("AnchoredObservation3D", calc_observation_residual_3d),
+    }
+}
+
+// expects the contents of the source pose, the target pose and the anchors of their sessions
+fn calc_odometry_residual_2d(contents: &[Vec<Dual>], constraint: &[f64]) -> Vec<Dual> {
+    let from = to_world_2d(&contents[2], &contents[0]);
+    let to = to_world_2d(&contents[3], &contents[1]);
+    let (sin, cos) = (from[2].sin(), from[2].cos());
+    let (dx, dy) = (to[0] - from[0], to[1] - from[1]);
+    let (dx, dy) = (cos * dx + sin * dy - constraint[0], cos * dy - sin * dx - constraint[1]);
+    let (sin, cos) = constraint[2].sin_cos();
+    vec![
+        dx * cos + dy * sin,
+        dy * cos - dx * sin,
+        normalize_rotation(to[2] - from[2] - constraint[2]),
+    ]
+}
+
+// expects the contents of the vehicle's pose, the observed position and the anchors of their sessions
+fn calc_observation_residual_2d(contents: &[Vec<Dual>], constraint: &[f64]) -> Vec<Dual> {
+    let pose = to_world_2d(&contents[2], &contents[0]);
+    let position = to_world_2d(&contents[3], &contents[1]);
+    let (sin, cos) = (pose[2].sin(), pose[2].cos());
+    let (dx, dy) = (position[0] - pose[0], position[1] - pose[1]);
+    vec![cos * dx + sin * dy - constraint[0], cos * dy - sin * dx - constraint[1]]
+}
+
+// expects the contents of the source pose, the target pose and the anchors of their sessions
+fn calc_odometry_residual_3d(contents: &[Vec<Dual>], constraint: &[f64]) -> Vec<Dual> {
+    let (position_from, rotation_from) = to_world_3d(&contents[2], &contents[0]);
+    let (position_to, rotation_to) = to_world_3d(&contents[3], &contents[1]);
+    let delta: Vec<Dual> = (0..3).map(|k| position_to[k] - position_from[k]).collect();
+    let local_translation = rotate_inverse(&rotation_from, &delta);
+    let local_rotation = quaternion_product(&quaternion_conjugate(&rotation_from), &rotation_to);
+
+    let measured_rotation = get_measured_rotation(constraint);
+    let translation_error: Vec<Dual> = (0..3).map(|k| local_translation[k] - constraint[k]).collect();
+    let translation_error = rotate_inverse(&measured_rotation, &translation_error);
+    let rotation_error = quaternion_product(&quaternion_conjugate(&measured_rotation), &local_rotation);
+    let sign = if rotation_error[3].value < 0.0 { -1.0 } else { 1.0 };
+    translation_error
+        .into_iter()
+        .chain(rotation_error[..3].iter().map(|v| *v * sign))
+        .collect()
+}
+
+// expects the contents of the vehicle's pose, the observed position and the anchors of their sessions
+fn calc_observation_residual_3d(contents: &[Vec<Dual>], constraint: &[f64]) -> Vec<Dual> {
+    let (pose_position, pose_rotation) = to_world_3d(&contents[2], &contents[0]);
+    let anchor = &contents[3];
+    let anchor_rotation = quaternion_conjugate(&[anchor[3], anchor[4], anchor[5], anchor[6]]);
+    let position = rotate_inverse(&anchor_rotation, &contents[1][..3]);
+    let delta: Vec<Dual> = (0..3).map(|k| anchor[k] + position[k] - pose_position[k]).collect();
+    let local_position = rotate_inverse(&pose_rotation, &delta);
+    (0..3).map(|k| local_position[k] - constraint[k]).collect()
+}
+
+// returns the pose in the world frame of a pose in the frame of the session with the given anchor
+fn to_world_2d(anchor: &[Dual], pose: &[Dual]) -> Vec<Dual> {
+    let (sin, cos) = (anchor[2].sin(), anchor[2].cos());
+    let mut world = vec![
+        anchor[0] + cos * pose[0] - sin * pose[1],
+        anchor[1] + sin * pose[0] + cos * pose[1],
+    ];
+    if pose.len() > 2 {
+        world.push(anchor[2] + pose[2]);
+    }
+    world
+}
+
+// returns the position and rotation in the world frame of a pose in the frame of the session with the given anchor
+fn to_world_3d(anchor: &[Dual], pose: &[Dual]) -> (Vec<Dual>, DualQuaternion) {
+    let anchor_rotation = [anchor[3], anchor[4], anchor[5], anchor[6]];
+    let position = rotate_inverse(&quaternion_conjugate(&anchor_rotation), &pose[..3]);
+    let rotation = quaternion_product(&anchor_rotation, &[pose[3], pose[4], pose[5], pose[6]]);
+    ((0..3).map(|k| anchor[k] + position[k]).collect(), rotation)
+}
+
+// returns the normalized rotation of the measurement as constant dual quaternion
+fn get_measured_rotation(constraint: &[f64]) -> DualQuaternion {
+    let rotation = UnitQuaternion::from_quaternion(Quaternion::new(
+        constraint[6],
+        constraint[3],
+        constraint[4],
+        constraint[5],
+    ));
+    let coords = rotation.coords;
+    [
+        Dual::constant(coords[0]),
+        Dual::constant(coords[1]),
+        Dual::constant(coords[2]),
+        Dual::constant(coords[3]),
+    ]
+}
+
+fn normalize_rotation(rotation: Dual) -> Dual {
+    rotation - 2.0 * PI * ((rotation.value + PI) / (2.0 * PI)).floor()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::factor_graph::factor::FactorId;
+    use crate::factor_graph::variable::VariableId;
+    use crate::optimizer::handler_check::{build_edge, build_factor_graph, check_factor};
+    use crate::optimizer::{optimize, total_chi2};
+    use approx::assert_relative_eq;
+    use nalgebra::DMatrix;
+
+    fn identity(dim: usize) -> Vec<f64> {
+        DMatrix::<f64>::identity(dim, dim).as_slice().to_vec()
+    }
+
+    // checks a factor of the given type between the given variables, whose measurement is replaced by the predicted
+    // one, so that the given restriction only determines its dimension
+    fn check_predicted_factor(vertices: &[(&str, Vec<f64>)], edge_type: &str, restriction: Vec<f64>) {
+        // the 7 restriction values of a 3D odometry have 6 degrees of freedom
+        let information_matrix = identity(restriction.len().min(6));
+        let edge = build_edge(edge_type, &[0, 1, 2, 3], restriction, information_matrix.clone());
+        let factor_graph = build_factor_graph(vertices, vec![edge], &[]);
+        let prediction = factor_graph.get_factor(FactorId(0)).unwrap().predict(&factor_graph);
+        let edge = build_edge(edge_type, &[0, 1, 2, 3], prediction, information_matrix);
+        let factor_graph = build_factor_graph(vertices, vec![edge], &[]);
+        assert_eq!(check_factor(&factor_graph, FactorId(0), 1e-6), Ok(()));
+    }
+
+    #[test]
+    fn test_handlers() {
+        let pose_2d = vec![0.3, -0.5, 0.4];
+        let anchors_2d = [("Anchor2D", vec![0.2, 0.1, -0.3]), ("Anchor2D", vec![-1.0, 2.0, 0.7])];
+        check_predicted_factor(
+            &[
+                ("Vehicle2D", pose_2d.clone()),
+                ("Vehicle2D", vec![1.2, 0.4, -0.6]),
+                anchors_2d[0].clone(),
+                anchors_2d[1].clone(),
+            ],
+            "AnchoredOdometry2D",
+            vec![1.5, 1.2, 0.3],
+        );
+        check_predicted_factor(
+            &[
+                ("Vehicle2D", pose_2d),
+                ("Landmark2D", vec![2.1, 1.4]),
+                anchors_2d[0].clone(),
+                anchors_2d[1].clone(),
+            ],
+            "AnchoredObservation2D",
+            vec![1.5, 1.2],
+        );
+        let pose_3d = vec![0.3, -0.2, 0.1, 0.1, -0.2, 0.3, 0.927362];
+        let anchors_3d = [
+            ("Anchor3D", vec![0.2, 0.0, 0.5, -0.5, 0.5, -0.5, 0.5]),
+            ("Anchor3D", vec![-1.0, 2.0, 0.3, 0.0, 0.0, 0.6, 0.8]),
+        ];
+        check_predicted_factor(
+            &[
+                ("Vehicle3D", pose_3d.clone()),
+                ("Vehicle3D", vec![1.0, 0.5, -0.4, 0.0, 0.3, 0.0, 0.953939]),
+                anchors_3d[0].clone(),
+                anchors_3d[1].clone(),
+            ],
+            "AnchoredOdometry3D",
+            vec![0.4, 1.1, 1.6, 0.1, 0.0, -0.1, 0.989949],
+        );
+        check_predicted_factor(
+            &[
+                ("Vehicle3D", pose_3d),
+                ("Landmark3D", vec![2.0, 1.0, -0.5]),
+                anchors_3d[0].clone(),
+                anchors_3d[1].clone(),
+            ],
+            "AnchoredObservation3D",
+            vec![0.4, 1.1, 1.6],
+        );
+    }
+
+    #[test]
+    fn test_sessions_are_aligned() {
+        // two sessions drive along parallel lanes, each in its own frame, and are connected by loop closures and a
+        // shared landmark, so that the anchor of the second session is estimated relative to the first one
+        let anchor: [f64; 3] = [1.0, 2.0, 0.5];
+        let world_poses: Vec<[f64; 3]> = (0..4)
+            .map(|k| [k as f64, 0.0, 0.0])
+            .chain((0..4).map(|k| [k as f64 + 0.5, 1.0, 0.1 * k as f64]))
+            .collect();
+        let landmark = [2.0, 3.0];
+        let (sin, cos) = anchor[2].sin_cos();
+        let session_poses: Vec<[f64; 3]> = world_poses
+            .iter()
+            .enumerate()
+            .map(|(k, pose)| match k {
+                0..=3 => *pose,
+                _ => {
+                    let (dx, dy) = (pose[0] - anchor[0], pose[1] - anchor[1]);
+                    [cos * dx + sin * dy, cos * dy - sin * dx, pose[2] - anchor[2]]
+                }
+            })
+            .collect();
+        let get_local = |from: &[f64; 3], to: &[f64]| {
+            let (sin, cos) = from[2].sin_cos();
+            let (dx, dy) = (to[0] - from[0], to[1] - from[1]);
+            vec![cos * dx + sin * dy, cos * dy - sin * dx]
+        };
+        let get_odometry = |from: &[f64; 3], to: &[f64; 3]| {
+            let mut odometry = get_local(from, to);
+            odometry.push(to[2] - from[2]);
+            odometry
+        };
+
+        let mut vertices: Vec<(&str, Vec<f64>)> = session_poses
+            .iter()
+            .enumerate()
+            .map(|(k, pose)| match k {
+                0 | 4 => ("Vehicle2D", pose.to_vec()),
+                _ => ("Vehicle2D", vec![pose[0] + 0.1, pose[1] - 0.1, pose[2] + 0.05]),
+            })
+            .collect();
+        vertices.push(("Landmark2D", vec![landmark[0] + 0.2, landmark[1] - 0.1]));
+        vertices.push(("Anchor2D", vec![0.0, 0.0, 0.0]));
+        vertices.push(("Anchor2D", vec![0.0, 0.0, 0.0]));
+        let (landmark_id, first_anchor_id, second_anchor_id) = (8, 9, 10);
+
+        let mut edges = vec![];
+        for k in (1..4).chain(5..8) {
+            let odometry = get_odometry(&session_poses[k - 1], &session_poses[k]);
+            edges.push(build_edge("Odometry2D", &[k - 1, k], odometry, identity(3)));
+        }
+        for k in 0..4 {
+            let closure = get_odometry(&world_poses[k], &world_poses[k + 4]);
+            edges.push(build_edge(
+                "AnchoredOdometry2D",
+                &[k, k + 4, first_anchor_id, second_anchor_id],
+                closure,
+                identity(3),
+            ));
+        }
+        edges.push(build_edge(
+            "Observation2D",
+            &[1, landmark_id],
+            get_local(&world_poses[1], &landmark),
+            identity(2),
+        ));
+        edges.push(build_edge(
+            "AnchoredObservation2D",
+            &[6, landmark_id, second_anchor_id, first_anchor_id],
+            get_local(&world_poses[6], &landmark),
+            identity(2),
+        ));
+
+        let factor_graph = build_factor_graph(&vertices, edges, &[0, 4, first_anchor_id]);
+        assert!(total_chi2(&factor_graph) > 1e-2);
+        optimize(&factor_graph, 20);
+        assert!(total_chi2(&factor_graph) < 1e-12);
+        let estimate = factor_graph
+            .get_var_by_id(VariableId(second_anchor_id))
+            .unwrap()
+            .get_content();
+        for (actual, expected) in estimate.iter().zip(&anchor) {
+            assert_relative_eq!(actual, expected, epsilon = 1e-6);
+        }
+        for id in 6..12 {
+            let factor = factor_graph.get_factor(FactorId(id)).unwrap();
+            for (actual, expected) in factor.predict(&factor_graph).iter().zip(&factor.constraint) {
+                assert_relative_eq!(actual, expected, epsilon = 1e-6);
+            }
+        }
+    }
+}
@@ -0,0 +1,174 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Bearing-range and bearing-only measurements from a 2D vehicle pose to a 2D landmark, e.g. of laser scanners or
+//! radars and of cameras, respectively.
+//!
+//! The constraint is [bearing, range] or [bearing], where the bearing is the angle between the vehicle's heading and
+//! the direction to the landmark. The error is [normalized(predicted bearing - bearing), predicted range - range],
+//! or only its first entry for bearing-only measurements.
+
+#![allow(non_snake_case)]
+
+use crate::factor_graph::factor::{Factor, FactorType};
+use crate::factor_graph::variable::{LandmarkVariable2D, Variable, VehicleVariable2D};
+use crate::optimizer::block_sparse::BlockSparseMatrix;
+use crate::optimizer::linear_system::add_to_H_b;
+use nalgebra::{DMatrix, DVector, Vector2};
+use std::f64::consts::PI;
+
+pub fn update_H_b(H: &mut BlockSparseMatrix, b: &mut DVector<f64>, factor: &Factor, vars: &[&Variable]) {
+    let (jacobi, err) = match (vars[0], vars[1]) {
+        (Variable::Vehicle2D(var_i), Variable::Landmark2D(var_j)) => {
+            (calc_jacobian(factor, var_i, var_j), calc_error(factor, var_i, var_j))
+        }
+        _ => unreachable!("No valid edge."),
+    };
+    add_to_H_b(H, b, &factor.information_matrix.content, &jacobi, &err, vars);
+}
+
+pub fn calc_error(factor: &Factor, var_i: &VehicleVariable2D, var_j: &LandmarkVariable2D) -> Vec<f64> {
+    let (delta, rot_i) = get_delta_and_rot(var_i, var_j);
+    let mut err_bearing = (delta[1].atan2(delta[0]) - rot_i - factor.constraint[0]) % (2.0 * PI);
+    if err_bearing >= PI {
+        err_bearing -= 2.0 * PI;
+    } else if err_bearing < -PI {
+        err_bearing += 2.0 * PI;
+    }
+    match factor.factor_type {
+        FactorType::Bearing2D => vec![err_bearing],
+        _ => vec![err_bearing, delta.norm() - factor.constraint[1]],
+    }
+}
+
+/// Calculates the Jacobian with respect to [x_i, y_i, rotation_i, x_j, y_j].
+///
+/// The Jacobian is undefined if the landmark coincides with the vehicle's position, in which case it is zero.
+pub fn calc_jacobian(factor: &Factor, var_i: &VehicleVariable2D, var_j: &LandmarkVariable2D) -> DMatrix<f64> {
+    let rows = if factor.factor_type == FactorType::Bearing2D {
+        1
+    } else {
+        2
+    };
+    let (delta, _) = get_delta_and_rot(var_i, var_j);
+    let range_sq = delta.norm_squared();
+    if range_sq == 0.0 {
+        return DMatrix::zeros(rows, 5);
+    }
+    let range = range_sq.sqrt();
+    let (d_bearing, d_range) = (Vector2::new(-delta[1], delta[0]) / range_sq, delta / range);
+    #[rustfmt::skip]
+    let jacobian = DMatrix::from_row_slice(2, 5, &[
+        -d_bearing[0], -d_bearing[1], -1.0, d_bearing[0], d_bearing[1],
+          -d_range[0],   -d_range[1],  0.0,   d_range[0],   d_range[1],
+    ]);
+    jacobian.rows(0, rows).into_owned()
+}
+
+// returns the landmark's position relative to the vehicle's one in world coordinates and the vehicle's rotation
+fn get_delta_and_rot(var_i: &VehicleVariable2D, var_j: &LandmarkVariable2D) -> (Vector2<f64>, f64) {
+    let pose_i = var_i.pose.borrow();
+    let pos_j = var_j.position.borrow();
+    (Vector2::new(pos_j[0] - pose_i[0], pos_j[1] - pose_i[1]), pose_i[2])
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::factor_graph::factor::{FactorId, FactorType};
+    use crate::factor_graph::variable::VariableId;
+    use crate::factor_graph::FactorGraph;
+    use crate::optimizer::handler_check::{build_edge, build_factor_graph, check_factor};
+    use crate::optimizer::{optimize, total_chi2};
+    use crate::parser::g2o::G2oParser;
+    use crate::parser::Parser;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn test_handlers() {
+        let (pose, position) = (vec![0.3, -0.2, 0.4], vec![2.0, 1.5]);
+        let (delta_x, delta_y) = (position[0] - pose[0], position[1] - pose[1]);
+        let factor_graph = build_factor_graph(
+            &[("Vehicle2D", pose.clone()), ("Landmark2D", position.clone())],
+            vec![build_edge(
+                "BearingRange2D",
+                &[0, 1],
+                vec![delta_y.atan2(delta_x) - pose[2], delta_x.hypot(delta_y)],
+                vec![1.0, 0.0, 0.0, 1.0],
+            )],
+            &[],
+        );
+        assert_eq!(
+            factor_graph.get_factor(FactorId(0)).unwrap().factor_type,
+            FactorType::BearingRange2D
+        );
+        assert_eq!(check_factor(&factor_graph, FactorId(0), 1e-6), Ok(()));
+
+        let factor_graph = build_factor_graph(
+            &[("Vehicle2D", pose.clone()), ("Landmark2D", position)],
+            vec![build_edge(
+                "Bearing2D",
+                &[0, 1],
+                vec![delta_y.atan2(delta_x) - pose[2]],
+                vec![1.0],
+            )],
+            &[],
+        );
+        assert_eq!(check_factor(&factor_graph, FactorId(0), 1e-6), Ok(()));
+    }
+
+    #[test]
+    fn test_bearing_range_optimization() {
+        // the landmark is at (1, 1), observed from both poses
+        let factor_graph: FactorGraph = G2oParser::parse_string_to_model(
+            &[
+                "VERTEX_SE2 0 0.0 0.0 0.0",
+                "FIX 0",
+                "VERTEX_SE2 1 1.2 0.1 0.2",
+                "VERTEX_XY 2 0.5 0.5",
+                "EDGE_SE2 0 1 1.0 0.0 1.5707963267948966 1.0 0.0 0.0 1.0 0.0 1.0",
+                "EDGE_BEARING_RANGE_SE2_XY 0 2 0.7853981633974483 1.4142135623730951 1.0 0.0 1.0",
+                "EDGE_BEARING_RANGE_SE2_XY 1 2 0.0 1.0 1.0 0.0 1.0",
+            ]
+            .join("\n"),
+        )
+        .unwrap()
+        .into();
+        optimize(&factor_graph, 20);
+        assert!(total_chi2(&factor_graph) < 1e-12);
+        let landmark = factor_graph.get_var_by_id(VariableId(2)).unwrap().get_content();
+        assert_relative_eq!(landmark[0], 1.0, epsilon = 1e-6);
+        assert_relative_eq!(landmark[1], 1.0, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn test_bearing_optimization() {
+        // the landmark is at (2, 2), observed from both fixed poses
+        let factor_graph: FactorGraph = G2oParser::parse_string_to_model(
+            &[
+                "VERTEX_SE2 0 0.0 0.0 0.0",
+                "VERTEX_SE2 1 2.0 0.0 1.5707963267948966",
+                "FIX 0 1",
+                "VERTEX_XY 2 1.5 1.0",
+                "EDGE_BEARING_SE2_XY 0 2 0.7853981633974483 1.0",
+                "EDGE_BEARING_SE2_XY 1 2 0.0 1.0",
+            ]
+            .join("\n"),
+        )
+        .unwrap()
+        .into();
+        optimize(&factor_graph, 20);
+        assert!(total_chi2(&factor_graph) < 1e-12);
+        let landmark = factor_graph.get_var_by_id(VariableId(2)).unwrap().get_content();
+        assert_relative_eq!(landmark[0], 2.0, epsilon = 1e-6);
+        assert_relative_eq!(landmark[1], 2.0, epsilon = 1e-6);
+    }
+}
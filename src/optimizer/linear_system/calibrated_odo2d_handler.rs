@@ -0,0 +1,182 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Relative measurements between two vehicle poses in 2D by an odometry with systematic errors, whose calibration is
+//! estimated jointly with the poses.
+//!
+//! The calibration (s_t, s_r, b) models an odometry which measures the relative translation t scaled by s_t and the
+//! relative rotation r as s_r * r + b * |t|, e.g. due to wrong wheel radii or a wrong track width of a differential
+//! drive. The error is the difference between the measurement predicted this way and the actual one, with its
+//! rotation normalized to [-PI, PI). The Jacobians are calculated with automatic differentiation.
+
+#![allow(non_snake_case)]
+
+use crate::factor_graph::factor::{CustomResidual, Factor};
+use crate::factor_graph::variable::Variable;
+use crate::optimizer::autodiff::Dual;
+use crate::optimizer::block_sparse::BlockSparseMatrix;
+use crate::optimizer::linear_system::custom_handler;
+use nalgebra::{DMatrix, DVector};
+use std::f64::consts::PI;
+
+pub fn update_H_b(H: &mut BlockSparseMatrix, b: &mut DVector<f64>, factor: &Factor, vars: &[&Variable]) {
+    custom_handler::update_H_b(H, b, factor, &get_residual(), vars);
+}
+
+pub fn calc_error(factor: &Factor, vars: &[&Variable]) -> Vec<f64> {
+    custom_handler::calc_error(factor, &get_residual(), vars)
+}
+
+pub fn calc_jacobian(factor: &Factor, vars: &[&Variable]) -> DMatrix<f64> {
+    custom_handler::calc_jacobian(factor, &get_residual(), vars)
+}
+
+fn get_residual() -> CustomResidual {
+    CustomResidual::new("CalibratedOdometry2D", calc_residual)
+}
+
+// expects the contents of the source pose, the target pose and the calibration
+fn calc_residual(contents: &[Vec<Dual>], constraint: &[f64]) -> Vec<Dual> {
+    let (from, to, calibration) = (&contents[0], &contents[1], &contents[2]);
+    let (sin, cos) = (from[2].sin(), from[2].cos());
+    let (dx, dy) = (to[0] - from[0], to[1] - from[1]);
+    let local_translation = [cos * dx + sin * dy, cos * dy - sin * dx];
+    let local_rotation = normalize_rotation(to[2] - from[2]);
+    let squared_distance = local_translation[0] * local_translation[0] + local_translation[1] * local_translation[1];
+    // the distance is not differentiable without motion, where its bias vanishes
+    let distance = if squared_distance.value > 0.0 {
+        squared_distance.sqrt()
+    } else {
+        Dual::constant(0.0)
+    };
+    let measured_rotation = calibration[1] * local_rotation + calibration[2] * distance;
+    vec![
+        calibration[0] * local_translation[0] - constraint[0],
+        calibration[0] * local_translation[1] - constraint[1],
+        normalize_rotation(measured_rotation - constraint[2]),
+    ]
+}
+
+fn normalize_rotation(rotation: Dual) -> Dual {
+    rotation - 2.0 * PI * ((rotation.value + PI) / (2.0 * PI)).floor()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::factor_graph::factor::FactorId;
+    use crate::factor_graph::variable::VariableId;
+    use crate::optimizer::handler_check::{build_edge, build_factor_graph, check_factor};
+    use crate::optimizer::{optimize, total_chi2};
+    use crate::parser::model::Edge;
+    use approx::assert_relative_eq;
+    use std::f64::consts::PI;
+
+    const INFORMATION_MATRIX: [f64; 9] = [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0];
+
+    fn normalize(rotation: f64) -> f64 {
+        rotation.sin().atan2(rotation.cos())
+    }
+
+    // returns the odometry between both poses measured with the given calibration
+    fn measure(from: &[f64], to: &[f64], calibration: &[f64]) -> Vec<f64> {
+        let (sin, cos) = from[2].sin_cos();
+        let (dx, dy) = (to[0] - from[0], to[1] - from[1]);
+        let (x, y) = (cos * dx + sin * dy, cos * dy - sin * dx);
+        let rotation = calibration[1] * normalize(to[2] - from[2]) + calibration[2] * x.hypot(y);
+        vec![calibration[0] * x, calibration[0] * y, rotation]
+    }
+
+    #[test]
+    fn test_handler() {
+        let (from, to, calibration) = ([0.3, -0.5, 0.4], [1.6, 0.2, 0.9], [1.05, 0.97, 0.02]);
+        let factor_graph = build_factor_graph(
+            &[
+                ("Vehicle2D", from.to_vec()),
+                ("Vehicle2D", to.to_vec()),
+                ("OdometryCalibration2D", calibration.to_vec()),
+            ],
+            vec![build_edge(
+                "CalibratedOdometry2D",
+                &[0, 1, 2],
+                measure(&from, &to, &calibration),
+                INFORMATION_MATRIX.to_vec(),
+            )],
+            &[],
+        );
+        assert_eq!(check_factor(&factor_graph, FactorId(0), 1e-6), Ok(()));
+    }
+
+    #[test]
+    fn test_shared_calibration_is_estimated() {
+        // a vehicle drives a square twice, its odometry overestimates distances and turns and drifts to the left,
+        // while every second pose is measured, e.g. by GPS
+        let calibration = [1.05, 1.1, 0.02];
+        let mut poses = vec![[0.0, 0.0, 0.0]];
+        for k in 0..16 {
+            let [x, y, rotation]: [f64; 3] = poses[k];
+            let (sin, cos) = rotation.sin_cos();
+            let turn = if k % 2 == 1 { PI / 2.0 } else { 0.0 };
+            let rotation = normalize(rotation + turn + 0.05);
+            poses.push([x + cos * 1.5 - sin * 0.1, y + sin * 1.5 + cos * 0.1, rotation]);
+        }
+        let measurements: Vec<Vec<f64>> = poses
+            .windows(2)
+            .map(|pair| measure(&pair[0], &pair[1], &calibration))
+            .collect();
+
+        // the initial estimates integrate the odometry without calibration
+        let mut vertices = vec![("Vehicle2D", poses[0].to_vec())];
+        for measurement in &measurements {
+            let pose = &vertices.last().unwrap().1;
+            let (sin, cos) = pose[2].sin_cos();
+            let next = vec![
+                pose[0] + cos * measurement[0] - sin * measurement[1],
+                pose[1] + sin * measurement[0] + cos * measurement[1],
+                normalize(pose[2] + measurement[2]),
+            ];
+            vertices.push(("Vehicle2D", next));
+        }
+        let calibration_id = vertices.len();
+        vertices.push(("OdometryCalibration2D", vec![1.0, 1.0, 0.0]));
+        let mut edges: Vec<Edge> = measurements
+            .into_iter()
+            .enumerate()
+            .map(|(k, measurement)| {
+                build_edge(
+                    "CalibratedOdometry2D",
+                    &[k, k + 1, calibration_id],
+                    measurement,
+                    INFORMATION_MATRIX.to_vec(),
+                )
+            })
+            .collect();
+        for k in (2..poses.len()).step_by(2) {
+            edges.push(build_edge(
+                "Position2D",
+                &[k],
+                poses[k].to_vec(),
+                INFORMATION_MATRIX.to_vec(),
+            ));
+        }
+        let factor_graph = build_factor_graph(&vertices, edges, &[0]);
+        assert!(total_chi2(&factor_graph) > 1e-2);
+        optimize(&factor_graph, 20);
+        assert!(total_chi2(&factor_graph) < 1e-12);
+        let estimate = factor_graph
+            .get_var_by_id(VariableId(calibration_id))
+            .unwrap()
+            .get_content();
+        for (actual, expected) in estimate.iter().zip(&calibration) {
+            assert_relative_eq!(actual, expected, epsilon = 1e-6);
+        }
+    }
+}
@@ -0,0 +1,157 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Smoothness priors on three consecutive vehicle poses in 2D or 3D, which penalize the change of the relative
+//! motion between them, i.e. the acceleration of a vehicle whose poses are equally spaced in time.
+//!
+//! The factor's source is the previous pose, its target the next pose and its only additional variable the middle
+//! pose, so that it does not collide with odometry factors between consecutive poses. The error is the difference
+//! between the motion from the middle to the next pose and the motion from the previous to the middle pose, both in
+//! the frame of their first pose. Like for odometry, the rotation is expressed as angle in 2D and as vector part of
+//! the quaternion with non-negative w in 3D. The constraint is empty and the Jacobians are calculated with automatic
+//! differentiation.
+
+#![allow(non_snake_case)]
+
+use crate::factor_graph::factor::{CustomResidual, Factor, FactorType};
+use crate::factor_graph::variable::Variable;
+use crate::optimizer::autodiff::{quaternion_conjugate, quaternion_product, rotate_inverse, Dual, DualQuaternion};
+use crate::optimizer::block_sparse::BlockSparseMatrix;
+use crate::optimizer::linear_system::custom_handler;
+use nalgebra::{DMatrix, DVector};
+use std::f64::consts::PI;
+
+pub fn update_H_b(H: &mut BlockSparseMatrix, b: &mut DVector<f64>, factor: &Factor, vars: &[&Variable]) {
+    custom_handler::update_H_b(H, b, factor, &get_residual(factor), vars);
+}
+
+pub fn calc_error(factor: &Factor, vars: &[&Variable]) -> Vec<f64> {
+    custom_handler::calc_error(factor, &get_residual(factor), vars)
+}
+
+pub fn calc_jacobian(factor: &Factor, vars: &[&Variable]) -> DMatrix<f64> {
+    custom_handler::calc_jacobian(factor, &get_residual(factor), vars)
+}
+
+fn get_residual(factor: &Factor) -> CustomResidual {
+    match factor.factor_type {
+        FactorType::ConstantVelocity2D => CustomResidual::new("ConstantVelocity2D", calc_residual_2d),
+        _ => CustomResidual::new("ConstantVelocity3D", calc_residual_3d),
+    }
+}
+
+// expects the contents of the previous, the next and the middle pose
+fn calc_residual_2d(contents: &[Vec<Dual>], _: &[f64]) -> Vec<Dual> {
+    let (previous, next, middle) = (&contents[0], &contents[1], &contents[2]);
+    let first_motion = get_local_motion_2d(previous, middle);
+    let second_motion = get_local_motion_2d(middle, next);
+    let rotation_change = second_motion[2] - first_motion[2];
+    let turns = ((rotation_change.value + PI) / (2.0 * PI)).floor();
+    vec![
+        second_motion[0] - first_motion[0],
+        second_motion[1] - first_motion[1],
+        rotation_change - turns * 2.0 * PI,
+    ]
+}
+
+fn get_local_motion_2d(from: &[Dual], to: &[Dual]) -> [Dual; 3] {
+    let (dx, dy) = (to[0] - from[0], to[1] - from[1]);
+    let (sin, cos) = (from[2].sin(), from[2].cos());
+    [cos * dx + sin * dy, cos * dy - sin * dx, to[2] - from[2]]
+}
+
+// expects the contents of the previous, the next and the middle pose
+fn calc_residual_3d(contents: &[Vec<Dual>], _: &[f64]) -> Vec<Dual> {
+    let (previous, next, middle) = (&contents[0], &contents[1], &contents[2]);
+    let (first_translation, first_rotation) = get_local_motion_3d(previous, middle);
+    let (second_translation, second_rotation) = get_local_motion_3d(middle, next);
+    let rotation_change = quaternion_product(&quaternion_conjugate(&first_rotation), &second_rotation);
+    let sign = if rotation_change[3].value < 0.0 { -1.0 } else { 1.0 };
+    (0..3)
+        .map(|k| second_translation[k] - first_translation[k])
+        .chain(rotation_change[..3].iter().map(|v| *v * sign))
+        .collect()
+}
+
+fn get_local_motion_3d(from: &[Dual], to: &[Dual]) -> (Vec<Dual>, DualQuaternion) {
+    let rotation_from = [from[3], from[4], from[5], from[6]];
+    let rotation_to = [to[3], to[4], to[5], to[6]];
+    let delta: Vec<Dual> = (0..3).map(|k| to[k] - from[k]).collect();
+    (
+        rotate_inverse(&rotation_from, &delta),
+        quaternion_product(&quaternion_conjugate(&rotation_from), &rotation_to),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::factor_graph::factor::FactorId;
+    use crate::factor_graph::variable::VariableId;
+    use crate::optimizer::handler_check::{build_factor_graph, check_factor};
+    use nalgebra::{DMatrix, Isometry3, Translation3, UnitQuaternion, Vector3};
+    use std::f64::consts::PI;
+
+    fn get_content(pose: &Isometry3<f64>) -> Vec<f64> {
+        let (t, q) = (pose.translation.vector, pose.rotation.coords);
+        vec![t.x, t.y, t.z, q[0], q[1], q[2], q[3]]
+    }
+
+    #[test]
+    fn test_handlers() {
+        // both motions are [1, 0.5, 0.4], while the rotations of the poses differ by 6.3 - 2 * PI and 0.4
+        let poses: Vec<VariableId> = (0..3).map(VariableId).collect();
+        let (rotation_0, rotation_1) = (3.0_f64, 3.4 - 2.0 * PI);
+        let (x_1, y_1) = (
+            0.1 + rotation_0.cos() - 0.5 * rotation_0.sin(),
+            -0.2 + rotation_0.sin() + 0.5 * rotation_0.cos(),
+        );
+        let (x_2, y_2) = (
+            x_1 + rotation_1.cos() - 0.5 * rotation_1.sin(),
+            y_1 + rotation_1.sin() + 0.5 * rotation_1.cos(),
+        );
+        let mut factor_graph = build_factor_graph(
+            &[
+                ("Vehicle2D", vec![0.1, -0.2, rotation_0]),
+                ("Vehicle2D", vec![x_1, y_1, rotation_1]),
+                ("Vehicle2D", vec![x_2, y_2, rotation_1 + 0.4]),
+            ],
+            vec![],
+            &[],
+        );
+        factor_graph
+            .add_constant_velocity_factors(&poses, DMatrix::<f64>::identity(3, 3).as_slice().to_vec().into())
+            .unwrap();
+        assert_eq!(check_factor(&factor_graph, FactorId(0), 1e-6), Ok(()));
+
+        let pose_0 = Isometry3::from_parts(
+            Translation3::new(0.3, -0.2, 0.1),
+            UnitQuaternion::from_scaled_axis(Vector3::new(0.2, -0.4, 0.6)),
+        );
+        let motion = Isometry3::from_parts(
+            Translation3::new(0.9, 0.3, 0.2),
+            UnitQuaternion::from_scaled_axis(Vector3::new(-0.2, 0.4, 0.1)),
+        );
+        let mut factor_graph = build_factor_graph(
+            &[
+                ("Vehicle3D", get_content(&pose_0)),
+                ("Vehicle3D", get_content(&(pose_0 * motion))),
+                ("Vehicle3D", get_content(&(pose_0 * motion * motion))),
+            ],
+            vec![],
+            &[],
+        );
+        factor_graph
+            .add_constant_velocity_factors(&poses, DMatrix::<f64>::identity(6, 6).as_slice().to_vec().into())
+            .unwrap();
+        assert_eq!(check_factor(&factor_graph, FactorId(0), 1e-6), Ok(()));
+    }
+}
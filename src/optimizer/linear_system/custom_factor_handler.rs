@@ -0,0 +1,233 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! User-defined factors, whose errors and Jacobians are calculated by their
+//! [CustomFactor](../../../factor_graph/factor/trait.CustomFactor.html) implementation.
+
+#![allow(non_snake_case)]
+
+use crate::factor_graph::factor::{CustomFactor, Factor};
+use crate::factor_graph::variable::Variable;
+use crate::optimizer::block_sparse::BlockSparseMatrix;
+use crate::optimizer::linear_system::{add_to_H_b, get_tangent_offsets};
+use nalgebra::{DMatrix, DVector};
+
+pub fn update_H_b(
+    H: &mut BlockSparseMatrix,
+    b: &mut DVector<f64>,
+    factor: &Factor,
+    custom: &dyn CustomFactor,
+    vars: &[&Variable],
+) {
+    let (err, jacobi) = evaluate(factor, custom, vars);
+    add_to_H_b(H, b, &factor.information_matrix.content, &jacobi, &err, vars);
+}
+
+pub fn calc_error(factor: &Factor, custom: &dyn CustomFactor, vars: &[&Variable]) -> Vec<f64> {
+    evaluate(factor, custom, vars).0
+}
+
+/// Calculates the Jacobian with respect to all variables by placing the Jacobians of the single variables side by
+/// side.
+pub fn calc_jacobian(factor: &Factor, custom: &dyn CustomFactor, vars: &[&Variable]) -> DMatrix<f64> {
+    evaluate(factor, custom, vars).1
+}
+
+// returns the error and the Jacobian with respect to all variables, panicking if their dimensions do not match the
+// information matrix and the variables
+fn evaluate(factor: &Factor, custom: &dyn CustomFactor, vars: &[&Variable]) -> (Vec<f64>, DMatrix<f64>) {
+    let (err, jacobians) = custom.error_and_jacobians(vars);
+    let dim = factor.information_matrix.content.nrows();
+    if err.len() != dim {
+        panic!(
+            "Error of factor {} has {} entries, but its information matrix has {} rows.",
+            factor.id,
+            err.len(),
+            dim
+        );
+    }
+    if jacobians.len() != vars.len() {
+        panic!(
+            "Factor {} returned {} Jacobians, but it has {} variables.",
+            factor.id,
+            jacobians.len(),
+            vars.len()
+        );
+    }
+    let offsets = get_tangent_offsets(vars);
+    let mut jacobian = DMatrix::zeros(dim, offsets[vars.len()]);
+    for (i, var_jacobian) in jacobians.iter().enumerate() {
+        let tangent_dim = offsets[i + 1] - offsets[i];
+        if var_jacobian.shape() != (dim, tangent_dim) {
+            panic!(
+                "Jacobian {} of factor {} has the shape {:?}, but {:?} is expected.",
+                i,
+                factor.id,
+                var_jacobian.shape(),
+                (dim, tangent_dim)
+            );
+        }
+        jacobian.columns_mut(offsets[i], tangent_dim).copy_from(var_jacobian);
+    }
+    (err, jacobian)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::factor_graph::factor::{CustomFactor, FactorId, FactorType};
+    use crate::factor_graph::variable::{Variable, VariableId};
+    use crate::factor_graph::FactorGraph;
+    use crate::optimizer::linear_system::{calculate_error, calculate_jacobian};
+    use crate::optimizer::{optimize, total_chi2};
+    use crate::parser::model::{Edge, FactorGraphModel, Vertex};
+    use approx::assert_relative_eq;
+    use nalgebra::DMatrix;
+    use std::collections::BTreeSet;
+
+    // distance between a vehicle pose and a landmark in 2D with analytic Jacobians
+    struct Distance {
+        vehicle: VariableId,
+        landmark: VariableId,
+        range: f64,
+    }
+
+    impl CustomFactor for Distance {
+        fn name(&self) -> &str {
+            "Distance2D"
+        }
+
+        fn residual_dim(&self) -> usize {
+            1
+        }
+
+        fn variable_ids(&self) -> Vec<VariableId> {
+            vec![self.vehicle, self.landmark]
+        }
+
+        fn error_and_jacobians(&self, vars: &[&Variable]) -> (Vec<f64>, Vec<DMatrix<f64>>) {
+            let (pose, landmark) = (vars[0].get_content(), vars[1].get_content());
+            let (dx, dy) = (landmark[0] - pose[0], landmark[1] - pose[1]);
+            let distance = dx.hypot(dy);
+            (
+                vec![distance - self.range],
+                vec![
+                    DMatrix::from_row_slice(1, 3, &[-dx / distance, -dy / distance, 0.0]),
+                    DMatrix::from_row_slice(1, 2, &[dx / distance, dy / distance]),
+                ],
+            )
+        }
+    }
+
+    fn get_vertex(id: usize, vertex_type: &str, content: Vec<f64>) -> Vertex {
+        Vertex {
+            id: VariableId(id),
+            vertex_type: String::from(vertex_type),
+            content,
+        }
+    }
+
+    // returns fixed vehicle poses at the given positions and a landmark after them, connected by the given edges
+    fn get_factor_graph(positions: &[[f64; 2]], landmark: Vec<f64>, edges: Vec<Edge>) -> FactorGraph {
+        let mut vertices: Vec<Vertex> = positions
+            .iter()
+            .enumerate()
+            .map(|(id, position)| get_vertex(id, "Vehicle2D", vec![position[0], position[1], 0.0]))
+            .collect();
+        vertices.push(get_vertex(positions.len(), "Landmark2D", landmark));
+        FactorGraphModel {
+            vertices,
+            edges,
+            fixed_vertices: (0..positions.len()).map(VariableId).collect::<BTreeSet<_>>(),
+            ..Default::default()
+        }
+        .into()
+    }
+
+    #[test]
+    fn test_user_defined_factor_matches_builtin_factor() {
+        let range = Edge {
+            edge_type: String::from("Range2D"),
+            vertices: vec![VariableId(0), VariableId(1)],
+            restriction: vec![2.0],
+            information_matrix: vec![4.0],
+        };
+        let builtin = get_factor_graph(&[[0.5, -0.5]], vec![1.5, 1.0], vec![range]);
+        let mut custom = get_factor_graph(&[[0.5, -0.5]], vec![1.5, 1.0], vec![]);
+        let distance = Distance {
+            vehicle: VariableId(0),
+            landmark: VariableId(1),
+            range: 2.0,
+        };
+        let id = custom.add_custom_factor(distance, vec![4.0].into()).unwrap();
+        assert_eq!(FactorGraphModel::from(&custom).edges[0].edge_type, "Distance2D");
+
+        let (expected_error, error) = (
+            calculate_error(&builtin, FactorId(0)).unwrap(),
+            calculate_error(&custom, id).unwrap(),
+        );
+        assert_relative_eq!(error[0], expected_error[0], epsilon = 1e-12);
+        let (expected_jacobian, jacobian) = (
+            calculate_jacobian(&builtin, FactorId(0)).unwrap(),
+            calculate_jacobian(&custom, id).unwrap(),
+        );
+        assert_eq!(jacobian.shape(), expected_jacobian.shape());
+        for (actual, expected) in jacobian.iter().zip(expected_jacobian.iter()) {
+            assert_relative_eq!(actual, expected, epsilon = 1e-12);
+        }
+    }
+
+    #[test]
+    fn test_user_defined_factors_are_optimized() {
+        let positions = [[0.0, 0.0], [4.0, 0.0], [0.0, 3.0]];
+        let mut factor_graph = get_factor_graph(&positions, vec![1.5, 1.8], vec![]);
+        for (id, position) in positions.iter().enumerate() {
+            let distance = Distance {
+                vehicle: VariableId(id),
+                landmark: VariableId(3),
+                range: (2.0 - position[0]).hypot(1.0 - position[1]),
+            };
+            factor_graph.add_custom_factor(distance, vec![1.0].into()).unwrap();
+        }
+        assert!(total_chi2(&factor_graph) > 1e-2);
+        optimize(&factor_graph, 20);
+        assert!(total_chi2(&factor_graph) < 1e-12);
+        let landmark = factor_graph.get_var_by_id(VariableId(3)).unwrap().get_content();
+        assert_relative_eq!(landmark[0], 2.0, epsilon = 1e-6);
+        assert_relative_eq!(landmark[1], 1.0, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn test_invalid_user_defined_factors_are_rejected() {
+        let mut factor_graph = get_factor_graph(&[[0.0, 0.0]], vec![1.0, 0.0], vec![]);
+        let get_distance = |landmark| Distance {
+            vehicle: VariableId(0),
+            landmark: VariableId(landmark),
+            range: 1.0,
+        };
+        assert_eq!(
+            factor_graph
+                .add_custom_factor(get_distance(1), vec![1.0, 0.0, 0.0, 1.0].into())
+                .unwrap_err(),
+            "The custom factor Distance2D has a residual of dimension 1, but an information matrix of dimension 2"
+        );
+        assert!(factor_graph
+            .add_custom_factor(get_distance(2), vec![1.0].into())
+            .is_err());
+        let id = factor_graph
+            .add_custom_factor(get_distance(1), vec![1.0].into())
+            .unwrap();
+        assert!(matches!(
+            factor_graph.get_factor(id).unwrap().factor_type,
+            FactorType::UserDefined(_)
+        ));
+    }
+}
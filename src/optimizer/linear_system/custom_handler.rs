@@ -0,0 +1,127 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+#![allow(non_snake_case)]
+
+use crate::factor_graph::factor::{CustomResidual, Factor};
+use crate::factor_graph::variable::parameterization::get_plane_tangent_basis;
+use crate::factor_graph::variable::Variable;
+use crate::optimizer::autodiff::Dual;
+use crate::optimizer::block_sparse::BlockSparseMatrix;
+use crate::optimizer::linear_system::{add_to_H_b, get_tangent_offsets};
+use nalgebra::{DMatrix, DVector, Quaternion, UnitQuaternion, Vector3};
+
+// the step of the central differences for the derivatives of user-defined variables' contents
+const USER_DEFINED_STEP: f64 = 1e-6;
+
+pub fn update_H_b(
+    H: &mut BlockSparseMatrix,
+    b: &mut DVector<f64>,
+    factor: &Factor,
+    residual: &CustomResidual,
+    vars: &[&Variable],
+) {
+    let jacobi = calc_jacobian(factor, residual, vars);
+    let err = calc_error(factor, residual, vars);
+    add_to_H_b(H, b, &factor.information_matrix.content, &jacobi, &err, vars);
+}
+
+pub fn calc_error(factor: &Factor, residual: &CustomResidual, vars: &[&Variable]) -> Vec<f64> {
+    let contents: Vec<Vec<Dual>> = vars.iter().map(|var| seed_content(var, None)).collect();
+    let err = residual.evaluate(&contents, &factor.constraint);
+    check_dimension(factor, err.len());
+    err.iter().map(|d| d.value).collect()
+}
+
+/// Calculates the Jacobian with respect to the corrections of the variables, seeding one tangent direction of one
+/// variable per evaluation of the residual.
+pub fn calc_jacobian(factor: &Factor, residual: &CustomResidual, vars: &[&Variable]) -> DMatrix<f64> {
+    let offsets = get_tangent_offsets(vars);
+    let mut jacobian = DMatrix::zeros(factor.information_matrix.content.nrows(), offsets[vars.len()]);
+    for (i, var) in vars.iter().enumerate() {
+        for k in 0..var.get_parameterization().tangent_dim() {
+            let contents: Vec<Vec<Dual>> = vars
+                .iter()
+                .enumerate()
+                .map(|(j, var)| seed_content(var, if i == j { Some(k) } else { None }))
+                .collect();
+            let err = residual.evaluate(&contents, &factor.constraint);
+            check_dimension(factor, err.len());
+            for (row, d) in err.iter().enumerate() {
+                jacobian[(row, offsets[i] + k)] = d.derivative;
+            }
+        }
+    }
+    jacobian
+}
+
+fn check_dimension(factor: &Factor, err_dim: usize) {
+    let expected_dim = factor.information_matrix.content.nrows();
+    if err_dim != expected_dim {
+        panic!(
+            "Residual of factor {} has {} entries, but its information matrix has {} rows.",
+            factor.id, err_dim, expected_dim
+        );
+    }
+}
+
+// Returns the variable's content as dual numbers, with normalized quaternions. If a tangent direction is given, the
+// derivatives are the ones of the content after applying a correction along that direction, i.e. of the variable's
+// parameterization at zero.
+fn seed_content(var: &Variable, direction: Option<usize>) -> Vec<Dual> {
+    let mut content = var.get_content();
+    let mut derivatives = vec![0.0; content.len()];
+    if let Variable::Vehicle3D(_) | Variable::VehicleSim3(_) | Variable::Extrinsic3D(_) | Variable::Anchor3D(_) = var {
+        let rotation = UnitQuaternion::from_quaternion(Quaternion::new(content[6], content[3], content[4], content[5]));
+        content[3..7].copy_from_slice(rotation.coords.as_slice());
+        match direction {
+            // the translation is corrected in the local frame
+            Some(k) if k < 3 => derivatives[..3].copy_from_slice((rotation * Vector3::ith(k, 1.0)).as_slice()),
+            // the scale of a Sim(3) pose is multiplied by exp(sigma)
+            Some(6) => derivatives[7] = content[7],
+            // the rotation is corrected from the right by a quaternion with vector part v and w = 1 up to second
+            // order, i.e. the derivative is q * (e_k, 0)
+            Some(k) => {
+                let derivative = rotation.quaternion() * Quaternion::from_imag(Vector3::ith(k - 3, 1.0));
+                derivatives[3..7].copy_from_slice(derivative.coords.as_slice());
+            }
+            None => (),
+        }
+    } else if let Variable::Plane3D(_) = var {
+        let normal = Vector3::new(content[0], content[1], content[2]).normalize();
+        content[..3].copy_from_slice(normal.as_slice());
+        match direction {
+            // the normal is rotated along its tangent basis, the distance is corrected additively
+            Some(k) if k < 2 => derivatives[..3].copy_from_slice(get_plane_tangent_basis(&normal)[k].as_slice()),
+            Some(_) => derivatives[3] = 1.0,
+            None => (),
+        }
+    } else if let (Variable::UserDefined(_), Some(k)) = (var, direction) {
+        // the manifold of user-defined variables is unknown, so the derivative is approximated by central differences
+        let parameterization = var.get_parameterization();
+        let mut correction = vec![0.0; parameterization.tangent_dim()];
+        correction[k] = USER_DEFINED_STEP;
+        let forward = parameterization.plus(&content, &correction);
+        correction[k] = -USER_DEFINED_STEP;
+        let backward = parameterization.plus(&content, &correction);
+        for (derivative, (f, b)) in derivatives.iter_mut().zip(forward.iter().zip(backward.iter())) {
+            *derivative = (f - b) / (2.0 * USER_DEFINED_STEP);
+        }
+    } else if let Some(k) = direction {
+        derivatives[k] = 1.0;
+    }
+    content
+        .iter()
+        .zip(derivatives.iter())
+        .map(|(value, derivative)| Dual::new(*value, *derivative))
+        .collect()
+}
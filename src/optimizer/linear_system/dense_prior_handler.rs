@@ -0,0 +1,94 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Dense priors on several variables, e.g. as left by marginalizing out their neighbours.
+//!
+//! The error of a dense prior is the concatenation of each variable's error with respect to its part of the
+//! constraint, which is the one of a Position2D or Position3D factor for vehicle poses and the difference of the
+//! contents otherwise. Its Jacobian is therefore block-diagonal, while its information matrix is dense.
+
+#![allow(non_snake_case)]
+
+use crate::factor_graph::factor::{Factor, FactorType, InformationMatrix};
+use crate::factor_graph::variable::Variable;
+use crate::optimizer::block_sparse::BlockSparseMatrix;
+use crate::optimizer::linear_system::{add_to_H_b, get_tangent_offsets, pos2d_handler, pos3d_handler};
+use nalgebra::{DMatrix, DVector};
+
+pub fn update_H_b(H: &mut BlockSparseMatrix, b: &mut DVector<f64>, factor: &Factor, vars: &[&Variable]) {
+    let jacobi = calc_jacobian(factor, vars);
+    let err = calc_error(factor, vars);
+    add_to_H_b(H, b, &factor.information_matrix.content, &jacobi, &err, vars);
+}
+
+pub fn calc_error(factor: &Factor, vars: &[&Variable]) -> Vec<f64> {
+    vars.iter()
+        .zip(split_constraint(factor, vars))
+        .flat_map(|(var, constraint)| match var {
+            Variable::Vehicle2D(var) => pos2d_handler::calc_error(&get_position_factor(factor, constraint), var),
+            Variable::Vehicle3D(var) => pos3d_handler::calc_error(&get_position_factor(factor, constraint), var),
+            _ => var
+                .get_content()
+                .iter()
+                .zip(constraint)
+                .map(|(content, prior)| content - prior)
+                .collect(),
+        })
+        .collect()
+}
+
+/// Calculates the block-diagonal Jacobian with respect to the corrections of all variables.
+pub fn calc_jacobian(factor: &Factor, vars: &[&Variable]) -> DMatrix<f64> {
+    let offsets = get_tangent_offsets(vars);
+    let dim = offsets[vars.len()];
+    let mut jacobian = DMatrix::zeros(dim, dim);
+    for (i, (var, constraint)) in vars.iter().zip(split_constraint(factor, vars)).enumerate() {
+        let block = match var {
+            Variable::Vehicle2D(_) => pos2d_handler::calc_jacobian(&get_position_factor(factor, constraint)),
+            Variable::Vehicle3D(var) => pos3d_handler::calc_jacobian(&get_position_factor(factor, constraint), var),
+            _ => DMatrix::identity(offsets[i + 1] - offsets[i], offsets[i + 1] - offsets[i]),
+        };
+        jacobian
+            .index_mut((offsets[i]..offsets[i + 1], offsets[i]..offsets[i + 1]))
+            .copy_from(&block);
+    }
+    jacobian
+}
+
+// returns each variable's part of the constraint
+fn split_constraint<'a>(factor: &'a Factor, vars: &[&Variable]) -> Vec<&'a [f64]> {
+    let mut start = 0;
+    vars.iter()
+        .map(|var| {
+            let len = var.get_content().len();
+            start += len;
+            &factor.constraint[start - len..start]
+        })
+        .collect()
+}
+
+// returns a prior on a single vehicle pose, whose information matrix is not used
+fn get_position_factor(factor: &Factor, constraint: &[f64]) -> Factor {
+    Factor {
+        id: factor.id,
+        factor_type: if constraint.len() == 3 {
+            FactorType::Position2D
+        } else {
+            FactorType::Position3D
+        },
+        constraint: constraint.to_vec(),
+        information_matrix: InformationMatrix {
+            content: DMatrix::zeros(0, 0),
+        },
+        additional_variables: vec![],
+    }
+}
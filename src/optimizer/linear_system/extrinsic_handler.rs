@@ -0,0 +1,194 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Observations of stationary variables by a sensor whose pose in the vehicle's frame, its extrinsic calibration,
+//! is estimated jointly with the vehicle's poses.
+//!
+//! The sensor's pose in the world frame is the composition of the vehicle's pose and the extrinsic calibration. The
+//! error is the difference between the position of the observed variable in the sensor's frame and the measured
+//! one. The Jacobians are calculated with automatic differentiation.
+
+#![allow(non_snake_case)]
+
+use crate::factor_graph::factor::{CustomResidual, Factor, FactorType};
+use crate::factor_graph::variable::Variable;
+use crate::optimizer::autodiff::{rotate_inverse, Dual};
+use crate::optimizer::block_sparse::BlockSparseMatrix;
+use crate::optimizer::linear_system::custom_handler;
+use nalgebra::{DMatrix, DVector};
+
+pub fn update_H_b(H: &mut BlockSparseMatrix, b: &mut DVector<f64>, factor: &Factor, vars: &[&Variable]) {
+    custom_handler::update_H_b(H, b, factor, &get_residual(factor), vars);
+}
+
+pub fn calc_error(factor: &Factor, vars: &[&Variable]) -> Vec<f64> {
+    custom_handler::calc_error(factor, &get_residual(factor), vars)
+}
+
+pub fn calc_jacobian(factor: &Factor, vars: &[&Variable]) -> DMatrix<f64> {
+    custom_handler::calc_jacobian(factor, &get_residual(factor), vars)
+}
+
+fn get_residual(factor: &Factor) -> CustomResidual {
+    match factor.factor_type {
+        FactorType::ExtrinsicObservation2D => CustomResidual::new("ExtrinsicObservation2D", calc_residual_2d),
+        _ => CustomResidual::new("ExtrinsicObservation3D", calc_residual_3d),
+    }
+}
+
+// expects the contents of the vehicle's pose, the observed position and the sensor's pose in the vehicle's frame
+fn calc_residual_2d(contents: &[Vec<Dual>], constraint: &[f64]) -> Vec<Dual> {
+    let (pose, position, extrinsic) = (&contents[0], &contents[1], &contents[2]);
+    let (sin, cos) = (pose[2].sin(), pose[2].cos());
+    let sensor_x = pose[0] + cos * extrinsic[0] - sin * extrinsic[1];
+    let sensor_y = pose[1] + sin * extrinsic[0] + cos * extrinsic[1];
+    let sensor_rotation = pose[2] + extrinsic[2];
+    let (sin, cos) = (sensor_rotation.sin(), sensor_rotation.cos());
+    let (dx, dy) = (position[0] - sensor_x, position[1] - sensor_y);
+    vec![cos * dx + sin * dy - constraint[0], cos * dy - sin * dx - constraint[1]]
+}
+
+// expects the contents of the vehicle's pose, the observed position and the sensor's pose in the vehicle's frame
+fn calc_residual_3d(contents: &[Vec<Dual>], constraint: &[f64]) -> Vec<Dual> {
+    let (pose, position, extrinsic) = (&contents[0], &contents[1], &contents[2]);
+    let delta: Vec<Dual> = (0..3).map(|k| position[k] - pose[k]).collect();
+    let body_position = rotate_inverse(&[pose[3], pose[4], pose[5], pose[6]], &delta);
+    let delta: Vec<Dual> = (0..3).map(|k| body_position[k] - extrinsic[k]).collect();
+    let sensor_position = rotate_inverse(&[extrinsic[3], extrinsic[4], extrinsic[5], extrinsic[6]], &delta);
+    (0..3).map(|k| sensor_position[k] - constraint[k]).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::factor_graph::factor::FactorId;
+    use crate::factor_graph::variable::VariableId;
+    use crate::optimizer::handler_check::{build_edge, build_factor_graph, check_factor};
+    use crate::optimizer::linear_system::iso3d_gradients::get_isometry;
+    use crate::optimizer::{optimize, total_chi2};
+    use approx::assert_relative_eq;
+    use nalgebra::Point3;
+
+    const IDENTITY_2X2: [f64; 4] = [1.0, 0.0, 0.0, 1.0];
+    const IDENTITY_3X3: [f64; 9] = [1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0];
+
+    // returns the position observed by a sensor mounted with the given extrinsic at the given pose
+    fn observe_2d(pose: &[f64], extrinsic: &[f64], position: &[f64]) -> Vec<f64> {
+        let (sin, cos) = pose[2].sin_cos();
+        let sensor_x = pose[0] + cos * extrinsic[0] - sin * extrinsic[1];
+        let sensor_y = pose[1] + sin * extrinsic[0] + cos * extrinsic[1];
+        let (sin, cos) = (pose[2] + extrinsic[2]).sin_cos();
+        let (dx, dy) = (position[0] - sensor_x, position[1] - sensor_y);
+        vec![cos * dx + sin * dy, cos * dy - sin * dx]
+    }
+
+    #[test]
+    fn test_handlers() {
+        let (pose, position, extrinsic) = (vec![0.3, -0.5, 0.4], vec![2.1, 1.4], vec![0.2, 0.1, -0.3]);
+        let observation = observe_2d(&pose, &extrinsic, &position);
+        let factor_graph = build_factor_graph(
+            &[
+                ("Vehicle2D", pose),
+                ("Landmark2D", position),
+                ("Extrinsic2D", extrinsic),
+            ],
+            vec![build_edge(
+                "ExtrinsicObservation2D",
+                &[0, 1, 2],
+                observation,
+                IDENTITY_2X2.to_vec(),
+            )],
+            &[],
+        );
+        assert_eq!(check_factor(&factor_graph, FactorId(0), 1e-6), Ok(()));
+
+        let pose = vec![0.3, -0.2, 0.1, 0.1, -0.2, 0.3, 0.927362];
+        let extrinsic = vec![0.2, 0.0, 0.5, -0.5, 0.5, -0.5, 0.5];
+        let sensor = get_isometry(&pose) * get_isometry(&extrinsic);
+        let observation = sensor.inverse_transform_point(&Point3::new(2.0, 1.0, -0.5));
+        let factor_graph = build_factor_graph(
+            &[
+                ("Vehicle3D", pose),
+                ("Landmark3D", vec![2.0, 1.0, -0.5]),
+                ("Extrinsic3D", extrinsic),
+            ],
+            vec![build_edge(
+                "ExtrinsicObservation3D",
+                &[0, 1, 2],
+                observation.coords.as_slice().to_vec(),
+                IDENTITY_3X3.to_vec(),
+            )],
+            &[],
+        );
+        assert_eq!(check_factor(&factor_graph, FactorId(0), 1e-6), Ok(()));
+    }
+
+    #[test]
+    fn test_shared_extrinsic_is_estimated() {
+        // a vehicle drives along an arc and observes known landmarks with a sensor mounted at an unknown pose
+        let extrinsic = [0.3, -0.1, 0.2];
+        let landmarks = [[3.0, 1.0], [1.0, 4.0], [-2.0, 2.5], [4.0, -1.5]];
+        let poses: Vec<[f64; 3]> = (0..6)
+            .map(|k| {
+                let angle = 0.3 * k as f64;
+                [2.0 * angle.sin(), 2.0 - 2.0 * angle.cos(), angle]
+            })
+            .collect();
+        let mut vertices: Vec<(&str, Vec<f64>)> = poses
+            .iter()
+            .enumerate()
+            .map(|(k, pose)| {
+                (
+                    "Vehicle2D",
+                    vec![pose[0] + 0.1 * k as f64, pose[1] - 0.05, pose[2] + 0.05],
+                )
+            })
+            .collect();
+        vertices[0].1 = poses[0].to_vec();
+        let landmark_id = vertices.len();
+        vertices.extend(landmarks.iter().map(|landmark| ("Landmark2D", landmark.to_vec())));
+        let extrinsic_id = vertices.len();
+        vertices.push(("Extrinsic2D", vec![0.0, 0.0, 0.0]));
+
+        let mut edges = vec![];
+        for (k, pose) in poses.iter().enumerate() {
+            if k > 0 {
+                let previous = poses[k - 1];
+                let (sin, cos) = previous[2].sin_cos();
+                let (dx, dy) = (pose[0] - previous[0], pose[1] - previous[1]);
+                let odometry = vec![cos * dx + sin * dy, cos * dy - sin * dx, pose[2] - previous[2]];
+                edges.push(build_edge("Odometry2D", &[k - 1, k], odometry, IDENTITY_3X3.to_vec()));
+            }
+            for (l, landmark) in landmarks.iter().enumerate() {
+                let observation = observe_2d(pose, &extrinsic, landmark);
+                edges.push(build_edge(
+                    "ExtrinsicObservation2D",
+                    &[k, landmark_id + l, extrinsic_id],
+                    observation,
+                    IDENTITY_2X2.to_vec(),
+                ));
+            }
+        }
+        let mut fixed: Vec<usize> = (landmark_id..extrinsic_id).collect();
+        fixed.push(0);
+        let factor_graph = build_factor_graph(&vertices, edges, &fixed);
+        assert!(total_chi2(&factor_graph) > 1e-2);
+        optimize(&factor_graph, 20);
+        assert!(total_chi2(&factor_graph) < 1e-12);
+        let estimate = factor_graph
+            .get_var_by_id(VariableId(extrinsic_id))
+            .unwrap()
+            .get_content();
+        for (actual, expected) in estimate.iter().zip(&extrinsic) {
+            assert_relative_eq!(actual, expected, epsilon = 1e-6);
+        }
+    }
+}
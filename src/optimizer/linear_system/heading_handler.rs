@@ -0,0 +1,139 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Heading-only priors on vehicle poses, e.g. from a compass or a magnetometer.
+//!
+//! The constraint is the measured heading, i.e. the rotation of a 2D pose or the yaw of a 3D pose, which is the
+//! angle of its x axis projected onto the xy plane of the world frame. The error is the difference between the
+//! vehicle's and the measured heading, normalized to [-PI, PI), its information matrix the inverse of the heading's
+//! 1x1 covariance. The position and, in 3D, the roll and pitch of the vehicle do not influence the error. The yaw is
+//! undefined if the vehicle's x axis is vertical.
+
+#![allow(non_snake_case)]
+
+use crate::factor_graph::factor::Factor;
+use crate::factor_graph::variable::Variable;
+use crate::optimizer::block_sparse::BlockSparseMatrix;
+use crate::optimizer::linear_system::add_to_H_b;
+use crate::optimizer::linear_system::iso3d_gradients::get_isometry;
+use nalgebra::{DMatrix, DVector, Vector3};
+use std::f64::consts::PI;
+
+pub fn update_H_b(H: &mut BlockSparseMatrix, b: &mut DVector<f64>, factor: &Factor, vars: &[&Variable]) {
+    let jacobi = calc_jacobian(vars);
+    let err = calc_error(factor, vars);
+    add_to_H_b(H, b, &factor.information_matrix.content, &jacobi, &err, vars);
+}
+
+pub fn calc_error(factor: &Factor, vars: &[&Variable]) -> Vec<f64> {
+    vec![(get_heading(vars[0]) - factor.constraint[0] + PI).rem_euclid(2.0 * PI) - PI]
+}
+
+/// Calculates the Jacobian with respect to the vehicle.
+///
+/// In 3D, the rotational correction v rotates the vehicle's x axis x by R * ([2v] × e_x) up to first order, where
+/// R is the vehicle's rotation matrix, which changes the yaw by the component of this rotation orthogonal to the
+/// projection of x.
+pub fn calc_jacobian(vars: &[&Variable]) -> DMatrix<f64> {
+    match vars[0] {
+        Variable::Vehicle2D(_) => DMatrix::from_row_slice(1, 3, &[0.0, 0.0, 1.0]),
+        Variable::Vehicle3D(var) => {
+            let rotation = get_isometry(&*var.pose.borrow()).rotation.to_rotation_matrix();
+            let x_axis = rotation * Vector3::x();
+            let squared_norm = x_axis.x * x_axis.x + x_axis.y * x_axis.y;
+            let mut jacobian = DMatrix::zeros(1, 6);
+            for k in 0..3 {
+                let derivative = rotation * (2.0 * Vector3::<f64>::ith(k, 1.0)).cross(&Vector3::x());
+                jacobian[(0, 3 + k)] = (x_axis.x * derivative.y - x_axis.y * derivative.x) / squared_norm;
+            }
+            jacobian
+        }
+        _ => unreachable!("No valid edge."),
+    }
+}
+
+/// Returns the heading of a 2D or 3D vehicle pose.
+pub fn get_heading(var: &Variable) -> f64 {
+    match var {
+        Variable::Vehicle2D(var) => var.pose.borrow()[2],
+        Variable::Vehicle3D(var) => {
+            let x_axis = get_isometry(&*var.pose.borrow()).rotation * Vector3::x();
+            x_axis.y.atan2(x_axis.x)
+        }
+        _ => unreachable!("No valid edge."),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::factor_graph::factor::FactorId;
+    use crate::factor_graph::variable::VariableId;
+    use crate::optimizer::handler_check::{build_edge, build_factor_graph, check_factor};
+    use crate::optimizer::{optimize, total_chi2};
+    use crate::parser::model::Edge;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn test_handlers() {
+        let content = vec![0.3, -0.2, 0.1, 0.1, -0.2, 0.3, 0.927362];
+        let x_axis = get_isometry(&content).rotation * Vector3::x();
+        for (vertex_type, content, heading) in [
+            ("Vehicle2D", vec![0.3, -0.5, 3.0], 3.0 - 2.0 * PI),
+            ("Vehicle3D", content, x_axis.y.atan2(x_axis.x)),
+        ] {
+            let factor_graph = build_factor_graph(
+                &[(vertex_type, content)],
+                vec![build_edge(
+                    &vertex_type.replace("Vehicle", "Heading"),
+                    &[0],
+                    vec![heading],
+                    vec![1.0],
+                )],
+                &[],
+            );
+            assert_eq!(check_factor(&factor_graph, FactorId(0), 1e-6), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_compass_completes_range_localization() {
+        // the ranges to two beacons only determine the vehicle's position
+        let pose: [f64; 3] = [1.0, 2.0, 0.7];
+        let beacons: [[f64; 2]; 2] = [[4.0, 2.0], [1.0, -1.0]];
+        let mut edges: Vec<Edge> = beacons
+            .iter()
+            .enumerate()
+            .map(|(k, beacon)| {
+                let range = (beacon[0] - pose[0]).hypot(beacon[1] - pose[1]);
+                build_edge("Range2D", &[0, k + 1], vec![range], vec![1.0])
+            })
+            .collect();
+        edges.push(build_edge("Heading2D", &[0], vec![pose[2]], vec![1.0]));
+        let factor_graph = build_factor_graph(
+            &[
+                ("Vehicle2D", vec![1.3, 2.2, 0.2]),
+                ("Landmark2D", beacons[0].to_vec()),
+                ("Landmark2D", beacons[1].to_vec()),
+            ],
+            edges,
+            &[1, 2],
+        );
+        assert!(total_chi2(&factor_graph) > 1e-2);
+        optimize(&factor_graph, 20);
+        assert!(total_chi2(&factor_graph) < 1e-12);
+        let estimate = factor_graph.get_var_by_id(VariableId(0)).unwrap().get_content();
+        for (actual, expected) in estimate.iter().zip(&pose) {
+            assert_relative_eq!(actual, expected, epsilon = 1e-6);
+        }
+    }
+}
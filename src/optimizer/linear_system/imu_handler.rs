@@ -0,0 +1,199 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Preintegrated IMU measurements between two vehicle poses in 3D, see
+//! [imu](../../../factor_graph/imu/index.html).
+//!
+//! The error is [position, velocity, rotation, acc_bias, gyro_bias]. The position and velocity errors are the
+//! differences between the motion predicted by the estimates in the frame of the first pose and the preintegrated
+//! motion after the first-order correction for the change of the biases. Like for odometry, the rotation error is the
+//! vector part of the error quaternion with non-negative w. The bias errors are the changes of the biases between both
+//! poses. The Jacobians are calculated with automatic differentiation.
+
+#![allow(non_snake_case)]
+
+use crate::factor_graph::factor::{CustomResidual, Factor};
+use crate::factor_graph::imu::{
+    BIAS, BIAS_JACOBIANS, DELTA_POSITION, DELTA_ROTATION, DELTA_TIME, DELTA_VELOCITY, GRAVITY,
+};
+use crate::factor_graph::variable::Variable;
+use crate::optimizer::autodiff::{quaternion_conjugate, quaternion_product, rotate_inverse, Dual, DualQuaternion};
+use crate::optimizer::block_sparse::BlockSparseMatrix;
+use crate::optimizer::linear_system::custom_handler;
+use nalgebra::{DMatrix, DVector};
+
+pub fn update_H_b(H: &mut BlockSparseMatrix, b: &mut DVector<f64>, factor: &Factor, vars: &[&Variable]) {
+    custom_handler::update_H_b(H, b, factor, &get_residual(), vars);
+}
+
+pub fn calc_error(factor: &Factor, vars: &[&Variable]) -> Vec<f64> {
+    custom_handler::calc_error(factor, &get_residual(), vars)
+}
+
+pub fn calc_jacobian(factor: &Factor, vars: &[&Variable]) -> DMatrix<f64> {
+    custom_handler::calc_jacobian(factor, &get_residual(), vars)
+}
+
+fn get_residual() -> CustomResidual {
+    CustomResidual::new("ImuPreintegration3D", calc_residual)
+}
+
+// expects the contents of the poses, velocities and biases at both ends, in the order of get_factor_var_indices
+fn calc_residual(contents: &[Vec<Dual>], constraint: &[f64]) -> Vec<Dual> {
+    let (pose_i, pose_j) = (&contents[0], &contents[1]);
+    let (velocity_i, velocity_j) = (&contents[2], &contents[3]);
+    let (bias_i, bias_j) = (&contents[4], &contents[5]);
+    let dt = constraint[DELTA_TIME];
+    let gravity = &constraint[GRAVITY..GRAVITY + 3];
+    let bias_change: Vec<Dual> = (0..6).map(|k| bias_i[k] - constraint[BIAS + k]).collect();
+    // returns the product of the k-th Jacobian of the constraint with the bias change of the accelerometer or gyroscope
+    let correct = |k: usize, bias_offset: usize| -> Vec<Dual> {
+        let jacobian = &constraint[BIAS_JACOBIANS + 9 * k..BIAS_JACOBIANS + 9 * (k + 1)];
+        (0..3)
+            .map(|row| {
+                (0..3).fold(Dual::constant(0.0), |sum, col| {
+                    sum + bias_change[bias_offset + col] * jacobian[3 * col + row]
+                })
+            })
+            .collect()
+    };
+    let (position_acc, position_gyro) = (correct(0, 0), correct(1, 3));
+    let (velocity_acc, velocity_gyro) = (correct(2, 0), correct(3, 3));
+    let rotation_gyro = correct(4, 3);
+
+    let rotation_i = [pose_i[3], pose_i[4], pose_i[5], pose_i[6]];
+    let position_delta: Vec<Dual> = (0..3)
+        .map(|k| pose_j[k] - pose_i[k] - velocity_i[k] * dt - 0.5 * gravity[k] * dt * dt)
+        .collect();
+    let velocity_delta: Vec<Dual> = (0..3)
+        .map(|k| velocity_j[k] - velocity_i[k] - gravity[k] * dt)
+        .collect();
+    let local_position = rotate_inverse(&rotation_i, &position_delta);
+    let local_velocity = rotate_inverse(&rotation_i, &velocity_delta);
+    let mut err: Vec<Dual> = (0..3)
+        .map(|k| local_position[k] - constraint[DELTA_POSITION + k] - position_acc[k] - position_gyro[k])
+        .chain((0..3).map(|k| local_velocity[k] - constraint[DELTA_VELOCITY + k] - velocity_acc[k] - velocity_gyro[k]))
+        .collect();
+
+    let measured_rotation = quaternion_product(
+        &constant_quaternion(&constraint[DELTA_ROTATION..DELTA_ROTATION + 4]),
+        &exp(&rotation_gyro),
+    );
+    let rotation_j = [pose_j[3], pose_j[4], pose_j[5], pose_j[6]];
+    let rotation_error = quaternion_product(
+        &quaternion_conjugate(&measured_rotation),
+        &quaternion_product(&quaternion_conjugate(&rotation_i), &rotation_j),
+    );
+    let sign = if rotation_error[3].value < 0.0 { -1.0 } else { 1.0 };
+    err.extend(rotation_error[..3].iter().map(|v| *v * sign));
+    err.extend((0..6).map(|k| bias_j[k] - bias_i[k]));
+    err
+}
+
+fn constant_quaternion(coords: &[f64]) -> DualQuaternion {
+    [
+        Dual::constant(coords[0]),
+        Dual::constant(coords[1]),
+        Dual::constant(coords[2]),
+        Dual::constant(coords[3]),
+    ]
+}
+
+// returns the unit quaternion of the rotation vector, whose square root is avoided close to zero
+fn exp(rotation_vector: &[Dual]) -> DualQuaternion {
+    let squared_angle = rotation_vector.iter().fold(Dual::constant(0.0), |sum, v| sum + *v * *v);
+    if squared_angle.value < 1e-12 {
+        return [
+            rotation_vector[0] * 0.5,
+            rotation_vector[1] * 0.5,
+            rotation_vector[2] * 0.5,
+            Dual::constant(1.0) - squared_angle * 0.125,
+        ];
+    }
+    let angle = squared_angle.sqrt();
+    let scale = (angle * 0.5).sin() / angle;
+    [
+        rotation_vector[0] * scale,
+        rotation_vector[1] * scale,
+        rotation_vector[2] * scale,
+        (angle * 0.5).cos(),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::factor_graph::factor::FactorId;
+    use crate::factor_graph::imu::{ImuPreintegrator, ImuStateIds};
+    use crate::factor_graph::variable::VariableId;
+    use crate::optimizer::handler_check::{build_factor_graph, check_factor};
+    use nalgebra::{Isometry3, Quaternion, Translation3, UnitQuaternion, Vector3};
+
+    fn get_content(pose: &Isometry3<f64>) -> Vec<f64> {
+        let (t, q) = (pose.translation.vector, pose.rotation.coords);
+        vec![t.x, t.y, t.z, q[0], q[1], q[2], q[3]]
+    }
+
+    #[test]
+    fn test_handler() {
+        let bias = [0.02, -0.01, 0.03, 0.001, -0.002, 0.003];
+        let mut preintegrator = ImuPreintegrator::new(bias, Default::default());
+        for k in 0..50 {
+            let t = k as f64 * 0.01;
+            preintegrator.integrate([0.3 * t, -0.2, 9.7], [0.1, 0.4 * t, -0.2], 0.01);
+        }
+
+        // the second state is predicted from the first one by the preintegrated motion
+        let constraint = preintegrator.constraint();
+        let (dt, gravity) = (
+            constraint[DELTA_TIME],
+            Vector3::from_column_slice(&constraint[GRAVITY..GRAVITY + 3]),
+        );
+        let pose_i = Isometry3::from_parts(
+            Translation3::new(0.3, -0.2, 0.1),
+            UnitQuaternion::from_scaled_axis(Vector3::new(0.2, -0.4, 0.6)),
+        );
+        let velocity_i = Vector3::new(0.5, -0.3, 0.2);
+        let position_j = pose_i.translation.vector
+            + velocity_i * dt
+            + 0.5 * gravity * dt * dt
+            + pose_i.rotation * Vector3::from_column_slice(&constraint[DELTA_POSITION..DELTA_POSITION + 3]);
+        let velocity_j = velocity_i
+            + gravity * dt
+            + pose_i.rotation * Vector3::from_column_slice(&constraint[DELTA_VELOCITY..DELTA_VELOCITY + 3]);
+        let q = &constraint[DELTA_ROTATION..DELTA_ROTATION + 4];
+        let rotation_j = pose_i.rotation * UnitQuaternion::from_quaternion(Quaternion::new(q[3], q[0], q[1], q[2]));
+        let pose_j = Isometry3::from_parts(Translation3::from(position_j), rotation_j);
+
+        let mut factor_graph = build_factor_graph(
+            &[
+                ("Vehicle3D", get_content(&pose_i)),
+                ("Vehicle3D", get_content(&pose_j)),
+                ("Velocity3D", velocity_i.as_slice().to_vec()),
+                ("Velocity3D", velocity_j.as_slice().to_vec()),
+                ("ImuBias", bias.to_vec()),
+                ("ImuBias", bias.to_vec()),
+            ],
+            vec![],
+            &[],
+        );
+        let states = |pose, velocity, bias| ImuStateIds {
+            pose: VariableId(pose),
+            velocity: VariableId(velocity),
+            bias: VariableId(bias),
+        };
+        factor_graph
+            .add_imu_factor(states(0, 2, 4), states(1, 3, 5), &preintegrator)
+            .unwrap();
+        assert_eq!(check_factor(&factor_graph, FactorId(0), 1e-6), Ok(()));
+    }
+}
@@ -0,0 +1,202 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Pose measurements taken between the times of two poses, e.g. by a GPS which is not synchronized with the
+//! odometry.
+//!
+//! The measured pose is compared with the pose interpolated between the poses before and after the measurement at
+//! the fraction of the time between them, with the error of a Position2D or Position3D factor. Positions and 2D
+//! rotations are interpolated linearly, 3D rotations by SLERP. The Jacobians are calculated with automatic
+//! differentiation.
+
+#![allow(non_snake_case)]
+
+use crate::factor_graph::factor::{CustomResidual, Factor, FactorType};
+use crate::factor_graph::variable::Variable;
+use crate::optimizer::autodiff::{quaternion_conjugate, quaternion_product, rotate_inverse, Dual, DualQuaternion};
+use crate::optimizer::block_sparse::BlockSparseMatrix;
+use crate::optimizer::linear_system::custom_handler;
+use nalgebra::{DMatrix, DVector, Quaternion, UnitQuaternion};
+use std::f64::consts::PI;
+
+pub fn update_H_b(H: &mut BlockSparseMatrix, b: &mut DVector<f64>, factor: &Factor, vars: &[&Variable]) {
+    custom_handler::update_H_b(H, b, factor, &get_residual(factor), vars);
+}
+
+pub fn calc_error(factor: &Factor, vars: &[&Variable]) -> Vec<f64> {
+    custom_handler::calc_error(factor, &get_residual(factor), vars)
+}
+
+pub fn calc_jacobian(factor: &Factor, vars: &[&Variable]) -> DMatrix<f64> {
+    custom_handler::calc_jacobian(factor, &get_residual(factor), vars)
+}
+
+/// Returns the pose at the given fraction of the time between the 2D or 3D poses before and after it.
+pub(crate) fn interpolate(before: &[f64], after: &[f64], fraction: f64) -> Vec<f64> {
+    let to_dual = |pose: &[f64]| pose.iter().copied().map(Dual::constant).collect::<Vec<_>>();
+    let interpolated = match before.len() {
+        3 => interpolate_2d(&to_dual(before), &to_dual(after), fraction),
+        _ => {
+            let (position, rotation) = interpolate_3d(&to_dual(before), &to_dual(after), fraction);
+            position.into_iter().chain(rotation.iter().copied()).collect()
+        }
+    };
+    interpolated.iter().map(|v| v.value).collect()
+}
+
+fn get_residual(factor: &Factor) -> CustomResidual {
+    match factor.factor_type {
+        FactorType::InterpolatedPose2D => CustomResidual::new("InterpolatedPose2D", calc_residual_2d),
+        _ => CustomResidual::new("InterpolatedPose3D", calc_residual_3d),
+    }
+}
+
+// expects the contents of the poses before and after the measurement
+fn calc_residual_2d(contents: &[Vec<Dual>], constraint: &[f64]) -> Vec<Dual> {
+    let pose = interpolate_2d(&contents[0], &contents[1], constraint[3]);
+    let (sin, cos) = constraint[2].sin_cos();
+    let (dx, dy) = (pose[0] - constraint[0], pose[1] - constraint[1]);
+    vec![
+        dx * cos + dy * sin,
+        dy * cos - dx * sin,
+        normalize_rotation(pose[2] - constraint[2]),
+    ]
+}
+
+// expects the contents of the poses before and after the measurement
+fn calc_residual_3d(contents: &[Vec<Dual>], constraint: &[f64]) -> Vec<Dual> {
+    let (position, rotation) = interpolate_3d(&contents[0], &contents[1], constraint[7]);
+    let measured_rotation = get_measured_rotation(constraint);
+    let delta: Vec<Dual> = (0..3).map(|k| position[k] - constraint[k]).collect();
+    let translation_error = rotate_inverse(&measured_rotation, &delta);
+    let rotation_error = quaternion_product(&quaternion_conjugate(&measured_rotation), &rotation);
+    // like for Position3D factors, the quaternion with non-negative w is used
+    let sign = if rotation_error[3].value < 0.0 { -1.0 } else { 1.0 };
+    translation_error
+        .into_iter()
+        .chain(rotation_error[..3].iter().map(|v| *v * sign))
+        .collect()
+}
+
+fn interpolate_2d(before: &[Dual], after: &[Dual], fraction: f64) -> Vec<Dual> {
+    vec![
+        before[0] + (after[0] - before[0]) * fraction,
+        before[1] + (after[1] - before[1]) * fraction,
+        normalize_rotation(before[2] + normalize_rotation(after[2] - before[2]) * fraction),
+    ]
+}
+
+// returns the interpolated position and rotation
+fn interpolate_3d(before: &[Dual], after: &[Dual], fraction: f64) -> (Vec<Dual>, DualQuaternion) {
+    let position = (0..3).map(|k| before[k] + (after[k] - before[k]) * fraction).collect();
+    let rotation_before = [before[3], before[4], before[5], before[6]];
+    let mut delta = quaternion_product(
+        &quaternion_conjugate(&rotation_before),
+        &[after[3], after[4], after[5], after[6]],
+    );
+    // the shorter of both arcs between the rotations is interpolated
+    if delta[3].value < 0.0 {
+        delta.iter_mut().for_each(|v| *v = -*v);
+    }
+    let squared_sin = delta[0] * delta[0] + delta[1] * delta[1] + delta[2] * delta[2];
+    let partial_delta = if squared_sin.value < 1e-18 {
+        // the sine of the partial angle is proportional to the one of the whole angle for small rotations
+        [
+            delta[0] * fraction,
+            delta[1] * fraction,
+            delta[2] * fraction,
+            Dual::constant(1.0),
+        ]
+    } else {
+        let sin = squared_sin.sqrt();
+        let half_angle = sin.atan2(delta[3]) * fraction;
+        let scale = half_angle.sin() / sin;
+        [delta[0] * scale, delta[1] * scale, delta[2] * scale, half_angle.cos()]
+    };
+    (position, quaternion_product(&rotation_before, &partial_delta))
+}
+
+// returns the normalized rotation of the measurement as constant dual quaternion
+fn get_measured_rotation(constraint: &[f64]) -> DualQuaternion {
+    let rotation = UnitQuaternion::from_quaternion(Quaternion::new(
+        constraint[6],
+        constraint[3],
+        constraint[4],
+        constraint[5],
+    ));
+    let coords = rotation.coords;
+    [
+        Dual::constant(coords[0]),
+        Dual::constant(coords[1]),
+        Dual::constant(coords[2]),
+        Dual::constant(coords[3]),
+    ]
+}
+
+fn normalize_rotation(rotation: Dual) -> Dual {
+    rotation - 2.0 * PI * ((rotation.value + PI) / (2.0 * PI)).floor()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::factor_graph::factor::FactorId;
+    use crate::factor_graph::variable::VariableId;
+    use crate::optimizer::handler_check::{build_factor_graph, check_factor};
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn test_handlers() {
+        let (before, after) = (vec![0.3, -0.5, 3.0], vec![1.2, 0.4, -3.0]);
+        let measurement = interpolate(&before, &after, 0.3);
+        let mut factor_graph = build_factor_graph(&[("Vehicle2D", before), ("Vehicle2D", after)], vec![], &[]);
+        factor_graph.set_timestamp(VariableId(0), Some(0.0)).unwrap();
+        factor_graph.set_timestamp(VariableId(1), Some(1.0)).unwrap();
+        factor_graph
+            .add_interpolated_pose_factor(
+                0.3,
+                measurement,
+                vec![1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0].into(),
+            )
+            .unwrap();
+        assert_eq!(check_factor(&factor_graph, FactorId(0), 1e-6), Ok(()));
+
+        let before = vec![0.3, -0.2, 0.1, 0.1, -0.2, 0.3, 0.927362];
+        let after = vec![1.0, 0.5, -0.4, 0.0, 0.3, 0.0, 0.953939];
+        let measurement = interpolate(&before, &after, 0.75);
+        let mut factor_graph = build_factor_graph(&[("Vehicle3D", before), ("Vehicle3D", after)], vec![], &[]);
+        factor_graph.set_timestamp(VariableId(0), Some(0.0)).unwrap();
+        factor_graph.set_timestamp(VariableId(1), Some(2.0)).unwrap();
+        let information: Vec<f64> = (0..36).map(|i| if i % 7 == 0 { 1.0 } else { 0.0 }).collect();
+        factor_graph
+            .add_interpolated_pose_factor(1.5, measurement, information.into())
+            .unwrap();
+        assert_eq!(check_factor(&factor_graph, FactorId(0), 1e-6), Ok(()));
+    }
+
+    #[test]
+    fn test_interpolation() {
+        let before = [1.0, 2.0, 3.0, 0.0, 0.0, 0.0, 1.0];
+        let after = [3.0, 2.0, 1.0, 0.0, 0.0, 0.5_f64.sqrt(), 0.5_f64.sqrt()];
+        let interpolated = interpolate(&before, &after, 0.5);
+        let half_angle = std::f64::consts::PI / 8.0;
+        let expected = [2.0, 2.0, 2.0, 0.0, 0.0, half_angle.sin(), half_angle.cos()];
+        for (actual, expected) in interpolated.iter().zip(&expected) {
+            assert_relative_eq!(actual, expected, epsilon = 1e-12);
+        }
+        // the rotation takes the shorter way across ±π
+        let interpolated = interpolate(&[0.0, 0.0, 3.0], &[2.0, -2.0, -3.0], 0.25);
+        assert_relative_eq!(interpolated[0], 0.5);
+        assert_relative_eq!(interpolated[1], -0.5);
+        assert_relative_eq!(interpolated[2], 3.0 + 0.25 * (2.0 * std::f64::consts::PI - 6.0));
+    }
+}
@@ -0,0 +1,101 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Displacements between two landmarks in 2D or 3D, e.g. from a map prior or a structure prior.
+//!
+//! The constraint is the target's position minus the source's position in the world frame, so the error is linear in
+//! both positions and the Jacobians are the negative and the positive identity.
+
+#![allow(non_snake_case)]
+
+use crate::factor_graph::factor::Factor;
+use crate::factor_graph::variable::Variable;
+use crate::optimizer::block_sparse::BlockSparseMatrix;
+use crate::optimizer::linear_system::add_to_H_b;
+use nalgebra::{DMatrix, DVector};
+
+pub fn update_H_b(H: &mut BlockSparseMatrix, b: &mut DVector<f64>, factor: &Factor, vars: &[&Variable]) {
+    let jacobi = calc_jacobian(vars);
+    let err = calc_error(factor, vars);
+    add_to_H_b(H, b, &factor.information_matrix.content, &jacobi, &err, vars);
+}
+
+pub fn calc_error(factor: &Factor, vars: &[&Variable]) -> Vec<f64> {
+    let (position_i, position_j) = (vars[0].get_content(), vars[1].get_content());
+    (0..factor.constraint.len())
+        .map(|k| position_j[k] - position_i[k] - factor.constraint[k])
+        .collect()
+}
+
+pub fn calc_jacobian(vars: &[&Variable]) -> DMatrix<f64> {
+    let dim = vars[0].get_content().len();
+    let mut jacobian = DMatrix::zeros(dim, 2 * dim);
+    jacobian.columns_mut(0, dim).fill_diagonal(-1.0);
+    jacobian.columns_mut(dim, dim).fill_diagonal(1.0);
+    jacobian
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::factor_graph::factor::FactorId;
+    use crate::factor_graph::variable::VariableId;
+    use crate::factor_graph::FactorGraph;
+    use crate::optimizer::{optimize, total_chi2};
+    use crate::parser::g2o::G2oParser;
+    use crate::parser::Parser;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn test_error_and_jacobian() {
+        let factor_graph: FactorGraph = G2oParser::parse_string_to_model(
+            "VERTEX_XY 0 1.0 2.0\nVERTEX_XY 1 2.5 1.0\nEDGE_POINTXY 0 1 1.0 -1.0 1 0 1",
+        )
+        .unwrap()
+        .into();
+        let vars = [
+            factor_graph.get_var_by_id(VariableId(0)).unwrap(),
+            factor_graph.get_var_by_id(VariableId(1)).unwrap(),
+        ];
+        let factor = factor_graph.get_factor(FactorId(0)).unwrap();
+        assert_eq!(calc_error(factor, &vars), vec![0.5, 0.0]);
+        assert_eq!(
+            calc_jacobian(&vars),
+            DMatrix::from_row_slice(2, 4, &[-1.0, 0.0, 1.0, 0.0, 0.0, -1.0, 0.0, 1.0])
+        );
+    }
+
+    #[test]
+    fn test_structure_prior_completes_observations() {
+        // only the first landmark of a rigid structure is observed, the others are placed by their known offsets
+        let factor_graph: FactorGraph = G2oParser::parse_string_to_model(
+            &[
+                "VERTEX_SE2 0 0.0 0.0 0.0",
+                "FIX 0",
+                "VERTEX_XY 1 1.8 1.3",
+                "VERTEX_XY 2 2.5 0.2",
+                "VERTEX_XY 3 3.3 2.4",
+                "EDGE_SE2_XY 0 1 2.0 1.0 10.0 0.0 10.0",
+                "EDGE_POINTXY 1 2 1.0 -1.0 10.0 0.0 10.0",
+                "EDGE_POINTXY 2 3 0.0 2.0 10.0 0.0 10.0",
+            ]
+            .join("\n"),
+        )
+        .unwrap()
+        .into();
+        optimize(&factor_graph, 5);
+        assert!(total_chi2(&factor_graph) < 1e-12);
+        let position = factor_graph.get_var_by_id(VariableId(3)).unwrap().get_content();
+        assert_relative_eq!(position[0], 3.0, epsilon = 1e-9);
+        assert_relative_eq!(position[1], 2.0, epsilon = 1e-9);
+    }
+}
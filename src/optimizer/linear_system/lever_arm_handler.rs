@@ -0,0 +1,195 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Position priors on vehicle poses in 2D and 3D, which are measured by an antenna at a fixed lever arm in the
+//! vehicle's frame, e.g. a GPS receiver which is not mounted at the vehicle's origin.
+//!
+//! The constraint is the measured position of the antenna in the world frame, followed by the lever arm. The error is
+//! the difference between the antenna's position predicted by the vehicle's pose, i.e. its position plus the lever arm
+//! rotated by its orientation, and the measured position, so that the measurements of a turning vehicle do not bias
+//! its position.
+
+#![allow(non_snake_case)]
+
+use crate::factor_graph::factor::Factor;
+use crate::factor_graph::variable::Variable;
+use crate::optimizer::block_sparse::BlockSparseMatrix;
+use crate::optimizer::linear_system::add_to_H_b;
+use crate::optimizer::linear_system::iso3d_gradients::get_isometry;
+use nalgebra::{DMatrix, DVector, Point3, Vector3};
+
+pub fn update_H_b(H: &mut BlockSparseMatrix, b: &mut DVector<f64>, factor: &Factor, vars: &[&Variable]) {
+    let jacobi = calc_jacobian(factor, vars);
+    let err = calc_error(factor, vars);
+    add_to_H_b(H, b, &factor.information_matrix.content, &jacobi, &err, vars);
+}
+
+pub fn calc_error(factor: &Factor, vars: &[&Variable]) -> Vec<f64> {
+    let dim = get_dim(factor);
+    let antenna = get_antenna_position(&vars[0].get_content(), &factor.constraint[dim..]);
+    (0..dim).map(|k| antenna[k] - factor.constraint[k]).collect()
+}
+
+/// Calculates the Jacobian with respect to the vehicle.
+///
+/// The translational part is the identity in 2D and, since the correction of 3D poses is applied in the vehicle's
+/// frame, its rotation matrix in 3D. Rotational corrections move the antenna around the vehicle's position.
+pub fn calc_jacobian(factor: &Factor, vars: &[&Variable]) -> DMatrix<f64> {
+    let pose = vars[0].get_content();
+    let lever_arm = &factor.constraint[get_dim(factor)..];
+    if pose.len() == 3 {
+        let (sin, cos) = pose[2].sin_cos();
+        DMatrix::from_row_slice(
+            2,
+            3,
+            &[
+                1.0,
+                0.0,
+                -sin * lever_arm[0] - cos * lever_arm[1],
+                0.0,
+                1.0,
+                cos * lever_arm[0] - sin * lever_arm[1],
+            ],
+        )
+    } else {
+        let rotation = get_isometry(&pose).rotation.to_rotation_matrix();
+        let lever_arm = Vector3::new(lever_arm[0], lever_arm[1], lever_arm[2]);
+        let mut jacobian = DMatrix::zeros(3, 6);
+        jacobian.columns_mut(0, 3).copy_from(rotation.matrix());
+        // the rotational correction is half of the rotation vector
+        jacobian
+            .columns_mut(3, 3)
+            .copy_from(&(rotation.matrix() * lever_arm.cross_matrix() * -2.0));
+        jacobian
+    }
+}
+
+/// Returns the antenna's position in the world frame for the given 2D or 3D vehicle pose and lever arm.
+pub(crate) fn get_antenna_position(pose: &[f64], lever_arm: &[f64]) -> Vec<f64> {
+    if pose.len() == 3 {
+        let (sin, cos) = pose[2].sin_cos();
+        vec![
+            pose[0] + cos * lever_arm[0] - sin * lever_arm[1],
+            pose[1] + sin * lever_arm[0] + cos * lever_arm[1],
+        ]
+    } else {
+        let antenna = get_isometry(pose) * Point3::new(lever_arm[0], lever_arm[1], lever_arm[2]);
+        antenna.coords.data.as_slice().to_vec()
+    }
+}
+
+// returns the dimension of the measured position, after which the lever arm follows in the constraint
+fn get_dim(factor: &Factor) -> usize {
+    factor.constraint.len() / 2
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::factor_graph::factor::{FactorId, FactorType};
+    use crate::factor_graph::variable::VariableId;
+    use crate::factor_graph::FactorGraph;
+    use crate::optimizer::handler_check::{build_factor_graph, check_factor};
+    use crate::optimizer::{optimize, total_chi2};
+    use crate::parser::g2o::G2oParser;
+    use crate::parser::Parser;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn test_handlers() {
+        for (vertex_type, pose, lever_arm, factor_type) in [
+            (
+                "Vehicle2D",
+                vec![0.3, -0.2, 0.7],
+                vec![0.5, -0.3],
+                FactorType::LeverArmPosition2D,
+            ),
+            (
+                "Vehicle3D",
+                vec![0.3, -0.2, 0.1, 0.1, -0.2, 0.3, 0.927362],
+                vec![0.5, -0.3, 0.8],
+                FactorType::LeverArmPosition3D,
+            ),
+        ] {
+            let dim = lever_arm.len();
+            let mut constraint = get_antenna_position(&pose, &lever_arm);
+            constraint.extend_from_slice(&lever_arm);
+            let mut factor_graph = build_factor_graph(&[(vertex_type, pose)], vec![], &[]);
+            factor_graph
+                .add_factor(
+                    VariableId(0),
+                    VariableId(0),
+                    factor_type,
+                    constraint,
+                    (0..dim * dim)
+                        .map(|i| if i % (dim + 1) == 0 { 1.0 } else { 0.0 })
+                        .collect::<Vec<f64>>()
+                        .into(),
+                )
+                .unwrap();
+            assert_eq!(check_factor(&factor_graph, FactorId(0), 1e-6), Ok(()));
+        }
+    }
+
+    #[test]
+    fn test_turning_vehicle_with_offset_antenna() {
+        // the vehicle drives along a circle with an antenna one meter ahead of and half a meter left of its origin,
+        // so that the antenna's positions lie on a circle with a larger radius
+        let lever_arm = [1.0, 0.5];
+        let poses: Vec<[f64; 3]> = (0..8)
+            .map(|k| {
+                let angle = 0.4 * k as f64;
+                [3.0 * angle.sin(), 3.0 - 3.0 * angle.cos(), angle]
+            })
+            .collect();
+        let mut lines: Vec<String> = poses
+            .iter()
+            .enumerate()
+            .map(|(k, pose)| format!("VERTEX_SE2 {} {} {} {}", k, pose[0] - 0.4, pose[1] + 0.3, pose[2] - 0.2))
+            .collect();
+        for k in 1..poses.len() {
+            let (previous, pose) = (poses[k - 1], poses[k]);
+            let (sin, cos) = previous[2].sin_cos();
+            let (dx, dy) = (pose[0] - previous[0], pose[1] - previous[1]);
+            lines.push(format!(
+                "EDGE_SE2 {} {} {} {} {} 1 0 0 1 0 1",
+                k - 1,
+                k,
+                cos * dx + sin * dy,
+                cos * dy - sin * dx,
+                pose[2] - previous[2]
+            ));
+        }
+        let mut factor_graph: FactorGraph = G2oParser::parse_string_to_model(&lines.join("\n")).unwrap().into();
+        for (k, pose) in poses.iter().enumerate() {
+            let mut constraint = get_antenna_position(pose, &lever_arm);
+            constraint.extend_from_slice(&lever_arm);
+            factor_graph
+                .add_factor(
+                    VariableId(k),
+                    VariableId(k),
+                    FactorType::LeverArmPosition2D,
+                    constraint,
+                    vec![1.0, 0.0, 0.0, 1.0].into(),
+                )
+                .unwrap();
+        }
+        optimize(&factor_graph, 20);
+        assert!(total_chi2(&factor_graph) < 1e-12);
+        for (k, pose) in poses.iter().enumerate() {
+            let estimate = factor_graph.get_var_by_id(VariableId(k)).unwrap().get_content();
+            assert_relative_eq!(estimate[0], pose[0], epsilon = 1e-6);
+            assert_relative_eq!(estimate[1], pose[1], epsilon = 1e-6);
+            assert_relative_eq!(estimate[2], pose[2], epsilon = 1e-6);
+        }
+    }
+}
@@ -0,0 +1,134 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Observations of infinite line landmarks from a vehicle pose in 2D, e.g. walls extracted from a laser scan in a
+//! corridor where point landmarks are scarce.
+//!
+//! Lines are given in Hessian normal form [angle, distance], i.e. as the points x with
+//! (cos(angle), sin(angle)) * x = distance. The constraint is the line in the vehicle's frame, the error the
+//! difference between the predicted and the measured angle, normalized to [-PI, PI), and distance.
+
+#![allow(non_snake_case)]
+
+use crate::factor_graph::factor::Factor;
+use crate::factor_graph::variable::Variable;
+use crate::optimizer::block_sparse::BlockSparseMatrix;
+use crate::optimizer::linear_system::add_to_H_b;
+use nalgebra::{DMatrix, DVector};
+use std::f64::consts::PI;
+
+pub fn update_H_b(H: &mut BlockSparseMatrix, b: &mut DVector<f64>, factor: &Factor, vars: &[&Variable]) {
+    let jacobi = calc_jacobian(vars);
+    let err = calc_error(factor, vars);
+    add_to_H_b(H, b, &factor.information_matrix.content, &jacobi, &err, vars);
+}
+
+pub fn calc_error(factor: &Factor, vars: &[&Variable]) -> Vec<f64> {
+    let (pose, line) = get_pose_and_line(vars);
+    let (sin, cos) = line[0].sin_cos();
+    let angle_error = (line[0] - pose[2] - factor.constraint[0] + PI).rem_euclid(2.0 * PI) - PI;
+    let distance = line[1] - cos * pose[0] - sin * pose[1];
+    vec![angle_error, distance - factor.constraint[1]]
+}
+
+/// Calculates the Jacobian with respect to the vehicle and the line.
+pub fn calc_jacobian(vars: &[&Variable]) -> DMatrix<f64> {
+    let (pose, line) = get_pose_and_line(vars);
+    let (sin, cos) = line[0].sin_cos();
+    #[rustfmt::skip]
+    let jacobian = DMatrix::from_row_slice(2, 5, &[
+        0.0,  0.0, -1.0,                             1.0, 0.0,
+        -cos, -sin, 0.0, sin * pose[0] - cos * pose[1], 1.0,
+    ]);
+    jacobian
+}
+
+// returns the vehicle's pose and the line
+fn get_pose_and_line(vars: &[&Variable]) -> ([f64; 3], [f64; 2]) {
+    match (vars[0], vars[1]) {
+        (Variable::Vehicle2D(var_i), Variable::LineLandmark2D(var_j)) => (*var_i.pose.borrow(), *var_j.line.borrow()),
+        _ => unreachable!("No valid edge."),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::factor_graph::factor::FactorId;
+    use crate::factor_graph::variable::VariableId;
+    use crate::optimizer::handler_check::{build_factor_graph, check_factor};
+    use crate::optimizer::{optimize, total_chi2};
+    use crate::parser::model::Edge;
+    use approx::assert_relative_eq;
+    use std::f64::consts::PI;
+
+    fn get_edge(vehicle: usize, line: usize, restriction: Vec<f64>) -> Edge {
+        Edge {
+            edge_type: String::from("LineObservation2D"),
+            vertices: vec![VariableId(vehicle), VariableId(line)],
+            restriction,
+            information_matrix: vec![1.0, 0.0, 0.0, 1.0],
+        }
+    }
+
+    // returns the line in the frame of the pose
+    fn get_restriction(pose: &[f64; 3], [angle, distance]: &[f64; 2]) -> Vec<f64> {
+        vec![
+            angle - pose[2],
+            distance - angle.cos() * pose[0] - angle.sin() * pose[1],
+        ]
+    }
+
+    #[test]
+    fn test_handler() {
+        let (pose, line) = ([0.4, -1.3, 0.7], [2.1, 1.8]);
+        let factor_graph = build_factor_graph(
+            &[("Vehicle2D", pose.to_vec()), ("LineLandmark2D", line.to_vec())],
+            vec![get_edge(0, 1, get_restriction(&pose, &line))],
+            &[],
+        );
+        assert_eq!(check_factor(&factor_graph, FactorId(0), 1e-6), Ok(()));
+    }
+
+    #[test]
+    fn test_corridor_walls_localize_vehicle_and_map_wall() {
+        // the corridor walls y = 1 and y = -1 and its end x = 5 are known, the last line is mapped
+        let lines = [[PI / 2.0, 1.0], [-PI / 2.0, 1.0], [0.0, 5.0], [2.5, 2.0]];
+        let pose = [1.0, 0.2, 0.1];
+        let edges = lines
+            .iter()
+            .enumerate()
+            .map(|(k, line)| get_edge(0, k + 1, get_restriction(&pose, line)))
+            .collect();
+        let factor_graph = build_factor_graph(
+            &[
+                ("Vehicle2D", vec![1.4, -0.1, -0.1]),
+                ("LineLandmark2D", lines[0].to_vec()),
+                ("LineLandmark2D", lines[1].to_vec()),
+                ("LineLandmark2D", lines[2].to_vec()),
+                ("LineLandmark2D", vec![2.3, 2.2]),
+            ],
+            edges,
+            &[1, 2, 3],
+        );
+        assert!(total_chi2(&factor_graph) > 1e-2);
+        optimize(&factor_graph, 20);
+        assert!(total_chi2(&factor_graph) < 1e-12);
+        let estimate = factor_graph.get_var_by_id(VariableId(0)).unwrap().get_content();
+        for (actual, expected) in estimate.iter().zip(&pose) {
+            assert_relative_eq!(actual, expected, epsilon = 1e-6);
+        }
+        let wall = factor_graph.get_var_by_id(VariableId(4)).unwrap().get_content();
+        for (actual, expected) in wall.iter().zip(&lines[3]) {
+            assert_relative_eq!(actual, expected, epsilon = 1e-6);
+        }
+    }
+}
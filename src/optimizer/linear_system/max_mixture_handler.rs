@@ -0,0 +1,52 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+use crate::factor_graph::factor::{Factor, MaxMixture};
+use crate::factor_graph::FactorGraph;
+use crate::optimizer::linear_system::{calc_chi2, get_dominant_component as resolve};
+use crate::factor_graph::adjacency::NodeIndex;
+use std::iter;
+
+/// Returns the component with the lowest negative log-likelihood as a factor of the wrapped type.
+pub fn get_dominant_component(
+    factor_graph: &FactorGraph,
+    factor: &Factor,
+    mixture: &MaxMixture,
+    source: NodeIndex<usize>,
+    target: NodeIndex<usize>,
+) -> Factor {
+    iter::once((mixture.weight, &factor.constraint, &factor.information_matrix))
+        .chain(
+            mixture
+                .alternatives
+                .iter()
+                .map(|c| (c.weight, &c.constraint, &c.information_matrix)),
+        )
+        .map(|(weight, constraint, information_matrix)| {
+            let candidate = Factor {
+                id: factor.id,
+                factor_type: (*mixture.factor_type).clone(),
+                constraint: constraint.clone(),
+                information_matrix: information_matrix.clone(),
+                additional_variables: factor.additional_variables.clone(),
+            };
+            // nested mixtures are resolved first
+            let candidate = resolve(factor_graph, &candidate).into_owned();
+            let cost = 0.5 * calc_chi2(factor_graph, &candidate, source, target)
+                - weight.ln()
+                - 0.5 * candidate.information_matrix.content.determinant().ln();
+            (cost, candidate)
+        })
+        .min_by(|(a, _), (b, _)| a.partial_cmp(b).unwrap())
+        .unwrap()
+        .1
+}
@@ -10,54 +10,490 @@
 // This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
 //
 
-use crate::factor_graph::factor::{Factor, FactorType::*};
+use crate::factor_graph::factor::{Factor, FactorId, FactorType, FactorType::*};
+use crate::factor_graph::variable::{FixedType, Variable};
 use crate::factor_graph::FactorGraph;
+use crate::optimizer::block_sparse::BlockSparseMatrix;
+use crate::optimizer::FactorReweighting;
 use nalgebra::{DMatrix, DVector};
-use petgraph::csr::EdgeReference;
-use petgraph::visit::EdgeRef;
-use petgraph::Directed;
+use crate::factor_graph::adjacency::NodeIndex;
+use std::borrow::Cow;
 
+mod altitude_handler;
+mod anchor_handler;
+mod bearing_range2d_handler;
+mod calibrated_odo2d_handler;
+mod constant_velocity_handler;
+mod custom_factor_handler;
+mod custom_handler;
+mod dense_prior_handler;
+mod extrinsic_handler;
+pub(crate) mod heading_handler;
+mod imu_handler;
+pub(crate) mod interpolation_handler;
+mod landmark_offset_handler;
+pub(crate) mod lever_arm_handler;
+mod line2d_handler;
+mod max_mixture_handler;
 mod obs2d_handler;
 mod odo2d_handler;
+mod point_to_plane_handler;
 mod pos2d_handler;
+mod pos_only3d_handler;
+pub(crate) mod projection_handler;
+mod sim3_handler;
+pub(crate) mod stereo_projection_handler;
+mod range_handler;
 
 pub mod iso3d_gradients;
 mod obs3d_handler;
 mod odo3d_handler;
 mod pos3d_handler;
 
-pub fn calculate_H_b(factor_graph: &FactorGraph) -> (DMatrix<f64>, DVector<f64>) {
+mod switch_handler;
+
+pub fn calculate_H_b(factor_graph: &FactorGraph) -> (BlockSparseMatrix, DVector<f64>) {
+    calculate_scaled_H_b(factor_graph, None)
+}
+
+/// Returns H and b with the information matrices scaled by the given reweighting at the current estimates, or
+/// unscaled if no reweighting is given.
+///
+/// Max-mixture factors are scaled according to the type of their dominant component.
+pub fn calculate_scaled_H_b(
+    factor_graph: &FactorGraph,
+    reweighting: Option<&dyn FactorReweighting>,
+) -> (BlockSparseMatrix, DVector<f64>) {
     let dim = factor_graph.matrix_dim;
-    let mut H = DMatrix::from_vec(dim, dim, vec![0.0; dim * dim]);
+    let mut H = BlockSparseMatrix::new(dim);
     let mut b = DVector::from_vec(vec![0.0; dim]);
 
-    factor_graph
-        .node_indices
-        .iter()
-        .map(|i| factor_graph.csr.edges(*i))
-        .for_each(|edges| edges.for_each(|edge| update_H_b(factor_graph, &mut H, &mut b, edge)));
+    factor_graph.node_indices.iter().for_each(|i| {
+        factor_graph.adjacency.edges(*i).for_each(|edge| {
+            let factor = get_dominant_component(factor_graph, edge.weight());
+            let weight = match reweighting {
+                Some(reweighting) if reweighting.is_reweighted(&factor.factor_type) => reweighting.get_weight(
+                    &factor.factor_type,
+                    calc_chi2(factor_graph, &factor, edge.source(), edge.target()),
+                ),
+                _ => 1.0,
+            };
+            let mut factor = factor;
+            if weight != 1.0 {
+                factor.to_mut().information_matrix.content *= weight;
+            }
+            update_H_b(factor_graph, &mut H, &mut b, &factor, edge.source(), edge.target());
+        })
+    });
+    fix_components(factor_graph, &mut H, &mut b);
 
     (H, b)
 }
 
+// decouples the rows of the fixed components of partially fixed variables, see FactorGraph::fix_components
+pub(crate) fn fix_components(factor_graph: &FactorGraph, H: &mut BlockSparseMatrix, b: &mut DVector<f64>) {
+    let rows = factor_graph.get_fixed_rows();
+    H.fix_rows(&rows);
+    rows.iter().for_each(|row| b[*row] = 0.0);
+}
+
+/// Returns the contribution of a single factor to H and b, or None if the factor is not part of the factor graph.
+pub fn calculate_factor_H_b(factor_graph: &FactorGraph, id: FactorId) -> Option<(BlockSparseMatrix, DVector<f64>)> {
+    let (source, target) = factor_graph.factor_id_map.get(&id)?;
+    let factor = factor_graph.get_factor(id)?;
+    let dim = factor_graph.matrix_dim;
+    let mut H = BlockSparseMatrix::new(dim);
+    let mut b = DVector::from_vec(vec![0.0; dim]);
+    let factor = factor_graph.materialize_factor(factor);
+    update_H_b(factor_graph, &mut H, &mut b, &factor, *source, *target);
+    Some((H, b))
+}
+
+/// Returns the factor's error vector at the current estimates, or None if the factor is not part of the factor graph.
+///
+/// The error of max-mixture factors is the one of their dominant component.
+pub fn calculate_error(factor_graph: &FactorGraph, id: FactorId) -> Option<DVector<f64>> {
+    let (source, target) = factor_graph.factor_id_map.get(&id)?;
+    let factor = factor_graph.get_factor(id)?;
+    Some(DVector::from_vec(calc_error(factor_graph, factor, *source, *target)))
+}
+
+/// Returns the Jacobian of the factor's error vector with respect to the corrections of its variables, or None if
+/// the factor is not part of the factor graph.
+///
+/// The columns of unary factors belong to their only variable, the ones of binary factors to the source variable
+/// first and to the target variable afterwards, followed by the ones of the factor's additional variables.
+pub fn calculate_jacobian(factor_graph: &FactorGraph, id: FactorId) -> Option<DMatrix<f64>> {
+    let (source, target) = factor_graph.factor_id_map.get(&id)?;
+    let factor = factor_graph.get_factor(id)?;
+    Some(calc_jacobian(factor_graph, factor, *source, *target))
+}
+
+/// Returns the factor's squared error weighted by its information matrix, or None if the factor is not part of the
+/// factor graph.
+///
+/// The squared error of max-mixture factors is the one of their dominant component.
+pub fn calculate_chi2(factor_graph: &FactorGraph, id: FactorId) -> Option<f64> {
+    let (source, target) = factor_graph.factor_id_map.get(&id)?;
+    let factor = factor_graph.get_factor(id)?;
+    Some(calc_chi2(factor_graph, &get_dominant_component(factor_graph, factor), *source, *target))
+}
+
+/// Returns the component of a max-mixture factor which is used at the current estimates as a factor with the same
+/// ID and variables, see [MaxMixture](../../factor_graph/factor/struct.MaxMixture.html). Other factors are returned
+/// as they are, with their payloads materialized if they were offloaded.
+///
+/// Panics if the factor is not part of the factor graph.
+pub fn get_dominant_component<'a>(factor_graph: &FactorGraph, factor: &'a Factor) -> Cow<'a, Factor> {
+    let factor = factor_graph.materialize_factor(factor);
+    match &factor.factor_type {
+        MaxMixture(mixture) => {
+            let (source, target) = factor_graph.factor_id_map[&factor.id];
+            Cow::Owned(max_mixture_handler::get_dominant_component(
+                factor_graph,
+                &factor,
+                mixture,
+                source,
+                target,
+            ))
+        }
+        _ => factor,
+    }
+}
+
+fn calc_error(
+    factor_graph: &FactorGraph,
+    factor: &Factor,
+    source: NodeIndex<usize>,
+    target: NodeIndex<usize>,
+) -> Vec<f64> {
+    use crate::factor_graph::variable::Variable::*;
+    let var_i = factor_graph.get_var(source);
+    let var_j = factor_graph.get_var(target);
+
+    match (&factor.factor_type, var_i, var_j) {
+        (Position2D, Vehicle2D(var_i), _) => pos2d_handler::calc_error(factor, var_i),
+        (Odometry2D, Vehicle2D(var_i), Vehicle2D(var_j)) => odo2d_handler::calc_error(factor, var_i, var_j),
+        (Observation2D, Vehicle2D(var_i), Landmark2D(var_j)) => obs2d_handler::calc_error(factor, var_i, var_j),
+        (BearingRange2D, Vehicle2D(var_i), Landmark2D(var_j)) | (Bearing2D, Vehicle2D(var_i), Landmark2D(var_j)) => {
+            bearing_range2d_handler::calc_error(factor, var_i, var_j)
+        }
+        (Position3D, Vehicle3D(var_i), _) => pos3d_handler::calc_error(factor, var_i),
+        (PositionOnly3D, Vehicle3D(_), _) => pos_only3d_handler::calc_error(factor, &get_vars(factor_graph, factor.id)),
+        (Altitude3D, Vehicle3D(_), _) => altitude_handler::calc_error(factor, &get_vars(factor_graph, factor.id)),
+        (LeverArmPosition2D, Vehicle2D(_), _) | (LeverArmPosition3D, Vehicle3D(_), _) => {
+            lever_arm_handler::calc_error(factor, &get_vars(factor_graph, factor.id))
+        }
+        (Heading2D, Vehicle2D(_), _) | (Heading3D, Vehicle3D(_), _) => {
+            heading_handler::calc_error(factor, &get_vars(factor_graph, factor.id))
+        }
+        (Odometry3D, Vehicle3D(var_i), Vehicle3D(var_j)) => odo3d_handler::calc_error(factor, var_i, var_j),
+        (Observation3D, Vehicle3D(var_i), Landmark3D(var_j)) => obs3d_handler::calc_error(factor, var_i, var_j),
+        (Projection3D, Vehicle3D(_), Landmark3D(_)) => {
+            projection_handler::calc_error(factor, &get_vars(factor_graph, factor.id))
+        }
+        (StereoProjection3D, Vehicle3D(_), Landmark3D(_)) => {
+            stereo_projection_handler::calc_error(factor, &get_vars(factor_graph, factor.id))
+        }
+        (OdometrySim3, VehicleSim3(_), VehicleSim3(_)) => {
+            sim3_handler::calc_error(factor, &get_vars(factor_graph, factor.id))
+        }
+        (PointToPlane3D, Vehicle3D(_), Plane3D(_)) => {
+            point_to_plane_handler::calc_error(factor, &get_vars(factor_graph, factor.id))
+        }
+        (ExtrinsicObservation2D, Vehicle2D(_), Landmark2D(_))
+        | (ExtrinsicObservation3D, Vehicle3D(_), Landmark3D(_)) => {
+            extrinsic_handler::calc_error(factor, &get_vars(factor_graph, factor.id))
+        }
+        (AnchoredOdometry2D, Vehicle2D(_), Vehicle2D(_))
+        | (AnchoredOdometry3D, Vehicle3D(_), Vehicle3D(_))
+        | (AnchoredObservation2D, Vehicle2D(_), Landmark2D(_))
+        | (AnchoredObservation3D, Vehicle3D(_), Landmark3D(_)) => {
+            anchor_handler::calc_error(factor, &get_vars(factor_graph, factor.id))
+        }
+        (CalibratedOdometry2D, Vehicle2D(_), Vehicle2D(_)) => {
+            calibrated_odo2d_handler::calc_error(factor, &get_vars(factor_graph, factor.id))
+        }
+        (LineObservation2D, Vehicle2D(_), LineLandmark2D(_)) => {
+            line2d_handler::calc_error(factor, &get_vars(factor_graph, factor.id))
+        }
+        (InterpolatedPose2D, Vehicle2D(_), _) | (InterpolatedPose3D, Vehicle3D(_), _) => {
+            interpolation_handler::calc_error(factor, &get_vars(factor_graph, factor.id))
+        }
+        (LandmarkOffset2D, Landmark2D(_), Landmark2D(_)) | (LandmarkOffset3D, Landmark3D(_), Landmark3D(_)) => {
+            landmark_offset_handler::calc_error(factor, &get_vars(factor_graph, factor.id))
+        }
+        (Range2D, Vehicle2D(_), Landmark2D(_)) | (Range3D, Vehicle3D(_), Landmark3D(_)) => {
+            range_handler::calc_error(factor, &get_vars(factor_graph, factor.id))
+        }
+        (SwitchableOdometry2D, _, _) | (SwitchableOdometry3D, _, _) | (SwitchPrior, _, _) => {
+            switch_handler::calc_error(factor, &get_vars(factor_graph, factor.id))
+        }
+        (ImuPreintegration3D, Vehicle3D(_), Vehicle3D(_)) => {
+            imu_handler::calc_error(factor, &get_vars(factor_graph, factor.id))
+        }
+        (ConstantVelocity2D, Vehicle2D(_), Vehicle2D(_)) | (ConstantVelocity3D, Vehicle3D(_), Vehicle3D(_)) => {
+            constant_velocity_handler::calc_error(factor, &get_vars(factor_graph, factor.id))
+        }
+        (MaxMixture(_), _, _) => calc_error(
+            factor_graph,
+            &get_dominant_component(factor_graph, factor),
+            source,
+            target,
+        ),
+        (Custom(residual), _, _) => custom_handler::calc_error(factor, residual, &get_vars(factor_graph, factor.id)),
+        (FactorType::UserDefined(custom), _, _) => {
+            custom_factor_handler::calc_error(factor, custom.as_ref(), &get_vars(factor_graph, factor.id))
+        }
+        (DensePrior, _, _) => dense_prior_handler::calc_error(factor, &get_vars(factor_graph, factor.id)),
+        _ => unreachable!("No valid edge."),
+    }
+}
+
+fn calc_jacobian(
+    factor_graph: &FactorGraph,
+    factor: &Factor,
+    source: NodeIndex<usize>,
+    target: NodeIndex<usize>,
+) -> DMatrix<f64> {
+    use crate::factor_graph::variable::Variable::*;
+    let var_i = factor_graph.get_var(source);
+    let var_j = factor_graph.get_var(target);
+
+    match (&factor.factor_type, var_i, var_j) {
+        (Position2D, Vehicle2D(_), _) => pos2d_handler::calc_jacobian(factor),
+        (Odometry2D, Vehicle2D(var_i), Vehicle2D(var_j)) => odo2d_handler::calc_jacobian(factor, var_i, var_j),
+        (Observation2D, Vehicle2D(var_i), Landmark2D(var_j)) => obs2d_handler::calc_jacobian(var_i, var_j),
+        (BearingRange2D, Vehicle2D(var_i), Landmark2D(var_j)) | (Bearing2D, Vehicle2D(var_i), Landmark2D(var_j)) => {
+            bearing_range2d_handler::calc_jacobian(factor, var_i, var_j)
+        }
+        (Position3D, Vehicle3D(var_i), _) => pos3d_handler::calc_jacobian(factor, var_i),
+        (PositionOnly3D, Vehicle3D(_), _) => pos_only3d_handler::calc_jacobian(&get_vars(factor_graph, factor.id)),
+        (Altitude3D, Vehicle3D(_), _) => altitude_handler::calc_jacobian(&get_vars(factor_graph, factor.id)),
+        (LeverArmPosition2D, Vehicle2D(_), _) | (LeverArmPosition3D, Vehicle3D(_), _) => {
+            lever_arm_handler::calc_jacobian(factor, &get_vars(factor_graph, factor.id))
+        }
+        (Heading2D, Vehicle2D(_), _) | (Heading3D, Vehicle3D(_), _) => {
+            heading_handler::calc_jacobian(&get_vars(factor_graph, factor.id))
+        }
+        (Odometry3D, Vehicle3D(var_i), Vehicle3D(var_j)) => odo3d_handler::calc_jacobian(factor, var_i, var_j),
+        (Observation3D, Vehicle3D(var_i), Landmark3D(var_j)) => obs3d_handler::calc_jacobian(var_i, var_j),
+        (Projection3D, Vehicle3D(_), Landmark3D(_)) => {
+            projection_handler::calc_jacobian(factor, &get_vars(factor_graph, factor.id))
+        }
+        (StereoProjection3D, Vehicle3D(_), Landmark3D(_)) => {
+            stereo_projection_handler::calc_jacobian(factor, &get_vars(factor_graph, factor.id))
+        }
+        (OdometrySim3, VehicleSim3(_), VehicleSim3(_)) => {
+            sim3_handler::calc_jacobian(factor, &get_vars(factor_graph, factor.id))
+        }
+        (PointToPlane3D, Vehicle3D(_), Plane3D(_)) => {
+            point_to_plane_handler::calc_jacobian(factor, &get_vars(factor_graph, factor.id))
+        }
+        (ExtrinsicObservation2D, Vehicle2D(_), Landmark2D(_))
+        | (ExtrinsicObservation3D, Vehicle3D(_), Landmark3D(_)) => {
+            extrinsic_handler::calc_jacobian(factor, &get_vars(factor_graph, factor.id))
+        }
+        (AnchoredOdometry2D, Vehicle2D(_), Vehicle2D(_))
+        | (AnchoredOdometry3D, Vehicle3D(_), Vehicle3D(_))
+        | (AnchoredObservation2D, Vehicle2D(_), Landmark2D(_))
+        | (AnchoredObservation3D, Vehicle3D(_), Landmark3D(_)) => {
+            anchor_handler::calc_jacobian(factor, &get_vars(factor_graph, factor.id))
+        }
+        (CalibratedOdometry2D, Vehicle2D(_), Vehicle2D(_)) => {
+            calibrated_odo2d_handler::calc_jacobian(factor, &get_vars(factor_graph, factor.id))
+        }
+        (LineObservation2D, Vehicle2D(_), LineLandmark2D(_)) => {
+            line2d_handler::calc_jacobian(&get_vars(factor_graph, factor.id))
+        }
+        (InterpolatedPose2D, Vehicle2D(_), _) | (InterpolatedPose3D, Vehicle3D(_), _) => {
+            interpolation_handler::calc_jacobian(factor, &get_vars(factor_graph, factor.id))
+        }
+        (LandmarkOffset2D, Landmark2D(_), Landmark2D(_)) | (LandmarkOffset3D, Landmark3D(_), Landmark3D(_)) => {
+            landmark_offset_handler::calc_jacobian(&get_vars(factor_graph, factor.id))
+        }
+        (Range2D, Vehicle2D(_), Landmark2D(_)) | (Range3D, Vehicle3D(_), Landmark3D(_)) => {
+            range_handler::calc_jacobian(&get_vars(factor_graph, factor.id))
+        }
+        (SwitchableOdometry2D, _, _) | (SwitchableOdometry3D, _, _) | (SwitchPrior, _, _) => {
+            switch_handler::calc_jacobian(factor, &get_vars(factor_graph, factor.id))
+        }
+        (ImuPreintegration3D, Vehicle3D(_), Vehicle3D(_)) => {
+            imu_handler::calc_jacobian(factor, &get_vars(factor_graph, factor.id))
+        }
+        (ConstantVelocity2D, Vehicle2D(_), Vehicle2D(_)) | (ConstantVelocity3D, Vehicle3D(_), Vehicle3D(_)) => {
+            constant_velocity_handler::calc_jacobian(factor, &get_vars(factor_graph, factor.id))
+        }
+        (MaxMixture(_), _, _) => calc_jacobian(
+            factor_graph,
+            &get_dominant_component(factor_graph, factor),
+            source,
+            target,
+        ),
+        (Custom(residual), _, _) => {
+            custom_handler::calc_jacobian(factor, residual, &get_vars(factor_graph, factor.id))
+        }
+        (FactorType::UserDefined(custom), _, _) => {
+            custom_factor_handler::calc_jacobian(factor, custom.as_ref(), &get_vars(factor_graph, factor.id))
+        }
+        (DensePrior, _, _) => dense_prior_handler::calc_jacobian(factor, &get_vars(factor_graph, factor.id)),
+        _ => unreachable!("No valid edge."),
+    }
+}
+
+// expects a factor which is not a max-mixture factor
+fn calc_chi2(factor_graph: &FactorGraph, factor: &Factor, source: NodeIndex<usize>, target: NodeIndex<usize>) -> f64 {
+    let err = DVector::from_vec(calc_error(factor_graph, factor, source, target));
+    err.dot(&(&factor.information_matrix.content * &err))
+}
+
 fn update_H_b(
     factor_graph: &FactorGraph,
-    H: &mut DMatrix<f64>,
+    H: &mut BlockSparseMatrix,
     b: &mut DVector<f64>,
-    edge: EdgeReference<Factor, Directed, usize>,
+    factor: &Factor,
+    source: NodeIndex<usize>,
+    target: NodeIndex<usize>,
 ) {
     use crate::factor_graph::variable::Variable::*;
-    let factor = edge.weight();
-    let var_i = &factor_graph.get_var(edge.source());
-    let var_j = &factor_graph.get_var(edge.target());
+    let var_i = &factor_graph.get_var(source);
+    let var_j = &factor_graph.get_var(target);
 
     match (&factor.factor_type, var_i, var_j) {
         (Position2D, Vehicle2D(var_i), _) => pos2d_handler::update_H_b(H, b, factor, var_i),
         (Odometry2D, Vehicle2D(var_i), Vehicle2D(var_j)) => odo2d_handler::update_H_b(H, b, factor, var_i, var_j),
         (Observation2D, Vehicle2D(var_i), Landmark2D(var_j)) => obs2d_handler::update_H_b(H, b, factor, var_i, var_j),
+        (BearingRange2D, Vehicle2D(_), Landmark2D(_)) | (Bearing2D, Vehicle2D(_), Landmark2D(_)) => {
+            bearing_range2d_handler::update_H_b(H, b, factor, &get_vars(factor_graph, factor.id))
+        }
         (Position3D, Vehicle3D(var_i), _) => pos3d_handler::update_H_b(H, b, factor, var_i),
+        (PositionOnly3D, Vehicle3D(_), _) => {
+            pos_only3d_handler::update_H_b(H, b, factor, &get_vars(factor_graph, factor.id))
+        }
+        (Altitude3D, Vehicle3D(_), _) => {
+            altitude_handler::update_H_b(H, b, factor, &get_vars(factor_graph, factor.id))
+        }
+        (LeverArmPosition2D, Vehicle2D(_), _) | (LeverArmPosition3D, Vehicle3D(_), _) => {
+            lever_arm_handler::update_H_b(H, b, factor, &get_vars(factor_graph, factor.id))
+        }
+        (Heading2D, Vehicle2D(_), _) | (Heading3D, Vehicle3D(_), _) => {
+            heading_handler::update_H_b(H, b, factor, &get_vars(factor_graph, factor.id))
+        }
         (Odometry3D, Vehicle3D(var_i), Vehicle3D(var_j)) => odo3d_handler::update_H_b(H, b, factor, var_i, var_j),
         (Observation3D, Vehicle3D(var_i), Landmark3D(var_j)) => obs3d_handler::update_H_b(H, b, factor, var_i, var_j),
+        (Projection3D, Vehicle3D(_), Landmark3D(_)) => {
+            projection_handler::update_H_b(H, b, factor, &get_vars(factor_graph, factor.id))
+        }
+        (StereoProjection3D, Vehicle3D(_), Landmark3D(_)) => {
+            stereo_projection_handler::update_H_b(H, b, factor, &get_vars(factor_graph, factor.id))
+        }
+        (OdometrySim3, VehicleSim3(_), VehicleSim3(_)) => {
+            sim3_handler::update_H_b(H, b, factor, &get_vars(factor_graph, factor.id))
+        }
+        (PointToPlane3D, Vehicle3D(_), Plane3D(_)) => {
+            point_to_plane_handler::update_H_b(H, b, factor, &get_vars(factor_graph, factor.id))
+        }
+        (ExtrinsicObservation2D, Vehicle2D(_), Landmark2D(_))
+        | (ExtrinsicObservation3D, Vehicle3D(_), Landmark3D(_)) => {
+            extrinsic_handler::update_H_b(H, b, factor, &get_vars(factor_graph, factor.id))
+        }
+        (AnchoredOdometry2D, Vehicle2D(_), Vehicle2D(_))
+        | (AnchoredOdometry3D, Vehicle3D(_), Vehicle3D(_))
+        | (AnchoredObservation2D, Vehicle2D(_), Landmark2D(_))
+        | (AnchoredObservation3D, Vehicle3D(_), Landmark3D(_)) => {
+            anchor_handler::update_H_b(H, b, factor, &get_vars(factor_graph, factor.id))
+        }
+        (CalibratedOdometry2D, Vehicle2D(_), Vehicle2D(_)) => {
+            calibrated_odo2d_handler::update_H_b(H, b, factor, &get_vars(factor_graph, factor.id))
+        }
+        (LineObservation2D, Vehicle2D(_), LineLandmark2D(_)) => {
+            line2d_handler::update_H_b(H, b, factor, &get_vars(factor_graph, factor.id))
+        }
+        (InterpolatedPose2D, Vehicle2D(_), _) | (InterpolatedPose3D, Vehicle3D(_), _) => {
+            interpolation_handler::update_H_b(H, b, factor, &get_vars(factor_graph, factor.id))
+        }
+        (LandmarkOffset2D, Landmark2D(_), Landmark2D(_)) | (LandmarkOffset3D, Landmark3D(_), Landmark3D(_)) => {
+            landmark_offset_handler::update_H_b(H, b, factor, &get_vars(factor_graph, factor.id))
+        }
+        (Range2D, Vehicle2D(_), Landmark2D(_)) | (Range3D, Vehicle3D(_), Landmark3D(_)) => {
+            range_handler::update_H_b(H, b, factor, &get_vars(factor_graph, factor.id))
+        }
+        (SwitchableOdometry2D, _, _) | (SwitchableOdometry3D, _, _) | (SwitchPrior, _, _) => {
+            switch_handler::update_H_b(H, b, factor, &get_vars(factor_graph, factor.id))
+        }
+        (ImuPreintegration3D, Vehicle3D(_), Vehicle3D(_)) => {
+            imu_handler::update_H_b(H, b, factor, &get_vars(factor_graph, factor.id))
+        }
+        (ConstantVelocity2D, Vehicle2D(_), Vehicle2D(_)) | (ConstantVelocity3D, Vehicle3D(_), Vehicle3D(_)) => {
+            constant_velocity_handler::update_H_b(H, b, factor, &get_vars(factor_graph, factor.id))
+        }
+        (MaxMixture(_), _, _) => update_H_b(
+            factor_graph,
+            H,
+            b,
+            &get_dominant_component(factor_graph, factor),
+            source,
+            target,
+        ),
+        (Custom(residual), _, _) => {
+            custom_handler::update_H_b(H, b, factor, residual, &get_vars(factor_graph, factor.id))
+        }
+        (FactorType::UserDefined(custom), _, _) => {
+            custom_factor_handler::update_H_b(H, b, factor, custom.as_ref(), &get_vars(factor_graph, factor.id))
+        }
+        (DensePrior, _, _) => dense_prior_handler::update_H_b(H, b, factor, &get_vars(factor_graph, factor.id)),
         _ => unreachable!("No valid edge."),
     }
 }
+
+// returns the variables of a factor in the order of get_factor_var_indices
+fn get_vars(factor_graph: &FactorGraph, id: FactorId) -> Vec<&Variable> {
+    factor_graph
+        .get_factor_var_indices(id)
+        .unwrap()
+        .iter()
+        .map(|i| factor_graph.get_var(*i))
+        .collect()
+}
+
+// returns the offsets of the variables' columns in a Jacobian with respect to all of them
+fn get_tangent_offsets(vars: &[&Variable]) -> Vec<usize> {
+    let mut offsets = vec![0];
+    for var in vars {
+        offsets.push(offsets.last().unwrap() + var.get_parameterization().tangent_dim());
+    }
+    offsets
+}
+
+// adds the contribution of a factor with the given Jacobian with respect to all of its variables and error to H and b
+fn add_to_H_b(
+    H: &mut BlockSparseMatrix,
+    b: &mut DVector<f64>,
+    information: &DMatrix<f64>,
+    jacobi: &DMatrix<f64>,
+    err: &[f64],
+    vars: &[&Variable],
+) {
+    let right_mult = information * jacobi;
+    let H_updates = jacobi.transpose() * &right_mult;
+    let b_updates = (DVector::from_column_slice(err).transpose() * &right_mult).transpose();
+
+    let offsets = get_tangent_offsets(vars);
+    for (i, var_i) in vars.iter().enumerate() {
+        if let FixedType::NonFixed(row_range) = var_i.get_fixed_type() {
+            let rows = offsets[i]..offsets[i + 1];
+            for (j, var_j) in vars.iter().enumerate() {
+                if let FixedType::NonFixed(col_range) = var_j.get_fixed_type() {
+                    let cols = offsets[j]..offsets[j + 1];
+                    H.add_block(
+                        row_range.to_owned(),
+                        col_range.to_owned(),
+                        &H_updates.index((rows.clone(), cols)),
+                    );
+                }
+            }
+            let updated_subvector = b.index((row_range.to_owned(), ..)) + b_updates.index((rows, ..));
+            b.index_mut((row_range.to_owned(), ..)).copy_from(&updated_subvector);
+        }
+    }
+}
@@ -16,15 +16,16 @@
 use crate::factor_graph::factor::Factor;
 use crate::factor_graph::variable::{FixedType, LandmarkVariable3D, VehicleVariable3D};
 use crate::optimizer::linear_system::iso3d_gradients::{get_isometry, skew_trans};
+use crate::optimizer::block_sparse::BlockSparseMatrix;
 use nalgebra::{
-    DMatrix, DVector, Dynamic, Isometry3, Matrix, Matrix3, MatrixMN, RowVector3, SliceStorage, Translation3, Vector,
+    DMatrix, DVector, Dynamic, Isometry3, Matrix, Matrix3, OMatrix, RowVector3, SliceStorage, Translation3, Vector,
     Vector3, U1, U3, U9,
 };
 
 
 
 pub fn update_H_b(
-    H: &mut DMatrix<f64>,
+    H: &mut BlockSparseMatrix,
     b: &mut DVector<f64>,
     factor: &Factor,
     var_i: &VehicleVariable3D,
@@ -33,7 +34,6 @@ pub fn update_H_b(
     let iso_i = get_isometry(&*var_i.pose.borrow());
     let trans_j = get_trans(&var_j.position.borrow());
     let local_j = (iso_i.inverse() * trans_j).translation;
-    let pos_ij = get_pos(&factor.constraint);
     let (jacobi, jacobi_T) = calc_jacobians(&iso_i, &local_j);
     let right_mult = &factor.information_matrix.content * jacobi;
 
@@ -43,19 +43,33 @@ pub fn update_H_b(
     update_H_submatrix(H, &H_updates.index((6.., ..6)), &var_j.fixed_type, &var_i.fixed_type);
     update_H_submatrix(H, &H_updates.index((6.., 6..)), &var_j.fixed_type, &var_j.fixed_type);
 
-    let err_pos = local_j.vector - pos_ij;
-    let err_vec = err_pos.data.as_slice().to_vec();
-    let b_updates = (RowVector3::from_vec(err_vec) * &right_mult).transpose();
+    let b_updates = (RowVector3::from_vec(calc_error(factor, var_i, var_j)) * &right_mult).transpose();
     update_b_subvector(b, &b_updates.index((..6, ..)), &var_i.fixed_type);
     update_b_subvector(b, &b_updates.index((6.., ..)), &var_j.fixed_type);
 }
 
+pub fn calc_error(factor: &Factor, var_i: &VehicleVariable3D, var_j: &LandmarkVariable3D) -> Vec<f64> {
+    let iso_i = get_isometry(&*var_i.pose.borrow());
+    let trans_j = get_trans(&var_j.position.borrow());
+    let local_j = (iso_i.inverse() * trans_j).translation;
+    let err_pos = local_j.vector - get_pos(&factor.constraint);
+    err_pos.data.as_slice().to_vec()
+}
+
+pub fn calc_jacobian(var_i: &VehicleVariable3D, var_j: &LandmarkVariable3D) -> DMatrix<f64> {
+    let iso_i = get_isometry(&*var_i.pose.borrow());
+    let trans_j = get_trans(&var_j.position.borrow());
+    let local_j = (iso_i.inverse() * trans_j).translation;
+    let (jacobi, _) = calc_jacobians(&iso_i, &local_j);
+    DMatrix::from_column_slice(3, 9, jacobi.as_slice())
+}
+
 fn calc_jacobians(
     iso_i: &Isometry3<f64>,
     local_j: &Translation3<f64>,
-) -> (MatrixMN<f64, U3, U9>, MatrixMN<f64, U9, U3>) {
+) -> (OMatrix<f64, U3, U9>, OMatrix<f64, U9, U3>) {
     let rot_i_inv = iso_i.inverse().rotation.to_rotation_matrix();
-    let mut jacobian = MatrixMN::<f64, U3, U9>::from_vec(vec![0.0; 27]);
+    let mut jacobian = OMatrix::<f64, U3, U9>::from_vec(vec![0.0; 27]);
     jacobian.index_mut((.., 0..3)).copy_from(&-Matrix3::<f64>::identity());
     jacobian
         .index_mut((.., 3..6))
@@ -65,15 +79,13 @@ fn calc_jacobians(
 }
 
 fn update_H_submatrix(
-    H: &mut DMatrix<f64>,
+    H: &mut BlockSparseMatrix,
     added_matrix: &Matrix<f64, Dynamic, Dynamic, SliceStorage<f64, Dynamic, Dynamic, U1, U9>>,
     row_type: &FixedType,
     col_type: &FixedType,
 ) {
     if let (FixedType::NonFixed(row_range), FixedType::NonFixed(col_range)) = (row_type, col_type) {
-        let updated_submatrix = &(H.index((row_range.to_owned(), col_range.to_owned())) + added_matrix);
-        H.index_mut((row_range.to_owned(), col_range.to_owned()))
-            .copy_from(updated_submatrix);
+        H.add_block(row_range.to_owned(), col_range.to_owned(), added_matrix);
     }
 }
 
@@ -15,6 +15,7 @@
 
 use crate::factor_graph::factor::Factor;
 use crate::factor_graph::variable::{FixedType, VehicleVariable2D};
+use crate::optimizer::block_sparse::BlockSparseMatrix;
 use nalgebra::{
     DMatrix, DVector, Dynamic, Matrix, Matrix3, Matrix3x6, Matrix6x3, Rotation2, Rotation3, RowVector3, SliceStorage,
     Vector, Vector2, Vector3, U1, U6,
@@ -23,15 +24,15 @@ use std::f64::consts::PI;
 
 
 pub fn update_H_b(
-    H: &mut DMatrix<f64>,
+    H: &mut BlockSparseMatrix,
     b: &mut DVector<f64>,
     factor: &Factor,
     var_i: &VehicleVariable2D,
     var_j: &VehicleVariable2D,
 ) {
     let (pos_i, rot_i) = get_pos_and_rot(&*var_i.pose.borrow());
-    let (pos_j, rot_j) = get_pos_and_rot(&*var_j.pose.borrow());
-    let (pos_ij, rot_ij) = get_pos_and_rot(&factor.constraint);
+    let (pos_j, _) = get_pos_and_rot(&*var_j.pose.borrow());
+    let (_, rot_ij) = get_pos_and_rot(&factor.constraint);
     let (jacobi, jacobi_T) = calc_jacobians(&pos_i, rot_i, &pos_j, rot_ij);
     let right_mult = &factor.information_matrix.content * jacobi;
 
@@ -41,6 +42,15 @@ pub fn update_H_b(
     update_H_submatrix(H, &H_updates.index((3.., ..3)), &var_j.fixed_type, &var_i.fixed_type);
     update_H_submatrix(H, &H_updates.index((3.., 3..)), &var_j.fixed_type, &var_j.fixed_type);
 
+    let b_updates = (RowVector3::from_vec(calc_error(factor, var_i, var_j)) * &right_mult).transpose();
+    update_b_subvector(b, &b_updates.index((..3, ..)), &var_i.fixed_type);
+    update_b_subvector(b, &b_updates.index((3.., ..)), &var_j.fixed_type);
+}
+
+pub fn calc_error(factor: &Factor, var_i: &VehicleVariable2D, var_j: &VehicleVariable2D) -> Vec<f64> {
+    let (pos_i, rot_i) = get_pos_and_rot(&*var_i.pose.borrow());
+    let (pos_j, rot_j) = get_pos_and_rot(&*var_j.pose.borrow());
+    let (pos_ij, rot_ij) = get_pos_and_rot(&factor.constraint);
     let err_pos = Rotation2::new(-rot_ij) * (Rotation2::new(-rot_i) * (pos_j - pos_i) - pos_ij);
     let mut err_rot = rot_j - rot_i - rot_ij;
     if err_rot >= PI {
@@ -50,9 +60,15 @@ pub fn update_H_b(
     }
     let mut err_vec = err_pos.data.as_slice().to_vec();
     err_vec.push(err_rot);
-    let b_updates = (RowVector3::from_vec(err_vec) * &right_mult).transpose();
-    update_b_subvector(b, &b_updates.index((..3, ..)), &var_i.fixed_type);
-    update_b_subvector(b, &b_updates.index((3.., ..)), &var_j.fixed_type);
+    err_vec
+}
+
+pub fn calc_jacobian(factor: &Factor, var_i: &VehicleVariable2D, var_j: &VehicleVariable2D) -> DMatrix<f64> {
+    let (pos_i, rot_i) = get_pos_and_rot(&*var_i.pose.borrow());
+    let (pos_j, _) = get_pos_and_rot(&*var_j.pose.borrow());
+    let (_, rot_ij) = get_pos_and_rot(&factor.constraint);
+    let (jacobi, _) = calc_jacobians(&pos_i, rot_i, &pos_j, rot_ij);
+    DMatrix::from_column_slice(3, 6, jacobi.as_slice())
 }
 
 fn calc_jacobians(
@@ -70,7 +86,7 @@ fn calc_jacobians(
     let last_column_top = -sin_i * delta_pos[0] + cos_i * delta_pos[1];
     let last_column_mid = -cos_i * delta_pos[0] - sin_i * delta_pos[1];
     #[rustfmt::skip]
-    let jacobian_i = R_ij_T * &Matrix3::from_vec(vec![         -cos_i,           sin_i,  0.0,    // transposed matrix is displayed
+    let jacobian_i = R_ij_T * Matrix3::from_vec(vec![         -cos_i,           sin_i,  0.0,    // transposed matrix is displayed
                                                                -sin_i,          -cos_i,  0.0,
                                                       last_column_top, last_column_mid, -1.0,]);
     let jacobian_j = R_ij_T * Rotation3::from_axis_angle(&Vector3::z_axis(), -rot_i).matrix();
@@ -81,15 +97,13 @@ fn calc_jacobians(
 }
 
 fn update_H_submatrix(
-    H: &mut DMatrix<f64>,
+    H: &mut BlockSparseMatrix,
     added_matrix: &Matrix<f64, Dynamic, Dynamic, SliceStorage<f64, Dynamic, Dynamic, U1, U6>>,
     row_type: &FixedType,
     col_type: &FixedType,
 ) {
     if let (FixedType::NonFixed(row_range), FixedType::NonFixed(col_range)) = (row_type, col_type) {
-        let (row_range, col_range) = (row_range.to_owned(), col_range.to_owned());
-        let updated_submatrix = &(H.index((row_range.clone(), col_range.clone())) + added_matrix);
-        H.index_mut((row_range, col_range)).copy_from(updated_submatrix);
+        H.add_block(row_range.to_owned(), col_range.to_owned(), added_matrix);
     }
 }
 
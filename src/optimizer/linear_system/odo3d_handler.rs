@@ -18,14 +18,15 @@ use crate::factor_graph::variable::{FixedType, VehicleVariable3D};
 use crate::optimizer::linear_system::iso3d_gradients::{
     calc_dq_dR, get_isometry, skew_matr_T_and_mult_parts, skew_matr_and_mult_parts, skew_trans,
 };
+use crate::optimizer::block_sparse::BlockSparseMatrix;
 use nalgebra::{
-    DMatrix, DVector, Dynamic, Isometry3, Matrix, Matrix3, Matrix6, MatrixMN, RowVector6, SliceStorage, Vector, U1,
-    U12, U6,
+    DMatrix, DVector, Dynamic, Isometry3, Matrix, Matrix3, Matrix6, OMatrix, RowVector6, SliceStorage, Vector, U1, U12,
+    U6,
 };
 
 
 pub fn update_H_b(
-    H: &mut DMatrix<f64>,
+    H: &mut BlockSparseMatrix,
     b: &mut DVector<f64>,
     factor: &Factor,
     var_i: &VehicleVariable3D,
@@ -43,19 +44,37 @@ pub fn update_H_b(
     update_H_submatrix(H, &H_updates.index((6.., ..6)), &var_j.fixed_type, &var_i.fixed_type);
     update_H_submatrix(H, &H_updates.index((6.., 6..)), &var_j.fixed_type, &var_j.fixed_type);
 
-    let err = iso_ij.inverse() * iso_i.inverse() * iso_j;
-    let mut err_vec = err.translation.vector.data.as_slice().to_vec();
-    err_vec.extend_from_slice(&err.rotation.quaternion().coords.data.as_slice().to_vec()[..3]);
-    let b_updates = (RowVector6::from_vec(err_vec) * &right_mult).transpose();
+    let b_updates = (RowVector6::from_vec(calc_error(factor, var_i, var_j)) * &right_mult).transpose();
     update_b_subvector(b, &b_updates.index((..6, ..)), &var_i.fixed_type);
     update_b_subvector(b, &b_updates.index((6.., ..)), &var_j.fixed_type);
 }
 
+pub fn calc_error(factor: &Factor, var_i: &VehicleVariable3D, var_j: &VehicleVariable3D) -> Vec<f64> {
+    let iso_i = get_isometry(&*var_i.pose.borrow());
+    let iso_j = get_isometry(&*var_j.pose.borrow());
+    let iso_ij = get_isometry(&factor.constraint);
+    let err = iso_ij.inverse() * iso_i.inverse() * iso_j;
+    let mut err_vec = err.translation.vector.data.as_slice().to_vec();
+    // like g2o, the quaternion with non-negative w is used, which is the one the Jacobians are derived for
+    let quaternion = err.rotation.quaternion();
+    let sign = if quaternion.w < 0.0 { -1.0 } else { 1.0 };
+    err_vec.extend(quaternion.imag().iter().map(|v| sign * v));
+    err_vec
+}
+
+pub fn calc_jacobian(factor: &Factor, var_i: &VehicleVariable3D, var_j: &VehicleVariable3D) -> DMatrix<f64> {
+    let iso_i = get_isometry(&*var_i.pose.borrow());
+    let iso_j = get_isometry(&*var_j.pose.borrow());
+    let iso_ij = get_isometry(&factor.constraint);
+    let (jacobi, _) = calc_jacobians(&iso_i, &iso_j, &iso_ij);
+    DMatrix::from_column_slice(6, 12, jacobi.as_slice())
+}
+
 fn calc_jacobians(
     iso_i: &Isometry3<f64>,
     iso_j: &Isometry3<f64>,
     iso_ij: &Isometry3<f64>,
-) -> (MatrixMN<f64, U6, U12>, MatrixMN<f64, U12, U6>) {
+) -> (OMatrix<f64, U6, U12>, OMatrix<f64, U12, U6>) {
     let A_ij = iso_ij.inverse();
     let B_ij = iso_i.inverse() * iso_j;
     let Err_ij = A_ij * B_ij;
@@ -78,22 +97,20 @@ fn calc_jacobians(
         .index_mut((3.., 3..))
         .copy_from(&(dq_dR * skew_matr_and_mult_parts(&Matrix3::<f64>::identity(), Err_rot.matrix())));
 
-    let mut jacobian = MatrixMN::<f64, U6, U12>::from_vec(vec![0.0; 72]);
+    let mut jacobian = OMatrix::<f64, U6, U12>::from_vec(vec![0.0; 72]);
     jacobian.index_mut((.., ..6)).copy_from(&jacobian_i);
     jacobian.index_mut((.., 6..)).copy_from(&jacobian_j);
     (jacobian, jacobian.transpose())
 }
 
 fn update_H_submatrix(
-    H: &mut DMatrix<f64>,
+    H: &mut BlockSparseMatrix,
     added_matrix: &Matrix<f64, Dynamic, Dynamic, SliceStorage<f64, Dynamic, Dynamic, U1, U12>>,
     row_type: &FixedType,
     col_type: &FixedType,
 ) {
     if let (FixedType::NonFixed(row_range), FixedType::NonFixed(col_range)) = (row_type, col_type) {
-        let (row_range, col_range) = (row_range.to_owned(), col_range.to_owned());
-        let updated_submatrix = &(H.index((row_range.clone(), col_range.clone())) + added_matrix);
-        H.index_mut((row_range, col_range)).copy_from(updated_submatrix);
+        H.add_block(row_range.to_owned(), col_range.to_owned(), added_matrix);
     }
 }
 
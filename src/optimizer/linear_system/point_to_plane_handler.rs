@@ -0,0 +1,184 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Points measured in the frame of a vehicle pose in 3D which lie on an infinite plane landmark, e.g. LiDAR points of
+//! walls, floors and ceilings.
+//!
+//! The error of each point p is its signed distance n * (R * p + t) - d, after transforming it into the world frame by
+//! the vehicle's pose (R, t), to the plane with the unit normal n and the distance d. All points of a scan which lie
+//! on the same plane belong to one factor, whose information matrix is the inverse of the covariance of their
+//! distances.
+
+#![allow(non_snake_case)]
+
+use crate::factor_graph::factor::Factor;
+use crate::factor_graph::variable::parameterization::get_plane_tangent_basis;
+use crate::factor_graph::variable::Variable;
+use crate::optimizer::block_sparse::BlockSparseMatrix;
+use crate::optimizer::linear_system::add_to_H_b;
+use crate::optimizer::linear_system::iso3d_gradients::get_isometry;
+use nalgebra::{DMatrix, DVector, Isometry3, Point3, Vector3};
+
+pub fn update_H_b(H: &mut BlockSparseMatrix, b: &mut DVector<f64>, factor: &Factor, vars: &[&Variable]) {
+    let jacobi = calc_jacobian(factor, vars);
+    let err = calc_error(factor, vars);
+    add_to_H_b(H, b, &factor.information_matrix.content, &jacobi, &err, vars);
+}
+
+pub fn calc_error(factor: &Factor, vars: &[&Variable]) -> Vec<f64> {
+    let (pose, normal, distance) = get_pose_and_plane(vars);
+    get_points(factor)
+        .map(|point| normal.dot(&(pose * point).coords) - distance)
+        .collect()
+}
+
+/// Calculates the Jacobian with respect to the vehicle and the plane, with one row per point.
+pub fn calc_jacobian(factor: &Factor, vars: &[&Variable]) -> DMatrix<f64> {
+    let (pose, normal, _) = get_pose_and_plane(vars);
+    let local_normal = pose.rotation.inverse() * normal;
+    let [e_a, e_b] = get_plane_tangent_basis(&normal);
+    let mut jacobian = DMatrix::zeros(factor.constraint.len() / 3, 9);
+    for (row, point) in get_points(factor).enumerate() {
+        let world_point = pose * point;
+        // the translation is corrected in the vehicle's frame, the rotation by 2v from the right
+        jacobian
+            .fixed_slice_mut::<1, 3>(row, 0)
+            .copy_from(&local_normal.transpose());
+        jacobian
+            .fixed_slice_mut::<1, 3>(row, 3)
+            .copy_from(&(2.0 * point.coords.cross(&local_normal)).transpose());
+        // the normal is rotated along its tangent basis
+        jacobian[(row, 6)] = e_a.dot(&world_point.coords);
+        jacobian[(row, 7)] = e_b.dot(&world_point.coords);
+        jacobian[(row, 8)] = -1.0;
+    }
+    jacobian
+}
+
+// returns the measured points in the vehicle's frame
+fn get_points(factor: &Factor) -> impl Iterator<Item = Point3<f64>> + '_ {
+    factor.constraint.chunks(3).map(|c| Point3::new(c[0], c[1], c[2]))
+}
+
+// returns the vehicle's pose, the plane's unit normal and its distance
+fn get_pose_and_plane(vars: &[&Variable]) -> (Isometry3<f64>, Vector3<f64>, f64) {
+    match (vars[0], vars[1]) {
+        (Variable::Vehicle3D(var_i), Variable::Plane3D(var_j)) => {
+            let plane = var_j.plane.borrow();
+            (
+                get_isometry(&*var_i.pose.borrow()),
+                Vector3::new(plane[0], plane[1], plane[2]).normalize(),
+                plane[3],
+            )
+        }
+        _ => unreachable!("No valid edge."),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::factor_graph::factor::FactorId;
+    use crate::factor_graph::variable::VariableId;
+    use crate::optimizer::handler_check::{build_factor_graph, check_factor};
+    use crate::optimizer::{optimize, total_chi2};
+    use crate::parser::model::Edge;
+    use approx::assert_relative_eq;
+    use nalgebra::{Isometry3, Translation3, UnitQuaternion};
+
+    fn get_edge(vehicle: usize, plane: usize, points: &[Point3<f64>]) -> Edge {
+        let dim = points.len();
+        Edge {
+            edge_type: String::from("PointToPlane3D"),
+            vertices: vec![VariableId(vehicle), VariableId(plane)],
+            restriction: points.iter().flat_map(|p| p.coords.data.as_slice().to_vec()).collect(),
+            information_matrix: (0..dim * dim)
+                .map(|i| if i % (dim + 1) == 0 { 1.0 } else { 0.0 })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn test_handler() {
+        let pose = Isometry3::from_parts(
+            Translation3::new(0.3, -0.2, 0.1),
+            UnitQuaternion::from_euler_angles(0.2, -0.4, 0.6),
+        );
+        let (normal, distance) = (Vector3::new(0.2, -0.3, 0.9).normalize(), 1.5);
+        let [e_a, e_b] = get_plane_tangent_basis(&normal);
+        let points: Vec<Point3<f64>> = [(1.2, 0.5), (-0.3, 2.0)]
+            .iter()
+            .map(|(a, b)| pose.inverse_transform_point(&Point3::from(distance * normal + *a * e_a + *b * e_b)))
+            .collect();
+        let (t, q) = (pose.translation.vector, pose.rotation.coords);
+        let factor_graph = build_factor_graph(
+            &[
+                ("Vehicle3D", vec![t.x, t.y, t.z, q[0], q[1], q[2], q[3]]),
+                ("Plane3D", vec![normal.x, normal.y, normal.z, distance]),
+            ],
+            vec![get_edge(0, 1, &points)],
+            &[],
+        );
+        assert_eq!(check_factor(&factor_graph, FactorId(0), 1e-6), Ok(()));
+    }
+
+    #[test]
+    fn test_planes_localize_vehicle_and_map_ceiling() {
+        // the floor z = 0 and the walls x = 3 and y = 4 are known, the ceiling z = 2.5 is mapped
+        let planes = [
+            [0.0, 0.0, 1.0, 0.0],
+            [1.0, 0.0, 0.0, 3.0],
+            [0.0, 1.0, 0.0, 4.0],
+            [0.0, 0.0, 1.0, 2.5],
+        ];
+        let pose = Isometry3::from_parts(
+            Translation3::new(0.5, 0.2, 1.0),
+            UnitQuaternion::from_euler_angles(0.05, -0.1, 0.3),
+        );
+        let mut edges = vec![];
+        for (k, plane) in planes.iter().enumerate() {
+            let normal = Vector3::new(plane[0], plane[1], plane[2]);
+            let [e_a, e_b] = get_plane_tangent_basis(&normal);
+            let points: Vec<Point3<f64>> = [(0.0, 0.0), (1.0, 0.5), (-0.5, 1.5), (2.0, -1.0)]
+                .iter()
+                .map(|(a, b)| pose.inverse_transform_point(&Point3::from(plane[3] * normal + *a * e_a + *b * e_b)))
+                .collect();
+            edges.push(get_edge(0, k + 1, &points));
+        }
+        let factor_graph = build_factor_graph(
+            &[
+                ("Vehicle3D", vec![0.7, 0.0, 1.2, 0.0, 0.0, 0.1, 0.994987]),
+                ("Plane3D", planes[0].to_vec()),
+                ("Plane3D", planes[1].to_vec()),
+                ("Plane3D", planes[2].to_vec()),
+                ("Plane3D", vec![0.1, -0.1, 1.0, 2.2]),
+            ],
+            edges,
+            &[1, 2, 3],
+        );
+        assert!(total_chi2(&factor_graph) > 1e-2);
+        optimize(&factor_graph, 20);
+        assert!(total_chi2(&factor_graph) < 1e-12);
+        let content = factor_graph.get_var_by_id(VariableId(0)).unwrap().get_content();
+        let estimate = get_isometry(&content);
+        assert_relative_eq!(
+            (estimate.translation.vector - pose.translation.vector).norm(),
+            0.0,
+            epsilon = 1e-6
+        );
+        assert_relative_eq!(estimate.rotation.angle_to(&pose.rotation), 0.0, epsilon = 1e-6);
+        let ceiling = factor_graph.get_var_by_id(VariableId(4)).unwrap().get_content();
+        for (actual, expected) in ceiling.iter().zip(&planes[3]) {
+            assert_relative_eq!(actual, expected, epsilon = 1e-6);
+        }
+    }
+}
@@ -15,6 +15,7 @@
 
 use crate::factor_graph::factor::Factor;
 use crate::factor_graph::variable::{FixedType, VehicleVariable2D};
+use crate::optimizer::block_sparse::BlockSparseMatrix;
 use nalgebra::{
     ArrayStorage, DMatrix, DVector, Matrix, Matrix3, Rotation2, Rotation3, RowVector3, Vector, Vector2, Vector3, U3,
 };
@@ -22,21 +23,27 @@ use std::{f64::consts::PI, ops::Range};
 
 
 
-pub fn update_H_b(H: &mut DMatrix<f64>, b: &mut DVector<f64>, factor: &Factor, var: &VehicleVariable2D) {
+pub fn update_H_b(H: &mut BlockSparseMatrix, b: &mut DVector<f64>, factor: &Factor, var: &VehicleVariable2D) {
     let range = if let FixedType::NonFixed(range) = &var.fixed_type {
         range
     } else {
         return;
     };
 
-    let (pos_v, rot_v) = get_pos_and_rot(&*var.pose.borrow());
-    let (pos_m, rot_m) = get_pos_and_rot(&factor.constraint);
+    let (_, rot_m) = get_pos_and_rot(&factor.constraint);
     let (jacobi, jacobi_T) = calc_jacobians(rot_m);
     let right_mult = &factor.information_matrix.content * jacobi;
 
     let H_update = jacobi_T * &right_mult;
     update_H_submatrix(H, &H_update, range.to_owned());
 
+    let b_update = (RowVector3::from_vec(calc_error(factor, var)) * &right_mult).transpose();
+    update_b_subvector(b, &b_update, range.to_owned());
+}
+
+pub fn calc_error(factor: &Factor, var: &VehicleVariable2D) -> Vec<f64> {
+    let (pos_v, rot_v) = get_pos_and_rot(&*var.pose.borrow());
+    let (pos_m, rot_m) = get_pos_and_rot(&factor.constraint);
     let err_pos = Rotation2::new(-rot_m) * (pos_v - pos_m);
     let mut err_rot = rot_v - rot_m;
     if err_rot > PI {
@@ -46,8 +53,13 @@ pub fn update_H_b(H: &mut DMatrix<f64>, b: &mut DVector<f64>, factor: &Factor, v
     }
     let mut err_vec = err_pos.data.as_slice().to_vec();
     err_vec.push(err_rot);
-    let b_update = (RowVector3::from_vec(err_vec) * &right_mult).transpose();
-    update_b_subvector(b, &b_update, range.to_owned());
+    err_vec
+}
+
+pub fn calc_jacobian(factor: &Factor) -> DMatrix<f64> {
+    let (_, rot_m) = get_pos_and_rot(&factor.constraint);
+    let (jacobi, _) = calc_jacobians(rot_m);
+    DMatrix::from_column_slice(3, 3, jacobi.as_slice())
 }
 
 fn calc_jacobians(rot_m: f64) -> (Matrix3<f64>, Matrix3<f64>) {
@@ -56,12 +68,11 @@ fn calc_jacobians(rot_m: f64) -> (Matrix3<f64>, Matrix3<f64>) {
 }
 
 fn update_H_submatrix(
-    H: &mut DMatrix<f64>,
+    H: &mut BlockSparseMatrix,
     added_matrix: &Matrix<f64, U3, U3, ArrayStorage<f64, 3, 3>>,
     range: Range<usize>,
 ) {
-    let updated_submatrix = &(H.index((range.clone(), range.clone())) + added_matrix);
-    H.index_mut((range.clone(), range)).copy_from(updated_submatrix);
+    H.add_block(range.clone(), range, added_matrix);
 }
 
 fn update_b_subvector(
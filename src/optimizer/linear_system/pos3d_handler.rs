@@ -16,11 +16,12 @@
 use crate::factor_graph::factor::Factor;
 use crate::factor_graph::variable::{FixedType, VehicleVariable3D};
 use crate::optimizer::linear_system::iso3d_gradients::{calc_dq_dR, get_isometry, skew_matr_and_mult_parts};
+use crate::optimizer::block_sparse::BlockSparseMatrix;
 use nalgebra::{ArrayStorage, DMatrix, DVector, Isometry3, Matrix, Matrix3, Matrix6, RowVector6, Vector, U6};
 use std::ops::Range;
 
 
-pub fn update_H_b(H: &mut DMatrix<f64>, b: &mut DVector<f64>, factor: &Factor, var: &VehicleVariable3D) {
+pub fn update_H_b(H: &mut BlockSparseMatrix, b: &mut DVector<f64>, factor: &Factor, var: &VehicleVariable3D) {
     let range = if let FixedType::NonFixed(range) = &var.fixed_type {
         range
     } else {
@@ -35,13 +36,27 @@ pub fn update_H_b(H: &mut DMatrix<f64>, b: &mut DVector<f64>, factor: &Factor, v
     let H_update = jacobi_T * &right_mult;
     update_H_submatrix(H, &H_update, range);
 
-    let err = iso_m.inverse() * iso_v;
-    let mut err_vec = err.translation.vector.data.as_slice().to_vec();
-    err_vec.extend_from_slice(&err.rotation.quaternion().coords.data.as_slice().to_vec()[..3]);
-    let b_update = (RowVector6::from_vec(err_vec) * &right_mult).transpose();
+    let b_update = (RowVector6::from_vec(calc_error(factor, var)) * &right_mult).transpose();
     update_b_subvector(b, &b_update, range);
 }
 
+pub fn calc_error(factor: &Factor, var: &VehicleVariable3D) -> Vec<f64> {
+    let err = get_isometry(&factor.constraint).inverse() * get_isometry(&*var.pose.borrow());
+    let mut err_vec = err.translation.vector.data.as_slice().to_vec();
+    // like g2o, the quaternion with non-negative w is used, which is the one the Jacobians are derived for
+    let quaternion = err.rotation.quaternion();
+    let sign = if quaternion.w < 0.0 { -1.0 } else { 1.0 };
+    err_vec.extend(quaternion.imag().iter().map(|v| sign * v));
+    err_vec
+}
+
+pub fn calc_jacobian(factor: &Factor, var: &VehicleVariable3D) -> DMatrix<f64> {
+    let iso_v = get_isometry(&*var.pose.borrow());
+    let iso_m = get_isometry(&factor.constraint);
+    let (jacobi, _) = calc_jacobians(&iso_v, &iso_m);
+    DMatrix::from_column_slice(6, 6, jacobi.as_slice())
+}
+
 fn calc_jacobians(iso_v: &Isometry3<f64>, iso_m: &Isometry3<f64>) -> (Matrix6<f64>, Matrix6<f64>) {
     let Err_iso = iso_m.inverse() * iso_v;
     let Err_rot = Err_iso.rotation.to_rotation_matrix();
@@ -57,13 +72,11 @@ fn calc_jacobians(iso_v: &Isometry3<f64>, iso_m: &Isometry3<f64>) -> (Matrix6<f6
 }
 
 fn update_H_submatrix(
-    H: &mut DMatrix<f64>,
+    H: &mut BlockSparseMatrix,
     added_matrix: &Matrix<f64, U6, U6, ArrayStorage<f64, 6, 6>>,
     range: &Range<usize>,
 ) {
-    let updated_submatrix = &(H.index((range.to_owned(), range.to_owned())) + added_matrix);
-    H.index_mut((range.to_owned(), range.to_owned()))
-        .copy_from(updated_submatrix);
+    H.add_block(range.to_owned(), range.to_owned(), added_matrix);
 }
 
 fn update_b_subvector(
@@ -0,0 +1,110 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Position-only priors on vehicle poses in 3D, e.g. from GPS or ground-truth anchors.
+//!
+//! The constraint is the measured position of the vehicle in the world frame, its information matrix the inverse of
+//! the position's 3x3 covariance. The vehicle's orientation does not influence the error.
+
+#![allow(non_snake_case)]
+
+use crate::factor_graph::factor::Factor;
+use crate::factor_graph::variable::Variable;
+use crate::optimizer::block_sparse::BlockSparseMatrix;
+use crate::optimizer::linear_system::add_to_H_b;
+use crate::optimizer::linear_system::iso3d_gradients::get_isometry;
+use nalgebra::{DMatrix, DVector};
+
+pub fn update_H_b(H: &mut BlockSparseMatrix, b: &mut DVector<f64>, factor: &Factor, vars: &[&Variable]) {
+    let jacobi = calc_jacobian(vars);
+    let err = calc_error(factor, vars);
+    add_to_H_b(H, b, &factor.information_matrix.content, &jacobi, &err, vars);
+}
+
+pub fn calc_error(factor: &Factor, vars: &[&Variable]) -> Vec<f64> {
+    let pose = vars[0].get_content();
+    (0..3).map(|k| pose[k] - factor.constraint[k]).collect()
+}
+
+/// Calculates the Jacobian with respect to the vehicle.
+///
+/// Since the correction of 3D poses is applied in the vehicle's frame, the translational part is the vehicle's
+/// rotation matrix, while rotational corrections do not move the vehicle's position.
+pub fn calc_jacobian(vars: &[&Variable]) -> DMatrix<f64> {
+    let rotation = get_isometry(&vars[0].get_content()).rotation.to_rotation_matrix();
+    let mut jacobian = DMatrix::zeros(3, 6);
+    jacobian.columns_mut(0, 3).copy_from(rotation.matrix());
+    jacobian
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::factor_graph::factor::{FactorId, FactorType};
+    use crate::factor_graph::variable::VariableId;
+    use crate::factor_graph::FactorGraph;
+    use crate::optimizer::handler_check::{build_edge, build_factor_graph, check_factor};
+    use crate::optimizer::{optimize, total_chi2};
+    use crate::parser::g2o::G2oParser;
+    use crate::parser::Parser;
+    use approx::assert_relative_eq;
+
+    const INFORMATION_3D: &str = "1 0 0 1 0 1";
+
+    #[test]
+    fn test_handler() {
+        let factor_graph = build_factor_graph(
+            &[("Vehicle3D", vec![0.3, -0.2, 0.1, 0.1, -0.2, 0.3, 0.927362])],
+            vec![build_edge(
+                "PositionOnly3D",
+                &[0],
+                vec![0.3, -0.2, 0.1],
+                vec![1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0],
+            )],
+            &[],
+        );
+        assert_eq!(
+            factor_graph.get_factor(FactorId(0)).unwrap().factor_type,
+            FactorType::PositionOnly3D
+        );
+        assert_eq!(check_factor(&factor_graph, FactorId(0), 1e-6), Ok(()));
+    }
+
+    #[test]
+    fn test_gps_anchored_trajectory() {
+        // the poses move one meter forward and one meter to the left while facing along the y axis, which the GPS
+        // positions only determine together with the odometry
+        let information_6d = "100 0 0 0 0 0 100 0 0 0 0 100 0 0 0 100 0 0 100 0 100";
+        let initial_rotation = "0 0 0.5 0.8660254037844386";
+        let factor_graph: FactorGraph = G2oParser::parse_string_to_model(
+            &[
+                format!("VERTEX_SE3:QUAT 0 0.2 -0.1 0.1 {}", initial_rotation),
+                format!("VERTEX_SE3:QUAT 1 0.5 0.8 0 {}", initial_rotation),
+                format!("VERTEX_SE3:QUAT 2 -0.6 1.3 0 {}", initial_rotation),
+                format!("EDGE_SE3:QUAT 0 1 1 0 0 0 0 0 1 {}", information_6d),
+                format!("EDGE_SE3:QUAT 1 2 0 1 0 0 0 0 1 {}", information_6d),
+                format!("EDGE_SE3_XYZPRIOR 0 0 0 0 0 {}", INFORMATION_3D),
+                format!("EDGE_SE3_XYZPRIOR 1 0 0 1 0 {}", INFORMATION_3D),
+                format!("EDGE_SE3_XYZPRIOR 2 0 -1 1 0 {}", INFORMATION_3D),
+            ]
+            .join("\n"),
+        )
+        .unwrap()
+        .into();
+        optimize(&factor_graph, 20);
+        assert!(total_chi2(&factor_graph) < 1e-12);
+        let pose = factor_graph.get_var_by_id(VariableId(1)).unwrap().get_content();
+        assert_relative_eq!(pose[0], 0.0, epsilon = 1e-6);
+        assert_relative_eq!(pose[1], 1.0, epsilon = 1e-6);
+        assert_relative_eq!(pose[5].abs(), 0.5_f64.sqrt(), epsilon = 1e-6);
+        assert_relative_eq!(pose[6].abs(), 0.5_f64.sqrt(), epsilon = 1e-6);
+    }
+}
@@ -0,0 +1,209 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Pixel observations of landmarks in 3D by a pinhole camera at a vehicle pose, e.g. for bundle adjustment.
+//!
+//! The camera's optical axis is the z axis of the vehicle frame, its x axis points to the right of the image and its
+//! y axis downwards. The constraint contains the measured pixel and the camera's fixed intrinsics, the error is the
+//! difference between the projection of the landmark and the measured pixel. Landmarks are expected to be in front
+//! of the camera, i.e. to have a positive z coordinate in the vehicle frame.
+
+#![allow(non_snake_case)]
+
+use crate::factor_graph::factor::Factor;
+use crate::factor_graph::variable::Variable;
+use crate::optimizer::block_sparse::BlockSparseMatrix;
+use crate::optimizer::linear_system::add_to_H_b;
+use crate::optimizer::linear_system::iso3d_gradients::get_isometry;
+use nalgebra::{DMatrix, DVector, Point3, Vector3};
+
+pub fn update_H_b(H: &mut BlockSparseMatrix, b: &mut DVector<f64>, factor: &Factor, vars: &[&Variable]) {
+    let jacobi = calc_jacobian(factor, vars);
+    let err = calc_error(factor, vars);
+    add_to_H_b(H, b, &factor.information_matrix.content, &jacobi, &err, vars);
+}
+
+pub fn calc_error(factor: &Factor, vars: &[&Variable]) -> Vec<f64> {
+    let pixel = project(&factor.constraint, &get_local_position(vars));
+    (0..2).map(|k| pixel[k] - factor.constraint[k]).collect()
+}
+
+/// Calculates the Jacobian with respect to the vehicle and the landmark.
+///
+/// It is the chain of the derivative of the projection and the derivative of the landmark's position in the vehicle
+/// frame p, which is -I for the translation of the vehicle, 2 * [p]× for the vector part of its rotation and the
+/// inverse of its rotation for the landmark.
+pub fn calc_jacobian(factor: &Factor, vars: &[&Variable]) -> DMatrix<f64> {
+    let (z, local) = (&factor.constraint, get_local_position(vars));
+    #[rustfmt::skip]
+    let projection_jacobian = DMatrix::from_row_slice(2, 3, &[
+        z[2] / local.z, 0.0, -z[2] * local.x / local.z.powi(2),
+        0.0, z[3] / local.z, -z[3] * local.y / local.z.powi(2),
+    ]);
+    chain_local_position_jacobian(&projection_jacobian, vars, &local)
+}
+
+// returns the chain of the given derivative with respect to the landmark's position in the vehicle frame and the
+// derivative of this position with respect to the vehicle and the landmark
+pub(crate) fn chain_local_position_jacobian(
+    local_jacobian: &DMatrix<f64>,
+    vars: &[&Variable],
+    local: &Vector3<f64>,
+) -> DMatrix<f64> {
+    let inverse_rotation = get_isometry(&vars[0].get_content())
+        .rotation
+        .inverse()
+        .to_rotation_matrix();
+    let mut jacobian = DMatrix::zeros(local_jacobian.nrows(), 9);
+    jacobian.columns_mut(0, 3).copy_from(&-local_jacobian);
+    jacobian
+        .columns_mut(3, 3)
+        .copy_from(&(local_jacobian * local.cross_matrix() * 2.0));
+    jacobian
+        .columns_mut(6, 3)
+        .copy_from(&(local_jacobian * inverse_rotation.matrix()));
+    jacobian
+}
+
+// returns the position of the landmark in the vehicle frame
+pub(crate) fn get_local_position(vars: &[&Variable]) -> Vector3<f64> {
+    let landmark = vars[1].get_content();
+    get_isometry(&vars[0].get_content())
+        .inverse_transform_point(&Point3::new(landmark[0], landmark[1], landmark[2]))
+        .coords
+}
+
+// returns the pixel of the position in the vehicle frame with the intrinsics of the constraint
+pub(crate) fn project(constraint: &[f64], local: &Vector3<f64>) -> [f64; 2] {
+    [
+        constraint[2] * local.x / local.z + constraint[4],
+        constraint[3] * local.y / local.z + constraint[5],
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::factor_graph::factor::FactorId;
+    use crate::factor_graph::variable::VariableId;
+    use crate::factor_graph::FactorGraph;
+    use crate::optimizer::handler_check::{build_factor_graph, check_factor};
+    use crate::optimizer::{optimize, total_chi2};
+    use crate::parser::model::{Edge, FactorGraphModel, Vertex};
+    use approx::assert_relative_eq;
+    use std::collections::BTreeSet;
+
+    const INTRINSICS: [f64; 4] = [500.0, 480.0, 320.0, 240.0];
+
+    fn get_vertex(id: usize, vertex_type: &str, content: Vec<f64>) -> Vertex {
+        Vertex {
+            id: VariableId(id),
+            vertex_type: String::from(vertex_type),
+            content,
+        }
+    }
+
+    fn get_edge(camera: usize, landmark: usize, pixel: [f64; 2]) -> Edge {
+        let mut restriction = pixel.to_vec();
+        restriction.extend_from_slice(&INTRINSICS);
+        Edge {
+            edge_type: String::from("Projection3D"),
+            vertices: vec![VariableId(camera), VariableId(landmark)],
+            restriction,
+            information_matrix: vec![1.0, 0.0, 0.0, 1.0],
+        }
+    }
+
+    #[test]
+    fn test_handler() {
+        let vertices = [
+            ("Vehicle3D", vec![0.3, -0.2, 0.1, 0.1, -0.2, 0.3, 0.927362]),
+            ("Landmark3D", vec![1.2, 0.5, 4.0]),
+        ];
+        let factor_graph = build_factor_graph(&vertices, vec![], &[]);
+        let local = get_local_position(&[
+            factor_graph.get_var_by_id(VariableId(0)).unwrap(),
+            factor_graph.get_var_by_id(VariableId(1)).unwrap(),
+        ]);
+        assert!(local.z > 0.0);
+        let mut restriction = vec![0.0, 0.0];
+        restriction.extend_from_slice(&INTRINSICS);
+        let factor_graph = build_factor_graph(&vertices, vec![get_edge(0, 1, project(&restriction, &local))], &[]);
+        assert_eq!(check_factor(&factor_graph, FactorId(0), 1e-6), Ok(()));
+    }
+
+    #[test]
+    fn test_bundle_adjustment_recovers_landmarks_and_camera() {
+        let cameras = [
+            vec![0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0],
+            vec![1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0],
+            vec![0.5, -0.5, 0.2, 0.05, -0.1, 0.0, 0.993730],
+        ];
+        let landmarks = [[0.2, 0.3, 4.0], [1.5, -0.4, 5.0], [-0.6, -0.2, 3.5], [0.8, 0.9, 6.0]];
+        let expected: FactorGraph = FactorGraphModel {
+            vertices: cameras
+                .iter()
+                .enumerate()
+                .map(|(id, camera)| get_vertex(id, "Vehicle3D", camera.clone()))
+                .chain(
+                    landmarks
+                        .iter()
+                        .enumerate()
+                        .map(|(id, landmark)| get_vertex(cameras.len() + id, "Landmark3D", landmark.to_vec())),
+                )
+                .collect(),
+            edges: vec![],
+            fixed_vertices: BTreeSet::new(),
+            ..Default::default()
+        }
+        .into();
+        let mut edges = vec![];
+        for camera in 0..cameras.len() {
+            for landmark in 0..landmarks.len() {
+                let id = cameras.len() + landmark;
+                let vars = [
+                    expected.get_var_by_id(VariableId(camera)).unwrap(),
+                    expected.get_var_by_id(VariableId(id)).unwrap(),
+                ];
+                let mut restriction = vec![0.0, 0.0];
+                restriction.extend_from_slice(&INTRINSICS);
+                edges.push(get_edge(camera, id, project(&restriction, &get_local_position(&vars))));
+            }
+        }
+
+        let mut vertices: Vec<Vertex> = FactorGraphModel::from(&expected).vertices;
+        vertices[2].content[0] += 0.1;
+        vertices[2].content[2] -= 0.1;
+        for (k, vertex) in vertices.iter_mut().skip(cameras.len()).enumerate() {
+            vertex.content[k % 3] += 0.2;
+        }
+        let factor_graph: FactorGraph = FactorGraphModel {
+            vertices,
+            edges,
+            fixed_vertices: [VariableId(0), VariableId(1)].iter().copied().collect(),
+            ..Default::default()
+        }
+        .into();
+        assert!(total_chi2(&factor_graph) > 1.0);
+        optimize(&factor_graph, 20);
+        assert!(total_chi2(&factor_graph) < 1e-12);
+        for id in 2..cameras.len() + landmarks.len() {
+            let (actual, expected) = (
+                factor_graph.get_var_by_id(VariableId(id)).unwrap().get_content(),
+                expected.get_var_by_id(VariableId(id)).unwrap().get_content(),
+            );
+            for (actual, expected) in actual.iter().zip(expected) {
+                assert_relative_eq!(actual, &expected, epsilon = 1e-6);
+            }
+        }
+    }
+}
@@ -0,0 +1,144 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Range-only measurements from a vehicle pose to a landmark in 2D or 3D, e.g. to UWB or acoustic beacons.
+//!
+//! The constraint is the measured distance between the vehicle's and the landmark's positions, its information
+//! matrix the inverse of the range's variance. The vehicle's orientation does not influence the error.
+
+#![allow(non_snake_case)]
+
+use crate::factor_graph::factor::Factor;
+use crate::factor_graph::variable::Variable;
+use crate::optimizer::block_sparse::BlockSparseMatrix;
+use crate::optimizer::linear_system::add_to_H_b;
+use crate::optimizer::linear_system::iso3d_gradients::get_isometry;
+use nalgebra::{DMatrix, DVector, Point3, Vector3};
+
+pub fn update_H_b(H: &mut BlockSparseMatrix, b: &mut DVector<f64>, factor: &Factor, vars: &[&Variable]) {
+    let jacobi = calc_jacobian(vars);
+    let err = calc_error(factor, vars);
+    add_to_H_b(H, b, &factor.information_matrix.content, &jacobi, &err, vars);
+}
+
+pub fn calc_error(factor: &Factor, vars: &[&Variable]) -> Vec<f64> {
+    vec![get_local_position(vars).norm() - factor.constraint[0]]
+}
+
+/// Calculates the Jacobian with respect to the vehicle and the landmark.
+///
+/// The Jacobian is undefined if the landmark coincides with the vehicle's position, in which case it is zero.
+pub fn calc_jacobian(vars: &[&Variable]) -> DMatrix<f64> {
+    let local_position = get_local_position(vars);
+    let range = local_position.norm();
+    let direction = if range == 0.0 {
+        local_position
+    } else {
+        &local_position / range
+    };
+    match vars[0] {
+        // the correction of 2D poses is applied in the world frame
+        Variable::Vehicle2D(_) => {
+            DMatrix::from_row_slice(1, 5, &[-direction[0], -direction[1], 0.0, direction[0], direction[1]])
+        }
+        // the correction of 3D poses is applied in the vehicle's frame, so the landmark's columns are rotated back
+        Variable::Vehicle3D(v) => {
+            let world_direction =
+                get_isometry(&*v.pose.borrow()).rotation * Vector3::new(direction[0], direction[1], direction[2]);
+            let mut jacobian = DMatrix::zeros(1, 9);
+            jacobian.columns_mut(0, 3).copy_from(&-direction.transpose());
+            jacobian.columns_mut(6, 3).copy_from(&world_direction.transpose());
+            jacobian
+        }
+        _ => unreachable!("No valid edge."),
+    }
+}
+
+// returns the landmark's position relative to the vehicle, in the world frame for 2D and in the vehicle's frame for
+// 3D poses
+fn get_local_position(vars: &[&Variable]) -> DVector<f64> {
+    match (vars[0], vars[1]) {
+        (Variable::Vehicle2D(var_i), Variable::Landmark2D(var_j)) => {
+            let (pose_i, pos_j) = (var_i.pose.borrow(), var_j.position.borrow());
+            DVector::from_vec(vec![pos_j[0] - pose_i[0], pos_j[1] - pose_i[1]])
+        }
+        (Variable::Vehicle3D(var_i), Variable::Landmark3D(var_j)) => {
+            let pos_j = var_j.position.borrow();
+            let local_j =
+                get_isometry(&*var_i.pose.borrow()).inverse_transform_point(&Point3::new(pos_j[0], pos_j[1], pos_j[2]));
+            DVector::from_column_slice(local_j.coords.as_slice())
+        }
+        _ => unreachable!("No valid edge."),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::factor_graph::factor::{FactorId, FactorType};
+    use crate::factor_graph::variable::VariableId;
+    use crate::optimizer::handler_check::{build_edge, build_factor_graph, check_factor};
+    use crate::optimizer::{optimize, total_chi2};
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn test_handlers() {
+        let factor_graph = build_factor_graph(
+            &[("Vehicle2D", vec![0.3, -0.2, 0.4]), ("Landmark2D", vec![2.0, 1.5])],
+            vec![build_edge("Range2D", &[0, 1], vec![1.7_f64.hypot(1.7)], vec![1.0])],
+            &[],
+        );
+        assert_eq!(
+            factor_graph.get_factor(FactorId(0)).unwrap().factor_type,
+            FactorType::Range2D
+        );
+        assert_eq!(check_factor(&factor_graph, FactorId(0), 1e-6), Ok(()));
+
+        let factor_graph = build_factor_graph(
+            &[
+                ("Vehicle3D", vec![0.3, -0.2, 0.1, 0.1, -0.2, 0.3, 0.927362]),
+                ("Landmark3D", vec![2.0, 1.5, -1.0]),
+            ],
+            vec![build_edge(
+                "Range3D",
+                &[0, 1],
+                vec![(1.7_f64.powi(2) * 2.0 + 1.1_f64.powi(2)).sqrt()],
+                vec![1.0],
+            )],
+            &[],
+        );
+        assert_eq!(check_factor(&factor_graph, FactorId(0), 1e-6), Ok(()));
+    }
+
+    #[test]
+    fn test_beacon_localization() {
+        // the vehicle at (1, 2) measures its ranges to three fixed beacons
+        let beacons = [[0.0, 0.0], [4.0, 0.0], [0.0, 4.0]];
+        let mut vertices = vec![("Vehicle2D", vec![2.0, 1.0, 0.3])];
+        let mut edges = vec![build_edge(
+            "Position2D",
+            &[0],
+            vec![0.0, 0.0, 0.0],
+            vec![0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0],
+        )];
+        for (i, beacon) in beacons.iter().enumerate() {
+            vertices.push(("Landmark2D", beacon.to_vec()));
+            let range = (beacon[0] - 1.0f64).hypot(beacon[1] - 2.0);
+            edges.push(build_edge("Range2D", &[0, i + 1], vec![range], vec![100.0]));
+        }
+        let factor_graph = build_factor_graph(&vertices, edges, &[1, 2, 3]);
+        optimize(&factor_graph, 10);
+        assert!(total_chi2(&factor_graph) < 1e-12);
+        let pose = factor_graph.get_var_by_id(VariableId(0)).unwrap().get_content();
+        assert_relative_eq!(pose[0], 1.0, epsilon = 1e-6);
+        assert_relative_eq!(pose[1], 2.0, epsilon = 1e-6);
+    }
+}
@@ -0,0 +1,169 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Relative measurements between two vehicle poses with a scale, i.e. between elements of Sim(3).
+//!
+//! A Sim(3) pose (R, t, s) maps a point p in its frame to s * R * p + t. The measurement is the pose of the target
+//! in the frame of the source, i.e. the rotation, the translation divided by the source's scale, and the ratio of
+//! the target's and the source's scale. The error is the difference between this relative pose and the measured one
+//! in the frame of the measurement: its translation, the vector part of its rotation quaternion with non-negative w
+//! and the logarithm of its scale. The Jacobians are calculated with automatic differentiation.
+
+#![allow(non_snake_case)]
+
+use crate::factor_graph::factor::{CustomResidual, Factor};
+use crate::factor_graph::variable::Variable;
+use crate::optimizer::autodiff::{quaternion_conjugate, quaternion_product, rotate_inverse, Dual, DualQuaternion};
+use crate::optimizer::block_sparse::BlockSparseMatrix;
+use crate::optimizer::linear_system::custom_handler;
+use nalgebra::{DMatrix, DVector, Quaternion, UnitQuaternion};
+
+pub fn update_H_b(H: &mut BlockSparseMatrix, b: &mut DVector<f64>, factor: &Factor, vars: &[&Variable]) {
+    custom_handler::update_H_b(H, b, factor, &get_residual(), vars);
+}
+
+pub fn calc_error(factor: &Factor, vars: &[&Variable]) -> Vec<f64> {
+    custom_handler::calc_error(factor, &get_residual(), vars)
+}
+
+pub fn calc_jacobian(factor: &Factor, vars: &[&Variable]) -> DMatrix<f64> {
+    custom_handler::calc_jacobian(factor, &get_residual(), vars)
+}
+
+fn get_residual() -> CustomResidual {
+    CustomResidual::new("OdometrySim3", calc_residual)
+}
+
+// expects the contents of the source and the target pose
+fn calc_residual(contents: &[Vec<Dual>], constraint: &[f64]) -> Vec<Dual> {
+    let (from, to) = (&contents[0], &contents[1]);
+    let rotation_from = [from[3], from[4], from[5], from[6]];
+    let rotation_to = [to[3], to[4], to[5], to[6]];
+    let delta: Vec<Dual> = (0..3).map(|k| to[k] - from[k]).collect();
+    let local_translation = rotate_inverse(&rotation_from, &delta);
+    let local_rotation = quaternion_product(&quaternion_conjugate(&rotation_from), &rotation_to);
+    let local_scale = to[7] / from[7];
+
+    let measured_rotation = get_measured_rotation(constraint);
+    let translation_error: Vec<Dual> = (0..3).map(|k| local_translation[k] / from[7] - constraint[k]).collect();
+    let translation_error = rotate_inverse(&measured_rotation, &translation_error);
+    let rotation_error = quaternion_product(&quaternion_conjugate(&measured_rotation), &local_rotation);
+    let sign = if rotation_error[3].value < 0.0 { -1.0 } else { 1.0 };
+    translation_error
+        .iter()
+        .map(|t| *t / constraint[7])
+        .chain(rotation_error[..3].iter().map(|v| *v * sign))
+        .chain(core::iter::once((local_scale / constraint[7]).ln()))
+        .collect()
+}
+
+// returns the normalized rotation of the measurement as constant dual quaternion
+fn get_measured_rotation(constraint: &[f64]) -> DualQuaternion {
+    let rotation = UnitQuaternion::from_quaternion(Quaternion::new(
+        constraint[6],
+        constraint[3],
+        constraint[4],
+        constraint[5],
+    ));
+    let coords = rotation.coords;
+    [
+        Dual::constant(coords[0]),
+        Dual::constant(coords[1]),
+        Dual::constant(coords[2]),
+        Dual::constant(coords[3]),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::factor_graph::factor::FactorId;
+    use crate::factor_graph::variable::VariableId;
+    use crate::optimizer::handler_check::{build_factor_graph, check_factor};
+    use crate::optimizer::{optimize, total_chi2};
+    use crate::parser::model::Edge;
+    use approx::assert_relative_eq;
+    use nalgebra::{Quaternion, UnitQuaternion, Vector3};
+    use std::f64::consts::FRAC_PI_4;
+
+    fn get_edge(from: usize, to: usize, restriction: Vec<f64>) -> Edge {
+        Edge {
+            edge_type: String::from("OdometrySim3"),
+            vertices: vec![VariableId(from), VariableId(to)],
+            restriction,
+            information_matrix: (0..49).map(|i| if i % 8 == 0 { 1.0 } else { 0.0 }).collect(),
+        }
+    }
+
+    // returns a pose with the given yaw as multiple of a quarter turn
+    fn get_pose(x: f64, y: f64, z: f64, quarter_turns: f64, scale: f64) -> Vec<f64> {
+        let (sin, cos) = (quarter_turns * FRAC_PI_4).sin_cos();
+        vec![x, y, z, 0.0, 0.0, sin, cos, scale]
+    }
+
+    // returns the measurement of the pose to in the frame of the pose from
+    fn get_relative_pose(from: &[f64], to: &[f64]) -> Vec<f64> {
+        let rotation =
+            |pose: &[f64]| UnitQuaternion::from_quaternion(Quaternion::new(pose[6], pose[3], pose[4], pose[5]));
+        let delta = Vector3::new(to[0] - from[0], to[1] - from[1], to[2] - from[2]);
+        let t = rotation(from).inverse_transform_vector(&delta) / from[7];
+        let q = (rotation(from).inverse() * rotation(to)).coords;
+        vec![t.x, t.y, t.z, q[0], q[1], q[2], q[3], to[7] / from[7]]
+    }
+
+    #[test]
+    fn test_handler() {
+        let poses = [
+            vec![0.3, -0.2, 0.1, 0.1, -0.2, 0.3, 0.927362, 1.3],
+            vec![1.2, 0.5, -0.4, -0.3, 0.1, 0.2, 0.927362, 0.8],
+        ];
+        let factor_graph = build_factor_graph(
+            &[("VehicleSim3", poses[0].clone()), ("VehicleSim3", poses[1].clone())],
+            vec![get_edge(0, 1, get_relative_pose(&poses[0], &poses[1]))],
+            &[],
+        );
+        assert_eq!(check_factor(&factor_graph, FactorId(0), 1e-6), Ok(()));
+    }
+
+    #[test]
+    fn test_loop_closure_corrects_scale_drift() {
+        // a unit square with a quarter turn at each corner, whose initial estimates drifted in scale
+        let step = get_pose(1.0, 0.0, 0.0, 1.0, 1.0);
+        let factor_graph = build_factor_graph(
+            &[
+                ("VehicleSim3", get_pose(0.0, 0.0, 0.0, 0.0, 1.0)),
+                ("VehicleSim3", get_pose(1.2, 0.1, 0.0, 1.1, 1.2)),
+                ("VehicleSim3", get_pose(1.3, 1.4, 0.1, 2.1, 1.4)),
+                ("VehicleSim3", get_pose(-0.1, 1.7, 0.1, 2.9, 1.7)),
+            ],
+            (0..4).map(|k| get_edge(k, (k + 1) % 4, step.clone())).collect(),
+            &[0],
+        );
+        assert!(total_chi2(&factor_graph) > 1e-2);
+        optimize(&factor_graph, 20);
+        assert!(total_chi2(&factor_graph) < 1e-12);
+        let expected = [
+            get_pose(1.0, 0.0, 0.0, 1.0, 1.0),
+            get_pose(1.0, 1.0, 0.0, 2.0, 1.0),
+            get_pose(0.0, 1.0, 0.0, 3.0, 1.0),
+        ];
+        for (k, pose) in expected.iter().enumerate() {
+            let content = factor_graph.get_var_by_id(VariableId(k + 1)).unwrap().get_content();
+            assert_relative_eq!(content[7], 1.0, epsilon = 1e-6);
+            for (actual, expected) in content[..3].iter().zip(&pose[..3]) {
+                assert_relative_eq!(actual, expected, epsilon = 1e-6);
+            }
+            // the quaternion is determined up to its sign
+            let dot: f64 = content[3..7].iter().zip(&pose[3..7]).map(|(a, b)| a * b).sum();
+            assert_relative_eq!(dot.abs(), 1.0, epsilon = 1e-6);
+        }
+    }
+}
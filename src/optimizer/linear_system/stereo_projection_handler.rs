@@ -0,0 +1,139 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Pixel observations of landmarks in 3D by a rectified stereo camera at a vehicle pose, e.g. for stereo visual
+//! odometry.
+//!
+//! The vehicle frame is the frame of the left camera, as for
+//! [projection_handler](../projection_handler/index.html), and the right camera is offset by the baseline along its
+//! x axis. Both cameras share their intrinsics, so a pixel observation consists of the x coordinates in the left and
+//! the right image and the common y coordinate. Their difference, the disparity, makes the depth of the landmark
+//! observable from a single pose.
+
+#![allow(non_snake_case)]
+
+use crate::factor_graph::factor::Factor;
+use crate::factor_graph::variable::Variable;
+use crate::optimizer::block_sparse::BlockSparseMatrix;
+use crate::optimizer::linear_system::add_to_H_b;
+use crate::optimizer::linear_system::projection_handler::{chain_local_position_jacobian, get_local_position};
+use nalgebra::{DMatrix, DVector, Vector3};
+
+pub fn update_H_b(H: &mut BlockSparseMatrix, b: &mut DVector<f64>, factor: &Factor, vars: &[&Variable]) {
+    let jacobi = calc_jacobian(factor, vars);
+    let err = calc_error(factor, vars);
+    add_to_H_b(H, b, &factor.information_matrix.content, &jacobi, &err, vars);
+}
+
+pub fn calc_error(factor: &Factor, vars: &[&Variable]) -> Vec<f64> {
+    let pixels = project(&factor.constraint, &get_local_position(vars));
+    (0..3).map(|k| pixels[k] - factor.constraint[k]).collect()
+}
+
+/// Calculates the Jacobian with respect to the vehicle and the landmark.
+///
+/// The derivative of the projection into the right image differs from the one into the left image by the baseline
+/// in the derivative with respect to the depth.
+pub fn calc_jacobian(factor: &Factor, vars: &[&Variable]) -> DMatrix<f64> {
+    let (z, local) = (&factor.constraint, get_local_position(vars));
+    let baseline = z[7];
+    #[rustfmt::skip]
+    let projection_jacobian = DMatrix::from_row_slice(3, 3, &[
+        z[3] / local.z, 0.0, -z[3] * local.x / local.z.powi(2),
+        z[3] / local.z, 0.0, -z[3] * (local.x - baseline) / local.z.powi(2),
+        0.0, z[4] / local.z, -z[4] * local.y / local.z.powi(2),
+    ]);
+    chain_local_position_jacobian(&projection_jacobian, vars, &local)
+}
+
+// returns the x coordinates in the left and the right image and the y coordinate of the position in the vehicle frame
+// with the intrinsics and the baseline of the constraint
+pub(crate) fn project(constraint: &[f64], local: &Vector3<f64>) -> [f64; 3] {
+    let (focal_length_x, focal_length_y) = (constraint[3], constraint[4]);
+    let (principal_point_x, principal_point_y, baseline) = (constraint[5], constraint[6], constraint[7]);
+    [
+        focal_length_x * local.x / local.z + principal_point_x,
+        focal_length_x * (local.x - baseline) / local.z + principal_point_x,
+        focal_length_y * local.y / local.z + principal_point_y,
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::factor_graph::factor::FactorId;
+    use crate::factor_graph::variable::VariableId;
+    use crate::optimizer::handler_check::{build_factor_graph, check_factor};
+    use crate::optimizer::{optimize, total_chi2};
+    use crate::parser::model::Edge;
+    use approx::assert_relative_eq;
+
+    const CALIBRATION: [f64; 5] = [450.0, 460.0, 320.0, 240.0, 0.12];
+
+    fn get_edge(camera: usize, landmark: usize, pixels: [f64; 3]) -> Edge {
+        let mut restriction = pixels.to_vec();
+        restriction.extend_from_slice(&CALIBRATION);
+        Edge {
+            edge_type: String::from("StereoProjection3D"),
+            vertices: vec![VariableId(camera), VariableId(landmark)],
+            restriction,
+            information_matrix: vec![1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0],
+        }
+    }
+
+    #[test]
+    fn test_handler() {
+        let vertices = [
+            ("Vehicle3D", vec![0.3, -0.2, 0.1, 0.1, -0.2, 0.3, 0.927362]),
+            ("Landmark3D", vec![1.2, 0.5, 4.0]),
+        ];
+        let factor_graph = build_factor_graph(&vertices, vec![], &[]);
+        let local = get_local_position(&[
+            factor_graph.get_var_by_id(VariableId(0)).unwrap(),
+            factor_graph.get_var_by_id(VariableId(1)).unwrap(),
+        ]);
+        let mut restriction = vec![0.0; 3];
+        restriction.extend_from_slice(&CALIBRATION);
+        let factor_graph = build_factor_graph(&vertices, vec![get_edge(0, 1, project(&restriction, &local))], &[]);
+        assert_eq!(check_factor(&factor_graph, FactorId(0), 1e-6), Ok(()));
+    }
+
+    #[test]
+    fn test_single_stereo_frame_recovers_depth() {
+        let landmarks = [[0.4, -0.3, 5.0], [-1.0, 0.2, 8.0]];
+        let mut restriction = vec![0.0; 3];
+        restriction.extend_from_slice(&CALIBRATION);
+        let edges = landmarks
+            .iter()
+            .enumerate()
+            .map(|(k, landmark)| get_edge(0, k + 1, project(&restriction, &Vector3::from(*landmark))))
+            .collect();
+        let factor_graph = build_factor_graph(
+            &[
+                ("Vehicle3D", vec![0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0]),
+                ("Landmark3D", vec![0.5, -0.2, 4.0]),
+                ("Landmark3D", vec![-0.8, 0.3, 10.0]),
+            ],
+            edges,
+            &[0],
+        );
+        assert!(total_chi2(&factor_graph) > 1.0);
+        optimize(&factor_graph, 20);
+        assert!(total_chi2(&factor_graph) < 1e-12);
+        for (k, landmark) in landmarks.iter().enumerate() {
+            let content = factor_graph.get_var_by_id(VariableId(k + 1)).unwrap().get_content();
+            for (actual, expected) in content.iter().zip(landmark) {
+                assert_relative_eq!(actual, expected, epsilon = 1e-6);
+            }
+        }
+    }
+}
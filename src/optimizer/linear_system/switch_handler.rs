@@ -0,0 +1,80 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Switchable constraints as described by Sünderhauf and Protzel, "Switchable Constraints for Robust Pose Graph SLAM".
+//!
+//! The error e of a switchable factor is scaled by its switch variable s, which is equivalent to scaling its
+//! information matrix by s². The Jacobian of s * e is [s * J | e], so the optimizer can switch off factors whose
+//! error is large compared to the cost of leaving the switch prior.
+
+#![allow(non_snake_case)]
+
+use crate::factor_graph::factor::{Factor, FactorType};
+use crate::factor_graph::variable::Variable;
+use crate::optimizer::block_sparse::BlockSparseMatrix;
+use crate::optimizer::linear_system::{add_to_H_b, odo2d_handler, odo3d_handler};
+use nalgebra::{DMatrix, DVector};
+
+pub fn update_H_b(H: &mut BlockSparseMatrix, b: &mut DVector<f64>, factor: &Factor, vars: &[&Variable]) {
+    let jacobi = calc_jacobian(factor, vars);
+    let err = calc_error(factor, vars);
+    add_to_H_b(H, b, &factor.information_matrix.content, &jacobi, &err, vars);
+}
+
+pub fn calc_error(factor: &Factor, vars: &[&Variable]) -> Vec<f64> {
+    if factor.factor_type == FactorType::SwitchPrior {
+        return vec![get_switch(vars[0]) - factor.constraint[0]];
+    }
+    let switch = get_switch(vars[2]);
+    calc_unswitched_error(factor, vars).iter().map(|e| switch * e).collect()
+}
+
+/// Calculates the Jacobian with respect to the source, the target and the switch variable, or only the switch
+/// variable for switch priors.
+pub fn calc_jacobian(factor: &Factor, vars: &[&Variable]) -> DMatrix<f64> {
+    if factor.factor_type == FactorType::SwitchPrior {
+        return DMatrix::identity(1, 1);
+    }
+    let switch = get_switch(vars[2]);
+    let unswitched_jacobian = match (vars[0], vars[1]) {
+        (Variable::Vehicle2D(var_i), Variable::Vehicle2D(var_j)) => odo2d_handler::calc_jacobian(factor, var_i, var_j),
+        (Variable::Vehicle3D(var_i), Variable::Vehicle3D(var_j)) => odo3d_handler::calc_jacobian(factor, var_i, var_j),
+        _ => unreachable!("No valid edge."),
+    };
+    let err = DVector::from_vec(calc_unswitched_error(factor, vars));
+    let pose_cols = unswitched_jacobian.ncols();
+    let mut jacobian = DMatrix::zeros(unswitched_jacobian.nrows(), pose_cols + 1);
+    jacobian
+        .columns_mut(0, pose_cols)
+        .copy_from(&(unswitched_jacobian * switch));
+    jacobian.column_mut(pose_cols).copy_from(&err);
+    jacobian
+}
+
+fn calc_unswitched_error(factor: &Factor, vars: &[&Variable]) -> Vec<f64> {
+    match (&factor.factor_type, vars[0], vars[1]) {
+        (FactorType::SwitchableOdometry2D, Variable::Vehicle2D(var_i), Variable::Vehicle2D(var_j)) => {
+            odo2d_handler::calc_error(factor, var_i, var_j)
+        }
+        (FactorType::SwitchableOdometry3D, Variable::Vehicle3D(var_i), Variable::Vehicle3D(var_j)) => {
+            odo3d_handler::calc_error(factor, var_i, var_j)
+        }
+        _ => unreachable!("No valid edge."),
+    }
+}
+
+fn get_switch(var: &Variable) -> f64 {
+    match var {
+        Variable::Switch(v) => v.value.borrow()[0],
+        _ => unreachable!("No valid edge."),
+    }
+}
@@ -0,0 +1,261 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Alignment of a new session with an existing map, similar to ICP (iterative closest point), but for factor graphs.
+//!
+//! Each iteration associates the session's vehicle poses with the closest poses of the prior map, links them with
+//! priors at the map poses and optimizes the session. The iterations stop as soon as the associations do not change
+//! anymore, i.e. when the session has converged onto the map.
+
+use crate::factor_graph::factor::{FactorId, FactorType};
+use crate::factor_graph::variable::{FixedType, Variable, VariableId};
+use crate::factor_graph::FactorGraph;
+use crate::optimizer::optimize;
+use core::mem::discriminant;
+use nalgebra::DMatrix;
+
+/// Configuration of [align_to_map](fn.align_to_map.html).
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapAlignmentConfig {
+    /// The maximum distance between the positions of a session pose and a map pose, up to which they are associated.
+    pub association_distance: f64,
+    /// The maximum number of alternations between the data association and the optimization.
+    pub max_iterations: usize,
+    /// The number of iterations of each optimization.
+    pub optimization_iterations: usize,
+    /// The diagonal entries of the information matrices of the priors at the associated map poses.
+    pub prior_information: f64,
+}
+
+impl Default for MapAlignmentConfig {
+    fn default() -> Self {
+        MapAlignmentConfig {
+            association_distance: 1.0,
+            max_iterations: 10,
+            optimization_iterations: 5,
+            prior_information: 1.0,
+        }
+    }
+}
+
+/// The result of aligning a session with a prior map.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MapAlignment {
+    /// The final associations, as pairs of the session's and the map's IDs.
+    pub associations: Vec<(VariableId, VariableId)>,
+    /// The IDs of the Position2D or Position3D factors which link the associated session poses to the map poses, in
+    /// the order of the associations.
+    pub factors: Vec<FactorId>,
+    /// The number of optimizations, i.e. of iterations whose associations differed from the previous ones.
+    pub iterations: usize,
+    /// Whether the associations stopped changing before the maximum number of iterations was reached.
+    pub converged: bool,
+}
+
+/// Aligns the session with the prior map by alternating the association of each session pose with the closest map
+/// pose and the optimization of the session against priors at the associated map poses.
+///
+/// Only vehicle poses are associated, with map poses of the same type. Fixed poses and poses which already have a
+/// Position2D or Position3D factor are not associated, and fixed poses keep their estimates, so a new session should
+/// usually not have any fixed variables. The session is expected to be roughly aligned with the map already, e.g.
+/// by [merge_session](../../factor_graph/struct.FactorGraph.html#method.merge_session) or by a place recognition,
+/// since the associations are only found within the association distance.
+///
+/// The priors of the final associations stay in the session, so that it can be optimized further in the map's frame.
+/// Returns an error if no session pose is within the association distance of a map pose, after removing the priors
+/// which were added by previous iterations.
+///
+/// ```
+/// use gs_rs::examples_gen::triangle_with_loop_closure;
+/// use gs_rs::factor_graph::FactorGraph;
+/// use gs_rs::optimizer::map_alignment::{align_to_map, MapAlignmentConfig};
+/// use gs_rs::optimizer::optimize;
+///
+/// let prior_map: FactorGraph = triangle_with_loop_closure().into();
+/// optimize(&prior_map, 10);
+/// let mut model = triangle_with_loop_closure();
+/// model.fixed_vertices.clear();
+/// let mut session: FactorGraph = model.into();
+/// let alignment = align_to_map(&mut session, &prior_map, &MapAlignmentConfig::default()).unwrap();
+/// assert!(alignment.converged);
+/// assert_eq!(alignment.associations.len(), 3);
+/// ```
+pub fn align_to_map(
+    graph: &mut FactorGraph,
+    prior_map: &FactorGraph,
+    config: &MapAlignmentConfig,
+) -> Result<MapAlignment, String> {
+    let candidates: Vec<VariableId> = graph
+        .node_indices
+        .iter()
+        .map(|i| graph.get_var(*i))
+        .filter(|var| matches!(var, Variable::Vehicle2D(_) | Variable::Vehicle3D(_)))
+        .filter(|var| var.get_fixed_type() != &FixedType::Fixed)
+        .map(|var| var.get_id())
+        .filter(|id| graph.factors_between(*id, *id).is_empty())
+        .collect();
+    let mut alignment = MapAlignment {
+        associations: vec![],
+        factors: vec![],
+        iterations: 0,
+        converged: false,
+    };
+    for _ in 0..config.max_iterations {
+        let associations = associate(graph, prior_map, &candidates, config.association_distance);
+        if associations.is_empty() {
+            for id in alignment.factors {
+                graph.remove_factor(id)?;
+            }
+            return Err(String::from(
+                "No pose of the session is within the association distance of a pose of the prior map",
+            ));
+        }
+        if associations == alignment.associations {
+            alignment.converged = true;
+            break;
+        }
+        for id in alignment.factors.drain(..) {
+            graph.remove_factor(id)?;
+        }
+        for (session_id, map_id) in &associations {
+            let (factor_type, dim) = match graph.get_var_by_id(*session_id).unwrap() {
+                Variable::Vehicle2D(_) => (FactorType::Position2D, 3),
+                _ => (FactorType::Position3D, 6),
+            };
+            let information = DMatrix::from_diagonal_element(dim, dim, config.prior_information);
+            alignment.factors.push(graph.add_factor(
+                *session_id,
+                *session_id,
+                factor_type,
+                prior_map.get_var_by_id(*map_id).unwrap().get_content(),
+                information.as_slice().to_vec().into(),
+            )?);
+        }
+        alignment.associations = associations;
+        optimize(graph, config.optimization_iterations);
+        alignment.iterations += 1;
+    }
+    Ok(alignment)
+}
+
+// returns the pairs of each candidate and the closest map pose of the same type within the maximum distance
+fn associate(
+    graph: &FactorGraph,
+    prior_map: &FactorGraph,
+    candidates: &[VariableId],
+    max_distance: f64,
+) -> Vec<(VariableId, VariableId)> {
+    let map_vars: Vec<&Variable> = prior_map
+        .node_indices
+        .iter()
+        .map(|i| prior_map.get_var(*i))
+        .filter(|var| matches!(var, Variable::Vehicle2D(_) | Variable::Vehicle3D(_)))
+        .collect();
+    candidates
+        .iter()
+        .filter_map(|id| {
+            let var = graph.get_var_by_id(*id).unwrap();
+            let position = get_position(var);
+            map_vars
+                .iter()
+                .filter(|map_var| discriminant(**map_var) == discriminant(var))
+                .map(|map_var| {
+                    let map_position = get_position(map_var);
+                    let distance = position
+                        .iter()
+                        .zip(&map_position)
+                        .map(|(a, b)| (a - b).powi(2))
+                        .sum::<f64>()
+                        .sqrt();
+                    (distance, map_var.get_id())
+                })
+                .filter(|(distance, _)| *distance <= max_distance)
+                .min_by(|a, b| a.0.total_cmp(&b.0))
+                .map(|(_, map_id)| (*id, map_id))
+        })
+        .collect()
+}
+
+// returns the position of a vehicle pose
+fn get_position(var: &Variable) -> Vec<f64> {
+    let mut content = var.get_content();
+    content.truncate(match var {
+        Variable::Vehicle2D(_) => 2,
+        _ => 3,
+    });
+    content
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimizer::total_chi2;
+    use crate::parser::model::{Edge, FactorGraphModel, Vertex};
+    use approx::assert_relative_eq;
+    use std::collections::BTreeSet;
+
+    // returns poses along the x axis with a spacing of 1, whose estimates are shifted by the given offset
+    fn get_trajectory(count: usize, offset: [f64; 3], fixed: bool) -> FactorGraph {
+        let mut fixed_vertices = BTreeSet::new();
+        if fixed {
+            fixed_vertices.insert(VariableId(0));
+        }
+        FactorGraphModel {
+            vertices: (0..count)
+                .map(|i| Vertex {
+                    id: VariableId(i),
+                    vertex_type: String::from("Vehicle2D"),
+                    content: vec![i as f64 + offset[0], offset[1], offset[2]],
+                })
+                .collect(),
+            edges: (1..count)
+                .map(|i| Edge {
+                    edge_type: String::from("Odometry2D"),
+                    vertices: vec![VariableId(i - 1), VariableId(i)],
+                    restriction: vec![1.0, 0.0, 0.0],
+                    information_matrix: vec![1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0],
+                })
+                .collect(),
+            fixed_vertices,
+            ..Default::default()
+        }
+        .into()
+    }
+
+    #[test]
+    fn test_session_converges_onto_map() {
+        let prior_map = get_trajectory(10, [0.0; 3], true);
+        let mut session = get_trajectory(6, [0.3, 0.2, 0.05], false);
+        let alignment = align_to_map(&mut session, &prior_map, &MapAlignmentConfig::default()).unwrap();
+        assert!(alignment.converged);
+        assert_eq!(
+            alignment.associations,
+            (0..6).map(|i| (VariableId(i), VariableId(i))).collect::<Vec<_>>()
+        );
+        assert_eq!(alignment.factors.len(), 6);
+        assert!(total_chi2(&session) < 1e-12);
+        let pose = session.get_var_by_id(VariableId(5)).unwrap().get_content();
+        assert_relative_eq!(pose.as_slice(), [5.0, 0.0, 0.0].as_ref(), epsilon = 1e-6);
+    }
+
+    #[test]
+    fn test_session_far_from_map() {
+        let prior_map = get_trajectory(10, [0.0; 3], true);
+        let mut session = get_trajectory(3, [0.0, 5.0, 0.0], false);
+        assert!(align_to_map(&mut session, &prior_map, &MapAlignmentConfig::default()).is_err());
+        assert_eq!(session.factor_id_map.len(), 2);
+
+        // fixed poses are not associated
+        let mut session = get_trajectory(1, [0.0; 3], true);
+        assert!(align_to_map(&mut session, &prior_map, &MapAlignmentConfig::default()).is_err());
+    }
+}
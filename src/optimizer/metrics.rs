@@ -0,0 +1,210 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Health metrics of long-running services which optimize many factor graphs, e.g. to monitor the optimizer's
+//! behavior over time.
+//!
+//! An optimization reports to the [OptimizerMetrics](trait.OptimizerMetrics.html) of its
+//! [OptimizerConfig](../config/struct.OptimizerConfig.html). [OptimizerCounters](struct.OptimizerCounters.html)
+//! accumulate these reports and can be exported in the text format of Prometheus.
+//!
+//! ```
+//! use gs_rs::examples_gen::triangle_with_loop_closure;
+//! use gs_rs::factor_graph::FactorGraph;
+//! use gs_rs::optimizer::config::OptimizerConfig;
+//! use gs_rs::optimizer::metrics::OptimizerCounters;
+//! use gs_rs::optimizer::optimize_with_config;
+//!
+//! let counters = OptimizerCounters::new();
+//! let config = OptimizerConfig {
+//!     metrics: Some(&counters),
+//!     ..Default::default()
+//! };
+//! let factor_graph: FactorGraph = triangle_with_loop_closure().into();
+//! optimize_with_config(&factor_graph, &config);
+//! assert_eq!(counters.snapshot().graphs_optimized, 1);
+//! ```
+
+use crate::optimizer::termination::OptimizationReport;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Receiver of the reports of optimizations, see [OptimizerConfig](../config/struct.OptimizerConfig.html).
+pub trait OptimizerMetrics {
+    /// Records an optimization which finished with the given report after the given time.
+    ///
+    /// An optimization fails if its final total χ² is not finite, e.g. because it diverged.
+    fn record_optimization(&self, report: &OptimizationReport, duration: Duration);
+
+    /// Records a failure outside of the optimization itself, e.g. a factor graph which could not be parsed or which
+    /// exceeds the [resource limits](../../factor_graph/limits/struct.ResourceLimits.html).
+    fn record_failure(&self);
+}
+
+/// Thread-safe counters of optimizations, which can be shared by all optimizations of a service.
+#[derive(Debug, Default)]
+pub struct OptimizerCounters {
+    graphs_optimized: AtomicU64,
+    iterations: AtomicU64,
+    solve_time_nanos: AtomicU64,
+    failures: AtomicU64,
+}
+
+/// The values of [OptimizerCounters](struct.OptimizerCounters.html) at one point in time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct MetricsSnapshot {
+    /// The number of finished optimizations, including failed ones.
+    pub graphs_optimized: u64,
+    /// The total number of iterations of all optimizations.
+    pub iterations: u64,
+    /// The total time spent in all optimizations.
+    pub solve_time: Duration,
+    /// The number of failed optimizations and of failures recorded outside of optimizations.
+    pub failures: u64,
+}
+
+impl MetricsSnapshot {
+    /// Returns the mean time of an optimization, or None if there was none.
+    pub fn average_solve_time(&self) -> Option<Duration> {
+        match self.graphs_optimized {
+            0 => None,
+            count => Some(Duration::from_nanos(
+                (self.solve_time.as_nanos() / count as u128) as u64,
+            )),
+        }
+    }
+}
+
+impl OptimizerCounters {
+    /// Returns counters which are all zero.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the current values of the counters.
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            graphs_optimized: self.graphs_optimized.load(Ordering::Relaxed),
+            iterations: self.iterations.load(Ordering::Relaxed),
+            solve_time: Duration::from_nanos(self.solve_time_nanos.load(Ordering::Relaxed)),
+            failures: self.failures.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Returns the current values of the counters in the text exposition format of Prometheus, e.g. as the body of
+    /// a metrics endpoint.
+    pub fn to_prometheus_text(&self) -> String {
+        let snapshot = self.snapshot();
+        let metrics = [
+            (
+                "gs_rs_graphs_optimized_total",
+                "counter",
+                "The number of finished optimizations.",
+                snapshot.graphs_optimized.to_string(),
+            ),
+            (
+                "gs_rs_iterations_total",
+                "counter",
+                "The total number of optimization iterations.",
+                snapshot.iterations.to_string(),
+            ),
+            (
+                "gs_rs_solve_seconds_total",
+                "counter",
+                "The total time spent in optimizations.",
+                snapshot.solve_time.as_secs_f64().to_string(),
+            ),
+            (
+                "gs_rs_average_solve_seconds",
+                "gauge",
+                "The mean time of an optimization.",
+                snapshot
+                    .average_solve_time()
+                    .unwrap_or_default()
+                    .as_secs_f64()
+                    .to_string(),
+            ),
+            (
+                "gs_rs_failures_total",
+                "counter",
+                "The number of failed optimizations and other failures.",
+                snapshot.failures.to_string(),
+            ),
+        ];
+        metrics
+            .iter()
+            .map(|(name, metric_type, help, value)| {
+                format!(
+                    "# HELP {0} {1}\n# TYPE {0} {2}\n{0} {3}\n",
+                    name, help, metric_type, value
+                )
+            })
+            .collect()
+    }
+}
+
+impl OptimizerMetrics for OptimizerCounters {
+    fn record_optimization(&self, report: &OptimizationReport, duration: Duration) {
+        self.graphs_optimized.fetch_add(1, Ordering::Relaxed);
+        self.iterations.fetch_add(report.iterations as u64, Ordering::Relaxed);
+        self.solve_time_nanos
+            .fetch_add(duration.as_nanos() as u64, Ordering::Relaxed);
+        if !report.chi2.is_finite() {
+            self.failures.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    fn record_failure(&self) {
+        self.failures.fetch_add(1, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimizer::termination::Termination;
+
+    fn get_report(iterations: usize, chi2: f64) -> OptimizationReport {
+        OptimizationReport {
+            iterations,
+            chi2,
+            termination: Termination::Converged,
+            components: vec![],
+        }
+    }
+
+    #[test]
+    fn test_counters() {
+        let counters = OptimizerCounters::new();
+        assert_eq!(counters.snapshot(), MetricsSnapshot::default());
+        assert_eq!(counters.snapshot().average_solve_time(), None);
+        counters.record_optimization(&get_report(3, 1.0), Duration::from_millis(10));
+        counters.record_optimization(&get_report(5, f64::NAN), Duration::from_millis(30));
+        counters.record_failure();
+        let snapshot = counters.snapshot();
+        assert_eq!(snapshot.graphs_optimized, 2);
+        assert_eq!(snapshot.iterations, 8);
+        assert_eq!(snapshot.failures, 2);
+        assert_eq!(snapshot.average_solve_time(), Some(Duration::from_millis(20)));
+    }
+
+    #[test]
+    fn test_prometheus_text() {
+        let counters = OptimizerCounters::new();
+        counters.record_optimization(&get_report(4, 1.0), Duration::from_millis(500));
+        let text = counters.to_prometheus_text();
+        assert!(text.contains("# TYPE gs_rs_graphs_optimized_total counter\ngs_rs_graphs_optimized_total 1\n"));
+        assert!(text.contains("\ngs_rs_iterations_total 4\n"));
+        assert!(text.contains("\ngs_rs_average_solve_seconds 0.5\n"));
+        assert!(text.contains("\ngs_rs_failures_total 0\n"));
+    }
+}
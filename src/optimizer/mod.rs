@@ -11,92 +11,595 @@
 //
 
 //! Improves variable estimates to better fit measurements.
+//!
+//! The optimization is deterministic: the linear system is assembled in the order of the variables and their
+//! factors, and all other reductions follow the order of the IDs, so repeated runs on the same input give
+//! bit-identical results.
 
 #![allow(non_snake_case)]
 
+use crate::factor_graph::factor::FactorType;
+use crate::factor_graph::limits::ResourceLimits;
 use crate::factor_graph::variable::{FixedType, Variable};
 use crate::factor_graph::FactorGraph;
-use crate::optimizer::linear_system::calculate_H_b;
-use crate::optimizer::linear_system::iso3d_gradients::{get_isometry, get_isometry_normalized};
+use crate::optimizer::block_sparse::BlockSparseMatrix;
+use crate::optimizer::config::{OptimizerConfig, Verbosity};
+use crate::optimizer::dcs::DynamicCovarianceScaling;
+use crate::optimizer::evaluation::{Evaluation, FactorEvaluation};
+use crate::optimizer::huber::HuberKernel;
+use crate::optimizer::linear_system::{calculate_chi2, calculate_error, calculate_scaled_H_b};
+use crate::optimizer::relinearization::RelinearizationCache;
+use crate::optimizer::ordering::{fill_reducing_permutation, permute_system, unpermute_solution, VariableOrdering};
 use crate::optimizer::solver::sparse_cholesky::SparseCholeskySolver;
-use crate::optimizer::solver::Solver;
-use std::f64::consts::PI;
+use crate::optimizer::solver::LinearSolver;
+use crate::optimizer::streaming::{get_snapshot, EstimateSink, IterationUpdate};
+use crate::optimizer::termination::{CancellationToken, OptimizationReport, Termination, TerminationCriteria};
+use crate::optimizer::warm_start::OptimizerState;
+use nalgebra::DVector;
+use std::cell::RefCell;
+use std::mem;
+use std::sync::mpsc::Sender;
+use std::time::{Duration, Instant};
 
+pub mod autodiff;
+pub mod block_sparse;
+pub mod chi2_gating;
+pub mod components;
+pub mod config;
+pub mod dcs;
+pub mod diagnostics;
+pub mod distributed;
+pub mod drift;
+pub mod evaluation;
+pub mod handler_check;
+pub mod hierarchical;
+pub mod huber;
+pub(crate) mod linear_system;
+pub mod map_alignment;
+pub mod metrics;
+pub mod nlls;
+pub mod ordering;
+pub mod regularization;
+pub mod relinearization;
+pub mod sensitivity;
+pub mod small;
+pub mod solver;
+pub mod streaming;
+pub mod structure;
+pub mod termination;
+pub mod warm_start;
 
-mod linear_system;
-mod solver;
+/// The number of times a step which increases the total χ² is halved before the step is rejected.
+const MAX_STEP_HALVINGS: usize = 10;
+
+/// The relative increase of the total χ² which is still accepted, so that rounding errors close to convergence do
+/// not shrink the steps.
+const CHI2_TOLERANCE: f64 = 1e-9;
+
+/// The relative decrease of the total χ² below which an optimization until convergence stops.
+pub(crate) const CONVERGENCE_THRESHOLD: f64 = 1e-6;
 
 /// Optimizes a factor graph with the given number of iterations.
+///
+/// If a Gauss-Newton step increases the total χ², it is halved until it does not, and rejected if that does not
+/// happen within a few halvings, in which case the estimates from before the step are restored.
+///
+/// Uses the [SparseCholeskySolver](solver/sparse_cholesky/struct.SparseCholeskySolver.html) for each linear system.
+/// This is a shorthand for [optimize_with_config](fn.optimize_with_config.html) without any termination criteria.
 pub fn optimize(graph: &FactorGraph, iterations: usize) {
-    for _i in 0..iterations {
-        update_once(graph);
-    }
+    optimize_with_iterations(
+        graph,
+        &OptimizerConfig {
+            max_iterations: iterations,
+            ..Default::default()
+        },
+    );
 }
 
-fn update_once(factor_graph: &FactorGraph) {
-    let (H, b) = calculate_H_b(factor_graph);
-    // TODO @Daniel: clumsy, since the solver transforms the arguments back to nalgebra matrices
-    let sol = SparseCholeskySolver::solve(H, &(b * -1.0)).unwrap();
-    factor_graph
-        .node_indices
-        .iter()
-        .map(|i| factor_graph.get_var(*i))
-        .for_each(|var| update_var(var, sol.as_slice()));
+/// Optimizes a factor graph like [optimize](fn.optimize.html), but with the options of the given configuration,
+/// e.g. its solver, robust kernel and termination criteria, see [OptimizerConfig](config/struct.OptimizerConfig.html).
+///
+/// ```
+/// use gs_rs::examples_gen::triangle_with_loop_closure;
+/// use gs_rs::factor_graph::FactorGraph;
+/// use gs_rs::optimizer::config::OptimizerConfig;
+/// use gs_rs::optimizer::optimize_with_config;
+/// use gs_rs::optimizer::termination::Termination;
+///
+/// let factor_graph: FactorGraph = triangle_with_loop_closure().into();
+/// let report = optimize_with_config(&factor_graph, &OptimizerConfig::default());
+/// assert_eq!(report.termination, Termination::Converged);
+/// assert!(report.chi2 < 1e-12);
+/// ```
+pub fn optimize_with_config(graph: &FactorGraph, config: &OptimizerConfig) -> OptimizationReport {
+    optimize_with_observer(graph, config, &mut |_| true)
 }
 
-fn update_var(var: &Variable, solution: &[f64]) {
-    let correction = if let FixedType::NonFixed(range) = var.get_fixed_type() {
-        &solution[range.to_owned()]
-    } else {
-        return;
+// optimizes like optimize_with_config, passing an update without a snapshot to the observer after each iteration,
+// and stops as if cancelled if the observer returns false
+fn optimize_with_observer(
+    graph: &FactorGraph,
+    config: &OptimizerConfig,
+    observer: &mut dyn FnMut(&IterationUpdate) -> bool,
+) -> OptimizationReport {
+    let start = Instant::now();
+    if let Some(state) = config.state {
+        state.borrow_mut().start(graph);
+    }
+    let permutation = match (config.state, config.ordering) {
+        (None, VariableOrdering::MinimumDegree) => fill_reducing_permutation(graph),
+        _ => None,
     };
+    let mut report = OptimizationReport {
+        iterations: 0,
+        chi2: total_chi2(graph),
+        termination: Termination::IterationLimit,
+        components: vec![],
+    };
+    while report.iterations < config.max_iterations {
+        if let Some(termination) = config.termination.check(start) {
+            report.termination = termination;
+            break;
+        }
+        let auto_tuned_kernel = match config.auto_tuned_huber {
+            true => Some(HuberKernel::estimate(graph)),
+            false => None,
+        };
+        let kernel = match &auto_tuned_kernel {
+            Some(kernel) => Some(kernel as &dyn FactorReweighting),
+            None => config.robust_kernel,
+        };
+        let step = match calculate_configured_step(graph, config, permutation.as_deref(), kernel) {
+            Ok(step) => step,
+            Err(_) => {
+                report.termination = Termination::SolverFailed;
+                break;
+            }
+        };
+        let (chi2, scale) = match kernel.is_none() && config.line_search {
+            true => line_search(graph, &step, report.chi2),
+            false => {
+                update_vars(graph, &step);
+                (total_chi2(graph), 1.0)
+            }
+        };
+        if let (None, Some(cache)) = (config.state, config.relinearization) {
+            cache
+                .borrow_mut()
+                .add_corrections(&step.iter().map(|v| v * scale).collect::<Vec<f64>>());
+        }
+        report.iterations += 1;
+        let previous_chi2 = report.chi2;
+        report.chi2 = chi2;
+        if config.verbosity == Verbosity::Iterations {
+            eprintln!("Iteration {}: total χ² {}", report.iterations, chi2);
+        }
+        let update = IterationUpdate {
+            iteration: report.iterations,
+            chi2,
+            step_norm: step.iter().map(|v| v * v).sum::<f64>().sqrt(),
+            rejected: scale == 0.0,
+            elapsed: start.elapsed(),
+            snapshot: None,
+        };
+        if !observer(&update) {
+            report.termination = Termination::Cancelled;
+            break;
+        }
+        if config.termination.has_converged(previous_chi2, chi2) {
+            report.termination = Termination::Converged;
+            break;
+        }
+    }
+    if let Some(state) = config.state {
+        state.borrow_mut().finish(graph);
+    }
+    if config.verbosity != Verbosity::Silent {
+        eprintln!(
+            "Stopped after {} iterations with a total χ² of {}: {:?}",
+            report.iterations, report.chi2, report.termination
+        );
+    }
+    if let Some(metrics) = config.metrics {
+        metrics.record_optimization(&report, start.elapsed());
+    }
+    report
+}
+
+/// Optimizes a factor graph like [optimize](fn.optimize.html) if it does not exceed the given limits, see
+/// [ResourceLimits](../factor_graph/limits/struct.ResourceLimits.html), and returns an error otherwise.
+pub fn optimize_with_limits(graph: &FactorGraph, iterations: usize, limits: &ResourceLimits) -> Result<(), String> {
+    limits.check(&graph.into())?;
+    optimize(graph, iterations);
+    Ok(())
+}
+
+/// Optimizes a factor graph like [optimize](fn.optimize.html) until convergence or until the given time budget is
+/// exhausted.
+///
+/// The budget is checked before each iteration, so the iteration which exceeds it is finished and its estimates are
+/// kept. Since no iteration increases the total χ², these are the best estimates so far.
+pub fn optimize_with_deadline(graph: &FactorGraph, budget: Duration) -> OptimizationReport {
+    optimize_with_config(
+        graph,
+        &OptimizerConfig {
+            max_iterations: usize::MAX,
+            termination: TerminationCriteria {
+                time_budget: Some(budget),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    )
+}
+
+/// Optimizes a factor graph like [optimize](fn.optimize.html) until convergence or until the given token is
+/// cancelled, see [optimize_with_deadline](fn.optimize_with_deadline.html).
+pub fn optimize_with_cancellation(graph: &FactorGraph, token: &CancellationToken) -> OptimizationReport {
+    optimize_with_config(
+        graph,
+        &OptimizerConfig {
+            max_iterations: usize::MAX,
+            termination: TerminationCriteria {
+                cancellation: Some(token),
+                ..Default::default()
+            },
+            ..Default::default()
+        },
+    )
+}
+
+/// Optimizes a factor graph like [optimize](fn.optimize.html), sending an
+/// [IterationUpdate](streaming/struct.IterationUpdate.html) after each iteration, which contains a snapshot of all
+/// estimates if requested.
+///
+/// The optimization stops after the current iteration if the receiver was dropped, and before it if its linear system
+/// cannot be solved.
+pub fn optimize_streaming(
+    graph: &FactorGraph,
+    iterations: usize,
+    sender: &Sender<IterationUpdate>,
+    with_snapshots: bool,
+) {
+    let start = Instant::now();
+    let mut chi2 = total_chi2(graph);
+    for iteration in 1..=iterations {
+        let step = match calculate_step(graph, &SparseCholeskySolver, None, None) {
+            Ok(step) => step,
+            Err(_) => return,
+        };
+        let (updated_chi2, scale) = line_search(graph, &step, chi2);
+        chi2 = updated_chi2;
+        let update = IterationUpdate {
+            iteration,
+            chi2,
+            step_norm: step.iter().map(|v| v * v).sum::<f64>().sqrt(),
+            rejected: scale == 0.0,
+            elapsed: start.elapsed(),
+            snapshot: if with_snapshots { Some(get_snapshot(graph)) } else { None },
+        };
+        if sender.send(update).is_err() {
+            return;
+        }
+    }
+}
 
-    let updated_content = match var {
-        Variable::Vehicle2D(var) => {
-            let mut updated_content: Vec<f64> = var
-                .pose
-                .borrow()
+/// Optimizes a factor graph like [optimize_with_config](fn.optimize_with_config.html), publishing the current
+/// estimates to the given sink whenever at least the given interval passed since the previous publication, see
+/// [EstimateSink](streaming/trait.EstimateSink.html).
+///
+/// The interval is checked after each iteration, so the updates are delayed by at most one iteration. The estimates
+/// after the last iteration are always published, so the sink receives the final result as well. If the sink
+/// returns an error, the optimization stops after the current iteration and the error is returned.
+///
+/// ```
+/// use gs_rs::examples_gen::triangle_with_loop_closure;
+/// use gs_rs::factor_graph::FactorGraph;
+/// use gs_rs::optimizer::config::OptimizerConfig;
+/// use gs_rs::optimizer::optimize_with_sink;
+/// use std::sync::mpsc::channel;
+/// use std::time::Duration;
+///
+/// let factor_graph: FactorGraph = triangle_with_loop_closure().into();
+/// let (mut sender, receiver) = channel();
+/// let config = OptimizerConfig::default();
+/// let report = optimize_with_sink(&factor_graph, &config, &mut sender, Duration::from_secs(60)).unwrap();
+/// let update = receiver.try_recv().unwrap();
+/// assert_eq!(update.iteration, report.iterations);
+/// assert!(update.snapshot.is_some());
+/// ```
+pub fn optimize_with_sink(
+    graph: &FactorGraph,
+    config: &OptimizerConfig,
+    sink: &mut dyn EstimateSink,
+    interval: Duration,
+) -> Result<OptimizationReport, String> {
+    let mut last_publication = Instant::now();
+    let mut pending: Option<IterationUpdate> = None;
+    let mut error = None;
+    let report = optimize_with_observer(graph, config, &mut |update| {
+        if last_publication.elapsed() < interval {
+            pending = Some(update.clone());
+            return true;
+        }
+        pending = None;
+        last_publication = Instant::now();
+        let update = IterationUpdate {
+            snapshot: Some(get_snapshot(graph)),
+            ..update.clone()
+        };
+        error = sink.publish(&update).err();
+        error.is_none()
+    });
+    if let Some(error) = error {
+        return Err(error);
+    }
+    if let Some(update) = pending {
+        sink.publish(&IterationUpdate {
+            snapshot: Some(get_snapshot(graph)),
+            ..update
+        })?;
+    }
+    Ok(report)
+}
+
+/// Optimizes a factor graph like [optimize](fn.optimize.html), reusing the state of previous calls with the same
+/// state and updating it for the next call, see [OptimizerState](warm_start/struct.OptimizerState.html).
+///
+/// Each linear system is passed to a sparse Cholesky solver in the fill-reducing variable ordering.
+pub fn optimize_with_state(graph: &FactorGraph, iterations: usize, state: &mut OptimizerState) {
+    let cell = RefCell::new(mem::take(state));
+    optimize_with_iterations(
+        graph,
+        &OptimizerConfig {
+            max_iterations: iterations,
+            state: Some(&cell),
+            ..Default::default()
+        },
+    );
+    *state = cell.into_inner();
+}
+
+/// Optimizes a factor graph like [optimize](fn.optimize.html), but only linearizes the factors whose variables moved
+/// by at least the cache's threshold, see [RelinearizationCache](relinearization/struct.RelinearizationCache.html).
+pub fn optimize_with_relinearization(graph: &FactorGraph, iterations: usize, cache: &mut RelinearizationCache) {
+    let cell = RefCell::new(mem::replace(cache, RelinearizationCache::new(0.0)));
+    optimize_with_iterations(
+        graph,
+        &OptimizerConfig {
+            max_iterations: iterations,
+            relinearization: Some(&cell),
+            ..Default::default()
+        },
+    );
+    *cache = cell.into_inner();
+}
+
+/// Optimizes a factor graph with the given number of iterations, solving each linear system with the given solver.
+///
+/// Unlike [optimize](fn.optimize.html), each Gauss-Newton step is applied as it is.
+pub fn optimize_with_solver(graph: &FactorGraph, iterations: usize, solver: &dyn LinearSolver) {
+    optimize_with_ordering(graph, iterations, solver, VariableOrdering::Natural);
+}
+
+/// Optimizes a factor graph with the given number of iterations, passing each linear system to the given solver
+/// in the given variable ordering.
+///
+/// Like [optimize_with_solver](fn.optimize_with_solver.html), each Gauss-Newton step is applied as it is.
+pub fn optimize_with_ordering(
+    graph: &FactorGraph,
+    iterations: usize,
+    solver: &dyn LinearSolver,
+    ordering: VariableOrdering,
+) {
+    optimize_with_iterations(
+        graph,
+        &OptimizerConfig {
+            max_iterations: iterations,
+            solver,
+            ordering,
+            line_search: false,
+            ..Default::default()
+        },
+    );
+}
+
+/// Optimizes a factor graph with the given number of iterations, reweighting the factors with Dynamic Covariance
+/// Scaling in each iteration, see [DynamicCovarianceScaling](dcs/struct.DynamicCovarianceScaling.html).
+pub fn optimize_with_dcs(graph: &FactorGraph, iterations: usize, dcs: &DynamicCovarianceScaling) {
+    optimize_with_reweighting(graph, iterations, dcs);
+}
+
+/// Optimizes a factor graph with the given number of iterations, reweighting the factors according to their χ² at
+/// the estimates of the previous iteration, e.g. with a [Chi2Gate](chi2_gating/struct.Chi2Gate.html).
+///
+/// The first iteration is reweighted according to the initial estimates.
+pub fn optimize_with_reweighting(graph: &FactorGraph, iterations: usize, reweighting: &dyn FactorReweighting) {
+    optimize_with_iterations(
+        graph,
+        &OptimizerConfig {
+            max_iterations: iterations,
+            robust_kernel: Some(reweighting),
+            ..Default::default()
+        },
+    );
+}
+
+/// Optimizes a factor graph with the given number of iterations, applying a Huber kernel whose widths are estimated
+/// from the residuals before each iteration, see
+/// [HuberKernel::estimate](huber/struct.HuberKernel.html#method.estimate).
+pub fn optimize_with_auto_tuned_huber(graph: &FactorGraph, iterations: usize) {
+    optimize_with_iterations(
+        graph,
+        &OptimizerConfig {
+            max_iterations: iterations,
+            auto_tuned_huber: true,
+            ..Default::default()
+        },
+    );
+}
+
+/// Scaling of the factors' information matrices depending on their χ² at the current estimates.
+pub trait FactorReweighting {
+    /// Returns whether factors of the given type are reweighted at all, so that the χ² of other factors does not
+    /// need to be calculated.
+    fn is_reweighted(&self, factor_type: &FactorType) -> bool;
+
+    /// Returns the factor by which the information matrix of a factor with the given type and χ² is scaled.
+    fn get_weight(&self, factor_type: &FactorType, chi2: f64) -> f64;
+}
+
+/// Returns the sum of the squared errors of all factors, weighted by their information matrices.
+pub fn total_chi2(graph: &FactorGraph) -> f64 {
+    graph
+        .factor_id_map
+        .keys()
+        .map(|id| calculate_chi2(graph, *id).unwrap())
+        .sum()
+}
+
+/// Returns the error and χ² of each factor and the total χ² at the current estimates without changing the factor
+/// graph, e.g. to find the factors which fit worst with [worst](evaluation/struct.Evaluation.html#method.worst).
+pub fn evaluate(graph: &FactorGraph) -> Evaluation {
+    let factors: Vec<FactorEvaluation> = graph
+        .factor_id_map
+        .keys()
+        .map(|id| FactorEvaluation {
+            id: *id,
+            factor_type: graph.get_factor(*id).unwrap().factor_type.clone(),
+            variables: graph
+                .get_factor_var_indices(*id)
+                .unwrap()
                 .iter()
-                .zip(correction.iter())
-                .map(|(old, cor)| old + cor)
-                .collect();
-            updated_content[2] %= 2.0 * PI;
-            if updated_content[2] > PI {
-                updated_content[2] -= 2.0 * PI;
-            } else if updated_content[2] < -PI {
-                updated_content[2] += 2.0 * PI;
-            }
-            updated_content
+                .map(|i| graph.get_var(*i).get_id())
+                .collect(),
+            error: calculate_error(graph, *id).unwrap().data.into(),
+            chi2: calculate_chi2(graph, *id).unwrap(),
+        })
+        .collect();
+    Evaluation {
+        total_chi2: factors.iter().map(|factor| factor.chi2).sum(),
+        factors,
+    }
+}
+
+// optimizes the factor graph with exactly the configuration's maximum number of iterations, i.e. without stopping at
+// convergence
+fn optimize_with_iterations(graph: &FactorGraph, config: &OptimizerConfig) {
+    optimize_with_config(
+        graph,
+        &OptimizerConfig {
+            termination: TerminationCriteria {
+                convergence_threshold: None,
+                ..config.termination
+            },
+            ..*config
+        },
+    );
+}
+
+// returns the Gauss-Newton step at the current estimates with the configuration's state or relinearization cache if
+// one is given, or else with its solver
+fn calculate_configured_step(
+    factor_graph: &FactorGraph,
+    config: &OptimizerConfig,
+    permutation: Option<&[usize]>,
+    reweighting: Option<&dyn FactorReweighting>,
+) -> Result<Vec<f64>, String> {
+    match (config.state, config.relinearization) {
+        (Some(state), _) => state.borrow_mut().calculate_step(factor_graph),
+        (None, Some(cache)) => {
+            let (H, b) = cache.borrow_mut().calculate_H_b(factor_graph);
+            solve(&H, &b, config.solver, permutation)
         }
-        Variable::Landmark2D(var) => var
-            .position
-            .borrow()
-            .iter()
-            .zip(correction.iter())
-            .map(|(old, cor)| old + cor)
-            .collect(),
-        Variable::Vehicle3D(var) => {
-            let old_iso = get_isometry(&*var.pose.borrow());
-            let cor_iso = get_isometry_normalized(correction);
-            let new_iso = old_iso * cor_iso;
-            let mut updated_content = new_iso.translation.vector.data.as_slice().to_vec();
-            updated_content.extend(&new_iso.rotation.quaternion().coords.data.as_slice().to_vec());
-            updated_content
+        (None, None) => calculate_step(factor_graph, config.solver, permutation, reweighting),
+    }
+}
+
+// returns the Gauss-Newton step at the current estimates
+fn calculate_step(
+    factor_graph: &FactorGraph,
+    solver: &dyn LinearSolver,
+    permutation: Option<&[usize]>,
+    reweighting: Option<&dyn FactorReweighting>,
+) -> Result<Vec<f64>, String> {
+    let (H, b) = calculate_scaled_H_b(factor_graph, reweighting);
+    solve(&H, &b, solver, permutation)
+}
+
+// returns the solution of H * x = -b, passing the linear system to the solver in the given variable ordering, or the
+// solver's error, e.g. if H is singular
+fn solve(
+    H: &BlockSparseMatrix,
+    b: &DVector<f64>,
+    solver: &dyn LinearSolver,
+    permutation: Option<&[usize]>,
+) -> Result<Vec<f64>, String> {
+    match permutation {
+        Some(permutation) => {
+            let (H, b) = permute_system(H, b, permutation);
+            Ok(unpermute_solution(&solver.solve_block_sparse(&H, &(b * -1.0))?, permutation))
         }
-        Variable::Landmark3D(var) => var
-            .position
-            .borrow()
+        None => solver.solve_block_sparse(H, &(b * -1.0)),
+    }
+}
+
+// applies the step, halving it until the total χ² does not increase beyond the tolerance, or rejects it if that does
+// not happen within MAX_STEP_HALVINGS halvings, and returns the resulting total χ²
+fn update_vars_with_line_search(factor_graph: &FactorGraph, step: &[f64], initial_chi2: f64) -> f64 {
+    line_search(factor_graph, step, initial_chi2).0
+}
+
+// applies the step like update_vars_with_line_search and returns the resulting total χ² and the factor by which the
+// applied step was scaled, which is zero if it was rejected
+fn line_search(factor_graph: &FactorGraph, step: &[f64], initial_chi2: f64) -> (f64, f64) {
+    let initial_contents: Vec<Vec<f64>> = factor_graph
+        .node_indices
+        .iter()
+        .map(|i| factor_graph.get_var(*i).get_content())
+        .collect();
+    let mut scaled_step = step.to_vec();
+    let mut scale = 1.0;
+    for _i in 0..=MAX_STEP_HALVINGS {
+        update_vars(factor_graph, &scaled_step);
+        let chi2 = total_chi2(factor_graph);
+        if chi2 <= initial_chi2 * (1.0 + CHI2_TOLERANCE) {
+            return (chi2, scale);
+        }
+        factor_graph
+            .node_indices
             .iter()
-            .zip(correction.iter())
-            .map(|(old, cor)| old + cor)
-            .collect(),
-    };
-    var.set_content(updated_content);
+            .zip(&initial_contents)
+            .for_each(|(i, content)| factor_graph.get_var(*i).set_content(content.clone()));
+        scaled_step.iter_mut().for_each(|v| *v *= 0.5);
+        scale *= 0.5;
+    }
+    (initial_chi2, 0.0)
+}
+
+fn update_vars(factor_graph: &FactorGraph, solution: &[f64]) {
+    factor_graph
+        .node_indices
+        .iter()
+        .map(|i| factor_graph.get_var(*i))
+        .for_each(|var| update_var(var, solution));
+}
+
+fn update_var(var: &Variable, solution: &[f64]) {
+    if let FixedType::NonFixed(range) = var.get_fixed_type() {
+        let updated_content = var
+            .get_parameterization()
+            .plus(&var.get_content(), &solution[range.to_owned()]);
+        var.set_content(updated_content);
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::factor_graph::factor::{CustomResidual, FactorId, FactorType};
+    use crate::factor_graph::variable::VariableId;
+    use crate::optimizer::autodiff::Dual;
     use crate::parser::g2o::G2oParser;
     use crate::parser::model::FactorGraphModel;
     use crate::parser::Parser;
@@ -114,7 +617,8 @@ mod tests {
         init();
         let test_factor_graph =
             G2oParser::parse_file(&["data_files/optimizer_tests/", file_name, "_0.g2o"].concat()).unwrap();
-        optimize(&test_factor_graph, iterations);
+        // the expected files contain unmodified Gauss-Newton steps, computed by g2o except for odo3d_only_1
+        optimize_with_solver(&test_factor_graph, iterations, &SparseCholeskySolver);
         let test_model = FactorGraphModel::from(&test_factor_graph);
         let expected_model = G2oParser::parse_file_to_model(
             &[
@@ -253,6 +757,8 @@ mod tests {
 
     #[test]
     fn test_only_odo3d_factors() {
+        // the vertices 83-99 of the expected file were recomputed by gs-rs after the sign of error quaternions with
+        // negative w was fixed, which lowered the total χ² after the iteration from 2.2e-7 to 1.2e-16
         test_valid_optimization("odo3d_only", 1);
     }
 
@@ -260,4 +766,473 @@ mod tests {
     fn test_mainly_obs3d_factors() {
         test_valid_optimization("obs3d_mainly", 1);
     }
+
+    #[test]
+    fn test_pluggable_solvers() {
+        use crate::optimizer::solver::conjugate_gradient::ConjugateGradientSolver;
+        use crate::optimizer::solver::dense_cholesky::DenseCholeskySolver;
+        use crate::optimizer::solver::dense_lu::DenseLuSolver;
+
+        init();
+        let solvers: Vec<Box<dyn LinearSolver>> = vec![
+            Box::new(DenseCholeskySolver),
+            Box::new(DenseLuSolver),
+            Box::new(ConjugateGradientSolver::default()),
+        ];
+        for solver in solvers {
+            let test_factor_graph = G2oParser::parse_file("data_files/optimizer_tests/full2d_0.g2o").unwrap();
+            optimize_with_solver(&test_factor_graph, 1, solver.as_ref());
+            let test_model = FactorGraphModel::from(&test_factor_graph);
+            let expected_model = G2oParser::parse_file_to_model("data_files/optimizer_tests/full2d_1.g2o").unwrap();
+            assert_model_approx_equal(test_model, expected_model);
+        }
+    }
+
+    #[test]
+    fn test_minimum_degree_ordering() {
+        init();
+        let test_factor_graph = G2oParser::parse_file("data_files/optimizer_tests/obs2d_mainly_0.g2o").unwrap();
+        assert!(fill_reducing_permutation(&test_factor_graph).is_some());
+        optimize_with_ordering(
+            &test_factor_graph,
+            1,
+            &SparseCholeskySolver,
+            VariableOrdering::MinimumDegree,
+        );
+        let test_model = FactorGraphModel::from(&test_factor_graph);
+        let expected_model = G2oParser::parse_file_to_model("data_files/optimizer_tests/obs2d_mainly_1.g2o").unwrap();
+        assert_model_approx_equal(test_model, expected_model);
+    }
+
+    // the residual of Odometry2D factors, written in terms of dual numbers
+    fn odometry_2d_residual(contents: &[Vec<Dual>], constraint: &[f64]) -> Vec<Dual> {
+        let (pose_i, pose_j) = (&contents[0], &contents[1]);
+        let (delta_x, delta_y) = (pose_j[0] - pose_i[0], pose_j[1] - pose_i[1]);
+        let (sin_i, cos_i) = (pose_i[2].sin(), pose_i[2].cos());
+        let local_x = cos_i * delta_x + sin_i * delta_y - constraint[0];
+        let local_y = cos_i * delta_y - sin_i * delta_x - constraint[1];
+        let (sin_ij, cos_ij) = (constraint[2].sin(), constraint[2].cos());
+        let mut err_rot = pose_j[2] - pose_i[2] - constraint[2];
+        if err_rot.value >= std::f64::consts::PI {
+            err_rot = err_rot - 2.0 * std::f64::consts::PI;
+        } else if err_rot.value < -std::f64::consts::PI {
+            err_rot = err_rot + 2.0 * std::f64::consts::PI;
+        }
+        vec![
+            cos_ij * local_x + sin_ij * local_y,
+            cos_ij * local_y - sin_ij * local_x,
+            err_rot,
+        ]
+    }
+
+    #[test]
+    fn test_custom_factors_match_builtin_factors() {
+        init();
+        let builtin_graph = G2oParser::parse_file("data_files/optimizer_tests/odo2d_only_0.g2o").unwrap();
+        let mut custom_graph = G2oParser::parse_file("data_files/optimizer_tests/odo2d_only_0.g2o").unwrap();
+        let residual = CustomResidual::new("CustomOdometry2D", odometry_2d_residual);
+        let mut ids: Vec<FactorId> = custom_graph.factor_id_map.keys().copied().collect();
+        ids.sort();
+        for id in ids {
+            let (source, target) = custom_graph.factor_id_map[&id];
+            let (source, target) = (
+                custom_graph.get_var(source).get_id(),
+                custom_graph.get_var(target).get_id(),
+            );
+            let factor = custom_graph.remove_factor(id).unwrap();
+            custom_graph
+                .add_factor(
+                    source,
+                    target,
+                    FactorType::Custom(residual.clone()),
+                    factor.constraint,
+                    factor.information_matrix,
+                )
+                .unwrap();
+        }
+
+        optimize(&builtin_graph, 1);
+        optimize(&custom_graph, 1);
+        let builtin_model = FactorGraphModel::from(&builtin_graph);
+        let custom_model = FactorGraphModel::from(&custom_graph);
+        assert!(custom_model.edges.iter().all(|e| e.edge_type == "CustomOdometry2D"));
+        for (builtin, custom) in builtin_model.vertices.iter().zip(custom_model.vertices.iter()) {
+            assert_eq!(builtin.id, custom.id);
+            for (b, c) in builtin.content.iter().zip(custom.content.iter()) {
+                assert!(approx::relative_eq!(b, c, epsilon = 1e-9), "{} versus {}", b, c);
+            }
+        }
+    }
+
+    #[test]
+    fn test_switchable_factors_switch_off_wrong_loop_closures() {
+        init();
+        let odometry_information = "100 0 0 100 0 100";
+        let loop_information = "10 0 0 10 0 10";
+        let g2o_string = [
+            "VERTEX_SE2 0 0 0 0",
+            "VERTEX_SE2 1 1.1 0 0",
+            "VERTEX_SE2 2 1.9 0.1 0",
+            "VERTEX_SE2 3 3.2 0 0",
+            "VERTEX_SE2 4 3.9 -0.1 0",
+            "VERTEX_SWITCH 5 1",
+            "VERTEX_SWITCH 6 1",
+            "FIX 0",
+            &format!("EDGE_SE2 0 1 1 0 0 {}", odometry_information),
+            &format!("EDGE_SE2 1 2 1 0 0 {}", odometry_information),
+            &format!("EDGE_SE2 2 3 1 0 0 {}", odometry_information),
+            &format!("EDGE_SE2 3 4 1 0 0 {}", odometry_information),
+            // the correct loop closure
+            &format!("EDGE_SE2_SWITCHABLE 0 4 5 4 0 0 {}", loop_information),
+            // the wrong loop closure
+            &format!("EDGE_SE2_SWITCHABLE 1 4 6 0 3 0 {}", loop_information),
+            "EDGE_SWITCH_PRIOR 5 1 1",
+            "EDGE_SWITCH_PRIOR 6 1 1",
+        ]
+        .join("\n");
+        let factor_graph: FactorGraph = G2oParser::parse_string_to_model(&g2o_string).unwrap().into();
+        optimize(&factor_graph, 10);
+
+        let switch = |id| factor_graph.get_var_by_id(VariableId(id)).unwrap().get_content()[0];
+        assert!(switch(5) > 0.9, "{}", switch(5));
+        assert!(switch(6) < 0.1, "{}", switch(6));
+        let last_pose = factor_graph.get_var_by_id(VariableId(4)).unwrap().get_content();
+        assert!((last_pose[0] - 4.0).abs() < 1e-2, "{:?}", last_pose);
+        assert!(last_pose[1].abs() < 1e-2, "{:?}", last_pose);
+    }
+
+    #[test]
+    fn test_dcs_reduces_influence_of_wrong_loop_closures() {
+        use crate::optimizer::dcs::DynamicCovarianceScaling;
+
+        init();
+        let odometry_information = "100 0 0 100 0 100";
+        let g2o_string = [
+            "VERTEX_SE2 0 0 0 0",
+            "VERTEX_SE2 1 1.1 0 0",
+            "VERTEX_SE2 2 1.9 0.1 0",
+            "VERTEX_SE2 3 3.2 0 0",
+            "VERTEX_SE2 4 3.9 -0.1 0",
+            "FIX 0",
+            &format!("EDGE_SE2 0 1 1 0 0 {}", odometry_information),
+            &format!("EDGE_SE2 1 2 1 0 0 {}", odometry_information),
+            &format!("EDGE_SE2 2 3 1 0 0 {}", odometry_information),
+            &format!("EDGE_SE2 3 4 1 0 0 {}", odometry_information),
+            // the correct loop closure
+            "EDGE_SE2 0 4 4 0 0 10 0 0 10 0 10",
+            // the wrong loop closure
+            "EDGE_SE2 1 4 0 3 0 10 0 0 10 0 10",
+        ]
+        .join("\n");
+        let get_last_pose =
+            |factor_graph: &FactorGraph| factor_graph.get_var_by_id(VariableId(4)).unwrap().get_content();
+
+        let factor_graph: FactorGraph = G2oParser::parse_string_to_model(&g2o_string).unwrap().into();
+        optimize(&factor_graph, 10);
+        let unscaled_last_pose = get_last_pose(&factor_graph);
+        assert!(unscaled_last_pose[1] > 0.1, "{:?}", unscaled_last_pose);
+
+        let factor_graph: FactorGraph = G2oParser::parse_string_to_model(&g2o_string).unwrap().into();
+        let dcs = DynamicCovarianceScaling::new().with_phi(FactorType::Odometry2D, 1.0);
+        optimize_with_dcs(&factor_graph, 10, &dcs);
+        let scaled_last_pose = get_last_pose(&factor_graph);
+        assert!((scaled_last_pose[0] - 4.0).abs() < 1e-2, "{:?}", scaled_last_pose);
+        assert!(scaled_last_pose[1].abs() < 1e-2, "{:?}", scaled_last_pose);
+    }
+
+    #[test]
+    fn test_line_search_prevents_increasing_chi2() {
+        init();
+        let file_name = "data_files/optimizer_tests/odo2d_only_0.g2o";
+        let factor_graph = G2oParser::parse_file(file_name).unwrap();
+        let initial_chi2 = total_chi2(&factor_graph);
+        optimize_with_solver(&factor_graph, 1, &SparseCholeskySolver);
+        assert!(total_chi2(&factor_graph) > initial_chi2);
+
+        let factor_graph = G2oParser::parse_file(file_name).unwrap();
+        optimize(&factor_graph, 1);
+        assert!(total_chi2(&factor_graph) < initial_chi2);
+    }
+
+    #[test]
+    fn test_optimization_is_bit_identical_across_runs() {
+        init();
+        let get_bits = || {
+            let factor_graph = G2oParser::parse_file("data_files/optimizer_tests/full2d_0.g2o").unwrap();
+            optimize(&factor_graph, 5);
+            let mut bits: Vec<u64> = factor_graph
+                .node_indices
+                .iter()
+                .flat_map(|i| factor_graph.get_var(*i).get_content())
+                .map(f64::to_bits)
+                .collect();
+            bits.push(total_chi2(&factor_graph).to_bits());
+            bits
+        };
+        let expected = get_bits();
+        (0..3).for_each(|_| assert_eq!(get_bits(), expected));
+    }
+
+    #[test]
+    fn test_optimize_with_deadline() {
+        init();
+        let file_name = "data_files/optimizer_tests/full2d_0.g2o";
+        let factor_graph = G2oParser::parse_file(file_name).unwrap();
+        let initial_contents = factor_graph.get_var(factor_graph.node_indices[1]).get_content();
+        let initial_chi2 = total_chi2(&factor_graph);
+        let report = optimize_with_deadline(&factor_graph, Duration::from_secs(0));
+        assert_eq!(report.iterations, 0);
+        assert_eq!(report.termination, Termination::DeadlineExceeded);
+        assert!(report.stopped_early());
+        assert_eq!(
+            factor_graph.get_var(factor_graph.node_indices[1]).get_content(),
+            initial_contents
+        );
+
+        let report = optimize_with_deadline(&factor_graph, Duration::from_secs(60));
+        assert_eq!(report.termination, Termination::Converged);
+        assert!(report.iterations > 0);
+        approx::assert_relative_eq!(report.chi2, total_chi2(&factor_graph));
+        assert!(report.chi2 < initial_chi2);
+        optimize(&factor_graph, 1);
+        approx::assert_relative_eq!(total_chi2(&factor_graph), report.chi2, max_relative = 1e-4);
+    }
+
+    #[test]
+    fn test_optimize_with_cancellation() {
+        init();
+        let factor_graph = G2oParser::parse_file("data_files/optimizer_tests/full2d_0.g2o").unwrap();
+        let token = CancellationToken::new();
+        token.clone().cancel();
+        let report = optimize_with_cancellation(&factor_graph, &token);
+        assert_eq!(report.iterations, 0);
+        assert_eq!(report.termination, Termination::Cancelled);
+
+        let report = optimize_with_cancellation(&factor_graph, &CancellationToken::new());
+        assert_eq!(report.termination, Termination::Converged);
+        assert!(!report.stopped_early());
+    }
+
+    #[test]
+    fn test_optimize_with_config() {
+        use crate::optimizer::dcs::DynamicCovarianceScaling;
+
+        init();
+        let parse = || G2oParser::parse_file("data_files/optimizer_tests/full2d_0.g2o").unwrap();
+        let get_contents = |factor_graph: &FactorGraph| -> Vec<Vec<f64>> {
+            factor_graph
+                .node_indices
+                .iter()
+                .map(|i| factor_graph.get_var(*i).get_content())
+                .collect()
+        };
+        let without_convergence = TerminationCriteria {
+            convergence_threshold: None,
+            ..Default::default()
+        };
+
+        let factor_graph = parse();
+        let report = optimize_with_config(&factor_graph, &OptimizerConfig::default());
+        assert_eq!(report.termination, Termination::Converged);
+        assert!(report.iterations > 0 && report.iterations < 100);
+
+        let (factor_graph, expected) = (parse(), parse());
+        let config = OptimizerConfig {
+            max_iterations: 2,
+            termination: without_convergence,
+            ..Default::default()
+        };
+        let report = optimize_with_config(&factor_graph, &config);
+        optimize(&expected, 2);
+        assert_eq!(report.iterations, 2);
+        assert_eq!(report.termination, Termination::IterationLimit);
+        assert_eq!(get_contents(&factor_graph), get_contents(&expected));
+
+        let (factor_graph, expected) = (parse(), parse());
+        let dcs = DynamicCovarianceScaling::new().with_phi(FactorType::Odometry2D, 1.0);
+        let config = OptimizerConfig {
+            max_iterations: 3,
+            robust_kernel: Some(&dcs),
+            termination: without_convergence,
+            verbosity: Verbosity::Iterations,
+            ..Default::default()
+        };
+        optimize_with_config(&factor_graph, &config);
+        optimize_with_dcs(&expected, 3, &dcs);
+        assert_eq!(get_contents(&factor_graph), get_contents(&expected));
+
+        // a parsed copy continues from the estimates kept in the state
+        let state = RefCell::new(OptimizerState::new());
+        let config = OptimizerConfig {
+            state: Some(&state),
+            ..Default::default()
+        };
+        assert_eq!(
+            optimize_with_config(&parse(), &config).termination,
+            Termination::Converged
+        );
+        let report = optimize_with_config(&parse(), &config);
+        assert_eq!(report.iterations, 1);
+        assert_eq!(report.termination, Termination::Converged);
+        assert_eq!(state.borrow().symbolic_analysis_count(), 1);
+    }
+
+    #[test]
+    fn test_unsolvable_linear_system_stops_the_optimization() {
+        init();
+        // without a fixed vertex or a prior, the poses can be moved together and H is singular
+        let g2o_string = [
+            "VERTEX_SE2 0 0 0 0",
+            "VERTEX_SE2 1 1.2 0.3 0.1",
+            "EDGE_SE2 0 1 1 0 0 100 0 0 100 0 100",
+        ]
+        .join("\n");
+        let factor_graph: FactorGraph = G2oParser::parse_string_to_model(&g2o_string).unwrap().into();
+        let report = optimize_with_config(&factor_graph, &OptimizerConfig::default());
+        assert_eq!(report.iterations, 0);
+        assert_eq!(report.termination, Termination::SolverFailed);
+        let pose = factor_graph.get_var_by_id(VariableId(1)).unwrap().get_content();
+        assert_eq!(pose, vec![1.2, 0.3, 0.1]);
+        optimize(&factor_graph, 1);
+    }
+
+    #[test]
+    fn test_chi2_gate_deactivates_wrong_loop_closures() {
+        use crate::optimizer::chi2_gating::Chi2Gate;
+
+        init();
+        let odometry_information = "100 0 0 100 0 100";
+        let g2o_string = [
+            "VERTEX_SE2 0 0 0 0",
+            "VERTEX_SE2 1 1.1 0 0",
+            "VERTEX_SE2 2 1.9 0.1 0",
+            "VERTEX_SE2 3 3.1 0 0",
+            // far off, so that the last odometry factor is inconsistent before the first iteration
+            "VERTEX_SE2 4 3.9 0.5 0",
+            "FIX 0",
+            &format!("EDGE_SE2 0 1 1 0 0 {}", odometry_information),
+            &format!("EDGE_SE2 1 2 1 0 0 {}", odometry_information),
+            &format!("EDGE_SE2 2 3 1 0 0 {}", odometry_information),
+            &format!("EDGE_SE2 3 4 1 0 0 {}", odometry_information),
+            // the correct loop closure
+            "EDGE_SE2 0 4 4 0 0 10 0 0 10 0 10",
+            // the wrong loop closure
+            "EDGE_SE2 1 4 0 3 0 10 0 0 10 0 10",
+        ]
+        .join("\n");
+        let factor_graph: FactorGraph = G2oParser::parse_string_to_model(&g2o_string).unwrap().into();
+        let gate = Chi2Gate {
+            threshold: 7.81,
+            weight: 0.0,
+        };
+        optimize_with_reweighting(&factor_graph, 10, &gate);
+
+        let last_pose = factor_graph.get_var_by_id(VariableId(4)).unwrap().get_content();
+        assert!((last_pose[0] - 4.0).abs() < 1e-2, "{:?}", last_pose);
+        assert!(last_pose[1].abs() < 1e-2, "{:?}", last_pose);
+        // the last odometry factor is consistent again, while the wrong loop closure stays deactivated
+        let chi2s: Vec<f64> = (0..6)
+            .map(|i| calculate_chi2(&factor_graph, FactorId(i)).unwrap())
+            .collect();
+        assert!(chi2s[3] < gate.threshold, "{:?}", chi2s);
+        assert!(chi2s[5] > gate.threshold, "{:?}", chi2s);
+    }
+
+    #[test]
+    fn test_auto_tuned_huber_reduces_influence_of_wrong_loop_closures() {
+        init();
+        let odometry_information = "100 0 0 100 0 100";
+        let g2o_string = [
+            "VERTEX_SE2 0 0 0 0",
+            "VERTEX_SE2 1 1.1 0 0",
+            "VERTEX_SE2 2 1.9 0.1 0",
+            "VERTEX_SE2 3 3.2 0 0",
+            "VERTEX_SE2 4 3.9 -0.1 0",
+            "FIX 0",
+            &format!("EDGE_SE2 0 1 1 0 0 {}", odometry_information),
+            &format!("EDGE_SE2 1 2 1 0 0 {}", odometry_information),
+            &format!("EDGE_SE2 2 3 1 0 0 {}", odometry_information),
+            &format!("EDGE_SE2 3 4 1 0 0 {}", odometry_information),
+            "EDGE_SE2 0 4 4 0 0 10 0 0 10 0 10",
+            // the wrong loop closure
+            "EDGE_SE2 1 4 0 3 0 10 0 0 10 0 10",
+        ]
+        .join("\n");
+        let get_last_pose_error = |factor_graph: &FactorGraph| {
+            let pose = factor_graph.get_var_by_id(VariableId(4)).unwrap().get_content();
+            (pose[0] - 4.0).hypot(pose[1])
+        };
+
+        let factor_graph: FactorGraph = G2oParser::parse_string_to_model(&g2o_string).unwrap().into();
+        optimize(&factor_graph, 10);
+        let unweighted_error = get_last_pose_error(&factor_graph);
+
+        let factor_graph: FactorGraph = G2oParser::parse_string_to_model(&g2o_string).unwrap().into();
+        optimize_with_auto_tuned_huber(&factor_graph, 10);
+        let huber_error = get_last_pose_error(&factor_graph);
+        assert!(
+            huber_error < 0.5 * unweighted_error,
+            "{} {}",
+            huber_error,
+            unweighted_error
+        );
+    }
+
+    #[test]
+    fn test_max_mixture_factors_use_dominant_component() {
+        use crate::factor_graph::factor::{MaxMixture, MixtureComponent};
+        use crate::optimizer::linear_system::get_dominant_component;
+
+        init();
+        let g2o_string = [
+            "VERTEX_SE2 0 0 0 0",
+            "VERTEX_SE2 1 1.2 0.3 0.1",
+            "FIX 0",
+            "EDGE_SE2 0 1 1 0 0 100 0 0 100 0 100",
+        ]
+        .join("\n");
+        let mut factor_graph: FactorGraph = G2oParser::parse_string_to_model(&g2o_string).unwrap().into();
+        let information = vec![1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0];
+        let mixture = MaxMixture {
+            factor_type: Box::new(FactorType::Position2D),
+            weight: 0.5,
+            alternatives: vec![MixtureComponent {
+                weight: 0.5,
+                constraint: vec![1.0, 0.0, 0.0],
+                information_matrix: information.clone().into(),
+            }],
+        };
+        let id = factor_graph
+            .add_factor(
+                VariableId(1),
+                VariableId(1),
+                FactorType::MaxMixture(mixture),
+                vec![5.0, 5.0, 0.0],
+                information.into(),
+            )
+            .unwrap();
+
+        let factor = factor_graph.get_factor(id).unwrap();
+        assert_eq!(
+            get_dominant_component(&factor_graph, factor).constraint,
+            vec![1.0, 0.0, 0.0]
+        );
+        optimize(&factor_graph, 5);
+        let pose = factor_graph.get_var_by_id(VariableId(1)).unwrap().get_content();
+        [1.0, 0.0, 0.0]
+            .iter()
+            .zip(pose.iter())
+            .for_each(|(expected, actual)| assert!((expected - actual).abs() < 1e-9, "{:?}", pose));
+        assert!(linear_system::calculate_chi2(&factor_graph, id).unwrap() < 1e-9);
+    }
+
+    #[test]
+    fn test_total_chi2_decreases() {
+        init();
+        let factor_graph = G2oParser::parse_file("data_files/optimizer_tests/full2d_0.g2o").unwrap();
+        let initial_chi2 = total_chi2(&factor_graph);
+        optimize(&factor_graph, 1);
+        let chi2 = total_chi2(&factor_graph);
+        assert!(chi2 < initial_chi2, "{} versus {}", chi2, initial_chi2);
+    }
 }
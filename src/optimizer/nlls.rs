@@ -0,0 +1,350 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Export of a factor graph as a generic nonlinear least squares (NLLS) problem, e.g. to cross-validate and
+//! benchmark gs-rs against external solvers.
+
+use crate::factor_graph::adjacency::NodeIndex;
+use crate::factor_graph::factor::FactorId;
+use crate::factor_graph::variable::FixedType;
+use crate::factor_graph::FactorGraph;
+use crate::optimizer::linear_system::{calculate_error, calculate_jacobian, get_dominant_component};
+use nalgebra::{DMatrix, DVector, SymmetricEigen};
+
+/// Nonlinear least squares problem min_x ||r(x)||², whose parameters x are the corrections of the estimates at the
+/// time the problem was created, i.e. the initial parameters are zero.
+///
+/// The parameters are the tangent components of the non-fixed variables in the order of their ranges in H, without
+/// [fixed components](../../factor_graph/struct.FactorGraph.html#method.fix_components). Corrections are applied
+/// like in the optimizer, e.g. on the manifold SE(3) for 3D poses. The residuals are the factors' errors whitened by
+/// their information matrices, in the order of the factor IDs, so that ||r(x)||² equals the total χ² at the
+/// corrected estimates. Max-mixture factors contribute their dominant component at the evaluated parameters.
+///
+/// The Jacobian of each factor is the one with respect to the corrections of its variables at the evaluated
+/// estimates, as used by the optimizer. It is the exact derivative of r for vector spaces and 2D poses, and for 3D
+/// poses at x = 0, so external solvers should be restarted from the corrected estimates, see
+/// [apply](#method.apply), to compare their steps with the ones of gs-rs.
+///
+/// The factor graph is only changed by [apply](#method.apply); evaluations restore its estimates.
+///
+/// ```
+/// use gs_rs::optimizer::nlls::NllsProblem;
+/// use gs_rs::optimizer::total_chi2;
+/// use gs_rs::parser::g2o::G2oParser;
+/// use gs_rs::parser::Parser;
+///
+/// let factor_graph = G2oParser::parse_file("data_files/optimizer_tests/full2d_0.g2o").unwrap();
+/// let problem = NllsProblem::new(&factor_graph);
+/// let residuals = problem.residuals(&problem.initial_parameters());
+/// let squared_norm: f64 = residuals.iter().map(|r| r * r).sum();
+/// assert!((squared_norm - total_chi2(&factor_graph)).abs() < 1e-9 * squared_norm);
+/// ```
+pub struct NllsProblem<'a> {
+    factor_graph: &'a FactorGraph,
+    // the non-fixed variables and their estimates when the problem was created
+    initial_estimates: Vec<(NodeIndex<usize>, Vec<f64>)>,
+    // the parameter of each row in H, or None for fixed components
+    parameter_indices: Vec<Option<usize>>,
+    parameter_count: usize,
+    blocks: Vec<ResidualBlock>,
+}
+
+// the residuals of a single factor
+struct ResidualBlock {
+    id: FactorId,
+    first_row: usize,
+    row_count: usize,
+    // the parameter of each column of the factor's Jacobian, or None for fixed variables and components
+    columns: Vec<Option<usize>>,
+    // the sorted parameters of the columns
+    parameters: Vec<usize>,
+}
+
+impl<'a> NllsProblem<'a> {
+    /// Returns the problem of the factor graph at its current estimates.
+    pub fn new(factor_graph: &'a FactorGraph) -> Self {
+        let fixed_rows = factor_graph.get_fixed_rows();
+        let mut parameter_count = 0;
+        let parameter_indices = (0..factor_graph.matrix_dim)
+            .map(|row| match fixed_rows.binary_search(&row) {
+                Ok(_) => None,
+                Err(_) => {
+                    parameter_count += 1;
+                    Some(parameter_count - 1)
+                }
+            })
+            .collect();
+        let initial_estimates = factor_graph
+            .node_indices
+            .iter()
+            .filter(|i| factor_graph.get_var(**i).get_fixed_type() != &FixedType::Fixed)
+            .map(|i| (*i, factor_graph.get_var(*i).get_content()))
+            .collect();
+        let mut problem = NllsProblem {
+            factor_graph,
+            initial_estimates,
+            parameter_indices,
+            parameter_count,
+            blocks: vec![],
+        };
+        let mut first_row = 0;
+        for id in factor_graph.factor_id_map.keys() {
+            let factor = factor_graph.get_factor(*id).unwrap();
+            let columns: Vec<Option<usize>> = factor_graph
+                .get_factor_var_indices(*id)
+                .unwrap()
+                .iter()
+                .map(|i| factor_graph.get_var(*i))
+                .flat_map(|var| {
+                    let tangent_dim = var.get_parameterization().tangent_dim();
+                    let rows: Vec<Option<usize>> = match var.get_fixed_type() {
+                        FixedType::NonFixed(range) => range.clone().map(Some).collect(),
+                        FixedType::Fixed => vec![None; tangent_dim],
+                    };
+                    rows.into_iter()
+                        .map(|row| row.and_then(|row| problem.parameter_indices[row]))
+                })
+                .collect();
+            let mut parameters: Vec<usize> = columns.iter().flatten().copied().collect();
+            parameters.sort_unstable();
+            parameters.dedup();
+            let row_count = factor.information_matrix.content.nrows();
+            problem.blocks.push(ResidualBlock {
+                id: *id,
+                first_row,
+                row_count,
+                columns,
+                parameters,
+            });
+            first_row += row_count;
+        }
+        problem
+    }
+
+    /// Returns the number of parameters.
+    pub fn parameter_count(&self) -> usize {
+        self.parameter_count
+    }
+
+    /// Returns the number of residuals.
+    pub fn residual_count(&self) -> usize {
+        self.blocks.last().map_or(0, |block| block.first_row + block.row_count)
+    }
+
+    /// Returns the initial parameters, which are zero.
+    pub fn initial_parameters(&self) -> Vec<f64> {
+        vec![0.0; self.parameter_count]
+    }
+
+    /// Returns the residuals at the given parameters.
+    pub fn residuals(&self, parameters: &[f64]) -> Vec<f64> {
+        self.evaluate(parameters, |id, whitening| {
+            (whitening * calculate_error(self.factor_graph, id).unwrap())
+                .as_slice()
+                .to_vec()
+        })
+        .into_iter()
+        .flatten()
+        .collect()
+    }
+
+    /// Returns the positions (row, column) of the structurally non-zero entries of the Jacobian, sorted by row and
+    /// column. They do not depend on the parameters.
+    pub fn jacobian_pattern(&self) -> Vec<(usize, usize)> {
+        self.blocks
+            .iter()
+            .flat_map(|block| {
+                (block.first_row..block.first_row + block.row_count)
+                    .flat_map(move |row| block.parameters.iter().map(move |column| (row, *column)))
+            })
+            .collect()
+    }
+
+    /// Returns the entries of the Jacobian at the given parameters in the order of the
+    /// [pattern](#method.jacobian_pattern).
+    ///
+    /// Columns of variables which share their range in H, e.g. due to equality constraints, are summed up.
+    pub fn jacobian(&self, parameters: &[f64]) -> Vec<f64> {
+        let blocks = self.evaluate(parameters, |id, whitening| {
+            // row-major, like the pattern
+            (whitening * calculate_jacobian(self.factor_graph, id).unwrap())
+                .transpose()
+                .as_slice()
+                .to_vec()
+        });
+        self.blocks
+            .iter()
+            .zip(blocks)
+            .flat_map(|(block, jacobian)| {
+                let column_count = block.columns.len();
+                (0..block.row_count)
+                    .flat_map(|row| {
+                        let mut entries = vec![0.0; block.parameters.len()];
+                        for (k, column) in block.columns.iter().enumerate() {
+                            if let Some(column) = column {
+                                let entry = block.parameters.binary_search(column).unwrap();
+                                entries[entry] += jacobian[row * column_count + k];
+                            }
+                        }
+                        entries
+                    })
+                    .collect::<Vec<f64>>()
+            })
+            .collect()
+    }
+
+    /// Applies the corrections of the given parameters to the estimates of the factor graph, e.g. to write back the
+    /// solution of an external solver.
+    pub fn apply(&self, parameters: &[f64]) {
+        self.set_estimates(parameters);
+    }
+
+    // evaluates the function for each factor with its whitening matrix at the given parameters and restores the
+    // initial estimates afterwards
+    fn evaluate<T>(&self, parameters: &[f64], f: impl Fn(FactorId, &DMatrix<f64>) -> T) -> Vec<T> {
+        assert_eq!(parameters.len(), self.parameter_count, "Wrong number of parameters");
+        self.set_estimates(parameters);
+        let results = self
+            .blocks
+            .iter()
+            .map(|block| {
+                let factor = self.factor_graph.get_factor(block.id).unwrap();
+                let component = get_dominant_component(self.factor_graph, factor);
+                f(block.id, &get_whitening(&component.information_matrix.content))
+            })
+            .collect();
+        self.initial_estimates
+            .iter()
+            .for_each(|(i, estimate)| self.factor_graph.get_var(*i).set_content(estimate.clone()));
+        results
+    }
+
+    // sets the estimates to the initial ones corrected by the given parameters
+    fn set_estimates(&self, parameters: &[f64]) {
+        for (i, estimate) in &self.initial_estimates {
+            let var = self.factor_graph.get_var(*i);
+            if let FixedType::NonFixed(range) = var.get_fixed_type() {
+                let correction: Vec<f64> = range
+                    .clone()
+                    .map(|row| self.parameter_indices[row].map_or(0.0, |p| parameters[p]))
+                    .collect();
+                var.set_content(var.get_parameterization().plus(estimate, &correction));
+            }
+        }
+    }
+}
+
+// returns W with W^T * W = information, which also exists for positive semi-definite information matrices
+fn get_whitening(information: &DMatrix<f64>) -> DMatrix<f64> {
+    let eigen = SymmetricEigen::new(information.clone());
+    let roots = DVector::from_iterator(
+        eigen.eigenvalues.len(),
+        eigen.eigenvalues.iter().map(|value| value.max(0.0).sqrt()),
+    );
+    DMatrix::from_diagonal(&roots) * eigen.eigenvectors.transpose()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::factor_graph::variable::VariableId;
+    use crate::optimizer::linear_system::calculate_H_b;
+    use crate::optimizer::{optimize, total_chi2};
+    use crate::parser::g2o::G2oParser;
+    use crate::parser::Parser;
+
+    fn get_dense_jacobian(problem: &NllsProblem, parameters: &[f64]) -> DMatrix<f64> {
+        let mut jacobian = DMatrix::zeros(problem.residual_count(), problem.parameter_count());
+        for ((row, column), entry) in problem.jacobian_pattern().iter().zip(problem.jacobian(parameters)) {
+            jacobian[(*row, *column)] += entry;
+        }
+        jacobian
+    }
+
+    #[test]
+    fn test_normal_equations_match_H_b() {
+        for file_name in &["full2d_0", "obs3d_mainly_0", "odo3d_only_0"] {
+            let path = format!("data_files/optimizer_tests/{}.g2o", file_name);
+            let factor_graph = G2oParser::parse_file(&path).unwrap();
+            let problem = NllsProblem::new(&factor_graph);
+            let parameters = problem.initial_parameters();
+            let residuals = DVector::from_vec(problem.residuals(&parameters));
+            let jacobian = get_dense_jacobian(&problem, &parameters);
+            assert_eq!(problem.parameter_count(), factor_graph.matrix_dim);
+            assert_eq!(residuals.len(), problem.residual_count());
+
+            let (H, b) = calculate_H_b(&factor_graph);
+            let H = H.to_dense();
+            // the whitening matrices are only accurate relative to the largest eigenvalues
+            let tolerance = 1e-8 * (1.0 + H.norm());
+            assert!(
+                (jacobian.transpose() * &jacobian - &H).norm() < tolerance,
+                "{}",
+                file_name
+            );
+            assert!(
+                (jacobian.transpose() * &residuals - &b).norm() < tolerance,
+                "{}",
+                file_name
+            );
+            assert!(
+                (residuals.norm_squared() - total_chi2(&factor_graph)).abs() < tolerance,
+                "{}",
+                file_name
+            );
+        }
+    }
+
+    #[test]
+    fn test_external_gauss_newton_step() {
+        let path = "data_files/optimizer_tests/full2d_0.g2o";
+        let expected = G2oParser::parse_file(path).unwrap();
+        optimize(&expected, 1);
+        let factor_graph = G2oParser::parse_file(path).unwrap();
+        let initial_chi2 = total_chi2(&factor_graph);
+        let problem = NllsProblem::new(&factor_graph);
+        let parameters = problem.initial_parameters();
+        let jacobian = get_dense_jacobian(&problem, &parameters);
+        let residuals = DVector::from_vec(problem.residuals(&parameters));
+        let step = (jacobian.transpose() * &jacobian)
+            .cholesky()
+            .unwrap()
+            .solve(&(-jacobian.transpose() * residuals));
+
+        // evaluations do not change the estimates
+        let shifted_residuals = problem.residuals(step.as_slice());
+        assert_eq!(total_chi2(&factor_graph), initial_chi2);
+        let shifted_chi2: f64 = shifted_residuals.iter().map(|r| r * r).sum();
+        problem.apply(step.as_slice());
+        assert!((total_chi2(&factor_graph) - shifted_chi2).abs() < 1e-9 * shifted_chi2);
+        assert!((total_chi2(&factor_graph) - total_chi2(&expected)).abs() < 1e-6 * total_chi2(&expected));
+    }
+
+    #[test]
+    fn test_fixed_components_are_not_parameters() {
+        let mut factor_graph = G2oParser::parse_file("data_files/optimizer_tests/full2d_0.g2o").unwrap();
+        factor_graph
+            .fix_components(VariableId(1), vec![false, false, true])
+            .unwrap();
+        let problem = NllsProblem::new(&factor_graph);
+        assert_eq!(problem.parameter_count(), factor_graph.matrix_dim - 1);
+        let pattern = problem.jacobian_pattern();
+        assert_eq!(pattern.len(), problem.jacobian(&problem.initial_parameters()).len());
+        assert!(pattern.iter().all(|(_, column)| *column < problem.parameter_count()));
+        let rotation = factor_graph.get_var_by_id(VariableId(1)).unwrap().get_content()[2];
+        problem.apply(&vec![0.1; problem.parameter_count()]);
+        assert_eq!(
+            factor_graph.get_var_by_id(VariableId(1)).unwrap().get_content()[2],
+            rotation
+        );
+    }
+}
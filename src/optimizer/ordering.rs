@@ -0,0 +1,229 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Fill-reducing orderings of the linear system's variable blocks.
+//!
+//! Eliminating the variables of a sparse system in a good order drastically reduces the fill-in of its
+//! Cholesky factor. The ordering is computed on the block structure of the factor graph, i.e. with one node
+//! per non-fixed variable, and then expanded to the rows of H.
+
+#![allow(non_snake_case)]
+
+use crate::factor_graph::variable::FixedType;
+use crate::factor_graph::FactorGraph;
+use crate::optimizer::block_sparse::BlockSparseMatrix;
+use nalgebra::DVector;
+use crate::factor_graph::adjacency::NodeIndex;
+use std::collections::{BTreeMap, BTreeSet};
+use std::ops::Range;
+
+/// The order in which the variable blocks are passed to the linear solver.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VariableOrdering {
+    /// The variables keep the order of their ranges in H.
+    Natural,
+    /// The variables are reordered by [minimum_degree_ordering](fn.minimum_degree_ordering.html) if this
+    /// reduces the fill-in. Recommended for sparse solvers.
+    MinimumDegree,
+}
+
+/// Returns the internal indices of all non-fixed variables in a greedy minimum-degree elimination order.
+///
+/// In each step, the variable with the fewest (not yet eliminated) neighbors is eliminated and its neighbors
+/// are connected to each other. Ties are broken by the lower internal index, so the result is deterministic.
+pub fn minimum_degree_ordering(factor_graph: &FactorGraph) -> Vec<NodeIndex<usize>> {
+    let mut adjacency = get_block_adjacency(factor_graph);
+    let mut order = Vec::with_capacity(adjacency.len());
+    while let Some(next) = adjacency
+        .iter()
+        .min_by_key(|(i, neighbors)| (neighbors.len(), **i))
+        .map(|(i, _)| *i)
+    {
+        eliminate(&mut adjacency, next);
+        order.push(next);
+    }
+    order
+}
+
+/// Returns the number of blocks which are filled in when eliminating the variables in the given order.
+pub fn symbolic_fill(factor_graph: &FactorGraph, order: &[NodeIndex<usize>]) -> usize {
+    let mut adjacency = get_block_adjacency(factor_graph);
+    order.iter().map(|i| eliminate(&mut adjacency, *i)).sum()
+}
+
+/// Returns the fill-reducing permutation of H's rows and columns, i.e. the original row index for each new row.
+///
+/// Returns None if the minimum-degree ordering does not cause less fill-in than the original ordering.
+pub fn fill_reducing_permutation(factor_graph: &FactorGraph) -> Option<Vec<usize>> {
+    let natural_order: Vec<NodeIndex<usize>> = factor_graph
+        .node_indices
+        .iter()
+        .filter(|i| is_ordered(factor_graph, **i))
+        .copied()
+        .collect();
+    let order = minimum_degree_ordering(factor_graph);
+    if symbolic_fill(factor_graph, &order) >= symbolic_fill(factor_graph, &natural_order) {
+        return None;
+    }
+    Some(
+        order
+            .into_iter()
+            .flat_map(|i| get_range(factor_graph, i).unwrap())
+            .collect(),
+    )
+}
+
+/// Returns P*H*P^T and P*b for the permutation P given as the original row index for each new row.
+pub fn permute_system(
+    H: &BlockSparseMatrix,
+    b: &DVector<f64>,
+    permutation: &[usize],
+) -> (BlockSparseMatrix, DVector<f64>) {
+    (H.permute(permutation), b.select_rows(permutation))
+}
+
+/// Reverts the permutation on the solution of a permuted linear system.
+pub fn unpermute_solution(solution: &[f64], permutation: &[usize]) -> Vec<f64> {
+    let mut unpermuted = vec![0.0; solution.len()];
+    permutation
+        .iter()
+        .zip(solution)
+        .for_each(|(original, value)| unpermuted[*original] = *value);
+    unpermuted
+}
+
+type BlockAdjacency = BTreeMap<NodeIndex<usize>, BTreeSet<NodeIndex<usize>>>;
+
+fn get_block_adjacency(factor_graph: &FactorGraph) -> BlockAdjacency {
+    let mut adjacency: BlockAdjacency = factor_graph
+        .node_indices
+        .iter()
+        .filter(|i| is_ordered(factor_graph, **i))
+        .map(|i| (*i, BTreeSet::new()))
+        .collect();
+    factor_graph.factor_id_map.keys().for_each(|id| {
+        let indices: Vec<NodeIndex<usize>> = factor_graph
+            .get_factor_var_indices(*id)
+            .unwrap()
+            .into_iter()
+            .map(|i| factor_graph.get_representative(i))
+            .filter(|i| adjacency.contains_key(i))
+            .collect();
+        for a in &indices {
+            adjacency
+                .get_mut(a)
+                .unwrap()
+                .extend(indices.iter().filter(|b| *b != a));
+        }
+    });
+    adjacency
+}
+
+// removes the variable, connects its neighbors with each other and returns the number of added connections
+fn eliminate(adjacency: &mut BlockAdjacency, eliminated: NodeIndex<usize>) -> usize {
+    let neighbors = adjacency.remove(&eliminated).unwrap();
+    let mut fill = 0;
+    for n in &neighbors {
+        let n_neighbors = adjacency.get_mut(n).unwrap();
+        n_neighbors.remove(&eliminated);
+        for m in neighbors.iter().filter(|m| *m != n) {
+            if n_neighbors.insert(*m) {
+                fill += 1;
+            }
+        }
+    }
+    fill / 2
+}
+
+// returns whether the variable has its own range in H, i.e. whether it is neither fixed nor tied to another variable
+fn is_ordered(factor_graph: &FactorGraph, csr_index: NodeIndex<usize>) -> bool {
+    get_range(factor_graph, csr_index).is_some() && factor_graph.get_representative(csr_index) == csr_index
+}
+
+fn get_range(factor_graph: &FactorGraph, csr_index: NodeIndex<usize>) -> Option<Range<usize>> {
+    match factor_graph.get_var(csr_index).get_fixed_type() {
+        FixedType::NonFixed(range) => Some(range.to_owned()),
+        FixedType::Fixed => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::g2o::G2oParser;
+    use crate::parser::Parser;
+    use nalgebra::DMatrix;
+
+    fn get_star_graph() -> FactorGraph {
+        // star around vehicle 1: eliminating the center first would connect all leaves with each other
+        let information = "1 0 0 1 0 1";
+        let g2o_string = [
+            "VERTEX_SE2 0 0 0 0",
+            "FIX 0",
+            "VERTEX_SE2 1 1 0 0",
+            "VERTEX_SE2 2 2 0 0",
+            "VERTEX_SE2 3 1 1 0",
+            "VERTEX_SE2 4 1 -1 0",
+            &format!("EDGE_SE2 0 1 1 0 0 {}", information),
+            &format!("EDGE_SE2 1 2 1 0 0 {}", information),
+            &format!("EDGE_SE2 1 3 0 1 0 {}", information),
+            &format!("EDGE_SE2 1 4 0 -1 0 {}", information),
+        ]
+        .join("\n");
+        G2oParser::parse_string_to_model(&g2o_string).unwrap().into()
+    }
+
+    #[test]
+    fn test_leaves_are_eliminated_first() {
+        let factor_graph = get_star_graph();
+        let order: Vec<_> = minimum_degree_ordering(&factor_graph)
+            .into_iter()
+            .map(|i| factor_graph.get_var(i).get_id().0)
+            .collect();
+        assert_eq!(order, vec![2, 3, 1, 4]);
+    }
+
+    #[test]
+    fn test_permutation_reduces_fill() {
+        let factor_graph = get_star_graph();
+        let natural_order = factor_graph.node_indices[1..].to_vec();
+        assert_eq!(symbolic_fill(&factor_graph, &natural_order), 3);
+        assert_eq!(symbolic_fill(&factor_graph, &minimum_degree_ordering(&factor_graph)), 0);
+        let mut permutation = fill_reducing_permutation(&factor_graph).unwrap();
+        assert_eq!(permutation.len(), factor_graph.matrix_dim);
+        permutation.sort_unstable();
+        assert_eq!(permutation, (0..factor_graph.matrix_dim).collect::<Vec<usize>>());
+    }
+
+    #[test]
+    fn test_no_permutation_without_fill() {
+        let factor_graph = G2oParser::parse_file("data_files/optimizer_tests/pos2d_and_odo2d_0.g2o").unwrap();
+        assert!(fill_reducing_permutation(&factor_graph).is_none());
+    }
+
+    #[test]
+    fn test_permute_and_unpermute() {
+        let mut H = BlockSparseMatrix::new(3);
+        H.add_block(0..1, 0..1, &DMatrix::from_element(1, 1, 1.0));
+        H.add_block(0..1, 1..3, &DMatrix::from_row_slice(1, 2, &[2.0, 3.0]));
+        H.add_block(1..3, 0..1, &DMatrix::from_column_slice(2, 1, &[2.0, 3.0]));
+        H.add_block(1..3, 1..3, &DMatrix::from_row_slice(2, 2, &[4.0, 5.0, 5.0, 6.0]));
+        let b = DVector::from_vec(vec![1.0, 2.0, 3.0]);
+        let permutation = vec![1, 2, 0];
+        let (H_perm, b_perm) = permute_system(&H, &b, &permutation);
+        let H_perm = H_perm.to_dense();
+        assert_eq!(H_perm[(0, 0)], 4.0);
+        assert_eq!(H_perm[(0, 2)], 2.0);
+        assert_eq!(b_perm.as_slice(), &[2.0, 3.0, 1.0]);
+        assert_eq!(unpermute_solution(b_perm.as_slice(), &permutation), vec![1.0, 2.0, 3.0]);
+    }
+}
@@ -0,0 +1,246 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Regularization of rank-deficient linear systems and the diagnosis of their cause.
+//!
+//! H is singular if the factors do not determine all variables, e.g. for connected components of the factor graph
+//! without a fixed variable or a Position2D or Position3D factor, or for landmarks without observations. Then
+//! Cholesky decompositions fail and other solvers may return NaNs.
+
+#![allow(non_snake_case)]
+
+use crate::factor_graph::adjacency::NodeIndex;
+use crate::factor_graph::topology::UnionFind;
+use crate::factor_graph::variable::{FixedType, VariableId};
+use crate::factor_graph::FactorGraph;
+use crate::optimizer::block_sparse::BlockSparseMatrix;
+use crate::optimizer::linear_system::calculate_H_b;
+use crate::optimizer::solver::LinearSolver;
+use nalgebra::{DMatrix, DVector, SymmetricEigen};
+use std::collections::btree_map::Entry;
+use std::collections::BTreeMap;
+
+/// The relative eigenvalue of H up to which its eigenvector is considered unconstrained.
+const NULL_SPACE_TOLERANCE: f64 = 1e-9;
+
+/// Linear solver which adds a multiple of the identity to H before passing the linear system to the wrapped solver,
+/// which makes rank-deficient systems solvable (Tikhonov regularization).
+///
+/// Since b has no component in the directions in which H is singular, the steps in these directions are zero. All
+/// other steps are biased towards zero, so the damping should be small compared to the diagonal of H.
+pub struct TikhonovRegularization<S: LinearSolver> {
+    solver: S,
+    damping: f64,
+}
+
+impl<S: LinearSolver> TikhonovRegularization<S> {
+    /// Returns the wrapped solver with the given damping, which is added to each diagonal entry of H.
+    pub fn new(solver: S, damping: f64) -> Self {
+        TikhonovRegularization { solver, damping }
+    }
+}
+
+impl<S: LinearSolver> LinearSolver for TikhonovRegularization<S> {
+    fn name(&self) -> String {
+        format!("TikhonovRegularization({}, damping {})", self.solver.name(), self.damping)
+    }
+
+    fn solve(&self, H: DMatrix<f64>, b: &DVector<f64>) -> Result<Vec<f64>, String> {
+        let dim = H.nrows();
+        self.solver.solve(H + DMatrix::identity(dim, dim) * self.damping, b)
+    }
+
+    /// Keeps H block-sparse. Entries of the diagonal which are not part of a block, i.e. of variables without
+    /// factors, become blocks of their own.
+    fn solve_block_sparse(&self, H: &BlockSparseMatrix, b: &DVector<f64>) -> Result<Vec<f64>, String> {
+        let mut damped_H = H.clone();
+        let mut is_covered = vec![false; H.dim()];
+        for ((row, col), block) in H.blocks() {
+            if row == col {
+                let range = *row..row + block.nrows();
+                is_covered[range.clone()].iter_mut().for_each(|c| *c = true);
+                damped_H.add_block(
+                    range.clone(),
+                    range,
+                    &(DMatrix::identity(block.nrows(), block.nrows()) * self.damping),
+                );
+            }
+        }
+        for i in (0..H.dim()).filter(|i| !is_covered[*i]) {
+            damped_H.add_block(i..i + 1, i..i + 1, &DMatrix::from_element(1, 1, self.damping));
+        }
+        self.solver.solve_block_sparse(&damped_H, b)
+    }
+}
+
+/// Variables whose estimates are not fully determined by the factors.
+#[derive(Debug, Clone, PartialEq)]
+pub struct UnconstrainedBlock {
+    /// The non-fixed variables which are affected, in the order of insertion. They form a connected component of
+    /// the factor graph without its fixed variables, or a part of it.
+    pub variables: Vec<VariableId>,
+    /// The number of independent directions in which the variables can move without changing the total χ² to
+    /// second order, e.g. 3 for a 2D component without an anchor.
+    pub null_space_dimension: usize,
+}
+
+/// Returns the variables which are not fully determined by the factors at the current estimates, grouped by the
+/// connected components of the factor graph without its fixed variables.
+///
+/// The null space of H is calculated with a dense eigendecomposition of each component's part of H, so this is only
+/// meant as a diagnostic, e.g. if the optimization fails.
+pub fn find_unconstrained_blocks(factor_graph: &FactorGraph) -> Vec<UnconstrainedBlock> {
+    let is_non_fixed = |i: NodeIndex<usize>| factor_graph.get_var(i).get_fixed_type() != &FixedType::Fixed;
+    let mut components = UnionFind::new(factor_graph.node_indices.len());
+    for id in factor_graph.factor_id_map.keys() {
+        let indices: Vec<NodeIndex<usize>> = factor_graph
+            .get_factor_var_indices(*id)
+            .unwrap()
+            .into_iter()
+            .filter(|i| is_non_fixed(*i))
+            .collect();
+        indices.windows(2).for_each(|pair| components.union(pair[0].index(), pair[1].index()));
+    }
+    let mut component_vars: BTreeMap<usize, Vec<NodeIndex<usize>>> = BTreeMap::new();
+    for i in factor_graph.node_indices.iter().copied().filter(|i| is_non_fixed(*i)) {
+        component_vars.entry(components.find(i.index())).or_default().push(i);
+    }
+
+    let (H, _) = calculate_H_b(factor_graph);
+    let mut blocks: Vec<UnconstrainedBlock> = component_vars
+        .values()
+        .filter_map(|vars| find_unconstrained_block(factor_graph, &H, vars))
+        .collect();
+    blocks.sort_by_key(|block| block.variables[0].0);
+    blocks
+}
+
+// returns the unconstrained variables of the component, if there are any
+fn find_unconstrained_block(
+    factor_graph: &FactorGraph,
+    H: &BlockSparseMatrix,
+    vars: &[NodeIndex<usize>],
+) -> Option<UnconstrainedBlock> {
+    // maps the first row of each variable's range in H to its first row in the component's part of H
+    let mut local_rows = BTreeMap::new();
+    let mut dim = 0;
+    for i in vars {
+        // tied variables share their range with their representative
+        if let FixedType::NonFixed(range) = factor_graph.get_var(*i).get_fixed_type() {
+            if let Entry::Vacant(entry) = local_rows.entry(range.start) {
+                entry.insert(dim);
+                dim += range.len();
+            }
+        }
+    }
+    let mut local_H = DMatrix::zeros(dim, dim);
+    for ((row, col), block) in H.blocks() {
+        if let (Some(local_row), Some(local_col)) = (local_rows.get(row), local_rows.get(col)) {
+            local_H
+                .slice_mut((*local_row, *local_col), block.shape())
+                .copy_from(block);
+        }
+    }
+
+    let eigen = SymmetricEigen::new(local_H);
+    let tolerance = NULL_SPACE_TOLERANCE * eigen.eigenvalues.amax();
+    let null_space: Vec<usize> = (0..dim).filter(|k| eigen.eigenvalues[*k].abs() <= tolerance).collect();
+    if null_space.is_empty() {
+        return None;
+    }
+    let variables = vars
+        .iter()
+        .filter(|i| match factor_graph.get_var(**i).get_fixed_type() {
+            FixedType::NonFixed(range) => {
+                let local_row = local_rows[&range.start];
+                null_space
+                    .iter()
+                    .any(|k| eigen.eigenvectors.slice((local_row, *k), (range.len(), 1)).norm() > 1e-6)
+            }
+            FixedType::Fixed => false,
+        })
+        .map(|i| factor_graph.get_var(*i).get_id())
+        .collect();
+    Some(UnconstrainedBlock {
+        variables,
+        null_space_dimension: null_space.len(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimizer::solver::dense_cholesky::DenseCholeskySolver;
+    use crate::optimizer::solver::sparse_cholesky::SparseCholeskySolver;
+    use crate::optimizer::config::OptimizerConfig;
+    use crate::optimizer::termination::Termination;
+    use crate::optimizer::{optimize_with_config, optimize_with_solver, total_chi2};
+    use crate::parser::g2o::G2oParser;
+    use crate::parser::Parser;
+
+    // an anchored pair of poses, a pair of poses without an anchor and a landmark without observations
+    const RANK_DEFICIENT_GRAPH: &str = "VERTEX_SE2 0 0 0 0
+FIX 0
+VERTEX_SE2 1 0.9 0.1 0.1
+EDGE_SE2 0 1 1 0 0 1 0 0 1 0 1
+VERTEX_SE2 2 5 5 0
+VERTEX_SE2 3 6.2 4.9 0
+EDGE_SE2 2 3 1 0 0 1 0 0 1 0 1
+VERTEX_XY 4 3 3";
+
+    fn get_rank_deficient_graph() -> FactorGraph {
+        G2oParser::parse_string_to_model(RANK_DEFICIENT_GRAPH).unwrap().into()
+    }
+
+    #[test]
+    fn test_find_unconstrained_blocks() {
+        let factor_graph = get_rank_deficient_graph();
+        assert_eq!(
+            find_unconstrained_blocks(&factor_graph),
+            vec![
+                UnconstrainedBlock {
+                    variables: vec![VariableId(2), VariableId(3)],
+                    null_space_dimension: 3,
+                },
+                UnconstrainedBlock {
+                    variables: vec![VariableId(4)],
+                    null_space_dimension: 2,
+                },
+            ]
+        );
+        let anchored = G2oParser::parse_file("data_files/optimizer_tests/full2d_0.g2o").unwrap();
+        assert!(find_unconstrained_blocks(&anchored).is_empty());
+    }
+
+    #[test]
+    fn test_regularized_optimization() {
+        let factor_graph = get_rank_deficient_graph();
+        let solver = TikhonovRegularization::new(SparseCholeskySolver, 1e-6);
+        optimize_with_solver(&factor_graph, 5, &solver);
+        assert!(total_chi2(&factor_graph) < 1e-9);
+        assert_eq!(
+            factor_graph.get_var_by_id(VariableId(4)).unwrap().get_content(),
+            vec![3.0, 3.0]
+        );
+
+        let factor_graph = get_rank_deficient_graph();
+        let solver = TikhonovRegularization::new(DenseCholeskySolver, 1e-6);
+        optimize_with_solver(&factor_graph, 5, &solver);
+        assert!(total_chi2(&factor_graph) < 1e-9);
+    }
+
+    #[test]
+    fn test_unregularized_optimization_fails() {
+        let report = optimize_with_config(&get_rank_deficient_graph(), &OptimizerConfig::default());
+        assert_eq!(report.termination, Termination::SolverFailed);
+    }
+}
@@ -0,0 +1,238 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Relinearization thresholds, which skip the linearization of factors whose variables barely moved, e.g. in nearly
+//! converged factor graphs.
+
+#![allow(non_snake_case)]
+
+use crate::factor_graph::factor::FactorId;
+use crate::factor_graph::variable::FixedType;
+use crate::factor_graph::FactorGraph;
+use crate::optimizer::block_sparse::BlockSparseMatrix;
+use crate::optimizer::linear_system::{calculate_error, calculate_jacobian, fix_components, get_dominant_component};
+use nalgebra::{DMatrix, DVector};
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+// the contribution of a factor to H and b at its linearization point
+struct LinearizedFactor {
+    // the ranges in H of the factor's non-fixed variables
+    ranges: Vec<Range<usize>>,
+    // the accumulated corrections of the variables at the linearization point
+    corrections: Vec<DVector<f64>>,
+    // the blocks of H for each pair of variables, row by row
+    H_blocks: Vec<DMatrix<f64>>,
+    b_blocks: Vec<DVector<f64>>,
+}
+
+/// Cache of the linearized factors of previous iterations of
+/// [optimize_with_relinearization](../fn.optimize_with_relinearization.html).
+///
+/// A factor is linearized again unless its variables moved by less than the threshold in each dimension of the
+/// tangent space since its last linearization. Otherwise, its blocks of H are reused and its entries of b are
+/// corrected to first order by the movement of its variables.
+///
+/// The movement is tracked by accumulating the corrections of the optimization, so the cache is only valid for the
+/// factor graph it was used with, as long as its estimates and factors are only changed by the optimization. If the
+/// ranges of the variables in H change, e.g. because variables were frozen, the cache is cleared.
+pub struct RelinearizationCache {
+    threshold: f64,
+    corrections: DVector<f64>,
+    factors: BTreeMap<FactorId, LinearizedFactor>,
+    linearized_count: usize,
+    reused_count: usize,
+}
+
+impl RelinearizationCache {
+    /// Returns an empty cache with the given threshold, where a threshold of zero linearizes all factors in each
+    /// iteration.
+    pub fn new(threshold: f64) -> Self {
+        RelinearizationCache {
+            threshold,
+            corrections: DVector::zeros(0),
+            factors: BTreeMap::new(),
+            linearized_count: 0,
+            reused_count: 0,
+        }
+    }
+
+    /// Returns how often a factor was linearized.
+    pub fn linearized_count(&self) -> usize {
+        self.linearized_count
+    }
+
+    /// Returns how often a factor's previous linearization was reused.
+    pub fn reused_count(&self) -> usize {
+        self.reused_count
+    }
+
+    // returns H and b, linearizing the factors whose variables moved by at least the threshold
+    pub(crate) fn calculate_H_b(&mut self, factor_graph: &FactorGraph) -> (BlockSparseMatrix, DVector<f64>) {
+        let dim = factor_graph.matrix_dim;
+        if self.corrections.len() != dim
+            || self
+                .factors
+                .values()
+                .flat_map(|f| &f.ranges)
+                .any(|range| range.end > dim)
+        {
+            self.corrections = DVector::zeros(dim);
+            self.factors.clear();
+        }
+        self.factors.retain(|id, _| factor_graph.factor_id_map.contains_key(id));
+
+        let mut H = BlockSparseMatrix::new(dim);
+        let mut b = DVector::zeros(dim);
+        for id in factor_graph.factor_id_map.keys() {
+            let ranges = get_ranges(factor_graph, *id);
+            let is_valid = match self.factors.get(id) {
+                Some(factor) => factor.ranges == ranges && self.get_max_movement(factor) < self.threshold,
+                None => false,
+            };
+            if is_valid {
+                self.reused_count += 1;
+            } else {
+                let factor = self.linearize(factor_graph, *id, ranges);
+                self.factors.insert(*id, factor);
+                self.linearized_count += 1;
+            }
+            let factor = &self.factors[id];
+            let movements: Vec<DVector<f64>> = self.get_movements(factor);
+            let var_count = factor.ranges.len();
+            for (i, range_i) in factor.ranges.iter().enumerate() {
+                let mut b_block = factor.b_blocks[i].clone();
+                for (j, range_j) in factor.ranges.iter().enumerate() {
+                    let H_block = &factor.H_blocks[i * var_count + j];
+                    H.add_block(range_i.clone(), range_j.clone(), H_block);
+                    b_block += H_block * &movements[j];
+                }
+                let mut b_rows = b.rows_mut(range_i.start, range_i.len());
+                b_rows += b_block;
+            }
+        }
+        fix_components(factor_graph, &mut H, &mut b);
+        (H, b)
+    }
+
+    // accumulates the corrections which were applied to the variables
+    pub(crate) fn add_corrections(&mut self, corrections: &[f64]) {
+        self.corrections += DVector::from_column_slice(corrections);
+    }
+
+    fn get_movements(&self, factor: &LinearizedFactor) -> Vec<DVector<f64>> {
+        factor
+            .ranges
+            .iter()
+            .zip(&factor.corrections)
+            .map(|(range, correction)| self.corrections.rows(range.start, range.len()) - correction)
+            .collect()
+    }
+
+    fn get_max_movement(&self, factor: &LinearizedFactor) -> f64 {
+        self.get_movements(factor)
+            .iter()
+            .map(|movement| movement.amax())
+            .fold(0.0, f64::max)
+    }
+
+    fn linearize(&self, factor_graph: &FactorGraph, id: FactorId, ranges: Vec<Range<usize>>) -> LinearizedFactor {
+        let factor = get_dominant_component(factor_graph, factor_graph.get_factor(id).unwrap());
+        let jacobian = calculate_jacobian(factor_graph, id).unwrap();
+        let error = calculate_error(factor_graph, id).unwrap();
+        let weighted_jacobian = &factor.information_matrix.content * &jacobian;
+
+        // the columns of the non-fixed variables in the Jacobian
+        let mut column = 0;
+        let mut columns = vec![];
+        for i in factor_graph.get_factor_var_indices(id).unwrap() {
+            let var = factor_graph.get_var(i);
+            let tangent_dim = var.get_parameterization().tangent_dim();
+            if var.get_fixed_type() != &FixedType::Fixed {
+                columns.push(column..column + tangent_dim);
+            }
+            column += tangent_dim;
+        }
+        let mut H_blocks = vec![];
+        let mut b_blocks = vec![];
+        for columns_i in &columns {
+            let jacobian_i = jacobian.columns(columns_i.start, columns_i.len());
+            for columns_j in &columns {
+                H_blocks.push(jacobian_i.transpose() * weighted_jacobian.columns(columns_j.start, columns_j.len()));
+            }
+            b_blocks.push(weighted_jacobian.columns(columns_i.start, columns_i.len()).transpose() * &error);
+        }
+        LinearizedFactor {
+            corrections: ranges
+                .iter()
+                .map(|range| self.corrections.rows(range.start, range.len()).into_owned())
+                .collect(),
+            ranges,
+            H_blocks,
+            b_blocks,
+        }
+    }
+}
+
+// returns the ranges in H of the factor's non-fixed variables
+fn get_ranges(factor_graph: &FactorGraph, id: FactorId) -> Vec<Range<usize>> {
+    factor_graph
+        .get_factor_var_indices(id)
+        .unwrap()
+        .iter()
+        .filter_map(|i| match factor_graph.get_var(*i).get_fixed_type() {
+            FixedType::NonFixed(range) => Some(range.clone()),
+            FixedType::Fixed => None,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimizer::linear_system::calculate_H_b;
+    use crate::optimizer::{optimize, optimize_with_relinearization, total_chi2};
+    use crate::parser::g2o::G2oParser;
+    use crate::parser::Parser;
+    use approx::assert_relative_eq;
+
+    const FILE_NAME: &str = "data_files/optimizer_tests/full2d_0.g2o";
+
+    #[test]
+    fn test_linearization_matches_linear_system() {
+        let factor_graph = G2oParser::parse_file(FILE_NAME).unwrap();
+        let (expected_H, expected_b) = calculate_H_b(&factor_graph);
+        let (H, b) = RelinearizationCache::new(0.0).calculate_H_b(&factor_graph);
+        let expected_H = expected_H.to_dense();
+        assert!((H.to_dense() - &expected_H).norm() < 1e-9 * expected_H.norm());
+        assert!((b - &expected_b).norm() < 1e-9 * expected_b.norm());
+    }
+
+    #[test]
+    fn test_threshold() {
+        let expected = G2oParser::parse_file(FILE_NAME).unwrap();
+        optimize(&expected, 10);
+
+        let factor_graph = G2oParser::parse_file(FILE_NAME).unwrap();
+        let mut cache = RelinearizationCache::new(0.0);
+        optimize_with_relinearization(&factor_graph, 10, &mut cache);
+        assert_eq!(cache.reused_count(), 0);
+        assert_eq!(cache.linearized_count(), 10 * factor_graph.factor_id_map.len());
+        assert_relative_eq!(total_chi2(&factor_graph), total_chi2(&expected), max_relative = 1e-9);
+
+        let factor_graph = G2oParser::parse_file(FILE_NAME).unwrap();
+        let mut cache = RelinearizationCache::new(1e-3);
+        optimize_with_relinearization(&factor_graph, 10, &mut cache);
+        assert!(cache.reused_count() > cache.linearized_count());
+        assert_relative_eq!(total_chi2(&factor_graph), total_chi2(&expected), max_relative = 1e-6);
+    }
+}
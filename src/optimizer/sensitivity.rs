@@ -0,0 +1,345 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Sensitivity of the optimized estimates to the anchoring of the factor graph.
+//!
+//! The absolute position of a factor graph is only determined by its anchors, i.e. its fixed variables and its
+//! Position2D and Position3D factors. Slightly perturbing one anchor at a time and optimizing again shows how
+//! strongly the final vehicle pose and the extent of the map depend on each of them.
+
+use crate::factor_graph::factor::{FactorId, FactorType};
+use crate::factor_graph::variable::{FixedType, Variable, VariableId};
+use crate::factor_graph::FactorGraph;
+use crate::optimizer::config::OptimizerConfig;
+use crate::optimizer::optimize_with_config;
+use nalgebra::{Quaternion, Rotation2, UnitQuaternion, Vector3};
+
+/// An anchor of the factor graph which is perturbed by the analysis.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Anchor {
+    /// A fixed vehicle or landmark variable.
+    FixedVariable(VariableId),
+    /// A Position2D or Position3D factor.
+    Prior(FactorId),
+}
+
+/// The changes of the optimized estimates induced by perturbing one anchor.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AnchorSensitivity {
+    /// The perturbed anchor.
+    pub anchor: Anchor,
+    /// The distance between the final vehicle position with and without the perturbation.
+    pub final_position_change: f64,
+    /// The angle between the final vehicle rotation with and without the perturbation, in radians.
+    pub final_rotation_change: f64,
+    /// The perturbed minus the unperturbed map extent.
+    pub map_extent_change: f64,
+}
+
+/// Result of [analyze_anchor_sensitivity](fn.analyze_anchor_sensitivity.html).
+#[derive(Debug, Clone, PartialEq)]
+pub struct SensitivityReport {
+    /// The perturbation applied to each anchor.
+    pub perturbation: f64,
+    /// The vehicle variable with the highest ID, whose pose is the final pose, if there is any vehicle variable.
+    pub final_pose: Option<VariableId>,
+    /// The diagonal of the axis-aligned bounding box of all vehicle and landmark positions at the optimized
+    /// estimates.
+    pub map_extent: f64,
+    /// The sensitivities in the order of the anchors' variable IDs and factor IDs, respectively, fixed variables
+    /// first.
+    pub anchors: Vec<AnchorSensitivity>,
+}
+
+/// Optimizes the factor graph until convergence and reports how much the final vehicle pose and the map extent
+/// change if a single anchor is perturbed and the factor graph is optimized again.
+///
+/// Each anchor is translated by the given perturbation along every axis and, unless it is a landmark, rotated by
+/// the perturbation in radians around its z axis. Afterwards, the anchor and the optimized estimates are restored,
+/// so the factor graph is left in the same state as after only optimizing it once.
+///
+/// Returns an error if the perturbation is not positive.
+pub fn analyze_anchor_sensitivity(graph: &mut FactorGraph, perturbation: f64) -> Result<SensitivityReport, String> {
+    if perturbation.is_nan() || perturbation <= 0.0 {
+        return Err(format!("The perturbation must be positive, but is {}", perturbation));
+    }
+    optimize_until_convergence(graph);
+    let estimates = get_estimates(graph);
+    let final_pose = graph
+        .node_indices
+        .iter()
+        .map(|i| graph.get_var(*i))
+        .filter(|var| matches!(var, Variable::Vehicle2D(_) | Variable::Vehicle3D(_) | Variable::VehicleSim3(_)))
+        .map(|var| var.get_id())
+        .max();
+    let map_extent = get_map_extent(graph);
+
+    let mut anchors: Vec<Anchor> = graph
+        .custom_to_csr_id_map
+        .iter()
+        .filter(|(_, i)| {
+            let var = graph.get_var(**i);
+            var.get_fixed_type() == &FixedType::Fixed
+                && !matches!(
+                    var,
+                    Variable::Switch(_)
+                        | Variable::Velocity3D(_)
+                        | Variable::ImuBias(_)
+                        | Variable::Plane3D(_)
+                        | Variable::LineLandmark2D(_)
+                        | Variable::OdometryCalibration2D(_)
+                        | Variable::Extrinsic2D(_)
+                        | Variable::Extrinsic3D(_)
+                        | Variable::UserDefined(_)
+                )
+        })
+        .map(|(id, _)| Anchor::FixedVariable(*id))
+        .collect();
+    anchors.extend(
+        graph
+            .factor_id_map
+            .keys()
+            .filter(|id| {
+                let factor_type = &graph.get_factor(**id).unwrap().factor_type;
+                matches!(factor_type, FactorType::Position2D | FactorType::Position3D)
+            })
+            .map(|id| Anchor::Prior(*id)),
+    );
+
+    let mut sensitivities = vec![];
+    for anchor in anchors {
+        match anchor {
+            Anchor::FixedVariable(id) => {
+                let var = graph.get_var_by_id(id).unwrap();
+                var.set_content(perturb(var, &var.get_content(), perturbation));
+                optimize_until_convergence(graph);
+            }
+            Anchor::Prior(id) => {
+                let factor = graph.materialize_factor(graph.get_factor(id).unwrap()).into_owned();
+                let perturbed = perturb(
+                    graph.get_var(graph.factor_id_map[&id].0),
+                    &factor.constraint,
+                    perturbation,
+                );
+                graph.set_constraint(id, perturbed)?;
+                optimize_until_convergence(graph);
+                graph.set_constraint(id, factor.constraint)?;
+            }
+        }
+        let (final_position_change, final_rotation_change) = match final_pose {
+            Some(final_pose) => {
+                let index = graph.custom_to_csr_id_map[&final_pose];
+                get_pose_change(graph.get_var(index), &estimates[index.index()])
+            }
+            None => (0.0, 0.0),
+        };
+        sensitivities.push(AnchorSensitivity {
+            anchor,
+            final_position_change,
+            final_rotation_change,
+            map_extent_change: get_map_extent(graph) - map_extent,
+        });
+        set_estimates(graph, &estimates);
+    }
+    Ok(SensitivityReport {
+        perturbation,
+        final_pose,
+        map_extent,
+        anchors: sensitivities,
+    })
+}
+
+fn get_estimates(graph: &FactorGraph) -> Vec<Vec<f64>> {
+    graph
+        .node_indices
+        .iter()
+        .map(|i| graph.get_var(*i).get_content())
+        .collect()
+}
+
+fn set_estimates(graph: &FactorGraph, estimates: &[Vec<f64>]) {
+    graph
+        .node_indices
+        .iter()
+        .zip(estimates)
+        .for_each(|(i, content)| graph.get_var(*i).set_content(content.clone()));
+}
+
+// returns the pose or position of the variable's type, translated along every axis and rotated around the z axis
+fn perturb(var: &Variable, content: &[f64], perturbation: f64) -> Vec<f64> {
+    let mut perturbed = content.to_vec();
+    match var {
+        Variable::Vehicle2D(_) | Variable::Anchor2D(_) => {
+            perturbed[..3].iter_mut().for_each(|v| *v += perturbation);
+        }
+        Variable::Vehicle3D(_) | Variable::VehicleSim3(_) | Variable::Anchor3D(_) => {
+            perturbed[..3].iter_mut().for_each(|v| *v += perturbation);
+            let rotation = get_rotation_3d(content) * UnitQuaternion::from_euler_angles(0.0, 0.0, perturbation);
+            perturbed[3..7].copy_from_slice(rotation.coords.data.as_slice());
+        }
+        Variable::Landmark2D(_) | Variable::Landmark3D(_) => {
+            perturbed.iter_mut().for_each(|v| *v += perturbation);
+        }
+        Variable::Switch(_)
+        | Variable::Velocity3D(_)
+        | Variable::ImuBias(_)
+        | Variable::Plane3D(_)
+        | Variable::LineLandmark2D(_)
+        | Variable::OdometryCalibration2D(_)
+        | Variable::Extrinsic2D(_)
+        | Variable::Extrinsic3D(_)
+        | Variable::UserDefined(_) => {
+            unreachable!("Only pose and position variables are anchors")
+        }
+    }
+    perturbed
+}
+
+// returns the distance between the positions and the angle between the rotations of the vehicle's current pose
+// and the given one
+fn get_pose_change(var: &Variable, previous: &[f64]) -> (f64, f64) {
+    let current = var.get_content();
+    match var {
+        Variable::Vehicle2D(_) => (
+            (current[0] - previous[0]).hypot(current[1] - previous[1]),
+            Rotation2::new(previous[2]).angle_to(&Rotation2::new(current[2])).abs(),
+        ),
+        Variable::Vehicle3D(_) | Variable::VehicleSim3(_) => (
+            (Vector3::from_column_slice(&current[..3]) - Vector3::from_column_slice(&previous[..3])).norm(),
+            get_rotation_3d(previous).angle_to(&get_rotation_3d(&current)),
+        ),
+        _ => unreachable!("The final pose belongs to a vehicle variable"),
+    }
+}
+
+fn get_rotation_3d(pose: &[f64]) -> UnitQuaternion<f64> {
+    UnitQuaternion::from_quaternion(Quaternion::new(pose[6], pose[3], pose[4], pose[5]))
+}
+
+// returns the diagonal of the axis-aligned bounding box of all positions, or 0 if there are none
+fn get_map_extent(graph: &FactorGraph) -> f64 {
+    let positions: Vec<[f64; 3]> = graph
+        .node_indices
+        .iter()
+        .map(|i| graph.get_var(*i))
+        .filter_map(|var| {
+            let content = var.get_content();
+            match var {
+                Variable::Vehicle2D(_) | Variable::Landmark2D(_) => Some([content[0], content[1], 0.0]),
+                Variable::Vehicle3D(_) | Variable::Landmark3D(_) | Variable::VehicleSim3(_) => {
+                    Some([content[0], content[1], content[2]])
+                }
+                Variable::Switch(_)
+                | Variable::Velocity3D(_)
+                | Variable::ImuBias(_)
+                | Variable::Plane3D(_)
+                | Variable::LineLandmark2D(_)
+                | Variable::OdometryCalibration2D(_)
+                | Variable::Extrinsic2D(_)
+                | Variable::Extrinsic3D(_)
+                | Variable::Anchor2D(_)
+                | Variable::Anchor3D(_)
+                | Variable::UserDefined(_) => None,
+            }
+        })
+        .collect();
+    if positions.is_empty() {
+        return 0.0;
+    }
+    (0..3)
+        .map(|axis| {
+            let values = positions.iter().map(|p| p[axis]);
+            let min = values.clone().fold(f64::INFINITY, f64::min);
+            let max = values.fold(f64::NEG_INFINITY, f64::max);
+            (max - min).powi(2)
+        })
+        .sum::<f64>()
+        .sqrt()
+}
+
+fn optimize_until_convergence(graph: &FactorGraph) {
+    optimize_with_config(
+        graph,
+        &OptimizerConfig {
+            max_iterations: usize::MAX,
+            ..Default::default()
+        },
+    );
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::g2o::G2oParser;
+    use crate::parser::Parser;
+    use approx::assert_relative_eq;
+
+    const PERTURBATION: f64 = 0.01;
+
+    fn get_chain(anchor: &str) -> FactorGraph {
+        G2oParser::parse_string_to_model(
+            &[
+                "VERTEX_SE2 0 0 0 0",
+                "VERTEX_SE2 1 1 0 0",
+                "VERTEX_SE2 2 2 0 0",
+                anchor,
+                "EDGE_SE2 0 1 1 0 0 1 0 0 1 0 1",
+                "EDGE_SE2 1 2 1 0 0 1 0 0 1 0 1",
+            ]
+            .join("\n"),
+        )
+        .unwrap()
+        .into()
+    }
+
+    // the chain moves rigidly with its anchor at the origin, whose rotation keeps the map extent
+    fn assert_rigid_motion(sensitivity: &AnchorSensitivity) {
+        let (sin, cos) = PERTURBATION.sin_cos();
+        let expected_position_change = (PERTURBATION + 2.0 * cos - 2.0).hypot(PERTURBATION + 2.0 * sin);
+        assert_relative_eq!(
+            sensitivity.final_position_change,
+            expected_position_change,
+            epsilon = 1e-6
+        );
+        assert_relative_eq!(sensitivity.final_rotation_change, PERTURBATION, epsilon = 1e-6);
+        assert_relative_eq!(sensitivity.map_extent_change, 0.0, epsilon = 1e-6);
+    }
+
+    #[test]
+    fn test_fixed_variable_sensitivity() {
+        let mut graph = get_chain("FIX 0");
+        let report = analyze_anchor_sensitivity(&mut graph, PERTURBATION).unwrap();
+        assert_eq!(report.final_pose, Some(VariableId(2)));
+        assert_relative_eq!(report.map_extent, 2.0, epsilon = 1e-9);
+        assert_eq!(report.anchors.len(), 1);
+        assert_eq!(report.anchors[0].anchor, Anchor::FixedVariable(VariableId(0)));
+        assert_rigid_motion(&report.anchors[0]);
+        assert_eq!(
+            graph.get_var_by_id(VariableId(0)).unwrap().get_content(),
+            vec![0.0, 0.0, 0.0]
+        );
+        assert!(analyze_anchor_sensitivity(&mut graph, 0.0).is_err());
+    }
+
+    #[test]
+    fn test_prior_sensitivity() {
+        let mut graph = get_chain("EDGE_PRIOR_SE2 0 0 0 0 1 0 0 1 0 1");
+        let report = analyze_anchor_sensitivity(&mut graph, PERTURBATION).unwrap();
+        assert_eq!(report.anchors.len(), 1);
+        assert_eq!(report.anchors[0].anchor, Anchor::Prior(FactorId(0)));
+        assert_rigid_motion(&report.anchors[0]);
+        assert_eq!(graph.get_factor(FactorId(0)).unwrap().constraint, vec![0.0, 0.0, 0.0]);
+        let final_pose = graph.get_var_by_id(VariableId(2)).unwrap().get_content();
+        assert_relative_eq!(final_pose[0], 2.0, epsilon = 1e-9);
+        assert_relative_eq!(final_pose[2], 0.0, epsilon = 1e-9);
+    }
+}
@@ -0,0 +1,143 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Fast path for small factor graphs, e.g. of calibration problems or of refinements in each frame, whose linear
+//! systems are assembled in fixed-size matrices on the stack and solved densely, skipping the block-sparse matrices
+//! and the sparse solvers.
+//!
+//! ```
+//! use gs_rs::examples_gen::triangle_with_loop_closure;
+//! use gs_rs::factor_graph::FactorGraph;
+//! use gs_rs::optimizer::small::optimize_small;
+//! use gs_rs::optimizer::total_chi2;
+//!
+//! // the two non-fixed 2D poses have 6 dimensions
+//! let factor_graph: FactorGraph = triangle_with_loop_closure().into();
+//! optimize_small::<6>(&factor_graph, 10).unwrap();
+//! assert!(total_chi2(&factor_graph) < 1e-12);
+//! assert!(optimize_small::<5>(&factor_graph, 10).is_err());
+//! ```
+
+#![allow(non_snake_case)]
+
+use crate::factor_graph::variable::FixedType;
+use crate::factor_graph::FactorGraph;
+use crate::optimizer::linear_system::{calculate_error, calculate_jacobian, get_dominant_component};
+use crate::optimizer::{total_chi2, update_vars_with_line_search};
+use nalgebra::{SMatrix, SVector};
+
+/// Optimizes a factor graph like [optimize](../fn.optimize.html), but with linear systems of the fixed dimension N,
+/// which has to be at least the dimension of the state. Unused dimensions are padded with the identity.
+///
+/// Since the matrices are allocated on the stack, N should only be large enough for the factor graph, e.g. up to a
+/// few hundred.
+///
+/// Returns an error if the dimension of the state exceeds N or if H is not positive-definite.
+pub fn optimize_small<const N: usize>(graph: &FactorGraph, iterations: usize) -> Result<(), String> {
+    let dim = graph.matrix_dim;
+    if dim > N {
+        return Err(format!(
+            "The state dimension {} exceeds the dimension {} of the small linear system",
+            dim, N
+        ));
+    }
+    let mut chi2 = total_chi2(graph);
+    for _i in 0..iterations {
+        let (H, b) = calculate_small_H_b::<N>(graph);
+        let step = H.cholesky().ok_or("H is not positive-definite")?.solve(&-b);
+        chi2 = update_vars_with_line_search(graph, &step.as_slice()[..dim], chi2);
+    }
+    Ok(())
+}
+
+// returns H and b like calculate_H_b, but assembled densely from each factor's Jacobian and error
+fn calculate_small_H_b<const N: usize>(graph: &FactorGraph) -> (SMatrix<f64, N, N>, SVector<f64, N>) {
+    let mut H = SMatrix::<f64, N, N>::zeros();
+    let mut b = SVector::<f64, N>::zeros();
+    for i in graph.matrix_dim..N {
+        H[(i, i)] = 1.0;
+    }
+    for id in graph.factor_id_map.keys() {
+        let factor = get_dominant_component(graph, graph.get_factor(*id).unwrap());
+        let jacobian = calculate_jacobian(graph, *id).unwrap();
+        let error = calculate_error(graph, *id).unwrap();
+        let weighted_jacobian = &factor.information_matrix.content * &jacobian;
+
+        // the columns of the non-fixed variables in the Jacobian and their ranges in H
+        let mut column = 0;
+        let mut blocks = vec![];
+        for i in graph.get_factor_var_indices(*id).unwrap() {
+            let var = graph.get_var(i);
+            let tangent_dim = var.get_parameterization().tangent_dim();
+            if let FixedType::NonFixed(range) = var.get_fixed_type() {
+                blocks.push((column, range.start, tangent_dim));
+            }
+            column += tangent_dim;
+        }
+        for (column_i, start_i, dim_i) in &blocks {
+            let jacobian_i = jacobian.columns(*column_i, *dim_i);
+            for (column_j, start_j, dim_j) in &blocks {
+                let mut H_block = H.slice_mut((*start_i, *start_j), (*dim_i, *dim_j));
+                H_block += jacobian_i.transpose() * weighted_jacobian.columns(*column_j, *dim_j);
+            }
+            let mut b_block = b.rows_mut(*start_i, *dim_i);
+            b_block += weighted_jacobian.columns(*column_i, *dim_i).transpose() * &error;
+        }
+    }
+    for row in graph.get_fixed_rows() {
+        H.row_mut(row).fill(0.0);
+        H.column_mut(row).fill(0.0);
+        H[(row, row)] = 1.0;
+        b[row] = 0.0;
+    }
+    (H, b)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimizer::linear_system::calculate_H_b;
+    use crate::optimizer::optimize;
+    use crate::parser::g2o::G2oParser;
+    use crate::parser::Parser;
+
+    #[test]
+    fn test_same_system_as_general_path() {
+        let factor_graph = G2oParser::parse_file("data_files/full_demos/all_2d_types.g2o").unwrap();
+        let dim = factor_graph.matrix_dim;
+        let (H, b) = calculate_H_b(&factor_graph);
+        let (small_H, small_b) = calculate_small_H_b::<16>(&factor_graph);
+        let H = H.to_dense();
+        assert!((small_H.slice((0, 0), (dim, dim)) - &H).norm() < 1e-9 * H.norm());
+        assert!((small_b.rows(0, dim) - &b).norm() < 1e-9 * b.norm());
+        assert_eq!(
+            small_H.slice((dim, dim), (16 - dim, 16 - dim)).trace(),
+            (16 - dim) as f64
+        );
+    }
+
+    #[test]
+    fn test_same_estimates_as_general_path() {
+        let file_name = "data_files/full_demos/all_3d_types.g2o";
+        let expected = G2oParser::parse_file(file_name).unwrap();
+        optimize(&expected, 5);
+        let factor_graph = G2oParser::parse_file(file_name).unwrap();
+        optimize_small::<16>(&factor_graph, 5).unwrap();
+        for i in &factor_graph.node_indices {
+            let (content, expected_content) = (
+                factor_graph.get_var(*i).get_content(),
+                expected.get_var(*i).get_content(),
+            );
+            assert!(content.iter().zip(&expected_content).all(|(a, b)| (a - b).abs() < 1e-9));
+        }
+    }
+}
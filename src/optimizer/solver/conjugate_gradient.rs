@@ -0,0 +1,166 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Iterative solver for linear systems using the Jacobi-preconditioned conjugate gradient method.
+
+#![allow(non_snake_case)]
+
+use crate::optimizer::block_sparse::BlockSparseMatrix;
+use crate::optimizer::solver::LinearSolver;
+use nalgebra::{DMatrix, DVector};
+
+/// Implements the solver using the conjugate gradient method with a Jacobi (diagonal) preconditioner.
+#[derive(Debug, Clone)]
+pub struct ConjugateGradientSolver {
+    /// The maximum number of conjugate gradient iterations.
+    pub max_iterations: usize,
+    /// The relative residual norm |H*x - b| / |b| below which the solution is accepted.
+    pub tolerance: f64,
+}
+
+impl Default for ConjugateGradientSolver {
+    fn default() -> Self {
+        ConjugateGradientSolver {
+            max_iterations: 1000,
+            tolerance: 1e-12,
+        }
+    }
+}
+
+impl LinearSolver for ConjugateGradientSolver {
+    fn name(&self) -> String {
+        String::from("ConjugateGradient")
+    }
+
+    /// Assumes that H is symmetric and positive-definite. Might return wrong result if this is not the case.
+    fn solve(&self, H: DMatrix<f64>, b: &DVector<f64>) -> Result<Vec<f64>, String> {
+        if !H.is_square() || H.nrows() != b.len() {
+            return Err(format!(
+                "Incompatible dimensions: H is {}x{}, b has length {}",
+                H.nrows(),
+                H.ncols(),
+                b.len()
+            ));
+        }
+        self.solve_with(|p| &H * p, H.diagonal(), b)
+    }
+
+    /// Assumes that H is symmetric and positive-definite. Might return wrong result if this is not the case.
+    ///
+    /// H is only multiplied block by block and never converted to a dense matrix.
+    fn solve_block_sparse(&self, H: &BlockSparseMatrix, b: &DVector<f64>) -> Result<Vec<f64>, String> {
+        if H.dim() != b.len() {
+            return Err(format!(
+                "Incompatible dimensions: H is {}x{}, b has length {}",
+                H.dim(),
+                H.dim(),
+                b.len()
+            ));
+        }
+        self.solve_with(|p| H.mul_vector(p), H.diagonal(), b)
+    }
+}
+
+impl ConjugateGradientSolver {
+    // runs the iterations, only accessing H through its product with a vector and its diagonal
+    fn solve_with(
+        &self,
+        H_mul: impl Fn(&DVector<f64>) -> DVector<f64>,
+        H_diagonal: DVector<f64>,
+        b: &DVector<f64>,
+    ) -> Result<Vec<f64>, String> {
+        if H_diagonal.iter().any(|d| *d <= 0.0) {
+            return Err(String::from("H is not positive-definite"));
+        }
+        let inv_diag = H_diagonal.map(|d| 1.0 / d);
+        let b_norm = b.norm();
+        let mut x = DVector::zeros(b.len());
+        if b_norm == 0.0 {
+            return Ok(x.data.into());
+        }
+
+        let mut r = b.clone();
+        let mut z = r.component_mul(&inv_diag);
+        let mut p = z.clone();
+        let mut rz = r.dot(&z);
+        for _i in 0..self.max_iterations {
+            let Hp = H_mul(&p);
+            let pHp = p.dot(&Hp);
+            if pHp <= 0.0 {
+                return Err(String::from("H is not positive-definite"));
+            }
+            let alpha = rz / pHp;
+            x += alpha * &p;
+            r -= alpha * &Hp;
+            if r.norm() / b_norm < self.tolerance {
+                return Ok(x.data.into());
+            }
+            z = r.component_mul(&inv_diag);
+            let rz_new = r.dot(&z);
+            p = &z + (rz_new / rz) * &p;
+            rz = rz_new;
+        }
+        Err(format!(
+            "Conjugate gradient did not converge within {} iterations",
+            self.max_iterations
+        ))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use approx::relative_eq;
+    use log::LevelFilter;
+    use nalgebra::{DMatrix, DVector};
+
+    use crate::optimizer::solver::conjugate_gradient::ConjugateGradientSolver;
+    use crate::optimizer::solver::LinearSolver;
+
+    fn init() {
+        let _ = env_logger::builder()
+            .is_test(true)
+            .filter_level(LevelFilter::Debug)
+            .try_init();
+    }
+
+    #[test]
+    fn solver_positive_definite_test() {
+        init();
+        #[allow(non_snake_case)]
+        #[rustfmt::skip]
+        let positive_definite_H = vec![
+            2.0, -1.0, 0.0, // transposed H is displayed
+            -1.0, 2.0, -1.0,
+            0.0, -1.0, 2.0,
+        ];
+        let b = vec![6.0, 6.0, 6.0];
+        let x = ConjugateGradientSolver::default()
+            .solve(
+                DMatrix::<f64>::from_vec(3, 3, positive_definite_H),
+                &DVector::from_vec(b),
+            )
+            .unwrap();
+        assert!(relative_eq!(x[0], 9.0, epsilon = 1e-10));
+        assert!(relative_eq!(x[1], 12.0, epsilon = 1e-10));
+        assert!(relative_eq!(x[2], 9.0, epsilon = 1e-10));
+    }
+
+    #[test]
+    fn solver_incompatible_dimension_test() {
+        init();
+        let solve_output = ConjugateGradientSolver::default().solve(
+            DMatrix::<f64>::identity(3, 3),
+            &DVector::from_vec(vec![6.0, 6.0, 6.0, 6.0]),
+        );
+        assert!(solve_output.is_err());
+    }
+}
@@ -0,0 +1,92 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Solver for linear systems using a Cholesky decomposition on a dense matrix.
+
+#![allow(non_snake_case)]
+
+use crate::optimizer::solver::LinearSolver;
+use nalgebra::{DMatrix, DVector, RealField};
+
+/// Implements the solver using the Cholesky decomposition on a dense matrix.
+pub struct DenseCholeskySolver;
+
+impl<T: RealField + Copy> LinearSolver<T> for DenseCholeskySolver {
+    fn name(&self) -> String {
+        String::from("DenseCholesky")
+    }
+
+    /// Assumes that H is symmetric. Might return wrong result if this is not the case.
+    fn solve(&self, H: DMatrix<T>, b: &DVector<T>) -> Result<Vec<T>, String> {
+        match H.cholesky() {
+            None => Err(String::from("H is not positive-definite")),
+            Some(cholesky) => Ok(cholesky.solve(b).data.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use approx::relative_eq;
+    use log::LevelFilter;
+    use nalgebra::{DMatrix, DVector};
+
+    use crate::optimizer::solver::dense_cholesky::DenseCholeskySolver;
+    use crate::optimizer::solver::LinearSolver;
+
+    fn init() {
+        let _ = env_logger::builder()
+            .is_test(true)
+            .filter_level(LevelFilter::Debug)
+            .try_init();
+    }
+
+    #[test]
+    fn solver_positive_definite_test() {
+        init();
+        #[allow(non_snake_case)]
+        #[rustfmt::skip]
+        let positive_definite_H = vec![
+            2.0, -1.0, 0.0, // transposed H is displayed
+            -1.0, 2.0, -1.0,
+            0.0, -1.0, 2.0,
+        ];
+        let b = vec![6.0, 6.0, 6.0];
+        let x = DenseCholeskySolver
+            .solve(
+                DMatrix::<f64>::from_vec(3, 3, positive_definite_H),
+                &DVector::from_vec(b),
+            )
+            .unwrap();
+        assert!(relative_eq!(x[0], 9.0, epsilon = 1e-10));
+        assert!(relative_eq!(x[1], 12.0, epsilon = 1e-10));
+        assert!(relative_eq!(x[2], 9.0, epsilon = 1e-10));
+    }
+
+    #[test]
+    fn solver_not_positive_definite_test() {
+        init();
+        #[allow(non_snake_case)]
+        #[rustfmt::skip]
+        let not_positive_definite_H = vec![
+            1.0, 2.0, 4.0, // transposed H is displayed
+            2.0, 3.0, 5.0,
+            4.0, 5.0, 6.0,
+        ];
+        let b = vec![6.0, 6.0, 6.0];
+        let solve_output = DenseCholeskySolver.solve(
+            DMatrix::<f64>::from_vec(3, 3, not_positive_definite_H),
+            &DVector::from_vec(b),
+        );
+        assert!(solve_output.is_err());
+    }
+}
@@ -0,0 +1,87 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Solver for linear systems using an LU decomposition with partial pivoting on a dense matrix.
+
+#![allow(non_snake_case)]
+
+use crate::optimizer::solver::LinearSolver;
+use nalgebra::{DMatrix, DVector, RealField};
+
+/// Implements the solver using the LU decomposition on a dense matrix.
+///
+/// Does not require H to be symmetric or positive-definite, only invertible.
+pub struct DenseLuSolver;
+
+impl<T: RealField + Copy> LinearSolver<T> for DenseLuSolver {
+    fn name(&self) -> String {
+        String::from("DenseLu")
+    }
+
+    fn solve(&self, H: DMatrix<T>, b: &DVector<T>) -> Result<Vec<T>, String> {
+        match H.lu().solve(b) {
+            None => Err(String::from("H is not invertible")),
+            Some(x) => Ok(x.data.into()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use approx::relative_eq;
+    use log::LevelFilter;
+    use nalgebra::{DMatrix, DVector};
+
+    use crate::optimizer::solver::dense_lu::DenseLuSolver;
+    use crate::optimizer::solver::LinearSolver;
+
+    fn init() {
+        let _ = env_logger::builder()
+            .is_test(true)
+            .filter_level(LevelFilter::Debug)
+            .try_init();
+    }
+
+    #[test]
+    fn solver_not_symmetric_test() {
+        init();
+        #[allow(non_snake_case)]
+        #[rustfmt::skip]
+        let not_symmetric_H = vec![
+            2.0, -1.0, 2.0, // transposed H is displayed
+            -1.0, 2.0, -1.0,
+            0.0, -1.0, 2.0,
+        ];
+        let b = vec![6.0, 6.0, 6.0];
+        let x = DenseLuSolver
+            .solve(DMatrix::<f64>::from_vec(3, 3, not_symmetric_H), &DVector::from_vec(b))
+            .unwrap();
+        assert!(relative_eq!(x[0], 6.0, epsilon = 1e-10));
+        assert!(relative_eq!(x[1], 6.0, epsilon = 1e-10));
+        assert!(relative_eq!(x[2], 0.0, epsilon = 1e-10));
+    }
+
+    #[test]
+    fn solver_singular_test() {
+        init();
+        #[allow(non_snake_case)]
+        #[rustfmt::skip]
+        let singular_H = vec![
+            1.0, 2.0, 3.0, // transposed H is displayed
+            2.0, 4.0, 6.0,
+            0.0, 1.0, 1.0,
+        ];
+        let b = vec![6.0, 6.0, 6.0];
+        let solve_output = DenseLuSolver.solve(DMatrix::<f64>::from_vec(3, 3, singular_H), &DVector::from_vec(b));
+        assert!(solve_output.is_err());
+    }
+}
@@ -11,16 +11,40 @@
 //
 
 //! Solvers for systems of linear equations (linear systems).
+//!
+//! The optimizer only interacts with a solver through the [LinearSolver](trait.LinearSolver.html) trait,
+//! so any backend implementing it can be passed to
+//! [optimize_with_solver](../fn.optimize_with_solver.html).
+//!
+//! Solvers are generic over the scalar of the linear system. The optimizer assembles it in double precision, but
+//! can solve it in single precision with [SinglePrecision](single_precision/struct.SinglePrecision.html).
 
 #![allow(non_snake_case)]
 
-use nalgebra::{DMatrix, DVector};
+use crate::optimizer::block_sparse::BlockSparseMatrix;
+use nalgebra::{DMatrix, DVector, RealField};
 
+pub mod conjugate_gradient;
+pub mod dense_cholesky;
+pub mod dense_lu;
+pub mod single_precision;
 pub mod sparse_cholesky;
 
-/// Trait which all solvers should implement.
-pub trait Solver {
+/// Trait which all linear solvers should implement.
+pub trait LinearSolver<T: RealField + Copy = f64> {
     /// Solves the linear system defined by H*x = b.
     /// H is expected column-by-column.
-    fn solve(H: DMatrix<f64>, b: &DVector<f64>) -> Result<Vec<f64>, String>;
+    fn solve(&self, H: DMatrix<T>, b: &DVector<T>) -> Result<Vec<T>, String>;
+
+    /// Solves the linear system defined by H*x = b for a block-sparse H.
+    /// Converts H to a dense matrix by default; solvers which can exploit the sparsity should override this.
+    fn solve_block_sparse(&self, H: &BlockSparseMatrix<T>, b: &DVector<T>) -> Result<Vec<T>, String> {
+        self.solve(H.to_dense(), b)
+    }
+
+    /// Returns the name of the solver, e.g. for the summary of a composed file. Wrapping solvers include the name of
+    /// the wrapped solver.
+    fn name(&self) -> String {
+        String::from("Custom")
+    }
 }
@@ -0,0 +1,103 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Solving linear systems of double precision with solvers of single precision.
+
+#![allow(non_snake_case)]
+
+use crate::optimizer::block_sparse::BlockSparseMatrix;
+use crate::optimizer::solver::LinearSolver;
+use nalgebra::{DMatrix, DVector};
+
+/// Adapter which converts H and b to single precision, solves the linear system with the wrapped solver and
+/// converts the solution back to double precision.
+///
+/// H is still assembled in double precision and copied before it is solved, so this does not reduce the peak memory
+/// of an iteration. The solution is only accurate to about 1e-7 relative to the condition number of H, which the line search of
+/// [optimize](../../fn.optimize.html) tolerates, but which limits the final accuracy of the estimates.
+///
+/// ```
+/// use gs_rs::examples_gen::triangle_with_loop_closure;
+/// use gs_rs::factor_graph::FactorGraph;
+/// use gs_rs::optimizer::solver::single_precision::SinglePrecision;
+/// use gs_rs::optimizer::solver::sparse_cholesky::SparseCholeskySolver;
+/// use gs_rs::optimizer::{optimize_with_solver, total_chi2};
+///
+/// let factor_graph: FactorGraph = triangle_with_loop_closure().into();
+/// optimize_with_solver(&factor_graph, 10, &SinglePrecision::new(SparseCholeskySolver));
+/// assert!(total_chi2(&factor_graph) < 1e-6);
+/// ```
+pub struct SinglePrecision<S: LinearSolver<f32>> {
+    solver: S,
+}
+
+impl<S: LinearSolver<f32>> SinglePrecision<S> {
+    /// Returns the adapter of the given single precision solver.
+    pub fn new(solver: S) -> Self {
+        SinglePrecision { solver }
+    }
+}
+
+impl<S: LinearSolver<f32>> LinearSolver for SinglePrecision<S> {
+    fn name(&self) -> String {
+        format!("SinglePrecision({})", self.solver.name())
+    }
+
+    fn solve(&self, H: DMatrix<f64>, b: &DVector<f64>) -> Result<Vec<f64>, String> {
+        let x = self.solver.solve(H.map(|v| v as f32), &b.map(|v| v as f32))?;
+        Ok(x.iter().map(|v| *v as f64).collect())
+    }
+
+    fn solve_block_sparse(&self, H: &BlockSparseMatrix, b: &DVector<f64>) -> Result<Vec<f64>, String> {
+        let x = self.solver.solve_block_sparse(&H.cast(), &b.map(|v| v as f32))?;
+        Ok(x.iter().map(|v| *v as f64).collect())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::optimizer::linear_system::calculate_H_b;
+    use crate::optimizer::solver::dense_cholesky::DenseCholeskySolver;
+    use crate::optimizer::solver::sparse_cholesky::SparseCholeskySolver;
+    use crate::optimizer::{optimize, optimize_with_solver, total_chi2};
+    use crate::parser::g2o::G2oParser;
+    use crate::parser::Parser;
+    use approx::assert_relative_eq;
+
+    const FILE_NAME: &str = "data_files/optimizer_tests/full2d_0.g2o";
+
+    #[test]
+    fn test_single_precision_solution() {
+        let factor_graph = G2oParser::parse_file(FILE_NAME).unwrap();
+        let (H, b) = calculate_H_b(&factor_graph);
+        let expected = DVector::from_vec(SparseCholeskySolver.solve_block_sparse(&H, &b).unwrap());
+        let solver = SinglePrecision::new(SparseCholeskySolver);
+        let sparse = DVector::from_vec(solver.solve_block_sparse(&H, &b).unwrap());
+        let dense = DVector::from_vec(
+            SinglePrecision::new(DenseCholeskySolver)
+                .solve(H.to_dense(), &b)
+                .unwrap(),
+        );
+        assert!((&sparse - &expected).norm() < 1e-4 * expected.norm());
+        assert!((&dense - &expected).norm() < 1e-4 * expected.norm());
+    }
+
+    #[test]
+    fn test_single_precision_optimization() {
+        let expected = G2oParser::parse_file(FILE_NAME).unwrap();
+        optimize(&expected, 5);
+        let factor_graph = G2oParser::parse_file(FILE_NAME).unwrap();
+        optimize_with_solver(&factor_graph, 5, &SinglePrecision::new(SparseCholeskySolver));
+        assert_relative_eq!(total_chi2(&factor_graph), total_chi2(&expected), max_relative = 1e-3);
+    }
+}
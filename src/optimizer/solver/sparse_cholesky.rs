@@ -14,25 +14,97 @@
 
 #![allow(non_snake_case)]
 
-use crate::optimizer::solver::Solver;
-use nalgebra::{CsCholesky, CsMatrix, DMatrix, DVector};
+use crate::optimizer::block_sparse::BlockSparseMatrix;
+use crate::optimizer::solver::LinearSolver;
+use nalgebra::{CsCholesky, CsMatrix, DMatrix, DVector, Dynamic, RealField};
 
 /// Implements the solver using the Cholesky decomposition on a sparse matrix.
 pub struct SparseCholeskySolver;
 
-impl Solver for SparseCholeskySolver {
+impl<T: RealField + Copy> LinearSolver<T> for SparseCholeskySolver {
+    fn name(&self) -> String {
+        String::from("SparseCholesky")
+    }
+
+    /// Assumes that H is symmetric. Might return wrong result if this is not the case.
+    fn solve(&self, H: DMatrix<T>, b: &DVector<T>) -> Result<Vec<T>, String> {
+        solve_sparse(&CsMatrix::from(H), b)
+    }
+
     /// Assumes that H is symmetric. Might return wrong result if this is not the case.
-    fn solve(H: DMatrix<f64>, b: &DVector<f64>) -> Result<Vec<f64>, String> {
-        // TODO @Daniel: pass matrix as sparse already
-        let sparse = CsCholesky::new(&CsMatrix::from(H));
-        match sparse.l() {
-            None => Err(String::from("H is not positive-definite")),
-            Some(l) => Ok(l
-                .tr_solve_lower_triangular(&l.solve_lower_triangular(b).unwrap())
-                .unwrap()
-                .data
-                .into()),
+    fn solve_block_sparse(&self, H: &BlockSparseMatrix<T>, b: &DVector<T>) -> Result<Vec<T>, String> {
+        solve_sparse(&H.to_cs_matrix(), b)
+    }
+}
+
+/// Sparse Cholesky solver which keeps the symbolic analysis of the last matrix, so that following matrices with the
+/// same block structure are only decomposed numerically.
+///
+/// Unlike [SparseCholeskySolver](struct.SparseCholeskySolver.html), it keeps the zero entries within the blocks, so
+/// the non-zero pattern only depends on the block structure and not on the current estimates.
+#[derive(Default)]
+pub struct ReusableSparseCholeskySolver {
+    block_shapes: Vec<((usize, usize), (usize, usize))>,
+    cholesky: Option<CsCholesky<f64, Dynamic>>,
+    symbolic_analysis_count: usize,
+}
+
+impl ReusableSparseCholeskySolver {
+    /// Returns a solver without a symbolic analysis.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns how often the symbolic analysis was performed, i.e. how often the block structure changed.
+    pub fn symbolic_analysis_count(&self) -> usize {
+        self.symbolic_analysis_count
+    }
+
+    /// Solves the linear system defined by H*x = b for a block-sparse H, which is assumed to be symmetric.
+    pub fn solve_block_sparse(&mut self, H: &BlockSparseMatrix, b: &DVector<f64>) -> Result<Vec<f64>, String> {
+        let block_shapes: Vec<((usize, usize), (usize, usize))> =
+            H.blocks().map(|(position, block)| (*position, block.shape())).collect();
+        let mut irows = vec![];
+        let mut icols = vec![];
+        let mut vals = vec![];
+        H.blocks().for_each(|((row, col), block)| {
+            for (j, column) in block.column_iter().enumerate() {
+                for (i, value) in column.iter().enumerate() {
+                    irows.push(row + i);
+                    icols.push(col + j);
+                    vals.push(*value);
+                }
+            }
+        });
+        let mut matrix = CsMatrix::from_triplet(H.dim(), H.dim(), &irows, &icols, &vals);
+        if self.cholesky.is_none() || self.block_shapes != block_shapes {
+            self.cholesky = Some(CsCholesky::new_symbolic(&matrix));
+            self.block_shapes = block_shapes;
+            self.symbolic_analysis_count += 1;
         }
+        // the values in the order of the sorted non-zero pattern
+        let values: Vec<f64> = matrix.values_mut().map(|value| *value).collect();
+        let cholesky = self.cholesky.as_mut().unwrap();
+        cholesky.decompose_left_looking(&values);
+        solve_with_cholesky(cholesky, b)
+    }
+}
+
+fn solve_sparse<T: RealField + Copy>(H: &CsMatrix<T>, b: &DVector<T>) -> Result<Vec<T>, String> {
+    solve_with_cholesky(&CsCholesky::new(H), b)
+}
+
+pub(crate) fn solve_with_cholesky<T: RealField + Copy>(
+    cholesky: &CsCholesky<T, Dynamic>,
+    b: &DVector<T>,
+) -> Result<Vec<T>, String> {
+    match cholesky.l() {
+        None => Err(String::from("H is not positive-definite")),
+        Some(l) => Ok(l
+            .tr_solve_lower_triangular(&l.solve_lower_triangular(b).unwrap())
+            .unwrap()
+            .data
+            .into()),
     }
 }
 
@@ -43,8 +115,9 @@ mod test {
     use log::LevelFilter;
     use nalgebra::{DMatrix, DVector};
 
-    use crate::optimizer::solver::sparse_cholesky::SparseCholeskySolver;
-    use crate::optimizer::solver::Solver;
+    use crate::optimizer::block_sparse::BlockSparseMatrix;
+    use crate::optimizer::solver::sparse_cholesky::{ReusableSparseCholeskySolver, SparseCholeskySolver};
+    use crate::optimizer::solver::LinearSolver;
 
     fn init() {
         let _ = env_logger::builder()
@@ -64,7 +137,7 @@ mod test {
             0.0, -1.0, 2.0,
         ];
         let b = vec![6.0, 6.0, 6.0];
-        let solve_output = SparseCholeskySolver::solve(
+        let solve_output = SparseCholeskySolver.solve(
             DMatrix::<f64>::from_vec(3, 3, positive_definite_H.clone()),
             &DVector::from_vec(b.clone()),
         );
@@ -77,6 +150,52 @@ mod test {
         assert!(relative_eq!(x[2], 9.0, epsilon = 1e-10));
     }
 
+    #[test]
+    fn solver_block_sparse_test() {
+        init();
+        #[allow(non_snake_case)]
+        let mut H = BlockSparseMatrix::new(3);
+        H.add_block(0..1, 0..1, &DMatrix::from_element(1, 1, 2.0));
+        H.add_block(0..1, 1..3, &DMatrix::from_row_slice(1, 2, &[-1.0, 0.0]));
+        H.add_block(1..3, 0..1, &DMatrix::from_column_slice(2, 1, &[-1.0, 0.0]));
+        H.add_block(1..3, 1..3, &DMatrix::from_row_slice(2, 2, &[2.0, -1.0, -1.0, 2.0]));
+        let x = SparseCholeskySolver
+            .solve_block_sparse(&H, &DVector::from_vec(vec![6.0, 6.0, 6.0]))
+            .unwrap();
+        assert!(relative_eq!(x[0], 9.0, epsilon = 1e-10));
+        assert!(relative_eq!(x[1], 12.0, epsilon = 1e-10));
+        assert!(relative_eq!(x[2], 9.0, epsilon = 1e-10));
+    }
+
+    #[test]
+    fn reusable_solver_block_sparse_test() {
+        init();
+        let mut solver = ReusableSparseCholeskySolver::new();
+        for scale in &[1.0, 2.0] {
+            #[allow(non_snake_case)]
+            let mut H = BlockSparseMatrix::new(3);
+            H.add_block(0..1, 0..1, &DMatrix::from_element(1, 1, 2.0 * scale));
+            H.add_block(0..1, 1..3, &DMatrix::from_row_slice(1, 2, &[-1.0 * scale, 0.0]));
+            H.add_block(1..3, 0..1, &DMatrix::from_column_slice(2, 1, &[-1.0 * scale, 0.0]));
+            H.add_block(1..3, 1..3, &(DMatrix::from_row_slice(2, 2, &[2.0, -1.0, -1.0, 2.0]) * *scale));
+            let x = solver
+                .solve_block_sparse(&H, &DVector::from_vec(vec![6.0, 6.0, 6.0]))
+                .unwrap();
+            assert!(relative_eq!(x[0], 9.0 / scale, epsilon = 1e-10));
+            assert!(relative_eq!(x[1], 12.0 / scale, epsilon = 1e-10));
+            assert!(relative_eq!(x[2], 9.0 / scale, epsilon = 1e-10));
+        }
+        assert_eq!(solver.symbolic_analysis_count(), 1);
+
+        #[allow(non_snake_case)]
+        let mut not_positive_definite_H = BlockSparseMatrix::new(2);
+        not_positive_definite_H.add_block(0..2, 0..2, &DMatrix::from_row_slice(2, 2, &[1.0, 2.0, 2.0, 1.0]));
+        assert!(solver
+            .solve_block_sparse(&not_positive_definite_H, &DVector::from_vec(vec![1.0, 1.0]))
+            .is_err());
+        assert_eq!(solver.symbolic_analysis_count(), 2);
+    }
+
     #[test]
     #[should_panic]
     fn solver_not_positive_definite_test() {
@@ -89,7 +208,7 @@ mod test {
             4.0, 5.0, 6.0,
         ];
         let b = vec![6.0, 6.0, 6.0];
-        let solve_output = SparseCholeskySolver::solve(
+        let solve_output = SparseCholeskySolver.solve(
             DMatrix::<f64>::from_vec(3, 3, not_positive_definite_H.clone()),
             &DVector::from_vec(b),
         );
@@ -116,7 +235,7 @@ mod test {
             0.0, -1.0, 2.0,
         ];
         let b = vec![6.0, 6.0, 6.0];
-        let solve_output = SparseCholeskySolver::solve(
+        let solve_output = SparseCholeskySolver.solve(
             DMatrix::<f64>::from_vec(3, 3, not_symmetric_H.clone()),
             &DVector::from_vec(b),
         );
@@ -142,7 +261,7 @@ mod test {
             0.0, -1.0, 2.0,
         ];
         let b = vec![6.0, 6.0, 6.0, 6.0];
-        let solve_output = SparseCholeskySolver::solve(
+        let solve_output = SparseCholeskySolver.solve(
             DMatrix::<f64>::from_vec(3, 3, positive_definite_H.clone()),
             &DVector::from_vec(b.clone()),
         );
@@ -0,0 +1,351 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Progress messages of optimizations, which can be followed live from another thread, e.g. by a GUI or a logger.
+//!
+//! Besides channels, the current estimates of long optimizations can be published periodically to any
+//! [EstimateSink](trait.EstimateSink.html) with [optimize_with_sink](../fn.optimize_with_sink.html), e.g. as JSON
+//! lines to a socket or as a rotating set of files, so that downstream consumers can use partial results before the
+//! final convergence.
+
+use crate::factor_graph::variable::VariableId;
+use crate::factor_graph::FactorGraph;
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, VecDeque};
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::sync::mpsc::Sender;
+use std::time::Duration;
+
+/// Message which is sent after each iteration of [optimize_streaming](../fn.optimize_streaming.html) and published
+/// periodically by [optimize_with_sink](../fn.optimize_with_sink.html).
+#[derive(Debug, Clone, PartialEq)]
+pub struct IterationUpdate {
+    /// The number of the completed iteration, starting at 1.
+    pub iteration: usize,
+    /// The total χ² after the iteration.
+    pub chi2: f64,
+    /// The Euclidean norm of the iteration's Gauss-Newton step before the line search.
+    pub step_norm: f64,
+    /// Whether the step was rejected because it increased the total χ² even after halving it, in which case the
+    /// estimates from before the iteration were restored.
+    pub rejected: bool,
+    /// The time since the start of the optimization.
+    pub elapsed: Duration,
+    /// The estimates of all variables after the iteration, if snapshots were requested.
+    pub snapshot: Option<BTreeMap<VariableId, Vec<f64>>>,
+}
+
+/// Receiver of the updates of [optimize_with_sink](../fn.optimize_with_sink.html), whose snapshots are always
+/// present.
+pub trait EstimateSink {
+    /// Publishes the update, or returns an error which stops the optimization.
+    fn publish(&mut self, update: &IterationUpdate) -> Result<(), String>;
+}
+
+impl EstimateSink for Sender<IterationUpdate> {
+    fn publish(&mut self, update: &IterationUpdate) -> Result<(), String> {
+        self.send(update.clone())
+            .map_err(|_| String::from("The receiver of the updates was dropped"))
+    }
+}
+
+/// Sink which writes each update as one line of JSON, e.g. to a TCP stream or the standard output.
+///
+/// The line is an object with the keys "iteration", "chi2", "stepNorm", "rejected", "elapsedSeconds" and
+/// "estimates", which maps the ID of each variable to its content.
+pub struct JsonLinesSink<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> JsonLinesSink<W> {
+    /// Returns a sink which writes to the given writer.
+    pub fn new(writer: W) -> Self {
+        JsonLinesSink { writer }
+    }
+
+    /// Returns the writer of the sink.
+    pub fn into_inner(self) -> W {
+        self.writer
+    }
+}
+
+impl<W: Write> EstimateSink for JsonLinesSink<W> {
+    fn publish(&mut self, update: &IterationUpdate) -> Result<(), String> {
+        writeln!(self.writer, "{}", update_to_json(update))
+            .and_then(|_| self.writer.flush())
+            .map_err(|e| format!("Update of iteration {} could not be written: {}", update.iteration, e))
+    }
+}
+
+/// Sink which writes each update to its own file and only keeps the files of the latest updates.
+///
+/// The files are named after the given prefix and the iteration, e.g. "estimates_12.json" for the prefix
+/// "estimates", and contain the same JSON object as the lines of [JsonLinesSink](struct.JsonLinesSink.html). Each
+/// file is written to a temporary path first and then renamed, so readers never see a partially written file.
+pub struct RotatingFileSink {
+    prefix: PathBuf,
+    keep: usize,
+    files: VecDeque<PathBuf>,
+}
+
+impl RotatingFileSink {
+    /// Returns a sink which writes files with the given path prefix and keeps the given number of files, at least
+    /// one.
+    pub fn new(prefix: impl Into<PathBuf>, keep: usize) -> Self {
+        RotatingFileSink {
+            prefix: prefix.into(),
+            keep: keep.max(1),
+            files: VecDeque::new(),
+        }
+    }
+
+    /// Returns the paths of the kept files, the latest one last.
+    pub fn files(&self) -> Vec<PathBuf> {
+        self.files.iter().cloned().collect()
+    }
+
+    // returns the path of the file with the given suffix after the prefix
+    fn get_path(&self, suffix: &str) -> PathBuf {
+        let mut file_name = self.prefix.file_name().unwrap_or_default().to_os_string();
+        file_name.push(suffix);
+        self.prefix.with_file_name(file_name)
+    }
+}
+
+impl EstimateSink for RotatingFileSink {
+    fn publish(&mut self, update: &IterationUpdate) -> Result<(), String> {
+        let path = self.get_path(&format!("_{}.json", update.iteration));
+        let temporary_path = self.get_path(".json.tmp");
+        fs::write(&temporary_path, update_to_json(update).to_string())
+            .and_then(|_| fs::rename(&temporary_path, &path))
+            .map_err(|e| format!("Update could not be written to {}: {}", path.display(), e))?;
+        if !self.files.contains(&path) {
+            self.files.push_back(path);
+        }
+        while self.files.len() > self.keep {
+            let outdated = self.files.pop_front().unwrap();
+            fs::remove_file(&outdated)
+                .map_err(|e| format!("Outdated update {} could not be removed: {}", outdated.display(), e))?;
+        }
+        Ok(())
+    }
+}
+
+// returns the JSON object of an update, whose estimates are empty without a snapshot
+fn update_to_json(update: &IterationUpdate) -> Value {
+    let estimates: Map<String, Value> = update
+        .snapshot
+        .iter()
+        .flatten()
+        .map(|(id, content)| (id.to_string(), json!(content)))
+        .collect();
+    json!({
+        "iteration": update.iteration,
+        "chi2": update.chi2,
+        "stepNorm": update.step_norm,
+        "rejected": update.rejected,
+        "elapsedSeconds": update.elapsed.as_secs_f64(),
+        "estimates": estimates,
+    })
+}
+
+// returns the current estimates of all variables
+pub(crate) fn get_snapshot(factor_graph: &FactorGraph) -> BTreeMap<VariableId, Vec<f64>> {
+    factor_graph
+        .node_indices
+        .iter()
+        .map(|i| factor_graph.get_var(*i))
+        .map(|var| (var.get_id(), var.get_content()))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::factor_graph::factor::CustomResidual;
+    use crate::optimizer::autodiff::Dual;
+    use crate::optimizer::config::OptimizerConfig;
+    use crate::optimizer::{optimize, optimize_streaming, optimize_with_config, optimize_with_sink, total_chi2};
+    use crate::parser::g2o::G2oParser;
+    use crate::parser::model::converter::convert_model;
+    use crate::parser::model::{Edge, FactorGraphModel, Vertex};
+    use crate::parser::Parser;
+    use std::collections::BTreeSet;
+    use std::sync::mpsc::channel;
+    use std::thread;
+
+    const FILE_NAME: &str = "data_files/optimizer_tests/full2d_0.g2o";
+
+    #[test]
+    fn test_updates_follow_optimization() {
+        let expected = G2oParser::parse_file(FILE_NAME).unwrap();
+        optimize(&expected, 3);
+        let (sender, receiver) = channel();
+        let logger = thread::spawn(move || receiver.iter().collect::<Vec<_>>());
+
+        let factor_graph = G2oParser::parse_file(FILE_NAME).unwrap();
+        optimize_streaming(&factor_graph, 3, &sender, true);
+        drop(sender);
+        let updates = logger.join().unwrap();
+        assert_eq!(updates.len(), 3);
+        assert_eq!(updates.iter().map(|u| u.iteration).collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert!(updates
+            .windows(2)
+            .all(|u| u[0].chi2 >= u[1].chi2 && u[0].elapsed <= u[1].elapsed));
+        assert!(updates[0].step_norm > updates[2].step_norm);
+        assert!(updates.iter().all(|u| !u.rejected));
+        assert_eq!(updates[2].chi2, total_chi2(&expected));
+        let snapshot = updates[2].snapshot.as_ref().unwrap();
+        assert_eq!(snapshot.len(), factor_graph.node_indices.len());
+        assert_eq!(
+            snapshot[&VariableId(1)],
+            factor_graph.get_var_by_id(VariableId(1)).unwrap().get_content()
+        );
+    }
+
+    #[test]
+    fn test_rejected_steps_are_reported() {
+        // the negated derivatives make the Gauss-Newton step point away from the optimum
+        let residual = CustomResidual::new("Ascent", |contents: &[Vec<Dual>], _: &[f64]| {
+            contents[0].iter().map(|v| Dual::new(v.value, -v.derivative)).collect()
+        });
+        let mut residuals = BTreeMap::new();
+        residuals.insert(String::from("Ascent"), residual);
+        let model = FactorGraphModel {
+            vertices: vec![Vertex {
+                id: VariableId(0),
+                vertex_type: String::from("Vehicle2D"),
+                content: vec![1.0, 2.0, 0.3],
+            }],
+            edges: vec![Edge {
+                edge_type: String::from("Ascent"),
+                vertices: vec![VariableId(0)],
+                restriction: vec![],
+                information_matrix: vec![1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0],
+            }],
+            fixed_vertices: BTreeSet::new(),
+            ..Default::default()
+        };
+        let factor_graph = convert_model(&model, &residuals).unwrap();
+        let pose = factor_graph.get_var_by_id(VariableId(0)).unwrap().get_content();
+
+        let (sender, receiver) = channel();
+        optimize_streaming(&factor_graph, 2, &sender, false);
+        let updates: Vec<IterationUpdate> = receiver.try_iter().collect();
+        assert!(updates.iter().all(|u| u.rejected && u.step_norm > 0.0));
+        assert_eq!(factor_graph.get_var_by_id(VariableId(0)).unwrap().get_content(), pose);
+    }
+
+    #[test]
+    fn test_dropped_receiver_stops_optimization() {
+        let (sender, receiver) = channel();
+        let factor_graph = G2oParser::parse_file(FILE_NAME).unwrap();
+        optimize_streaming(&factor_graph, 1, &sender, false);
+        assert_eq!(receiver.try_recv().unwrap().snapshot, None);
+
+        drop(receiver);
+        let chi2 = total_chi2(&factor_graph);
+        optimize_streaming(&factor_graph, 3, &sender, false);
+        // the first iteration is finished before its update cannot be sent
+        let expected = G2oParser::parse_file(FILE_NAME).unwrap();
+        optimize(&expected, 2);
+        assert!(total_chi2(&factor_graph) < chi2);
+        assert_eq!(total_chi2(&factor_graph), total_chi2(&expected));
+    }
+
+    #[test]
+    fn test_sink_receives_periodic_and_final_estimates() {
+        let config = OptimizerConfig::default();
+        let expected = G2oParser::parse_file(FILE_NAME).unwrap();
+        let expected_report = optimize_with_config(&expected, &config);
+
+        let factor_graph = G2oParser::parse_file(FILE_NAME).unwrap();
+        let (mut sender, receiver) = channel();
+        let report = optimize_with_sink(&factor_graph, &config, &mut sender, Duration::from_secs(0)).unwrap();
+        assert_eq!(report, expected_report);
+        let updates: Vec<IterationUpdate> = receiver.try_iter().collect();
+        assert_eq!(updates.len(), report.iterations);
+        assert!(updates.iter().all(|u| u.snapshot.is_some()));
+        assert_eq!(updates.last().unwrap().snapshot, Some(get_snapshot(&expected)));
+
+        // only the final estimates are published if the interval never passes
+        let factor_graph = G2oParser::parse_file(FILE_NAME).unwrap();
+        optimize_with_sink(&factor_graph, &config, &mut sender, Duration::from_secs(3600)).unwrap();
+        let updates: Vec<IterationUpdate> = receiver.try_iter().collect();
+        assert_eq!(updates.len(), 1);
+        assert_eq!(updates[0].iteration, report.iterations);
+        assert_eq!(updates[0].snapshot, Some(get_snapshot(&expected)));
+    }
+
+    #[test]
+    fn test_failing_sink_stops_optimization() {
+        let (mut sender, receiver) = channel();
+        drop(receiver);
+        let factor_graph = G2oParser::parse_file(FILE_NAME).unwrap();
+        let chi2 = total_chi2(&factor_graph);
+        let result = optimize_with_sink(
+            &factor_graph,
+            &OptimizerConfig::default(),
+            &mut sender,
+            Duration::from_secs(0),
+        );
+        assert_eq!(result, Err(String::from("The receiver of the updates was dropped")));
+        // the first iteration is finished before its update cannot be published
+        let expected = G2oParser::parse_file(FILE_NAME).unwrap();
+        optimize(&expected, 1);
+        assert!(total_chi2(&factor_graph) < chi2);
+        assert_eq!(total_chi2(&factor_graph), total_chi2(&expected));
+    }
+
+    #[test]
+    fn test_json_lines_and_rotating_files() {
+        let factor_graph = G2oParser::parse_file(FILE_NAME).unwrap();
+        let update = |iteration| IterationUpdate {
+            iteration,
+            chi2: 2.5,
+            step_norm: 0.5,
+            rejected: false,
+            elapsed: Duration::from_millis(1500),
+            snapshot: Some(get_snapshot(&factor_graph)),
+        };
+        let mut sink = JsonLinesSink::new(vec![]);
+        sink.publish(&update(1)).unwrap();
+        sink.publish(&update(2)).unwrap();
+        let output = String::from_utf8(sink.into_inner()).unwrap();
+        let lines: Vec<Value> = output.lines().map(|l| serde_json::from_str(l).unwrap()).collect();
+        assert_eq!(lines.len(), 2);
+        assert_eq!(lines[1]["iteration"], json!(2));
+        assert_eq!(lines[1]["elapsedSeconds"], json!(1.5));
+        assert_eq!(
+            lines[1]["estimates"]["1"],
+            json!(factor_graph.get_var_by_id(VariableId(1)).unwrap().get_content())
+        );
+
+        let directory = std::env::temp_dir().join(format!("gs-rs-rotation-{}", std::process::id()));
+        fs::create_dir_all(&directory).unwrap();
+        let mut sink = RotatingFileSink::new(directory.join("estimates"), 2);
+        (1..=3).for_each(|iteration| sink.publish(&update(iteration)).unwrap());
+        let files = sink.files();
+        assert_eq!(
+            files,
+            vec![directory.join("estimates_2.json"), directory.join("estimates_3.json")]
+        );
+        let mut entries: Vec<PathBuf> = fs::read_dir(&directory).unwrap().map(|e| e.unwrap().path()).collect();
+        entries.sort();
+        assert_eq!(entries, files);
+        let content: Value = serde_json::from_str(&fs::read_to_string(&files[1]).unwrap()).unwrap();
+        assert_eq!(content, update_to_json(&update(3)));
+        fs::remove_dir_all(&directory).unwrap();
+    }
+}
@@ -0,0 +1,122 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Structure-only optimizations, which move the landmarks while the poses are kept, e.g. after a pose-graph
+//! correction.
+
+use crate::factor_graph::variable::{FixedType, Variable, VariableId};
+use crate::factor_graph::FactorGraph;
+use crate::optimizer::config::OptimizerConfig;
+use crate::optimizer::optimize_with_config;
+use crate::optimizer::termination::OptimizationReport;
+
+/// Re-initializes the landmarks from their observations of the current poses and optimizes only the landmarks with
+/// the given configuration, e.g. after large loop-closure corrections moved the poses, so that landmarks stuck at
+/// their pre-correction positions do not drag the map back during the next full optimization.
+///
+/// The landmarks are initialized like in
+/// [initialize_landmarks](../../factor_graph/struct.FactorGraph.html#method.initialize_landmarks). During the
+/// optimization, all other variables are [frozen](../../factor_graph/struct.FactorGraph.html#method.freeze), and
+/// afterwards the ones which were not fixed before are optimizable again.
+///
+/// ```
+/// use gs_rs::examples_gen::single_landmark_with_two_observations;
+/// use gs_rs::factor_graph::FactorGraph;
+/// use gs_rs::factor_graph::variable::VariableId;
+/// use gs_rs::optimizer::config::OptimizerConfig;
+/// use gs_rs::optimizer::structure::retriangulate_landmarks;
+///
+/// let mut factor_graph: FactorGraph = single_landmark_with_two_observations().into();
+/// let pose = factor_graph.get_var_by_id(VariableId(1)).unwrap().get_content();
+/// retriangulate_landmarks(&mut factor_graph, &OptimizerConfig::default());
+/// assert_eq!(factor_graph.get_var_by_id(VariableId(1)).unwrap().get_content(), pose);
+/// ```
+pub fn retriangulate_landmarks(graph: &mut FactorGraph, config: &OptimizerConfig) -> OptimizationReport {
+    graph.initialize_landmarks();
+    let frozen: Vec<VariableId> = graph
+        .node_indices
+        .iter()
+        .map(|i| graph.get_var(*i))
+        .filter(|var| !matches!(var, Variable::Landmark2D(_) | Variable::Landmark3D(_)))
+        .filter(|var| var.get_fixed_type() != &FixedType::Fixed)
+        .map(|var| var.get_id())
+        .collect();
+    graph.freeze(&frozen).unwrap();
+    let report = optimize_with_config(graph, config);
+    graph.unfreeze(&frozen).unwrap();
+    report
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimizer::{optimize, total_chi2};
+    use crate::parser::g2o::G2oParser;
+    use crate::parser::Parser;
+    use std::ops::Range;
+
+    const FILE_NAME: &str = "data_files/optimizer_tests/full2d_0.g2o";
+
+    fn get_contents(factor_graph: &FactorGraph, is_landmark: bool) -> Vec<Vec<f64>> {
+        factor_graph
+            .node_indices
+            .iter()
+            .map(|i| factor_graph.get_var(*i))
+            .filter(|var| matches!(var, Variable::Landmark2D(_)) == is_landmark)
+            .map(|var| var.get_content())
+            .collect()
+    }
+
+    fn get_fixed_types(factor_graph: &FactorGraph) -> Vec<Option<Range<usize>>> {
+        factor_graph
+            .node_indices
+            .iter()
+            .map(|i| match factor_graph.get_var(*i).get_fixed_type() {
+                FixedType::NonFixed(range) => Some(range.clone()),
+                FixedType::Fixed => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_only_landmarks_are_moved() {
+        let mut factor_graph = G2oParser::parse_file(FILE_NAME).unwrap();
+        let fixed_types = get_fixed_types(&factor_graph);
+        let (poses, landmarks) = (get_contents(&factor_graph, false), get_contents(&factor_graph, true));
+        let chi2 = total_chi2(&factor_graph);
+
+        let report = retriangulate_landmarks(&mut factor_graph, &OptimizerConfig::default());
+        assert_eq!(get_contents(&factor_graph, false), poses);
+        assert_ne!(get_contents(&factor_graph, true), landmarks);
+        assert!(report.chi2 < chi2);
+        assert_eq!(get_fixed_types(&factor_graph), fixed_types);
+    }
+
+    #[test]
+    fn test_stuck_landmarks_are_recovered() {
+        let expected = G2oParser::parse_file(FILE_NAME).unwrap();
+        optimize(&expected, 10);
+        let expected_chi2 = total_chi2(&expected);
+
+        // the poses are corrected while the landmarks are left far away
+        let mut factor_graph = G2oParser::parse_file(FILE_NAME).unwrap();
+        for i in factor_graph.node_indices.clone() {
+            let var = factor_graph.get_var(i);
+            match var {
+                Variable::Landmark2D(_) => var.set_content(vec![100.0, -100.0]),
+                _ => var.set_content(expected.get_var(i).get_content()),
+            }
+        }
+        retriangulate_landmarks(&mut factor_graph, &OptimizerConfig::default());
+        assert!(total_chi2(&factor_graph) < expected_chi2 * 1.01);
+    }
+}
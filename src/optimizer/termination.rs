@@ -0,0 +1,165 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Termination of optimizations which run until convergence, e.g. to bound the latency of online callers.
+
+use crate::optimizer::components::ComponentReport;
+use crate::optimizer::CONVERGENCE_THRESHOLD;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+/// Token to stop an optimization from another thread, see
+/// [optimize_with_cancellation](../fn.optimize_with_cancellation.html).
+///
+/// All clones of a token share its state, so one clone can be passed to the optimization while another one is kept
+/// to cancel it.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken {
+    is_cancelled: Arc<AtomicBool>,
+}
+
+impl CancellationToken {
+    /// Returns a token which is not cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes the optimization stop after its current iteration.
+    pub fn cancel(&self) {
+        self.is_cancelled.store(true, Ordering::SeqCst);
+    }
+
+    /// Returns whether the token was cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.is_cancelled.load(Ordering::SeqCst)
+    }
+}
+
+/// The reason why an optimization stopped.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Termination {
+    /// The total χ² did not decrease significantly in the last iteration.
+    Converged,
+    /// The deadline passed before convergence.
+    DeadlineExceeded,
+    /// The cancellation token was cancelled before convergence.
+    Cancelled,
+    /// The maximum number of iterations was reached before convergence.
+    IterationLimit,
+    /// The linear system of an iteration could not be solved, e.g. because a variable is not constrained by any
+    /// factor. The estimates of the previous iterations are kept.
+    SolverFailed,
+}
+
+/// Criteria for stopping an optimization before its maximum number of iterations, see
+/// [OptimizerConfig](../config/struct.OptimizerConfig.html).
+///
+/// The deadline and the token are checked before each iteration, so the iteration which exceeds the deadline or
+/// during which the token is cancelled is finished and its estimates are kept.
+#[derive(Debug, Clone, Copy)]
+pub struct TerminationCriteria<'a> {
+    /// The relative decrease of the total χ² in an iteration below which the optimization has converged, or None to
+    /// always run the maximum number of iterations.
+    pub convergence_threshold: Option<f64>,
+    /// The time budget from the start of the optimization.
+    pub time_budget: Option<Duration>,
+    /// The token to stop the optimization from another thread.
+    pub cancellation: Option<&'a CancellationToken>,
+}
+
+impl Default for TerminationCriteria<'_> {
+    fn default() -> Self {
+        TerminationCriteria {
+            convergence_threshold: Some(CONVERGENCE_THRESHOLD),
+            time_budget: None,
+            cancellation: None,
+        }
+    }
+}
+
+impl TerminationCriteria<'_> {
+    // returns why the optimization which started at the given time stops before its next iteration, if it does
+    pub(crate) fn check(&self, start: Instant) -> Option<Termination> {
+        if self.time_budget.is_some_and(|budget| start.elapsed() >= budget) {
+            Some(Termination::DeadlineExceeded)
+        } else if self.cancellation.is_some_and(|token| token.is_cancelled()) {
+            Some(Termination::Cancelled)
+        } else {
+            None
+        }
+    }
+
+    // returns whether an iteration which changed the total χ² from the previous to the current value has converged
+    pub(crate) fn has_converged(&self, previous_chi2: f64, chi2: f64) -> bool {
+        self.convergence_threshold
+            .is_some_and(|threshold| (previous_chi2 - chi2).abs() <= threshold * chi2)
+    }
+}
+
+/// Summary of an optimization which ran until convergence or stopped early.
+#[derive(Debug, Clone, PartialEq)]
+pub struct OptimizationReport {
+    /// The number of completed iterations.
+    pub iterations: usize,
+    /// The total χ² at the final estimates.
+    pub chi2: f64,
+    /// The reason why the optimization stopped.
+    pub termination: Termination,
+    /// The summaries of the connected components if they were optimized independently, see
+    /// [optimize_components](../components/fn.optimize_components.html), and empty otherwise.
+    pub components: Vec<ComponentReport>,
+}
+
+impl OptimizationReport {
+    /// Returns whether the optimization stopped before convergence.
+    pub fn stopped_early(&self) -> bool {
+        self.termination != Termination::Converged
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cancellation_token() {
+        let token = CancellationToken::new();
+        let clone = token.clone();
+        assert!(!clone.is_cancelled());
+        token.cancel();
+        assert!(clone.is_cancelled());
+        assert!(!CancellationToken::new().is_cancelled());
+    }
+
+    #[test]
+    fn test_termination_criteria() {
+        let token = CancellationToken::new();
+        let criteria = TerminationCriteria {
+            cancellation: Some(&token),
+            ..Default::default()
+        };
+        assert_eq!(criteria.check(Instant::now()), None);
+        assert!(criteria.has_converged(1.0, 1.0));
+        assert!(!criteria.has_converged(2.0, 1.0));
+        token.cancel();
+        assert_eq!(criteria.check(Instant::now()), Some(Termination::Cancelled));
+
+        let criteria = TerminationCriteria {
+            convergence_threshold: None,
+            time_budget: Some(Duration::from_secs(0)),
+            cancellation: None,
+        };
+        assert_eq!(criteria.check(Instant::now()), Some(Termination::DeadlineExceeded));
+        assert!(!criteria.has_converged(1.0, 1.0));
+    }
+}
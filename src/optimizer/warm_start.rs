@@ -0,0 +1,160 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Warm starts of optimizations which are repeated after the factor graph changed only slightly.
+
+#![allow(non_snake_case)]
+
+use crate::factor_graph::variable::{FixedType, VariableId};
+use crate::factor_graph::FactorGraph;
+use crate::optimizer::linear_system::calculate_scaled_H_b;
+use crate::optimizer::ordering::{fill_reducing_permutation, permute_system, unpermute_solution};
+use crate::optimizer::solver::sparse_cholesky::ReusableSparseCholeskySolver;
+use std::collections::BTreeMap;
+use std::ops::Range;
+
+// the ID and range in H of each variable, which determine whether a permutation of H is still valid
+type VariableLayout = Vec<(VariableId, Option<Range<usize>>)>;
+
+/// State of previous optimizations, which is kept between calls of
+/// [optimize_with_state](../fn.optimize_with_state.html).
+///
+/// The state consists of:
+/// * the optimized estimates, i.e. the last linearization point. They replace the estimates of the variables with
+///   the same IDs and types at the start of the next optimization, so that e.g. a factor graph which is parsed
+///   again after appending measurements continues from the previous solution. New variables keep their estimates.
+/// * the fill-reducing variable ordering, which is reused as long as the variables and their ranges in H do not
+///   change.
+/// * the symbolic Cholesky factorization, which is reused as long as the block structure of H does not change, see
+///   [ReusableSparseCholeskySolver](../solver/sparse_cholesky/struct.ReusableSparseCholeskySolver.html).
+#[derive(Default)]
+pub struct OptimizerState {
+    estimates: BTreeMap<VariableId, Vec<f64>>,
+    ordering: Option<(VariableLayout, Option<Vec<usize>>)>,
+    solver: ReusableSparseCholeskySolver,
+}
+
+impl OptimizerState {
+    /// Returns a state without any previous optimization.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns how often the symbolic factorization was computed, i.e. how often it could not be reused.
+    pub fn symbolic_analysis_count(&self) -> usize {
+        self.solver.symbolic_analysis_count()
+    }
+
+    // restores the previous estimates and updates the ordering if the variables changed
+    pub(crate) fn start(&mut self, factor_graph: &FactorGraph) {
+        factor_graph
+            .node_indices
+            .iter()
+            .map(|i| factor_graph.get_var(*i))
+            .filter(|var| var.get_fixed_type() != &FixedType::Fixed)
+            .for_each(|var| match self.estimates.get(&var.get_id()) {
+                Some(estimate) if estimate.len() == var.get_content().len() => var.set_content(estimate.clone()),
+                _ => (),
+            });
+        let layout: VariableLayout = factor_graph
+            .node_indices
+            .iter()
+            .map(|i| factor_graph.get_var(*i))
+            .map(|var| match var.get_fixed_type() {
+                FixedType::NonFixed(range) => (var.get_id(), Some(range.clone())),
+                FixedType::Fixed => (var.get_id(), None),
+            })
+            .collect();
+        if self.ordering.as_ref().map(|(previous, _)| previous) != Some(&layout) {
+            self.ordering = Some((layout, fill_reducing_permutation(factor_graph)));
+        }
+    }
+
+    // returns the Gauss-Newton step at the current estimates
+    pub(crate) fn calculate_step(&mut self, factor_graph: &FactorGraph) -> Result<Vec<f64>, String> {
+        let (H, b) = calculate_scaled_H_b(factor_graph, None);
+        match self.ordering.as_ref().and_then(|(_, permutation)| permutation.as_ref()) {
+            Some(permutation) => {
+                let (H, b) = permute_system(&H, &b, permutation);
+                Ok(unpermute_solution(&self.solver.solve_block_sparse(&H, &(b * -1.0))?, permutation))
+            }
+            None => self.solver.solve_block_sparse(&H, &(b * -1.0)),
+        }
+    }
+
+    // keeps the optimized estimates for the next optimization
+    pub(crate) fn finish(&mut self, factor_graph: &FactorGraph) {
+        self.estimates = factor_graph
+            .node_indices
+            .iter()
+            .map(|i| factor_graph.get_var(*i))
+            .map(|var| (var.get_id(), var.get_content()))
+            .collect();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::factor_graph::factor::FactorType;
+    use crate::optimizer::{optimize, optimize_with_state, total_chi2};
+    use crate::parser::g2o::G2oParser;
+    use crate::parser::Parser;
+    use approx::assert_relative_eq;
+
+    const FILE_NAME: &str = "data_files/optimizer_tests/full2d_0.g2o";
+
+    #[test]
+    fn test_warm_start_continues_optimization() {
+        let expected = G2oParser::parse_file(FILE_NAME).unwrap();
+        optimize(&expected, 6);
+        let factor_graph = G2oParser::parse_file(FILE_NAME).unwrap();
+        let mut state = OptimizerState::new();
+        optimize_with_state(&factor_graph, 3, &mut state);
+        optimize_with_state(&factor_graph, 3, &mut state);
+        assert_relative_eq!(total_chi2(&factor_graph), total_chi2(&expected), max_relative = 1e-6);
+        assert_eq!(state.symbolic_analysis_count(), 1);
+    }
+
+    #[test]
+    fn test_estimates_are_reused() {
+        let optimized = G2oParser::parse_file(FILE_NAME).unwrap();
+        let mut state = OptimizerState::new();
+        optimize_with_state(&optimized, 5, &mut state);
+
+        let mut factor_graph = G2oParser::parse_file(FILE_NAME).unwrap();
+        optimize_with_state(&factor_graph, 0, &mut state);
+        assert_eq!(total_chi2(&factor_graph), total_chi2(&optimized));
+
+        // a new factor changes the block structure
+        let (a, b) = (VariableId(1), VariableId(3));
+        assert!(factor_graph.factors_between(a, b).is_empty());
+        let (pose_a, pose_b) = (
+            factor_graph.get_var_by_id(a).unwrap().get_content(),
+            factor_graph.get_var_by_id(b).unwrap().get_content(),
+        );
+        let (sin, cos) = pose_a[2].sin_cos();
+        let (dx, dy) = (pose_b[0] - pose_a[0], pose_b[1] - pose_a[1]);
+        let constraint = vec![cos * dx + sin * dy, cos * dy - sin * dx, pose_b[2] - pose_a[2]];
+        factor_graph
+            .add_factor(
+                a,
+                b,
+                FactorType::Odometry2D,
+                constraint,
+                vec![1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0].into(),
+            )
+            .unwrap();
+        optimize_with_state(&factor_graph, 1, &mut state);
+        assert_eq!(state.symbolic_analysis_count(), 2);
+    }
+}
@@ -0,0 +1,176 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Export of a factor graph's trajectory to a compact fixed-point binary format.
+//!
+//! The format is meant for consumers such as microcontrollers, which can neither parse text nor handle floats
+//! efficiently. All numbers are little-endian:
+//!
+//! | Field   | Type      | Content                                                        |
+//! |---------|-----------|----------------------------------------------------------------|
+//! | magic   | [u8; 4]   | b"GSFP"                                                        |
+//! | version | u8        | 1                                                              |
+//! | scale   | u32       | factor by which all values were multiplied before rounding     |
+//! | count   | u32       | number of following pose records                               |
+//!
+//! Each pose record consists of the vehicle's ID (u32), its kind (u8, 0 for 2D and 1 for 3D poses) and its
+//! content as i32 values, i.e. [x, y, rotation] for 2D and [x, y, z, q_x, q_y, q_z, q_w] for 3D poses.
+//! The records are sorted by ID. Landmarks are not part of the trajectory.
+//!
+//! The [reader](reader/index.html) module decodes the format without requiring the standard library. The exporter
+//! requires the feature "std".
+
+#[cfg(feature = "std")]
+use crate::factor_graph::FactorGraph;
+#[cfg(feature = "std")]
+use crate::parser::model::FactorGraphModel;
+#[cfg(feature = "std")]
+use std::convert::TryFrom;
+#[cfg(feature = "std")]
+use std::fs;
+
+pub mod reader;
+
+/// The magic bytes at the start of the format.
+pub const MAGIC: [u8; 4] = *b"GSFP";
+/// The version of the format written by the exporter.
+pub const VERSION: u8 = 1;
+/// The record kind of 2D poses.
+pub const KIND_POSE_2D: u8 = 0;
+/// The record kind of 3D poses.
+pub const KIND_POSE_3D: u8 = 1;
+
+/// Exporter for the fixed-point trajectory format.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FixedPointExporter {
+    /// The factor by which all values are multiplied before rounding, e.g. 1000 for a resolution of 1 mm when the
+    /// positions are given in meters.
+    pub scale: u32,
+}
+
+#[cfg(feature = "std")]
+impl Default for FixedPointExporter {
+    fn default() -> Self {
+        FixedPointExporter { scale: 1000 }
+    }
+}
+
+#[cfg(feature = "std")]
+impl FixedPointExporter {
+    /// Tries to compose a file at the given path containing the factor graph's trajectory.
+    pub fn compose_file(&self, factor_graph: &FactorGraph, file_path: &str) -> Result<(), String> {
+        let bytes = self.compose_model_to_bytes(&factor_graph.into())?;
+        fs::write(file_path, bytes).map_err(|_| format!("File could not be written to: {}", file_path))
+    }
+
+    /// Tries to compose the trajectory contained in the factor graph model.
+    ///
+    /// Fails if an ID or a scaled value does not fit into the format's integer types.
+    pub fn compose_model_to_bytes(&self, model: &FactorGraphModel) -> Result<Vec<u8>, String> {
+        let mut poses: Vec<_> = model
+            .vertices
+            .iter()
+            .filter(|v| v.vertex_type == "Vehicle2D" || v.vertex_type == "Vehicle3D")
+            .collect();
+        poses.sort_by_key(|v| v.id);
+
+        let mut bytes = MAGIC.to_vec();
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&self.scale.to_le_bytes());
+        bytes.extend_from_slice(&to_u32(poses.len(), "Number of poses")?.to_le_bytes());
+        for pose in poses {
+            bytes.extend_from_slice(&to_u32(pose.id.0, "Vertex ID")?.to_le_bytes());
+            bytes.push(if pose.vertex_type == "Vehicle2D" {
+                KIND_POSE_2D
+            } else {
+                KIND_POSE_3D
+            });
+            for value in &pose.content {
+                bytes.extend_from_slice(&self.scale_value(*value)?.to_le_bytes());
+            }
+        }
+        Ok(bytes)
+    }
+
+    fn scale_value(&self, value: f64) -> Result<i32, String> {
+        let scaled = (value * f64::from(self.scale)).round();
+        if scaled.is_nan() || scaled < f64::from(i32::MIN) || scaled > f64::from(i32::MAX) {
+            return Err(format!(
+                "Value {} does not fit the format with scale {}",
+                value, self.scale
+            ));
+        }
+        Ok(scaled as i32)
+    }
+}
+
+#[cfg(feature = "std")]
+fn to_u32(value: usize, name: &str) -> Result<u32, String> {
+    u32::try_from(value).map_err(|_| format!("{} {} does not fit the format", name, value))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::reader::{PoseRecord, ReadError, TrajectoryReader};
+    use super::*;
+    use crate::parser::json::JsonParser;
+    use crate::parser::Parser;
+
+    #[test]
+    fn test_export_and_read_2d_trajectory() {
+        let model = JsonParser::parse_file_to_model("data_files/full_demos/all_2d_types.json").unwrap();
+        let bytes = FixedPointExporter { scale: 100 }
+            .compose_model_to_bytes(&model)
+            .unwrap();
+        let reader = TrajectoryReader::new(&bytes).unwrap();
+        assert_eq!(reader.scale(), 100);
+
+        let records: Vec<PoseRecord> = reader.map(|r| r.unwrap()).collect();
+        let expected: Vec<PoseRecord> = model
+            .vertices
+            .iter()
+            .filter(|v| v.vertex_type == "Vehicle2D")
+            .map(|v| PoseRecord::Pose2D {
+                id: v.id.0 as u32,
+                values: [
+                    (v.content[0] * 100.0).round() as i32,
+                    (v.content[1] * 100.0).round() as i32,
+                    (v.content[2] * 100.0).round() as i32,
+                ],
+            })
+            .collect();
+        assert_eq!(records, expected);
+    }
+
+    #[test]
+    fn test_export_and_read_3d_trajectory() {
+        let model = JsonParser::parse_file_to_model("data_files/full_demos/all_3d_types.json").unwrap();
+        let bytes = FixedPointExporter::default().compose_model_to_bytes(&model).unwrap();
+        let records: Vec<_> = TrajectoryReader::new(&bytes).unwrap().collect();
+        let vehicle_count = model.vertices.iter().filter(|v| v.vertex_type == "Vehicle3D").count();
+        assert_eq!(records.len(), vehicle_count);
+        assert!(records.iter().all(|r| matches!(r, Ok(PoseRecord::Pose3D { .. }))));
+
+        assert_eq!(TrajectoryReader::new(&bytes[..8]).err(), Some(ReadError::Truncated));
+        let mut truncated: Vec<_> = TrajectoryReader::new(&bytes[..bytes.len() - 1]).unwrap().collect();
+        assert_eq!(truncated.pop(), Some(Err(ReadError::Truncated)));
+    }
+
+    #[test]
+    fn test_overflow_is_rejected() {
+        let model = JsonParser::parse_file_to_model("data_files/full_demos/all_2d_types.json").unwrap();
+        assert!(FixedPointExporter { scale: u32::MAX }
+            .compose_model_to_bytes(&model)
+            .is_err());
+    }
+}
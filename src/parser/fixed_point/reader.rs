@@ -0,0 +1,126 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Reader for the fixed-point trajectory format.
+//!
+//! Only depends on `core` and neither allocates nor uses floats, so it can be copied into `no_std` firmware as is.
+
+use core::convert::TryInto;
+
+const MAGIC: [u8; 4] = *b"GSFP";
+const VERSION: u8 = 1;
+const HEADER_LEN: usize = 13;
+
+/// A decoded pose record. The values still have to be divided by the scale.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PoseRecord {
+    /// [x, y, rotation]
+    Pose2D { id: u32, values: [i32; 3] },
+    /// [x, y, z, q_x, q_y, q_z, q_w]
+    Pose3D { id: u32, values: [i32; 7] },
+}
+
+/// Errors which can occur while reading.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReadError {
+    /// The data does not start with the format's magic bytes.
+    InvalidMagic,
+    /// The data was written in an unsupported version of the format.
+    UnsupportedVersion(u8),
+    /// The data ends in the middle of the header or a record.
+    Truncated,
+    /// A record has an unknown kind.
+    UnknownKind(u8),
+}
+
+/// Iterator over the pose records of a fixed-point trajectory.
+pub struct TrajectoryReader<'a> {
+    data: &'a [u8],
+    scale: u32,
+    remaining: u32,
+}
+
+impl<'a> TrajectoryReader<'a> {
+    /// Reads the header and returns an iterator over the following records.
+    pub fn new(data: &'a [u8]) -> Result<Self, ReadError> {
+        if data.len() < HEADER_LEN {
+            return Err(ReadError::Truncated);
+        }
+        if data[..4] != MAGIC {
+            return Err(ReadError::InvalidMagic);
+        }
+        if data[4] != VERSION {
+            return Err(ReadError::UnsupportedVersion(data[4]));
+        }
+        Ok(TrajectoryReader {
+            data: &data[HEADER_LEN..],
+            scale: read_u32(&data[5..9]),
+            remaining: read_u32(&data[9..13]),
+        })
+    }
+
+    /// Returns the factor by which all values were multiplied.
+    pub fn scale(&self) -> u32 {
+        self.scale
+    }
+
+    fn read_record(&mut self) -> Result<PoseRecord, ReadError> {
+        if self.data.len() < 5 {
+            return Err(ReadError::Truncated);
+        }
+        let id = read_u32(&self.data[..4]);
+        let kind = self.data[4];
+        let value_count = match kind {
+            0 => 3,
+            1 => 7,
+            _ => return Err(ReadError::UnknownKind(kind)),
+        };
+        let record_len = 5 + 4 * value_count;
+        if self.data.len() < record_len {
+            return Err(ReadError::Truncated);
+        }
+        let mut values = [0; 7];
+        for (i, value) in values.iter_mut().take(value_count).enumerate() {
+            *value = read_i32(&self.data[5 + 4 * i..9 + 4 * i]);
+        }
+        self.data = &self.data[record_len..];
+        Ok(match kind {
+            0 => PoseRecord::Pose2D {
+                id,
+                values: [values[0], values[1], values[2]],
+            },
+            _ => PoseRecord::Pose3D { id, values },
+        })
+    }
+}
+
+impl<'a> Iterator for TrajectoryReader<'a> {
+    type Item = Result<PoseRecord, ReadError>;
+
+    /// Returns the next record. Stops after the first error.
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let record = self.read_record();
+        self.remaining = if record.is_ok() { self.remaining - 1 } else { 0 };
+        Some(record)
+    }
+}
+
+fn read_u32(bytes: &[u8]) -> u32 {
+    u32::from_le_bytes(bytes.try_into().unwrap())
+}
+
+fn read_i32(bytes: &[u8]) -> i32 {
+    i32::from_le_bytes(bytes.try_into().unwrap())
+}
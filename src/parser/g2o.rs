@@ -12,7 +12,9 @@
 
 //! Conversion between factor graph structures and G2O files.
 
-use crate::parser::model::{Edge, FactorGraphModel, Vertex};
+use crate::factor_graph::units::LengthUnit;
+use crate::factor_graph::variable::VariableId;
+use crate::parser::model::{Edge, FactorGraphModel, ModelSummary, Vertex};
 use crate::parser::Parser;
 use std::collections::BTreeSet;
 
@@ -21,40 +23,71 @@ use std::collections::BTreeSet;
 /// More information on the G2O file format: https://github.com/RainerKuemmerle/g2o/wiki/File-Format
 ///
 /// Currently supported G2O vertices:
-/// VERTEX_SE2, VERTEX_XY, VERTEX_SE3:QUAT, VERTEX_TRACKXYZ
+/// VERTEX_SE2, VERTEX_XY, VERTEX_SE3:QUAT, VERTEX_TRACKXYZ, VERTEX_SWITCH
 ///
 /// Currently supported G2O edges:
-/// EDGE_PRIOR_SE2, EDGE_SE2, EDGE_SE2_XY, EDGE_SE3_PRIOR (*), EDGE_SE3:QUAT, EDGE_SE3_TRACKXYZ (*)
+/// EDGE_PRIOR_SE2, EDGE_SE2, EDGE_SE2_XY, EDGE_BEARING_RANGE_SE2_XY, EDGE_BEARING_SE2_XY, EDGE_SE3_PRIOR (*),
+/// EDGE_SE3_XYZPRIOR (*), EDGE_SE3:QUAT, EDGE_SE3_TRACKXYZ (*), EDGE_SE2_SWITCHABLE, EDGE_SE3_SWITCHABLE,
+/// EDGE_SWITCH_PRIOR, EDGE_POINTXY, EDGE_POINTXYZ
 ///
-/// (*) When using one of these edges, the 2nd (EDGE_SE3_PRIOR) or 3rd (EDGE_SE3_TRACKXYZ)
+/// The marginal covariances of the model are stored in extension lines COV_SE2, COV_XY, COV_SE3:QUAT,
+/// COV_TRACKXYZ and COV_SWITCH after the edges, which contain the vertex ID and the upper triangle of the covariance
+/// like the information matrices of the edges, e.g. "COV_XY 2 3.0 0.0 2.0".
+///
+/// The timestamps of the model's vertices are stored in extension lines TIMESTAMP after the covariances, which
+/// contain the vertex ID and its timestamp, e.g. "TIMESTAMP 2 0.5".
+///
+/// The declared unit of the model is stored in the extension line UNIT before the vertices, e.g. "UNIT millimeters",
+/// see [LengthUnit](../../factor_graph/units/enum.LengthUnit.html).
+///
+/// The summary of the model is stored in comment lines "# SUMMARY" at the beginning of the file, which contain a
+/// name and a value, e.g. "# SUMMARY chi2After 0.5", "# SUMMARY edgeType Odometry2D 3" or
+/// "# SUMMARY setting maxIterations 100", see [ModelSummary](../model/struct.ModelSummary.html). Other programs
+/// ignore them like any other comment, and so does the parser for "# SUMMARY" lines which it does not recognize.
+///
+/// The bearing-range edge EDGE_BEARING_RANGE_SE2_XY follows the format of EDGE_SE2_XY, but its measurement is
+/// [bearing, range] instead of the landmark's relative position, e.g. "EDGE_BEARING_RANGE_SE2_XY 0 1 0.5 2.0 100.0
+/// 0.0 10.0". The bearing-only edge EDGE_BEARING_SE2_XY only contains the bearing and its information, e.g.
+/// "EDGE_BEARING_SE2_XY 0 1 0.5 100.0".
+///
+/// The landmark-to-landmark edges EDGE_POINTXY and EDGE_POINTXYZ follow the format of EDGE_SE2_XY and
+/// EDGE_SE3_TRACKXYZ without an offset parameter, but connect two landmarks with the displacement of the second one
+/// in the world frame, e.g. "EDGE_POINTXY 1 2 0.5 -1.0 10.0 0.0 10.0".
+///
+/// The switchable types follow the format of Vertigo (https://openslam-org.github.io/vertigo.html), i.e. a
+/// switchable edge lists its switch vertex after its two pose vertices.
+///
+/// (*) When using one of these edges, the 2nd (EDGE_SE3_PRIOR, EDGE_SE3_XYZPRIOR) or 3rd (EDGE_SE3_TRACKXYZ)
 /// vertex/offset parameter is expected to be the offset with ID 0 as follows:
 /// "PARAMS_SE3OFFSET 0 0 0 0 0 0 0 1".
 /// Anything else will result in undefined and most likely undesired behavior.
 /// The offset "PARAMS_SE3OFFSET" is not supported in any other scenario.
 ///
-/// Note: Currently panics instead of returning an Err() when parsing an invalid file.
+/// Parsing returns an Err() describing the first invalid line instead of panicking, so that untrusted input can be
+/// parsed, e.g. received by a service.
 pub struct G2oParser;
 
 impl Parser for G2oParser {
     fn parse_string_to_model(s: &str) -> Result<FactorGraphModel, String> {
-        let mut model = FactorGraphModel {
-            vertices: vec![],
-            edges: vec![],
-            fixed_vertices: BTreeSet::new(),
-        };
-        let lines = s.split('\n');
-        lines
-            .enumerate()
-            .for_each(|(i, line)| Self::parse_line(&mut model, line, i + 1));
+        let mut model = FactorGraphModel::default();
+        for (i, line) in s.split('\n').enumerate() {
+            Self::parse_line(&mut model, line, i + 1)?;
+        }
         Ok(model)
     }
 
     fn compose_model_to_string(model: FactorGraphModel) -> Result<String, String> {
         let mut str_vec: Vec<String> = vec![];
+        if let Some(summary) = &model.summary {
+            str_vec.extend(Self::summary_to_strings(summary));
+        }
+        if let Some(unit) = model.unit {
+            str_vec.push(format!("UNIT {}", unit.name()));
+        }
         if model
             .edges
             .iter()
-            .any(|e| e.edge_type == "Position3D" || e.edge_type == "Observation3D")
+            .any(|e| ["Position3D", "PositionOnly3D", "Observation3D"].contains(&e.edge_type.as_str()))
         {
             str_vec.push(String::from("PARAMS_SE3OFFSET 0 0 0 0 0 0 0 1"));
         }
@@ -66,80 +99,139 @@ impl Parser for G2oParser {
                 .collect(),
         );
         str_vec.extend::<Vec<String>>(model.edges.iter().map(Self::edge_to_string).collect());
+        for v in &model.vertices {
+            if let Some(covariance) = model.covariances.get(&v.id) {
+                str_vec.push(Self::covariance_to_string(v, covariance)?);
+            }
+        }
+        for (id, time) in &model.timestamps {
+            str_vec.push(format!("TIMESTAMP {} {:?}", id, time));
+        }
         Ok(str_vec.join("\n"))
     }
 }
 
 impl G2oParser {
-    fn parse_line(model: &mut FactorGraphModel, line: &str, line_number: usize) {
+    fn parse_line(model: &mut FactorGraphModel, line: &str, line_number: usize) -> Result<(), String> {
         let tokens: Vec<&str> = line.split_whitespace().collect();
+        if tokens.len() > 1 && tokens[0] == "#" && tokens[1] == "SUMMARY" {
+            let mut summary = model.summary.clone().unwrap_or_default();
+            if Self::parse_summary_line(&mut summary, &tokens[2..]).is_some() {
+                model.summary = Some(summary);
+            }
+            return Ok(());
+        }
         if tokens.is_empty() || line.starts_with('#') {
-            return;
+            return Ok(());
         }
         match tokens[0] {
-            "VERTEX_SE2" | "VERTEX_XY" | "VERTEX_SE3:QUAT" | "VERTEX_TRACKXYZ" => {
-                model.vertices.push(Self::parse_vertex(&tokens, line_number))
+            "VERTEX_SE2" | "VERTEX_XY" | "VERTEX_SE3:QUAT" | "VERTEX_TRACKXYZ" | "VERTEX_SWITCH" => {
+                model.vertices.push(Self::parse_vertex(&tokens, line_number)?)
             }
-            "EDGE_PRIOR_SE2" | "EDGE_SE2" | "EDGE_SE2_XY" | "EDGE_SE3_PRIOR" | "EDGE_SE3:QUAT"
-            | "EDGE_SE3_TRACKXYZ" => model.edges.push(Self::parse_edge(&tokens, line_number)),
+            "EDGE_PRIOR_SE2"
+            | "EDGE_SE2"
+            | "EDGE_SE2_XY"
+            | "EDGE_BEARING_RANGE_SE2_XY"
+            | "EDGE_BEARING_SE2_XY"
+            | "EDGE_SE3_PRIOR"
+            | "EDGE_SE3_XYZPRIOR"
+            | "EDGE_SE3:QUAT"
+            | "EDGE_SE3_TRACKXYZ"
+            | "EDGE_SE2_SWITCHABLE"
+            | "EDGE_SE3_SWITCHABLE"
+            | "EDGE_SWITCH_PRIOR"
+            | "EDGE_POINTXY"
+            | "EDGE_POINTXYZ" => model.edges.push(Self::parse_edge(&tokens, line_number)?),
+            "COV_SE2" | "COV_XY" | "COV_SE3:QUAT" | "COV_TRACKXYZ" | "COV_SWITCH" => {
+                let (id, covariance) = Self::parse_covariance(&tokens, line_number)?;
+                model.covariances.insert(id, covariance);
+            }
+            "TIMESTAMP" => {
+                Self::check_tokens(3, tokens.len(), line_number)?;
+                let id = VariableId(Self::parse_val(tokens[1], line_number)?);
+                model.timestamps.insert(id, Self::parse_val(tokens[2], line_number)?);
+            }
+            "UNIT" => match tokens.get(1).and_then(|name| LengthUnit::from_name(name)) {
+                Some(unit) => model.unit = Some(unit),
+                None => return Err(format!("Unknown unit in line {}: {}", line_number, line)),
+            },
             "FIX" => {
-                model.fixed_vertices.extend(Self::parse_fix(&tokens, line_number));
+                model.fixed_vertices.extend(Self::parse_fix(&tokens, line_number)?);
             }
             "PARAMS_SE3OFFSET" => (), // line expected to equal "PARAMS_SE3OFFSET 0 0 0 0 0 0 0 1"
-            _ => panic!("Unknown keyword at beginning of line {}: {}", line_number, tokens[0]),
+            _ => return Err(Self::unknown_keyword(tokens[0], line_number)),
         };
+        Ok(())
     }
 
-    fn parse_vertex(tokens: &[&str], line_number: usize) -> Vertex {
+    fn parse_vertex(tokens: &[&str], line_number: usize) -> Result<Vertex, String> {
         let (type_str, c_len) = match tokens[0] {
             "VERTEX_SE2" => ("Vehicle2D", 3),
             "VERTEX_XY" => ("Landmark2D", 2),
             "VERTEX_SE3:QUAT" => ("Vehicle3D", 7),
             "VERTEX_TRACKXYZ" => ("Landmark3D", 3),
-            _ => panic!("Unknown keyword at beginning of line {}: {}", line_number, tokens[0]),
+            "VERTEX_SWITCH" => ("Switch", 1),
+            _ => return Err(Self::unknown_keyword(tokens[0], line_number)),
         };
         let expected_length = 2 + c_len;
-        Self::assert_tokens(expected_length, tokens.len(), line_number);
-        Vertex {
-            id: Self::parse_val(tokens[1], line_number),
+        Self::check_tokens(expected_length, tokens.len(), line_number)?;
+        Ok(Vertex {
+            id: VariableId(Self::parse_val(tokens[1], line_number)?),
             vertex_type: String::from(type_str),
-            content: tokens[2..].iter().map(|s| Self::parse_val(s, line_number)).collect(),
-        }
+            content: Self::parse_vals(&tokens[2..], line_number)?,
+        })
     }
 
-    fn parse_edge(tokens: &[&str], line_number: usize) -> Edge {
+    fn parse_edge(tokens: &[&str], line_number: usize) -> Result<Edge, String> {
         let (type_str, v_num, c_len, (index_mapping, upper_t_len)) = match tokens[0] {
             "EDGE_PRIOR_SE2" => ("Position2D", 1, 3, Self::get_index_mapping_vec_and_upper_t_len(3)),
             "EDGE_SE2" => ("Odometry2D", 2, 3, Self::get_index_mapping_vec_and_upper_t_len(3)),
             "EDGE_SE2_XY" => ("Observation2D", 2, 2, Self::get_index_mapping_vec_and_upper_t_len(2)),
+            "EDGE_BEARING_RANGE_SE2_XY" => ("BearingRange2D", 2, 2, Self::get_index_mapping_vec_and_upper_t_len(2)),
+            "EDGE_BEARING_SE2_XY" => ("Bearing2D", 2, 1, Self::get_index_mapping_vec_and_upper_t_len(1)),
             "EDGE_SE3_PRIOR" => ("Position3D", 2, 7, Self::get_index_mapping_vec_and_upper_t_len(6)),
+            "EDGE_SE3_XYZPRIOR" => ("PositionOnly3D", 2, 3, Self::get_index_mapping_vec_and_upper_t_len(3)),
             "EDGE_SE3:QUAT" => ("Odometry3D", 2, 7, Self::get_index_mapping_vec_and_upper_t_len(6)),
             "EDGE_SE3_TRACKXYZ" => ("Observation3D", 3, 3, Self::get_index_mapping_vec_and_upper_t_len(3)),
-            _ => panic!("Unknown keyword at beginning of line {}: {}", line_number, tokens[0]),
+            "EDGE_SE2_SWITCHABLE" => ("SwitchableOdometry2D", 3, 3, Self::get_index_mapping_vec_and_upper_t_len(3)),
+            "EDGE_SE3_SWITCHABLE" => ("SwitchableOdometry3D", 3, 7, Self::get_index_mapping_vec_and_upper_t_len(6)),
+            "EDGE_SWITCH_PRIOR" => ("SwitchPrior", 1, 1, Self::get_index_mapping_vec_and_upper_t_len(1)),
+            "EDGE_POINTXY" => ("LandmarkOffset2D", 2, 2, Self::get_index_mapping_vec_and_upper_t_len(2)),
+            "EDGE_POINTXYZ" => ("LandmarkOffset3D", 2, 3, Self::get_index_mapping_vec_and_upper_t_len(3)),
+            _ => return Err(Self::unknown_keyword(tokens[0], line_number)),
         };
         let expected_length = 1 + v_num + c_len + upper_t_len;
-        Self::assert_tokens(expected_length, tokens.len(), line_number);
-        Edge {
+        Self::check_tokens(expected_length, tokens.len(), line_number)?;
+        let vertex_tokens = match tokens[0] {
+            "EDGE_SE3_PRIOR" | "EDGE_SE3_XYZPRIOR" | "EDGE_SE3_TRACKXYZ" => &tokens[1..v_num],
+            _ => &tokens[1..1 + v_num],
+        };
+        let upper_triangle: Vec<f64> = Self::parse_vals(&tokens[1 + v_num + c_len..], line_number)?;
+        Ok(Edge {
             edge_type: String::from(type_str),
-            vertices: match tokens[0] {
-                "EDGE_SE3_PRIOR" | "EDGE_SE3_TRACKXYZ" => tokens[1..v_num]
-                    .iter()
-                    .map(|s| Self::parse_val(s, line_number))
-                    .collect(),
-                _ => tokens[1..1 + v_num]
-                    .iter()
-                    .map(|s| Self::parse_val(s, line_number))
-                    .collect(),
-            },
-            restriction: tokens[1 + v_num..1 + v_num + c_len]
-                .iter()
-                .map(|s| Self::parse_val(s, line_number))
-                .collect(),
-            information_matrix: index_mapping
-                .iter()
-                .map(|i| Self::parse_val(tokens[1 + v_num + c_len + *i], line_number))
+            vertices: Self::parse_vals(vertex_tokens, line_number)?
+                .into_iter()
+                .map(VariableId)
                 .collect(),
-        }
+            restriction: Self::parse_vals(&tokens[1 + v_num..1 + v_num + c_len], line_number)?,
+            information_matrix: index_mapping.iter().map(|i| upper_triangle[*i]).collect(),
+        })
+    }
+
+    fn parse_covariance(tokens: &[&str], line_number: usize) -> Result<(VariableId, Vec<f64>), String> {
+        let (index_mapping, upper_t_len) = match tokens[0] {
+            "COV_SE2" | "COV_TRACKXYZ" => Self::get_index_mapping_vec_and_upper_t_len(3),
+            "COV_XY" => Self::get_index_mapping_vec_and_upper_t_len(2),
+            "COV_SE3:QUAT" => Self::get_index_mapping_vec_and_upper_t_len(6),
+            "COV_SWITCH" => Self::get_index_mapping_vec_and_upper_t_len(1),
+            _ => return Err(Self::unknown_keyword(tokens[0], line_number)),
+        };
+        Self::check_tokens(2 + upper_t_len, tokens.len(), line_number)?;
+        let upper_triangle: Vec<f64> = Self::parse_vals(&tokens[2..], line_number)?;
+        Ok((
+            VariableId(Self::parse_val(tokens[1], line_number)?),
+            index_mapping.iter().map(|i| upper_triangle[*i]).collect(),
+        ))
     }
 
     fn get_index_mapping_vec_and_upper_t_len(dim: usize) -> (Vec<usize>, usize) {
@@ -156,42 +248,96 @@ impl G2oParser {
         (full_matrix_vec, upper_t_len)
     }
 
-    fn parse_fix(tokens: &[&str], line_number: usize) -> BTreeSet<usize> {
+    fn parse_fix(tokens: &[&str], line_number: usize) -> Result<BTreeSet<VariableId>, String> {
         if tokens.len() == 1 {
-            panic!(
+            return Err(format!(
                 "Empty set of fixed vertices in line {}: Expected at least one vertex ID.",
                 line_number
-            );
+            ));
         }
-        tokens[1..].iter().map(|s| Self::parse_val(s, line_number)).collect()
+        Ok(Self::parse_vals(&tokens[1..], line_number)?
+            .into_iter()
+            .map(VariableId)
+            .collect())
     }
 
-    fn assert_tokens(expected: usize, actual: usize, line_number: usize) {
+    // returns None for lines which are not part of a summary, e.g. other comments starting with "# SUMMARY"
+    fn parse_summary_line(summary: &mut ModelSummary, tokens: &[&str]) -> Option<()> {
+        match tokens {
+            ["version", version] => summary.version = String::from(*version),
+            ["vertices", count] => summary.vertices = count.parse().ok()?,
+            ["fixedVertices", count] => summary.fixed_vertices = count.parse().ok()?,
+            ["edges", count] => summary.edges = count.parse().ok()?,
+            ["edgeType", edge_type, count] => {
+                summary.edge_types.insert(String::from(*edge_type), count.parse().ok()?);
+            }
+            ["chi2Before", chi2] => summary.chi2_before = Some(chi2.parse().ok()?),
+            ["chi2After", chi2] => summary.chi2_after = Some(chi2.parse().ok()?),
+            ["setting", name, value @ ..] if !value.is_empty() => {
+                summary.solver_settings.insert(String::from(*name), value.join(" "));
+            }
+            _ => return None,
+        }
+        Some(())
+    }
+
+    fn unknown_keyword(keyword: &str, line_number: usize) -> String {
+        format!("Unknown keyword at beginning of line {}: {}", line_number, keyword)
+    }
+
+    fn check_tokens(expected: usize, actual: usize, line_number: usize) -> Result<(), String> {
         if actual != expected {
-            panic!(
+            return Err(format!(
                 "Wrong number of tokens in line {}: Expected: {}; Actual: {}",
                 line_number, expected, actual
-            );
+            ));
         }
+        Ok(())
     }
 
-    fn parse_val<T: std::str::FromStr>(s: &str, line_number: usize) -> T {
-        match s.parse() {
-            Ok(val) => val,
-            Err(_str) => panic!(
+    fn parse_val<T: std::str::FromStr>(s: &str, line_number: usize) -> Result<T, String> {
+        s.parse().map_err(|_| {
+            format!(
                 "Could not parse the following value to the correct data type in line {}: {}",
                 line_number, s
-            ),
+            )
+        })
+    }
+
+    fn parse_vals<T: std::str::FromStr>(tokens: &[&str], line_number: usize) -> Result<Vec<T>, String> {
+        tokens.iter().map(|s| Self::parse_val(s, line_number)).collect()
+    }
+
+    fn summary_to_strings(summary: &ModelSummary) -> Vec<String> {
+        let mut str_vec = vec![
+            format!("# SUMMARY version {}", summary.version),
+            format!("# SUMMARY vertices {}", summary.vertices),
+            format!("# SUMMARY fixedVertices {}", summary.fixed_vertices),
+            format!("# SUMMARY edges {}", summary.edges),
+        ];
+        for (edge_type, count) in &summary.edge_types {
+            str_vec.push(format!("# SUMMARY edgeType {} {}", edge_type, count));
+        }
+        if let Some(chi2) = summary.chi2_before {
+            str_vec.push(format!("# SUMMARY chi2Before {}", chi2));
+        }
+        if let Some(chi2) = summary.chi2_after {
+            str_vec.push(format!("# SUMMARY chi2After {}", chi2));
         }
+        for (name, value) in &summary.solver_settings {
+            str_vec.push(format!("# SUMMARY setting {} {}", name, value));
+        }
+        str_vec
     }
 
-    fn vertex_to_string(v: &Vertex, fixed_vertices: &BTreeSet<usize>) -> String {
+    fn vertex_to_string(v: &Vertex, fixed_vertices: &BTreeSet<VariableId>) -> String {
         let mut tokens: Vec<String> = vec![];
         match v.vertex_type.as_str() {
             "Vehicle2D" => tokens.push(String::from("VERTEX_SE2")),
             "Landmark2D" => tokens.push(String::from("VERTEX_XY")),
             "Vehicle3D" => tokens.push(String::from("VERTEX_SE3:QUAT")),
             "Landmark3D" => tokens.push(String::from("VERTEX_TRACKXYZ")),
+            "Switch" => tokens.push(String::from("VERTEX_SWITCH")),
             other_type => panic!(
                 "Vertex type unsupported to be composed to G2O format: {}",
                 other_type
@@ -212,23 +358,35 @@ impl G2oParser {
             "Position2D" => tokens.push(String::from("EDGE_PRIOR_SE2")),
             "Odometry2D" => tokens.push(String::from("EDGE_SE2")),
             "Observation2D" => tokens.push(String::from("EDGE_SE2_XY")),
+            "BearingRange2D" => tokens.push(String::from("EDGE_BEARING_RANGE_SE2_XY")),
+            "Bearing2D" => tokens.push(String::from("EDGE_BEARING_SE2_XY")),
             "Position3D" => tokens.push(String::from("EDGE_SE3_PRIOR")),
+            "PositionOnly3D" => tokens.push(String::from("EDGE_SE3_XYZPRIOR")),
             "Odometry3D" => tokens.push(String::from("EDGE_SE3:QUAT")),
             "Observation3D" => tokens.push(String::from("EDGE_SE3_TRACKXYZ")),
+            "SwitchableOdometry2D" => tokens.push(String::from("EDGE_SE2_SWITCHABLE")),
+            "SwitchableOdometry3D" => tokens.push(String::from("EDGE_SE3_SWITCHABLE")),
+            "SwitchPrior" => tokens.push(String::from("EDGE_SWITCH_PRIOR")),
+            "LandmarkOffset2D" => tokens.push(String::from("EDGE_POINTXY")),
+            "LandmarkOffset3D" => tokens.push(String::from("EDGE_POINTXYZ")),
             other_type => panic!(
                 "Edge type unsupported to be composed to G2O format: {}",
                 other_type
             ),
         }
-        Self::append_usize_slice_to_string_vec(&mut tokens, e.vertices.as_slice());
-        if e.edge_type == "Position3D" || e.edge_type == "Observation3D" {
+        Self::append_id_slice_to_string_vec(&mut tokens, e.vertices.as_slice());
+        if ["Position3D", "PositionOnly3D", "Observation3D"].contains(&e.edge_type.as_str()) {
             Self::append_usize_slice_to_string_vec(&mut tokens, &[0]); // the last vertex/offset index should be 0 for these edges
         }
         Self::append_f64_slice_to_string_vec(&mut tokens, &e.restriction);
         let upper_triangle = match e.edge_type.as_str() {
-            "Position2D" | "Odometry2D" | "Observation3D" => Self::get_upper_triangle_indices(3),
-            "Observation2D" => Self::get_upper_triangle_indices(2),
-            "Position3D" | "Odometry3D" => Self::get_upper_triangle_indices(6),
+            "Position2D" | "Odometry2D" | "PositionOnly3D" | "Observation3D" | "SwitchableOdometry2D"
+            | "LandmarkOffset3D" => {
+                Self::get_upper_triangle_indices(3)
+            }
+            "Observation2D" | "BearingRange2D" | "LandmarkOffset2D" => Self::get_upper_triangle_indices(2),
+            "Position3D" | "Odometry3D" | "SwitchableOdometry3D" => Self::get_upper_triangle_indices(6),
+            "Bearing2D" | "SwitchPrior" => Self::get_upper_triangle_indices(1),
             other_type => panic!(
                 "Edge type unsupported to be composed to G2O format: {}",
                 other_type
@@ -238,6 +396,28 @@ impl G2oParser {
         tokens.join(" ")
     }
 
+    fn covariance_to_string(v: &Vertex, covariance: &[f64]) -> Result<String, String> {
+        let (keyword, dim) = match v.vertex_type.as_str() {
+            "Vehicle2D" => ("COV_SE2", 3),
+            "Landmark2D" => ("COV_XY", 2),
+            "Vehicle3D" => ("COV_SE3:QUAT", 6),
+            "Landmark3D" => ("COV_TRACKXYZ", 3),
+            "Switch" => ("COV_SWITCH", 1),
+            other_type => return Err(format!("Vertex type unsupported to be composed to G2O format: {}", other_type)),
+        };
+        if covariance.len() != dim * dim {
+            return Err(format!(
+                "Covariance of vertex {} has {} entries instead of {}",
+                v.id,
+                covariance.len(),
+                dim * dim
+            ));
+        }
+        let mut tokens = vec![String::from(keyword), v.id.to_string()];
+        Self::append_f64_slice_elements_to_string_vec(&mut tokens, covariance, &Self::get_upper_triangle_indices(dim));
+        Ok(tokens.join(" "))
+    }
+
     fn get_upper_triangle_indices(dim: usize) -> Vec<usize> {
         let mut indices: Vec<usize> = vec![];
         for i in 0..dim {
@@ -252,6 +432,10 @@ impl G2oParser {
         tokens.extend::<Vec<String>>(f64_slice.iter().map(|val| format!("{:?}", val)).collect());
     }
 
+    fn append_id_slice_to_string_vec(tokens: &mut Vec<String>, id_slice: &[VariableId]) {
+        tokens.extend::<Vec<String>>(id_slice.iter().map(|id| id.to_string()).collect());
+    }
+
     fn append_usize_slice_to_string_vec(tokens: &mut Vec<String>, usize_slice: &[usize]) {
         tokens.extend::<Vec<String>>(usize_slice.iter().map(|val| format!("{:?}", val)).collect());
     }
@@ -264,8 +448,11 @@ impl G2oParser {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::factor_graph::variable::VariableId;
+    use crate::factor_graph::FactorGraph;
     use crate::parser::model::{Edge, Vertex};
     use log::LevelFilter;
+    use nalgebra::DMatrix;
     use std::collections::BTreeSet;
     use std::fs;
 
@@ -276,20 +463,21 @@ mod tests {
             .try_init();
     }
 
+    #[allow(clippy::approx_constant)]
     fn get_2d_model() -> FactorGraphModel {
         let vertices = vec![
             Vertex {
-                id: 0,
+                id: VariableId(0),
                 vertex_type: String::from("Vehicle2D"),
                 content: vec![1.0, 0.0, 1.57],
             },
             Vertex {
-                id: 1,
+                id: VariableId(1),
                 vertex_type: String::from("Vehicle2D"),
                 content: vec![0.0, 1.0, 3.14],
             },
             Vertex {
-                id: 2,
+                id: VariableId(2),
                 vertex_type: String::from("Landmark2D"),
                 content: vec![1.5, 2.0],
             },
@@ -297,29 +485,30 @@ mod tests {
         let edges = vec![
             Edge {
                 edge_type: String::from("Odometry2D"),
-                vertices: vec![0, 1],
+                vertices: vec![VariableId(0), VariableId(1)],
                 restriction: vec![1.0, 1.5, 1.57],
                 information_matrix: vec![1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0],
             },
             Edge {
                 edge_type: String::from("Observation2D"),
-                vertices: vec![0, 2],
+                vertices: vec![VariableId(0), VariableId(2)],
                 restriction: vec![0.0, -1.0],
                 information_matrix: vec![1.0, 0.0, 0.0, 1.0],
             },
             Edge {
                 edge_type: String::from("Position2D"),
-                vertices: vec![1],
+                vertices: vec![VariableId(1)],
                 restriction: vec![0.0, 1.0, 3.13],
                 information_matrix: vec![10.0, 0.0, 0.0, 0.0, 10.0, 0.0, 0.0, 0.0, 1.0],
             },
         ];
         let mut fixed_vertices = BTreeSet::new();
-        fixed_vertices.insert(0);
+        fixed_vertices.insert(VariableId(0));
         FactorGraphModel {
             vertices,
             edges,
             fixed_vertices,
+            ..Default::default()
         }
     }
 
@@ -343,17 +532,17 @@ mod tests {
     fn get_3d_model() -> FactorGraphModel {
         let vertices = vec![
             Vertex {
-                id: 0,
+                id: VariableId(0),
                 vertex_type: String::from("Vehicle3D"),
                 content: vec![18.7381, 0.000000274428, 8.2287, 0.0, 0.0, 0.0, 1.0],
             },
             Vertex {
-                id: 1,
+                id: VariableId(1),
                 vertex_type: String::from("Vehicle3D"),
                 content: vec![19.0477, 2.34636, 8.2319, -0.139007, 0.0806488, 0.14657, 0.976059],
             },
             Vertex {
-                id: 2,
+                id: VariableId(2),
                 vertex_type: String::from("Landmark3D"),
                 content: vec![18.2645, 4.24943, 15.2057],
             },
@@ -361,7 +550,7 @@ mod tests {
         let edges = vec![
             Edge {
                 edge_type: String::from("Odometry3D"),
-                vertices: vec![0, 1],
+                vertices: vec![VariableId(0), VariableId(1)],
                 restriction: vec![0.309576, 2.34636, 0.00315914, -0.139007, 0.0806488, 0.14657, 0.976059],
                 information_matrix: vec![
                     1.0,
@@ -404,7 +593,7 @@ mod tests {
             },
             Edge {
                 edge_type: String::from("Position3D"),
-                vertices: vec![1],
+                vertices: vec![VariableId(1)],
                 restriction: vec![0.309576, 2.34636, 0.00315914, -0.139007, 0.0806488, 0.14657, 0.976059],
                 information_matrix: vec![
                     1.0,
@@ -447,7 +636,7 @@ mod tests {
             },
             Edge {
                 edge_type: String::from("Observation3D"),
-                vertices: vec![1, 2],
+                vertices: vec![VariableId(1), VariableId(2)],
                 restriction: vec![-0.034127, 2.24359, -0.503123],
                 information_matrix: vec![
                     3934.45, -9.14727, 63.005, -9.14727, 3998.72, 10.7561, 63.005, 10.7561, 3909.38,
@@ -455,11 +644,12 @@ mod tests {
             },
         ];
         let mut fixed_vertices = BTreeSet::new();
-        fixed_vertices.insert(0);
+        fixed_vertices.insert(VariableId(0));
         FactorGraphModel {
             vertices,
             edges,
             fixed_vertices,
+            ..Default::default()
         }
     }
 
@@ -479,4 +669,224 @@ mod tests {
         let expected_string = fs::read_to_string("data_files/full_demos/all_3d_types.g2o").unwrap();
         assert_eq!(&composed_string, &expected_string);
     }
+
+    #[test]
+    fn test_switchable_type_round_trip() {
+        let g2o_string = [
+            "VERTEX_SE2 0 0.0 0.0 0.0",
+            "VERTEX_SE2 1 1.0 0.0 0.0",
+            "VERTEX_SWITCH 2 1.0",
+            "EDGE_SE2_SWITCHABLE 0 1 2 1.0 0.0 0.0 1.0 0.0 0.0 1.0 0.0 1.0",
+            "EDGE_SWITCH_PRIOR 2 1.0 1.0",
+        ]
+        .join("\n");
+        let model = G2oParser::parse_string_to_model(&g2o_string).unwrap();
+        assert_eq!(model.edges[0].edge_type, "SwitchableOdometry2D");
+        assert_eq!(model.edges[0].vertices, vec![VariableId(0), VariableId(1), VariableId(2)]);
+        assert_eq!(model.edges[1].edge_type, "SwitchPrior");
+        let model = FactorGraphModel::from(&FactorGraph::from(model));
+        assert_eq!(G2oParser::compose_model_to_string(model).unwrap(), g2o_string);
+    }
+
+    #[test]
+    fn test_bearing_range_round_trip() {
+        let g2o_string = [
+            "VERTEX_SE2 0 0.0 0.0 0.0",
+            "FIX 0",
+            "VERTEX_XY 1 1.0 1.0",
+            "VERTEX_XY 2 1.0 -1.0",
+            "EDGE_BEARING_RANGE_SE2_XY 0 1 0.7853981633974483 1.4142135623730951 100.0 0.0 10.0",
+            "EDGE_BEARING_SE2_XY 0 2 -0.7853981633974483 100.0",
+        ]
+        .join("\n");
+        let model = G2oParser::parse_string_to_model(&g2o_string).unwrap();
+        assert_eq!(model.edges[0].edge_type, "BearingRange2D");
+        assert_eq!(model.edges[0].information_matrix, vec![100.0, 0.0, 0.0, 10.0]);
+        assert_eq!(model.edges[1].edge_type, "Bearing2D");
+        assert_eq!(model.edges[1].information_matrix, vec![100.0]);
+        let model = FactorGraphModel::from(&FactorGraph::from(model));
+        assert_eq!(G2oParser::compose_model_to_string(model).unwrap(), g2o_string);
+    }
+
+    #[test]
+    fn test_position_only_3d_round_trip() {
+        let g2o_string = [
+            "PARAMS_SE3OFFSET 0 0 0 0 0 0 0 1",
+            "VERTEX_SE3:QUAT 0 1.0 2.0 3.0 0.0 0.0 0.0 1.0",
+            "EDGE_SE3_XYZPRIOR 0 0 1.0 2.0 3.5 4.0 0.0 0.0 4.0 0.0 1.0",
+        ]
+        .join("\n");
+        let model = G2oParser::parse_string_to_model(&g2o_string).unwrap();
+        assert_eq!(model.edges[0].edge_type, "PositionOnly3D");
+        assert_eq!(model.edges[0].vertices, vec![VariableId(0)]);
+        assert_eq!(model.edges[0].restriction, vec![1.0, 2.0, 3.5]);
+        let model = FactorGraphModel::from(&FactorGraph::from(model));
+        assert_eq!(G2oParser::compose_model_to_string(model).unwrap(), g2o_string);
+    }
+
+    #[test]
+    fn test_landmark_offset_round_trip() {
+        let g2o_string = [
+            "VERTEX_XY 0 1.0 1.0",
+            "FIX 0",
+            "VERTEX_XY 1 1.5 0.0",
+            "VERTEX_TRACKXYZ 2 1.0 2.0 3.0",
+            "FIX 2",
+            "VERTEX_TRACKXYZ 3 1.0 2.0 4.0",
+            "EDGE_POINTXY 0 1 0.5 -1.0 10.0 0.0 10.0",
+            "EDGE_POINTXYZ 2 3 0.0 0.0 1.0 4.0 0.0 0.0 4.0 0.0 1.0",
+        ]
+        .join("\n");
+        let model = G2oParser::parse_string_to_model(&g2o_string).unwrap();
+        assert_eq!(model.edges[0].edge_type, "LandmarkOffset2D");
+        assert_eq!(model.edges[0].vertices, vec![VariableId(0), VariableId(1)]);
+        assert_eq!(model.edges[1].edge_type, "LandmarkOffset3D");
+        assert_eq!(model.edges[1].restriction, vec![0.0, 0.0, 1.0]);
+        let model = FactorGraphModel::from(&FactorGraph::from(model));
+        assert_eq!(G2oParser::compose_model_to_string(model).unwrap(), g2o_string);
+    }
+
+    #[test]
+    fn test_covariance_round_trip() {
+        let g2o_string = [
+            "VERTEX_SE2 0 0.0 0.0 0.0",
+            "FIX 0",
+            "VERTEX_XY 1 1.0 1.0",
+            "EDGE_SE2_XY 0 1 1.0 1.0 1.0 0.0 1.0",
+            "COV_XY 1 1.0 0.5 2.0",
+        ]
+        .join("\n");
+        let model = G2oParser::parse_string_to_model(&g2o_string).unwrap();
+        assert_eq!(model.covariances[&VariableId(1)], vec![1.0, 0.5, 0.5, 2.0]);
+        assert_eq!(G2oParser::compose_model_to_string(model).unwrap(), g2o_string);
+    }
+
+    #[test]
+    fn test_timestamp_round_trip() {
+        let g2o_string = [
+            "VERTEX_SE2 0 0.0 0.0 0.0",
+            "VERTEX_SE2 1 1.0 0.0 0.0",
+            "TIMESTAMP 0 0.0",
+            "TIMESTAMP 1 0.02",
+        ]
+        .join("\n");
+        let model = G2oParser::parse_string_to_model(&g2o_string).unwrap();
+        assert_eq!(model.timestamps[&VariableId(1)], 0.02);
+        let factor_graph = FactorGraph::from(model);
+        assert_eq!(factor_graph.timestamp(VariableId(1)), Some(0.02));
+        let model = FactorGraphModel::from(&factor_graph);
+        assert_eq!(G2oParser::compose_model_to_string(model).unwrap(), g2o_string);
+    }
+
+    #[test]
+    fn test_unit_round_trip() {
+        let g2o_string = ["UNIT millimeters", "VERTEX_SE2 0 1000.0 0.0 0.0"].join("\n");
+        let model = G2oParser::parse_string_to_model(&g2o_string).unwrap();
+        assert_eq!(model.unit, Some(LengthUnit::Millimeters));
+        assert_eq!(G2oParser::compose_model_to_string(model).unwrap(), g2o_string);
+    }
+
+    #[test]
+    fn test_compose_file_with_covariances() {
+        let factor_graph = G2oParser::parse_file("data_files/full_demos/all_2d_types.g2o").unwrap();
+        let file_path = std::env::temp_dir().join(format!("gs-rs-covariances-{}.g2o", std::process::id()));
+        let file_path = file_path.to_str().unwrap();
+        G2oParser::compose_file_with_covariances(&factor_graph, file_path).unwrap();
+        let model = G2oParser::parse_file_to_model(file_path).unwrap();
+        fs::remove_file(file_path).unwrap();
+        let expected = factor_graph.marginal_covariances().unwrap();
+        assert_eq!(model.covariances.len(), expected.len());
+        for (id, covariance) in expected {
+            let parsed = DMatrix::from_row_slice(covariance.nrows(), covariance.ncols(), &model.covariances[&id]);
+            assert!((parsed - covariance).norm() < 1e-9);
+        }
+    }
+
+    #[test]
+    fn test_invalid_lines_are_errors() {
+        let cases = [
+            ("VERTEX_SE2 0 0.0 0.0", "Wrong number of tokens in line 1: Expected: 5; Actual: 4"),
+            ("VERTEX_XY 0 1.0 1.0\nEDGE_SE2_XY 0 1 1.0", "Wrong number of tokens in line 2"),
+            ("VERTEX_XY -1 1.0 1.0", "correct data type in line 1: -1"),
+            ("VERTEX_XY 0 1.0 one", "correct data type in line 1: one"),
+            ("COV_XY 0 1.0 0.0 x", "correct data type in line 1: x"),
+            ("\nFIX", "Empty set of fixed vertices in line 2"),
+            ("VERTEX_PLANE 0 1.0", "Unknown keyword at beginning of line 1: VERTEX_PLANE"),
+        ];
+        for (g2o_string, expected) in cases.iter() {
+            let error = G2oParser::parse_string_to_model(g2o_string).unwrap_err();
+            assert!(error.contains(expected), "{}", error);
+        }
+        let error = G2oParser::parse_string("VERTEX_XY 0 1.0 1.0\nEDGE_SE2_XY 0 1 1.0 1.0 1.0 0.0 1.0").unwrap_err();
+        assert!(error.contains("Invalid edge in the model"), "{}", error);
+    }
+
+    #[test]
+    fn test_edges_between_wrong_variable_types_are_errors() {
+        let cases = [
+            "VERTEX_XY 0 0.0 0.0\nVERTEX_XY 1 1.0 0.0\nEDGE_SE2 0 1 1.0 0.0 0.0 1.0 0.0 0.0 1.0 0.0 1.0",
+            "VERTEX_SE2 0 0.0 0.0 0.0\nEDGE_SWITCH_PRIOR 0 1.0 1.0",
+            "VERTEX_SE2 0 0.0 0.0 0.0\nVERTEX_XY 1 1.0 0.0\nEDGE_SE2_XY 1 0 1.0 0.0 1.0 0.0 1.0",
+            "VERTEX_SE3:QUAT 0 0.0 0.0 0.0 0.0 0.0 0.0 1.0\nVERTEX_XY 1 1.0 0.0\nEDGE_BEARING_SE2_XY 0 1 0.5 100.0",
+        ];
+        for g2o_string in cases.iter() {
+            let error = G2oParser::parse_string(g2o_string).unwrap_err();
+            assert!(error.contains("Invalid edge in the model"), "{}", error);
+            assert!(error.contains("cannot connect the variables"), "{}", error);
+        }
+    }
+
+    #[test]
+    fn test_truncated_and_mutated_input() {
+        for file in &["all_2d_types.g2o", "all_3d_types.g2o"] {
+            let g2o_string = fs::read_to_string(format!("data_files/full_demos/{}", file)).unwrap();
+            assert!(G2oParser::parse_string(&g2o_string).is_ok());
+            assert!(g2o_string.is_ascii());
+            for i in 0..g2o_string.len() {
+                let (prefix, suffix) = (&g2o_string[..i], &g2o_string[i + 1..]);
+                let _ = G2oParser::parse_string(prefix);
+                for replacement in &["", " ", "\n", "-", "e9", "NaN", "9999999999999999999999"] {
+                    let _ = G2oParser::parse_string(&format!("{}{}{}", prefix, replacement, suffix));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_compose_file_with_summary() {
+        let factor_graph = G2oParser::parse_file("data_files/full_demos/all_2d_types.g2o").unwrap();
+        let chi2_before = crate::optimizer::total_chi2(&factor_graph);
+        let config = crate::optimizer::config::OptimizerConfig::default();
+        crate::optimizer::optimize_with_config(&factor_graph, &config);
+        let file_path = std::env::temp_dir().join(format!("gs-rs-summary-{}.g2o", std::process::id()));
+        let file_path = file_path.to_str().unwrap();
+        G2oParser::compose_file_with_summary(&factor_graph, Some(chi2_before), Some(&config), file_path).unwrap();
+        let file_string = fs::read_to_string(file_path).unwrap();
+        let model = G2oParser::parse_file_to_model(file_path).unwrap();
+        fs::remove_file(file_path).unwrap();
+        assert!(file_string.starts_with(&format!("# SUMMARY version {}\n", env!("CARGO_PKG_VERSION"))));
+
+        let summary = model.summary.clone().unwrap();
+        let expected = ModelSummary::new(&model);
+        assert_eq!((summary.vertices, summary.fixed_vertices), (expected.vertices, expected.fixed_vertices));
+        assert_eq!((summary.edges, &summary.edge_types), (expected.edges, &expected.edge_types));
+        assert_eq!(summary.chi2_before, Some(chi2_before));
+        assert_eq!(summary.chi2_after, Some(crate::optimizer::total_chi2(&factor_graph)));
+        assert_eq!(summary.solver_settings, config.settings());
+        assert_eq!(summary.solver_settings["maxIterations"], "100");
+        let mut without_summary = G2oParser::parse_string_to_model(&file_string).unwrap();
+        without_summary.summary = None;
+        assert_eq!(without_summary, FactorGraphModel::from(&factor_graph));
+    }
+
+    #[test]
+    fn test_unrecognized_summary_lines_are_comments() {
+        for line in &["# SUMMARY of the run", "# SUMMARY vertices", "# SUMMARY edges many"] {
+            let model = G2oParser::parse_string_to_model(&format!("{}\nVERTEX_XY 0 1.0 1.0", line)).unwrap();
+            assert_eq!(model.summary, None);
+            assert_eq!(model.vertices.len(), 1);
+        }
+        let model = G2oParser::parse_string_to_model("# SUMMARY of the run\n# SUMMARY edges 2").unwrap();
+        assert_eq!(model.summary.unwrap().edges, 2);
+    }
 }
@@ -23,7 +23,10 @@ pub struct JsonParser;
 impl Parser for JsonParser {
     fn parse_string_to_model(s: &str) -> Result<FactorGraphModel, String> {
         match serde_json::from_str::<FactorGraphModel>(s) {
-            Ok(model) => Ok(model),
+            Ok(model) => {
+                model.check_dimensions().map_err(|e| e.to_string())?;
+                Ok(model)
+            }
             Err(e) => Err(format!("Parsing to FactorGraphModel unsuccessful: {}", e)),
         }
     }
@@ -39,7 +42,8 @@ impl Parser for JsonParser {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use crate::parser::model::{Edge, Vertex};
+    use crate::factor_graph::variable::VariableId;
+    use crate::parser::model::{DimensionMismatch, Edge, EdgeComponent, Vertex};
     use log::info;
     use log::LevelFilter;
     use std::collections::BTreeSet;
@@ -63,20 +67,21 @@ mod tests {
         );
     }
 
+    #[allow(clippy::approx_constant)]
     fn get_2d_model() -> FactorGraphModel {
         let vertices = vec![
             Vertex {
-                id: 0,
+                id: VariableId(0),
                 vertex_type: String::from("Vehicle2D"),
                 content: vec![1.0, 0.0, 1.57],
             },
             Vertex {
-                id: 1,
+                id: VariableId(1),
                 vertex_type: String::from("Vehicle2D"),
                 content: vec![0.0, 1.0, 3.14],
             },
             Vertex {
-                id: 2,
+                id: VariableId(2),
                 vertex_type: String::from("Landmark2D"),
                 content: vec![1.5, 2.0],
             },
@@ -84,29 +89,30 @@ mod tests {
         let edges = vec![
             Edge {
                 edge_type: String::from("Odometry2D"),
-                vertices: vec![0, 1],
+                vertices: vec![VariableId(0), VariableId(1)],
                 restriction: vec![1.0, 1.5, 1.57],
                 information_matrix: vec![1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0],
             },
             Edge {
                 edge_type: String::from("Observation2D"),
-                vertices: vec![0, 2],
+                vertices: vec![VariableId(0), VariableId(2)],
                 restriction: vec![0.0, -1.0],
                 information_matrix: vec![1.0, 0.0, 0.0, 1.0],
             },
             Edge {
                 edge_type: String::from("Position2D"),
-                vertices: vec![1],
+                vertices: vec![VariableId(1)],
                 restriction: vec![0.0, 1.0, 3.13],
                 information_matrix: vec![10.0, 0.0, 0.0, 0.0, 10.0, 0.0, 0.0, 0.0, 1.0],
             },
         ];
         let mut fixed_vertices = BTreeSet::new();
-        fixed_vertices.insert(0);
+        fixed_vertices.insert(VariableId(0));
         FactorGraphModel {
             vertices,
             edges,
             fixed_vertices,
+            ..Default::default()
         }
     }
 
@@ -130,17 +136,17 @@ mod tests {
     fn get_3d_model() -> FactorGraphModel {
         let vertices = vec![
             Vertex {
-                id: 0,
+                id: VariableId(0),
                 vertex_type: String::from("Vehicle3D"),
                 content: vec![18.7381, 0.000000274428, 8.2287, 0.0, 0.0, 0.0, 1.0],
             },
             Vertex {
-                id: 1,
+                id: VariableId(1),
                 vertex_type: String::from("Vehicle3D"),
                 content: vec![19.0477, 2.34636, 8.2319, -0.139007, 0.0806488, 0.14657, 0.976059],
             },
             Vertex {
-                id: 2,
+                id: VariableId(2),
                 vertex_type: String::from("Landmark3D"),
                 content: vec![18.2645, 4.24943, 15.2057],
             },
@@ -149,29 +155,30 @@ mod tests {
         let edges = vec![
             Edge {
                 edge_type: String::from("Odometry3D"),
-                vertices: vec![0, 1],
+                vertices: vec![VariableId(0), VariableId(1)],
                 restriction: vec![0.309576, 2.34636, 0.00315914, -0.139007, 0.0806488, 0.14657, 0.976059],
                 information_matrix: vec![1.0, 0.000000000000000000962965, 0.000000000000000000962965, 0.0000000588441, -0.0000000203096, 0.00000000340337, 0.000000000000000000962965, 1.0, 0.000000000000000000962965, 0.0000000588441, -0.0000000203096, 0.00000000340337, 0.000000000000000000962965, 0.000000000000000000962965, 1.0, 0.0000000588441, -0.0000000203096, 0.00000000340337, 0.0000000588441, 0.0000000588441, 0.0000000588441, 4108.72, -34.2982, 884.091, -0.0000000203096, -0.0000000203096, -0.0000000203096, -34.2982, 3951.5, 40.2084, 0.00000000340337, 0.00000000340337, 0.00000000340337, 884.091, 40.2084, 4100.08],
             },
             Edge {
                 edge_type: String::from("Position3D"),
-                vertices: vec![1],
+                vertices: vec![VariableId(1)],
                 restriction: vec![0.309576, 2.34636, 0.00315914, -0.139007, 0.0806488, 0.14657, 0.976059],
                 information_matrix: vec![1.0, 0.000000000000000000962965, 0.000000000000000000962965, 0.0000000588441, -0.0000000203096, 0.00000000340337, 0.000000000000000000962965, 1.0, 0.000000000000000000962965, 0.0000000588441, -0.0000000203096, 0.00000000340337, 0.000000000000000000962965, 0.000000000000000000962965, 1.0, 0.0000000588441, -0.0000000203096, 0.00000000340337, 0.0000000588441, 0.0000000588441, 0.0000000588441, 4108.72, -34.2982, 884.091, -0.0000000203096, -0.0000000203096, -0.0000000203096, -34.2982, 3951.5, 40.2084, 0.00000000340337, 0.00000000340337, 0.00000000340337, 884.091, 40.2084, 4100.08],
             },
             Edge {
                 edge_type: String::from("Observation3D"),
-                vertices: vec![1, 2],
+                vertices: vec![VariableId(1), VariableId(2)],
                 restriction: vec![-0.034127, 2.24359, -0.503123],
                 information_matrix: vec![3934.45, -9.14727, 63.005, -9.14727, 3998.72, 10.7561, 63.005, 10.7561, 3909.38],
             }
         ];
         let mut fixed_vertices = BTreeSet::new();
-        fixed_vertices.insert(0);
+        fixed_vertices.insert(VariableId(0));
         FactorGraphModel {
             vertices,
             edges,
             fixed_vertices,
+            ..Default::default()
         }
     }
 
@@ -191,4 +198,183 @@ mod tests {
         let expected_string = fs::read_to_string("data_files/full_demos/all_3d_types.json").unwrap();
         assert_eq!(&composed_string, &expected_string);
     }
+
+    #[test]
+    fn test_max_mixture_composition_keeps_all_components() {
+        use crate::factor_graph::factor::{FactorType, MaxMixture, MixtureComponent};
+        use crate::factor_graph::FactorGraph;
+
+        init();
+        let mut factor_graph: FactorGraph = JsonParser::parse_file("data_files/full_demos/all_2d_types.json").unwrap();
+        let mixture = MaxMixture {
+            factor_type: Box::new(FactorType::Position2D),
+            weight: 0.7,
+            alternatives: vec![MixtureComponent {
+                weight: 0.3,
+                constraint: vec![1.0, 2.0, 0.5],
+                information_matrix: vec![2.0, 0.0, 0.0, 0.0, 2.0, 0.0, 0.0, 0.0, 2.0].into(),
+            }],
+        };
+        let mixture_type = FactorType::MaxMixture(mixture);
+        factor_graph
+            .add_factor(
+                VariableId(0),
+                VariableId(0),
+                mixture_type.clone(),
+                vec![5.0, 5.0, 0.0],
+                vec![1.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0].into(),
+            )
+            .unwrap();
+
+        let composed_string = JsonParser::compose_model_to_string((&factor_graph).into()).unwrap();
+        let parsed_model = JsonParser::parse_string_to_model(&composed_string).unwrap();
+        let mixture_edge = parsed_model
+            .edges
+            .iter()
+            .find(|edge| edge.edge_type == "MaxMixture:Position2D")
+            .unwrap();
+        assert_eq!(
+            mixture_edge.restriction,
+            vec![2.0, 0.7, 0.3, 5.0, 5.0, 0.0, 1.0, 2.0, 0.5]
+        );
+        let parsed_graph: FactorGraph = parsed_model.into();
+        let parsed_factor = parsed_graph
+            .factors_between(VariableId(0), VariableId(0))
+            .into_iter()
+            .filter_map(|id| parsed_graph.get_factor(id))
+            .find(|factor| factor.factor_type == mixture_type)
+            .unwrap();
+        assert_eq!(parsed_factor.constraint, vec![5.0, 5.0, 0.0]);
+    }
+
+    #[test]
+    fn test_covariance_round_trip() {
+        let mut model = get_2d_model();
+        model.covariances.insert(VariableId(1), vec![1.0, 0.5, 0.0, 0.5, 2.0, 0.0, 0.0, 0.0, 3.0]);
+        let composed_string = JsonParser::compose_model_to_string(model).unwrap();
+        assert!(composed_string.contains("\"covariances\""));
+        let parsed_model = JsonParser::parse_string_to_model(&composed_string).unwrap();
+        assert_eq!(
+            parsed_model.covariances[&VariableId(1)],
+            vec![1.0, 0.5, 0.0, 0.5, 2.0, 0.0, 0.0, 0.0, 3.0]
+        );
+        assert!(!JsonParser::compose_model_to_string(get_2d_model())
+            .unwrap()
+            .contains("\"covariances\""));
+    }
+
+    #[test]
+    fn test_invalid_models_are_errors() {
+        let mut model = get_2d_model();
+        model.vertices[1].content.pop();
+        let error = JsonParser::parse_string(&JsonParser::compose_model_to_string(model).unwrap()).unwrap_err();
+        assert_eq!(
+            error,
+            "Invalid vertex 1 in the model: Vehicle2D with 2 values instead of 3"
+        );
+
+        let mut model = get_2d_model();
+        model.vertices[2].id = VariableId(1);
+        let error = JsonParser::parse_string(&JsonParser::compose_model_to_string(model).unwrap()).unwrap_err();
+        assert_eq!(error, "Invalid vertex in the model: ID 1 is not unique");
+
+        let mut model = get_2d_model();
+        model.edges[0].vertices.pop();
+        let error = JsonParser::parse_string(&JsonParser::compose_model_to_string(model).unwrap()).unwrap_err();
+        assert_eq!(
+            error,
+            "Invalid edge 0 of type Odometry2D with the vertices [0] in the model: expected 2 vertices, found 1"
+        );
+
+        let mut model = get_2d_model();
+        model.edges.push(Edge {
+            edge_type: String::from("DensePrior"),
+            vertices: vec![VariableId(2)],
+            restriction: vec![1.5, 2.0],
+            information_matrix: vec![1.0, 0.0, 1.0],
+        });
+        let error = JsonParser::parse_string(&JsonParser::compose_model_to_string(model).unwrap()).unwrap_err();
+        assert_eq!(
+            error,
+            "Invalid edge in the model: DensePrior with 3 values of the information matrix, which is not a square \
+             number"
+        );
+    }
+
+    #[test]
+    fn test_truncated_and_mutated_input() {
+        let json_string = fs::read_to_string("data_files/full_demos/all_3d_types.json").unwrap();
+        assert!(JsonParser::parse_string(&json_string).is_ok());
+        assert!(json_string.is_ascii());
+        for i in 0..json_string.len() {
+            let (prefix, suffix) = (&json_string[..i], &json_string[i + 1..]);
+            let _ = JsonParser::parse_string(prefix);
+            for replacement in &["", "[", "]", "{", "0", "-1", "\"Vehicle2D\""] {
+                let _ = JsonParser::parse_string(&format!("{}{}{}", prefix, replacement, suffix));
+            }
+        }
+    }
+
+    #[test]
+    fn test_dimension_mismatch() {
+        let mut model = get_2d_model();
+        assert_eq!(model.check_dimensions(), Ok(()));
+        model.edges[0].restriction.pop();
+        assert_eq!(
+            model.check_dimensions(),
+            Err(DimensionMismatch {
+                edge_index: 0,
+                edge_type: String::from("Odometry2D"),
+                vertices: vec![VariableId(0), VariableId(1)],
+                component: EdgeComponent::Restriction,
+                expected: 3,
+                actual: 2,
+            })
+        );
+        let composed_string = JsonParser::compose_model_to_string(model).unwrap();
+        assert_eq!(
+            JsonParser::parse_string_to_model(&composed_string),
+            Err(String::from(
+                "Invalid edge 0 of type Odometry2D with the vertices [0, 1] in the model: expected 3 restriction \
+                 values, found 2"
+            ))
+        );
+
+        let mut model = get_2d_model();
+        model.edges[1].information_matrix.push(0.0);
+        model.edges[2].vertices.push(VariableId(0));
+        let error = model.check_dimensions().unwrap_err();
+        assert_eq!((error.edge_index, error.component), (1, EdgeComponent::InformationMatrix));
+        model.edges[1].information_matrix.pop();
+        assert_eq!(model.check_dimensions().unwrap_err().component, EdgeComponent::Vertices);
+
+        let mut model = get_2d_model();
+        model.edges.push(Edge {
+            edge_type: String::from("MaxMixture:Odometry2D"),
+            vertices: vec![VariableId(0), VariableId(1)],
+            restriction: vec![2.0, 0.5, 0.5, 1.0, 0.0, 1.0, 0.0],
+            information_matrix: vec![1.0, 0.0, 0.0, 1.0, 1.0, 0.0, 0.0, 1.0],
+        });
+        let error = model.check_dimensions().unwrap_err();
+        assert_eq!((error.component, error.expected, error.actual), (EdgeComponent::Restriction, 9, 7));
+        model.edges[3].restriction = vec![2.0, 0.5, 0.5, 1.0, 0.0, 0.0, 1.0, 0.0, 0.0];
+        assert_eq!(model.check_dimensions().unwrap_err().component, EdgeComponent::InformationMatrix);
+        model.edges[3].information_matrix = (0..18).map(|i| if i % 9 % 4 == 0 { 1.0 } else { 0.0 }).collect();
+        assert_eq!(model.check_dimensions(), Ok(()));
+    }
+
+    #[test]
+    fn test_point_to_plane_dimensions_depend_on_points() {
+        let mut model = get_2d_model();
+        model.edges.truncate(1);
+        model.edges[0].edge_type = String::from("PointToPlane3D");
+        model.edges[0].restriction = vec![1.0, 2.0, 3.0, 4.0, 5.0, 6.0];
+        model.edges[0].information_matrix = vec![1.0, 0.0, 0.0, 1.0];
+        assert_eq!(model.check_dimensions(), Ok(()));
+        model.edges[0].restriction.pop();
+        assert_eq!(model.check_dimensions().unwrap_err().component, EdgeComponent::Restriction);
+        model.edges[0].restriction.clear();
+        let error = model.check_dimensions().unwrap_err();
+        assert_eq!((error.component, error.expected), (EdgeComponent::Restriction, 3));
+    }
 }
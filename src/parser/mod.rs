@@ -11,23 +11,74 @@
 //
 
 //! Conversion between factor graph structures and files.
+//!
+//! Without the feature "std", only the [model](model/index.html) and the fixed-point
+//! [reader](fixed_point/reader/index.html) are available.
 
+#[cfg(feature = "std")]
+use crate::factor_graph::limits::ResourceLimits;
+#[cfg(feature = "std")]
 use crate::factor_graph::FactorGraph;
-use crate::parser::model::FactorGraphModel;
+#[cfg(feature = "std")]
+use crate::parser::model::converter::convert_model;
+#[cfg(feature = "std")]
+use crate::optimizer::config::OptimizerConfig;
+#[cfg(feature = "std")]
+use crate::optimizer::total_chi2;
+#[cfg(feature = "std")]
+use crate::parser::model::{FactorGraphModel, ModelSummary};
+#[cfg(feature = "std")]
+use std::collections::BTreeMap;
+#[cfg(feature = "std")]
 use std::fs;
 
+pub mod fixed_point;
+#[cfg(feature = "std")]
 pub mod g2o;
+#[cfg(feature = "std")]
 pub mod json;
 pub mod model;
+#[cfg(feature = "std")]
+pub mod raster;
+#[cfg(feature = "std")]
+pub mod registry;
 
 /// Trait to be used by all parsers with the basic file parsing and composition functionality.
+///
+/// Invalid input is reported as an error instead of panicking, so that untrusted files and strings can be parsed.
+#[cfg(feature = "std")]
 pub trait Parser {
     /// Tries to parse a file at the given path to the internal factor graph representation.
+    ///
+    /// Edges whose dimensions do not match their type are reported as errors, see
+    /// [check_dimensions](model/struct.FactorGraphModel.html#method.check_dimensions).
     fn parse_file(file_path: &str) -> Result<FactorGraph, String> {
-        match Self::parse_file_to_model(file_path) {
-            Ok(model) => Ok(model.into()),
-            Err(s) => Err(s),
+        convert_model(&Self::parse_file_to_model(file_path)?, &BTreeMap::new())
+    }
+
+    /// Tries to parse a string to the internal factor graph representation.
+    fn parse_string(s: &str) -> Result<FactorGraph, String> {
+        convert_model(&Self::parse_string_to_model(s)?, &BTreeMap::new())
+    }
+
+    /// Tries to parse a file at the given path like [parse_file](#method.parse_file), but returns an error instead of
+    /// allocating the factor graph if the model exceeds the given limits, or if the file is larger than the memory
+    /// limit. Invalid models are reported as errors as well.
+    fn parse_file_with_limits(file_path: &str, limits: &ResourceLimits) -> Result<FactorGraph, String> {
+        if let Some(max_memory) = limits.max_memory {
+            let file_size = fs::metadata(file_path)
+                .map_err(|_| format!("File could not be parsed: {}", file_path))?
+                .len();
+            if file_size > max_memory as u64 {
+                return Err(format!(
+                    "The limit of {} for the memory estimate is exceeded by the file size {}",
+                    max_memory, file_size
+                ));
+            }
         }
+        let model = Self::parse_file_to_model(file_path)?;
+        limits.check(&(&model).into())?;
+        convert_model(&model, &BTreeMap::new())
     }
 
     /// Tries to parse a file at the given path to the factor graph model used in the context with files.
@@ -47,6 +98,64 @@ pub trait Parser {
         Self::compose_model_to_file(factor_graph.into(), file_path)
     }
 
+    /// Tries to compose a file at the given path like [compose_file](#method.compose_file), but also embeds the
+    /// marginal covariances of all non-fixed variables at their current estimates, see
+    /// [marginal_covariances](../factor_graph/struct.FactorGraph.html#method.marginal_covariances).
+    fn compose_file_with_covariances(factor_graph: &FactorGraph, file_path: &str) -> Result<(), String> {
+        let mut model: FactorGraphModel = factor_graph.into();
+        model.covariances = factor_graph
+            .marginal_covariances()?
+            .into_iter()
+            .map(|(id, covariance)| (id, covariance.transpose().as_slice().to_owned()))
+            .collect();
+        Self::compose_model_to_file(model, file_path)
+    }
+
+    /// Tries to compose a file at the given path like [compose_file](#method.compose_file), but with a header
+    /// summarizing the factor graph and its optimization, see [ModelSummary](model/struct.ModelSummary.html).
+    ///
+    /// The file is composed in two passes: the summary of the factor graph's model is collected first and then
+    /// serialized before the model. The χ² after the optimization is the one of the current estimates, while the χ²
+    /// before and the settings of the optimization are only known to the caller.
+    ///
+    /// ```
+    /// use gs_rs::examples_gen::triangle_with_loop_closure;
+    /// use gs_rs::factor_graph::FactorGraph;
+    /// use gs_rs::optimizer::config::OptimizerConfig;
+    /// use gs_rs::optimizer::{optimize_with_config, total_chi2};
+    /// use gs_rs::parser::json::JsonParser;
+    /// use gs_rs::parser::Parser;
+    ///
+    /// let factor_graph: FactorGraph = triangle_with_loop_closure().into();
+    /// let chi2_before = total_chi2(&factor_graph);
+    /// let config = OptimizerConfig::default();
+    /// optimize_with_config(&factor_graph, &config);
+    /// let file_path = std::env::temp_dir().join("gs-rs-summary-doctest.json");
+    /// let file_path = file_path.to_str().unwrap();
+    /// JsonParser::compose_file_with_summary(&factor_graph, Some(chi2_before), Some(&config), file_path).unwrap();
+    /// let summary = JsonParser::parse_file_to_model(file_path).unwrap().summary.unwrap();
+    /// assert_eq!((summary.vertices, summary.edges), (3, 3));
+    /// assert_eq!(summary.chi2_before, Some(chi2_before));
+    /// assert_eq!(summary.solver_settings["solver"], "SparseCholesky");
+    /// # std::fs::remove_file(file_path).unwrap();
+    /// ```
+    fn compose_file_with_summary(
+        factor_graph: &FactorGraph,
+        chi2_before: Option<f64>,
+        config: Option<&OptimizerConfig>,
+        file_path: &str,
+    ) -> Result<(), String> {
+        let mut model: FactorGraphModel = factor_graph.into();
+        let mut summary = ModelSummary::new(&model);
+        summary.chi2_before = chi2_before;
+        summary.chi2_after = Some(total_chi2(factor_graph));
+        if let Some(config) = config {
+            summary.solver_settings = config.settings();
+        }
+        model.summary = Some(summary);
+        Self::compose_model_to_file(model, file_path)
+    }
+
     /// Tries to compose a file at the given path containing the factor graph model's serialization.
     fn compose_model_to_file(model: FactorGraphModel, file_path: &str) -> Result<(), String> {
         let s = Self::compose_model_to_string(model)?;
@@ -10,48 +10,112 @@
 // This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
 //
 
-use petgraph::csr::Csr;
-
-use crate::factor_graph::factor::{Factor, FactorType::*};
+use crate::factor_graph::factor::{self, CustomResidual, Factor, FactorType, FactorType::*, MixtureComponent};
 use crate::factor_graph::variable::{
-    FixedType, LandmarkVariable2D, LandmarkVariable3D, Variable, VehicleVariable2D, VehicleVariable3D,
+    AnchorVariable2D, AnchorVariable3D, ExtrinsicVariable2D, ExtrinsicVariable3D, FixedType, ImuBiasVariable,
+    LandmarkVariable2D, LandmarkVariable3D, LineLandmarkVariable2D, OdometryCalibrationVariable2D, PlaneVariable3D,
+    SwitchVariable, Variable, VehicleVariable2D, VehicleVariable3D, VehicleVariableSim3, VelocityVariable3D,
 };
 use crate::factor_graph::FactorGraph;
-use crate::parser::model::{Edge, FactorGraphModel, Vertex};
+use crate::parser::model::{Edge, FactorGraphModel, Vertex, MAX_MIXTURE_PREFIX};
 
-use petgraph::visit::EdgeRef;
-use std::collections::{BTreeSet, HashMap};
-use std::ops::Index;
+use std::collections::BTreeMap;
 
 impl From<FactorGraphModel> for FactorGraph {
     fn from(model: FactorGraphModel) -> Self {
-        let mut factor_graph = FactorGraph {
-            csr: Csr::new(),
-            node_indices: vec![],
-            matrix_dim: 0,
-            custom_to_csr_id_map: HashMap::new(),
-        };
+        match convert_model(&model, &BTreeMap::new()) {
+            Ok(factor_graph) => factor_graph,
+            Err(s) => panic!("{}", s),
+        }
+    }
+}
 
-        model
-            .vertices
-            .iter()
-            .for_each(|v| add_vertex(&mut factor_graph, v, model.fixed_vertices.contains(&v.id)));
+// converts the model into a factor graph, adding edges of types which are not built in as custom factors with the
+// residual of the same name
+//
+// invalid models, e.g. parsed from untrusted input, are reported as errors instead of panicking
+pub(crate) fn convert_model(
+    model: &FactorGraphModel,
+    residuals: &BTreeMap<String, CustomResidual>,
+) -> Result<FactorGraph, String> {
+    model.check_dimensions().map_err(|e| e.to_string())?;
+    if let Some(entry) = model.custom_variables.first() {
+        return Err(format!("Unsupported custom variable type in the model: {}", entry.entry_type));
+    }
+    if let Some(entry) = model.custom_factors.first() {
+        return Err(format!("Unsupported custom factor type in the model: {}", entry.entry_type));
+    }
+    let mut factor_graph = FactorGraph::new();
+    factor_graph.set_unit(model.unit);
 
-        model.edges.iter().for_each(|e| add_edge(&mut factor_graph, e));
+    for vertex in &model.vertices {
+        add_vertex(&mut factor_graph, vertex, model.fixed_vertices.contains(&vertex.id))?;
+    }
+    for (id, time) in &model.timestamps {
+        factor_graph.set_timestamp(*id, Some(*time))?;
+    }
 
-        factor_graph
+    for edge in &model.edges {
+        add_edge(&mut factor_graph, edge, residuals)?;
     }
+
+    Ok(factor_graph)
+}
+
+// returns the index of the target vertex and the factor type of a built-in edge type except "DensePrior"
+pub(crate) fn get_builtin_factor_type(edge_type: &str) -> Option<(usize, FactorType)> {
+    Some(match edge_type {
+        "Position2D" => (0, Position2D),
+        "Odometry2D" => (1, Odometry2D),
+        "Observation2D" => (1, Observation2D),
+        "BearingRange2D" => (1, BearingRange2D),
+        "Bearing2D" => (1, Bearing2D),
+        "Position3D" => (0, Position3D),
+        "PositionOnly3D" => (0, PositionOnly3D),
+        "Heading2D" => (0, Heading2D),
+        "Heading3D" => (0, Heading3D),
+        "Altitude3D" => (0, Altitude3D),
+        "Odometry3D" => (1, Odometry3D),
+        "Observation3D" => (1, Observation3D),
+        "Projection3D" => (1, Projection3D),
+        "StereoProjection3D" => (1, StereoProjection3D),
+        "Range2D" => (1, Range2D),
+        "Range3D" => (1, Range3D),
+        "SwitchableOdometry2D" => (1, SwitchableOdometry2D),
+        "SwitchableOdometry3D" => (1, SwitchableOdometry3D),
+        "SwitchPrior" => (0, SwitchPrior),
+        "ImuPreintegration3D" => (1, ImuPreintegration3D),
+        "ConstantVelocity2D" => (1, ConstantVelocity2D),
+        "ConstantVelocity3D" => (1, ConstantVelocity3D),
+        "OdometrySim3" => (1, OdometrySim3),
+        "PointToPlane3D" => (1, PointToPlane3D),
+        "LineObservation2D" => (1, LineObservation2D),
+        "CalibratedOdometry2D" => (1, CalibratedOdometry2D),
+        "ExtrinsicObservation2D" => (1, ExtrinsicObservation2D),
+        "ExtrinsicObservation3D" => (1, ExtrinsicObservation3D),
+        "AnchoredOdometry2D" => (1, AnchoredOdometry2D),
+        "AnchoredOdometry3D" => (1, AnchoredOdometry3D),
+        "AnchoredObservation2D" => (1, AnchoredObservation2D),
+        "AnchoredObservation3D" => (1, AnchoredObservation3D),
+        "LandmarkOffset2D" => (1, LandmarkOffset2D),
+        "LandmarkOffset3D" => (1, LandmarkOffset3D),
+        "InterpolatedPose2D" => (0, InterpolatedPose2D),
+        "InterpolatedPose3D" => (0, InterpolatedPose3D),
+        "LeverArmPosition2D" => (0, LeverArmPosition2D),
+        "LeverArmPosition3D" => (0, LeverArmPosition3D),
+        _ => return None,
+    })
 }
 
 impl From<&FactorGraph> for FactorGraphModel {
     fn from(factor_graph: &FactorGraph) -> Self {
         let mut model = FactorGraphModel {
-            vertices: vec![],
-            edges: vec![],
-            fixed_vertices: BTreeSet::new(),
+            timestamps: factor_graph.timestamps().clone(),
+            unit: factor_graph.unit(),
+            ..Default::default()
         };
         for node_index in &factor_graph.node_indices {
-            let node = factor_graph.csr.index(*node_index);
+            let node = factor_graph.get_var(*node_index);
             model.vertices.push(Vertex {
                 id: node.get_id(),
                 vertex_type: match node {
@@ -59,27 +123,33 @@ impl From<&FactorGraph> for FactorGraphModel {
                     Variable::Landmark2D(_) => String::from("Landmark2D"),
                     Variable::Vehicle3D(_) => String::from("Vehicle3D"),
                     Variable::Landmark3D(_) => String::from("Landmark3D"),
+                    Variable::Switch(_) => String::from("Switch"),
+                    Variable::Velocity3D(_) => String::from("Velocity3D"),
+                    Variable::ImuBias(_) => String::from("ImuBias"),
+                    Variable::VehicleSim3(_) => String::from("VehicleSim3"),
+                    Variable::Plane3D(_) => String::from("Plane3D"),
+                    Variable::LineLandmark2D(_) => String::from("LineLandmark2D"),
+                    Variable::OdometryCalibration2D(_) => String::from("OdometryCalibration2D"),
+                    Variable::Extrinsic2D(_) => String::from("Extrinsic2D"),
+                    Variable::Extrinsic3D(_) => String::from("Extrinsic3D"),
+                    Variable::Anchor2D(_) => String::from("Anchor2D"),
+                    Variable::Anchor3D(_) => String::from("Anchor3D"),
+                    Variable::UserDefined(v) => String::from(v.state.borrow().name()),
                 },
                 content: node.get_content(),
             });
-            for edge in factor_graph.csr.edges(*node_index) {
-                let factor: &Factor = edge.weight();
+            for edge in factor_graph.adjacency.edges(*node_index) {
+                let factor = factor_graph.materialize_factor(edge.weight());
                 let mut edge_vertices = vec![node.get_id()];
                 if edge.target() != *node_index {
-                    edge_vertices.push(factor_graph.csr.index(edge.target()).get_id());
+                    edge_vertices.push(factor_graph.get_var(edge.target()).get_id());
                 }
+                edge_vertices.extend_from_slice(&factor.additional_variables);
                 model.edges.push(Edge {
-                    edge_type: match factor.factor_type {
-                        Position2D => String::from("Position2D"),
-                        Odometry2D => String::from("Odometry2D"),
-                        Observation2D => String::from("Observation2D"),
-                        Position3D => String::from("Position3D"),
-                        Odometry3D => String::from("Odometry3D"),
-                        Observation3D => String::from("Observation3D"),
-                    },
+                    edge_type: get_edge_type(&factor.factor_type),
                     vertices: edge_vertices,
-                    restriction: factor.constraint.clone(),
-                    information_matrix: factor.information_matrix.content.as_slice().to_owned(),
+                    restriction: get_restriction(&factor),
+                    information_matrix: get_information_matrix(&factor),
                 });
             }
             if node.get_fixed_type() == &FixedType::Fixed {
@@ -90,73 +160,404 @@ impl From<&FactorGraph> for FactorGraphModel {
     }
 }
 
-fn add_edge(factor_graph: &mut FactorGraph, edge: &Edge) {
-    let (target_index, factor_type) = match edge.edge_type.as_str() {
-        "Position2D" => (0, Position2D),
-        "Odometry2D" => (1, Odometry2D),
-        "Observation2D" => (1, Observation2D),
-        "Position3D" => (0, Position3D),
-        "Odometry3D" => (1, Odometry3D),
-        "Observation3D" => (1, Observation3D),
-        other_type => panic!("Unsupported edge type in the model: {}", other_type),
+// max-mixture factors are converted to edges of the type "MaxMixture:" followed by their wrapped type
+fn get_edge_type(factor_type: &FactorType) -> String {
+    String::from(match factor_type {
+        Position2D => "Position2D",
+        Odometry2D => "Odometry2D",
+        Observation2D => "Observation2D",
+        BearingRange2D => "BearingRange2D",
+        Bearing2D => "Bearing2D",
+        Position3D => "Position3D",
+        PositionOnly3D => "PositionOnly3D",
+        Heading2D => "Heading2D",
+        Heading3D => "Heading3D",
+        Altitude3D => "Altitude3D",
+        Odometry3D => "Odometry3D",
+        Observation3D => "Observation3D",
+        Projection3D => "Projection3D",
+        StereoProjection3D => "StereoProjection3D",
+        Range2D => "Range2D",
+        Range3D => "Range3D",
+        SwitchableOdometry2D => "SwitchableOdometry2D",
+        SwitchableOdometry3D => "SwitchableOdometry3D",
+        SwitchPrior => "SwitchPrior",
+        ImuPreintegration3D => "ImuPreintegration3D",
+        ConstantVelocity2D => "ConstantVelocity2D",
+        ConstantVelocity3D => "ConstantVelocity3D",
+        OdometrySim3 => "OdometrySim3",
+        PointToPlane3D => "PointToPlane3D",
+        LineObservation2D => "LineObservation2D",
+        CalibratedOdometry2D => "CalibratedOdometry2D",
+        ExtrinsicObservation2D => "ExtrinsicObservation2D",
+        ExtrinsicObservation3D => "ExtrinsicObservation3D",
+        AnchoredOdometry2D => "AnchoredOdometry2D",
+        AnchoredOdometry3D => "AnchoredOdometry3D",
+        AnchoredObservation2D => "AnchoredObservation2D",
+        AnchoredObservation3D => "AnchoredObservation3D",
+        LandmarkOffset2D => "LandmarkOffset2D",
+        LandmarkOffset3D => "LandmarkOffset3D",
+        InterpolatedPose2D => "InterpolatedPose2D",
+        InterpolatedPose3D => "InterpolatedPose3D",
+        LeverArmPosition2D => "LeverArmPosition2D",
+        LeverArmPosition3D => "LeverArmPosition3D",
+        MaxMixture(mixture) => return format!("{}{}", MAX_MIXTURE_PREFIX, get_edge_type(&mixture.factor_type)),
+        DensePrior => "DensePrior",
+        Custom(residual) => residual.name(),
+        UserDefined(custom) => custom.name(),
+    })
+}
+
+// max-mixture factors list the number of their components and the components' weights before their constraints
+fn get_restriction(factor: &Factor) -> Vec<f64> {
+    match &factor.factor_type {
+        MaxMixture(mixture) => {
+            let mut restriction = vec![(mixture.alternatives.len() + 1) as f64, mixture.weight];
+            restriction.extend(mixture.alternatives.iter().map(|component| component.weight));
+            restriction.extend_from_slice(&factor.constraint);
+            for component in &mixture.alternatives {
+                restriction.extend_from_slice(&component.constraint);
+            }
+            restriction
+        }
+        _ => factor.constraint.clone(),
+    }
+}
+
+// max-mixture factors list the information matrices of all their components
+fn get_information_matrix(factor: &Factor) -> Vec<f64> {
+    let mut information_matrix = factor.information_matrix.content.as_slice().to_owned();
+    if let MaxMixture(mixture) = &factor.factor_type {
+        for component in &mixture.alternatives {
+            information_matrix.extend_from_slice(component.information_matrix.content.as_slice());
+        }
+    }
+    information_matrix
+}
+
+fn add_edge(
+    factor_graph: &mut FactorGraph,
+    edge: &Edge,
+    residuals: &BTreeMap<String, CustomResidual>,
+) -> Result<(), String> {
+    if let Some(component_type) = edge.edge_type.strip_prefix(MAX_MIXTURE_PREFIX) {
+        return add_max_mixture_edge(factor_graph, edge, component_type);
+    }
+    let information_len = edge.information_matrix.len();
+    if !is_square_number(information_len) {
+        return Err(format!(
+            "Invalid edge in the model: {} with {} values of the information matrix, which is not a square number",
+            edge.edge_type, information_len
+        ));
+    }
+    if edge.edge_type == "DensePrior" {
+        return factor_graph
+            .add_dense_prior(
+                &edge.vertices,
+                edge.restriction.to_vec(),
+                edge.information_matrix.to_vec().into(),
+            )
+            .map(|_| ())
+            .map_err(|s| format!("Invalid edge in the model: {}", s));
+    }
+    let (target_index, factor_type) = match get_builtin_factor_type(&edge.edge_type) {
+        Some(builtin) => builtin,
+        // custom edges list their source, their target unless it is the source, and their additional vertices
+        None => match residuals.get(&edge.edge_type) {
+            Some(residual) if !edge.vertices.is_empty() => (edge.vertices.len().min(2) - 1, Custom(residual.clone())),
+            Some(_) => {
+                return Err(format!(
+                    "Invalid edge in the model: {} without vertices",
+                    edge.edge_type
+                ))
+            }
+            None => return Err(format!("Unsupported edge type in the model: {}", edge.edge_type)),
+        },
     };
-    factor_graph.csr.add_edge(
-        factor_graph.custom_to_csr_id_map[&edge.vertices[0]],
-        factor_graph.custom_to_csr_id_map[&edge.vertices[target_index]],
-        Factor {
+    if edge.vertices.len() <= target_index {
+        return Err(format!(
+            "Invalid edge in the model: {} with {} vertices",
+            edge.edge_type,
+            edge.vertices.len()
+        ));
+    }
+    factor_graph
+        .add_factor_with_additional_variables(
+            edge.vertices[0],
+            edge.vertices[target_index],
+            edge.vertices[target_index + 1..].to_vec(),
             factor_type,
-            constraint: edge.restriction.to_vec(),
-            information_matrix: edge.information_matrix.to_vec().into(),
-        },
-    );
+            edge.restriction.to_vec(),
+            edge.information_matrix.to_vec().into(),
+        )
+        .map(|_| ())
+        .map_err(|s| format!("Invalid edge in the model: {}", s))
+}
+
+// adds a max-mixture factor, whose edge lists the components as composed by get_restriction and
+// get_information_matrix
+fn add_max_mixture_edge(factor_graph: &mut FactorGraph, edge: &Edge, component_type: &str) -> Result<(), String> {
+    let (target_index, factor_type) = match get_builtin_factor_type(component_type) {
+        Some(builtin) => builtin,
+        None => return Err(format!("Unsupported edge type in the model: {}", edge.edge_type)),
+    };
+    let count = edge.restriction.first().map_or(0.0, |count| *count) as usize;
+    if count == 0
+        || edge.restriction.len() <= count + 1
+        || edge.information_matrix.is_empty()
+        || !(edge.restriction.len() - count - 1).is_multiple_of(count)
+        || !edge.information_matrix.len().is_multiple_of(count)
+        || !is_square_number(edge.information_matrix.len() / count)
+        || edge.vertices.len() <= target_index
+    {
+        return Err(format!(
+            "Invalid edge in the model: {} with inconsistent components",
+            edge.edge_type
+        ));
+    }
+    let weights = &edge.restriction[1..=count];
+    let mut constraints = edge.restriction[count + 1..].chunks((edge.restriction.len() - count - 1) / count);
+    let mut information_matrices = edge.information_matrix.chunks(edge.information_matrix.len() / count);
+    let (constraint, information_matrix) = (constraints.next().unwrap(), information_matrices.next().unwrap());
+    let mixture = factor::MaxMixture {
+        factor_type: Box::new(factor_type),
+        weight: weights[0],
+        alternatives: weights[1..]
+            .iter()
+            .zip(constraints.zip(information_matrices))
+            .map(|(weight, (constraint, information_matrix))| MixtureComponent {
+                weight: *weight,
+                constraint: constraint.to_vec(),
+                information_matrix: information_matrix.to_vec().into(),
+            })
+            .collect(),
+    };
+    factor_graph
+        .add_factor_with_additional_variables(
+            edge.vertices[0],
+            edge.vertices[target_index],
+            edge.vertices[target_index + 1..].to_vec(),
+            MaxMixture(mixture),
+            constraint.to_vec(),
+            information_matrix.to_vec().into(),
+        )
+        .map(|_| ())
+        .map_err(|s| format!("Invalid edge in the model: {}", s))
+}
+
+fn is_square_number(n: usize) -> bool {
+    (0..).take_while(|root| root * root <= n).any(|root| root * root == n)
 }
 
-fn add_vertex(factor_graph: &mut FactorGraph, vertex: &Vertex, fixed: bool) {
+fn add_vertex(factor_graph: &mut FactorGraph, vertex: &Vertex, fixed: bool) -> Result<(), String> {
+    let content_len = match vertex.vertex_type.as_str() {
+        "Vehicle2D" => 3,
+        "Landmark2D" => 2,
+        "Vehicle3D" => 7,
+        "Landmark3D" => 3,
+        "Switch" => 1,
+        "Velocity3D" => 3,
+        "ImuBias" => 6,
+        "VehicleSim3" => 8,
+        "Plane3D" => 4,
+        "LineLandmark2D" => 2,
+        "OdometryCalibration2D" => 3,
+        "Extrinsic2D" => 3,
+        "Extrinsic3D" => 7,
+        "Anchor2D" => 3,
+        "Anchor3D" => 7,
+        other_type => return Err(format!("Unsupported vertex type in the model: {}", other_type)),
+    };
+    if vertex.content.len() != content_len {
+        return Err(format!(
+            "Invalid vertex {} in the model: {} with {} values instead of {}",
+            vertex.id,
+            vertex.vertex_type,
+            vertex.content.len(),
+            content_len
+        ));
+    }
+    if factor_graph.custom_to_csr_id_map.contains_key(&vertex.id) {
+        return Err(format!("Invalid vertex in the model: ID {} is not unique", vertex.id));
+    }
     match vertex.vertex_type.as_str() {
         "Vehicle2D" => factor_graph
             .node_indices
-            .push(factor_graph.csr.add_node(Variable::Vehicle2D(VehicleVariable2D::new(
-                vertex.id,
-                vertex.content[0],
-                vertex.content[1],
-                vertex.content[2],
-                add_var_to_matrix(&mut factor_graph.matrix_dim, 3, fixed),
-            )))),
+            .push(
+                factor_graph
+                    .adjacency
+                    .add_node(Variable::Vehicle2D(VehicleVariable2D::new(
+                        vertex.id,
+                        vertex.content[0],
+                        vertex.content[1],
+                        vertex.content[2],
+                        add_var_to_matrix(&mut factor_graph.matrix_dim, 3, fixed),
+                    ))),
+            ),
         "Landmark2D" => factor_graph
             .node_indices
-            .push(factor_graph.csr.add_node(Variable::Landmark2D(LandmarkVariable2D::new(
-                vertex.id,
-                vertex.content[0],
-                vertex.content[1],
-                add_var_to_matrix(&mut factor_graph.matrix_dim, 2, fixed),
-            )))),
+            .push(
+                factor_graph
+                    .adjacency
+                    .add_node(Variable::Landmark2D(LandmarkVariable2D::new(
+                        vertex.id,
+                        vertex.content[0],
+                        vertex.content[1],
+                        add_var_to_matrix(&mut factor_graph.matrix_dim, 2, fixed),
+                    ))),
+            ),
         "Vehicle3D" => factor_graph
             .node_indices
-            .push(factor_graph.csr.add_node(Variable::Vehicle3D(VehicleVariable3D::new(
+            .push(
+                factor_graph
+                    .adjacency
+                    .add_node(Variable::Vehicle3D(VehicleVariable3D::new(
+                        vertex.id,
+                        vertex.content[0],
+                        vertex.content[1],
+                        vertex.content[2],
+                        vertex.content[3],
+                        vertex.content[4],
+                        vertex.content[5],
+                        vertex.content[6],
+                        add_var_to_matrix(&mut factor_graph.matrix_dim, 6, fixed),
+                    ))),
+            ),
+        "Landmark3D" => factor_graph
+            .node_indices
+            .push(
+                factor_graph
+                    .adjacency
+                    .add_node(Variable::Landmark3D(LandmarkVariable3D::new(
+                        vertex.id,
+                        vertex.content[0],
+                        vertex.content[1],
+                        vertex.content[2],
+                        add_var_to_matrix(&mut factor_graph.matrix_dim, 3, fixed),
+                    ))),
+            ),
+        "Switch" => factor_graph
+            .node_indices
+            .push(factor_graph.adjacency.add_node(Variable::Switch(SwitchVariable::new(
                 vertex.id,
                 vertex.content[0],
-                vertex.content[1],
-                vertex.content[2],
-                vertex.content[3],
-                vertex.content[4],
-                vertex.content[5],
-                vertex.content[6],
-                add_var_to_matrix(&mut factor_graph.matrix_dim, 6, fixed),
+                add_var_to_matrix(&mut factor_graph.matrix_dim, 1, fixed),
             )))),
-        "Landmark3D" => factor_graph
+        "Velocity3D" => factor_graph
+            .node_indices
+            .push(
+                factor_graph
+                    .adjacency
+                    .add_node(Variable::Velocity3D(VelocityVariable3D::new(
+                        vertex.id,
+                        vertex.content[0],
+                        vertex.content[1],
+                        vertex.content[2],
+                        add_var_to_matrix(&mut factor_graph.matrix_dim, 3, fixed),
+                    ))),
+            ),
+        "ImuBias" => factor_graph
             .node_indices
-            .push(factor_graph.csr.add_node(Variable::Landmark3D(LandmarkVariable3D::new(
+            .push(factor_graph.adjacency.add_node(Variable::ImuBias(ImuBiasVariable::new(
                 vertex.id,
-                vertex.content[0],
-                vertex.content[1],
-                vertex.content[2],
-                add_var_to_matrix(&mut factor_graph.matrix_dim, 3, fixed),
+                [
+                    vertex.content[0],
+                    vertex.content[1],
+                    vertex.content[2],
+                    vertex.content[3],
+                    vertex.content[4],
+                    vertex.content[5],
+                ],
+                add_var_to_matrix(&mut factor_graph.matrix_dim, 6, fixed),
             )))),
-        other_type => panic!("Unsupported vertex type in the model: {}", other_type),
+        "VehicleSim3" => {
+            let c = &vertex.content;
+            factor_graph
+                .node_indices
+                .push(factor_graph.adjacency.add_node(Variable::VehicleSim3(VehicleVariableSim3::new(
+                    vertex.id,
+                    [c[0], c[1], c[2], c[3], c[4], c[5], c[6], c[7]],
+                    add_var_to_matrix(&mut factor_graph.matrix_dim, 7, fixed),
+                ))))
+        }
+        "Plane3D" => {
+            let c = &vertex.content;
+            factor_graph
+                .node_indices
+                .push(factor_graph.adjacency.add_node(Variable::Plane3D(PlaneVariable3D::new(
+                    vertex.id,
+                    [c[0], c[1], c[2], c[3]],
+                    add_var_to_matrix(&mut factor_graph.matrix_dim, 3, fixed),
+                ))))
+        }
+        "LineLandmark2D" => factor_graph
+            .node_indices
+            .push(
+                factor_graph
+                    .adjacency
+                    .add_node(Variable::LineLandmark2D(LineLandmarkVariable2D::new(
+                        vertex.id,
+                        vertex.content[0],
+                        vertex.content[1],
+                        add_var_to_matrix(&mut factor_graph.matrix_dim, 2, fixed),
+                    ))),
+            ),
+        "OdometryCalibration2D" => {
+            let c = &vertex.content;
+            factor_graph.node_indices.push(factor_graph.adjacency.add_node(Variable::OdometryCalibration2D(
+                OdometryCalibrationVariable2D::new(
+                    vertex.id,
+                    [c[0], c[1], c[2]],
+                    add_var_to_matrix(&mut factor_graph.matrix_dim, 3, fixed),
+                ),
+            )))
+        }
+        "Extrinsic2D" => {
+            let c = &vertex.content;
+            factor_graph
+                .node_indices
+                .push(factor_graph.adjacency.add_node(Variable::Extrinsic2D(ExtrinsicVariable2D::new(
+                    vertex.id,
+                    [c[0], c[1], c[2]],
+                    add_var_to_matrix(&mut factor_graph.matrix_dim, 3, fixed),
+                ))))
+        }
+        "Extrinsic3D" => {
+            let c = &vertex.content;
+            factor_graph
+                .node_indices
+                .push(factor_graph.adjacency.add_node(Variable::Extrinsic3D(ExtrinsicVariable3D::new(
+                    vertex.id,
+                    [c[0], c[1], c[2], c[3], c[4], c[5], c[6]],
+                    add_var_to_matrix(&mut factor_graph.matrix_dim, 6, fixed),
+                ))))
+        }
+        "Anchor2D" => {
+            let c = &vertex.content;
+            factor_graph
+                .node_indices
+                .push(factor_graph.adjacency.add_node(Variable::Anchor2D(AnchorVariable2D::new(
+                    vertex.id,
+                    [c[0], c[1], c[2]],
+                    add_var_to_matrix(&mut factor_graph.matrix_dim, 3, fixed),
+                ))))
+        }
+        "Anchor3D" => {
+            let c = &vertex.content;
+            factor_graph
+                .node_indices
+                .push(factor_graph.adjacency.add_node(Variable::Anchor3D(AnchorVariable3D::new(
+                    vertex.id,
+                    [c[0], c[1], c[2], c[3], c[4], c[5], c[6]],
+                    add_var_to_matrix(&mut factor_graph.matrix_dim, 6, fixed),
+                ))))
+        }
+        _ => unreachable!(),
     };
     factor_graph
         .custom_to_csr_id_map
         .insert(vertex.id, *factor_graph.node_indices.last().unwrap());
+    Ok(())
 }
 
 fn add_var_to_matrix(dim: &mut usize, added_dim: usize, fixed: bool) -> FixedType {
@@ -12,29 +12,139 @@
 
 //! Structures and functions for an intermediate step when converting between factor graphs and serialized files.
 
+use crate::factor_graph::units::LengthUnit;
+use crate::factor_graph::variable::VariableId;
+use alloc::collections::{BTreeMap, BTreeSet};
+use alloc::string::String;
+use alloc::vec::Vec;
+use core::fmt;
+use core::fmt::Debug;
 use serde::{Deserialize, Serialize};
-use std::collections::BTreeSet;
-use std::fmt::Debug;
 
-mod converter;
+#[cfg(feature = "std")]
+pub(crate) mod converter;
+
+// prefix of the type of an edge of max-mixture factors, followed by the type of their components
+pub(crate) const MAX_MIXTURE_PREFIX: &str = "MaxMixture:";
 
 /// Structure containing the serializable model of a factor graph.
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Default, Serialize, Deserialize, PartialEq)]
 pub struct FactorGraphModel {
+    /// The summary of the factor graph and its optimization, which is composed as a header of the file, see
+    /// [compose_file_with_summary](../trait.Parser.html#method.compose_file_with_summary). The summary is ignored
+    /// when converting the model into a factor graph.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<ModelSummary>,
     /// All vertices in the factor graph.
     pub vertices: Vec<Vertex>,
     /// All edges in the factor graph.
     pub edges: Vec<Edge>,
     /// The IDs of all fixed vertices, i.e. vertices which will not be changed during optimization.
     #[serde(rename = "fixedVertices")]
-    pub fixed_vertices: BTreeSet<usize>,
+    pub fixed_vertices: BTreeSet<VariableId>,
+    /// The marginal covariances of vertices in row-major order, e.g. after an optimization, see
+    /// [compose_file_with_covariances](../trait.Parser.html#method.compose_file_with_covariances). They refer to the
+    /// corrections applied by the optimizer, i.e. their dimensions are:
+    ///
+    /// "Vehicle2D": 3x3 for (position_x, position_y, rotation)
+    ///
+    /// "Landmark2D": 2x2 for (position_x, position_y)
+    ///
+    /// "Vehicle3D": 6x6 for (position_x, position_y, position_z, quaternion_x, quaternion_y, quaternion_z) of a
+    /// correction applied in the vertex's local frame
+    ///
+    /// "Landmark3D": 3x3 for (position_x, position_y, position_z)
+    ///
+    /// "Switch": 1x1 for (switch_value)
+    ///
+    /// "VehicleSim3": 7x7 for the six entries of "Vehicle3D" and the logarithm of the correction's scale factor
+    ///
+    /// "LineLandmark2D": 2x2 for (angle, distance)
+    ///
+    /// "Plane3D": 3x3 for the rotation of the normal along both vectors of its tangent basis, see
+    /// [PlaneParameterization](../../factor_graph/variable/parameterization/struct.PlaneParameterization.html), and
+    /// the distance
+    ///
+    /// "OdometryCalibration2D": 3x3 for (translation_scale, rotation_scale, rotation_bias)
+    ///
+    /// "Extrinsic2D" and "Extrinsic3D": as for "Vehicle2D" and "Vehicle3D"
+    ///
+    /// "Anchor2D" and "Anchor3D": as for "Vehicle2D" and "Vehicle3D"
+    ///
+    /// The covariances are ignored when converting the model into a factor graph.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub covariances: BTreeMap<VariableId, Vec<f64>>,
+    /// The timestamps of vertices, e.g. of the poses of an odometry, see
+    /// [timestamps](../../factor_graph/timestamps/index.html).
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub timestamps: BTreeMap<VariableId, f64>,
+    /// The declared unit of all positions and translations, see [units](../../factor_graph/units/index.html).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub unit: Option<LengthUnit>,
+    /// Variables of types from other crates, which are converted into vertices by the deserializers registered for
+    /// their types, see [Registry](../registry/struct.Registry.html). The IDs of the resulting vertices may be listed
+    /// in the fixed vertices.
+    #[serde(rename = "customVariables", default, skip_serializing_if = "Vec::is_empty")]
+    pub custom_variables: Vec<CustomEntry>,
+    /// Factors of types from other crates, which are converted into edges by the deserializers registered for their
+    /// types, see [Registry](../registry/struct.Registry.html).
+    #[serde(rename = "customFactors", default, skip_serializing_if = "Vec::is_empty")]
+    pub custom_factors: Vec<CustomEntry>,
+}
+
+/// Structure summarizing a factor graph model and the optimization which resulted in its estimates, so that composed
+/// files are self-describing.
+#[derive(Debug, Clone, Default, Serialize, Deserialize, PartialEq)]
+pub struct ModelSummary {
+    /// The version of gs-rs which composed the file.
+    pub version: String,
+    /// The number of vertices.
+    pub vertices: usize,
+    /// The number of fixed vertices.
+    #[serde(rename = "fixedVertices")]
+    pub fixed_vertices: usize,
+    /// The number of edges.
+    pub edges: usize,
+    /// The number of edges of each type.
+    #[serde(rename = "edgeTypes")]
+    pub edge_types: BTreeMap<String, usize>,
+    /// The total χ² before the optimization.
+    #[serde(rename = "chi2Before", default, skip_serializing_if = "Option::is_none")]
+    pub chi2_before: Option<f64>,
+    /// The total χ² at the composed estimates.
+    #[serde(rename = "chi2After", default, skip_serializing_if = "Option::is_none")]
+    pub chi2_after: Option<f64>,
+    /// The settings of the optimization by name, see
+    /// [OptimizerConfig::settings](../../optimizer/config/struct.OptimizerConfig.html#method.settings).
+    #[serde(rename = "solverSettings", default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub solver_settings: BTreeMap<String, String>,
+}
+
+impl ModelSummary {
+    /// Returns the summary of the model's vertices and edges, i.e. without the χ² and the solver settings.
+    pub fn new(model: &FactorGraphModel) -> Self {
+        let mut edge_types = BTreeMap::new();
+        for edge in &model.edges {
+            *edge_types.entry(edge.edge_type.clone()).or_insert(0) += 1;
+        }
+        ModelSummary {
+            version: String::from(env!("CARGO_PKG_VERSION")),
+            vertices: model.vertices.len(),
+            fixed_vertices: model.fixed_vertices.len(),
+            edges: model.edges.len(),
+            edge_types,
+            chi2_before: None,
+            chi2_after: None,
+            solver_settings: BTreeMap::new(),
+        }
+    }
 }
 
 /// Structure containing a factor graph model's vertex, representing a variable.
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Vertex {
     /// The vertex's ID. Should be unique within the factor graph.
-    pub id: usize,
+    pub id: VariableId,
     /// The vertex's type. Supported types: "Vehicle2D", "Landmark2D"
     #[serde(rename = "type")]
     pub vertex_type: String,
@@ -47,11 +157,32 @@ pub struct Vertex {
     /// Content for "Vehicle3D": vec![position_x, position_y, position_z, quaternion_x, quaternion_y, quaternion_z, quaternion_w]
     ///
     /// Content for "Landmark3D": vec![position_x, position_y, position_z]
+    ///
+    /// Content for "Switch": vec![switch_value]
+    ///
+    /// Content for "Velocity3D": vec![velocity_x, velocity_y, velocity_z]
+    ///
+    /// Content for "ImuBias": vec![acc_bias_x, acc_bias_y, acc_bias_z, gyro_bias_x, gyro_bias_y, gyro_bias_z]
+    ///
+    /// Content for "VehicleSim3": vec![position_x, position_y, position_z, quaternion_x, quaternion_y, quaternion_z, quaternion_w, scale]
+    ///
+    /// Content for "Plane3D": vec![normal_x, normal_y, normal_z, distance] of the points x with normal * x = distance
+    ///
+    /// Content for "LineLandmark2D": vec![angle, distance] of the points x with (cos(angle), sin(angle)) * x = distance
+    ///
+    /// Content for "OdometryCalibration2D": vec![translation_scale, rotation_scale, rotation_bias], see
+    /// [OdometryCalibrationVariable2D](../../factor_graph/variable/struct.OdometryCalibrationVariable2D.html)
+    ///
+    /// Content for "Extrinsic2D" and "Extrinsic3D": as for "Vehicle2D" and "Vehicle3D", of the sensor in the
+    /// vehicle's frame
+    ///
+    /// Content for "Anchor2D" and "Anchor3D": as for "Vehicle2D" and "Vehicle3D", of the session's frame in the world
+    /// frame
     pub content: Vec<f64>,
 }
 
 /// Structure containing a factor graph model's edge, representing a factor.
-#[derive(Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
 pub struct Edge {
     /// The edge's type. Supported types: "Position2D", "Odometry2D", "Observation2D"
     #[serde(rename = "type")]
@@ -62,14 +193,68 @@ pub struct Edge {
     ///
     /// Content for "Odometry2D": vec![Vehicle2D_vertex, Vehicle2D_vertex]
     ///
-    /// Content for "Observation2D": vec![Vehicle2D_vertex, Landmark2D_vertex]
+    /// Content for "Observation2D", "BearingRange2D" and "Bearing2D": vec![Vehicle2D_vertex, Landmark2D_vertex]
     ///
-    /// Content for "Position3D": vec![Vehicle3D_vertex]
+    /// Content for "Position3D" and "PositionOnly3D": vec![Vehicle3D_vertex]
+    ///
+    /// Content for "Heading2D": vec![Vehicle2D_vertex]
+    ///
+    /// Content for "Heading3D" and "Altitude3D": vec![Vehicle3D_vertex]
     ///
     /// Content for "Odometry3D": vec![Vehicle3D_vertex, Vehicle3D_vertex]
     ///
-    /// Content for "Observation3D": vec![Vehicle3D_vertex, Landmark3D_vertex]
-    pub vertices: Vec<usize>,
+    /// Content for "Observation3D", "Projection3D" and "StereoProjection3D": vec![Vehicle3D_vertex, Landmark3D_vertex]
+    ///
+    /// Content for "Range2D": vec![Vehicle2D_vertex, Landmark2D_vertex]
+    ///
+    /// Content for "Range3D": vec![Vehicle3D_vertex, Landmark3D_vertex]
+    ///
+    /// Content for "SwitchableOdometry2D": vec![Vehicle2D_vertex, Vehicle2D_vertex, Switch_vertex]
+    ///
+    /// Content for "SwitchableOdometry3D": vec![Vehicle3D_vertex, Vehicle3D_vertex, Switch_vertex]
+    ///
+    /// Content for "SwitchPrior": vec![Switch_vertex]
+    ///
+    /// Content for "ImuPreintegration3D": vec![Vehicle3D_vertex, Vehicle3D_vertex, Velocity3D_vertex, Velocity3D_vertex, ImuBias_vertex, ImuBias_vertex]
+    ///
+    /// Content for "ConstantVelocity2D": vec![previous_Vehicle2D_vertex, next_Vehicle2D_vertex, middle_Vehicle2D_vertex]
+    ///
+    /// Content for "ConstantVelocity3D": vec![previous_Vehicle3D_vertex, next_Vehicle3D_vertex, middle_Vehicle3D_vertex]
+    ///
+    /// Content for "OdometrySim3": vec![VehicleSim3_vertex, VehicleSim3_vertex]
+    ///
+    /// Content for "PointToPlane3D": vec![Vehicle3D_vertex, Plane3D_vertex]
+    ///
+    /// Content for "LineObservation2D": vec![Vehicle2D_vertex, LineLandmark2D_vertex]
+    ///
+    /// Content for "CalibratedOdometry2D": vec![Vehicle2D_vertex, Vehicle2D_vertex, OdometryCalibration2D_vertex]
+    ///
+    /// Content for "ExtrinsicObservation2D": vec![Vehicle2D_vertex, Landmark2D_vertex, Extrinsic2D_vertex]
+    ///
+    /// Content for "ExtrinsicObservation3D": vec![Vehicle3D_vertex, Landmark3D_vertex, Extrinsic3D_vertex]
+    ///
+    /// Content for "AnchoredOdometry2D": vec![Vehicle2D_vertex, Vehicle2D_vertex, source_Anchor2D_vertex, target_Anchor2D_vertex]
+    ///
+    /// Content for "AnchoredOdometry3D": vec![Vehicle3D_vertex, Vehicle3D_vertex, source_Anchor3D_vertex, target_Anchor3D_vertex]
+    ///
+    /// Content for "AnchoredObservation2D": vec![Vehicle2D_vertex, Landmark2D_vertex, vehicle_Anchor2D_vertex, landmark_Anchor2D_vertex]
+    ///
+    /// Content for "AnchoredObservation3D": vec![Vehicle3D_vertex, Landmark3D_vertex, vehicle_Anchor3D_vertex, landmark_Anchor3D_vertex]
+    ///
+    /// Content for "LandmarkOffset2D": vec![Landmark2D_vertex, Landmark2D_vertex]
+    ///
+    /// Content for "LandmarkOffset3D": vec![Landmark3D_vertex, Landmark3D_vertex]
+    ///
+    /// Content for "InterpolatedPose2D": vec![Vehicle2D_vertex_before, Vehicle2D_vertex_after]
+    ///
+    /// Content for "InterpolatedPose3D": vec![Vehicle3D_vertex_before, Vehicle3D_vertex_after]
+    ///
+    /// Content for "LeverArmPosition2D": vec![Vehicle2D_vertex]
+    ///
+    /// Content for "LeverArmPosition3D": vec![Vehicle3D_vertex]
+    ///
+    /// Content for "MaxMixture:" followed by a type: as for the wrapped type
+    pub vertices: Vec<VariableId>,
     /// The edge's restriction, representing a measurement. The structure depends on the edge's type:
     ///
     /// Content for "Position2D": vec![position_x, position_y, rotation]
@@ -78,13 +263,232 @@ pub struct Edge {
     ///
     /// Content for "Observation2D": vec![delta_position_x, delta_position_y]
     ///
+    /// Content for "BearingRange2D": vec![bearing, range]
+    ///
+    /// Content for "Bearing2D": vec![bearing]
+    ///
     /// Content for "Position3D": vec![position_x, position_y, position_z, quaternion_x, quaternion_y, quaternion_z, quaternion_w]
     ///
     /// Content for "Odometry3D": vec![delta_position_x, delta_position_y, delta_position_z, quaternion_x, quaternion_y, quaternion_z, quaternion_w]
     ///
+    /// Content for "PositionOnly3D": vec![position_x, position_y, position_z]
+    ///
+    /// Content for "Heading2D": vec![rotation]
+    ///
+    /// Content for "Heading3D": vec![yaw] of the vehicle's x axis around the z axis
+    ///
+    /// Content for "Altitude3D": vec![position_z]
+    ///
     /// Content for "Observation3D": vec![delta_position_x, delta_position_y, delta_position_z]
+    ///
+    /// Content for "Projection3D": vec![pixel_x, pixel_y, focal_length_x, focal_length_y, principal_point_x, principal_point_y]
+    ///
+    /// Content for "StereoProjection3D": vec![left_pixel_x, right_pixel_x, pixel_y, focal_length_x, focal_length_y, principal_point_x, principal_point_y, baseline]
+    ///
+    /// Content for "Range2D" and "Range3D": vec![range]
+    ///
+    /// Content for "SwitchableOdometry2D" and "SwitchableOdometry3D": as for "Odometry2D" and "Odometry3D"
+    ///
+    /// Content for "SwitchPrior": vec![prior_value]
+    ///
+    /// Content for "ImuPreintegration3D": as the constraint of the factor type ImuPreintegration3D
+    ///
+    /// Content for "ConstantVelocity2D" and "ConstantVelocity3D": vec![]
+    ///
+    /// Content for "OdometrySim3": vec![delta_position_x, delta_position_y, delta_position_z, quaternion_x, quaternion_y, quaternion_z, quaternion_w, delta_scale]
+    ///
+    /// Content for "PointToPlane3D": vec![point_x, point_y, point_z] of each point on the plane in the vehicle's frame
+    ///
+    /// Content for "LineObservation2D": vec![angle, distance] of the line in the vehicle's frame
+    ///
+    /// Content for "CalibratedOdometry2D": as for "Odometry2D", before the calibration
+    ///
+    /// Content for "ExtrinsicObservation2D" and "ExtrinsicObservation3D": as for "Observation2D" and "Observation3D",
+    /// in the sensor's frame
+    ///
+    /// Content for "AnchoredOdometry2D", "AnchoredOdometry3D", "AnchoredObservation2D" and "AnchoredObservation3D": as
+    /// for "Odometry2D", "Odometry3D", "Observation2D" and "Observation3D"
+    ///
+    /// Content for "LandmarkOffset2D": vec![delta_position_x, delta_position_y] of the target in the world frame
+    ///
+    /// Content for "LandmarkOffset3D": vec![delta_position_x, delta_position_y, delta_position_z] of the target in the
+    /// world frame
+    ///
+    /// Content for "InterpolatedPose2D" and "InterpolatedPose3D": as for "Position2D" and "Position3D", followed by
+    /// the fraction of the time between both vertices which passed at the measurement
+    ///
+    /// Content for "LeverArmPosition2D": vec![position_x, position_y, lever_arm_x, lever_arm_y] with the antenna's
+    /// position in the world frame and the lever arm in the vehicle's frame
+    ///
+    /// Content for "LeverArmPosition3D": vec![position_x, position_y, position_z, lever_arm_x, lever_arm_y, lever_arm_z]
+    ///
+    /// Content for "MaxMixture:" followed by a type: vec![component_count, weight_1, ..., weight_n, restriction_1..., ..., restriction_n...]
     pub restriction: Vec<f64>,
     /// The edge's entire information matrix. It is expected to be symmetric, hence having identical row- and column-major representations.
+    /// A "MaxMixture:" edge lists the information matrices of all its components one after another.
     #[serde(rename = "informationMatrix")]
     pub information_matrix: Vec<f64>,
 }
+
+/// Structure containing a factor graph model's custom variable or factor, whose payload has a structure defined by
+/// the crate which contributes its type.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct CustomEntry {
+    /// The entry's type, which selects the registered deserializer of its payload.
+    #[serde(rename = "type")]
+    pub entry_type: String,
+    /// The entry's payload, which may be any JSON value.
+    pub payload: serde_json::Value,
+}
+
+/// The part of an edge whose dimension does not match the edge's type, see
+/// [DimensionMismatch](struct.DimensionMismatch.html).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EdgeComponent {
+    /// The number of vertices.
+    Vertices,
+    /// The length of the restriction.
+    Restriction,
+    /// The number of values of the information matrix, i.e. the square of its dimension.
+    InformationMatrix,
+}
+
+/// Error identifying an edge whose vertices, restriction or information matrix do not have the dimension required by
+/// the edge's type, see [check_dimensions](struct.FactorGraphModel.html#method.check_dimensions).
+#[derive(Debug, Clone, PartialEq)]
+pub struct DimensionMismatch {
+    /// The index of the edge in the model's edges.
+    pub edge_index: usize,
+    /// The edge's type.
+    pub edge_type: String,
+    /// The IDs of the edge's vertices.
+    pub vertices: Vec<VariableId>,
+    /// The part of the edge with the wrong dimension.
+    pub component: EdgeComponent,
+    /// The dimension required by the edge's type.
+    pub expected: usize,
+    /// The edge's dimension.
+    pub actual: usize,
+}
+
+impl fmt::Display for DimensionMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let component = match self.component {
+            EdgeComponent::Vertices => "vertices",
+            EdgeComponent::Restriction => "restriction values",
+            EdgeComponent::InformationMatrix => "information matrix values",
+        };
+        write!(
+            f,
+            "Invalid edge {} of type {} with the vertices {:?} in the model: expected {} {}, found {}",
+            self.edge_index,
+            self.edge_type,
+            self.vertices.iter().map(|id| id.0).collect::<Vec<_>>(),
+            self.expected,
+            component,
+            self.actual
+        )
+    }
+}
+
+impl FactorGraphModel {
+    /// Returns an error for the first edge of a built-in type whose number of vertices, length of the restriction or
+    /// number of values of the information matrix does not match its type, e.g. an "Odometry2D" edge with a
+    /// restriction of length 2, which would otherwise only fail during the optimization.
+    ///
+    /// Edges of the type "DensePrior" and of custom types are not checked, since their dimensions depend on their
+    /// vertices. "MaxMixture:" edges are checked against the type and the number of their components.
+    pub fn check_dimensions(&self) -> Result<(), DimensionMismatch> {
+        for (edge_index, edge) in self.edges.iter().enumerate() {
+            let (vertex_count, restriction_len, information_len) = match get_expected_lengths(edge) {
+                Some(lengths) => lengths,
+                None => continue,
+            };
+            let checks = [
+                (EdgeComponent::Vertices, vertex_count, edge.vertices.len()),
+                (EdgeComponent::Restriction, restriction_len, edge.restriction.len()),
+                (
+                    EdgeComponent::InformationMatrix,
+                    information_len,
+                    edge.information_matrix.len(),
+                ),
+            ];
+            let mismatch = checks.iter().find(|(_, expected, actual)| expected != actual);
+            if let Some((component, expected, actual)) = mismatch {
+                return Err(DimensionMismatch {
+                    edge_index,
+                    edge_type: edge.edge_type.clone(),
+                    vertices: edge.vertices.clone(),
+                    component: *component,
+                    expected: *expected,
+                    actual: *actual,
+                });
+            }
+        }
+        Ok(())
+    }
+}
+
+// returns the number of vertices, the length of the restriction and the number of values of the information matrix
+// of an edge of a built-in type except "DensePrior", also if it is wrapped by a "MaxMixture:" edge
+fn get_expected_lengths(edge: &Edge) -> Option<(usize, usize, usize)> {
+    match edge.edge_type.strip_prefix(MAX_MIXTURE_PREFIX) {
+        Some(component_type) => {
+            // the restriction starts with the number of components, which cannot exceed its length if it is valid
+            let count = (edge.restriction.first().map_or(0.0, |count| *count) as usize).min(edge.restriction.len());
+            let component_len = edge.restriction.len().saturating_sub(1 + count) / count.max(1);
+            let (vertex_count, restriction_len, information_dim) =
+                get_builtin_dimensions(component_type, component_len)?;
+            Some((
+                vertex_count,
+                1 + count * (1 + restriction_len),
+                count * information_dim * information_dim,
+            ))
+        }
+        None => {
+            let (vertex_count, restriction_len, information_dim) =
+                get_builtin_dimensions(&edge.edge_type, edge.restriction.len())?;
+            Some((vertex_count, restriction_len, information_dim * information_dim))
+        }
+    }
+}
+
+// returns the number of vertices, the length of the restriction and the dimension of the information matrix of a
+// built-in edge type except "DensePrior", given the length of the restriction for types with a variable number of
+// measurements
+fn get_builtin_dimensions(edge_type: &str, restriction_len: usize) -> Option<(usize, usize, usize)> {
+    Some(match edge_type {
+        "Position2D" => (1, 3, 3),
+        "Odometry2D" => (2, 3, 3),
+        "Observation2D" | "BearingRange2D" | "LineObservation2D" | "LandmarkOffset2D" => (2, 2, 2),
+        "Bearing2D" | "Range2D" | "Range3D" => (2, 1, 1),
+        "Position3D" => (1, 7, 6),
+        "PositionOnly3D" => (1, 3, 3),
+        "Heading2D" | "Heading3D" | "Altitude3D" => (1, 1, 1),
+        "Odometry3D" => (2, 7, 6),
+        "Observation3D" | "LandmarkOffset3D" => (2, 3, 3),
+        "Projection3D" => (2, 6, 2),
+        "StereoProjection3D" => (2, 8, 3),
+        "SwitchableOdometry2D" | "CalibratedOdometry2D" | "ExtrinsicObservation3D" => (3, 3, 3),
+        "ExtrinsicObservation2D" => (3, 2, 2),
+        "SwitchableOdometry3D" => (3, 7, 6),
+        "InterpolatedPose2D" => (2, 4, 3),
+        "InterpolatedPose3D" => (2, 8, 6),
+        "LeverArmPosition2D" => (1, 4, 2),
+        "LeverArmPosition3D" => (1, 6, 3),
+        "AnchoredOdometry2D" | "AnchoredObservation3D" => (4, 3, 3),
+        "AnchoredOdometry3D" => (4, 7, 6),
+        "AnchoredObservation2D" => (4, 2, 2),
+        "SwitchPrior" => (1, 1, 1),
+        "ImuPreintegration3D" => (6, 65, 15),
+        "ConstantVelocity2D" => (3, 0, 3),
+        "ConstantVelocity3D" => (3, 0, 6),
+        "OdometrySim3" => (2, 8, 7),
+        "PointToPlane3D" => {
+            // at least one point, whose distance is the error of each point
+            let points = (restriction_len / 3).max(1);
+            (2, 3 * points, points)
+        }
+        _ => return None,
+    })
+}
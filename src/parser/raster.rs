@@ -0,0 +1,346 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Export of optimized maps to elevation or density rasters, e.g. for GIS and occupancy-grid tooling.
+//!
+//! The gridded points are the landmarks of the factor graph, or its vehicle poses if it does not contain any
+//! landmarks. Their x and y coordinates determine the cell, so the raster is a top-down view of the map:
+//! * the density of a cell is the number of points in it.
+//! * the elevation of a cell is the mean z coordinate of the points in it, which requires a 3D graph. Cells without
+//!   points have no elevation, i.e. NaN.
+//!
+//! Rasters can be composed as binary PGM (P5) images, where the values are scaled linearly from their minimum to
+//! 255, or as uncompressed single-strip GeoTIFF images with one 32-bit float sample per pixel. The GeoTIFF's model
+//! tie point and pixel scale place the raster in the factor graph's frame, which is declared as user-defined since
+//! factor graphs are not georeferenced.
+
+use crate::factor_graph::FactorGraph;
+use crate::parser::model::FactorGraphModel;
+use std::convert::TryFrom;
+use std::fs;
+
+/// The value gridded into the cells of a raster.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RasterMode {
+    /// The number of points in each cell.
+    Density,
+    /// The mean z coordinate of the points in each cell.
+    Elevation,
+}
+
+/// Exporter for elevation and density rasters.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RasterExporter {
+    /// The side length of the square cells, in the unit of the positions.
+    pub cell_size: f64,
+    /// The value gridded into the cells.
+    pub mode: RasterMode,
+}
+
+impl Default for RasterExporter {
+    fn default() -> Self {
+        RasterExporter {
+            cell_size: 1.0,
+            mode: RasterMode::Elevation,
+        }
+    }
+}
+
+/// Grid of values whose first row is the northernmost one, i.e. the one with the largest y coordinates.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Raster {
+    /// The number of columns.
+    pub width: usize,
+    /// The number of rows.
+    pub height: usize,
+    /// The side length of the square cells.
+    pub cell_size: f64,
+    /// The x and y coordinates of the raster's upper left corner.
+    pub origin: [f64; 2],
+    /// The values in row-major order, NaN for cells without an elevation.
+    pub values: Vec<f32>,
+}
+
+impl RasterExporter {
+    /// Tries to compose a PGM file at the given path containing the factor graph's raster.
+    pub fn compose_pgm_file(&self, factor_graph: &FactorGraph, file_path: &str) -> Result<(), String> {
+        let bytes = self.compose_model_to_raster(&factor_graph.into())?.to_pgm_bytes();
+        fs::write(file_path, bytes).map_err(|_| format!("File could not be written to: {}", file_path))
+    }
+
+    /// Tries to compose a GeoTIFF file at the given path containing the factor graph's raster.
+    pub fn compose_geotiff_file(&self, factor_graph: &FactorGraph, file_path: &str) -> Result<(), String> {
+        let bytes = self.compose_model_to_raster(&factor_graph.into())?.to_geotiff_bytes()?;
+        fs::write(file_path, bytes).map_err(|_| format!("File could not be written to: {}", file_path))
+    }
+
+    /// Tries to grid the points of the factor graph model.
+    ///
+    /// Fails if the cell size is not positive, if the model does not contain any points or if the elevation is
+    /// requested for 2D points.
+    pub fn compose_model_to_raster(&self, model: &FactorGraphModel) -> Result<Raster, String> {
+        if self.cell_size.is_nan() || self.cell_size <= 0.0 {
+            return Err(format!("Cell size {} is not positive", self.cell_size));
+        }
+        let points = get_points(model, self.mode)?;
+        let min_x = points.iter().map(|p| p[0]).fold(f64::INFINITY, f64::min);
+        let max_x = points.iter().map(|p| p[0]).fold(f64::NEG_INFINITY, f64::max);
+        let min_y = points.iter().map(|p| p[1]).fold(f64::INFINITY, f64::min);
+        let max_y = points.iter().map(|p| p[1]).fold(f64::NEG_INFINITY, f64::max);
+        let width = ((max_x - min_x) / self.cell_size).floor() as usize + 1;
+        let height = ((max_y - min_y) / self.cell_size).floor() as usize + 1;
+
+        let mut sums = vec![0.0; width * height];
+        let mut counts = vec![0usize; width * height];
+        for point in &points {
+            let column = (((point[0] - min_x) / self.cell_size).floor() as usize).min(width - 1);
+            let row = height - 1 - (((point[1] - min_y) / self.cell_size).floor() as usize).min(height - 1);
+            sums[row * width + column] += point[2];
+            counts[row * width + column] += 1;
+        }
+        let values = sums
+            .iter()
+            .zip(&counts)
+            .map(|(sum, count)| match self.mode {
+                RasterMode::Density => *count as f32,
+                RasterMode::Elevation if *count == 0 => f32::NAN,
+                RasterMode::Elevation => (sum / *count as f64) as f32,
+            })
+            .collect();
+        Ok(Raster {
+            width,
+            height,
+            cell_size: self.cell_size,
+            origin: [min_x, min_y + height as f64 * self.cell_size],
+            values,
+        })
+    }
+}
+
+impl Raster {
+    /// Returns the raster as binary PGM image, whose gray values scale linearly from 0 at the minimum to 255 at the
+    /// maximum value. Cells without a value are black.
+    pub fn to_pgm_bytes(&self) -> Vec<u8> {
+        let present = || self.values.iter().filter(|v| !v.is_nan());
+        let min = present().fold(f32::INFINITY, |a, b| a.min(*b));
+        let max = present().fold(f32::NEG_INFINITY, |a, b| a.max(*b));
+        let mut bytes = format!("P5\n{} {}\n255\n", self.width, self.height).into_bytes();
+        bytes.extend(self.values.iter().map(|value| {
+            if value.is_nan() || max <= min {
+                0
+            } else {
+                (255.0 * (value - min) / (max - min)).round() as u8
+            }
+        }));
+        bytes
+    }
+
+    /// Tries to return the raster as little-endian GeoTIFF image with 32-bit float samples.
+    ///
+    /// Fails if the image does not fit into the 32-bit offsets of TIFF.
+    pub fn to_geotiff_bytes(&self) -> Result<Vec<u8>, String> {
+        let pixel_scale = [self.cell_size, self.cell_size, 0.0];
+        let tie_point = [0.0, 0.0, 0.0, self.origin[0], self.origin[1], 0.0];
+        // version 1.1.0 with a user-defined model type and pixels which represent areas
+        let geo_keys: [u16; 12] = [1, 1, 0, 2, 1024, 0, 1, 32767, 1025, 0, 1, 1];
+
+        let entry_count = 15;
+        let pixel_scale_offset = 8 + 2 + 12 * entry_count + 4;
+        let tie_point_offset = pixel_scale_offset + 8 * pixel_scale.len();
+        let geo_keys_offset = tie_point_offset + 8 * tie_point.len();
+        let image_offset = geo_keys_offset + 2 * geo_keys.len();
+        let image_size = 4 * self.values.len();
+        let to_u32 = |value: usize| {
+            u32::try_from(value).map_err(|_| format!("Raster of size {}x{} does not fit TIFF", self.width, self.height))
+        };
+        to_u32(image_offset + image_size)?;
+
+        let mut bytes = b"II".to_vec();
+        bytes.extend_from_slice(&42u16.to_le_bytes());
+        bytes.extend_from_slice(&8u32.to_le_bytes());
+        bytes.extend_from_slice(&(entry_count as u16).to_le_bytes());
+        let mut entry = |tag: u16, field_type: u16, count: usize, value: u32| {
+            bytes.extend_from_slice(&tag.to_le_bytes());
+            bytes.extend_from_slice(&field_type.to_le_bytes());
+            bytes.extend_from_slice(&(count as u32).to_le_bytes());
+            bytes.extend_from_slice(&value.to_le_bytes());
+        };
+        // SHORT values are stored in the lower bytes of the value field, which are the first ones in little-endian
+        entry(256, LONG, 1, to_u32(self.width)?);
+        entry(257, LONG, 1, to_u32(self.height)?);
+        entry(258, SHORT, 1, 32);
+        entry(259, SHORT, 1, 1);
+        entry(262, SHORT, 1, 1);
+        entry(273, LONG, 1, image_offset as u32);
+        entry(277, SHORT, 1, 1);
+        entry(278, LONG, 1, to_u32(self.height)?);
+        entry(279, LONG, 1, image_size as u32);
+        entry(284, SHORT, 1, 1);
+        entry(339, SHORT, 1, 3);
+        entry(33550, DOUBLE, pixel_scale.len(), pixel_scale_offset as u32);
+        entry(33922, DOUBLE, tie_point.len(), tie_point_offset as u32);
+        entry(34735, SHORT, geo_keys.len(), geo_keys_offset as u32);
+        entry(42113, ASCII, 4, u32::from_le_bytes(*b"nan\0"));
+        bytes.extend_from_slice(&0u32.to_le_bytes());
+
+        pixel_scale
+            .iter()
+            .chain(&tie_point)
+            .for_each(|value| bytes.extend_from_slice(&value.to_le_bytes()));
+        geo_keys
+            .iter()
+            .for_each(|value| bytes.extend_from_slice(&value.to_le_bytes()));
+        self.values
+            .iter()
+            .for_each(|value| bytes.extend_from_slice(&value.to_le_bytes()));
+        Ok(bytes)
+    }
+}
+
+const ASCII: u16 = 2;
+const SHORT: u16 = 3;
+const LONG: u16 = 4;
+const DOUBLE: u16 = 12;
+
+// returns the x, y and z coordinates of the landmarks, or of the vehicle poses if there are no landmarks
+fn get_points(model: &FactorGraphModel, mode: RasterMode) -> Result<Vec<[f64; 3]>, String> {
+    let has_landmarks = model
+        .vertices
+        .iter()
+        .any(|v| v.vertex_type == "Landmark2D" || v.vertex_type == "Landmark3D");
+    let points: Vec<_> = model
+        .vertices
+        .iter()
+        .filter(|v| match v.vertex_type.as_str() {
+            "Landmark2D" | "Landmark3D" => true,
+            "Vehicle2D" | "Vehicle3D" => !has_landmarks,
+            _ => false,
+        })
+        .map(|v| {
+            match v.vertex_type.as_str() {
+                "Landmark3D" | "Vehicle3D" => Some([v.content[0], v.content[1], v.content[2]]),
+                _ => None,
+            }
+            .ok_or(v)
+        })
+        .collect();
+    if points.is_empty() {
+        return Err(String::from(
+            "Factor graph does not contain any landmarks or vehicle poses to grid",
+        ));
+    }
+    points
+        .into_iter()
+        .map(|point| match (point, mode) {
+            (Ok(point), _) => Ok(point),
+            (Err(v), RasterMode::Density) => Ok([v.content[0], v.content[1], 0.0]),
+            (Err(v), RasterMode::Elevation) => Err(format!(
+                "Elevation of vertex {} of type {} is unknown",
+                v.id.0, v.vertex_type
+            )),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::examples_gen::{single_landmark_with_two_observations, triangle_with_loop_closure};
+    use crate::factor_graph::variable::VariableId;
+    use crate::parser::model::Vertex;
+
+    fn get_landmark(id: usize, content: Vec<f64>) -> Vertex {
+        Vertex {
+            id: VariableId(id),
+            vertex_type: String::from("Landmark3D"),
+            content,
+        }
+    }
+
+    #[test]
+    fn test_elevation_raster() {
+        let mut model = single_landmark_with_two_observations();
+        model.vertices = vec![
+            get_landmark(0, vec![0.2, 0.2, 1.0]),
+            get_landmark(1, vec![0.7, 0.4, 3.0]),
+            get_landmark(2, vec![2.5, 1.5, -2.0]),
+        ];
+        let raster = RasterExporter::default().compose_model_to_raster(&model).unwrap();
+        assert_eq!((raster.width, raster.height), (3, 2));
+        assert_eq!(raster.origin, [0.2, 2.2]);
+        assert!(raster.values[0].is_nan() && raster.values[1].is_nan());
+        assert_eq!(raster.values[2], -2.0);
+        assert_eq!(raster.values[3], 2.0);
+        assert_eq!(&raster.to_pgm_bytes()[11..], &[0, 0, 0, 255, 0, 0]);
+    }
+
+    #[test]
+    fn test_density_raster() {
+        let exporter = RasterExporter {
+            cell_size: 0.5,
+            mode: RasterMode::Density,
+        };
+        // the landmark is gridded instead of the poses
+        let raster = exporter
+            .compose_model_to_raster(&single_landmark_with_two_observations())
+            .unwrap();
+        assert_eq!(raster.values, vec![1.0]);
+
+        let raster = exporter.compose_model_to_raster(&triangle_with_loop_closure()).unwrap();
+        assert_eq!((raster.width, raster.height), (3, 3));
+        assert_eq!(raster.values.iter().sum::<f32>(), 3.0);
+        assert_eq!(raster.values[6], 1.0);
+        let bytes = raster.to_pgm_bytes();
+        assert!(bytes.starts_with(b"P5\n3 3\n255\n"));
+        assert_eq!(bytes.len(), 11 + 9);
+
+        assert!(RasterExporter::default()
+            .compose_model_to_raster(&triangle_with_loop_closure())
+            .is_err());
+        assert!(RasterExporter {
+            cell_size: 0.0,
+            ..exporter
+        }
+        .compose_model_to_raster(&triangle_with_loop_closure())
+        .is_err());
+    }
+
+    #[test]
+    fn test_geotiff_layout() {
+        let raster = Raster {
+            width: 2,
+            height: 1,
+            cell_size: 0.5,
+            origin: [1.0, 2.0],
+            values: vec![4.0, f32::NAN],
+        };
+        let bytes = raster.to_geotiff_bytes().unwrap();
+        assert_eq!(&bytes[..8], &[b'I', b'I', 42, 0, 8, 0, 0, 0]);
+        let read_u32 = |offset: usize| {
+            u32::from_le_bytes([bytes[offset], bytes[offset + 1], bytes[offset + 2], bytes[offset + 3]])
+        };
+        let read_f64 = |offset: usize| {
+            let mut value = [0; 8];
+            value.copy_from_slice(&bytes[offset..offset + 8]);
+            f64::from_le_bytes(value)
+        };
+        // the strip offset is the value of the sixth entry
+        let image_offset = read_u32(10 + 5 * 12 + 8) as usize;
+        assert_eq!(bytes.len(), image_offset + 8);
+        assert_eq!(&bytes[image_offset..image_offset + 4], &4.0f32.to_le_bytes());
+        // the tie point follows the pixel scale
+        let pixel_scale_offset = read_u32(10 + 11 * 12 + 8) as usize;
+        assert_eq!(read_f64(pixel_scale_offset), 0.5);
+        assert_eq!(read_f64(pixel_scale_offset + 24 + 24), 1.0);
+        assert_eq!(read_f64(pixel_scale_offset + 24 + 32), 2.0);
+    }
+}
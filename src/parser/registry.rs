@@ -0,0 +1,449 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Registration of factor types and parsers from other crates, so that extension crates can contribute them
+//! without modifying gs-rs.
+//!
+//! A factor type is contributed as a [CustomResidual](../../factor_graph/factor/struct.CustomResidual.html), which
+//! defines its error and, by automatic differentiation, its Jacobians, i.e. its handler. Once the factors are part
+//! of a factor graph, the optimizer handles them like built-in ones, so the registry is only needed for the
+//! conversion from models and files.
+//!
+//! Variables and factors whose file representation is defined by an extension crate are contributed as
+//! deserializers of the payloads of the model's custom entries, see
+//! [FactorGraphModel](../model/struct.FactorGraphModel.html). They convert each payload into vertices and edges,
+//! which may be of registered factor types, so that files composed afterwards contain these vertices and edges.
+//!
+//! ```
+//! use gs_rs::factor_graph::factor::CustomResidual;
+//! use gs_rs::factor_graph::variable::VariableId;
+//! use gs_rs::optimizer::autodiff::Dual;
+//! use gs_rs::parser::json::JsonParser;
+//! use gs_rs::parser::model::Edge;
+//! use gs_rs::parser::registry::Registry;
+//! use gs_rs::parser::Parser;
+//!
+//! let mut registry = Registry::new();
+//! let residual = CustomResidual::new("Distance2D", |contents: &[Vec<Dual>], constraint: &[f64]| {
+//!     let (dx, dy) = (contents[1][0] - contents[0][0], contents[1][1] - contents[0][1]);
+//!     vec![(dx * dx + dy * dy).sqrt() - constraint[0]]
+//! });
+//! registry.register_factor_type(residual).unwrap();
+//! assert!(registry.factor_type("Distance2D").is_some());
+//!
+//! registry
+//!     .register_factor_deserializer("Distance", |payload| {
+//!         let vertices = serde_json::from_value(payload["vertices"].clone()).map_err(|e| e.to_string())?;
+//!         let distance = payload["distance"].as_f64().ok_or("The distance is missing")?;
+//!         Ok(vec![Edge {
+//!             edge_type: String::from("Distance2D"),
+//!             vertices,
+//!             restriction: vec![distance],
+//!             information_matrix: vec![1.0],
+//!         }])
+//!     })
+//!     .unwrap();
+//! let model = JsonParser::parse_string_to_model(
+//!     r#"{"vertices": [{"id": 0, "type": "Landmark2D", "content": [0.0, 0.0]},
+//!                      {"id": 1, "type": "Landmark2D", "content": [1.5, 0.0]}],
+//!         "fixedVertices": [0],
+//!         "edges": [],
+//!         "customFactors": [{"type": "Distance", "payload": {"vertices": [0, 1], "distance": 2.0}}]}"#,
+//! )
+//! .unwrap();
+//! let factor_graph = registry.convert_model(&model).unwrap();
+//! assert_eq!(factor_graph.factors_between(VariableId(0), VariableId(1)).len(), 1);
+//! ```
+
+use crate::factor_graph::factor::{CustomResidual, FactorType};
+use crate::factor_graph::FactorGraph;
+use crate::parser::g2o::G2oParser;
+use crate::parser::json::JsonParser;
+use crate::parser::model::converter::{convert_model, get_builtin_factor_type};
+use crate::parser::model::{Edge, FactorGraphModel, Vertex};
+use crate::parser::Parser;
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+
+// the composition and parsing functions of a parser
+struct ParserFunctions {
+    parse: fn(&str) -> Result<FactorGraphModel, String>,
+    compose: fn(FactorGraphModel) -> Result<String, String>,
+}
+
+/// Deserializer of the payloads of a custom variable type, which returns the vertices representing a variable.
+pub type VariableDeserializer = fn(&serde_json::Value) -> Result<Vec<Vertex>, String>;
+
+/// Deserializer of the payloads of a custom factor type, which returns the edges representing a factor.
+pub type FactorDeserializer = fn(&serde_json::Value) -> Result<Vec<Edge>, String>;
+
+/// Registry of custom factor types, of deserializers of custom entries by type and of parsers by file extension.
+///
+/// A new registry contains the built-in parsers for the extensions "g2o" and "json".
+pub struct Registry {
+    residuals: BTreeMap<String, CustomResidual>,
+    variable_deserializers: BTreeMap<String, VariableDeserializer>,
+    factor_deserializers: BTreeMap<String, FactorDeserializer>,
+    parsers: BTreeMap<String, ParserFunctions>,
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        let mut registry = Registry {
+            residuals: BTreeMap::new(),
+            variable_deserializers: BTreeMap::new(),
+            factor_deserializers: BTreeMap::new(),
+            parsers: BTreeMap::new(),
+        };
+        registry.register_parser::<G2oParser>("g2o");
+        registry.register_parser::<JsonParser>("json");
+        registry
+    }
+}
+
+impl Registry {
+    /// Returns a registry with the built-in parsers and without custom factor types.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers the custom residual as the factor type of model edges whose type equals its name.
+    ///
+    /// Edges of custom types list their source vertex, their target vertex unless it is the source, and their
+    /// additional vertices, like the edges of models converted from factor graphs.
+    ///
+    /// Returns an error if the name is the one of a built-in or already registered factor type.
+    pub fn register_factor_type(&mut self, residual: CustomResidual) -> Result<(), String> {
+        let name = residual.name();
+        if get_builtin_factor_type(name).is_some() || name == "DensePrior" {
+            return Err(format!("Factor type {} is built in", name));
+        }
+        if self.residuals.contains_key(name) {
+            return Err(format!("Factor type {} is already registered", name));
+        }
+        self.residuals.insert(String::from(name), residual);
+        Ok(())
+    }
+
+    /// Returns the registered factor type with the given name.
+    pub fn factor_type(&self, name: &str) -> Option<FactorType> {
+        self.residuals
+            .get(name)
+            .map(|residual| FactorType::Custom(residual.clone()))
+    }
+
+    /// Registers the deserializer of the payloads of custom variables with the given type.
+    ///
+    /// Returns an error if a deserializer is already registered for the type.
+    pub fn register_variable_deserializer(
+        &mut self,
+        entry_type: &str,
+        deserializer: VariableDeserializer,
+    ) -> Result<(), String> {
+        if self.variable_deserializers.contains_key(entry_type) {
+            return Err(format!("Custom variable type {} is already registered", entry_type));
+        }
+        self.variable_deserializers
+            .insert(String::from(entry_type), deserializer);
+        Ok(())
+    }
+
+    /// Registers the deserializer of the payloads of custom factors with the given type.
+    ///
+    /// Returns an error if a deserializer is already registered for the type.
+    pub fn register_factor_deserializer(
+        &mut self,
+        entry_type: &str,
+        deserializer: FactorDeserializer,
+    ) -> Result<(), String> {
+        if self.factor_deserializers.contains_key(entry_type) {
+            return Err(format!("Custom factor type {} is already registered", entry_type));
+        }
+        self.factor_deserializers.insert(String::from(entry_type), deserializer);
+        Ok(())
+    }
+
+    /// Registers the parser for files with the given extension, replacing the previous parser of the extension.
+    pub fn register_parser<P: Parser>(&mut self, extension: &str) {
+        let functions = ParserFunctions {
+            parse: P::parse_string_to_model,
+            compose: P::compose_model_to_string,
+        };
+        self.parsers.insert(String::from(extension), functions);
+    }
+
+    /// Returns whether a parser is registered for the extension.
+    pub fn has_parser(&self, extension: &str) -> bool {
+        self.parsers.contains_key(extension)
+    }
+
+    /// Tries to convert the model into a factor graph, including edges of the registered factor types and the custom
+    /// entries of the registered types, see [expand_custom_entries](#method.expand_custom_entries).
+    pub fn convert_model(&self, model: &FactorGraphModel) -> Result<FactorGraph, String> {
+        if model.custom_variables.is_empty() && model.custom_factors.is_empty() {
+            return convert_model(model, &self.residuals);
+        }
+        convert_model(&self.expand_custom_entries(model)?, &self.residuals)
+    }
+
+    /// Tries to return a copy of the model whose custom variables and factors are replaced by the vertices and edges
+    /// returned by the deserializers registered for their types.
+    ///
+    /// Returns an error if no deserializer is registered for the type of an entry or if a deserializer fails.
+    pub fn expand_custom_entries(&self, model: &FactorGraphModel) -> Result<FactorGraphModel, String> {
+        let mut vertices = model.vertices.clone();
+        for entry in &model.custom_variables {
+            let deserialize = self
+                .variable_deserializers
+                .get(&entry.entry_type)
+                .ok_or_else(|| format!("Unsupported custom variable type in the model: {}", entry.entry_type))?;
+            let mut entry_vertices = deserialize(&entry.payload).map_err(|e| {
+                format!(
+                    "Invalid custom variable of type {} in the model: {}",
+                    entry.entry_type, e
+                )
+            })?;
+            vertices.append(&mut entry_vertices);
+        }
+        let mut edges = model.edges.clone();
+        for entry in &model.custom_factors {
+            let deserialize = self
+                .factor_deserializers
+                .get(&entry.entry_type)
+                .ok_or_else(|| format!("Unsupported custom factor type in the model: {}", entry.entry_type))?;
+            let mut entry_edges = deserialize(&entry.payload)
+                .map_err(|e| format!("Invalid custom factor of type {} in the model: {}", entry.entry_type, e))?;
+            edges.append(&mut entry_edges);
+        }
+        Ok(FactorGraphModel {
+            vertices,
+            edges,
+            fixed_vertices: model.fixed_vertices.clone(),
+            covariances: model.covariances.clone(),
+            timestamps: model.timestamps.clone(),
+            unit: model.unit,
+            summary: model.summary.clone(),
+            ..Default::default()
+        })
+    }
+
+    /// Tries to parse a file at the given path with the parser registered for its extension and to convert it into
+    /// a factor graph like [convert_model](#method.convert_model).
+    pub fn parse_file(&self, file_path: &str) -> Result<FactorGraph, String> {
+        let parser = self.get_parser(file_path)?;
+        let file_string =
+            fs::read_to_string(file_path).map_err(|_| format!("File could not be parsed: {}", file_path))?;
+        self.convert_model(&(parser.parse)(&file_string)?)
+    }
+
+    /// Tries to compose a file at the given path with the parser registered for its extension.
+    pub fn compose_file(&self, factor_graph: &FactorGraph, file_path: &str) -> Result<(), String> {
+        let parser = self.get_parser(file_path)?;
+        let s = (parser.compose)(factor_graph.into())?;
+        fs::write(file_path, s).map_err(|_| format!("File could not be written to: {}", file_path))
+    }
+
+    fn get_parser(&self, file_path: &str) -> Result<&ParserFunctions, String> {
+        let extension = Path::new(file_path)
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .ok_or_else(|| format!("File has no extension: {}", file_path))?;
+        self.parsers
+            .get(extension)
+            .ok_or_else(|| format!("No parser registered for the extension: {}", extension))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::factor_graph::variable::VariableId;
+    use crate::optimizer::autodiff::Dual;
+    use crate::optimizer::{optimize, total_chi2};
+    use std::env;
+
+    // parser of a format from another crate, here JSON with another extension
+    struct OtherParser;
+
+    impl Parser for OtherParser {
+        fn parse_string_to_model(s: &str) -> Result<FactorGraphModel, String> {
+            JsonParser::parse_string_to_model(s)
+        }
+
+        fn compose_model_to_string(model: FactorGraphModel) -> Result<String, String> {
+            JsonParser::compose_model_to_string(model)
+        }
+    }
+
+    // the distance between two 2D points or poses
+    fn get_range_residual() -> CustomResidual {
+        CustomResidual::new("Distance2D", |contents: &[Vec<Dual>], constraint: &[f64]| {
+            let (dx, dy) = (contents[1][0] - contents[0][0], contents[1][1] - contents[0][1]);
+            vec![(dx * dx + dy * dy).sqrt() - constraint[0]]
+        })
+    }
+
+    fn get_range_model() -> FactorGraphModel {
+        let g2o_string = ["VERTEX_XY 0 0 0", "FIX 0", "VERTEX_SE2 1 3 0.5 0"].join("\n");
+        let mut model = G2oParser::parse_string_to_model(&g2o_string).unwrap();
+        let mut range: FactorGraphModel = JsonParser::parse_string_to_model(
+            r#"{"vertices": [], "fixedVertices": [], "edges": [
+                {"type": "Distance2D", "vertices": [0, 1], "restriction": [2.0], "informationMatrix": [1.0]},
+                {"type": "Position2D", "vertices": [1], "restriction": [0.0, 1.2, 0.0],
+                 "informationMatrix": [0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 1.0]}
+            ]}"#,
+        )
+        .unwrap();
+        model.edges.append(&mut range.edges);
+        model
+    }
+
+    #[test]
+    fn test_register_factor_type() {
+        let mut registry = Registry::new();
+        assert_eq!(
+            registry.convert_model(&get_range_model()).unwrap_err(),
+            "Unsupported edge type in the model: Distance2D"
+        );
+        registry.register_factor_type(get_range_residual()).unwrap();
+        assert!(registry.register_factor_type(get_range_residual()).is_err());
+        assert!(registry
+            .register_factor_type(CustomResidual::new("Odometry2D", |_: &[Vec<Dual>], _: &[f64]| vec![]))
+            .is_err());
+        assert_eq!(
+            registry.factor_type("Distance2D"),
+            Some(FactorType::Custom(get_range_residual()))
+        );
+        assert_eq!(registry.factor_type("Distance3D"), None);
+        assert!(registry.has_parser("g2o") && registry.has_parser("json") && !registry.has_parser("graph"));
+    }
+
+    #[test]
+    fn test_registered_parser_and_factor_type() {
+        let mut registry = Registry::new();
+        registry.register_factor_type(get_range_residual()).unwrap();
+        registry.register_parser::<OtherParser>("graph");
+        let factor_graph = registry.convert_model(&get_range_model()).unwrap();
+        optimize(&factor_graph, 10);
+        assert!(total_chi2(&factor_graph) < 1e-12);
+        let pose = factor_graph.get_var_by_id(VariableId(1)).unwrap().get_content();
+        assert!((pose[0] - 1.6).abs() < 1e-6 && (pose[1] - 1.2).abs() < 1e-6);
+
+        let file_path = env::temp_dir().join(format!("gs-rs-registry-{}.graph", std::process::id()));
+        let file_path = file_path.to_str().unwrap();
+        registry.compose_file(&factor_graph, file_path).unwrap();
+        let parsed = registry.parse_file(file_path);
+        fs::remove_file(file_path).unwrap();
+        assert_eq!(
+            FactorGraphModel::from(&parsed.unwrap()),
+            FactorGraphModel::from(&factor_graph)
+        );
+        assert!(registry.parse_file("data_files/full_demos/all_2d_types.txt").is_err());
+    }
+
+    // a beacon is a fixed 2D landmark, whose payload contains its ID and position
+    fn deserialize_beacon(payload: &serde_json::Value) -> Result<Vec<Vertex>, String> {
+        let id = payload["id"].as_u64().ok_or("The ID is missing")?;
+        let content = serde_json::from_value(payload["position"].clone()).map_err(|e| e.to_string())?;
+        Ok(vec![Vertex {
+            id: VariableId(id as usize),
+            vertex_type: String::from("Landmark2D"),
+            content,
+        }])
+    }
+
+    // one range measurement from the point in the payload to each beacon
+    fn deserialize_ranges(payload: &serde_json::Value) -> Result<Vec<Edge>, String> {
+        let point = VariableId(payload["point"].as_u64().ok_or("The point is missing")? as usize);
+        let ranges: Vec<(usize, f64)> = serde_json::from_value(payload["ranges"].clone()).map_err(|e| e.to_string())?;
+        Ok(ranges
+            .into_iter()
+            .map(|(beacon, range)| Edge {
+                edge_type: String::from("Distance2D"),
+                vertices: vec![VariableId(beacon), point],
+                restriction: vec![range],
+                information_matrix: vec![1.0],
+            })
+            .collect())
+    }
+
+    fn get_custom_model() -> FactorGraphModel {
+        JsonParser::parse_string_to_model(
+            r#"{"vertices": [{"id": 0, "type": "Landmark2D", "content": [0.5, 0.5]}],
+                "fixedVertices": [1, 2, 3],
+                "edges": [],
+                "customVariables": [
+                    {"type": "Beacon", "payload": {"id": 1, "position": [0.0, 0.0]}},
+                    {"type": "Beacon", "payload": {"id": 2, "position": [3.0, 0.0]}},
+                    {"type": "Beacon", "payload": {"id": 3, "position": [0.0, 4.0]}}
+                ],
+                "customFactors": [{"type": "Ranges", "payload": {"point": 0, "ranges": [[1, 2.5], [2, 2.5], [3, 2.5]]}}]
+            }"#,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_custom_entries() {
+        let model = get_custom_model();
+        assert_eq!(model.custom_variables.len(), 3);
+        assert_eq!(
+            convert_model(&model, &BTreeMap::new()).unwrap_err(),
+            "Unsupported custom variable type in the model: Beacon"
+        );
+        let mut registry = Registry::new();
+        registry.register_factor_type(get_range_residual()).unwrap();
+        registry
+            .register_variable_deserializer("Beacon", deserialize_beacon)
+            .unwrap();
+        assert!(registry
+            .register_variable_deserializer("Beacon", deserialize_beacon)
+            .is_err());
+        assert_eq!(
+            registry.convert_model(&model).unwrap_err(),
+            "Unsupported custom factor type in the model: Ranges"
+        );
+        registry
+            .register_factor_deserializer("Ranges", deserialize_ranges)
+            .unwrap();
+
+        let expanded = registry.expand_custom_entries(&model).unwrap();
+        assert_eq!((expanded.vertices.len(), expanded.edges.len()), (4, 3));
+        assert!(expanded.custom_variables.is_empty() && expanded.custom_factors.is_empty());
+        let factor_graph = registry.convert_model(&model).unwrap();
+        assert_eq!(FactorGraphModel::from(&factor_graph), expanded);
+        optimize(&factor_graph, 20);
+        assert!(total_chi2(&factor_graph) < 1e-12);
+        let point = factor_graph.get_var_by_id(VariableId(0)).unwrap().get_content();
+        assert!((point[0] - 1.5).abs() < 1e-6 && (point[1] - 2.0).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_invalid_custom_payload() {
+        let mut model = get_custom_model();
+        model.custom_variables[1].payload = serde_json::json!({"id": 2});
+        let mut registry = Registry::new();
+        registry
+            .register_variable_deserializer("Beacon", deserialize_beacon)
+            .unwrap();
+        assert!(registry
+            .expand_custom_entries(&model)
+            .unwrap_err()
+            .starts_with("Invalid custom variable of type Beacon in the model: "));
+        let composed = JsonParser::compose_model_to_string(get_custom_model()).unwrap();
+        assert_eq!(
+            JsonParser::parse_string_to_model(&composed).unwrap(),
+            get_custom_model()
+        );
+    }
+}
@@ -0,0 +1,197 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Keyboard and mouse bindings of the visualizer, e.g. to remap keys of an application which embeds it or to
+//! disable interactions.
+
+use kiss3d::camera::ArcBall;
+use kiss3d::event::{Key, MouseButton};
+use std::collections::BTreeMap;
+
+/// An interaction of the visualizer which can be bound to a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Command {
+    /// Moves the region by a tenth of its size in negative x direction.
+    MoveRegionLeft,
+    /// Moves the region by a tenth of its size in positive x direction.
+    MoveRegionRight,
+    /// Moves the region by a tenth of its size in negative y direction.
+    MoveRegionDown,
+    /// Moves the region by a tenth of its size in positive y direction.
+    MoveRegionUp,
+    /// Moves the region by a tenth of its size in negative z direction.
+    MoveRegionBackward,
+    /// Moves the region by a tenth of its size in positive z direction.
+    MoveRegionForward,
+    /// Scales the region by 1.25.
+    GrowRegion,
+    /// Scales the region by 0.8.
+    ShrinkRegion,
+    /// Shows or hides the region, i.e. whether variables outside of it are hidden.
+    ToggleRegion,
+    /// Shows or hides the factors.
+    ToggleFactors,
+    /// Shows or hides the arrows of a difference between two states of a factor graph.
+    ToggleArrows,
+    /// Shows or hides the χ² plot of an optimization.
+    TogglePlot,
+    /// Pauses or resumes an optimization.
+    TogglePause,
+    /// Performs a single iteration of a paused optimization.
+    Step,
+    /// Saves the current frame as "gs-rs-screenshot-<number>.png" in the working directory, counting from 0 per
+    /// window.
+    Screenshot,
+}
+
+/// Keyboard and mouse bindings of the visualizer.
+///
+/// The default bindings are:
+/// * J/L, K/I and U/O: move the region along the x, y and z axis
+/// * +/- and =/-: grow or shrink the region
+/// * F: toggle the region
+/// * T: toggle the factors
+/// * A: toggle the arrows
+/// * C: toggle the χ² plot
+/// * Space: pause or resume the optimization
+/// * N: step the paused optimization
+/// * P: take a screenshot
+/// * left mouse button: rotate the camera
+/// * right mouse button: move the camera
+/// * mouse wheel: zoom
+/// * Enter: reset the camera
+#[derive(Debug, Clone, PartialEq)]
+pub struct Bindings {
+    keys: BTreeMap<Key, Command>,
+    /// The mouse button which rotates the camera, or None to disable rotation.
+    pub rotate_button: Option<MouseButton>,
+    /// The mouse button which moves the camera, or None to disable moving.
+    pub drag_button: Option<MouseButton>,
+    /// The key which resets the camera, or None to disable resetting.
+    pub reset_key: Option<Key>,
+    /// Whether the mouse wheel zooms.
+    pub zoom_enabled: bool,
+}
+
+impl Default for Bindings {
+    fn default() -> Self {
+        let mut bindings = Self::none();
+        [
+            (Key::J, Command::MoveRegionLeft),
+            (Key::L, Command::MoveRegionRight),
+            (Key::K, Command::MoveRegionDown),
+            (Key::I, Command::MoveRegionUp),
+            (Key::U, Command::MoveRegionBackward),
+            (Key::O, Command::MoveRegionForward),
+            (Key::Add, Command::GrowRegion),
+            (Key::Equals, Command::GrowRegion),
+            (Key::Subtract, Command::ShrinkRegion),
+            (Key::Minus, Command::ShrinkRegion),
+            (Key::F, Command::ToggleRegion),
+            (Key::T, Command::ToggleFactors),
+            (Key::A, Command::ToggleArrows),
+            (Key::C, Command::TogglePlot),
+            (Key::Space, Command::TogglePause),
+            (Key::N, Command::Step),
+            (Key::P, Command::Screenshot),
+        ]
+        .iter()
+        .for_each(|(key, command)| bindings.bind(*key, *command));
+        bindings.rotate_button = Some(MouseButton::Button1);
+        bindings.drag_button = Some(MouseButton::Button2);
+        bindings.reset_key = Some(Key::Return);
+        bindings.zoom_enabled = true;
+        bindings
+    }
+}
+
+impl Bindings {
+    /// Returns bindings without any keyboard or mouse interaction, e.g. for a static view.
+    pub fn none() -> Self {
+        Bindings {
+            keys: BTreeMap::new(),
+            rotate_button: None,
+            drag_button: None,
+            reset_key: None,
+            zoom_enabled: false,
+        }
+    }
+
+    /// Binds the key to the command, replacing its previous binding. A command can be bound to several keys.
+    pub fn bind(&mut self, key: Key, command: Command) {
+        self.keys.insert(key, command);
+    }
+
+    /// Removes the binding of the key.
+    pub fn unbind_key(&mut self, key: Key) {
+        self.keys.remove(&key);
+    }
+
+    /// Removes all bindings of the command, i.e. disables it.
+    pub fn unbind(&mut self, command: Command) {
+        self.keys.retain(|_, bound| *bound != command);
+    }
+
+    /// Returns the command bound to the key.
+    pub fn command(&self, key: Key) -> Option<Command> {
+        self.keys.get(&key).copied()
+    }
+
+    /// Returns all keys bound to the command.
+    pub fn keys(&self, command: Command) -> Vec<Key> {
+        self.keys
+            .iter()
+            .filter(|(_, bound)| **bound == command)
+            .map(|(key, _)| *key)
+            .collect()
+    }
+
+    // applies the mouse bindings and the reset key to the camera
+    pub(crate) fn apply_to_camera(&self, camera: &mut ArcBall) {
+        camera.rebind_rotate_button(self.rotate_button);
+        camera.rebind_drag_button(self.drag_button);
+        camera.rebind_reset_key(self.reset_key);
+        if !self.zoom_enabled {
+            camera.set_dist_step(1.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_default_bindings() {
+        let bindings = Bindings::default();
+        assert_eq!(bindings.command(Key::F), Some(Command::ToggleRegion));
+        assert_eq!(bindings.keys(Command::GrowRegion), vec![Key::Add, Key::Equals]);
+        assert_eq!(bindings.command(Key::Z), None);
+        assert_eq!(bindings.reset_key, Some(Key::Return));
+    }
+
+    #[test]
+    fn test_rebinding() {
+        let mut bindings = Bindings::default();
+        bindings.bind(Key::F, Command::Screenshot);
+        assert_eq!(bindings.keys(Command::Screenshot), vec![Key::F, Key::P]);
+        assert!(bindings.keys(Command::ToggleRegion).is_empty());
+
+        bindings.unbind(Command::Screenshot);
+        bindings.unbind_key(Key::J);
+        assert_eq!(bindings.command(Key::F), None);
+        assert_eq!(bindings.command(Key::P), None);
+        assert_eq!(bindings.command(Key::J), None);
+        assert_eq!(bindings.command(Key::L), Some(Command::MoveRegionRight));
+        assert!(Bindings::none().keys(Command::Step).is_empty());
+    }
+}
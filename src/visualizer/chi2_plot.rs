@@ -0,0 +1,129 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Plot of the total χ² of a factor graph per optimization iteration.
+
+use nalgebra::{Point2, Vector2};
+
+/// The smallest χ² shown, so that the logarithmic scale stays finite for perfectly fitting factor graphs.
+const MIN_CHI2: f64 = 1e-12;
+
+/// Curve of the total χ² per iteration, starting with the value before the first iteration.
+///
+/// The χ² is plotted on a logarithmic scale, since it usually decreases by orders of magnitude.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Chi2Plot {
+    values: Vec<f64>,
+}
+
+impl Chi2Plot {
+    /// Returns an empty plot.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Appends the χ² after the next iteration.
+    pub fn push(&mut self, chi2: f64) {
+        self.values.push(chi2);
+    }
+
+    /// Returns all χ² values in the order of the iterations.
+    pub fn values(&self) -> &[f64] {
+        &self.values
+    }
+
+    /// Returns line segments drawing the axes and the curve within the rectangle with the given lower left corner
+    /// and size, with y pointing upwards.
+    pub fn lines(&self, origin: &Point2<f32>, size: &Vector2<f32>) -> Vec<[Point2<f32>; 2]> {
+        let points: Vec<Point2<f32>> = self
+            .get_points(&size.cast())
+            .iter()
+            .map(|p| origin + p.coords.cast())
+            .collect();
+        let mut lines = vec![
+            [*origin, origin + Vector2::new(size.x, 0.0)],
+            [*origin, origin + Vector2::new(0.0, size.y)],
+        ];
+        lines.extend(points.windows(2).map(|w| [w[0], w[1]]));
+        lines
+    }
+
+    /// Returns the plot as an SVG image of the given size.
+    pub fn to_svg(&self, width: u32, height: u32) -> String {
+        let size = Vector2::new(f64::from(width), f64::from(height));
+        let points: Vec<String> = self
+            .get_points(&size)
+            .iter()
+            .map(|p| format!("{:.2},{:.2}", p.x, size.y - p.y))
+            .collect();
+        [
+            format!(
+                "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{}\" height=\"{}\">",
+                width, height
+            ),
+            format!(
+                "<polyline points=\"0,0 0,{h} {w},{h}\" fill=\"none\" stroke=\"black\"/>",
+                w = width,
+                h = height
+            ),
+            format!(
+                "<polyline points=\"{}\" fill=\"none\" stroke=\"blue\"/>",
+                points.join(" ")
+            ),
+            String::from("</svg>"),
+        ]
+        .join("\n")
+    }
+
+    // returns the curve's points within [0, size.x] x [0, size.y], with y pointing upwards
+    fn get_points(&self, size: &Vector2<f64>) -> Vec<Point2<f64>> {
+        let log_values: Vec<f64> = self.values.iter().map(|v| v.max(MIN_CHI2).log10()).collect();
+        let min = log_values.iter().cloned().fold(f64::INFINITY, f64::min);
+        let max = log_values.iter().cloned().fold(f64::NEG_INFINITY, f64::max);
+        let x_step = size.x / (self.values.len().max(2) - 1) as f64;
+        log_values
+            .iter()
+            .enumerate()
+            .map(|(i, v)| {
+                let y = if max > min { (v - min) / (max - min) } else { 0.5 };
+                Point2::new(i as f64 * x_step, y * size.y)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn test_lines() {
+        let mut plot = Chi2Plot::new();
+        [1000.0, 10.0, 1.0].iter().for_each(|v| plot.push(*v));
+        let lines = plot.lines(&Point2::new(10.0, 20.0), &Vector2::new(100.0, 30.0));
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[2][0], Point2::new(10.0, 50.0));
+        assert_relative_eq!(lines[2][1].x, 60.0);
+        assert_relative_eq!(lines[2][1].y, 30.0);
+        assert_eq!(lines[3][1], Point2::new(110.0, 20.0));
+    }
+
+    #[test]
+    fn test_to_svg() {
+        let mut plot = Chi2Plot::new();
+        plot.push(0.0);
+        let svg = plot.to_svg(200, 100);
+        assert!(svg.starts_with("<svg"));
+        assert!(svg.contains("points=\"0.00,50.00\""));
+    }
+}
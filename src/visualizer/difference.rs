@@ -0,0 +1,159 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Displacements between corresponding variables of two states of a factor graph.
+
+use crate::factor_graph::variable::{Variable, VariableId};
+use crate::factor_graph::FactorGraph;
+use nalgebra::{Point3, Vector3};
+
+/// The relative length of an arrow's head.
+const HEAD_RATIO: f64 = 0.2;
+
+/// The movement of a variable's position from one state of a factor graph to another.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Displacement {
+    /// The ID of the variable in both states.
+    pub id: VariableId,
+    /// The position in the first state.
+    pub from: Point3<f64>,
+    /// The position in the second state.
+    pub to: Point3<f64>,
+}
+
+impl Displacement {
+    /// Returns the distance between both positions.
+    pub fn length(&self) -> f64 {
+        (self.to - self.from).norm()
+    }
+
+    /// Returns line segments drawing an arrow from the first to the second position, i.e. its shaft followed by
+    /// the two lines of its head. Returns no lines if both positions are equal.
+    pub fn arrow(&self) -> Vec<[Point3<f64>; 2]> {
+        let delta = self.to - self.from;
+        let length = delta.norm();
+        if length == 0.0 {
+            return vec![];
+        }
+        let direction = delta / length;
+        // the head lies in the plane spanned by the direction and the z axis, or the x axis for vertical arrows
+        let axis = if direction.z.abs() < 0.99 {
+            Vector3::z()
+        } else {
+            Vector3::x()
+        };
+        let normal = direction.cross(&axis).normalize();
+        let head_base = self.to - direction * HEAD_RATIO * length;
+        let head_offset = normal * 0.5 * HEAD_RATIO * length;
+        vec![
+            [self.from, self.to],
+            [self.to, head_base + head_offset],
+            [self.to, head_base - head_offset],
+        ]
+    }
+}
+
+/// Returns the displacements of all variables of the first factor graph which have a position and are also part of
+/// the second factor graph, in the order of the first factor graph's variables.
+pub fn get_displacements(from: &FactorGraph, to: &FactorGraph) -> Vec<Displacement> {
+    from.node_indices
+        .iter()
+        .map(|i| from.get_var(*i))
+        .filter_map(|var| {
+            let id = var.get_id();
+            Some(Displacement {
+                id,
+                from: get_position(var)?,
+                to: get_position(to.get_var_by_id(id)?)?,
+            })
+        })
+        .collect()
+}
+
+// returns the variable's position, with z = 0 in 2D, or None if the variable has no position
+fn get_position(var: &Variable) -> Option<Point3<f64>> {
+    let content = var.get_content();
+    match var {
+        Variable::Vehicle2D(_) | Variable::Landmark2D(_) => Some(Point3::new(content[0], content[1], 0.0)),
+        Variable::Vehicle3D(_) | Variable::Landmark3D(_) | Variable::VehicleSim3(_) => {
+            Some(Point3::new(content[0], content[1], content[2]))
+        }
+        Variable::Switch(_)
+        | Variable::Velocity3D(_)
+        | Variable::ImuBias(_)
+        | Variable::Plane3D(_)
+        | Variable::LineLandmark2D(_)
+        | Variable::OdometryCalibration2D(_)
+        | Variable::Extrinsic2D(_)
+        | Variable::Extrinsic3D(_)
+        | Variable::Anchor2D(_)
+        | Variable::Anchor3D(_)
+        | Variable::UserDefined(_) => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::parser::g2o::G2oParser;
+    use crate::parser::Parser;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn test_get_displacements() {
+        let from: FactorGraph = G2oParser::parse_string_to_model(
+            &[
+                "VERTEX_SE2 0 0 0 0",
+                "VERTEX_XY 1 1 2",
+                "VERTEX_SWITCH 2 1",
+                "VERTEX_XY 3 5 5",
+            ]
+            .join("\n"),
+        )
+        .unwrap()
+        .into();
+        let to: FactorGraph = G2oParser::parse_string_to_model(
+            &["VERTEX_SE2 0 0 0 1", "VERTEX_XY 1 4 6", "VERTEX_SWITCH 2 0"].join("\n"),
+        )
+        .unwrap()
+        .into();
+        let displacements = get_displacements(&from, &to);
+        assert_eq!(displacements.len(), 2);
+        assert_relative_eq!(displacements[0].length(), 0.0);
+        assert_eq!(displacements[1].id, VariableId(1));
+        assert_relative_eq!(displacements[1].length(), 5.0);
+    }
+
+    #[test]
+    fn test_arrow() {
+        let displacement = Displacement {
+            id: VariableId(0),
+            from: Point3::new(0.0, 0.0, 0.0),
+            to: Point3::new(10.0, 0.0, 0.0),
+        };
+        let arrow = displacement.arrow();
+        assert_eq!(arrow.len(), 3);
+        for head_line in &arrow[1..] {
+            assert_relative_eq!(head_line[1].x, 8.0);
+            assert_relative_eq!(head_line[1].y.abs(), 1.0);
+        }
+        let mut vertical = displacement.clone();
+        vertical.to = Point3::new(0.0, 0.0, 1.0);
+        assert!(vertical
+            .arrow()
+            .iter()
+            .flatten()
+            .all(|p| p.iter().all(|v| v.is_finite())));
+        vertical.to = vertical.from;
+        assert!(vertical.arrow().is_empty());
+    }
+}
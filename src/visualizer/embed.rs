@@ -0,0 +1,138 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Embedding of the visualization into windows which are owned by the application.
+//!
+//! ```no_run
+//! use gs_rs::examples_gen::triangle_with_loop_closure;
+//! use gs_rs::factor_graph::FactorGraph;
+//! use gs_rs::optimizer::optimize;
+//! use gs_rs::visualizer::embed::attach;
+//! use kiss3d::window::Window;
+//!
+//! let factor_graph: FactorGraph = triangle_with_loop_closure().into();
+//! let mut window = Window::new("application");
+//! let mut handle = attach(&mut window, &factor_graph);
+//! let mut frame = 0;
+//! while window.render() {
+//!     frame += 1;
+//!     if frame % 30 == 0 {
+//!         optimize(&factor_graph, 1);
+//!         handle.update(&mut window, &factor_graph);
+//!     }
+//!     handle.draw(&mut window);
+//! }
+//! ```
+
+use crate::factor_graph::FactorGraph;
+use crate::visualizer::{add_factor_graph_to_window, ViewState, VisualFactorGraph, VisualizationOptions};
+use kiss3d::scene::SceneNode;
+use kiss3d::window::Window;
+use nalgebra::Point3;
+
+/// Handle of a factor graph's visualization within a window, see [attach](fn.attach.html).
+///
+/// The variables and measurements are objects of the scene, which are shown until the handle is detached. Lines
+/// are drawn immediately by kiss3d, so [draw](#method.draw) has to be called once per frame.
+pub struct GraphSceneHandle {
+    visual_factor_graph: VisualFactorGraph,
+    state: ViewState,
+}
+
+/// Adds the visualization of the factor graph to the window, whose render loop is driven by the caller.
+pub fn attach(window: &mut Window, factor_graph: &FactorGraph) -> GraphSceneHandle {
+    attach_with_options(window, factor_graph, &VisualizationOptions::default())
+}
+
+/// Adds the visualization of the factor graph to the window like [attach](fn.attach.html), configured by the given
+/// options.
+///
+/// Only the region of the options is used, since the bindings are handled by the caller's render loop.
+pub fn attach_with_options(
+    window: &mut Window,
+    factor_graph: &FactorGraph,
+    options: &VisualizationOptions,
+) -> GraphSceneHandle {
+    let state = ViewState::new(options);
+    GraphSceneHandle {
+        visual_factor_graph: add_factor_graph_to_window(window, factor_graph, &state),
+        state,
+    }
+}
+
+impl GraphSceneHandle {
+    /// Returns the group node containing all objects of the visualization, e.g. to transform it within the scene.
+    pub fn scene_node(&self) -> &SceneNode {
+        &self.visual_factor_graph.scene_node
+    }
+
+    /// Returns the group node containing all objects of the visualization mutably.
+    pub fn scene_node_mut(&mut self) -> &mut SceneNode {
+        &mut self.visual_factor_graph.scene_node
+    }
+
+    /// Draws the lines of the visualization, i.e. the factors' connections, into the current frame.
+    ///
+    /// The lines are not affected by transformations of the scene node.
+    pub fn draw(&self, window: &mut Window) {
+        self.visual_factor_graph
+            .lines
+            .iter()
+            .for_each(|line| window.draw_line(&line[0], &line[1], &line[2]));
+        if let Some(region) = &self.state.region {
+            region
+                .outline()
+                .iter()
+                .for_each(|line| window.draw_line(&line[0].cast(), &line[1].cast(), &Point3::new(1.0, 1.0, 0.0)));
+        }
+    }
+
+    /// Replaces the visualization with the current state of the factor graph, e.g. after an optimization.
+    ///
+    /// The scene node is replaced as well, so transformations of the previous one have to be applied again.
+    pub fn update(&mut self, window: &mut Window, factor_graph: &FactorGraph) {
+        self.visual_factor_graph.scene_node.unlink();
+        self.visual_factor_graph = add_factor_graph_to_window(window, factor_graph, &self.state);
+    }
+
+    /// Removes the visualization from the window.
+    pub fn detach(mut self) {
+        self.visual_factor_graph.scene_node.unlink();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::optimizer::optimize;
+    use crate::parser::g2o::G2oParser;
+    use crate::parser::Parser;
+
+    #[test]
+    #[ignore] // don't open a window every time all tests are run
+    fn test_attach() {
+        let factor_graph = G2oParser::parse_file("data_files/optimizer_tests/full2d_0.g2o").unwrap();
+        let mut window = Window::new("embedded");
+        let mut handle = attach(&mut window, &factor_graph);
+        for frame in 0..300 {
+            if !window.render() {
+                break;
+            }
+            if frame % 30 == 29 {
+                optimize(&factor_graph, 1);
+                handle.update(&mut window, &factor_graph);
+            }
+            handle.draw(&mut window);
+        }
+        handle.detach();
+    }
+}
@@ -11,17 +11,94 @@
 //
 
 //! Handles the graphical user interface.
+//!
+//! While a region is set, it can be moved with the keys J/L (x axis), K/I (y axis) and U/O (z axis), scaled with
+//! +/- and toggled with F. These and all other keyboard and mouse interactions can be remapped or disabled with
+//! [Bindings](bindings/struct.Bindings.html).
+//!
+//! Arbitrary subsets of factors and variables can be highlighted by tagging them and coloring the tags, see
+//! [Tags](tags/struct.Tags.html).
+//!
+//! The difference between two states of a factor graph, e.g. before and after optimization, can be shown with
+//! [visualize_difference](fn.visualize_difference.html), which draws an arrow from each variable's first position
+//! to its second one.
+//!
+//! Applications which own a kiss3d window and drive its render loop themselves can embed the visualization of a
+//! factor graph with [attach](embed/fn.attach.html).
+//!
+//! The course of an optimization can be followed with [visualize_optimization](fn.visualize_optimization.html),
+//! which shows the factor graph after each iteration next to a plot of its total χ².
 
 use crate::factor_graph::FactorGraph;
 use crate::factor_graph::{
     factor::{Factor, FactorType::*},
-    variable::{LandmarkVariable2D, LandmarkVariable3D, Variable, VehicleVariable2D, VehicleVariable3D},
+    variable::{
+        LandmarkVariable2D, LandmarkVariable3D, Variable, VehicleVariable2D, VehicleVariable3D, VehicleVariableSim3,
+    },
 };
+use crate::optimizer::linear_system::get_dominant_component;
+use crate::optimizer::{optimize, total_chi2};
 use kiss3d::camera::ArcBall;
+use kiss3d::event::{Action, WindowEvent};
 use kiss3d::scene::SceneNode;
 use kiss3d::window::Window;
-use nalgebra::{Point3, Quaternion, Rotation3, Translation3, UnitQuaternion, Vector3};
-use petgraph::visit::EdgeRef;
+use nalgebra::{Point2, Point3, Quaternion, Rotation3, Translation3, UnitQuaternion, Vector2, Vector3};
+
+pub mod bindings;
+pub mod chi2_plot;
+pub mod difference;
+pub mod embed;
+pub mod region;
+pub mod tags;
+
+use bindings::{Bindings, Command};
+use chi2_plot::Chi2Plot;
+use difference::Displacement;
+use region::Region;
+use tags::{Color, Tags};
+
+/// Options for the visualization of a factor graph.
+#[derive(Debug, Clone, Default)]
+pub struct VisualizationOptions {
+    /// The region outside of which variables and their factors are hidden, or None to show everything.
+    pub region: Option<Region>,
+    /// The keyboard and mouse bindings.
+    pub bindings: Bindings,
+    /// The tags which override the colors of factors and variables.
+    pub tags: Tags,
+}
+
+/// The number of frames for which each state of an animated optimization is shown.
+const FRAMES_PER_ITERATION: usize = 30;
+
+// the state of the view which is changed by the bindings
+struct ViewState {
+    region: Option<Region>,
+    region_enabled: bool,
+    tags: Tags,
+    factors_visible: bool,
+    arrows_visible: bool,
+    plot_visible: bool,
+    paused: bool,
+    step_requested: bool,
+    screenshot_count: usize,
+}
+
+impl ViewState {
+    fn new(options: &VisualizationOptions) -> Self {
+        ViewState {
+            region: options.region.clone(),
+            region_enabled: true,
+            tags: options.tags.clone(),
+            factors_visible: true,
+            arrows_visible: true,
+            plot_visible: true,
+            paused: false,
+            step_requested: false,
+            screenshot_count: 0,
+        }
+    }
+}
 
 struct VisualFactorGraph {
     scene_node: SceneNode,
@@ -31,61 +108,307 @@ struct VisualFactorGraph {
 // TODO: Does not work multiple times in a single execution of the program.
 /// Displays the visualization of the given factor graph in a new window.
 pub fn visualize(factor_graph: &FactorGraph) {
+    visualize_with_options(factor_graph, &VisualizationOptions::default());
+}
+
+/// Displays the visualization of the given factor graph in a new window, configured by the given options.
+pub fn visualize_with_options(factor_graph: &FactorGraph, options: &VisualizationOptions) {
+    show_window(factor_graph, options, &[], &mut |_, _| false);
+}
+
+/// Displays the second state of a factor graph in a new window together with arrows from the positions of its
+/// variables in the first state, see [get_displacements](difference/fn.get_displacements.html).
+pub fn visualize_difference(from: &FactorGraph, to: &FactorGraph) {
+    visualize_difference_with_options(from, to, &VisualizationOptions::default());
+}
+
+/// Displays the difference between two states of a factor graph in a new window, configured by the given options.
+///
+/// Arrows are hidden if the region does not contain the variable's position in the second state.
+pub fn visualize_difference_with_options(from: &FactorGraph, to: &FactorGraph, options: &VisualizationOptions) {
+    show_window(to, options, &difference::get_displacements(from, to), &mut |_, _| false);
+}
+
+/// Optimizes the factor graph with the given number of iterations while displaying it in a new window, together
+/// with a plot of the total χ² per iteration in the lower left corner. Returns the plot, e.g. to export it with
+/// [to_svg](chi2_plot/struct.Chi2Plot.html#method.to_svg).
+///
+/// Each state is shown for a fixed number of frames unless the optimization is paused, see
+/// [Bindings](bindings/struct.Bindings.html). The optimization stops early if the window is closed.
+pub fn visualize_optimization(
+    factor_graph: &FactorGraph,
+    iterations: usize,
+    options: &VisualizationOptions,
+) -> Chi2Plot {
+    let mut plot = Chi2Plot::new();
+    plot.push(total_chi2(factor_graph));
+    let mut frame = 0;
+    show_window(factor_graph, options, &[], &mut |window, state| {
+        if state.plot_visible {
+            draw_chi2_plot(window, &plot);
+        }
+        if !state.paused {
+            frame += 1;
+        }
+        let is_step = state.step_requested || (!state.paused && frame % FRAMES_PER_ITERATION == 0);
+        state.step_requested = false;
+        if plot.values().len() > iterations || !is_step {
+            return false;
+        }
+        optimize(factor_graph, 1);
+        plot.push(total_chi2(factor_graph));
+        true
+    });
+    plot
+}
+
+// shows the factor graph until the window is closed, calling on_frame once per frame, which returns whether the
+// factor graph changed
+fn show_window(
+    factor_graph: &FactorGraph,
+    options: &VisualizationOptions,
+    displacements: &[Displacement],
+    on_frame: &mut dyn FnMut(&mut Window, &mut ViewState) -> bool,
+) {
     let mut window = Window::new("gs-rs");
-    let visual_factor_graph = add_factor_graph_to_window(&mut window, factor_graph);
+    let mut state = ViewState::new(options);
+    let mut visual_factor_graph = add_factor_graph_to_window(&mut window, factor_graph, &state);
+    let mut arrow_lines = get_arrow_lines(displacements, &state);
     let init_point = match factor_graph.node_indices.len() {
         0 => Point3::new(0.0, 0.0, 0.0),
         _ => get_var_point(factor_graph.get_var(factor_graph.node_indices[0])),
     };
     let mut cam = ArcBall::new(Point3::new(0.0, 0.0, 50.0), init_point);
+    options.bindings.apply_to_camera(&mut cam);
     while window.render_with_camera(&mut cam) {
+        let view_changed = handle_events(&window, &options.bindings, &mut state);
+        if on_frame(&mut window, &mut state) || view_changed {
+            visual_factor_graph.scene_node.unlink();
+            visual_factor_graph = add_factor_graph_to_window(&mut window, factor_graph, &state);
+            arrow_lines = get_arrow_lines(displacements, &state);
+        }
         visual_factor_graph
             .lines
             .iter()
+            .chain(arrow_lines.iter())
             .for_each(|line| window.draw_line(&line[0], &line[1], &line[2]));
+        if let (Some(region), true) = (&state.region, state.region_enabled) {
+            region
+                .outline()
+                .iter()
+                .for_each(|line| window.draw_line(&line[0].cast(), &line[1].cast(), &Point3::new(1.0, 1.0, 0.0)));
+        }
     }
 }
 
-fn add_factor_graph_to_window(window: &mut Window, factor_graph: &FactorGraph) -> VisualFactorGraph {
+// draws the plot into the lower left corner of the window
+fn draw_chi2_plot(window: &mut Window, plot: &Chi2Plot) {
+    let scale = window.scale_factor() as f32;
+    let (width, height) = (window.width() as f32 / scale, window.height() as f32 / scale);
+    let origin = Point2::new(20.0 - width / 2.0, 20.0 - height / 2.0);
+    let size = Vector2::new((width / 4.0).max(100.0), (height / 6.0).max(50.0));
+    plot.lines(&origin, &size)
+        .iter()
+        .for_each(|line| window.draw_planar_line(&line[0], &line[1], &Point3::new(1.0, 1.0, 1.0)));
+}
+
+// returns the colored lines of the arrows whose end lies within the active region
+fn get_arrow_lines(displacements: &[Displacement], state: &ViewState) -> Vec<[Point3<f32>; 3]> {
+    if !state.arrows_visible {
+        return vec![];
+    }
+    let region = get_active_region(state);
+    displacements
+        .iter()
+        .filter(|d| region.is_none_or(|r| r.contains(&d.to)))
+        .flat_map(|d| d.arrow())
+        .map(|line| [line[0].cast(), line[1].cast(), Point3::new(0.0, 1.0, 1.0)])
+        .collect()
+}
+
+fn get_active_region(state: &ViewState) -> Option<&Region> {
+    state.region.as_ref().filter(|_| state.region_enabled)
+}
+
+// applies the commands bound to the pressed keys and returns whether the shown factor graph or arrows changed
+fn handle_events(window: &Window, bindings: &Bindings, state: &mut ViewState) -> bool {
+    let mut changed = false;
+    for event in window.events().iter() {
+        let command = match event.value {
+            WindowEvent::Key(key, Action::Press, _) => match bindings.command(key) {
+                Some(command) => command,
+                None => continue,
+            },
+            _ => continue,
+        };
+        match command {
+            Command::ToggleRegion => state.region_enabled = !state.region_enabled,
+            Command::ToggleFactors => state.factors_visible = !state.factors_visible,
+            Command::ToggleArrows => state.arrows_visible = !state.arrows_visible,
+            Command::TogglePlot => {
+                state.plot_visible = !state.plot_visible;
+                continue;
+            }
+            Command::TogglePause => {
+                state.paused = !state.paused;
+                continue;
+            }
+            Command::Step => {
+                state.step_requested = true;
+                continue;
+            }
+            Command::Screenshot => {
+                let file_name = format!("gs-rs-screenshot-{}.png", state.screenshot_count);
+                // a failed screenshot should not end the visualization
+                let _ = window.snap_image().save(file_name);
+                state.screenshot_count += 1;
+                continue;
+            }
+            _ => match state.region.as_ref().and_then(|region| move_region(region, command)) {
+                Some(updated) => state.region = Some(updated),
+                None => continue,
+            },
+        }
+        changed = true;
+    }
+    changed
+}
+
+// returns the region after moving or scaling it according to the command
+fn move_region(region: &Region, command: Command) -> Option<Region> {
+    let step = 0.1 * region.size();
+    let offset = match command {
+        Command::MoveRegionLeft => Vector3::new(-step, 0.0, 0.0),
+        Command::MoveRegionRight => Vector3::new(step, 0.0, 0.0),
+        Command::MoveRegionDown => Vector3::new(0.0, -step, 0.0),
+        Command::MoveRegionUp => Vector3::new(0.0, step, 0.0),
+        Command::MoveRegionBackward => Vector3::new(0.0, 0.0, -step),
+        Command::MoveRegionForward => Vector3::new(0.0, 0.0, step),
+        Command::GrowRegion => return Some(region.scaled(1.25)),
+        Command::ShrinkRegion => return Some(region.scaled(0.8)),
+        _ => return None,
+    };
+    Some(region.translated(&offset))
+}
+
+fn add_factor_graph_to_window(window: &mut Window, factor_graph: &FactorGraph, state: &ViewState) -> VisualFactorGraph {
     let mut visual_factor_graph = VisualFactorGraph {
         scene_node: window.add_group(),
         lines: vec![],
     };
+    let region = get_active_region(state);
+    // switch, velocity, IMU bias, plane, line, calibration and extrinsic variables have no position in the world
+    // frame, anchors are no part of the map and the position of user-defined variables is unknown, so they and
+    // their factors are not shown
+    let is_visible = |var: &Variable| match var {
+        Variable::Switch(_)
+        | Variable::Velocity3D(_)
+        | Variable::ImuBias(_)
+        | Variable::Plane3D(_)
+        | Variable::LineLandmark2D(_)
+        | Variable::OdometryCalibration2D(_)
+        | Variable::Extrinsic2D(_)
+        | Variable::Extrinsic3D(_)
+        | Variable::Anchor2D(_)
+        | Variable::Anchor3D(_)
+        | Variable::UserDefined(_) => false,
+        _ => region.is_none_or(|r| r.contains(&get_var_point(var).cast())),
+    };
 
     factor_graph
         .node_indices
         .iter()
-        .for_each(|i| add_var(&mut visual_factor_graph, factor_graph.get_var(*i)));
+        .map(|i| factor_graph.get_var(*i))
+        .filter(|var| is_visible(var))
+        .for_each(|var| add_var(&mut visual_factor_graph, var, &state.tags));
 
+    if !state.factors_visible {
+        return visual_factor_graph;
+    }
     factor_graph.node_indices.iter().for_each(|i| {
-        factor_graph.csr.edges(*i).for_each(|edge| {
-            add_factor(
-                &mut visual_factor_graph,
-                edge.weight(),
-                factor_graph.get_var(edge.source()),
-                factor_graph.get_var(edge.target()),
-            )
-        })
+        factor_graph
+            .adjacency
+            .edges(*i)
+            .filter(|edge| {
+                is_visible(factor_graph.get_var(edge.source())) && is_visible(factor_graph.get_var(edge.target()))
+            })
+            .for_each(|edge| {
+                add_factor(
+                    &mut visual_factor_graph,
+                    &state.tags,
+                    &get_dominant_component(factor_graph, edge.weight()),
+                    factor_graph.get_var(edge.source()),
+                    factor_graph.get_var(edge.target()),
+                )
+            })
     });
 
     visual_factor_graph
 }
 
-fn add_var(visual_factor_graph: &mut VisualFactorGraph, var: &Variable) {
+fn add_var(visual_factor_graph: &mut VisualFactorGraph, var: &Variable, tags: &Tags) {
     let var_point = get_var_point(var);
     let mut var_object = add_var_core(visual_factor_graph, &var_point);
     handle_var_rotation(var, &mut var_object);
-    color_var_object(var, &mut var_object);
+    color_var_object(var, tags, &mut var_object);
 }
 
-fn add_factor(visual_factor_graph: &mut VisualFactorGraph, factor: &Factor, source: &Variable, target: &Variable) {
+fn add_factor(
+    visual_factor_graph: &mut VisualFactorGraph,
+    tags: &Tags,
+    factor: &Factor,
+    source: &Variable,
+    target: &Variable,
+) {
+    if let DensePrior | Heading2D | Heading3D | Altitude3D | InterpolatedPose2D | InterpolatedPose3D =
+        factor.factor_type
+    {
+        // a dense prior has no single measurement point, and its variables are shown anyway, while heading and
+        // altitude priors only measure a single component of the vehicle's pose, which is shown by the vehicle itself,
+        // and interpolated poses lie between the vehicle's poses
+        return;
+    }
+    let color = tags.factor_color(factor.id).unwrap_or_else(|| get_factor_color(factor));
+    if let Custom(_)
+    | UserDefined(_)
+    | SwitchableOdometry2D
+    | SwitchableOdometry3D
+    | Range2D
+    | Range3D
+    | Bearing2D
+    | ImuPreintegration3D
+    | ConstantVelocity2D
+    | ConstantVelocity3D
+    | Projection3D
+    | StereoProjection3D
+    | OdometrySim3
+    | CalibratedOdometry2D
+    | ExtrinsicObservation2D
+    | ExtrinsicObservation3D
+    | AnchoredOdometry2D
+    | AnchoredOdometry3D
+    | AnchoredObservation2D
+    | AnchoredObservation3D = factor.factor_type
+    {
+        // the measurement of a custom factor has no known meaning, the one of a switchable factor may be an outlier,
+        // the ones of range, bearing and projection factors have no direction or no distance, the ones of IMU
+        // and constant-velocity factors are no relative poses, the ones of Sim(3) and calibrated odometry factors are
+        // scaled, the ones of extrinsic observations are in the sensor's frame and the ones of anchored factors are
+        // between different sessions' frames, so only their variables are connected
+        let (r, g, b) = color;
+        visual_factor_graph
+            .lines
+            .push([get_var_point(source), get_var_point(target), Point3::new(r, g, b)]);
+        return;
+    }
     let meas_point = calc_meas_point(factor, source);
     let mut meas_object = add_factor_core(visual_factor_graph, &meas_point);
     handle_factor_rotation(factor, &mut meas_object, source);
-    color_meas_object(factor, &mut meas_object);
+    color_meas_object(color, &mut meas_object);
     add_factor_lines(
         visual_factor_graph,
         factor,
+        color,
         meas_point,
         get_var_point(source),
         get_var_point(target),
@@ -109,33 +432,88 @@ fn handle_var_rotation(var: &Variable, var_object: &mut SceneNode) {
         Variable::Vehicle3D(v) => {
             rot_object.set_local_rotation(get_rot_from_3d(&*v.pose.borrow()));
         }
+        Variable::VehicleSim3(v) => {
+            rot_object.set_local_rotation(get_rot_from_3d(&*v.pose.borrow()));
+        }
         _ => (),
     }
 
     rot_object.prepend_to_local_translation(&Translation3::new(0.0, 0.20, 0.0));
 }
 
-fn color_var_object(var: &Variable, var_object: &mut SceneNode) {
+fn color_var_object(var: &Variable, tags: &Tags, var_object: &mut SceneNode) {
+    if let Some((r, g, b)) = tags.variable_color(var.get_id()) {
+        var_object.set_color(r, g, b);
+        return;
+    }
     match var {
-        Variable::Vehicle2D(_) | Variable::Vehicle3D(_) => var_object.set_color(1.0, 0.0, 0.0),
+        Variable::Vehicle2D(_) | Variable::Vehicle3D(_) | Variable::VehicleSim3(_) => {
+            var_object.set_color(1.0, 0.0, 0.0)
+        }
         Variable::Landmark2D(_) | Variable::Landmark3D(_) => var_object.set_color(0.0, 1.0, 0.0),
+        Variable::Switch(_)
+        | Variable::Velocity3D(_)
+        | Variable::ImuBias(_)
+        | Variable::Plane3D(_)
+        | Variable::LineLandmark2D(_)
+        | Variable::OdometryCalibration2D(_)
+        | Variable::Extrinsic2D(_)
+        | Variable::Extrinsic3D(_)
+        | Variable::Anchor2D(_)
+        | Variable::Anchor3D(_)
+        | Variable::UserDefined(_) => {
+            unreachable!("Only pose and position variables are visualized.")
+        }
     };
 }
 
 fn calc_meas_point(factor: &Factor, source: &Variable) -> Point3<f32> {
     let factor_point = get_factor_point(factor);
     match factor.factor_type {
-        Position2D | Position3D => factor_point,
-        Odometry2D | Observation2D => {
+        Position2D | Position3D | PositionOnly3D | LeverArmPosition2D | LeverArmPosition3D => factor_point,
+        Odometry2D | Observation2D | BearingRange2D => {
             let source_rot = get_rot_from_2d(&source.get_content());
             let local_point = Rotation3::new(Vector3::z() * source_rot) * factor_point;
             (get_var_point(source).coords + local_point.coords).into()
         }
+        LandmarkOffset2D | LandmarkOffset3D => (get_var_point(source).coords + factor_point.coords).into(),
         Odometry3D | Observation3D => {
             let source_rot = get_rot_from_3d(&source.get_content());
             let local_point = source_rot.to_rotation_matrix() * factor_point;
             (get_var_point(source).coords + local_point.coords).into()
         }
+        Custom(_)
+        | UserDefined(_)
+        | SwitchableOdometry2D
+        | SwitchableOdometry3D
+        | SwitchPrior
+        | MaxMixture(_)
+        | DensePrior
+        | Heading2D
+        | Heading3D
+        | Altitude3D
+        | InterpolatedPose2D
+        | InterpolatedPose3D
+        | Range2D
+        | Range3D
+        | Bearing2D
+        | ImuPreintegration3D
+        | ConstantVelocity2D
+        | ConstantVelocity3D
+        | Projection3D
+        | StereoProjection3D
+        | OdometrySim3
+        | CalibratedOdometry2D
+        | ExtrinsicObservation2D
+        | ExtrinsicObservation3D
+        | AnchoredOdometry2D
+        | AnchoredOdometry3D
+        | AnchoredObservation2D
+        | AnchoredObservation3D
+        | PointToPlane3D
+        | LineObservation2D => {
+            unreachable!("Only position, odometry and observation factors have a measurement point.")
+        }
     }
 }
 
@@ -165,23 +543,22 @@ fn handle_factor_rotation(factor: &Factor, meas_object: &mut SceneNode, source:
     }
 }
 
-fn color_meas_object(factor: &Factor, meas_object: &mut SceneNode) {
-    let (r, g, b) = get_factor_color(factor);
+fn color_meas_object((r, g, b): Color, meas_object: &mut SceneNode) {
     meas_object.set_color(r, g, b);
 }
 
 fn add_factor_lines(
     visual_factor_graph: &mut VisualFactorGraph,
     factor: &Factor,
+    (r, g, b): Color,
     meas_point: Point3<f32>,
     source_point: Point3<f32>,
     target_point: Point3<f32>,
 ) {
-    let (r, g, b) = get_factor_color(factor);
     visual_factor_graph
         .lines
         .push([meas_point, source_point, Point3::new(r, g, b)]);
-    if factor.factor_type == Observation2D || factor.factor_type == Observation3D {
+    if let Observation2D | BearingRange2D | Observation3D | LandmarkOffset2D | LandmarkOffset3D = factor.factor_type {
         visual_factor_graph
             .lines
             .push([meas_point, target_point, Point3::new(r, g, b)]);
@@ -192,11 +569,29 @@ fn add_factor_lines(
     }
 }
 
-fn get_factor_color(factor: &Factor) -> (f32, f32, f32) {
+fn get_factor_color(factor: &Factor) -> Color {
     match factor.factor_type {
-        Position2D | Position3D => (1.0, 0.5, 0.5),
-        Odometry2D | Odometry3D => (0.5, 0.5, 1.0),
-        Observation2D | Observation3D => (0.5, 1.0, 0.5),
+        Position2D | Position3D | PositionOnly3D | LeverArmPosition2D | LeverArmPosition3D => (1.0, 0.5, 0.5),
+        Odometry2D | Odometry3D | OdometrySim3 | CalibratedOdometry2D | ImuPreintegration3D | ConstantVelocity2D
+        | ConstantVelocity3D | AnchoredOdometry2D | AnchoredOdometry3D => (0.5, 0.5, 1.0),
+        Observation2D
+        | BearingRange2D
+        | Observation3D
+        | ExtrinsicObservation2D
+        | ExtrinsicObservation3D
+        | AnchoredObservation2D
+        | AnchoredObservation3D
+        | LandmarkOffset2D
+        | LandmarkOffset3D => (0.5, 1.0, 0.5),
+        Range2D | Range3D | Bearing2D | Projection3D | StereoProjection3D => (0.5, 1.0, 1.0),
+        Custom(_) | UserDefined(_) => (1.0, 1.0, 0.5),
+        SwitchableOdometry2D | SwitchableOdometry3D => (1.0, 0.5, 1.0),
+        SwitchPrior => unreachable!("Switch priors are not visualized."),
+        MaxMixture(_) => unreachable!("Max-mixture factors are visualized by their dominant component."),
+        DensePrior | Heading2D | Heading3D | Altitude3D | InterpolatedPose2D | InterpolatedPose3D => {
+            unreachable!("Dense, heading, altitude and interpolated priors are not visualized.")
+        }
+        PointToPlane3D | LineObservation2D => unreachable!("Plane and line factors are not visualized."),
     }
 }
 
@@ -205,21 +600,77 @@ fn get_var_point(var: &Variable) -> Point3<f32> {
         Variable::Vehicle2D(VehicleVariable2D { pose, .. }) => (pose.borrow()[0], pose.borrow()[1], 0.),
         Variable::Landmark2D(LandmarkVariable2D { position, .. }) => (position.borrow()[0], position.borrow()[1], 0.),
         Variable::Vehicle3D(VehicleVariable3D { pose, .. }) => (pose.borrow()[0], pose.borrow()[1], pose.borrow()[2]),
+        Variable::VehicleSim3(VehicleVariableSim3 { pose, .. }) => {
+            (pose.borrow()[0], pose.borrow()[1], pose.borrow()[2])
+        }
         Variable::Landmark3D(LandmarkVariable3D { position, .. }) => {
             (position.borrow()[0], position.borrow()[1], position.borrow()[2])
         }
+        Variable::Switch(_)
+        | Variable::Velocity3D(_)
+        | Variable::ImuBias(_)
+        | Variable::Plane3D(_)
+        | Variable::LineLandmark2D(_)
+        | Variable::OdometryCalibration2D(_)
+        | Variable::Extrinsic2D(_)
+        | Variable::Extrinsic3D(_)
+        | Variable::Anchor2D(_)
+        | Variable::Anchor3D(_)
+        | Variable::UserDefined(_) => {
+            unreachable!("Only pose and position variables have a position.")
+        }
     };
 
     Point3::new(x as f32, y as f32, z as f32)
 }
 
+// returns the measured position relative to the source, or the measured position itself for unary factors
 fn get_factor_point(factor: &Factor) -> Point3<f32> {
+    if factor.factor_type == BearingRange2D {
+        let (sin, cos) = factor.constraint[0].sin_cos();
+        let range = factor.constraint[1];
+        return Point3::new((range * cos) as f32, (range * sin) as f32, 0.0);
+    }
     Point3::new(
         factor.constraint[0] as f32,
         factor.constraint[1] as f32,
         match factor.factor_type {
-            Position2D | Odometry2D | Observation2D => 0.0_f32,
-            Position3D | Odometry3D | Observation3D => factor.constraint[2] as f32,
+            Position2D | Odometry2D | Observation2D | BearingRange2D | LandmarkOffset2D | LeverArmPosition2D => 0.0_f32,
+            Position3D | PositionOnly3D | Odometry3D | Observation3D | LandmarkOffset3D | LeverArmPosition3D => {
+                factor.constraint[2] as f32
+            }
+            Custom(_)
+            | UserDefined(_)
+            | SwitchableOdometry2D
+            | SwitchableOdometry3D
+            | SwitchPrior
+            | MaxMixture(_)
+            | DensePrior
+            | Heading2D
+            | Heading3D
+            | Altitude3D
+            | InterpolatedPose2D
+            | InterpolatedPose3D
+            | Range2D
+            | Range3D
+            | Bearing2D
+            | ImuPreintegration3D
+            | ConstantVelocity2D
+            | ConstantVelocity3D
+            | Projection3D
+            | StereoProjection3D
+            | OdometrySim3
+            | CalibratedOdometry2D
+            | ExtrinsicObservation2D
+            | ExtrinsicObservation3D
+            | AnchoredOdometry2D
+            | AnchoredOdometry3D
+            | AnchoredObservation2D
+            | AnchoredObservation3D
+            | PointToPlane3D
+            | LineObservation2D => {
+                unreachable!("Only position, odometry and observation factors have a measurement point.")
+            }
         },
     )
 }
@@ -237,17 +688,6 @@ fn get_rot_from_3d(content: &[f64]) -> UnitQuaternion<f32> {
     ))
 }
 
-impl Variable {
-    pub fn get_content(&self) -> Vec<f64> {
-        match self {
-            Variable::Vehicle2D(v) => v.pose.borrow().to_vec(),
-            Variable::Landmark2D(v) => v.position.borrow().to_vec(),
-            Variable::Vehicle3D(v) => v.pose.borrow().to_vec(),
-            Variable::Landmark3D(v) => v.position.borrow().to_vec(),
-        }
-    }
-}
-
 #[cfg(test)]
 mod test {
     use super::*;
@@ -272,6 +712,42 @@ mod test {
         visualize(&factor_graph);
     }
 
+    #[test]
+    #[ignore] // don't open a window every time all tests are run
+    fn test_visualize_region() {
+        init();
+
+        let factor_graph = G2oParser::parse_file("data_files/optimizer_tests/full2d_0.g2o").unwrap();
+        let options = VisualizationOptions {
+            region: Some(Region::Sphere {
+                center: Point3::new(0.0, 0.0, 0.0),
+                radius: 10.0,
+            }),
+            ..Default::default()
+        };
+        visualize_with_options(&factor_graph, &options);
+    }
+
+    #[test]
+    #[ignore] // don't open a window every time all tests are run
+    fn test_visualize_difference() {
+        init();
+
+        let before = G2oParser::parse_file("data_files/optimizer_tests/full2d_0.g2o").unwrap();
+        let after = G2oParser::parse_file("data_files/optimizer_tests/full2d_1.g2o").unwrap();
+        visualize_difference(&before, &after);
+    }
+
+    #[test]
+    #[ignore] // don't open a window every time all tests are run
+    fn test_visualize_optimization() {
+        init();
+
+        let factor_graph = G2oParser::parse_file("data_files/optimizer_tests/full2d_0.g2o").unwrap();
+        let plot = visualize_optimization(&factor_graph, 10, &VisualizationOptions::default());
+        assert!(!plot.values().is_empty());
+    }
+
     #[test]
     #[ignore] // don't open a window every time all tests are run
     fn test_visualize_3d() {
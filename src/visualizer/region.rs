@@ -0,0 +1,160 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Regions which restrict the visualization to a part of the factor graph.
+
+use nalgebra::{Point3, Vector3};
+
+/// A region in space. Variables outside of it are hidden, as are factors connected to them.
+///
+/// 2D factor graphs are visualized at z = 0, so the region has to include z = 0 to show anything.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Region {
+    /// Axis-aligned box between two corners.
+    Box { min: Point3<f64>, max: Point3<f64> },
+    /// Sphere around a center.
+    Sphere { center: Point3<f64>, radius: f64 },
+}
+
+impl Region {
+    /// Returns whether the point lies within the region, including its border.
+    pub fn contains(&self, point: &Point3<f64>) -> bool {
+        match self {
+            Region::Box { min, max } => (0..3).all(|i| min[i] <= point[i] && point[i] <= max[i]),
+            Region::Sphere { center, radius } => (point - center).norm() <= *radius,
+        }
+    }
+
+    /// Returns the region's center.
+    pub fn center(&self) -> Point3<f64> {
+        match self {
+            Region::Box { min, max } => nalgebra::center(min, max),
+            Region::Sphere { center, .. } => *center,
+        }
+    }
+
+    /// Returns the region moved by the given offset.
+    pub fn translated(&self, offset: &Vector3<f64>) -> Self {
+        match self {
+            Region::Box { min, max } => Region::Box {
+                min: min + offset,
+                max: max + offset,
+            },
+            Region::Sphere { center, radius } => Region::Sphere {
+                center: center + offset,
+                radius: *radius,
+            },
+        }
+    }
+
+    /// Returns the region scaled by the given factor around its center.
+    pub fn scaled(&self, factor: f64) -> Self {
+        let center = self.center();
+        match self {
+            Region::Box { min, max } => Region::Box {
+                min: center + (min - center) * factor,
+                max: center + (max - center) * factor,
+            },
+            Region::Sphere { center, radius } => Region::Sphere {
+                center: *center,
+                radius: radius * factor,
+            },
+        }
+    }
+
+    /// Returns the length of the region's largest extent, i.e. the diameter of spheres.
+    pub fn size(&self) -> f64 {
+        match self {
+            Region::Box { min, max } => (max - min).max(),
+            Region::Sphere { radius, .. } => 2.0 * radius,
+        }
+    }
+
+    /// Returns line segments outlining the region, i.e. the edges of boxes and three great circles of spheres.
+    pub fn outline(&self) -> Vec<[Point3<f64>; 2]> {
+        match self {
+            Region::Box { min, max } => {
+                let corner = |i: usize| {
+                    Point3::new(
+                        if i & 1 == 0 { min.x } else { max.x },
+                        if i & 2 == 0 { min.y } else { max.y },
+                        if i & 4 == 0 { min.z } else { max.z },
+                    )
+                };
+                // corners differing in exactly one coordinate are connected
+                let mut lines = vec![];
+                for i in 0..8 {
+                    for bit in &[1, 2, 4] {
+                        if i & bit == 0 {
+                            lines.push([corner(i), corner(i | bit)]);
+                        }
+                    }
+                }
+                lines
+            }
+            Region::Sphere { center, radius } => {
+                const SEGMENTS: usize = 48;
+                let point = |axis: usize, k: usize| {
+                    let angle = 2.0 * std::f64::consts::PI * k as f64 / SEGMENTS as f64;
+                    let mut offset = Vector3::zeros();
+                    offset[(axis + 1) % 3] = radius * angle.cos();
+                    offset[(axis + 2) % 3] = radius * angle.sin();
+                    center + offset
+                };
+                (0..3)
+                    .flat_map(|axis| (0..SEGMENTS).map(move |k| [point(axis, k), point(axis, k + 1)]))
+                    .collect()
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use approx::assert_relative_eq;
+
+    #[test]
+    fn test_contains() {
+        let region = Region::Box {
+            min: Point3::new(-1.0, 0.0, -1.0),
+            max: Point3::new(1.0, 2.0, 1.0),
+        };
+        assert!(region.contains(&Point3::new(0.5, 2.0, 0.0)));
+        assert!(!region.contains(&Point3::new(0.5, 2.1, 0.0)));
+        let region = Region::Sphere {
+            center: Point3::new(1.0, 1.0, 0.0),
+            radius: 2.0,
+        };
+        assert!(region.contains(&Point3::new(2.0, 2.0, 0.0)));
+        assert!(!region.contains(&Point3::new(3.0, 3.0, 0.0)));
+    }
+
+    #[test]
+    fn test_translate_and_scale() {
+        let region = Region::Box {
+            min: Point3::new(0.0, 0.0, 0.0),
+            max: Point3::new(2.0, 4.0, 2.0),
+        }
+        .translated(&Vector3::new(1.0, 0.0, 0.0))
+        .scaled(0.5);
+        assert_eq!(
+            region,
+            Region::Box {
+                min: Point3::new(1.5, 1.0, 0.5),
+                max: Point3::new(2.5, 3.0, 1.5),
+            }
+        );
+        assert_relative_eq!(region.size(), 2.0);
+        assert_eq!(region.outline().len(), 12);
+    }
+}
@@ -0,0 +1,136 @@
+// -----------------------------------------------------------------------------------------------------
+//                                      gs-rs - Graph SLAM in Rust
+// -----------------------------------------------------------------------------------------------------
+//
+// SPDX-FileCopyrightText:      © 2020 Samuel Valenzuela (samuel.valenzuela@tngtech.com)
+//                              © 2020 Florian Rohm (florian.rohm@tngtech.com)
+//                              © 2020 Daniel Pape (daniel.pape@tngtech.com)
+// SPDX-License-Identifier:     MIT OR Apache-2.0
+//
+// This product includes software developed at TNG Technology Consulting GmbH (https://www.tngtech.com/).
+//
+
+//! Tags of factors and variables which override their colors, e.g. to highlight suspect factors or the variables of
+//! one session.
+
+use crate::factor_graph::factor::FactorId;
+use crate::factor_graph::variable::VariableId;
+use std::collections::BTreeMap;
+
+/// An RGB color with components between 0 and 1.
+pub type Color = (f32, f32, f32);
+
+/// Tags of factors and variables, and the colors of the tags.
+///
+/// Factors and variables with a colored tag are shown in its color instead of the color of their type. If they have
+/// several colored tags, the tag which was colored first wins.
+///
+/// ```
+/// use gs_rs::factor_graph::factor::FactorId;
+/// use gs_rs::visualizer::tags::Tags;
+///
+/// let mut tags = Tags::new();
+/// tags.tag_factor(FactorId(3), "suspect");
+/// tags.set_color("suspect", (1.0, 0.0, 1.0));
+/// assert_eq!(tags.factor_color(FactorId(3)), Some((1.0, 0.0, 1.0)));
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Tags {
+    factor_tags: BTreeMap<FactorId, Vec<String>>,
+    variable_tags: BTreeMap<VariableId, Vec<String>>,
+    colors: Vec<(String, Color)>,
+}
+
+impl Tags {
+    /// Returns tags without any tagged factors or variables.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds the tag to the factor with the given ID, unless it already has the tag.
+    pub fn tag_factor(&mut self, id: FactorId, tag: &str) {
+        add_tag(self.factor_tags.entry(id).or_default(), tag);
+    }
+
+    /// Adds the tag to the variable with the given ID, unless it already has the tag.
+    pub fn tag_variable(&mut self, id: VariableId, tag: &str) {
+        add_tag(self.variable_tags.entry(id).or_default(), tag);
+    }
+
+    /// Removes the tag from all factors and variables. Its color is kept.
+    pub fn remove_tag(&mut self, tag: &str) {
+        self.factor_tags.values_mut().for_each(|tags| tags.retain(|t| t != tag));
+        self.variable_tags
+            .values_mut()
+            .for_each(|tags| tags.retain(|t| t != tag));
+    }
+
+    /// Returns the tags of the factor with the given ID in the order in which they were added.
+    pub fn factor_tags(&self, id: FactorId) -> &[String] {
+        self.factor_tags.get(&id).map_or(&[], |tags| tags.as_slice())
+    }
+
+    /// Returns the tags of the variable with the given ID in the order in which they were added.
+    pub fn variable_tags(&self, id: VariableId) -> &[String] {
+        self.variable_tags.get(&id).map_or(&[], |tags| tags.as_slice())
+    }
+
+    /// Sets the color of the tag, keeping its priority if it already had a color.
+    pub fn set_color(&mut self, tag: &str, color: Color) {
+        match self.colors.iter_mut().find(|(t, _)| t == tag) {
+            Some((_, c)) => *c = color,
+            None => self.colors.push((String::from(tag), color)),
+        }
+    }
+
+    /// Returns the color of the factor's first colored tag, or None if it is shown in the color of its type.
+    pub fn factor_color(&self, id: FactorId) -> Option<Color> {
+        self.get_color(self.factor_tags(id))
+    }
+
+    /// Returns the color of the variable's first colored tag, or None if it is shown in the color of its type.
+    pub fn variable_color(&self, id: VariableId) -> Option<Color> {
+        self.get_color(self.variable_tags(id))
+    }
+
+    fn get_color(&self, tags: &[String]) -> Option<Color> {
+        self.colors
+            .iter()
+            .find(|(tag, _)| tags.contains(tag))
+            .map(|(_, color)| *color)
+    }
+}
+
+fn add_tag(tags: &mut Vec<String>, tag: &str) {
+    if !tags.iter().any(|t| t == tag) {
+        tags.push(String::from(tag));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_color_priority() {
+        let mut tags = Tags::new();
+        tags.tag_variable(VariableId(1), "session2");
+        tags.tag_variable(VariableId(1), "suspect");
+        tags.tag_variable(VariableId(1), "suspect");
+        tags.tag_variable(VariableId(2), "session2");
+        assert_eq!(tags.variable_tags(VariableId(1)), ["session2", "suspect"]);
+        assert_eq!(tags.variable_color(VariableId(1)), None);
+
+        tags.set_color("suspect", (1.0, 0.0, 0.0));
+        tags.set_color("session2", (0.0, 0.0, 1.0));
+        assert_eq!(tags.variable_color(VariableId(1)), Some((1.0, 0.0, 0.0)));
+        assert_eq!(tags.variable_color(VariableId(2)), Some((0.0, 0.0, 1.0)));
+        tags.set_color("suspect", (1.0, 1.0, 0.0));
+        assert_eq!(tags.variable_color(VariableId(1)), Some((1.0, 1.0, 0.0)));
+        assert_eq!(tags.factor_color(FactorId(1)), None);
+
+        tags.remove_tag("suspect");
+        assert_eq!(tags.variable_color(VariableId(1)), Some((0.0, 0.0, 1.0)));
+        assert!(tags.factor_tags(FactorId(1)).is_empty());
+    }
+}